@@ -1,7 +1,7 @@
 import { mkdtempSync, rmSync } from "fs";
 import { tmpdir } from "os";
 import { join } from "path";
-import { afterEach, beforeEach, describe, expect, it } from "vitest";
+import { afterEach, beforeEach, describe, expect, it, vi } from "vitest";
 import { JsGraphStore } from "../index.js";
 
 describe("JsGraphStore", () => {
@@ -224,6 +224,25 @@ describe("JsGraphStore", () => {
 		expect(results[0].matchedField).toBe("content");
 	});
 
+	it("pages search results and counts them all", () => {
+		for (const n of [1, 2, 3]) {
+			store.createNode({
+				parentId: "root",
+				nodeType: "detail",
+				content: `Guitar lesson ${n}`,
+			});
+		}
+
+		const { results, total } = store.searchNodesPage("guitar", {
+			offset: 1,
+			limit: 1,
+		});
+		expect(total).toBe(3);
+		expect(results).toHaveLength(1);
+		expect(results[0].content).toBe(store.searchNodes("guitar")[1].content);
+		expect(store.searchNodes("guitar", 2)).toHaveLength(2);
+	});
+
 	it("persists across reopens", () => {
 		store.createNode({
 			parentId: "root",
@@ -252,4 +271,226 @@ describe("JsGraphStore", () => {
 		expect(node.temporal!.validFrom).toContain("2020");
 		expect(node.temporal!.label).toBe("residence");
 	});
+
+	it("undoes and redoes changes", () => {
+		const node = store.createNode({
+			parentId: "root",
+			nodeType: "detail",
+			content: "Plays chess",
+		});
+		expect(store.canUndo()).toBe(true);
+		expect(store.canRedo()).toBe(false);
+
+		expect(store.undo()).toBe(true);
+		expect(store.nodeExists(node.id)).toBe(false);
+		expect(store.canRedo()).toBe(true);
+
+		expect(store.redo()).toBe(true);
+		expect(store.getNode(node.id).content).toBe("Plays chess");
+		expect(store.redo()).toBe(false);
+	});
+
+	it("keeps transactions to one undo step and rolls them back", () => {
+		store.beginTransaction();
+		expect(store.inTransaction()).toBe(true);
+		store.createNode({ parentId: "root", nodeType: "detail", content: "A" });
+		store.createNode({ parentId: "root", nodeType: "detail", content: "B" });
+		store.commitTransaction();
+		expect(store.countNodes()).toBe(3);
+		store.undo();
+		expect(store.countNodes()).toBe(1);
+
+		store.beginTransaction();
+		store.createNode({ parentId: "root", nodeType: "detail", content: "C" });
+		store.rollbackTransaction();
+		expect(store.inTransaction()).toBe(false);
+		expect(store.countNodes()).toBe(1);
+	});
+
+	it("reorders children", () => {
+		const ids = store.createNodes(
+			["A", "B", "C"].map((content) => ({
+				parentId: "root",
+				nodeType: "detail",
+				content,
+			})),
+		);
+		const reversed = [...ids].reverse();
+
+		const root = store.reorderChildren("root", reversed);
+		expect(root.children).toEqual(reversed);
+		expect(store.getNode("root").children).toEqual(reversed);
+		expect(() => store.reorderChildren("root", ids.slice(1))).toThrow();
+	});
+
+	it("gets nodes and links by id", () => {
+		const a = store.createNode({
+			parentId: "root",
+			nodeType: "category",
+			content: "A",
+		});
+		const b = store.createNode({
+			parentId: "root",
+			nodeType: "category",
+			content: "B",
+		});
+		const link = store.addLink({
+			fromNode: a.id,
+			toNode: b.id,
+			relation: "related_to",
+		});
+
+		expect(store.getNode(a.id).content).toBe("A");
+		expect(store.getLink(link.id).toNode).toBe(b.id);
+		expect(() => store.getNode("missing")).toThrow("Node not found");
+		expect(() => store.getLink("missing")).toThrow("Link not found");
+	});
+
+	it("notifies subscribers until they unsubscribe", async () => {
+		const kinds: string[] = [];
+		const id = store.subscribe((event) => {
+			kinds.push(event.kind);
+		});
+		const node = store.createNode({
+			parentId: "root",
+			nodeType: "detail",
+			content: "Watched",
+		});
+		await vi.waitFor(() => expect(kinds).toEqual(["node_created"]));
+
+		expect(store.unsubscribe(id)).toBe(true);
+		store.deleteNode(node.id);
+		await new Promise((resolve) => setTimeout(resolve, 50));
+		expect(kinds).toEqual(["node_created"]);
+	});
+
+	it("exports and imports the whole graph", () => {
+		const node = store.createNode({
+			parentId: "root",
+			nodeType: "detail",
+			content: "Exported",
+		});
+		const other = JsGraphStore.open(join(tmpDir, "other.json"));
+
+		for (const format of ["json", "msgpack", "compressed"]) {
+			other.importGraph(store.exportGraph(format));
+			expect(other.getNode(node.id).content).toBe("Exported");
+			other.deleteNode(node.id);
+		}
+		other.importGraph(store.exportGraph("msgpack"), { format: "msgpack" });
+		expect(other.nodeExists(node.id)).toBe(true);
+		expect(() =>
+			other.importGraph(Buffer.from("not a graph"), { format: "json" }),
+		).toThrow();
+		expect(other.nodeExists(node.id)).toBe(true);
+	});
+
+	it("rejects writes through a read-only instance", () => {
+		store.createNode({ parentId: "root", nodeType: "detail", content: "A" });
+		const reader = JsGraphStore.openReadOnly(graphPath);
+
+		expect(reader.searchNodes("A")).toHaveLength(1);
+		expect(() =>
+			reader.createNode({ parentId: "root", nodeType: "detail", content: "B" }),
+		).toThrow("read-only");
+		expect(() => reader.undo()).toThrow("read-only");
+		expect(store.countNodes()).toBe(2);
+	});
+
+	it("throws once closed", () => {
+		expect(store.isClosed()).toBe(false);
+		store.close();
+		expect(store.isClosed()).toBe(true);
+		expect(() => store.getNode("root")).toThrow("Store is closed");
+		expect(() =>
+			store.createNode({ parentId: "root", nodeType: "detail", content: "A" }),
+		).toThrow("Store is closed");
+		store.close();
+
+		const reopened = JsGraphStore.open(graphPath);
+		expect(reopened.getNode("root").id).toBe("root");
+	});
+
+	it("lists, counts and filters nodes", () => {
+		store.createNodes([
+			{ parentId: "root", nodeType: "detail", content: "Tea", metadata: { tags: "drink" } },
+			{ parentId: "root", nodeType: "detail", content: "Coffee", metadata: { tags: "drink" } },
+			{ parentId: "root", nodeType: "category", content: "Food" },
+		]);
+		const drinks = { tags: ["drink"] };
+
+		expect(store.countNodes(drinks)).toBe(2);
+		const page = store.listNodes(drinks, undefined, { limit: 1 });
+		expect(page.total).toBe(2);
+		expect(page.nodes).toHaveLength(1);
+		expect(store.deleteWhere({ nodeTypes: ["category"] })).toBe(1);
+		expect(store.countNodes()).toBe(3);
+	});
+
+	it("pins, archives and reparents nodes", () => {
+		const [a, b] = store.createNodes([
+			{ parentId: "root", nodeType: "category", content: "A" },
+			{ parentId: "root", nodeType: "category", content: "B" },
+		]);
+		const child = store.createNode({
+			parentId: a,
+			nodeType: "detail",
+			content: "Shared",
+		});
+
+		expect(store.pinNode(child.id).pinned).toBe(true);
+		expect(store.listPinned().map((n) => n.id)).toEqual([child.id]);
+		expect(store.archiveNode(b).archived).toBe(true);
+		expect(store.unarchiveNode(b).archived).toBe(false);
+		expect(store.addParent(child.id, b).extraParents).toEqual([b]);
+		expect(store.getNode(b).children).toContain(child.id);
+		expect(store.removeParent(child.id, b).extraParents).toHaveLength(0);
+	});
+
+	it("records who changed what in the audit log", () => {
+		const audited = JsGraphStore.open(join(tmpDir, "audited.json"), {
+			audit: true,
+		});
+		audited.setActor("assistant");
+		const node = audited.createNode({
+			parentId: "root",
+			nodeType: "detail",
+			content: "Audited",
+		});
+
+		const entries = audited.auditLog({ nodeId: node.id });
+		expect(entries).toHaveLength(1);
+		expect(entries[0].actor).toBe("assistant");
+		expect(entries[0].operation).toBe("create_node");
+		expect(node.createdBy).toBe("assistant");
+	});
+
+	it("applies rules to new nodes", () => {
+		store.setRules([
+			{
+				id: "meetings",
+				contentContains: "meeting",
+				actions: [{ action: "add_tag", tag: "work" }],
+			},
+		]);
+		const node = store.createNode({
+			parentId: "root",
+			nodeType: "detail",
+			content: "Weekly meeting",
+		});
+
+		expect(store.getNode(node.id).metadata.tags).toBe("work");
+		expect(() =>
+			store.setRules([{ id: "bad", actions: [{ action: "add_tag" }] }]),
+		).toThrow("needs `tag`");
+	});
+
+	it("stores attachments by hash", () => {
+		const data = Buffer.from("hello");
+		const attachment = store.attachBlob("root", data, "text/plain");
+
+		expect(attachment.size).toBe(5);
+		expect(store.getNode("root").attachments).toEqual([attachment]);
+		expect(store.getAttachment(attachment.hash)).toEqual(data);
+	});
 });
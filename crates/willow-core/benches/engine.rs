@@ -0,0 +1,200 @@
+//! Criterion benchmarks for the engine's hot paths, to check performance
+//! work against:
+//!
+//!     cargo bench --features bench [-- <name filter>]
+//!
+//! Criterion keeps each run's estimates under `target/criterion` and reports
+//! the change against the previous run. Setup is not timed.
+
+use std::hint::black_box;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use tempfile::TempDir;
+use willow_core::bench::{
+    save_graph, search_nodes, Graph, GraphStore, NewNode, NodeId, OpenOptions, SearchOptions, StorageOptions,
+    WriteBehind,
+};
+use willow_core::vcs::merge::{three_way_merge, MergeResult};
+use willow_core::vcs::types::{CommitHash, CommitInput, CommitSource, RepoConfig};
+
+const TOPICS: [&str; 5] = [
+    "weekly planning meeting",
+    "tomatoes in the back garden",
+    "fighting the borrow checker",
+    "trip to Lisbon in spring",
+    "birthday gift ideas",
+];
+
+fn held_back() -> OpenOptions {
+    let hour = Duration::from_secs(3600);
+    OpenOptions {
+        write_behind: Some(WriteBehind { debounce: hour, max_delay: hour }),
+        ..OpenOptions::default()
+    }
+}
+
+fn new_node(node_type: &str, content: String, children: Vec<NewNode>) -> NewNode {
+    NewNode {
+        node_type: node_type.to_string(),
+        content,
+        format: None,
+        metadata: None,
+        temporal: None,
+        children,
+    }
+}
+
+/// A store in `dir` holding about `nodes` details, in categories of 100.
+fn populated_store(dir: &Path, nodes: usize, options: &OpenOptions) -> GraphStore {
+    let mut store = GraphStore::open(&dir.join("graph.json"), options).unwrap();
+    let categories = (0..nodes.div_ceil(100))
+        .map(|c| {
+            let details = (c * 100..(c + 1) * 100)
+                .map(|i| new_node("detail", format!("Note {i} about {}", TOPICS[i % TOPICS.len()]), Vec::new()))
+                .collect();
+            ("root".to_string(), new_node("category", format!("Category {c}"), details))
+        })
+        .collect();
+    store.create_nodes(categories).unwrap();
+    store
+}
+
+fn graph_of(nodes: usize) -> Graph {
+    let dir = TempDir::new().unwrap();
+    populated_store(dir.path(), nodes, &held_back()).graph.clone()
+}
+
+fn bench_create_node(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_node");
+    group.sample_size(10);
+    let cases = [("1000 held back", 1_000, held_back()), ("100 saving each", 100, OpenOptions::default())];
+    for (label, count, options) in cases {
+        group.bench_function(label, |b| {
+            b.iter_batched(
+                || {
+                    let dir = TempDir::new().unwrap();
+                    let store = GraphStore::open(&dir.path().join("graph.json"), &options).unwrap();
+                    (dir, store)
+                },
+                |(_dir, mut store)| {
+                    for i in 0..count {
+                        black_box(store.create_node("root", "detail", &format!("Note {i}"), None, None).unwrap());
+                    }
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search_nodes");
+    let options = SearchOptions::default();
+    for nodes in [1_000, 10_000, 50_000] {
+        let graph = graph_of(nodes);
+        group.bench_with_input(BenchmarkId::from_parameter(nodes), &graph, |b, graph| {
+            b.iter(|| black_box(search_nodes(graph, "planning meeting", None, &options)));
+        });
+    }
+    group.finish();
+}
+
+fn bench_reconstruct(c: &mut Criterion) {
+    let depths = [10, 100, 400];
+    let dir = TempDir::new().unwrap();
+    let mut store = populated_store(dir.path(), 1_000, &held_back());
+    store.vcs_init().unwrap();
+    let config = RepoConfig {
+        snapshot_interval: 10_000,
+        ..store.get_repo().unwrap().config().clone()
+    };
+    store.set_repo_config(config).unwrap();
+
+    // Twice the deepest chain, so reconstructing a commit forward from the
+    // snapshot is never beaten by undoing deltas back from the branch tip.
+    let mut hashes: Vec<CommitHash> = Vec::new();
+    for i in 0..2 * depths[depths.len() - 1] {
+        store.create_node("root", "detail", &format!("Commit {i}"), None, None).unwrap();
+        let input = CommitInput {
+            message: format!("Commit {i}"),
+            source: CommitSource::Manual { tool_name: None },
+        };
+        hashes.push(store.commit(input).unwrap());
+    }
+
+    let repo = store.get_repo().unwrap();
+    let mut group = c.benchmark_group("reconstruct_at");
+    for depth in depths {
+        let target = &hashes[depth - 1];
+        group.bench_with_input(BenchmarkId::new("depth", depth), target, |b, target| {
+            b.iter_batched(
+                || repo.clear_cache(),
+                |()| black_box(repo.reconstruct_at(target).unwrap()),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+/// `base` with the content of every `every`th node, from `offset`, edited.
+fn edited(base: &Graph, offset: usize, every: usize) -> Graph {
+    let mut graph = base.clone();
+    let mut ids: Vec<NodeId> = graph.nodes.keys().cloned().collect();
+    ids.sort();
+    for id in ids.iter().skip(offset).step_by(every) {
+        let node = Arc::make_mut(graph.nodes.get_mut(id).unwrap());
+        node.content.push_str(" (edited)");
+    }
+    graph
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("three_way_merge");
+    group.sample_size(20);
+    for nodes in [10_000, 50_000] {
+        let base = graph_of(nodes);
+        // Each side edits a disjoint quarter of the nodes.
+        let ours = edited(&base, 0, 4);
+        let theirs = edited(&base, 1, 4);
+        assert!(matches!(three_way_merge(&base, &ours, &theirs), MergeResult::Success(_)));
+        group.bench_function(BenchmarkId::from_parameter(nodes), |b| {
+            b.iter(|| black_box(three_way_merge(&base, &ours, &theirs)));
+        });
+    }
+    group.finish();
+}
+
+fn bench_save(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("saved.json");
+    let compressed = StorageOptions {
+        compress: true,
+        ..StorageOptions::default()
+    };
+    let mut group = c.benchmark_group("save_graph");
+    group.sample_size(20);
+    for nodes in [1_000, 10_000, 50_000] {
+        let graph = graph_of(nodes);
+        for (label, options) in [("plain", StorageOptions::default()), ("compressed", compressed)] {
+            // Reported as throughput of the bytes written.
+            save_graph(&path, &graph, &options).unwrap();
+            group.throughput(Throughput::Bytes(std::fs::metadata(&path).unwrap().len()));
+            group.bench_function(BenchmarkId::new(label, nodes), |b| {
+                b.iter(|| save_graph(&path, &graph, &options).unwrap());
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = engine;
+    config = Criterion::default().measurement_time(Duration::from_secs(5));
+    targets = bench_create_node, bench_search, bench_reconstruct, bench_merge, bench_save
+}
+criterion_main!(engine);
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Shortest stem a suffix may be stripped down to, so short words such as
+/// "bus" or "was" are left alone.
+const MIN_STEM_CHARS: usize = 3;
+
+/// A light suffix-stripping stemmer for one language. Stems are only
+/// compared with each other, never shown, so they need not be real words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stemmer {
+    English,
+    French,
+    German,
+    Spanish,
+}
+
+impl Stemmer {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Stemmer::English => "english",
+            Stemmer::French => "french",
+            Stemmer::German => "german",
+            Stemmer::Spanish => "spanish",
+        }
+    }
+
+    /// Accepts the language name or its ISO 639-1 code.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Stemmer> {
+        match s.to_lowercase().as_str() {
+            "english" | "en" => Some(Stemmer::English),
+            "french" | "fr" => Some(Stemmer::French),
+            "german" | "de" => Some(Stemmer::German),
+            "spanish" | "es" => Some(Stemmer::Spanish),
+            _ => None,
+        }
+    }
+
+    /// Longest first, so "ings" is tried before "s".
+    fn suffixes(&self) -> &'static [&'static str] {
+        match self {
+            Stemmer::English => &[
+                "ingly", "edly", "ness", "ment", "ings", "ing", "ed", "es", "ly", "s",
+            ],
+            Stemmer::French => &[
+                "issements", "issement", "ations", "ation", "ements", "ement", "euses", "euse", "ités", "ité",
+                "ives", "ive", "es", "s", "e",
+            ],
+            Stemmer::German => &[
+                "ungen", "heiten", "keiten", "ung", "heit", "keit", "ern", "em", "en", "er", "es", "e", "n", "s",
+            ],
+            Stemmer::Spanish => &[
+                "amientos", "imientos", "amiento", "imiento", "aciones", "ación", "mente", "ando", "iendo", "ados",
+                "idos", "adas", "idas", "ado", "ido", "ada", "ida", "es", "os", "as", "a", "o", "s",
+            ],
+        }
+    }
+
+    /// Stem a lowercase word.
+    pub fn stem(&self, word: &str) -> String {
+        let long_enough = |stem: &str| stem.chars().count() >= MIN_STEM_CHARS;
+        let mut stem = self
+            .suffixes()
+            .iter()
+            .filter_map(|suffix| word.strip_suffix(suffix))
+            .find(|stem| long_enough(stem) && !(stem.ends_with('s') && word.ends_with("ss")))
+            .unwrap_or(word)
+            .to_string();
+        if *self == Stemmer::English {
+            // "studies"/"study", "likes"/"like", "running"/"run".
+            if stem.ends_with('y') {
+                stem.pop();
+                stem.push('i');
+            }
+            if stem.ends_with('e') && long_enough(&stem[..stem.len() - 1]) {
+                stem.pop();
+            }
+            let mut tail = stem.chars().rev();
+            if let (Some(a), Some(b)) = (tail.next(), tail.next()) {
+                if a == b && !"aeiouls".contains(a) && long_enough(&stem[..stem.len() - a.len_utf8()]) {
+                    stem.pop();
+                }
+            }
+        }
+        stem
+    }
+}
+
+/// Accented Latin letters and the plain letters they fold to.
+const DIACRITIC_FOLDS: &[(&str, &str)] = &[
+    ("àáâãäåāăą", "a"),
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("çćĉċč", "c"),
+    ("ÇĆĈĊČ", "C"),
+    ("ďđð", "d"),
+    ("ĎĐÐ", "D"),
+    ("èéêëēĕėęě", "e"),
+    ("ÈÉÊËĒĔĖĘĚ", "E"),
+    ("ĝğġģ", "g"),
+    ("ĜĞĠĢ", "G"),
+    ("ĥħ", "h"),
+    ("ĤĦ", "H"),
+    ("ìíîïĩīĭįı", "i"),
+    ("ÌÍÎÏĨĪĬĮİ", "I"),
+    ("ĵ", "j"),
+    ("Ĵ", "J"),
+    ("ķ", "k"),
+    ("Ķ", "K"),
+    ("ĺļľŀł", "l"),
+    ("ĹĻĽĿŁ", "L"),
+    ("ñńņň", "n"),
+    ("ÑŃŅŇ", "N"),
+    ("òóôõöøōŏő", "o"),
+    ("ÒÓÔÕÖØŌŎŐ", "O"),
+    ("ŕŗř", "r"),
+    ("ŔŖŘ", "R"),
+    ("śŝşš", "s"),
+    ("ŚŜŞŠ", "S"),
+    ("ţťŧ", "t"),
+    ("ŢŤŦ", "T"),
+    ("ùúûüũūŭůűų", "u"),
+    ("ÙÚÛÜŨŪŬŮŰŲ", "U"),
+    ("ŵ", "w"),
+    ("Ŵ", "W"),
+    ("ýÿŷ", "y"),
+    ("ÝŸŶ", "Y"),
+    ("źżž", "z"),
+    ("ŹŻŽ", "Z"),
+    ("ß", "ss"),
+    ("æ", "ae"),
+    ("Æ", "AE"),
+    ("œ", "oe"),
+    ("Œ", "OE"),
+    ("þ", "th"),
+    ("Þ", "TH"),
+];
+
+/// Fold accented Latin letters to their plain forms ("café" to "cafe") and
+/// drop combining marks, so decomposed text folds the same way.
+pub fn fold_diacritics(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if ('\u{300}'..='\u{36f}').contains(&c) {
+            continue;
+        }
+        match DIACRITIC_FOLDS.iter().find(|(from, _)| from.contains(c)) {
+            Some((_, to)) => out.push_str(to),
+            None => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Query-time text analysis for search: stemming and synonyms. Persisted
+/// next to the graph; the default does neither.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextAnalysis {
+    pub stemmer: Option<Stemmer>,
+    /// Groups of interchangeable terms, e.g. `["job", "work"]`. A query term
+    /// in a group also matches every other term in it.
+    pub synonyms: Vec<Vec<String>>,
+}
+
+impl TextAnalysis {
+    /// The other terms `term` may be replaced by, lowercased.
+    pub fn synonyms_of(&self, term: &str) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for group in &self.synonyms {
+            let group: Vec<String> = group.iter().map(|t| t.to_lowercase()).collect();
+            if group.iter().any(|t| t == term) {
+                for t in group {
+                    if t != term && !out.contains(&t) {
+                        out.push(t);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_english_stems_conflate_inflections() {
+        let stem = |w| Stemmer::English.stem(w);
+        assert_eq!(stem("running"), stem("run"));
+        assert_eq!(stem("runs"), stem("run"));
+        assert_eq!(stem("likes"), stem("liked"));
+        assert_eq!(stem("studies"), stem("study"));
+        assert_eq!(stem("class"), "class");
+        assert_eq!(stem("bus"), "bus");
+        assert_eq!(Stemmer::German.stem("zeitungen"), Stemmer::German.stem("zeitung"));
+        assert_eq!(Stemmer::from_str("ES"), Some(Stemmer::Spanish));
+    }
+
+    #[test]
+    fn test_fold_diacritics() {
+        assert_eq!(fold_diacritics("Café Zürich"), "Cafe Zurich");
+        assert_eq!(fold_diacritics("cafe\u{301}"), "cafe");
+        assert_eq!(fold_diacritics("Straße"), "Strasse");
+        assert_eq!(fold_diacritics("日本"), "日本");
+    }
+
+    #[test]
+    fn test_synonyms_are_symmetric() {
+        let analysis = TextAnalysis {
+            stemmer: None,
+            synonyms: vec![vec!["Job".to_string(), "work".to_string()], vec!["uni".to_string(), "university".to_string()]],
+        };
+        assert_eq!(analysis.synonyms_of("job"), vec!["work"]);
+        assert_eq!(analysis.synonyms_of("work"), vec!["job"]);
+        assert!(analysis.synonyms_of("school").is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::WillowError;
+use crate::files;
+use crate::model::Graph;
+use crate::vcs::types::Change;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Content-addressed blob storage: each blob lives at `attachments/<sha256>`
+/// next to the graph file, so identical files are stored once.
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(graph_dir: &Path) -> Self {
+        BlobStore {
+            dir: graph_dir.join("attachments"),
+        }
+    }
+
+    /// Reject anything but a SHA-256 hex digest, so hashes can't escape the directory.
+    fn blob_path(&self, hash: &str) -> Result<PathBuf, WillowError> {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(WillowError::AttachmentNotFound(hash.to_string()));
+        }
+        Ok(self.dir.join(hash))
+    }
+
+    pub fn put(&self, bytes: &[u8]) -> Result<String, WillowError> {
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        let path = self.blob_path(&hash)?;
+        if !files::exists(&path) {
+            files::create_dir_all(&self.dir)?;
+            files::write(&path, bytes)?;
+            debug!(hash = %hash, size = bytes.len(), "blob written");
+        }
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>, WillowError> {
+        let path = self.blob_path(hash)?;
+        if !files::exists(&path) {
+            return Err(WillowError::AttachmentNotFound(hash.to_string()));
+        }
+        Ok(files::read(&path)?)
+    }
+
+    /// Delete every blob not in `referenced`, returning the removed hashes.
+    pub fn gc(&self, referenced: &HashSet<String>) -> Result<Vec<String>, WillowError> {
+        if !files::exists(&self.dir) {
+            return Ok(Vec::new());
+        }
+        let mut removed = Vec::new();
+        for path in files::list(&self.dir)? {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else { continue };
+            if !referenced.contains(&name) {
+                files::remove_file(&path)?;
+                removed.push(name);
+            }
+        }
+        removed.sort();
+        Ok(removed)
+    }
+}
+
+pub fn collect_graph_refs(graph: &Graph, out: &mut HashSet<String>) {
+    for node in graph.nodes.values() {
+        out.extend(node.attachments.iter().map(|a| a.hash.clone()));
+    }
+}
+
+/// Attachment hashes mentioned by changes, including nodes they would recreate.
+pub fn collect_change_refs(changes: &[Change], out: &mut HashSet<String>) {
+    for change in changes {
+        match change {
+            Change::CreateNode { node, .. } => {
+                out.extend(node.attachments.iter().map(|a| a.hash.clone()))
+            }
+            Change::DeleteNode { deleted_nodes, .. } => {
+                for node in deleted_nodes {
+                    out.extend(node.attachments.iter().map(|a| a.hash.clone()));
+                }
+            }
+            Change::AttachBlob { attachment, .. } | Change::DetachBlob { attachment, .. } => {
+                out.insert(attachment.hash.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_gc() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs = BlobStore::new(dir.path());
+        let a = blobs.put(b"hello").unwrap();
+        let b = blobs.put(b"world").unwrap();
+        assert_eq!(blobs.put(b"hello").unwrap(), a);
+        assert_eq!(blobs.get(&a).unwrap(), b"hello");
+        assert!(blobs.get("../graph.json").is_err());
+
+        let removed = blobs.gc(&HashSet::from([a.clone()])).unwrap();
+        assert_eq!(removed, vec![b.clone()]);
+        assert!(blobs.get(&b).is_err());
+        assert!(blobs.get(&a).is_ok());
+    }
+}
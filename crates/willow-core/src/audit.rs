@@ -0,0 +1,159 @@
+use crate::error::WillowError;
+use crate::files;
+use crate::model::{LinkId, NodeId};
+use crate::search_index;
+use crate::vcs::types::Change;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// One mutating call on a store. Unlike commits, entries are written as the
+/// call is made, so they also cover work done without VCS, rolled back or
+/// never committed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// The call, e.g. "set_limits", or for calls that change the graph the
+    /// kinds of change they made, e.g. "create_node" or "delete_node+remove_link".
+    pub operation: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub node_ids: Vec<NodeId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub link_ids: Vec<LinkId>,
+    /// What else the call named: a branch, commit or profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(actor: Option<String>, operation: impl Into<String>) -> Self {
+        AuditEntry {
+            timestamp: Utc::now(),
+            actor,
+            operation: operation.into(),
+            node_ids: Vec::new(),
+            link_ids: Vec::new(),
+            target: None,
+        }
+    }
+
+    /// The entry for a call that made `changes`.
+    pub fn of_changes(actor: Option<String>, changes: &[Change]) -> Self {
+        let mut kinds: Vec<&str> = Vec::new();
+        let mut node_ids = BTreeSet::new();
+        let mut link_ids = BTreeSet::new();
+        for change in changes {
+            // Stamps only follow the edits already listed.
+            if !kinds.contains(&change.kind()) && !matches!(change, Change::StampNode { .. }) {
+                kinds.push(change.kind());
+            }
+            node_ids.extend(search_index::touched(change).into_iter().cloned());
+            link_ids.extend(search_index::touched_links(change).into_iter().cloned());
+        }
+        AuditEntry {
+            node_ids: node_ids.into_iter().collect(),
+            link_ids: link_ids.into_iter().collect(),
+            ..AuditEntry::new(actor, kinds.join("+"))
+        }
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+}
+
+/// Which entries `read` returns. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub actor: Option<String>,
+    /// Matches an entry whose operation is this, or has it as one of the
+    /// kinds of change joined by '+'.
+    pub operation: Option<String>,
+    /// Matches entries that touched this node.
+    pub node_id: Option<NodeId>,
+    /// At most this many entries, the most recent ones.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && self.actor.as_ref().is_none_or(|actor| entry.actor.as_ref() == Some(actor))
+            && self
+                .operation
+                .as_ref()
+                .is_none_or(|op| entry.operation.split('+').any(|kind| kind == op))
+            && self.node_id.as_ref().is_none_or(|id| entry.node_ids.contains(id))
+    }
+}
+
+/// The audit log kept next to the graph file, e.g. `graph.audit`.
+pub fn audit_path(graph_path: &Path) -> PathBuf {
+    graph_path.with_extension("audit")
+}
+
+pub fn append(graph_path: &Path, entry: &AuditEntry) -> Result<(), WillowError> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    files::append(&audit_path(graph_path), &line)?;
+    Ok(())
+}
+
+/// Every entry in the log, oldest first. Lines that do not parse, such as
+/// one cut short by a crash mid-append, are skipped.
+fn read_all(graph_path: &Path) -> Result<Vec<AuditEntry>, WillowError> {
+    let data = match files::read_to_string(&audit_path(graph_path)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(data
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!(error = %e, "skipping unreadable audit entry");
+                None
+            }
+        })
+        .collect())
+}
+
+/// The entries matching `query`, oldest first.
+pub fn read(graph_path: &Path, query: &AuditQuery) -> Result<Vec<AuditEntry>, WillowError> {
+    let mut entries: Vec<AuditEntry> = read_all(graph_path)?.into_iter().filter(|e| query.matches(e)).collect();
+    if let Some(limit) = query.limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+    Ok(entries)
+}
+
+/// Drop the entries made before `before`, rewriting the log. Returns the
+/// number dropped.
+pub fn prune(graph_path: &Path, before: DateTime<Utc>) -> Result<usize, WillowError> {
+    let entries = read_all(graph_path)?;
+    let kept: Vec<&AuditEntry> = entries.iter().filter(|e| e.timestamp >= before).collect();
+    let pruned = entries.len() - kept.len();
+    if pruned == 0 {
+        return Ok(0);
+    }
+    let mut data = Vec::new();
+    for entry in kept {
+        serde_json::to_writer(&mut data, entry)?;
+        data.push(b'\n');
+    }
+    let path = audit_path(graph_path);
+    let tmp = path.with_extension("audit.tmp");
+    files::write(&tmp, data)?;
+    files::rename(&tmp, &path)?;
+    Ok(pruned)
+}
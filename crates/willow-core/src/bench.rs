@@ -0,0 +1,8 @@
+//! What the `benches/` suite needs from the engine. The benches build as a
+//! separate crate, which cannot reach the private modules; this is not an
+//! API for anything else.
+
+pub use crate::model::{Graph, Node, NodeId};
+pub use crate::search::{search_nodes, SearchOptions};
+pub use crate::storage::{save_graph, StorageOptions};
+pub use crate::store::{GraphStore, NewNode, OpenOptions, WriteBehind};
@@ -0,0 +1,17 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: willow-mcp <graph.json>");
+        return ExitCode::FAILURE;
+    };
+    willow_core::init_tracing();
+    match willow_core::mcp::serve(Path::new(&path)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("willow-mcp: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::WillowError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// How a node's `content` string is interpreted. Structured formats store
+/// their payload as JSON in `content`, so search and history work unchanged.
+/// Markdown is free text whose syntax is ignored when searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFormat {
+    #[default]
+    Text,
+    Markdown,
+    /// `["item", ...]`
+    List,
+    /// `{"columns": ["a", ...], "rows": [["1", ...], ...]}`
+    Table,
+    /// `{"key": "value", ...}`
+    Record,
+}
+
+impl ContentFormat {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentFormat::Text => "text",
+            ContentFormat::Markdown => "markdown",
+            ContentFormat::List => "list",
+            ContentFormat::Table => "table",
+            ContentFormat::Record => "record",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<ContentFormat> {
+        match s {
+            "text" => Some(ContentFormat::Text),
+            "markdown" => Some(ContentFormat::Markdown),
+            "list" => Some(ContentFormat::List),
+            "table" => Some(ContentFormat::Table),
+            "record" => Some(ContentFormat::Record),
+            _ => None,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        *self == ContentFormat::Text
+    }
+
+    /// JSON payload formats, as opposed to free text (plain or markdown).
+    pub fn is_structured(&self) -> bool {
+        matches!(self, ContentFormat::List | ContentFormat::Table | ContentFormat::Record)
+    }
+
+    /// The text search scores against: markdown with its syntax stripped,
+    /// anything else unchanged.
+    pub fn searchable_text<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        match self {
+            ContentFormat::Markdown => Cow::Owned(strip_markdown(content)),
+            _ => Cow::Borrowed(content),
+        }
+    }
+
+    /// Check that `content` is a well-formed payload for this format.
+    pub fn validate(&self, content: &str) -> Result<(), WillowError> {
+        self.parse(content).map(|_| ())
+    }
+
+    /// Flatten a payload into addressable cells, e.g. `[2]`, `rows[1].price`, `name`.
+    fn parse(&self, content: &str) -> Result<BTreeMap<String, String>, WillowError> {
+        let invalid = |msg: &str| WillowError::InvalidContent(format!("{}: {msg}", self.as_str()));
+        if !self.is_structured() {
+            return Ok(BTreeMap::from([(String::new(), content.to_string())]));
+        }
+        let value: Value = serde_json::from_str(content).map_err(|e| invalid(&e.to_string()))?;
+        let mut cells = BTreeMap::new();
+        match self {
+            ContentFormat::Text | ContentFormat::Markdown => unreachable!(),
+            ContentFormat::List => {
+                let items = value.as_array().ok_or_else(|| invalid("expected an array"))?;
+                for (i, item) in items.iter().enumerate() {
+                    cells.insert(format!("[{i}]"), scalar(item).ok_or_else(|| invalid("items must be scalars"))?);
+                }
+            }
+            ContentFormat::Table => {
+                let columns: Vec<String> = value
+                    .get("columns")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| invalid("expected a \"columns\" array"))?
+                    .iter()
+                    .map(|c| c.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid("column names must be strings"))?;
+                let rows = value
+                    .get("rows")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| invalid("expected a \"rows\" array"))?;
+                for (r, row) in rows.iter().enumerate() {
+                    let row = row
+                        .as_array()
+                        .filter(|row| row.len() == columns.len())
+                        .ok_or_else(|| invalid(&format!("row {r} must have {} cells", columns.len())))?;
+                    for (column, cell) in columns.iter().zip(row) {
+                        let cell = scalar(cell).ok_or_else(|| invalid("cells must be scalars"))?;
+                        cells.insert(format!("rows[{r}].{column}"), cell);
+                    }
+                }
+            }
+            ContentFormat::Record => {
+                let fields = value.as_object().ok_or_else(|| invalid("expected an object"))?;
+                for (key, field) in fields {
+                    cells.insert(key.clone(), scalar(field).ok_or_else(|| invalid("values must be scalars"))?);
+                }
+            }
+        }
+        Ok(cells)
+    }
+}
+
+/// Plain text of a markdown document: heading, quote and list markers,
+/// emphasis and code marks, and link targets are dropped. Good enough for
+/// matching, not a renderer.
+pub fn strip_markdown(markdown: &str) -> String {
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        let mut line = line.trim_start();
+        line = line.trim_start_matches('#').trim_start();
+        while let Some(rest) = line.strip_prefix('>') {
+            line = rest.trim_start();
+        }
+        for marker in ["- ", "* ", "+ "] {
+            if let Some(rest) = line.strip_prefix(marker) {
+                line = rest;
+            }
+        }
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        if digits > 0 {
+            if let Some(rest) = line[digits..].strip_prefix(". ") {
+                line = rest;
+            }
+        }
+        lines.push(strip_inline(line));
+    }
+    lines.join("\n")
+}
+
+/// Drop `*`, `_`, `~` and backtick marks, `!` before images, and `(url)` after
+/// a `[label]`.
+fn strip_inline(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '_' | '~' | '`' | '[' => {}
+            '!' if chars.peek() == Some(&'[') => {}
+            ']' if chars.peek() == Some(&'(') => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            ']' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// One cell that differs between two payloads of the same structured format.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContentFieldChange {
+    pub path: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// Cell-level changes between two payloads. Empty for text, or when either
+/// side fails to parse (the caller falls back to whole-content comparison).
+pub fn diff_content(format: ContentFormat, old: &str, new: &str) -> Vec<ContentFieldChange> {
+    if !format.is_structured() {
+        return Vec::new();
+    }
+    let (Ok(old_cells), Ok(new_cells)) = (format.parse(old), format.parse(new)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<&String> = old_cells.keys().chain(new_cells.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+        .into_iter()
+        .filter(|p| old_cells.get(*p) != new_cells.get(*p))
+        .map(|p| ContentFieldChange {
+            path: p.clone(),
+            old_value: old_cells.get(p).cloned(),
+            new_value: new_cells.get(p).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_payloads() {
+        assert!(ContentFormat::List.validate(r#"["milk", "eggs"]"#).is_ok());
+        assert!(ContentFormat::List.validate(r#"{"a": 1}"#).is_err());
+        assert!(ContentFormat::Table.validate(r#"{"columns": ["a", "b"], "rows": [["1", "2"]]}"#).is_ok());
+        assert!(ContentFormat::Table.validate(r#"{"columns": ["a", "b"], "rows": [["1"]]}"#).is_err());
+        assert!(ContentFormat::Record.validate(r#"{"city": "Leeds", "since": 2019}"#).is_ok());
+        assert!(ContentFormat::Record.validate("plain text").is_err());
+        assert!(ContentFormat::Text.validate("anything").is_ok());
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        assert_eq!(
+            strip_markdown("## Food\n- My **favourite** is _pizza_\n> see [the menu](https://example.com)"),
+            "Food\nMy favourite is pizza\nsee the menu"
+        );
+        assert_eq!(strip_markdown("1. `cargo` ~~old~~ ![logo](a.png)"), "cargo old logo");
+    }
+
+    #[test]
+    fn test_diff_single_cell() {
+        let old = r#"{"columns": ["item", "price"], "rows": [["tea", "3"], ["cake", "4"]]}"#;
+        let new = r#"{"columns": ["item", "price"], "rows": [["tea", "3"], ["cake", "5"]]}"#;
+        assert_eq!(
+            diff_content(ContentFormat::Table, old, new),
+            vec![ContentFieldChange {
+                path: "rows[1].price".to_string(),
+                old_value: Some("4".to_string()),
+                new_value: Some("5".to_string()),
+            }]
+        );
+    }
+}
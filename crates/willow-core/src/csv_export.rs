@@ -0,0 +1,207 @@
+use crate::model::{Graph, Node, NodeId, Sensitivity};
+use crate::search::{self, SearchOptions};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+const NODE_COLUMNS: &[&str] = &[
+    "id",
+    "type",
+    "content",
+    "parent",
+    "path",
+    "created_at",
+    "updated_at",
+    "valid_from",
+    "valid_until",
+    "metadata",
+];
+
+const LINK_COLUMNS: &[&str] = &[
+    "id",
+    "from",
+    "to",
+    "from_content",
+    "to_content",
+    "relation",
+    "bidirectional",
+    "confidence",
+    "created_at",
+];
+
+/// Quote a field if it holds a separator, quote or line break (RFC 4180).
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn push_row(out: &mut String, fields: &[String]) {
+    let row: Vec<String> = fields.iter().map(|f| field(f)).collect();
+    out.push_str(&row.join(","));
+    out.push_str("\r\n");
+}
+
+fn header(columns: &[&str]) -> String {
+    let mut out = String::new();
+    push_row(&mut out, &columns.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+    out
+}
+
+fn date(d: Option<DateTime<Utc>>) -> String {
+    d.map(|d| d.to_rfc3339()).unwrap_or_default()
+}
+
+/// Every node in each profile tree, in breadth-first order, with subtrees
+/// above `max_sensitivity` left out. Archived nodes are included.
+fn exported_nodes(graph: &Graph, max_sensitivity: Sensitivity) -> Vec<&Node> {
+    let options = SearchOptions {
+        max_sensitivity,
+        include_archived: true,
+        ..SearchOptions::default()
+    };
+    std::iter::once(&graph.root_id)
+        .chain(graph.roots.values())
+        .flat_map(|root| search::visible_nodes(graph, root, &options))
+        .map(|(node, _)| node)
+        .collect()
+}
+
+/// One row per node: id, type, content, primary parent, the root-to-node
+/// path joined with " > ", creation, update and validity dates, and the
+/// metadata as a JSON object.
+pub fn nodes_csv(graph: &Graph, max_sensitivity: Sensitivity) -> String {
+    let mut out = header(NODE_COLUMNS);
+    for node in exported_nodes(graph, max_sensitivity) {
+        let temporal = node.temporal.as_ref();
+        let metadata: BTreeMap<&String, &String> = node.metadata.iter().collect();
+        push_row(
+            &mut out,
+            &[
+                node.id.0.to_string(),
+                node.node_type.as_str().to_string(),
+                node.content.clone(),
+                node.parent_id.as_ref().map(|p| p.0.to_string()).unwrap_or_default(),
+                graph.content_path(&node.id).join(" > "),
+                node.created_at.to_rfc3339(),
+                node.updated_at.to_rfc3339(),
+                date(temporal.and_then(|t| t.valid_from)),
+                date(temporal.and_then(|t| t.valid_until)),
+                serde_json::to_string(&metadata).unwrap_or_default(),
+            ],
+        );
+    }
+    out
+}
+
+/// One row per link between exported nodes, in link id order, with both
+/// endpoints' content alongside their ids.
+pub fn links_csv(graph: &Graph, max_sensitivity: Sensitivity) -> String {
+    let nodes: HashMap<&NodeId, &Node> = exported_nodes(graph, max_sensitivity)
+        .into_iter()
+        .map(|n| (&n.id, n))
+        .collect();
+    let mut links: Vec<_> = graph.links.values().collect();
+    links.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut out = header(LINK_COLUMNS);
+    for link in links {
+        let (Some(from), Some(to)) = (nodes.get(&link.from_node), nodes.get(&link.to_node)) else {
+            continue;
+        };
+        push_row(
+            &mut out,
+            &[
+                link.id.0.to_string(),
+                from.id.0.to_string(),
+                to.id.0.to_string(),
+                from.content.clone(),
+                to.content.clone(),
+                link.relation.clone(),
+                link.bidirectional.to_string(),
+                link.confidence.as_ref().map(|c| c.as_str().to_string()).unwrap_or_default(),
+                link.created_at.to_rfc3339(),
+            ],
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::model::{ContentFormat, Link, LinkId, NodeType};
+    use crate::storage::create_default_graph;
+
+    fn add_node(graph: &mut Graph, id: &str, content: &str, sensitivity: Sensitivity) -> NodeId {
+        let now = Utc::now();
+        let node_id = NodeId(id.into());
+        let node = Node {
+            id: node_id.clone(),
+            node_type: NodeType::Detail,
+            content: content.to_string(),
+            parent_id: Some(graph.root_id.clone()),
+            children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
+            metadata: HashMap::from([("source".to_string(), "chat".to_string())]),
+            previous_values: Vec::new(),
+            temporal: None,
+            created_at: now,
+            updated_at: now,
+            created_by: None,
+            updated_by: None,
+        };
+        graph.nodes.insert(node_id.clone(), node.into());
+        let root_id = graph.root_id.clone();
+        graph.nodes.get_mut(&root_id).map(Arc::make_mut).unwrap().children.push(node_id.clone());
+        node_id
+    }
+
+    #[test]
+    fn test_nodes_csv_quotes_fields() {
+        let mut graph = create_default_graph();
+        add_node(&mut graph, "n1", "Said \"hi\", then left", Sensitivity::Normal);
+        add_node(&mut graph, "n2", "Diagnosis", Sensitivity::Secret);
+
+        let csv = nodes_csv(&graph, Sensitivity::Normal);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], NODE_COLUMNS.join(","));
+        assert!(rows[2].starts_with("n1,detail,\"Said \"\"hi\"\", then left\",root,\"User > Said \"\"hi\"\", then left\","));
+        assert!(rows[2].ends_with(",\"{\"\"source\"\":\"\"chat\"\"}\""));
+    }
+
+    #[test]
+    fn test_links_csv_skips_hidden_endpoints() {
+        let mut graph = create_default_graph();
+        let a = add_node(&mut graph, "a", "Alice", Sensitivity::Normal);
+        let b = add_node(&mut graph, "b", "Bob", Sensitivity::Normal);
+        let c = add_node(&mut graph, "c", "Carol", Sensitivity::Sensitive);
+        for (id, to) in [("l1", &b), ("l2", &c)] {
+            let link = Link {
+                id: LinkId(id.into()),
+                from_node: a.clone(),
+                to_node: to.clone(),
+                relation: "knows".to_string(),
+                bidirectional: true,
+                confidence: None,
+                created_at: Utc::now(),
+            };
+            graph.links.insert(link.id.clone(), link);
+        }
+
+        let csv = links_csv(&graph, Sensitivity::Normal);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].starts_with("l1,a,b,Alice,Bob,knows,true,,"));
+        assert_eq!(links_csv(&graph, Sensitivity::Sensitive).lines().count(), 3);
+    }
+}
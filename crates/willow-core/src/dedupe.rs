@@ -0,0 +1,83 @@
+use crate::model::{Graph, NodeId};
+use std::collections::{HashMap, HashSet};
+
+/// Two nodes whose content is similar enough to be merge candidates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicatePair {
+    pub a: NodeId,
+    pub b: NodeId,
+    /// Trigram Jaccard similarity in `0.0..=1.0`.
+    pub similarity: f64,
+}
+
+type Trigrams = HashSet<[char; 3]>;
+
+/// Character trigrams of the lowercased, whitespace-normalised text, padded
+/// so that word boundaries contribute.
+fn trigrams(text: &str) -> Trigrams {
+    let normalised = text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let chars: Vec<char> = "  ".chars().chain(normalised.chars()).chain(" ".chars()).collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+fn jaccard(a: &Trigrams, b: &Trigrams) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Pairs of nodes with the same type and primary parent whose similarity is
+/// at least `threshold`, most similar first. The root is never reported.
+pub fn find_duplicates(graph: &Graph, threshold: f64) -> Vec<DuplicatePair> {
+    let mut groups: HashMap<(&str, &NodeId), Vec<(&NodeId, Trigrams)>> = HashMap::new();
+    for node in graph.nodes.values() {
+        let Some(parent) = &node.parent_id else { continue };
+        groups
+            .entry((node.node_type.as_str(), parent))
+            .or_default()
+            .push((&node.id, trigrams(&node.content)));
+    }
+
+    let mut pairs = Vec::new();
+    for mut members in groups.into_values() {
+        members.sort_by(|x, y| x.0 .0.cmp(&y.0 .0));
+        for (i, (a, a_grams)) in members.iter().enumerate() {
+            for (b, b_grams) in &members[i + 1..] {
+                let similarity = jaccard(a_grams, b_grams);
+                if similarity >= threshold {
+                    pairs.push(DuplicatePair {
+                        a: (*a).clone(),
+                        b: (*b).clone(),
+                        similarity,
+                    });
+                }
+            }
+        }
+    }
+    pairs.sort_by(|x, y| {
+        y.similarity
+            .partial_cmp(&x.similarity)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| x.a.0.cmp(&y.a.0))
+            .then_with(|| x.b.0.cmp(&y.b.0))
+    });
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn similarity(a: &str, b: &str) -> f64 {
+        jaccard(&trigrams(a), &trigrams(b))
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("Loves hiking", "loves   HIKING"), 1.0);
+        assert!(similarity("Loves hiking", "Enjoys hiking") > 0.3);
+        assert_eq!(similarity("Loves hiking", "Works at Acme"), 0.0);
+    }
+}
@@ -8,15 +8,82 @@ pub enum WillowError {
     #[error("Link not found: {0}")]
     LinkNotFound(String),
 
+    #[error("Attachment not found: {0}")]
+    AttachmentNotFound(String),
+
     #[error("Cannot delete root node")]
     CannotDeleteRoot,
 
+    #[error("Profile not found: {0}")]
+    ProfileNotFound(String),
+
+    #[error("Profile already exists: {0}")]
+    ProfileExists(String),
+
+    #[error("Cannot clone root node")]
+    CannotCloneRoot,
+
+    #[error("Cannot archive root node")]
+    CannotArchiveRoot,
+
+    #[error("Cannot supersede root node")]
+    CannotSupersedeRoot,
+
+    #[error("Operation would create a cycle at node: {0}")]
+    WouldCreateCycle(String),
+
+    #[error("Cannot remove the last parent of node: {0}")]
+    LastParent(String),
+
+    #[error("Node is not an orphan: {0}")]
+    NotAnOrphan(String),
+
+    #[error("Parent node is not reachable from the root: {0}")]
+    OrphanParent(String),
+
     #[error("Parent node not found: {0}")]
     ParentNotFound(String),
 
+    #[error("Content is {len} bytes, over the {max} byte limit")]
+    ContentTooLarge {
+        len: usize,
+        max: usize,
+    },
+
+    #[error("{count} metadata entries, over the limit of {max}")]
+    TooManyMetadataEntries {
+        count: usize,
+        max: usize,
+    },
+
+    #[error("Metadata value for '{key}' is {len} bytes, over the {max} byte limit")]
+    MetadataValueTooLarge {
+        key: String,
+        len: usize,
+        max: usize,
+    },
+
+    #[error("Node {parent} would exceed the limit of {max} children")]
+    TooManyChildren {
+        parent: String,
+        max: usize,
+    },
+
+    #[error("Invalid content format: {0}")]
+    InvalidContentFormat(String),
+
+    #[error("Invalid structured content for {0}")]
+    InvalidContent(String),
+
     #[error("Invalid node type: {0}")]
     InvalidNodeType(String),
 
+    #[error("Schema violation for {0}")]
+    SchemaViolation(String),
+
+    #[error("Invalid relation: {0}")]
+    InvalidRelation(String),
+
     #[error("Duplicate link from {from} to {to} with relation '{relation}'")]
     DuplicateLink {
         from: String,
@@ -24,9 +91,87 @@ pub enum WillowError {
         relation: String,
     },
 
+    #[error("Invalid child order for {0}: ids must be a permutation of the current children")]
+    InvalidChildOrder(String),
+
+    #[error("Invalid sensitivity level: {0}")]
+    InvalidSensitivity(String),
+
+    #[error("Invalid stemmer language: {0}")]
+    InvalidLanguage(String),
+
+    #[error("Invalid ranking boost: {0}")]
+    InvalidBoost(String),
+
+    #[error("Invalid sort field: {0}")]
+    InvalidSortField(String),
+
+    #[error("Filter has no predicates")]
+    EmptyFilter,
+
+    #[error("Invalid expiry policy: {0}")]
+    InvalidExpiryPolicy(String),
+
+    #[error("Invalid rule: {0}")]
+    InvalidRule(String),
+
     #[error("Invalid confidence level: {0}")]
     InvalidConfidence(String),
 
+    #[error("Embedding provider failed: {0}")]
+    EmbeddingProvider(String),
+
+    #[error("Embedding has {actual} dimensions, expected {expected}")]
+    EmbeddingDimensions {
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("A transaction is already active")]
+    TransactionAlreadyActive,
+
+    #[error("No active transaction")]
+    NoActiveTransaction,
+
+    #[error("Operation not allowed while a transaction is active")]
+    TransactionActive,
+
+    #[error("Invalid {format} import: {reason}")]
+    InvalidImport {
+        format: String,
+        reason: String,
+    },
+
+    #[error("Graph file is locked by another process: {0}")]
+    GraphLocked(String),
+
+    #[error("Graph file was changed by another process; reload before saving: {0}")]
+    ExternalChange(String),
+
+    #[error("Graph file {path} is corrupt: {reason}")]
+    CorruptGraph {
+        path: String,
+        reason: String,
+    },
+
+    #[error("Invalid graph format: {0}")]
+    InvalidGraphFormat(String),
+
+    #[error("Store is open read-only")]
+    ReadOnly,
+
+    #[error("Store is closed")]
+    StoreClosed,
+
+    #[error("Store {0} is already open in this process with other options")]
+    OpenOptionsMismatch(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Invalid lock mode: {0}")]
+    InvalidLockMode(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -63,8 +208,24 @@ pub enum WillowError {
 
     #[error("VCS already initialized")]
     VcsAlreadyInitialized,
+
+    #[error("Invalid repository config: {0}")]
+    InvalidRepoConfig(String),
+
+    #[error("Object storage error: {0}")]
+    ObjectStorage(String),
+
+    #[error("Cannot watch the graph file: {0}")]
+    FileWatch(String),
+
+    #[error("Reloading would drop unsaved changes, pending changes or undo history; pass discard to drop them")]
+    UnsavedChanges,
+
+    #[error("A metrics recorder is already installed")]
+    MetricsRecorderInstalled,
 }
 
+#[cfg(feature = "napi")]
 impl From<WillowError> for napi::Error {
     fn from(e: WillowError) -> Self {
         napi::Error::from_reason(e.to_string())
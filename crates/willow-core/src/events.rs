@@ -0,0 +1,81 @@
+use crate::model::{Graph, LinkId, NodeId};
+use crate::search_index;
+use crate::vcs::types::{Change, CommitHash};
+use std::collections::HashSet;
+
+/// Something that happened to a store, for hosts keeping views in sync
+/// without re-reading the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreEvent {
+    NodeCreated(NodeId),
+    NodeUpdated(NodeId),
+    NodeDeleted(NodeId),
+    LinkAdded(LinkId),
+    LinkUpdated(LinkId),
+    LinkRemoved(LinkId),
+    CommitCreated(CommitHash),
+    BranchSwitched(String),
+    /// The graph was replaced wholesale, e.g. by a reload, checkout or
+    /// rolled-back transaction; everything shown may be stale.
+    Reset,
+}
+
+impl StoreEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StoreEvent::NodeCreated(_) => "node_created",
+            StoreEvent::NodeUpdated(_) => "node_updated",
+            StoreEvent::NodeDeleted(_) => "node_deleted",
+            StoreEvent::LinkAdded(_) => "link_added",
+            StoreEvent::LinkUpdated(_) => "link_updated",
+            StoreEvent::LinkRemoved(_) => "link_removed",
+            StoreEvent::CommitCreated(_) => "commit_created",
+            StoreEvent::BranchSwitched(_) => "branch_switched",
+            StoreEvent::Reset => "reset",
+        }
+    }
+}
+
+/// The events for one operation's changes: one per node touched, as
+/// `search_index::notify` reports them, then one per link, each judged by
+/// its state in `graph` after the operation.
+pub fn from_changes(graph: &Graph, changes: &[Change]) -> Vec<StoreEvent> {
+    let mut events = Vec::new();
+    let mut created_nodes = HashSet::new();
+    let mut added_links = HashSet::new();
+    for change in changes {
+        match change {
+            Change::CreateNode { node_id, .. } => {
+                created_nodes.insert(node_id);
+            }
+            Change::AddLink { link_id, .. } => {
+                added_links.insert(link_id);
+            }
+            _ => {}
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for id in changes.iter().flat_map(search_index::touched) {
+        if !seen.insert(id) {
+            continue;
+        }
+        events.push(match graph.nodes.contains_key(id) {
+            true if created_nodes.contains(id) => StoreEvent::NodeCreated(id.clone()),
+            true => StoreEvent::NodeUpdated(id.clone()),
+            false => StoreEvent::NodeDeleted(id.clone()),
+        });
+    }
+    let mut seen = HashSet::new();
+    for id in changes.iter().flat_map(search_index::touched_links) {
+        if !seen.insert(id) {
+            continue;
+        }
+        events.push(match graph.links.contains_key(id) {
+            true if added_links.contains(id) => StoreEvent::LinkAdded(id.clone()),
+            true => StoreEvent::LinkUpdated(id.clone()),
+            false => StoreEvent::LinkRemoved(id.clone()),
+        });
+    }
+    events
+}
@@ -0,0 +1,440 @@
+//! Where the engine's files live. Paths under a directory passed to `mount`
+//! are kept by that mount's `FileBackend`, e.g. `MemoryFiles` in a browser,
+//! which has no file system and persists them some other way; every other
+//! path is on the real file system. Graph files, sidecars, journals,
+//! attachments and repositories all go through here.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// A file opened for reading.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// When a file was last written and how long it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub modified: SystemTime,
+    pub len: u64,
+}
+
+/// The operations the engine needs on the files of one mount, named by
+/// their full paths. Missing files fail with `ErrorKind::NotFound`.
+pub trait FileBackend: Send + Sync {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>>;
+    /// Create or replace the file.
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+    /// Add to the end of the file, creating it if missing.
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+    /// Move a file, replacing any file at `to`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Make `to` a copy of `from` that stays intact when `from` is replaced
+    /// by a rename.
+    fn link(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    /// Whether there is a file or directory at `path`.
+    fn exists(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    /// The files directly in `dir`, in no particular order.
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+    fn stat(&self, path: &Path) -> io::Result<FileStat>;
+}
+
+/// The real file system.
+struct LocalFiles;
+
+impl FileBackend for LocalFiles {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        fs::write(path, data)
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        fs::OpenOptions::new().create(true).append(true).open(path)?.write_all(data)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    /// A hard link where the file system allows, otherwise a copy.
+    fn link(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if fs::hard_link(from, to).is_err() {
+            fs::copy(from, to)?;
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        Ok(files)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        let meta = fs::metadata(path)?;
+        Ok(FileStat {
+            modified: meta.modified()?,
+            len: meta.len(),
+        })
+    }
+}
+
+/// Files held in memory, for a process that loads them from and saves them
+/// to storage of its own. Directories exist once created or once a file is
+/// written inside them. Every write or removal is recorded until taken with
+/// `take_changes`.
+#[derive(Default)]
+pub struct MemoryFiles {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    files: BTreeMap<PathBuf, MemoryFile>,
+    dirs: BTreeSet<PathBuf>,
+    /// Paths written or removed since the last `take_changes`.
+    changed: BTreeSet<PathBuf>,
+    /// Counts writes, so each version of a file has its own `modified`.
+    writes: u64,
+}
+
+struct MemoryFile {
+    data: Arc<Vec<u8>>,
+    modified: SystemTime,
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, path.display().to_string())
+}
+
+/// Whether any key of `map` lies strictly under `dir`. Keys under a
+/// directory sort right after it.
+fn has_under<V>(map: &BTreeMap<PathBuf, V>, dir: &Path) -> bool {
+    map.range::<Path, _>((Bound::Excluded(dir), Bound::Unbounded))
+        .next()
+        .is_some_and(|(path, _)| path.starts_with(dir))
+}
+
+impl MemoryState {
+    fn file(&self, path: &Path) -> io::Result<&MemoryFile> {
+        self.files.get(path).ok_or_else(|| not_found(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.dirs.iter().any(|dir| dir.starts_with(path)) || has_under(&self.files, path)
+    }
+
+    fn put(&mut self, path: &Path, data: Arc<Vec<u8>>) {
+        // Not a clock: `SystemTime::now` is unavailable in a browser, and
+        // only changes between stamps matter.
+        self.writes += 1;
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_nanos(self.writes);
+        self.files.insert(path.to_path_buf(), MemoryFile { data, modified });
+        self.changed.insert(path.to_path_buf());
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<Arc<Vec<u8>>> {
+        let file = self.files.remove(path).ok_or_else(|| not_found(path))?;
+        self.changed.insert(path.to_path_buf());
+        Ok(file.data)
+    }
+}
+
+impl MemoryFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Add a file loaded from elsewhere, without recording it as a change.
+    pub fn insert(&self, path: impl Into<PathBuf>, data: Vec<u8>) {
+        let path = path.into();
+        let mut state = self.state();
+        state.put(&path, Arc::new(data));
+        state.changed.remove(&path);
+    }
+
+    /// Every path written or removed since the last call, with its contents,
+    /// or `None` if it is gone.
+    pub fn take_changes(&self) -> Vec<(PathBuf, Option<Arc<Vec<u8>>>)> {
+        let mut state = self.state();
+        let changed = std::mem::take(&mut state.changed);
+        changed
+            .into_iter()
+            .map(|path| {
+                let data = state.files.get(&path).map(|file| file.data.clone());
+                (path, data)
+            })
+            .collect()
+    }
+}
+
+impl FileBackend for MemoryFiles {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.state().file(path)?.data.to_vec())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.state().put(path, Arc::new(data.to_vec()));
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut state = self.state();
+        let mut contents = match state.files.remove(path) {
+            Some(file) => file.data,
+            None => Arc::default(),
+        };
+        Arc::make_mut(&mut contents).extend_from_slice(data);
+        state.put(path, contents);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        let data = state.remove(from)?;
+        state.put(to, data);
+        Ok(())
+    }
+
+    fn link(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        let data = state.file(from)?.data.clone();
+        state.put(to, data);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.state().remove(path).map(drop)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.state().dirs.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let state = self.state();
+        state.files.contains_key(path) || state.is_dir(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.state().is_dir(path)
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let state = self.state();
+        Ok(state
+            .files
+            .range::<Path, _>((Bound::Excluded(dir), Bound::Unbounded))
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(dir))
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        let state = self.state();
+        let file = state.file(path)?;
+        Ok(FileStat {
+            modified: file.modified,
+            len: file.data.len() as u64,
+        })
+    }
+}
+
+/// Mounted directories and their backends, the most recent first.
+static MOUNTS: Mutex<Vec<(PathBuf, Arc<dyn FileBackend>)>> = Mutex::new(Vec::new());
+
+/// Keep the files under `dir` in `backend` until `unmount`, replacing any
+/// backend mounted at `dir` before. The deepest mount holding a path wins.
+pub fn mount(dir: impl Into<PathBuf>, backend: Arc<dyn FileBackend>) {
+    let dir = dir.into();
+    let mut mounts = MOUNTS.lock().unwrap_or_else(PoisonError::into_inner);
+    mounts.retain(|(mounted, _)| *mounted != dir);
+    mounts.push((dir, backend));
+    mounts.sort_by_key(|(mounted, _)| std::cmp::Reverse(mounted.components().count()));
+}
+
+/// Stop keeping the files under `dir` in its backend, returning it.
+pub fn unmount(dir: &Path) -> Option<Arc<dyn FileBackend>> {
+    let mut mounts = MOUNTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let i = mounts.iter().position(|(mounted, _)| mounted == dir)?;
+    Some(mounts.remove(i).1)
+}
+
+fn mounted(path: &Path) -> Option<Arc<dyn FileBackend>> {
+    let mounts = MOUNTS.lock().unwrap_or_else(PoisonError::into_inner);
+    mounts.iter().find(|(dir, _)| path.starts_with(dir)).map(|(_, backend)| backend.clone())
+}
+
+/// Whether `path` is kept by a mounted backend rather than on disk, where
+/// file locks and file watching mean nothing.
+pub fn is_mounted(path: &Path) -> bool {
+    mounted(path).is_some()
+}
+
+fn with<R>(path: &Path, f: impl FnOnce(&dyn FileBackend) -> R) -> R {
+    match mounted(path) {
+        Some(backend) => f(&*backend),
+        None => f(&LocalFiles),
+    }
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    with(path, |b| b.read(path))
+}
+
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub fn open(path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+    with(path, |b| b.open(path))
+}
+
+pub fn write(path: &Path, data: impl AsRef<[u8]>) -> io::Result<()> {
+    with(path, |b| b.write(path, data.as_ref()))
+}
+
+pub fn append(path: &Path, data: &[u8]) -> io::Result<()> {
+    with(path, |b| b.append(path, data))
+}
+
+/// `from` and `to` must be under the same mount.
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    with(from, |b| b.rename(from, to))
+}
+
+/// `from` and `to` must be under the same mount.
+pub fn link(from: &Path, to: &Path) -> io::Result<()> {
+    with(from, |b| b.link(from, to))
+}
+
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    with(path, |b| b.remove_file(path))
+}
+
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    with(path, |b| b.create_dir_all(path))
+}
+
+pub fn exists(path: &Path) -> bool {
+    with(path, |b| b.exists(path))
+}
+
+pub fn is_dir(path: &Path) -> bool {
+    with(path, |b| b.is_dir(path))
+}
+
+pub fn list(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    with(dir, |b| b.list(dir))
+}
+
+pub fn stat(path: &Path) -> io::Result<FileStat> {
+    with(path, |b| b.stat(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_files_record_their_changes() {
+        let files = MemoryFiles::new();
+        let dir = Path::new("/notes");
+        files.insert(dir.join("graph.json"), b"{}".to_vec());
+        assert!(files.take_changes().is_empty());
+        assert!(files.is_dir(dir) && files.exists(&dir.join("graph.json")));
+
+        files.append(&dir.join("graph.journal"), b"a\n").unwrap();
+        files.append(&dir.join("graph.journal"), b"b\n").unwrap();
+        files.write(&dir.join("repo/HEAD"), b"ref").unwrap();
+        let before = files.stat(&dir.join("graph.json")).unwrap();
+        files.write(&dir.join("graph.tmp"), b"{\"v\":2}").unwrap();
+        files.rename(&dir.join("graph.tmp"), &dir.join("graph.json")).unwrap();
+        assert_ne!(files.stat(&dir.join("graph.json")).unwrap(), before);
+        assert_eq!(files.read(&dir.join("graph.journal")).unwrap(), b"a\nb\n");
+        assert!(files.is_dir(&dir.join("repo")) && !files.is_dir(&dir.join("graph.json")));
+
+        let mut listed = files.list(dir).unwrap();
+        listed.sort();
+        assert_eq!(listed, [dir.join("graph.journal"), dir.join("graph.json")]);
+        let changes: Vec<(PathBuf, bool)> =
+            files.take_changes().into_iter().map(|(path, data)| (path, data.is_some())).collect();
+        assert_eq!(
+            changes,
+            [
+                (dir.join("graph.journal"), true),
+                (dir.join("graph.json"), true),
+                (dir.join("graph.tmp"), false),
+                (dir.join("repo/HEAD"), true),
+            ]
+        );
+        assert_eq!(files.remove_file(&dir.join("graph.tmp")).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_mounted_paths_leave_the_disk_alone() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mount_dir = dir.path().join("mounted");
+        let memory = Arc::new(MemoryFiles::new());
+        mount(&mount_dir, memory.clone());
+
+        write(&mount_dir.join("a.json"), "a").unwrap();
+        write(&dir.path().join("b.json"), "b").unwrap();
+        assert!(is_mounted(&mount_dir.join("a.json")) && !is_mounted(&dir.path().join("b.json")));
+        assert_eq!(read_to_string(&mount_dir.join("a.json")).unwrap(), "a");
+        assert!(!mount_dir.exists());
+        assert_eq!(memory.read(&mount_dir.join("a.json")).unwrap(), b"a");
+
+        assert!(unmount(&mount_dir).is_some());
+        assert!(!exists(&mount_dir.join("a.json")));
+        assert_eq!(read_to_string(&dir.path().join("b.json")).unwrap(), "b");
+    }
+}
@@ -0,0 +1,325 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// The longest status or header line a response may send.
+const MAX_LINE: u64 = 16 * 1024;
+
+/// The parts of an `http[s]://host[:port][/path]` URL a request needs.
+/// `https://` URLs are only reachable with the `tls` feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HttpUrl<'a> {
+    /// Whether the scheme is `https`.
+    pub tls: bool,
+    pub host: &'a str,
+    pub port: u16,
+    /// Starts with '/'; may carry a query string.
+    pub path: &'a str,
+}
+
+impl HttpUrl<'_> {
+    /// The Host header: the host, with the port unless it is the
+    /// scheme's default.
+    pub fn authority(&self) -> String {
+        match (self.tls, self.port) {
+            (false, 80) | (true, 443) => self.host.to_string(),
+            (_, port) => format!("{}:{port}", self.host),
+        }
+    }
+
+    /// Whether the host is `localhost` or a loopback address, so plain
+    /// HTTP to it never leaves the machine.
+    pub fn is_loopback(&self) -> bool {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
+/// Whether HTTPS requests can be made: the `tls` feature is on.
+pub const TLS_SUPPORTED: bool = cfg!(feature = "tls");
+
+pub fn parse_url(url: &str) -> Option<HttpUrl<'_>> {
+    let (tls, rest) = match url.strip_prefix("https://") {
+        Some(rest) => (true, rest),
+        None => (false, url.strip_prefix("http://")?),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, if tls { 443 } else { 80 }),
+    };
+    (!host.is_empty()).then_some(HttpUrl { tls, host, port, path })
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+fn too_large(limit: usize) -> io::Error {
+    invalid(&format!("response body over {limit} bytes"))
+}
+
+/// Read one line into `line`, failing rather than buffering a line longer
+/// than `MAX_LINE`.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    line.clear();
+    let read = reader.take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(invalid("response line too long"));
+    }
+    Ok(read)
+}
+
+/// Append up to `length` bytes to `body`, growing it only as bytes arrive,
+/// so a lying length costs nothing. Fails if the stream ends early or
+/// `body` would outgrow `limit`.
+fn read_exactly(reader: &mut impl Read, body: &mut Vec<u8>, length: usize, limit: usize) -> io::Result<()> {
+    if length > limit.saturating_sub(body.len()) {
+        return Err(too_large(limit));
+    }
+    let start = body.len();
+    reader.take(length as u64).read_to_end(body)?;
+    if body.len() - start < length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "response body cut short"));
+    }
+    Ok(())
+}
+
+/// Read a response body framed by `length` or chunked encoding, or running
+/// to the end of the stream, of at most `limit` bytes.
+fn read_body(reader: &mut impl BufRead, chunked: bool, length: Option<usize>, limit: usize) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    if chunked {
+        let mut line = String::new();
+        loop {
+            read_line(reader, &mut line)?;
+            let size = usize::from_str_radix(line.trim().split(';').next().unwrap_or(""), 16)
+                .map_err(|_| invalid("bad chunk size"))?;
+            if size == 0 {
+                break;
+            }
+            read_exactly(reader, &mut body, size, limit)?;
+            read_line(reader, &mut line)?;
+        }
+    } else if let Some(length) = length {
+        read_exactly(reader, &mut body, length, limit)?;
+    } else {
+        reader.take(limit as u64 + 1).read_to_end(&mut body)?;
+        if body.len() > limit {
+            return Err(too_large(limit));
+        }
+    }
+    Ok(body)
+}
+
+/// Make one HTTP/1.1 request on its own connection. `headers` go out as
+/// given, after Host, Content-Length and Connection. A response body over
+/// `max_body` bytes fails the request.
+pub fn request(
+    method: &str,
+    url: &HttpUrl,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+    max_body: usize,
+) -> io::Result<Response> {
+    if url.tls {
+        return tls_request(method, url, headers, body, timeout, max_body);
+    }
+    let addr = (url.host.trim_start_matches('[').trim_end_matches(']'), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid("host did not resolve"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut head = format!(
+        "{method} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.authority(),
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let status: u16 = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+    let mut length = None;
+    let mut chunked = false;
+    loop {
+        if read_line(&mut reader, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = Some(value.parse::<usize>().map_err(|_| invalid("bad Content-Length"))?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+    let body = match (method, status) {
+        // No body, whatever the headers say.
+        ("HEAD", _) | (_, 204 | 304) => Vec::new(),
+        _ => read_body(&mut reader, chunked, length, max_body)?,
+    };
+    Ok(Response { status, body })
+}
+
+#[cfg(feature = "tls")]
+fn tls_request(
+    method: &str,
+    url: &HttpUrl,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+    max_body: usize,
+) -> io::Result<Response> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let uri = format!("https://{}{}", url.authority(), url.path);
+    let mut builder = ureq::http::Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let result = match body {
+        [] => builder.body(()).map(|request| agent.run(request)),
+        body => builder.body(body).map(|request| agent.run(request)),
+    };
+    let mut response = result
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .map_err(ureq::Error::into_io)?;
+    let status = response.status().as_u16();
+    let body = match method {
+        "HEAD" => Vec::new(),
+        _ => response
+            .body_mut()
+            .with_config()
+            .limit(max_body as u64)
+            .read_to_vec()
+            .map_err(ureq::Error::into_io)?,
+    };
+    Ok(Response { status, body })
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_request(_: &str, url: &HttpUrl, _: &[(&str, &str)], _: &[u8], _: Duration, _: usize) -> io::Result<Response> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: HTTPS needs willow-core built with the `tls` feature", url.authority()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = parse_url("http://localhost:8080/hooks/willow?x=1").unwrap();
+        assert_eq!((url.host, url.port, url.path), ("localhost", 8080, "/hooks/willow?x=1"));
+        assert_eq!(url.authority(), "localhost:8080");
+        let url = parse_url("http://example.com").unwrap();
+        assert_eq!((url.host, url.port, url.path, url.authority().as_str()), ("example.com", 80, "/", "example.com"));
+        assert_eq!(parse_url("http://[::1]:9000/x").map(|u| (u.host, u.port)), Some(("[::1]", 9000)));
+        assert_eq!(parse_url("http://[::1]/").map(|u| u.port), Some(80));
+        let url = parse_url("https://example.com/bucket").unwrap();
+        assert_eq!((url.tls, url.port, url.authority().as_str()), (true, 443, "example.com"));
+        assert_eq!(parse_url("https://example.com:8443/").unwrap().authority(), "example.com:8443");
+        assert!(parse_url("ftp://example.com/").is_none());
+        assert!(parse_url("http://:80/").is_none());
+    }
+
+    #[test]
+    fn test_is_loopback() {
+        for url in ["http://localhost:1/", "http://127.0.0.1/", "http://127.8.0.1/", "http://[::1]:80/"] {
+            assert!(parse_url(url).unwrap().is_loopback(), "{url}");
+        }
+        for url in ["http://example.com/", "http://10.0.0.1/", "http://localhost.example.com/", "http://[::2]/"] {
+            assert!(!parse_url(url).unwrap().is_loopback(), "{url}");
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn test_https_needs_tls_feature() {
+        let url = parse_url("https://127.0.0.1:1/").unwrap();
+        let err = request("GET", &url, &[], &[], Duration::from_secs(1), 1024).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    /// Answer one request on a loopback port with `response`, returning
+    /// the URL to send it to.
+    fn serve(response: &'static [u8]) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let _ = stream.write_all(response);
+        });
+        url
+    }
+
+    fn get(response: &'static [u8], max_body: usize) -> io::Result<Response> {
+        let url = serve(response);
+        request("GET", &parse_url(&url).unwrap(), &[], &[], Duration::from_secs(5), max_body)
+    }
+
+    #[test]
+    fn test_response_bodies() {
+        let ok = get(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello", 5).unwrap();
+        assert_eq!((ok.status, ok.body.as_slice()), (200, &b"hello"[..]));
+        let chunked = get(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n", 5);
+        assert_eq!(chunked.unwrap().body, b"abcde");
+        let unframed = get(b"HTTP/1.1 200 OK\r\n\r\nuntil close", 64).unwrap();
+        assert_eq!(unframed.body, b"until close");
+        assert!(get(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n", 0).unwrap().body.is_empty());
+    }
+
+    #[test]
+    fn test_response_bodies_over_the_limit_fail() {
+        // A huge Content-Length is refused before anything is allocated.
+        let huge = get(b"HTTP/1.1 200 OK\r\nContent-Length: 1000000000000\r\n\r\nx", 1024).unwrap_err();
+        assert_eq!(huge.kind(), io::ErrorKind::InvalidData);
+        let chunks = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n";
+        assert_eq!(get(chunks, 5).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(get(b"HTTP/1.1 200 OK\r\n\r\ntoo long", 3).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let short = get(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc", 1024).unwrap_err();
+        assert_eq!(short.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(get(b"HTTP/1.1 200 OK\r\nContent-Length: nope\r\n\r\n", 1024).is_err());
+        assert!(get(b"garbage\r\n\r\n", 1024).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use super::Outline;
+use crate::model::NodeType;
+
+/// The list marker and text of a bullet or numbered list item.
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        return rest.strip_prefix([' ', '\t']);
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 {
+        return None;
+    }
+    line[digits..].strip_prefix(['.', ')'])?.strip_prefix([' ', '\t'])
+}
+
+/// Leading whitespace width, counting a tab as four spaces.
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Parse a Markdown outline. Headings become categories nested by level;
+/// list items nest by indentation under the nearest heading, becoming
+/// entities if they have children and details otherwise. Other lines become
+/// details under the current heading, and `[ ]`/`[x]` task boxes are kept as
+/// a `done` metadata entry. Code blocks are skipped.
+pub fn from_markdown(text: &str) -> Outline {
+    let mut outline = Outline::default();
+    // (nesting key, node index): headings use their level, list items and
+    // paragraphs sit below any heading.
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code || trimmed.is_empty() {
+            continue;
+        }
+        let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+        let heading = (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ');
+        let (key, node_type, mut content) = if heading {
+            (hashes, NodeType::Category, trimmed[hashes..].trim())
+        } else if let Some(item) = list_item(line.trim_start()) {
+            (10 + indent_width(line), NodeType::Detail, item.trim())
+        } else {
+            (10, NodeType::Detail, trimmed)
+        };
+        let mut done = None;
+        if !heading {
+            for (prefix, value) in [("[ ] ", "false"), ("[x] ", "true"), ("[X] ", "true")] {
+                if let Some(rest) = content.strip_prefix(prefix) {
+                    content = rest.trim();
+                    done = Some(value);
+                }
+            }
+        }
+        if content.is_empty() {
+            continue;
+        }
+        while stack.last().is_some_and(|&(k, _)| k >= key) {
+            stack.pop();
+        }
+        let index = outline.push(stack.last().map(|&(_, i)| i), node_type, content.to_string());
+        if let Some(done) = done {
+            outline.nodes[index].metadata.insert("done".to_string(), done.to_string());
+        }
+        stack.push((key, index));
+    }
+    outline.type_by_shape();
+    outline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_nests_headings_and_bullets() {
+        let text = "# Work\n\
+                    Intro line\n\
+                    ## Projects\n\
+                    - Falcon\n\
+                    \x20 - Due in May\n\
+                    \x20 1. [x] Kickoff\n\
+                    - Heron\n\
+                    ```\n\
+                    - not a bullet\n\
+                    ```\n\
+                    # Home\n\
+                    * Garden\n";
+        let outline = from_markdown(text);
+        let summary: Vec<(Option<usize>, NodeType, &str)> = outline
+            .nodes
+            .iter()
+            .map(|n| (n.parent, n.node_type.clone(), n.content.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, NodeType::Category, "Work"),
+                (Some(0), NodeType::Detail, "Intro line"),
+                (Some(0), NodeType::Category, "Projects"),
+                (Some(2), NodeType::Entity, "Falcon"),
+                (Some(3), NodeType::Detail, "Due in May"),
+                (Some(3), NodeType::Detail, "Kickoff"),
+                (Some(2), NodeType::Detail, "Heron"),
+                (None, NodeType::Category, "Home"),
+                (Some(7), NodeType::Detail, "Garden"),
+            ]
+        );
+        assert_eq!(outline.nodes[5].metadata["done"], "true");
+    }
+}
@@ -0,0 +1,147 @@
+//! Importers turning other tools' documents into an `Outline` of nodes and
+//! links, one module per format.
+
+use crate::error::WillowError;
+use crate::model::NodeType;
+use std::collections::HashMap;
+
+mod markdown;
+mod obsidian;
+mod opml;
+mod roam;
+
+pub use markdown::from_markdown;
+pub use obsidian::from_obsidian;
+pub use opml::from_opml;
+pub use roam::from_roam_json;
+
+/// Relation of links made from `[[wikilinks]]`.
+const WIKILINK_RELATION: &str = "links_to";
+
+/// A node to be created by an import. Parents come before their children.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineNode {
+    /// Index of the parent in `Outline::nodes`; `None` hangs the node off
+    /// the import's target parent.
+    pub parent: Option<usize>,
+    pub node_type: NodeType,
+    pub content: String,
+    pub metadata: HashMap<String, String>,
+}
+
+/// A link between two imported nodes, by index into `Outline::nodes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineLink {
+    pub from: usize,
+    pub to: usize,
+    pub relation: String,
+}
+
+/// A parsed document, ready for `GraphStore::import_outline`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outline {
+    pub nodes: Vec<OutlineNode>,
+    pub links: Vec<OutlineLink>,
+}
+
+impl Outline {
+    /// Append a node, returning its index.
+    pub fn push(&mut self, parent: Option<usize>, node_type: NodeType, content: String) -> usize {
+        self.nodes.push(OutlineNode {
+            parent,
+            node_type,
+            content,
+            metadata: HashMap::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Add a link unless it would be a self-link or a repeat.
+    pub fn link(&mut self, from: usize, to: usize, relation: &str) {
+        let link = OutlineLink {
+            from,
+            to,
+            relation: relation.to_string(),
+        };
+        if from != to && !self.links.contains(&link) {
+            self.links.push(link);
+        }
+    }
+
+    /// Append `other`'s nodes and links, hanging its top-level nodes off
+    /// `parent`.
+    fn graft(&mut self, other: Outline, parent: usize) {
+        let offset = self.nodes.len();
+        for node in other.nodes {
+            self.nodes.push(OutlineNode {
+                parent: Some(node.parent.map_or(parent, |p| p + offset)),
+                ..node
+            });
+        }
+        for link in other.links {
+            self.link(link.from + offset, link.to + offset, &link.relation);
+        }
+    }
+
+    /// Type nodes by shape: those with children become entities, leaves
+    /// details. Nodes already typed otherwise, e.g. headings, are kept.
+    fn type_by_shape(&mut self) {
+        let mut has_children = vec![false; self.nodes.len()];
+        for node in &self.nodes {
+            if let Some(p) = node.parent {
+                has_children[p] = true;
+            }
+        }
+        for (node, parent) in self.nodes.iter_mut().zip(has_children) {
+            if node.node_type == NodeType::Detail && parent {
+                node.node_type = NodeType::Entity;
+            }
+        }
+    }
+}
+
+fn invalid(format: &str, reason: impl Into<String>) -> WillowError {
+    WillowError::InvalidImport {
+        format: format.to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// The snake_case metadata key for an imported attribute or property:
+/// `_note` becomes `note`, `xmlUrl` becomes `xml_url`.
+fn metadata_key(attribute: &str) -> String {
+    let mut key = String::new();
+    for c in attribute.trim_start_matches('_').chars() {
+        if c.is_ascii_uppercase() {
+            if !key.is_empty() {
+                key.push('_');
+            }
+            key.push(c.to_ascii_lowercase());
+        } else {
+            key.push(c);
+        }
+    }
+    key
+}
+
+/// Wikilink targets in `text`, without `#heading`/`|alias` parts, and the
+/// text with each link replaced by its alias or target.
+fn wikilinks(text: &str) -> (Vec<String>, String) {
+    let mut targets = Vec::new();
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else { break };
+        let inner = &rest[start + 2..start + 2 + len];
+        let (target, alias) = inner.split_once('|').unwrap_or((inner, inner));
+        let target = target.split('#').next().unwrap_or("").trim();
+        out.push_str(rest[..start].strip_suffix('!').unwrap_or(&rest[..start]));
+        out.push_str(alias.trim());
+        if !target.is_empty() {
+            targets.push(target.to_string());
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    out.push_str(rest);
+    (targets, out)
+}
@@ -0,0 +1,178 @@
+use super::{from_markdown, invalid, wikilinks, Outline, WIKILINK_RELATION};
+use crate::error::WillowError;
+use crate::model::NodeType;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Split YAML frontmatter off a note, returning its flat `key: value`
+/// entries and the body. List values, inline (`[a, b]`) or as `- item`
+/// lines, are joined with ", ", which is how `tags` are stored.
+fn split_frontmatter(text: &str) -> (HashMap<String, String>, &str) {
+    let mut metadata = HashMap::new();
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (metadata, text);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (metadata, text);
+    };
+    let body = rest[end + 4..].split_once('\n').map_or("", |(_, body)| body);
+    let unquote = |v: &str| v.trim().trim_matches(|c| c == '"' || c == '\'').to_string();
+    let mut list_key: Option<String> = None;
+    for line in rest[..end].lines() {
+        if let (Some(key), Some(item)) = (&list_key, line.trim().strip_prefix("- ")) {
+            let entry = metadata.entry(key.clone()).or_insert_with(String::new);
+            if !entry.is_empty() {
+                entry.push_str(", ");
+            }
+            entry.push_str(&unquote(item));
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let (key, value) = (key.trim().to_string(), value.trim());
+        list_key = value.is_empty().then(|| key.clone());
+        let value = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(items) => items.split(',').map(unquote).collect::<Vec<_>>().join(", "),
+            None => unquote(value),
+        };
+        if !value.is_empty() {
+            metadata.insert(key, value);
+        }
+    }
+    (metadata, body)
+}
+
+/// Directory entries sorted by name, skipping hidden ones such as
+/// `.obsidian` and `.trash`.
+fn sorted_entries(dir: &Path) -> Result<Vec<fs::DirEntry>, WillowError> {
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .collect();
+    entries.sort_by_key(|e| e.file_name());
+    Ok(entries)
+}
+
+/// Notes found while walking a vault, for resolving wikilinks afterwards.
+struct VaultNote {
+    index: usize,
+    /// Path inside the vault without the `.md` extension, lowercased.
+    path: String,
+    targets: Vec<String>,
+}
+
+fn walk_vault(
+    vault: &Path,
+    dir: &Path,
+    parent: Option<usize>,
+    outline: &mut Outline,
+    notes: &mut Vec<VaultNote>,
+) -> Result<(), WillowError> {
+    for entry in sorted_entries(dir)? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir() {
+            let index = outline.push(parent, NodeType::Category, name);
+            walk_vault(vault, &path, Some(index), outline, notes)?;
+            continue;
+        }
+        let Some(title) = name.strip_suffix(".md") else { continue };
+        let text = fs::read_to_string(&path)?;
+        let (mut metadata, body) = split_frontmatter(&text);
+        let relative = path.strip_prefix(vault).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        metadata.insert("obsidian_path".to_string(), relative.clone());
+
+        let index = outline.push(parent, NodeType::Entity, title.to_string());
+        outline.nodes[index].metadata = metadata;
+        let (targets, body) = wikilinks(body);
+        outline.graft(from_markdown(&body), index);
+        notes.push(VaultNote {
+            index,
+            path: relative.trim_end_matches(".md").to_lowercase(),
+            targets,
+        });
+    }
+    Ok(())
+}
+
+/// Read an Obsidian vault: folders become categories and notes entities
+/// named after their files, with the note body parsed by `from_markdown`
+/// beneath them. Frontmatter becomes metadata, alongside the note's
+/// `obsidian_path`. `[[wikilinks]]` become `links_to` links between notes,
+/// resolved by path or, failing that, by note name; links to missing notes
+/// are dropped and the link text is kept in the content.
+pub fn from_obsidian(vault_dir: &Path) -> Result<Outline, WillowError> {
+    if !vault_dir.is_dir() {
+        return Err(invalid("obsidian", format!("{} is not a directory", vault_dir.display())));
+    }
+    let mut outline = Outline::default();
+    let mut notes = Vec::new();
+    walk_vault(vault_dir, vault_dir, None, &mut outline, &mut notes)?;
+
+    let mut by_name: HashMap<String, usize> = HashMap::new();
+    let by_path: HashMap<&str, usize> = notes.iter().map(|n| (n.path.as_str(), n.index)).collect();
+    for note in &notes {
+        let name = note.path.rsplit('/').next().unwrap_or(&note.path);
+        by_name.entry(name.to_string()).or_insert(note.index);
+    }
+    for note in &notes {
+        for target in &note.targets {
+            let target = target.trim_end_matches(".md").to_lowercase();
+            if let Some(&to) = by_path.get(target.as_str()).or_else(|| by_name.get(&target)) {
+                outline.link(note.index, to, WIKILINK_RELATION);
+            }
+        }
+    }
+    Ok(outline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::OutlineLink;
+
+    #[test]
+    fn test_obsidian_vault_folders_frontmatter_and_wikilinks() {
+        let vault = tempfile::TempDir::new().unwrap();
+        let root = vault.path();
+        fs::create_dir_all(root.join(".obsidian")).unwrap();
+        fs::write(root.join(".obsidian/app.json"), "{}").unwrap();
+        fs::create_dir_all(root.join("People")).unwrap();
+        fs::write(root.join("Acme.md"), "Makes anvils\n").unwrap();
+        fs::write(
+            root.join("People/Alice.md"),
+            "---\ntags: [friend, work]\naliases:\n  - Al\n---\n- Works at [[Acme|the company]]\n- Knows [[Nobody]]\n",
+        )
+        .unwrap();
+
+        let outline = from_obsidian(root).unwrap();
+        let summary: Vec<(Option<usize>, NodeType, &str)> = outline
+            .nodes
+            .iter()
+            .map(|n| (n.parent, n.node_type.clone(), n.content.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, NodeType::Entity, "Acme"),
+                (Some(0), NodeType::Detail, "Makes anvils"),
+                (None, NodeType::Category, "People"),
+                (Some(2), NodeType::Entity, "Alice"),
+                (Some(3), NodeType::Detail, "Works at the company"),
+                (Some(3), NodeType::Detail, "Knows Nobody"),
+            ]
+        );
+        let alice = &outline.nodes[3].metadata;
+        assert_eq!((alice["tags"].as_str(), alice["aliases"].as_str()), ("friend, work", "Al"));
+        assert_eq!(alice["obsidian_path"], "People/Alice.md");
+        assert_eq!(
+            outline.links,
+            vec![OutlineLink {
+                from: 3,
+                to: 0,
+                relation: "links_to".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use super::{invalid, metadata_key, Outline};
+use crate::error::WillowError;
+use crate::model::NodeType;
+use roxmltree::{Document, Node, NodeId, ParsingOptions};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// The content and metadata of an `<outline>`. Content is its `text`, or
+/// its `title` if `text` is blank, in which case `title` is not repeated as
+/// metadata. Other non-empty attributes become metadata under
+/// `metadata_key`; when several map to the same key, e.g. `_note` and
+/// `note`, the one already spelled as the key wins, and otherwise the first
+/// in document order.
+fn outline_fields(element: Node<'_, '_>) -> (String, HashMap<String, String>) {
+    let text = element.attribute("text").unwrap_or("").trim();
+    let title = element.attribute("title").unwrap_or("").trim();
+    let content = if text.is_empty() { title } else { text };
+
+    let mut fields: HashMap<String, (&str, bool)> = HashMap::new();
+    for attribute in element.attributes() {
+        let (name, value) = (attribute.name(), attribute.value());
+        if name == "text" || (name == "title" && text.is_empty()) || value.is_empty() {
+            continue;
+        }
+        let key = metadata_key(name);
+        let exact = key == name;
+        match fields.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert((value, exact));
+            }
+            Entry::Occupied(mut entry) if exact && !entry.get().1 => {
+                entry.insert((value, exact));
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+    let metadata = fields.into_iter().map(|(key, (value, _))| (key, value.to_string())).collect();
+    (content.to_string(), metadata)
+}
+
+/// Parse the `<outline>` elements in the `<body>` of an OPML document, as
+/// exported by Workflowy, OmniOutliner or feed readers. Each becomes a node
+/// nested under its nearest enclosing outline; see `outline_fields` for how
+/// attributes map to content and metadata. Outlines with children become
+/// entities, leaves details. Documents that are not well-formed XML, or lack
+/// an `<opml>` root or its `<body>`, are rejected.
+pub fn from_opml(text: &str) -> Result<Outline, WillowError> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document = Document::parse_with_options(text, options).map_err(|e| invalid("opml", e.to_string()))?;
+    let root = document.root_element();
+    if !root.has_tag_name("opml") {
+        return Err(invalid("opml", format!("expected <opml>, found <{}>", root.tag_name().name())));
+    }
+    let body = root
+        .children()
+        .find(|n| n.has_tag_name("body"))
+        .ok_or_else(|| invalid("opml", "missing <body>"))?;
+
+    let mut outline = Outline::default();
+    // Walked in document order, so parents are pushed before their children.
+    let mut indices: HashMap<NodeId, usize> = HashMap::new();
+    for element in body.descendants().filter(|n| n.has_tag_name("outline")) {
+        let parent = element.ancestors().skip(1).find_map(|a| indices.get(&a.id()).copied());
+        let (content, metadata) = outline_fields(element);
+        let index = outline.push(parent, NodeType::Detail, content);
+        outline.nodes[index].metadata = metadata;
+        indices.insert(element.id(), index);
+    }
+    outline.type_by_shape();
+    Ok(outline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opml_maps_attributes_to_metadata() {
+        let text = r#"<?xml version="1.0"?>
+            <opml version="2.0">
+              <head><title>Feeds</title></head>
+              <body>
+                <!-- exported -->
+                <outline text="Tech &amp; Science" _note="Daily reads">
+                  <outline type="rss" text="Rust Blog" xmlUrl="https://blog.rust-lang.org/feed.xml"/>
+                  <outline title='Caf&#233; news' text=""/>
+                </outline>
+              </body>
+            </opml>"#;
+        let outline = from_opml(text).unwrap();
+        assert_eq!(outline.nodes.len(), 3);
+        assert_eq!(outline.nodes[0].content, "Tech & Science");
+        assert_eq!(outline.nodes[0].node_type, NodeType::Entity);
+        assert_eq!(outline.nodes[0].metadata["note"], "Daily reads");
+        assert_eq!(outline.nodes[1].parent, Some(0));
+        assert_eq!(outline.nodes[1].metadata["xml_url"], "https://blog.rust-lang.org/feed.xml");
+        assert_eq!(outline.nodes[1].metadata["type"], "rss");
+        assert_eq!(outline.nodes[2].content, "Café news");
+        assert!(!outline.nodes[2].metadata.contains_key("title"));
+    }
+
+    #[test]
+    fn test_opml_attribute_collisions_are_deterministic() {
+        let text = r#"<opml version="2.0"><body>
+            <outline text="A" title="Heading" _note="underscored" note="plain"
+                     xml_url="snake" xmlUrl="camel" _status="first" Status="second" empty=""/>
+            <outline text="B" note="plain" _note="underscored"/>
+        </body></opml>"#;
+        let outline = from_opml(text).unwrap();
+        let a = &outline.nodes[0].metadata;
+        assert_eq!(a["note"], "plain");
+        assert_eq!(a["xml_url"], "snake");
+        assert_eq!(a["status"], "first");
+        assert_eq!(a["title"], "Heading");
+        assert!(!a.contains_key("text") && !a.contains_key("empty"));
+        assert_eq!(a.len(), 4);
+        assert_eq!(outline.nodes[1].metadata["note"], "plain");
+    }
+
+    #[test]
+    fn test_opml_rejects_malformed_documents() {
+        let cases = [
+            "",
+            "not xml at all",
+            r#"<body><outline text="A"></body>"#,
+            r#"<rss><body/></rss>"#,
+            r#"<opml version="2.0"><head/></opml>"#,
+            r#"<opml><body><outline text="A"></body></opml>"#,
+            r#"<opml><body></outline></body></opml>"#,
+            r#"<opml><body><outline text=A/></body></opml>"#,
+            r#"<opml><body><outline text="A/></body></opml>"#,
+            r#"<opml><body><outline text="A" text="B"/></body></opml>"#,
+            r#"<opml><body><outline text="&bogus;"/></body></opml>"#,
+            r#"<opml><body><outline text="A"/></body></opml><opml/>"#,
+        ];
+        for text in cases {
+            match from_opml(text) {
+                Err(WillowError::InvalidImport { format, reason }) => {
+                    assert_eq!(format, "opml");
+                    assert!(!reason.is_empty(), "{text}");
+                }
+                other => panic!("{text:?} parsed as {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_opml_nests_outlines_inside_other_elements() {
+        let text = r#"<!DOCTYPE opml>
+            <opml><body>
+              <outline text="Parent"><group><outline text="Child"/></group></outline>
+              <outline text="   "/>
+            </body></opml>"#;
+        let outline = from_opml(text).unwrap();
+        let summary: Vec<(Option<usize>, NodeType, &str)> = outline
+            .nodes
+            .iter()
+            .map(|n| (n.parent, n.node_type.clone(), n.content.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, NodeType::Entity, "Parent"),
+                (Some(0), NodeType::Detail, "Child"),
+                (None, NodeType::Detail, ""),
+            ]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use super::{invalid, metadata_key, wikilinks, Outline, WIKILINK_RELATION};
+use crate::error::WillowError;
+use crate::model::NodeType;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Relation of links made from `((uid))` block references.
+const BLOCK_REF_RELATION: &str = "references";
+
+/// The first of `keys` holding a string, as Roam and Logseq name the same
+/// fields differently (`title`/`page-name`, `string`/`content`, `uid`/`id`).
+fn json_str<'a>(value: &'a Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|k| value.get(*k).and_then(Value::as_str))
+}
+
+/// Logseq block properties as metadata; list values are joined with ", ".
+fn properties(value: &Value) -> HashMap<String, String> {
+    let Some(props) = value.get("properties").and_then(Value::as_object) else {
+        return HashMap::new();
+    };
+    props
+        .iter()
+        .map(|(key, v)| {
+            let text = match v {
+                Value::String(s) => s.clone(),
+                Value::Array(items) => items
+                    .iter()
+                    .map(|i| i.as_str().map_or_else(|| i.to_string(), str::to_string))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                other => other.to_string(),
+            };
+            (metadata_key(key), text)
+        })
+        .collect()
+}
+
+/// Block text without Logseq's inline `key:: value` property lines, which
+/// `properties` already carries.
+fn block_text(raw: &str) -> String {
+    raw.lines()
+        .filter(|line| {
+            let line = line.trim();
+            !line.split_once(":: ").is_some_and(|(key, _)| !key.is_empty() && !key.contains(' '))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// `((uid))` block references in `text`.
+fn block_refs(text: &str) -> Vec<&str> {
+    let mut refs = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("((") {
+        let Some(len) = rest[start + 2..].find("))") else { break };
+        refs.push(&rest[start + 2..start + 2 + len]);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    refs
+}
+
+fn walk_blocks(value: &Value, parent: usize, outline: &mut Outline, uids: &mut HashMap<String, usize>) {
+    let Some(blocks) = value.get("children").and_then(Value::as_array) else { return };
+    for block in blocks {
+        let text = block_text(json_str(block, &["string", "content"]).unwrap_or(""));
+        // Empty blocks are dropped, their children moving up to the parent.
+        let index = if text.is_empty() {
+            parent
+        } else {
+            let index = outline.push(Some(parent), NodeType::Detail, text);
+            outline.nodes[index].metadata = properties(block);
+            if let Some(uid) = json_str(block, &["uid", "id"]) {
+                uids.insert(uid.to_string(), index);
+            }
+            index
+        };
+        walk_blocks(block, index, outline, uids);
+    }
+}
+
+/// Parse a Roam Research JSON export (an array of pages) or a Logseq JSON
+/// export (an object with a `blocks` array of pages). Pages become entities
+/// holding their title and blocks nodes beneath them, typed by shape;
+/// Logseq properties become metadata. `((uid))` block references become
+/// `references` links and `[[Page]]`/`#[[Page]]` page references
+/// `links_to` links, with the reference text replaced by the referenced
+/// block's text or the page title.
+pub fn from_roam_json(text: &str) -> Result<Outline, WillowError> {
+    let data: Value = serde_json::from_str(text).map_err(|e| invalid("roam", e.to_string()))?;
+    let pages = match &data {
+        Value::Array(pages) => pages,
+        Value::Object(export) => export
+            .get("blocks")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("roam", "expected a \"blocks\" array"))?,
+        _ => return Err(invalid("roam", "expected an array of pages")),
+    };
+
+    let mut outline = Outline::default();
+    let mut uids: HashMap<String, usize> = HashMap::new();
+    let mut titles: HashMap<String, usize> = HashMap::new();
+    for page in pages {
+        let Some(title) = json_str(page, &["title", "page-name"]) else { continue };
+        let index = outline.push(None, NodeType::Entity, title.to_string());
+        outline.nodes[index].metadata = properties(page);
+        titles.entry(title.to_lowercase()).or_insert(index);
+        walk_blocks(page, index, &mut outline, &mut uids);
+    }
+    outline.type_by_shape();
+
+    let raw: Vec<String> = outline.nodes.iter().map(|n| n.content.clone()).collect();
+    for (index, content) in raw.iter().enumerate() {
+        let mut content = content.clone();
+        for uid in block_refs(&raw[index]) {
+            if let Some(&to) = uids.get(uid) {
+                content = content.replace(&format!("(({uid}))"), &raw[to]);
+                outline.link(index, to, BLOCK_REF_RELATION);
+            }
+        }
+        let (targets, content) = wikilinks(&content.replace("#[[", "[["));
+        for target in targets {
+            if let Some(&to) = titles.get(&target.to_lowercase()) {
+                outline.link(index, to, WIKILINK_RELATION);
+            }
+        }
+        outline.nodes[index].content = content;
+    }
+    Ok(outline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roam_and_logseq_json_with_references() {
+        let roam = r#"[
+            {"title": "Acme", "uid": "p1", "children": [
+                {"string": "Makes anvils", "uid": "b1"}
+            ]},
+            {"title": "Alice", "children": [
+                {"string": "Works at [[Acme]]", "uid": "b2", "children": [
+                    {"string": "Because ((b1))", "uid": "b3"}
+                ]}
+            ]}
+        ]"#;
+        let outline = from_roam_json(roam).unwrap();
+        let summary: Vec<(Option<usize>, NodeType, &str)> = outline
+            .nodes
+            .iter()
+            .map(|n| (n.parent, n.node_type.clone(), n.content.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, NodeType::Entity, "Acme"),
+                (Some(0), NodeType::Detail, "Makes anvils"),
+                (None, NodeType::Entity, "Alice"),
+                (Some(2), NodeType::Entity, "Works at Acme"),
+                (Some(3), NodeType::Detail, "Because Makes anvils"),
+            ]
+        );
+        let links: Vec<(usize, usize, &str)> =
+            outline.links.iter().map(|l| (l.from, l.to, l.relation.as_str())).collect();
+        assert_eq!(links, vec![(3, 0, "links_to"), (4, 1, "references")]);
+
+        let logseq = r#"{"version": 1, "blocks": [
+            {"page-name": "Bob", "properties": {"tags": ["friend", "work"]}, "children": [
+                {"id": "x", "content": "status:: active\nLikes tea", "properties": {"status": "active"}, "children": []}
+            ]}
+        ]}"#;
+        let outline = from_roam_json(logseq).unwrap();
+        assert_eq!(outline.nodes[0].metadata["tags"], "friend, work");
+        assert_eq!(outline.nodes[1].content, "Likes tea");
+        assert_eq!(outline.nodes[1].metadata["status"], "active");
+        assert!(matches!(from_roam_json("{}"), Err(WillowError::InvalidImport { .. })));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::model::{Graph, LinkId, NodeId};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+/// A structural inconsistency in the graph. `parent_id` on a node is treated
+/// as authoritative; `children` lists are expected to mirror it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityIssue {
+    /// A node's parent (primary or extra) does not exist.
+    DanglingParent { node_id: NodeId, parent_id: NodeId },
+    /// A children entry points to a node that does not exist.
+    MissingChild { parent_id: NodeId, child_id: NodeId },
+    /// A node names a parent that does not list it as a child.
+    ChildNotListed { parent_id: NodeId, child_id: NodeId },
+    /// A parent lists a child that does not name it as a parent.
+    ChildNotClaimed { parent_id: NodeId, child_id: NodeId },
+    /// A child appears more than once in a children list.
+    DuplicateChild { parent_id: NodeId, child_id: NodeId },
+    /// A link references a node that does not exist.
+    DanglingLink { link_id: LinkId, node_id: NodeId },
+    /// A node cannot be reached from the root.
+    Unreachable { node_id: NodeId },
+}
+
+impl IntegrityIssue {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IntegrityIssue::DanglingParent { .. } => "dangling_parent",
+            IntegrityIssue::MissingChild { .. } => "missing_child",
+            IntegrityIssue::ChildNotListed { .. } => "child_not_listed",
+            IntegrityIssue::ChildNotClaimed { .. } => "child_not_claimed",
+            IntegrityIssue::DuplicateChild { .. } => "duplicate_child",
+            IntegrityIssue::DanglingLink { .. } => "dangling_link",
+            IntegrityIssue::Unreachable { .. } => "unreachable",
+        }
+    }
+
+    /// The node the issue is reported against and the other id involved, if any.
+    pub fn ids(&self) -> (&str, Option<&str>) {
+        match self {
+            IntegrityIssue::DanglingParent { node_id, parent_id } => (&node_id.0, Some(&parent_id.0)),
+            IntegrityIssue::MissingChild { parent_id, child_id }
+            | IntegrityIssue::ChildNotListed { parent_id, child_id }
+            | IntegrityIssue::ChildNotClaimed { parent_id, child_id }
+            | IntegrityIssue::DuplicateChild { parent_id, child_id } => (&parent_id.0, Some(&child_id.0)),
+            IntegrityIssue::DanglingLink { link_id, node_id } => (&link_id.0, Some(&node_id.0)),
+            IntegrityIssue::Unreachable { node_id } => (&node_id.0, None),
+        }
+    }
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityIssue::DanglingParent { node_id, parent_id } => {
+                write!(f, "node {} has missing parent {}", node_id.0, parent_id.0)
+            }
+            IntegrityIssue::MissingChild { parent_id, child_id } => {
+                write!(f, "node {} lists missing child {}", parent_id.0, child_id.0)
+            }
+            IntegrityIssue::ChildNotListed { parent_id, child_id } => {
+                write!(f, "node {} is not in the children of its parent {}", child_id.0, parent_id.0)
+            }
+            IntegrityIssue::ChildNotClaimed { parent_id, child_id } => {
+                write!(f, "node {} lists child {} which has a different parent", parent_id.0, child_id.0)
+            }
+            IntegrityIssue::DuplicateChild { parent_id, child_id } => {
+                write!(f, "node {} lists child {} more than once", parent_id.0, child_id.0)
+            }
+            IntegrityIssue::DanglingLink { link_id, node_id } => {
+                write!(f, "link {} references missing node {}", link_id.0, node_id.0)
+            }
+            IntegrityIssue::Unreachable { node_id } => {
+                write!(f, "node {} is not reachable from the root", node_id.0)
+            }
+        }
+    }
+}
+
+/// Outcome of `repair`: what was fixed and what still needs manual attention.
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    pub repaired: Vec<IntegrityIssue>,
+    pub remaining: Vec<IntegrityIssue>,
+}
+
+/// Check the graph for structural inconsistencies.
+pub fn validate(graph: &Graph) -> Vec<IntegrityIssue> {
+    let mut issues = Vec::new();
+
+    let mut node_ids: Vec<&NodeId> = graph.nodes.keys().collect();
+    node_ids.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for nid in &node_ids {
+        let node = &graph.nodes[*nid];
+        for pid in node.parents() {
+            match graph.nodes.get(pid) {
+                None => issues.push(IntegrityIssue::DanglingParent {
+                    node_id: node.id.clone(),
+                    parent_id: pid.clone(),
+                }),
+                Some(parent) if !parent.children.contains(&node.id) => {
+                    issues.push(IntegrityIssue::ChildNotListed {
+                        parent_id: pid.clone(),
+                        child_id: node.id.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for cid in &node.children {
+            if !seen.insert(cid) {
+                issues.push(IntegrityIssue::DuplicateChild {
+                    parent_id: node.id.clone(),
+                    child_id: cid.clone(),
+                });
+                continue;
+            }
+            match graph.nodes.get(cid) {
+                None => issues.push(IntegrityIssue::MissingChild {
+                    parent_id: node.id.clone(),
+                    child_id: cid.clone(),
+                }),
+                Some(child) if !child.parents().any(|p| p == &node.id) => {
+                    issues.push(IntegrityIssue::ChildNotClaimed {
+                        parent_id: node.id.clone(),
+                        child_id: cid.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    let mut link_ids: Vec<&LinkId> = graph.links.keys().collect();
+    link_ids.sort_by(|a, b| a.0.cmp(&b.0));
+    for lid in link_ids {
+        let link = &graph.links[lid];
+        for end in [&link.from_node, &link.to_node] {
+            if !graph.nodes.contains_key(end) {
+                issues.push(IntegrityIssue::DanglingLink {
+                    link_id: lid.clone(),
+                    node_id: end.clone(),
+                });
+            }
+        }
+    }
+
+    let reachable = reachable_from_root(graph);
+    for nid in node_ids {
+        if !reachable.contains(nid) {
+            issues.push(IntegrityIssue::Unreachable { node_id: nid.clone() });
+        }
+    }
+
+    issues
+}
+
+/// Tops of the subtrees that cannot be reached from the root: unreachable
+/// nodes not held by another unreachable node. Members of a detached cycle
+/// have no top and are only reported by `validate`.
+pub fn orphans(graph: &Graph) -> Vec<NodeId> {
+    let reachable = reachable_from_root(graph);
+    let mut result: Vec<NodeId> = graph
+        .nodes
+        .values()
+        .filter(|node| !reachable.contains(&node.id))
+        .filter(|node| {
+            !node.parents().any(|pid| {
+                graph
+                    .nodes
+                    .get(pid)
+                    .is_some_and(|p| p.children.contains(&node.id))
+            })
+        })
+        .map(|node| node.id.clone())
+        .collect();
+    result.sort_by(|a, b| a.0.cmp(&b.0));
+    result
+}
+
+/// Nodes reachable from the root or a profile root by following `parent_id`
+/// links in reverse, i.e. only through edges both sides agree on.
+pub fn reachable_from_root(graph: &Graph) -> HashSet<&NodeId> {
+    let mut reachable = HashSet::new();
+    let mut queue = VecDeque::new();
+    for root in std::iter::once(&graph.root_id).chain(graph.roots.values()) {
+        if let Some((root_id, _)) = graph.nodes.get_key_value(root) {
+            if reachable.insert(root_id) {
+                queue.push_back(root_id);
+            }
+        }
+    }
+    while let Some(id) = queue.pop_front() {
+        for cid in &graph.nodes[id].children {
+            let Some((cid, child)) = graph.nodes.get_key_value(cid) else { continue };
+            if child.parents().any(|p| p == id) && reachable.insert(cid) {
+                queue.push_back(cid);
+            }
+        }
+    }
+    reachable
+}
+
+/// Fix every reparable issue in place and report the rest. Children lists are
+/// rebuilt from `parent_id`, nodes whose only parent is missing move under the
+/// root, and dangling links are dropped. Unreachable cycles are left alone.
+pub fn repair(graph: &mut Graph) -> RepairReport {
+    let before = validate(graph);
+    if before.is_empty() {
+        return RepairReport::default();
+    }
+
+    // Dangling parents: drop missing extras, promote an extra or fall back to root.
+    let ids: Vec<NodeId> = graph.nodes.keys().cloned().collect();
+    for nid in &ids {
+        if graph.is_root(nid) {
+            continue;
+        }
+        let node = &graph.nodes[nid];
+        let mut parents: Vec<NodeId> = node
+            .parents()
+            .filter(|p| graph.nodes.contains_key(*p))
+            .cloned()
+            .collect();
+        if parents.is_empty() && node.parent_id.is_some() {
+            parents.push(graph.root_id.clone());
+        }
+        let node = graph.nodes.get_mut(nid).map(Arc::make_mut).unwrap();
+        if !parents.is_empty() {
+            node.parent_id = Some(parents.remove(0));
+        }
+        node.extra_parents = parents;
+    }
+
+    // Children lists: keep existing order for valid entries, then append missing ones.
+    for nid in &ids {
+        let claimed = |cid: &NodeId| graph.nodes.get(cid).is_some_and(|c| c.parents().any(|p| p == nid));
+        let mut seen = HashSet::new();
+        let mut children: Vec<NodeId> = graph.nodes[nid]
+            .children
+            .iter()
+            .filter(|cid| claimed(cid) && seen.insert((*cid).clone()))
+            .cloned()
+            .collect();
+        let mut missing: Vec<NodeId> = ids
+            .iter()
+            .filter(|cid| !seen.contains(*cid) && claimed(cid))
+            .cloned()
+            .collect();
+        missing.sort_by(|a, b| a.0.cmp(&b.0));
+        children.extend(missing);
+        graph.nodes.get_mut(nid).map(Arc::make_mut).unwrap().children = children;
+    }
+
+    let nodes = &graph.nodes;
+    graph
+        .links
+        .retain(|_, link| nodes.contains_key(&link.from_node) && nodes.contains_key(&link.to_node));
+
+    let remaining = validate(graph);
+    let repaired = before.into_iter().filter(|i| !remaining.contains(i)).collect();
+    RepairReport { repaired, remaining }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ContentFormat, Link, Node, NodeType, Sensitivity};
+    use chrono::Utc;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn node(id: &str, parent: Option<&str>, children: &[&str]) -> Node {
+        Node {
+            id: NodeId(id.into()),
+            node_type: NodeType::Detail,
+            content: id.to_string(),
+            parent_id: parent.map(|p| NodeId(p.into())),
+            children: children.iter().map(|c| NodeId((*c).into())).collect(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
+            metadata: HashMap::new(),
+            previous_values: Vec::new(),
+            temporal: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            created_by: None,
+            updated_by: None,
+        }
+    }
+
+    fn graph(nodes: Vec<Node>) -> Graph {
+        Graph {
+            root_id: NodeId("root".into()),
+            nodes: nodes.into_iter().map(|n| (n.id.clone(), Arc::new(n))).collect(),
+            links: HashMap::new(),
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_clean_graph() {
+        let g = graph(vec![node("root", None, &["a"]), node("a", Some("root"), &[])]);
+        assert!(validate(&g).is_empty());
+    }
+
+    #[test]
+    fn test_repair_fixes_asymmetry_and_reports_cycles() {
+        let mut g = graph(vec![
+            node("root", None, &["ghost", "b"]),
+            node("a", Some("root"), &[]),
+            node("b", Some("a"), &[]),
+            node("c", Some("missing"), &[]),
+            node("x", Some("y"), &["y"]),
+            node("y", Some("x"), &["x"]),
+        ]);
+        g.links.insert(
+            LinkId("l1".into()),
+            Link {
+                id: LinkId("l1".into()),
+                from_node: NodeId("a".into()),
+                to_node: NodeId("gone".into()),
+                relation: "related_to".to_string(),
+                bidirectional: false,
+                confidence: None,
+                created_at: Utc::now(),
+            },
+        );
+
+        let kinds: Vec<&str> = validate(&g).iter().map(|i| i.kind()).collect();
+        for kind in ["missing_child", "child_not_claimed", "child_not_listed", "dangling_parent", "dangling_link", "unreachable"] {
+            assert!(kinds.contains(&kind), "expected {kind} in {kinds:?}");
+        }
+
+        let report = repair(&mut g);
+        assert!(!report.repaired.is_empty());
+        assert_eq!(
+            report.remaining,
+            vec![
+                IntegrityIssue::Unreachable { node_id: NodeId("x".into()) },
+                IntegrityIssue::Unreachable { node_id: NodeId("y".into()) },
+            ]
+        );
+        let root = &g.nodes[&NodeId("root".into())];
+        assert_eq!(root.children, vec![NodeId("a".into()), NodeId("c".into())]);
+        assert_eq!(g.nodes[&NodeId("a".into())].children, vec![NodeId("b".into())]);
+        assert!(g.links.is_empty());
+    }
+
+    #[test]
+    fn test_orphans_lists_subtree_tops() {
+        let g = graph(vec![
+            node("root", None, &["a"]),
+            node("a", Some("root"), &[]),
+            node("b", Some("missing"), &["c"]),
+            node("c", Some("b"), &[]),
+            node("d", Some("root"), &[]),
+        ]);
+        assert_eq!(orphans(&g), vec![NodeId("b".into()), NodeId("d".into())]);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::error::WillowError;
+use crate::files;
+use crate::model::{Graph, Link, LinkId, Node, NodeId};
+use crate::rules::Rule;
+use crate::search_index;
+use crate::vcs::types::Change;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// The state one operation left behind: every node and link it touched as
+/// it now is, or its id if it is gone. Replaying entries in order over the
+/// last full graph file reproduces the graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    nodes: Vec<Node>,
+    deleted_nodes: Vec<NodeId>,
+    links: Vec<Link>,
+    deleted_links: Vec<LinkId>,
+    roots: BTreeMap<String, NodeId>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+/// Nodes a change may have altered beyond those `search_index::touched`
+/// reports: former parents, whose child lists lost an entry.
+pub fn former_parents(change: &Change) -> Vec<&NodeId> {
+    match change {
+        Change::ReparentNode { old_parent, .. } => old_parent.iter().collect(),
+        Change::RemoveParent { parent_id, .. } => vec![parent_id],
+        Change::DeleteNode { deleted_nodes, .. } => deleted_nodes
+            .iter()
+            .flat_map(|n| n.parent_id.iter().chain(&n.extra_parents))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl JournalEntry {
+    /// The entry for an operation that made `changes`, read off `graph` as it
+    /// is afterwards. Parents of touched nodes are included, as their child
+    /// lists may have changed too.
+    pub fn new(graph: &Graph, changes: &[Change]) -> Self {
+        let mut node_ids: BTreeSet<&NodeId> = BTreeSet::new();
+        for change in changes {
+            node_ids.extend(search_index::touched(change));
+            node_ids.extend(former_parents(change));
+        }
+        let parents: Vec<&NodeId> = node_ids
+            .iter()
+            .filter_map(|id| graph.nodes.get(*id))
+            .flat_map(|n| n.parent_id.iter().chain(&n.extra_parents))
+            .collect();
+        node_ids.extend(parents);
+        let link_ids: BTreeSet<&LinkId> = changes.iter().flat_map(search_index::touched_links).collect();
+
+        let mut entry = JournalEntry {
+            nodes: Vec::new(),
+            deleted_nodes: Vec::new(),
+            links: Vec::new(),
+            deleted_links: Vec::new(),
+            roots: graph.roots.clone(),
+            rules: graph.rules.clone(),
+        };
+        for id in node_ids {
+            match graph.nodes.get(id) {
+                Some(node) => entry.nodes.push(Node::clone(node)),
+                None => entry.deleted_nodes.push(id.clone()),
+            }
+        }
+        for id in link_ids {
+            match graph.links.get(id) {
+                Some(link) => entry.links.push(link.clone()),
+                None => entry.deleted_links.push(id.clone()),
+            }
+        }
+        entry
+    }
+
+    pub fn apply(self, graph: &mut Graph) {
+        for id in &self.deleted_nodes {
+            graph.nodes.remove(id);
+        }
+        for node in self.nodes {
+            graph.nodes.insert(node.id.clone(), node.into());
+        }
+        for id in &self.deleted_links {
+            graph.links.remove(id);
+        }
+        for link in self.links {
+            graph.links.insert(link.id.clone(), link);
+        }
+        graph.roots = self.roots;
+        graph.rules = self.rules;
+    }
+}
+
+/// The journal kept next to the graph file, e.g. `graph.journal`.
+pub fn journal_path(graph_path: &Path) -> PathBuf {
+    graph_path.with_extension("journal")
+}
+
+pub fn append(graph_path: &Path, entry: &JournalEntry) -> Result<(), WillowError> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    files::append(&journal_path(graph_path), &line)?;
+    Ok(())
+}
+
+/// Replay the journal over `graph`, returning the number of entries applied.
+/// A final line cut short by a crash mid-append is ignored.
+pub fn replay(graph_path: &Path, graph: &mut Graph) -> Result<usize, WillowError> {
+    let path = journal_path(graph_path);
+    if !files::exists(&path) {
+        return Ok(0);
+    }
+    let data = files::read_to_string(&path)?;
+    let lines: Vec<&str> = data.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut applied = 0;
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(entry) => {
+                entry.apply(graph);
+                applied += 1;
+            }
+            Err(e) if i + 1 == lines.len() && !data.ends_with('\n') => {
+                warn!(error = %e, "ignoring truncated journal entry");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    debug!(entries = applied, "journal replayed");
+    Ok(applied)
+}
+
+pub fn clear(graph_path: &Path) -> Result<(), WillowError> {
+    match files::remove_file(&journal_path(graph_path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
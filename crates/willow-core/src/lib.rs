@@ -1,13 +1,56 @@
+#[cfg(feature = "napi")]
 #[macro_use]
 extern crate napi_derive;
 
-mod error;
-mod model;
+mod analysis;
+mod attachments;
+mod audit;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod content;
+mod csv_export;
+mod dedupe;
+pub mod error;
+mod events;
+pub mod files;
+mod http;
+mod import;
+mod integrity;
+mod journal;
+mod limits;
+mod link_index;
+mod maintenance;
+#[cfg(feature = "mcp")]
+pub mod mcp;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod model;
+#[cfg(feature = "napi")]
 mod napi_exports;
-mod search;
+mod parallel;
+mod perf;
+mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+mod query_syntax;
+mod relations;
+mod retention;
+mod rules;
+mod schema;
+pub mod search;
+mod search_index;
+mod shards;
+mod snapshot;
 mod storage;
-mod store;
+mod suggest;
+pub mod store;
+mod temporal;
+mod vector;
+mod webhooks;
 pub mod vcs;
+#[cfg(feature = "wasm")]
+mod wasm_exports;
 
 use std::sync::Once;
 
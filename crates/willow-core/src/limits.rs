@@ -0,0 +1,88 @@
+use crate::error::WillowError;
+use crate::model::Node;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Size limits enforced by mutation APIs. Existing data over a limit is left
+/// alone; only new writes are rejected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Maximum content length in bytes.
+    pub max_content_len: usize,
+    pub max_metadata_entries: usize,
+    /// Maximum length in bytes of a single metadata value.
+    pub max_metadata_value_len: usize,
+    pub max_children: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_content_len: 64 * 1024,
+            max_metadata_entries: 64,
+            max_metadata_value_len: 4 * 1024,
+            max_children: 10_000,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_content(&self, content: &str) -> Result<(), WillowError> {
+        if content.len() > self.max_content_len {
+            return Err(WillowError::ContentTooLarge {
+                len: content.len(),
+                max: self.max_content_len,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_metadata(&self, metadata: &HashMap<String, String>) -> Result<(), WillowError> {
+        if metadata.len() > self.max_metadata_entries {
+            return Err(WillowError::TooManyMetadataEntries {
+                count: metadata.len(),
+                max: self.max_metadata_entries,
+            });
+        }
+        if let Some((key, value)) = metadata.iter().find(|(_, v)| v.len() > self.max_metadata_value_len) {
+            return Err(WillowError::MetadataValueTooLarge {
+                key: key.clone(),
+                len: value.len(),
+                max: self.max_metadata_value_len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that `parent` can take `additional` more children.
+    pub fn check_children(&self, parent: &Node, additional: usize) -> Result<(), WillowError> {
+        if parent.children.len() + additional > self.max_children {
+            return Err(WillowError::TooManyChildren {
+                parent: parent.id.0.to_string(),
+                max: self.max_children,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_limits() {
+        let limits = Limits {
+            max_metadata_entries: 2,
+            max_metadata_value_len: 4,
+            ..Limits::default()
+        };
+        let ok = HashMap::from([("a".to_string(), "1234".to_string())]);
+        assert!(limits.check_metadata(&ok).is_ok());
+        let long = HashMap::from([("a".to_string(), "12345".to_string())]);
+        assert!(matches!(limits.check_metadata(&long), Err(WillowError::MetadataValueTooLarge { .. })));
+        let many: HashMap<String, String> = (0..3).map(|i| (i.to_string(), String::new())).collect();
+        assert!(matches!(limits.check_metadata(&many), Err(WillowError::TooManyMetadataEntries { .. })));
+    }
+}
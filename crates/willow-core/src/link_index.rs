@@ -0,0 +1,68 @@
+use crate::model::{Graph, Link, LinkId, NodeId};
+use crate::vcs::types::Change;
+use std::collections::{HashMap, HashSet};
+
+/// The links at each node, by either end, so a node's links are found in
+/// O(degree) rather than by scanning every link. `GraphStore` keeps it
+/// current from the changes it records and rebuilds it when the graph is
+/// replaced.
+#[derive(Debug, Default)]
+pub struct LinkIndex {
+    by_node: HashMap<NodeId, HashSet<LinkId>>,
+}
+
+impl LinkIndex {
+    pub fn build(graph: &Graph) -> Self {
+        let mut index = LinkIndex::default();
+        for link in graph.links.values() {
+            index.insert(link);
+        }
+        index
+    }
+
+    fn insert(&mut self, link: &Link) {
+        for node_id in [&link.from_node, &link.to_node] {
+            self.by_node.entry(node_id.clone()).or_default().insert(link.id.clone());
+        }
+    }
+
+    fn remove(&mut self, link: &Link) {
+        for node_id in [&link.from_node, &link.to_node] {
+            if let Some(ids) = self.by_node.get_mut(node_id) {
+                ids.remove(&link.id);
+                if ids.is_empty() {
+                    self.by_node.remove(node_id);
+                }
+            }
+        }
+    }
+
+    /// Follow changes already applied to the graph.
+    pub fn apply(&mut self, changes: &[Change]) {
+        for change in changes {
+            match change {
+                Change::AddLink { link, .. } => self.insert(link),
+                Change::RemoveLink { link, .. } => self.remove(link),
+                Change::UpdateLink { old_link, new_link, .. } => {
+                    self.remove(old_link);
+                    self.insert(new_link);
+                }
+                Change::DeleteNode { deleted_links, .. } => {
+                    for link in deleted_links {
+                        self.remove(link);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Links from or to `node_id`, in no particular order.
+    pub fn links_of<'a>(&'a self, graph: &'a Graph, node_id: &NodeId) -> impl Iterator<Item = &'a Link> + 'a {
+        self.by_node
+            .get(node_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| graph.links.get(id))
+    }
+}
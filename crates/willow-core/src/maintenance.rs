@@ -0,0 +1,139 @@
+use crate::temporal::ExpiryPolicy;
+use crate::vcs::types::CommitHash;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A maintenance job `GraphStore::run_due_jobs` can run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "job", rename_all = "snake_case")]
+pub enum MaintenanceJob {
+    /// `GraphStore::sweep_expired` with this policy.
+    ExpireTemporal { policy: ExpiryPolicy },
+    /// `GraphStore::prune_history` under the retention policy.
+    PruneHistory,
+    /// `GraphStore::find_duplicates` at this similarity; reports only.
+    DetectDuplicates { threshold: f64 },
+    /// `Repository::gc`.
+    GcRepo,
+    /// Rebuild the link, prefix and registered search indexes from the graph.
+    RebuildIndexes,
+}
+
+impl MaintenanceJob {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceJob::ExpireTemporal { .. } => "expire_temporal",
+            MaintenanceJob::PruneHistory => "prune_history",
+            MaintenanceJob::DetectDuplicates { .. } => "detect_duplicates",
+            MaintenanceJob::GcRepo => "gc_repo",
+            MaintenanceJob::RebuildIndexes => "rebuild_indexes",
+        }
+    }
+}
+
+/// How one run of a job went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRun {
+    pub at: DateTime<Utc>,
+    /// What the job did, e.g. "3 node(s) expired", or why it failed.
+    pub summary: String,
+    pub failed: bool,
+    /// The Maintenance commit of the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<CommitHash>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub job: MaintenanceJob,
+    /// Hours from one run to the next; a job never run is due at once.
+    pub interval_hours: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<JobRun>,
+}
+
+impl ScheduledJob {
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.last_run
+            .as_ref()
+            .is_none_or(|run| now - run.at >= Duration::hours(i64::from(self.interval_hours)))
+    }
+}
+
+/// The registered jobs, at most one of each kind, kept in the repository.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceSchedule {
+    pub jobs: Vec<ScheduledJob>,
+}
+
+impl MaintenanceSchedule {
+    /// Add `job`, replacing a job of the same kind but keeping its last run.
+    pub fn register(&mut self, job: MaintenanceJob, interval_hours: u32) {
+        match self.jobs.iter_mut().find(|s| s.job.name() == job.name()) {
+            Some(scheduled) => {
+                scheduled.job = job;
+                scheduled.interval_hours = interval_hours;
+            }
+            None => self.jobs.push(ScheduledJob {
+                job,
+                interval_hours,
+                last_run: None,
+            }),
+        }
+    }
+
+    /// Remove the job named `name`. Returns whether there was one.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let before = self.jobs.len();
+        self.jobs.retain(|s| s.job.name() != name);
+        self.jobs.len() < before
+    }
+
+    /// Indexes of the jobs due at `now`, in registration order.
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<usize> {
+        (0..self.jobs.len()).filter(|&i| self.jobs[i].is_due(now)).collect()
+    }
+}
+
+/// What `GraphStore::run_due_jobs` did: the jobs it ran, by name, with
+/// their runs, which share the run's commit.
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceReport {
+    pub runs: Vec<(&'static str, JobRun)>,
+    pub commit: Option<CommitHash>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_due_and_register() {
+        let now = Utc::now();
+        let mut schedule = MaintenanceSchedule::default();
+        schedule.register(MaintenanceJob::GcRepo, 24);
+        schedule.register(MaintenanceJob::ExpireTemporal { policy: ExpiryPolicy::Flag }, 1);
+        assert_eq!(schedule.due(now), [0, 1]);
+
+        schedule.jobs[1].last_run = Some(JobRun {
+            at: now - Duration::minutes(30),
+            summary: String::new(),
+            failed: false,
+            commit: None,
+        });
+        assert_eq!(schedule.due(now), [0]);
+        assert_eq!(schedule.due(now + Duration::minutes(30)), [0, 1]);
+
+        // Re-registering changes the job but keeps when it last ran.
+        schedule.register(MaintenanceJob::ExpireTemporal { policy: ExpiryPolicy::Archive }, 1);
+        assert_eq!(schedule.jobs.len(), 2);
+        assert!(schedule.jobs[1].last_run.is_some());
+
+        let json = serde_json::to_string(&schedule).unwrap();
+        assert!(json.contains(r#""job":"expire_temporal","policy":"archive""#));
+        assert_eq!(serde_json::from_str::<MaintenanceSchedule>(&json).unwrap(), schedule);
+        assert!(schedule.unregister("gc_repo"));
+        assert!(!schedule.unregister("gc_repo"));
+    }
+}
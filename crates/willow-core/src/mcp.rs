@@ -0,0 +1,363 @@
+use crate::error::WillowError;
+use crate::query::Page;
+use crate::search::SearchOptions;
+use crate::storage::LockMode;
+use crate::store::{ContextOptions, GraphStore, OpenOptions};
+use crate::vcs::types::{CommitInput, CommitSource};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use tracing::{debug, info};
+
+/// MCP revision this server implements.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// Serve the graph at `path` as MCP tools, reading newline-delimited
+/// JSON-RPC from stdin and answering on stdout until stdin closes. The
+/// graph is locked for the server's lifetime.
+pub fn serve(path: &Path) -> Result<(), WillowError> {
+    let options = OpenOptions {
+        lock: Some(LockMode::Exclusive),
+        ..OpenOptions::default()
+    };
+    let mut store = GraphStore::open(path, &options)?;
+    info!(path = %path.display(), "MCP server started");
+
+    let stdout = std::io::stdout();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_message(&mut store, &line) {
+            let mut out = stdout.lock();
+            serde_json::to_writer(&mut out, &response)?;
+            out.write_all(b"\n")?;
+            out.flush()?;
+        }
+    }
+    store.close()
+}
+
+/// The response to one JSON-RPC message; notifications get none.
+fn handle_message(store: &mut GraphStore, line: &str) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return Some(error_response(Value::Null, -32700, &e.to_string())),
+    };
+    let id = request.get("id").cloned()?;
+    let method = request["method"].as_str().unwrap_or_default();
+    debug!(method, "mcp request");
+
+    Some(match method {
+        "initialize" => result_response(
+            id,
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "willow", "version": env!("CARGO_PKG_VERSION") },
+            }),
+        ),
+        "ping" => result_response(id, json!({})),
+        "tools/list" => result_response(id, json!({ "tools": tool_definitions() })),
+        "tools/call" => {
+            let name = request["params"]["name"].as_str().unwrap_or_default();
+            let args = &request["params"]["arguments"];
+            match call_tool(store, name, args) {
+                Some(Ok(value)) => result_response(id, tool_result(&value.to_string(), false)),
+                Some(Err(message)) => result_response(id, tool_result(&message, true)),
+                None => error_response(id, -32602, &format!("Unknown tool: {name}")),
+            }
+        }
+        _ => error_response(id, -32601, &format!("Unknown method: {method}")),
+    })
+}
+
+fn result_response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Value, code: i32, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn tool_result(text: &str, is_error: bool) -> Value {
+    json!({ "content": [{ "type": "text", "text": text }], "isError": is_error })
+}
+
+fn tool_definitions() -> Value {
+    let string = json!({ "type": "string" });
+    let metadata = json!({ "type": "object", "additionalProperties": { "type": "string" } });
+    json!([
+        {
+            "name": "search_memory",
+            "description": "Search stored memories by text. Returns the best matches with their path from the root.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "query": string, "limit": { "type": "integer", "minimum": 1 } },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "get_context",
+            "description": "A node with its ancestors, descendants and links. Defaults to the root.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "node_id": string, "depth": { "type": "integer", "minimum": 0 } },
+            },
+        },
+        {
+            "name": "create_node",
+            "description": "Store a new memory under an existing node.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "parent_id": string, "node_type": string, "content": string, "metadata": metadata },
+                "required": ["parent_id", "node_type", "content"],
+            },
+        },
+        {
+            "name": "update_node",
+            "description": "Change a memory's content or metadata; the old content is kept in its history.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "node_id": string, "content": string, "metadata": metadata, "reason": string },
+                "required": ["node_id"],
+            },
+        },
+        {
+            "name": "link_nodes",
+            "description": "Relate two memories, e.g. with 'related_to' or 'caused_by'.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "from_node": string,
+                    "to_node": string,
+                    "relation": string,
+                    "bidirectional": { "type": "boolean" },
+                    "confidence": { "type": "string", "enum": ["low", "medium", "high"] },
+                },
+                "required": ["from_node", "to_node", "relation"],
+            },
+        },
+        {
+            "name": "commit",
+            "description": "Record the changes made since the last commit in the graph's history.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "message": string },
+                "required": ["message"],
+            },
+        },
+    ])
+}
+
+/// Run a tool, or None if there is no tool called `name`. Errors are
+/// reported to the client as failed tool calls rather than protocol errors.
+fn call_tool(store: &mut GraphStore, name: &str, args: &Value) -> Option<Result<Value, String>> {
+    let result = match name {
+        "search_memory" => search_memory(store, args),
+        "get_context" => get_context(store, args),
+        "create_node" => create_node(store, args),
+        "update_node" => update_node(store, args),
+        "link_nodes" => link_nodes(store, args),
+        "commit" => commit(store, args),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn search_memory(store: &GraphStore, args: &Value) -> Result<Value, String> {
+    let page = Page {
+        offset: 0,
+        limit: Some(usize_arg(args, "limit")?.unwrap_or(DEFAULT_SEARCH_LIMIT)),
+    };
+    let found = store.search_nodes(str_arg(args, "query")?, &page, None, &SearchOptions::default());
+    let results: Vec<Value> = found
+        .results
+        .iter()
+        .map(|r| {
+            json!({
+                "node_id": r.node_id.0,
+                "node_type": r.node_type,
+                "content": r.content,
+                "score": r.score,
+                "path": r.path,
+            })
+        })
+        .collect();
+    Ok(json!({ "results": results, "total": found.total }))
+}
+
+fn get_context(store: &GraphStore, args: &Value) -> Result<Value, String> {
+    let node_id = opt_str_arg(args, "node_id")?.unwrap_or(&store.graph.root_id.0);
+    let depth = usize_arg(args, "depth")?.map(|d| d as u32);
+    let ctx = store
+        .get_context(node_id, depth, &ContextOptions::default())
+        .map_err(|e| e.to_string())?;
+    Ok(json!({
+        "node": ctx.node,
+        "ancestors": ctx.ancestors,
+        "descendants": ctx.descendants,
+        "links": ctx.links,
+        "truncated": ctx.truncated,
+    }))
+}
+
+fn create_node(store: &mut GraphStore, args: &Value) -> Result<Value, String> {
+    let node = store
+        .create_node(
+            str_arg(args, "parent_id")?,
+            str_arg(args, "node_type")?,
+            str_arg(args, "content")?,
+            metadata_arg(args)?,
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+    serde_json::to_value(node).map_err(|e| e.to_string())
+}
+
+fn update_node(store: &mut GraphStore, args: &Value) -> Result<Value, String> {
+    let node = store
+        .update_node(
+            str_arg(args, "node_id")?,
+            opt_str_arg(args, "content")?,
+            metadata_arg(args)?,
+            None,
+            opt_str_arg(args, "reason")?,
+        )
+        .map_err(|e| e.to_string())?;
+    serde_json::to_value(node).map_err(|e| e.to_string())
+}
+
+fn link_nodes(store: &mut GraphStore, args: &Value) -> Result<Value, String> {
+    let bidirectional = match args.get("bidirectional") {
+        None | Some(Value::Null) => false,
+        Some(value) => value.as_bool().ok_or("'bidirectional' must be a boolean")?,
+    };
+    let link = store
+        .add_link(
+            str_arg(args, "from_node")?,
+            str_arg(args, "to_node")?,
+            str_arg(args, "relation")?,
+            bidirectional,
+            opt_str_arg(args, "confidence")?,
+        )
+        .map_err(|e| e.to_string())?;
+    serde_json::to_value(link).map_err(|e| e.to_string())
+}
+
+fn commit(store: &mut GraphStore, args: &Value) -> Result<Value, String> {
+    let input = CommitInput {
+        message: str_arg(args, "message")?.to_string(),
+        source: CommitSource::Manual {
+            tool_name: Some("mcp".to_string()),
+        },
+    };
+    let hash = store.commit(input).map_err(|e| e.to_string())?;
+    Ok(json!({ "hash": hash.0 }))
+}
+
+fn str_arg<'a>(args: &'a Value, key: &str) -> Result<&'a str, String> {
+    opt_str_arg(args, key)?.ok_or_else(|| format!("'{key}' is required"))
+}
+
+fn opt_str_arg<'a>(args: &'a Value, key: &str) -> Result<Option<&'a str>, String> {
+    match args.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_str().map(Some).ok_or_else(|| format!("'{key}' must be a string")),
+    }
+}
+
+fn usize_arg(args: &Value, key: &str) -> Result<Option<usize>, String> {
+    match args.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| format!("'{key}' must be a non-negative integer")),
+    }
+}
+
+fn metadata_arg(args: &Value) -> Result<Option<HashMap<String, String>>, String> {
+    match args.get("metadata") {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|_| "'metadata' must be an object of strings".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn call(store: &mut GraphStore, id: u32, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        handle_message(store, &request.to_string()).unwrap()
+    }
+
+    fn call_tool_text(store: &mut GraphStore, name: &str, arguments: Value) -> (Value, bool) {
+        let response = call(store, 1, "tools/call", json!({ "name": name, "arguments": arguments }));
+        let result = &response["result"];
+        let text = result["content"][0]["text"].as_str().unwrap();
+        let is_error = result["isError"].as_bool().unwrap();
+        (serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())), is_error)
+    }
+
+    #[test]
+    fn test_handshake_and_tool_listing() {
+        let dir = TempDir::new().unwrap();
+        let mut store = GraphStore::open(&dir.path().join("graph.json"), &OpenOptions::default()).unwrap();
+
+        let init = call(&mut store, 1, "initialize", json!({}));
+        assert_eq!(init["result"]["protocolVersion"], PROTOCOL_VERSION);
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(handle_message(&mut store, &notification.to_string()).is_none());
+
+        let tools = call(&mut store, 2, "tools/list", json!({}));
+        let names: Vec<&str> = tools["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["search_memory", "get_context", "create_node", "update_node", "link_nodes", "commit"]
+        );
+        assert_eq!(call(&mut store, 3, "nope", json!({}))["error"]["code"], -32601);
+        assert_eq!(handle_message(&mut store, "{").unwrap()["error"]["code"], -32700);
+    }
+
+    #[test]
+    fn test_tools_read_and_write_the_store() {
+        let dir = TempDir::new().unwrap();
+        let mut store = GraphStore::open(&dir.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        let root = store.graph.root_id.0.clone();
+
+        let args = json!({ "parent_id": root, "node_type": "detail", "content": "Likes hiking" });
+        let (node, is_error) = call_tool_text(&mut store, "create_node", args);
+        assert!(!is_error);
+        let args = json!({ "parent_id": root, "node_type": "detail", "content": "Owns a dog" });
+        let (other, _) = call_tool_text(&mut store, "create_node", args);
+
+        let args = json!({ "from_node": node["id"], "to_node": other["id"], "relation": "related_to" });
+        assert!(!call_tool_text(&mut store, "link_nodes", args).1);
+
+        let (found, _) = call_tool_text(&mut store, "search_memory", json!({ "query": "hiking" }));
+        assert_eq!(found["results"][0]["node_id"], node["id"]);
+
+        let (ctx, _) = call_tool_text(&mut store, "get_context", json!({ "node_id": node["id"] }));
+        assert_eq!(ctx["links"].as_array().unwrap().len(), 1);
+
+        let (message, is_error) = call_tool_text(&mut store, "update_node", json!({ "content": "x" }));
+        assert!(is_error);
+        assert_eq!(message, "'node_id' is required");
+        let (_, is_error) = call_tool_text(&mut store, "commit", json!({ "message": "m" }));
+        assert!(is_error, "VCS is not initialized");
+    }
+}
@@ -0,0 +1,179 @@
+//! Process-wide counters and histograms for monitoring long-running
+//! servers, recorded through the `metrics` facade so that whichever
+//! recorder the process installs receives them. `install_prometheus`
+//! installs the Prometheus one and returns the handle that renders the text
+//! format for a scrape endpoint.
+
+use crate::error::WillowError;
+use ::metrics::{describe_counter, describe_histogram, Unit};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle, PrometheusRecorder};
+use std::time::Duration;
+use web_time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Operations that changed a graph.
+    Mutations,
+    Searches,
+    Commits,
+    /// Writes of a whole graph file.
+    SaveDuration,
+    /// Graphs rebuilt at a commit, cache hits not included.
+    ReconstructDuration,
+}
+
+const COUNTERS: [Metric; 3] = [Metric::Mutations, Metric::Searches, Metric::Commits];
+const HISTOGRAMS: [Metric; 2] = [Metric::SaveDuration, Metric::ReconstructDuration];
+
+/// Upper bounds of the Prometheus histogram buckets, in seconds.
+pub const BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Mutations => "willow_mutations_total",
+            Metric::Searches => "willow_searches_total",
+            Metric::Commits => "willow_commits_total",
+            Metric::SaveDuration => "willow_save_duration_seconds",
+            Metric::ReconstructDuration => "willow_reconstruct_duration_seconds",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Metric::Mutations => "Operations that changed a graph.",
+            Metric::Searches => "Node searches run.",
+            Metric::Commits => "Commits created.",
+            Metric::SaveDuration => "Time to write a graph file.",
+            Metric::ReconstructDuration => "Time to rebuild the graph at a commit not in the cache.",
+        }
+    }
+}
+
+/// Give the current recorder each metric's help text and unit. Recorders
+/// only keep descriptions given after they are installed.
+pub fn describe() {
+    for metric in COUNTERS {
+        describe_counter!(metric.name(), metric.help());
+    }
+    for metric in HISTOGRAMS {
+        describe_histogram!(metric.name(), Unit::Seconds, metric.help());
+    }
+}
+
+pub(crate) fn increment(metric: Metric) {
+    ::metrics::counter!(metric.name()).increment(1);
+}
+
+pub(crate) fn observe(metric: Metric, elapsed: Duration) {
+    ::metrics::histogram!(metric.name()).record(elapsed);
+}
+
+/// Observes the time until it is dropped.
+pub(crate) struct MetricTimer(Metric, Instant);
+
+pub(crate) fn time(metric: Metric) -> MetricTimer {
+    MetricTimer(metric, Instant::now())
+}
+
+impl Drop for MetricTimer {
+    fn drop(&mut self) {
+        observe(self.0, self.1.elapsed());
+    }
+}
+
+/// A Prometheus recorder with `BUCKETS` for every histogram, not yet
+/// installed, for processes that compose or scope recorders themselves.
+pub fn prometheus_recorder() -> PrometheusRecorder {
+    PrometheusBuilder::new()
+        .set_buckets(&BUCKETS)
+        .expect("BUCKETS is not empty")
+        .build_recorder()
+}
+
+/// Install `prometheus_recorder` as the process's recorder and describe
+/// every metric. `PrometheusHandle::render` gives the scrape body. Fails if
+/// a recorder, ours or another library's, is installed already.
+pub fn install_prometheus() -> Result<PrometheusHandle, WillowError> {
+    let recorder = prometheus_recorder();
+    let handle = recorder.handle();
+    ::metrics::set_global_recorder(recorder).map_err(|_| WillowError::MetricsRecorderInstalled)?;
+    describe();
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Page;
+    use crate::search::SearchOptions;
+    use crate::store::{GraphStore, OpenOptions};
+    use crate::vcs::types::{CommitInput, CommitSource};
+    use prometheus_parse::{Scrape, Value};
+
+    fn parse(text: &str) -> Scrape {
+        Scrape::parse(text.lines().map(|line| Ok(line.to_string()))).unwrap()
+    }
+
+    fn sample<'a>(scrape: &'a Scrape, name: &str) -> &'a Value {
+        &scrape.samples.iter().find(|s| s.metric == name).unwrap_or_else(|| panic!("no {name}")).value
+    }
+
+    #[test]
+    fn test_store_operations_render_as_parseable_exposition() {
+        // A recorder local to this thread, so counts start from zero
+        // whatever other tests record.
+        let recorder = prometheus_recorder();
+        let handle = recorder.handle();
+        let dir = tempfile::TempDir::new().unwrap();
+        ::metrics::with_local_recorder(&recorder, || {
+            describe();
+            let mut store = GraphStore::open(&dir.path().join("graph.json"), &OpenOptions::default()).unwrap();
+            store.vcs_init().unwrap();
+            for i in 0..3 {
+                store.create_node("root", "detail", &format!("Note {i}"), None, None).unwrap();
+            }
+            store.search_nodes("note", &Page::default(), None, &SearchOptions::default());
+            let input = CommitInput {
+                message: "Notes".to_string(),
+                source: CommitSource::Manual { tool_name: None },
+            };
+            store.commit(input).unwrap();
+        });
+
+        let scrape = parse(&handle.render());
+        assert_eq!(sample(&scrape, "willow_mutations_total"), &Value::Counter(3.0));
+        assert_eq!(sample(&scrape, "willow_searches_total"), &Value::Counter(1.0));
+        assert_eq!(sample(&scrape, "willow_commits_total"), &Value::Counter(1.0));
+        assert_eq!(scrape.docs["willow_searches_total"], "Node searches run.");
+        let Value::Histogram(buckets) = sample(&scrape, "willow_save_duration_seconds") else {
+            panic!("expected a histogram");
+        };
+        let bounds: Vec<f64> = buckets.iter().map(|b| b.less_than).collect();
+        assert_eq!(bounds[..BUCKETS.len()], BUCKETS);
+        assert_eq!(bounds.last(), Some(&f64::INFINITY));
+        assert!(buckets.windows(2).all(|w| w[0].count <= w[1].count));
+        assert!(buckets.last().unwrap().count >= 3.0);
+    }
+
+    #[test]
+    fn test_recorders_are_isolated_and_install_once() {
+        let first = prometheus_recorder();
+        let second = prometheus_recorder();
+        ::metrics::with_local_recorder(&first, || increment(Metric::Commits));
+        ::metrics::with_local_recorder(&second, || {
+            increment(Metric::Commits);
+            increment(Metric::Commits);
+        });
+        let count = |recorder: &PrometheusRecorder| {
+            sample(&parse(&recorder.handle().render()), "willow_commits_total").clone()
+        };
+        assert_eq!(count(&first), Value::Counter(1.0));
+        assert_eq!(count(&second), Value::Counter(2.0));
+
+        let handle = install_prometheus().unwrap();
+        assert!(matches!(install_prometheus(), Err(WillowError::MetricsRecorderInstalled)));
+        observe(Metric::ReconstructDuration, Duration::from_millis(20));
+        assert!(handle.render().contains("# TYPE willow_reconstruct_duration_seconds histogram"));
+    }
+}
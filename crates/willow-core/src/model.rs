@@ -1,12 +1,30 @@
+use crate::rules::Rule;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+pub use crate::content::ContentFormat;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct NodeId(pub String);
+/// Ids are shared rather than copied: cloning one only bumps a count, and
+/// `Graph::intern_ids` makes a loaded graph hold each id once.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodeId(pub Arc<str>);
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct LinkId(pub String);
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LinkId(pub Arc<str>);
+
+/// Serialize a map in key order, so saving the same graph twice produces the
+/// same bytes; unordered while `save_graph` writes without `sorted_keys`.
+fn sorted_map<K: Serialize + Ord, V: Serialize, S: Serializer>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if crate::storage::sorting_keys() {
+        map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+    } else {
+        map.serialize(serializer)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -33,6 +51,7 @@ impl NodeType {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<NodeType> {
         match s {
             "root" => Some(NodeType::Root),
@@ -47,35 +66,152 @@ impl NodeType {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How carefully a node's content must be handled. Search, context and
+/// export leave out anything above `Normal` unless asked to include it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sensitivity {
+    #[default]
+    Normal,
+    Sensitive,
+    Secret,
+}
+
+impl Sensitivity {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Sensitivity::Normal => "normal",
+            Sensitivity::Sensitive => "sensitive",
+            Sensitivity::Secret => "secret",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Sensitivity> {
+        match s {
+            "normal" => Some(Sensitivity::Normal),
+            "sensitive" => Some(Sensitivity::Sensitive),
+            "secret" => Some(Sensitivity::Secret),
+            _ => None,
+        }
+    }
+
+    pub fn is_normal(&self) -> bool {
+        *self == Sensitivity::Normal
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TemporalMetadata {
     pub valid_from: Option<DateTime<Utc>>,
     pub valid_until: Option<DateTime<Utc>>,
     pub label: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl TemporalMetadata {
+    /// Whether `date` falls within the validity window; open ends are unbounded.
+    pub fn is_valid_at(&self, date: DateTime<Utc>) -> bool {
+        self.valid_from.is_none_or(|from| from <= date) && self.valid_until.is_none_or(|until| date <= until)
+    }
+
+    /// Whether the validity window shares any instant with `from..=until`.
+    pub fn overlaps(&self, from: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> bool {
+        let starts_in_time = match (self.valid_from, until) {
+            (Some(start), Some(end)) => start <= end,
+            _ => true,
+        };
+        let ends_in_time = match (self.valid_until, from) {
+            (Some(end), Some(start)) => start <= end,
+            _ => true,
+        };
+        starts_in_time && ends_in_time
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SupersededValue {
     pub old_content: String,
     pub superseded_at: DateTime<Utc>,
     pub reason: Option<String>,
 }
 
+/// How a UI should present a node. Carried alongside the content so it is
+/// versioned and merged like any other field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayHints {
+    pub icon: Option<String>,
+    /// Any CSS colour string.
+    pub color: Option<String>,
+    /// Show the node's children folded until expanded.
+    pub collapsed: bool,
+}
+
+/// A reference from a node to a blob in the attachment store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentRef {
+    pub hash: String,
+    pub mime: String,
+    pub size: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: NodeId,
     pub node_type: NodeType,
     pub content: String,
+    #[serde(default, skip_serializing_if = "ContentFormat::is_text")]
+    pub content_format: ContentFormat,
     pub parent_id: Option<NodeId>,
     pub children: Vec<NodeId>,
+    /// Additional parents besides `parent_id`; the node also appears in each
+    /// of these parents' `children`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_parents: Vec<NodeId>,
+    /// Pinned nodes rank above all other matches in search.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Sensitivity::is_normal")]
+    pub sensitivity: Sensitivity,
+    /// Archived subtrees stay in the graph and history but are skipped by
+    /// search and context unless asked for.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<DisplayHints>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<AttachmentRef>,
+    #[serde(serialize_with = "sorted_map")]
     pub metadata: HashMap<String, String>,
+    #[serde(
+        serialize_with = "crate::storage::serialize_previous_values",
+        deserialize_with = "crate::storage::previous_values"
+    )]
     pub previous_values: Vec<SupersededValue>,
     pub temporal: Option<TemporalMetadata>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Who created the node: a tool name, conversation id or user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    /// Who last changed the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+impl Node {
+    /// All parents, primary first.
+    pub fn parents(&self) -> impl Iterator<Item = &NodeId> {
+        self.parent_id.iter().chain(self.extra_parents.iter())
+    }
+
+    /// Nodes without temporal metadata are always valid.
+    pub fn is_valid_at(&self, date: DateTime<Utc>) -> bool {
+        self.temporal.as_ref().is_none_or(|t| t.is_valid_at(date))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ConfidenceLevel {
     Low,
@@ -84,6 +220,7 @@ pub enum ConfidenceLevel {
 }
 
 impl ConfidenceLevel {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<ConfidenceLevel> {
         match s {
             "low" => Some(ConfidenceLevel::Low),
@@ -114,11 +251,25 @@ pub struct Link {
     pub created_at: DateTime<Utc>,
 }
 
+/// Name of the profile rooted at `Graph::root_id`.
+pub const DEFAULT_PROFILE: &str = "default";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Graph {
     pub root_id: NodeId,
-    pub nodes: HashMap<NodeId, Node>,
+    /// Shared with clones of the graph until changed through
+    /// `Arc::make_mut`, so copies of large graphs are cheap.
+    #[serde(serialize_with = "sorted_map")]
+    pub nodes: HashMap<NodeId, Arc<Node>>,
+    #[serde(serialize_with = "sorted_map")]
     pub links: HashMap<LinkId, Link>,
+    /// Additional named profiles ("work", ...), each a separate tree whose
+    /// root node has no parent. The default profile is not listed here.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roots: BTreeMap<String, NodeId>,
+    /// Maintenance rules; see `rules::Rule`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
 }
 
 impl Graph {
@@ -127,6 +278,71 @@ impl Graph {
             root_id,
             nodes: HashMap::new(),
             links: HashMap::new(),
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// The root node of a profile, if it exists.
+    pub fn profile_root(&self, name: &str) -> Option<&NodeId> {
+        if name == DEFAULT_PROFILE {
+            Some(&self.root_id)
+        } else {
+            self.roots.get(name)
+        }
+    }
+
+    /// True for the graph root and every profile root.
+    pub fn is_root(&self, node_id: &NodeId) -> bool {
+        *node_id == self.root_id || self.roots.values().any(|id| id == node_id)
+    }
+
+    /// Contents from the root down to the node, following primary parents.
+    pub fn content_path(&self, node_id: &NodeId) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|id| self.nodes.get(id)) {
+            path.push(node.content.clone());
+            current = node.parent_id.as_ref();
+        }
+        path.reverse();
+        path
+    }
+
+    /// The highest sensitivity of a node and its ancestors: how carefully
+    /// anything naming the node, or showing its path, must be handled.
+    pub fn path_sensitivity(&self, node_id: &NodeId) -> Sensitivity {
+        let mut sensitivity = Sensitivity::Normal;
+        let mut current = Some(node_id);
+        while let Some(node) = current.and_then(|id| self.nodes.get(id)) {
+            sensitivity = sensitivity.max(node.sensitivity);
+            current = node.parent_id.as_ref();
+        }
+        sensitivity
+    }
+
+    /// Point every id in the graph at the allocation of its key in `nodes`
+    /// or `links`. Deserializing gives each occurrence of an id (as a key,
+    /// in `children`, as a link end) its own copy; this drops the copies.
+    pub fn intern_ids(&mut self) {
+        let node_ids: HashSet<NodeId> = self.nodes.keys().cloned().collect();
+        let intern = |id: &mut NodeId| {
+            if let Some(shared) = node_ids.get(id) {
+                *id = shared.clone();
+            }
+        };
+        intern(&mut self.root_id);
+        self.roots.values_mut().for_each(intern);
+        for node in self.nodes.values_mut().map(Arc::make_mut) {
+            intern(&mut node.id);
+            node.parent_id.iter_mut().for_each(intern);
+            node.children.iter_mut().for_each(intern);
+            node.extra_parents.iter_mut().for_each(intern);
+        }
+        for (lid, link) in self.links.iter_mut() {
+            link.id = lid.clone();
+            intern(&mut link.from_node);
+            intern(&mut link.to_node);
         }
     }
 }
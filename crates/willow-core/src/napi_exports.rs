@@ -1,15 +1,34 @@
+use crate::analysis;
+use crate::error::WillowError;
+use crate::events::StoreEvent;
+use crate::integrity;
+use crate::limits;
+use crate::maintenance;
 use crate::model;
+use crate::progress::{CancelToken, Progress, ProgressUpdate};
+use crate::query;
+use crate::relations;
+use crate::retention;
+use crate::rules;
+use crate::schema;
 use crate::search;
+use crate::search_index::SearchIndex;
+use crate::snapshot::GraphFormat;
 use crate::store;
 use crate::vcs;
+use crate::vector;
+use crate::webhooks;
+use napi::bindgen_prelude::{Buffer, Function};
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
 use std::collections::HashMap;
 use std::path::Path;
 use tracing::{info, debug};
 
 macro_rules! repo_op {
-    ($self:expr, $op:expr) => {
-        $op($self.repo()?).map_err(napi::Error::from)
-    };
+    ($self:expr, $op:expr) => {{
+        let store = $self.store();
+        $op(store.get_repo().map_err(napi::Error::from)?).map_err(napi::Error::from)
+    }};
 }
 
 // ---- DTO structs ----
@@ -21,6 +40,20 @@ pub struct JsTemporalMetadata {
     pub label: Option<String>,
 }
 
+#[napi(object)]
+pub struct JsDisplayHints {
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub collapsed: Option<bool>,
+}
+
+#[napi(object)]
+pub struct JsAttachment {
+    pub hash: String,
+    pub mime: String,
+    pub size: i64,
+}
+
 #[napi(object)]
 pub struct JsSupersededValue {
     pub old_content: String,
@@ -33,13 +66,22 @@ pub struct JsNode {
     pub id: String,
     pub node_type: String,
     pub content: String,
+    pub content_format: String,
     pub parent_id: Option<String>,
     pub children: Vec<String>,
+    pub extra_parents: Vec<String>,
+    pub pinned: bool,
+    pub sensitivity: String,
+    pub archived: bool,
+    pub display: Option<JsDisplayHints>,
+    pub attachments: Vec<JsAttachment>,
     pub metadata: HashMap<String, String>,
     pub previous_values: Vec<JsSupersededValue>,
     pub temporal: Option<JsTemporalMetadata>,
     pub created_at: String,
     pub updated_at: String,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
 }
 
 #[napi(object)]
@@ -61,6 +103,53 @@ pub struct JsSearchResult {
     pub score: f64,
     pub matched_field: String,
     pub depth: u32,
+    /// Contents from the root down to the node.
+    pub path: Vec<String>,
+}
+
+#[napi(object)]
+pub struct JsLinkSearchResult {
+    pub link: JsLink,
+    pub from_node: JsNode,
+    pub to_node: JsNode,
+    pub score: f64,
+    /// `relation`, `from_node` or `to_node`.
+    pub matched_field: String,
+}
+
+#[napi(object)]
+pub struct JsSuggestion {
+    pub text: String,
+    /// `content` or `tag`.
+    pub kind: String,
+    /// The node whose content this is; absent for tags.
+    pub node_id: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsStoreEvent {
+    /// `node_created`, `node_updated`, `node_deleted`, `link_added`,
+    /// `link_updated`, `link_removed`, `commit_created`, `branch_switched`
+    /// or `reset`, after which everything shown may be stale.
+    pub kind: String,
+    pub node_id: Option<String>,
+    /// The node after the change; absent for `node_deleted`.
+    pub node: Option<JsNode>,
+    pub link_id: Option<String>,
+    /// The link after the change; absent for `link_removed`.
+    pub link: Option<JsLink>,
+    pub commit_hash: Option<String>,
+    pub branch: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsIndexEvent {
+    /// `created`, `updated`, `deleted` or `reset`.
+    pub kind: String,
+    /// Absent for `reset`.
+    pub node_id: Option<String>,
+    /// The node after the change; absent for `deleted` and `reset`.
+    pub node: Option<JsNode>,
 }
 
 #[napi(object)]
@@ -69,6 +158,134 @@ pub struct JsContextResult {
     pub ancestors: Vec<JsNode>,
     pub descendants: Vec<JsNode>,
     pub links: Vec<JsLink>,
+    pub truncated: bool,
+}
+
+#[napi(object)]
+pub struct JsNodeFilter {
+    pub node_types: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub updated_after: Option<String>,
+    pub updated_before: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[napi(object)]
+pub struct JsImportGraphOptions {
+    /// `json`, `msgpack` or `compressed`.
+    pub format: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsLinkFilter {
+    pub from_node: Option<String>,
+    pub to_node: Option<String>,
+    pub relation: Option<String>,
+    /// `low`, `medium` or `high`; matches links at least this confident.
+    pub min_confidence: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsDuplicatePair {
+    pub a: String,
+    pub b: String,
+    pub similarity: f64,
+}
+
+#[napi(object)]
+pub struct JsNodeSort {
+    /// `created_at` (default), `updated_at` or `content`.
+    pub field: Option<String>,
+    pub descending: Option<bool>,
+}
+
+#[napi(object)]
+pub struct JsPage {
+    pub offset: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[napi(object)]
+pub struct JsSearchPage {
+    pub results: Vec<JsSearchResult>,
+    /// Matches before pagination.
+    pub total: u32,
+}
+
+#[napi(object)]
+pub struct JsNodePage {
+    pub nodes: Vec<JsNode>,
+    pub total: u32,
+}
+
+#[napi(object)]
+pub struct JsLinkPage {
+    pub links: Vec<JsLink>,
+    pub total: u32,
+}
+
+#[napi(object)]
+pub struct JsContextOptions {
+    pub node_types: Option<Vec<String>>,
+    pub include_archived: Option<bool>,
+    pub max_descendants: Option<u32>,
+    pub omit_previous_values: Option<bool>,
+    /// Include descendants up to this level: `normal` (default), `sensitive` or `secret`.
+    pub max_sensitivity: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsSearchOptions {
+    /// Include nodes up to this level: `normal` (default), `sensitive` or `secret`.
+    pub max_sensitivity: Option<String>,
+    pub include_archived: Option<bool>,
+    /// RFC 3339 bounds; `*After` is inclusive, `*Before` exclusive.
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub updated_after: Option<String>,
+    pub updated_before: Option<String>,
+    /// Only nodes whose validity window overlaps `validFrom..=validUntil`.
+    pub valid_from: Option<String>,
+    pub valid_until: Option<String>,
+    /// Stemmer language (`english`, `french`, `german`, `spanish` or their
+    /// two-letter codes), overriding the store's text analysis.
+    pub stemmer: Option<String>,
+    pub case_sensitive: Option<bool>,
+    /// Fold accented letters, so "café" matches "cafe".
+    pub fold_diacritics: Option<bool>,
+    /// Overrides the store's ranking boosts.
+    pub boosts: Option<JsRankingBoosts>,
+}
+
+#[napi(object)]
+pub struct JsNeighbor {
+    pub node: JsNode,
+    pub hops: u32,
+}
+
+#[napi(object)]
+pub struct JsNeighborhoodResult {
+    pub node: JsNode,
+    pub neighbors: Vec<JsNeighbor>,
+    pub links: Vec<JsLink>,
+}
+
+#[napi(object)]
+pub struct JsContextEntry {
+    pub node: JsNode,
+    /// `requested`, `ancestor` or `neighbor`.
+    pub role: String,
+    pub depth: u32,
+}
+
+#[napi(object)]
+pub struct JsAssembledContext {
+    pub entries: Vec<JsContextEntry>,
+    pub links: Vec<JsLink>,
+    pub used_chars: u32,
+    pub omitted: u32,
 }
 
 #[napi(object)]
@@ -76,8 +293,21 @@ pub struct JsCreateNodeInput {
     pub parent_id: String,
     pub node_type: String,
     pub content: String,
+    /// `text` (default), `markdown`, `list`, `table` or `record`; structured formats take JSON content.
+    pub content_format: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub temporal: Option<JsTemporalMetadata>,
+}
+
+/// A node and the nodes to create beneath it, for `createTree`.
+#[napi(object)]
+pub struct JsNodeTree {
+    pub node_type: String,
+    pub content: String,
+    pub content_format: Option<String>,
     pub metadata: Option<HashMap<String, String>>,
     pub temporal: Option<JsTemporalMetadata>,
+    pub children: Option<Vec<JsNodeTree>>,
 }
 
 #[napi(object)]
@@ -106,6 +336,270 @@ pub struct JsUpdateLinkInput {
     pub confidence: Option<String>,
 }
 
+#[napi(object)]
+pub struct JsFieldSpec {
+    pub key: String,
+    pub value_type: String, // "string", "number", "boolean", "date"
+    pub required: Option<bool>,
+}
+
+#[napi(object)]
+pub struct JsNodeTypeSchema {
+    pub node_type: String,
+    pub fields: Vec<JsFieldSpec>,
+    pub allow_unknown: Option<bool>,
+}
+
+#[napi(object)]
+pub struct JsProfile {
+    pub name: String,
+    pub root_id: String,
+    pub current: bool,
+}
+
+#[napi(object)]
+pub struct JsTextAnalysis {
+    /// `english`, `french`, `german` or `spanish`; unset disables stemming.
+    pub stemmer: Option<String>,
+    /// Groups of interchangeable terms, e.g. `[["job", "work"]]`.
+    pub synonyms: Vec<Vec<String>>,
+}
+
+#[napi(object)]
+pub struct JsImportReport {
+    /// Top-level imported nodes, children of the target parent.
+    pub root_ids: Vec<String>,
+    pub nodes: u32,
+    pub links: u32,
+    /// Commit recording the import, when VCS is initialized.
+    pub commit: Option<String>,
+}
+
+fn import_report_to_js(report: store::ImportReport) -> JsImportReport {
+    JsImportReport {
+        root_ids: report.root_ids.into_iter().map(|id| id.0.to_string()).collect(),
+        nodes: report.nodes as u32,
+        links: report.links as u32,
+        commit: report.commit.map(|h| h.0),
+    }
+}
+
+#[napi(object)]
+pub struct JsOpenOptions {
+    /// Write the graph file zstd-compressed; either format is read.
+    pub compress: Option<bool>,
+    /// Indent the graph file's JSON (default true). Compact JSON saves
+    /// large graphs much faster.
+    pub pretty: Option<bool>,
+    /// Write nodes, links and metadata in key order (default true), for
+    /// stable diffs; unordered saves are faster.
+    pub sorted_keys: Option<bool>,
+    /// Keep `previousValues` in the graph file (default true) rather than
+    /// in a `graph.history.json` sidecar.
+    pub inline_history: Option<bool>,
+    /// Lock the graph file against other processes while open: `exclusive`
+    /// for writers, `shared` for readers. Unlocked by default.
+    pub lock: Option<String>,
+    /// Append operations to a journal instead of rewriting the graph file,
+    /// folding it into the file after this many operations and on `compact`.
+    pub journal: Option<u32>,
+    /// Leave nodes' `previousValues` on disk until needed, for very large
+    /// graphs; nodes then show only values superseded since opening, and
+    /// `nodeHistory` returns the full list.
+    pub defer_history: Option<bool>,
+    /// Store each top-level subtree in its own file under `graph.shards/`,
+    /// rewriting only those that change. Sharded graphs open sharded anyway.
+    pub sharded: Option<bool>,
+    /// Hold saves back until operations pause for this many milliseconds,
+    /// so bursts such as bulk ingestion are written once. Ignored with
+    /// `journal`.
+    pub write_behind_ms: Option<u32>,
+    /// With `writeBehindMs`, write at most this long after the first unsaved
+    /// operation even if operations keep coming (default 10 times
+    /// `writeBehindMs`).
+    pub write_behind_max_ms: Option<u32>,
+    /// Log every mutating call, with its actor and the ids it touched, to
+    /// a `graph.audit` file kept apart from VCS; see `auditLog`.
+    pub audit: Option<bool>,
+}
+
+#[napi(object)]
+pub struct JsRankingBoosts {
+    /// A node updated just now scores up to `1 + recencyWeight` times as
+    /// much. Default 0.
+    pub recency_weight: Option<f64>,
+    /// Days for the recency bonus to halve. Default 30.
+    pub recency_half_life_days: Option<f64>,
+    /// Score multipliers by node type, e.g. `{ entity: 1.5, detail: 0.8 }`.
+    pub type_weights: Option<HashMap<String, f64>>,
+}
+
+#[napi(object)]
+pub struct JsLimits {
+    pub max_content_len: u32,
+    pub max_metadata_entries: u32,
+    pub max_metadata_value_len: u32,
+    pub max_children: u32,
+}
+
+#[napi(object)]
+pub struct JsRetentionPolicy {
+    pub max_values: Option<u32>,
+    pub max_age_days: Option<u32>,
+    pub summarize: Option<bool>,
+}
+
+#[napi(object)]
+pub struct JsRelationSpec {
+    pub relation: String,
+    pub allow_bidirectional: Option<bool>,
+    pub inverse: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsRelationUsage {
+    pub relation: String,
+    pub count: u32,
+    pub registered: bool,
+    pub allow_bidirectional: bool,
+    pub inverse: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsIntegrityIssue {
+    pub kind: String,
+    /// Node (or link, for dangling links) the issue is reported against.
+    pub subject_id: String,
+    pub related_id: Option<String>,
+    pub message: String,
+}
+
+#[napi(object)]
+pub struct JsSweepReport {
+    pub expired: Vec<String>,
+    pub commit_hash: Option<String>,
+}
+
+/// A maintenance rule: nodes matching every condition set get its actions.
+#[napi(object)]
+pub struct JsRule {
+    pub id: String,
+    pub node_type: Option<String>,
+    /// Case-insensitive substring of the content.
+    pub content_contains: Option<String>,
+    /// Metadata entries the node must have, with these values.
+    pub metadata: Option<HashMap<String, String>>,
+    pub actions: Vec<JsRuleAction>,
+}
+
+/// One of `add_tag` (with `tag`), `create_link` (with `to` and
+/// `relation`), `move_under` (with `parent`) or `set_validity` (with `days`
+/// and optionally `label`).
+#[napi(object)]
+pub struct JsRuleAction {
+    pub action: String,
+    pub tag: Option<String>,
+    pub to: Option<String>,
+    pub relation: Option<String>,
+    pub parent: Option<String>,
+    pub days: Option<u32>,
+    pub label: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsJobRun {
+    pub job: String,
+    pub at: String,
+    pub summary: String,
+    pub failed: bool,
+    pub commit_hash: Option<String>,
+}
+
+/// A registered maintenance job: `expire_temporal` (with `policy`),
+/// `prune_history`, `detect_duplicates` (with `threshold`), `gc_repo` or
+/// `rebuild_indexes`.
+#[napi(object)]
+pub struct JsScheduledJob {
+    pub job: String,
+    pub interval_hours: u32,
+    pub policy: Option<String>,
+    pub threshold: Option<f64>,
+    pub last_run: Option<JsJobRun>,
+}
+
+#[napi(object)]
+pub struct JsMaintenanceReport {
+    pub runs: Vec<JsJobRun>,
+    pub commit_hash: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsRulesReport {
+    pub changed: Vec<String>,
+    pub links_created: u32,
+    pub commit_hash: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsRepairReport {
+    pub repaired: Vec<JsIntegrityIssue>,
+    pub remaining: Vec<JsIntegrityIssue>,
+}
+
+#[napi(object)]
+pub struct JsOperationTimings {
+    pub operation: String,
+    pub count: u32,
+    pub total_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    /// Runs within each of the report's `bucketBoundsMs`, then the slower ones.
+    pub histogram: Vec<u32>,
+}
+
+#[napi(object)]
+pub struct JsMemoryFootprint {
+    pub nodes: u32,
+    pub links: u32,
+    pub content_bytes: i64,
+    pub metadata_bytes: i64,
+    pub previous_values_bytes: i64,
+    pub estimated_total_bytes: i64,
+}
+
+#[napi(object)]
+pub struct JsPerfReport {
+    pub operations: Vec<JsOperationTimings>,
+    pub bucket_bounds_ms: Vec<f64>,
+    pub memory: JsMemoryFootprint,
+    pub pending_changes: u32,
+}
+
+#[napi(object)]
+pub struct JsAuditEntry {
+    pub timestamp: String,
+    pub actor: Option<String>,
+    /// The call, or the kinds of change it made joined by '+', e.g.
+    /// `create_node` or `delete_node+remove_link`.
+    pub operation: String,
+    pub node_ids: Vec<String>,
+    pub link_ids: Vec<String>,
+    /// The branch, commit or profile the call named, if any.
+    pub target: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsAuditQuery {
+    /// RFC 3339 bounds, inclusive.
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub actor: Option<String>,
+    pub operation: Option<String>,
+    pub node_id: Option<String>,
+    /// At most this many entries, the most recent ones.
+    pub limit: Option<u32>,
+}
+
 // ---- VCS DTO structs ----
 
 #[napi(object)]
@@ -118,6 +612,101 @@ pub struct JsCommitInput {
     pub tool_name: Option<String>,
 }
 
+/// `JsCommitInput` without the message, for `commitAuto`.
+#[napi(object)]
+pub struct JsCommitSource {
+    pub source: String,
+    pub conversation_id: Option<String>,
+    pub summary: Option<String>,
+    pub job_id: Option<String>,
+    pub tool_name: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsRepoConfig {
+    pub format_version: u32,
+    /// Commits between full snapshots; the rest store deltas.
+    pub snapshot_interval: u32,
+    pub default_branch: String,
+    /// zstd level new snapshots are written at.
+    pub snapshot_compression_level: i32,
+    /// Id of the trained dictionary snapshots are compressed with, if any.
+    pub snapshot_dictionary: Option<u32>,
+    /// Every this many snapshots one holds the full graph; the rest store
+    /// only what changed since the snapshot before.
+    pub snapshot_keyframe_interval: u32,
+    pub webhooks: Vec<JsWebhook>,
+    /// The bucket objects are kept in, if not only on disk.
+    pub object_storage: Option<JsObjectStorage>,
+}
+
+/// An S3-compatible bucket at an `https://host[:port]` endpoint (or `http://`
+/// on a loopback address), addressed path-style. Requests are signed with
+/// the AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables.
+#[napi(object)]
+pub struct JsObjectStorage {
+    pub endpoint: String,
+    pub bucket: String,
+    /// Put before every key, e.g. "graphs/alice/".
+    pub prefix: Option<String>,
+    /// Defaults to "us-east-1".
+    pub region: Option<String>,
+}
+
+/// An `https://` URL, or `http://` to a loopback address, POSTed a JSON
+/// payload with the change summary after commits, merges and branch
+/// operations.
+#[napi(object)]
+pub struct JsWebhook {
+    pub url: String,
+    /// Any of "commit", "merge", "create_branch", "delete_branch",
+    /// "switch_branch"; unset or empty sends them all.
+    pub events: Option<Vec<String>>,
+    /// "normal" (the default), "sensitive" or "secret": changes above it
+    /// are left out of the payloads.
+    pub max_sensitivity: Option<String>,
+}
+
+/// Fields to change in the repository config; unset fields are kept.
+#[napi(object)]
+pub struct JsRepoConfigUpdate {
+    pub snapshot_interval: Option<u32>,
+    pub default_branch: Option<String>,
+    pub snapshot_compression_level: Option<i32>,
+    pub snapshot_keyframe_interval: Option<u32>,
+    /// Replaces every webhook.
+    pub webhooks: Option<Vec<JsWebhook>>,
+}
+
+#[napi(object)]
+pub struct JsAutoCommit {
+    pub hash: String,
+    pub message: String,
+}
+
+#[napi(object)]
+pub struct JsCommitStats {
+    pub nodes_created: u32,
+    pub nodes_updated: u32,
+    pub nodes_deleted: u32,
+    pub links_created: u32,
+    pub links_removed: u32,
+    pub links_updated: u32,
+}
+
+#[napi(object)]
+pub struct JsHistoryMatch {
+    pub node_id: String,
+    /// The content as it was in `commit`.
+    pub content: String,
+    pub score: f64,
+    pub matched_field: String,
+    /// The most recent commit that still had this content.
+    pub commit: JsCommitEntry,
+    /// The node is gone at HEAD, rather than edited.
+    pub deleted: bool,
+}
+
 #[napi(object)]
 pub struct JsCommitEntry {
     pub hash: String,
@@ -127,6 +716,7 @@ pub struct JsCommitEntry {
     pub source_detail: Option<String>,
     pub parents: Vec<String>,
     pub storage_type: String,
+    pub stats: Option<JsCommitStats>,
 }
 
 #[napi(object)]
@@ -136,6 +726,21 @@ pub struct JsNodeChangeSummary {
     pub content: String,
     pub old_content: Option<String>,
     pub path: Vec<String>,
+    pub content_changes: Vec<JsContentFieldChange>,
+}
+
+#[napi(object)]
+pub struct JsContentFieldChange {
+    pub path: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+#[napi(object)]
+pub struct JsAttachmentChangeSummary {
+    pub node_id: String,
+    pub hash: String,
+    pub mime: String,
 }
 
 #[napi(object)]
@@ -157,6 +762,8 @@ pub struct JsChangeSummary {
     pub links_created: Vec<JsLinkChangeSummary>,
     pub links_removed: Vec<JsLinkChangeSummary>,
     pub links_updated: Vec<JsLinkChangeSummary>,
+    pub attachments_added: Vec<JsAttachmentChangeSummary>,
+    pub attachments_removed: Vec<JsAttachmentChangeSummary>,
 }
 
 #[napi(object)]
@@ -174,23 +781,150 @@ pub struct JsBranchInfo {
 
 // ---- Conversions ----
 
+fn context_to_js(ctx: &store::ContextResult) -> JsContextResult {
+    JsContextResult {
+        node: node_to_js(&ctx.node),
+        ancestors: ctx.ancestors.iter().map(|n| node_to_js(n)).collect(),
+        descendants: ctx.descendants.iter().map(|n| node_to_js(n)).collect(),
+        links: map_vec(&ctx.links, link_to_js),
+        truncated: ctx.truncated,
+    }
+}
+
+fn parse_node_types(types: Option<Vec<String>>) -> napi::Result<Option<Vec<model::NodeType>>> {
+    let types = types
+        .map(|types| {
+            types
+                .iter()
+                .map(|t| model::NodeType::from_str(t).ok_or_else(|| WillowError::InvalidNodeType(t.clone())))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    Ok(types)
+}
+
+fn js_node_filter_to_model(filter: JsNodeFilter) -> napi::Result<query::NodeFilter> {
+    let date = |d: Option<String>| d.as_deref().map(parse_date).transpose();
+    Ok(query::NodeFilter {
+        node_types: parse_node_types(filter.node_types)?,
+        tags: filter.tags.unwrap_or_default(),
+        created_after: date(filter.created_after)?,
+        created_before: date(filter.created_before)?,
+        updated_after: date(filter.updated_after)?,
+        updated_before: date(filter.updated_before)?,
+        metadata: filter.metadata.unwrap_or_default(),
+    })
+}
+
+fn parse_graph_format(format: Option<String>) -> napi::Result<Option<GraphFormat>> {
+    match format {
+        Some(f) => Ok(Some(GraphFormat::from_str(&f).ok_or(WillowError::InvalidGraphFormat(f))?)),
+        None => Ok(None),
+    }
+}
+
+fn js_link_filter_to_model(filter: JsLinkFilter) -> napi::Result<query::LinkFilter> {
+    let min_confidence = match filter.min_confidence {
+        Some(c) => Some(model::ConfidenceLevel::from_str(&c).ok_or(WillowError::InvalidConfidence(c))?),
+        None => None,
+    };
+    Ok(query::LinkFilter {
+        from_node: filter.from_node.map(|id| model::NodeId(id.into())),
+        to_node: filter.to_node.map(|id| model::NodeId(id.into())),
+        relation: filter.relation,
+        min_confidence,
+    })
+}
+
+fn js_node_sort_to_model(sort: JsNodeSort) -> napi::Result<query::NodeSort> {
+    let field = match sort.field {
+        Some(f) => query::SortField::from_str(&f).ok_or(WillowError::InvalidSortField(f))?,
+        None => query::SortField::default(),
+    };
+    Ok(query::NodeSort {
+        field,
+        descending: sort.descending.unwrap_or(false),
+    })
+}
+
+fn js_context_options_to_model(options: JsContextOptions) -> napi::Result<store::ContextOptions> {
+    Ok(store::ContextOptions {
+        node_types: parse_node_types(options.node_types)?,
+        include_archived: options.include_archived.unwrap_or(false),
+        max_descendants: options.max_descendants.map(|m| m as usize),
+        omit_previous_values: options.omit_previous_values.unwrap_or(false),
+        max_sensitivity: parse_sensitivity(options.max_sensitivity)?,
+    })
+}
+
+fn js_search_options_to_model(options: Option<JsSearchOptions>) -> napi::Result<search::SearchOptions> {
+    let Some(options) = options else {
+        return Ok(search::SearchOptions::default());
+    };
+    let date = |d: Option<String>| d.as_deref().map(parse_date).transpose();
+    Ok(search::SearchOptions {
+        max_sensitivity: parse_sensitivity(options.max_sensitivity)?,
+        include_archived: options.include_archived.unwrap_or(false),
+        created_after: date(options.created_after)?,
+        created_before: date(options.created_before)?,
+        updated_after: date(options.updated_after)?,
+        updated_before: date(options.updated_before)?,
+        valid_from: date(options.valid_from)?,
+        valid_until: date(options.valid_until)?,
+        analysis: analysis::TextAnalysis {
+            stemmer: parse_stemmer(options.stemmer)?,
+            synonyms: Vec::new(),
+        },
+        case_sensitive: options.case_sensitive.unwrap_or(false),
+        fold_diacritics: options.fold_diacritics.unwrap_or(false),
+        boosts: options.boosts.map(js_ranking_boosts_to_model).transpose()?,
+    })
+}
+
+fn js_ranking_boosts_to_model(boosts: JsRankingBoosts) -> napi::Result<search::RankingBoosts> {
+    let defaults = search::RankingBoosts::default();
+    let boosts = search::RankingBoosts {
+        recency_weight: boosts.recency_weight.unwrap_or(defaults.recency_weight),
+        recency_half_life_days: boosts.recency_half_life_days.unwrap_or(defaults.recency_half_life_days),
+        type_weights: boosts.type_weights.unwrap_or_default().into_iter().collect(),
+    };
+    boosts.validate()?;
+    Ok(boosts)
+}
+
+fn parse_stemmer(language: Option<String>) -> napi::Result<Option<analysis::Stemmer>> {
+    language
+        .map(|l| analysis::Stemmer::from_str(&l).ok_or(WillowError::InvalidLanguage(l).into()))
+        .transpose()
+}
+
+fn parse_sensitivity(level: Option<String>) -> napi::Result<model::Sensitivity> {
+    match level {
+        Some(l) => Ok(model::Sensitivity::from_str(&l).ok_or(WillowError::InvalidSensitivity(l))?),
+        None => Ok(model::Sensitivity::default()),
+    }
+}
+
 fn node_to_js(node: &model::Node) -> JsNode {
     JsNode {
-        id: node.id.0.clone(),
+        id: node.id.0.to_string(),
         node_type: node.node_type.as_str().to_string(),
         content: node.content.clone(),
-        parent_id: node.parent_id.as_ref().map(|id| id.0.clone()),
-        children: node.children.iter().map(|id| id.0.clone()).collect(),
+        content_format: node.content_format.as_str().to_string(),
+        parent_id: node.parent_id.as_ref().map(|id| id.0.to_string()),
+        children: node.children.iter().map(|id| id.0.to_string()).collect(),
+        extra_parents: node.extra_parents.iter().map(|id| id.0.to_string()).collect(),
+        pinned: node.pinned,
+        sensitivity: node.sensitivity.as_str().to_string(),
+        archived: node.archived,
+        display: node.display.as_ref().map(|d| JsDisplayHints {
+            icon: d.icon.clone(),
+            color: d.color.clone(),
+            collapsed: Some(d.collapsed),
+        }),
+        attachments: map_vec(&node.attachments, attachment_to_js),
         metadata: node.metadata.clone(),
-        previous_values: node
-            .previous_values
-            .iter()
-            .map(|sv| JsSupersededValue {
-                old_content: sv.old_content.clone(),
-                superseded_at: sv.superseded_at.to_rfc3339(),
-                reason: sv.reason.clone(),
-            })
-            .collect(),
+        previous_values: map_vec(&node.previous_values, superseded_to_js),
         temporal: node.temporal.as_ref().map(|t| JsTemporalMetadata {
             valid_from: t.valid_from.map(|d| d.to_rfc3339()),
             valid_until: t.valid_until.map(|d| d.to_rfc3339()),
@@ -198,15 +932,44 @@ fn node_to_js(node: &model::Node) -> JsNode {
         }),
         created_at: node.created_at.to_rfc3339(),
         updated_at: node.updated_at.to_rfc3339(),
+        created_by: node.created_by.clone(),
+        updated_by: node.updated_by.clone(),
     }
 }
 
-fn link_to_js(link: &model::Link) -> JsLink {
-    JsLink {
-        id: link.id.0.clone(),
-        from_node: link.from_node.0.clone(),
-        to_node: link.to_node.0.clone(),
-        relation: link.relation.clone(),
+fn js_tree_to_model(tree: JsNodeTree) -> store::NewNode {
+    store::NewNode {
+        node_type: tree.node_type,
+        content: tree.content,
+        format: tree.content_format,
+        metadata: tree.metadata,
+        temporal: tree.temporal.as_ref().map(js_temporal_to_model),
+        children: tree.children.unwrap_or_default().into_iter().map(js_tree_to_model).collect(),
+    }
+}
+
+fn superseded_to_js(sv: &model::SupersededValue) -> JsSupersededValue {
+    JsSupersededValue {
+        old_content: sv.old_content.clone(),
+        superseded_at: sv.superseded_at.to_rfc3339(),
+        reason: sv.reason.clone(),
+    }
+}
+
+fn attachment_to_js(a: &model::AttachmentRef) -> JsAttachment {
+    JsAttachment {
+        hash: a.hash.clone(),
+        mime: a.mime.clone(),
+        size: a.size as i64,
+    }
+}
+
+fn link_to_js(link: &model::Link) -> JsLink {
+    JsLink {
+        id: link.id.0.to_string(),
+        from_node: link.from_node.0.to_string(),
+        to_node: link.to_node.0.to_string(),
+        relation: link.relation.clone(),
         bidirectional: link.bidirectional,
         confidence: link.confidence.as_ref().map(|c| c.as_str().to_string()),
         created_at: link.created_at.to_rfc3339(),
@@ -219,6 +982,11 @@ fn parse_rfc3339(s: &Option<String>) -> Option<chrono::DateTime<chrono::Utc>> {
         .map(|d| d.with_timezone(&chrono::Utc))
 }
 
+fn parse_date(s: &str) -> napi::Result<chrono::DateTime<chrono::Utc>> {
+    parse_rfc3339(&Some(s.to_string()))
+        .ok_or_else(|| napi::Error::from_reason(format!("Invalid RFC 3339 date: {s}")))
+}
+
 fn js_temporal_to_model(t: &JsTemporalMetadata) -> model::TemporalMetadata {
     model::TemporalMetadata {
         valid_from: parse_rfc3339(&t.valid_from),
@@ -227,14 +995,296 @@ fn js_temporal_to_model(t: &JsTemporalMetadata) -> model::TemporalMetadata {
     }
 }
 
+fn schema_to_js(schema: &schema::MetadataSchema) -> Vec<JsNodeTypeSchema> {
+    schema
+        .types
+        .iter()
+        .map(|(node_type, s)| JsNodeTypeSchema {
+            node_type: node_type.clone(),
+            fields: s
+                .fields
+                .iter()
+                .map(|(key, spec)| JsFieldSpec {
+                    key: key.clone(),
+                    value_type: spec.value_type.as_str().to_string(),
+                    required: Some(spec.required),
+                })
+                .collect(),
+            allow_unknown: Some(s.allow_unknown),
+        })
+        .collect()
+}
+
+fn js_schema_to_model(types: Vec<JsNodeTypeSchema>) -> napi::Result<schema::MetadataSchema> {
+    let mut result = schema::MetadataSchema::default();
+    for t in types {
+        if model::NodeType::from_str(&t.node_type).is_none() {
+            return Err(napi::Error::from_reason(format!("Invalid node type: {}", t.node_type)));
+        }
+        let mut fields = std::collections::BTreeMap::new();
+        for f in t.fields {
+            let value_type = schema::ValueType::from_str(&f.value_type).ok_or_else(|| {
+                napi::Error::from_reason(format!("Invalid value type: {}", f.value_type))
+            })?;
+            fields.insert(
+                f.key,
+                schema::FieldSpec {
+                    value_type,
+                    required: f.required.unwrap_or(false),
+                },
+            );
+        }
+        result.types.insert(
+            t.node_type,
+            schema::NodeTypeSchema {
+                fields,
+                allow_unknown: t.allow_unknown.unwrap_or(true),
+            },
+        );
+    }
+    Ok(result)
+}
+
+fn relation_usage_to_js(u: &relations::RelationUsage) -> JsRelationUsage {
+    JsRelationUsage {
+        relation: u.relation.clone(),
+        count: u.count as u32,
+        registered: u.registered,
+        allow_bidirectional: u.allow_bidirectional,
+        inverse: u.inverse.clone(),
+    }
+}
+
+fn integrity_issue_to_js(issue: &integrity::IntegrityIssue) -> JsIntegrityIssue {
+    let (subject_id, related_id) = issue.ids();
+    JsIntegrityIssue {
+        kind: issue.kind().to_string(),
+        subject_id: subject_id.to_string(),
+        related_id: related_id.map(str::to_string),
+        message: issue.to_string(),
+    }
+}
+
+/// Searches return ten results unless the page asks for another limit.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+fn js_page_to_model(page: Option<JsPage>, default_limit: Option<usize>) -> query::Page {
+    let page = page.unwrap_or(JsPage {
+        offset: None,
+        limit: None,
+    });
+    query::Page {
+        offset: page.offset.unwrap_or(0) as usize,
+        limit: page.limit.map(|l| l as usize).or(default_limit),
+    }
+}
+
+fn search_page_to_js(page: &search::SearchPage) -> JsSearchPage {
+    JsSearchPage {
+        results: map_vec(&page.results, search_result_to_js),
+        total: page.total as u32,
+    }
+}
+
+/// Embeds through a JS `(texts: string[]) => number[][]` callback.
+struct JsEmbeddingProvider<'a> {
+    embed: &'a Function<'a, Vec<String>, Vec<Vec<f64>>>,
+}
+
+impl vector::EmbeddingProvider for JsEmbeddingProvider<'_> {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, WillowError> {
+        let vectors = self
+            .embed
+            .call(texts.to_vec())
+            .map_err(|e| WillowError::EmbeddingProvider(e.reason.clone()))?;
+        Ok(vectors
+            .into_iter()
+            .map(|v| v.into_iter().map(|x| x as f32).collect())
+            .collect())
+    }
+}
+
+#[napi(object)]
+pub struct JsProgress {
+    pub phase: String,
+    pub done: u32,
+    /// Unset when the number of steps is not known up front.
+    pub total: Option<u32>,
+}
+
+/// Called synchronously between steps of a long operation.
+type ProgressListener<'a> = Function<'a, JsProgress, ()>;
+
+/// Pass to a long operation, then call `cancel` (e.g. from its progress
+/// callback) to make it stop with an "Operation cancelled" error.
+#[napi]
+#[derive(Default)]
+pub struct JsCancelToken {
+    inner: CancelToken,
+}
+
+#[napi]
+impl JsCancelToken {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        JsCancelToken::default()
+    }
+
+    #[napi]
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    #[napi]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+fn js_progress<'a>(on_progress: Option<&'a ProgressListener<'a>>, cancel: Option<&JsCancelToken>) -> Progress<'a> {
+    let callback = on_progress.map(|listener| {
+        Box::new(move |update: &ProgressUpdate| {
+            let progress = JsProgress {
+                phase: update.phase.to_string(),
+                done: update.done as u32,
+                total: update.total.map(|t| t as u32),
+            };
+            if let Err(e) = listener.call(progress) {
+                debug!(error = %e, "progress callback failed");
+            }
+        }) as Box<dyn FnMut(&ProgressUpdate) + 'a>
+    });
+    Progress::new(callback, cancel.map(|c| c.inner.clone()))
+}
+
+/// Called with the graph file path; weak, like `IndexListener`.
+type ExternalChangeListener = ThreadsafeFunction<String, (), String, napi::Status, false, true>;
+
+/// Reads the log from the HEAD at the time it was created, a batch at a
+/// time, so long histories need not be materialized at once.
+#[napi]
+pub struct JsLogIterator {
+    repo: vcs::repository::Repository,
+    cursor: vcs::repository::LogCursor,
+}
+
+#[napi]
+impl JsLogIterator {
+    /// The next `batchSize` (default 50) entries, newest first; empty once
+    /// the first commit has been returned.
+    #[napi]
+    pub fn next(&mut self, batch_size: Option<u32>) -> napi::Result<Vec<JsCommitEntry>> {
+        let limit = batch_size.map_or(50, |n| n as usize);
+        let entries = self.repo.log_batch(&mut self.cursor, limit).map_err(napi::Error::from)?;
+        Ok(map_vec(&entries, commit_entry_to_js))
+    }
+
+    #[napi(getter)]
+    pub fn done(&self) -> bool {
+        self.cursor.is_done()
+    }
+}
+
+/// Stops watching when `stop` is called or the object is garbage collected.
+#[napi]
+pub struct JsFileWatcher {
+    inner: crate::storage::FileWatcher,
+}
+
+#[napi]
+impl JsFileWatcher {
+    #[napi]
+    pub fn stop(&self) {
+        self.inner.stop();
+    }
+}
+
+/// Weak, like `IndexListener`.
+type StoreListener = ThreadsafeFunction<JsStoreEvent, (), JsStoreEvent, napi::Status, false, true>;
+
+fn store_event_to_js(event: &StoreEvent, graph: &model::Graph) -> JsStoreEvent {
+    let mut js = JsStoreEvent {
+        kind: event.kind().to_string(),
+        node_id: None,
+        node: None,
+        link_id: None,
+        link: None,
+        commit_hash: None,
+        branch: None,
+    };
+    match event {
+        StoreEvent::NodeCreated(id) | StoreEvent::NodeUpdated(id) | StoreEvent::NodeDeleted(id) => {
+            js.node_id = Some(id.0.to_string());
+            js.node = graph.nodes.get(id).map(|node| node_to_js(node));
+        }
+        StoreEvent::LinkAdded(id) | StoreEvent::LinkUpdated(id) | StoreEvent::LinkRemoved(id) => {
+            js.link_id = Some(id.0.to_string());
+            js.link = graph.links.get(id).map(link_to_js);
+        }
+        StoreEvent::CommitCreated(hash) => js.commit_hash = Some(hash.0.clone()),
+        StoreEvent::BranchSwitched(name) => js.branch = Some(name.clone()),
+        StoreEvent::Reset => {}
+    }
+    js
+}
+
+/// A weak listener, so registering one does not keep the process alive.
+type IndexListener = ThreadsafeFunction<JsIndexEvent, (), JsIndexEvent, napi::Status, false, true>;
+
+/// Forwards index events to a JS listener. Events are queued and delivered
+/// on the JS thread once the mutating call has returned.
+struct JsSearchIndex {
+    listener: IndexListener,
+}
+
+impl JsSearchIndex {
+    fn send(&self, kind: &str, node_id: Option<&model::NodeId>, node: Option<&model::Node>) {
+        let event = JsIndexEvent {
+            kind: kind.to_string(),
+            node_id: node_id.map(|id| id.0.to_string()),
+            node: node.map(node_to_js),
+        };
+        self.listener.call(event, ThreadsafeFunctionCallMode::NonBlocking);
+    }
+}
+
+impl SearchIndex for JsSearchIndex {
+    fn on_node_created(&mut self, node: &model::Node) {
+        self.send("created", Some(&node.id), Some(node));
+    }
+
+    fn on_node_updated(&mut self, node: &model::Node) {
+        self.send("updated", Some(&node.id), Some(node));
+    }
+
+    fn on_node_deleted(&mut self, node_id: &model::NodeId) {
+        self.send("deleted", Some(node_id), None);
+    }
+
+    fn on_reset(&mut self, _graph: &model::Graph) {
+        self.send("reset", None, None);
+    }
+}
+
+fn link_search_result_to_js(r: &search::LinkSearchResult) -> JsLinkSearchResult {
+    JsLinkSearchResult {
+        link: link_to_js(&r.link),
+        from_node: node_to_js(&r.from_node),
+        to_node: node_to_js(&r.to_node),
+        score: r.score,
+        matched_field: r.matched_field.clone(),
+    }
+}
+
 fn search_result_to_js(r: &search::SearchResult) -> JsSearchResult {
     JsSearchResult {
-        node_id: r.node_id.0.clone(),
+        node_id: r.node_id.0.to_string(),
         node_type: r.node_type.clone(),
         content: r.content.clone(),
         score: r.score,
         matched_field: r.matched_field.clone(),
         depth: r.depth as u32,
+        path: r.path.clone(),
     }
 }
 
@@ -255,259 +1305,1815 @@ fn commit_source_to_string(source: &vcs::types::CommitSource) -> (String, Option
         vcs::types::CommitSource::Manual { tool_name } => {
             ("manual".to_string(), tool_name.clone())
         }
-        vcs::types::CommitSource::Merge {
-            source_branch,
-            target_branch,
-        } => (
-            "merge".to_string(),
-            Some(format!("{} -> {}", source_branch, target_branch)),
-        ),
-        vcs::types::CommitSource::Migration => ("migration".to_string(), None),
+        vcs::types::CommitSource::Merge {
+            source_branch,
+            target_branch,
+        } => (
+            "merge".to_string(),
+            Some(format!("{} -> {}", source_branch, target_branch)),
+        ),
+        vcs::types::CommitSource::Migration => ("migration".to_string(), None),
+    }
+}
+
+fn commit_entry_to_js(entry: &vcs::types::CommitEntry) -> JsCommitEntry {
+    let (source, source_detail) = commit_source_to_string(&entry.data.source);
+    JsCommitEntry {
+        hash: entry.hash.0.clone(),
+        message: entry.data.message.clone(),
+        timestamp: entry.data.timestamp.to_rfc3339(),
+        source,
+        source_detail,
+        parents: entry.data.parents.iter().map(|p| p.0.clone()).collect(),
+        storage_type: match entry.data.storage_type {
+            vcs::types::CommitStorageType::Snapshot => "snapshot".to_string(),
+            vcs::types::CommitStorageType::Delta => "delta".to_string(),
+        },
+        stats: entry.data.stats.as_ref().map(commit_stats_to_js),
+    }
+}
+
+fn commit_stats_to_js(s: &vcs::types::CommitStats) -> JsCommitStats {
+    JsCommitStats {
+        nodes_created: s.nodes_created,
+        nodes_updated: s.nodes_updated,
+        nodes_deleted: s.nodes_deleted,
+        links_created: s.links_created,
+        links_removed: s.links_removed,
+        links_updated: s.links_updated,
+    }
+}
+
+fn node_change_to_js(n: &vcs::diff::NodeChangeSummary) -> JsNodeChangeSummary {
+    JsNodeChangeSummary {
+        node_id: n.node_id.clone(),
+        node_type: n.node_type.clone(),
+        content: n.content.clone(),
+        old_content: n.old_content.clone(),
+        path: n.path.clone(),
+        content_changes: n
+            .content_changes
+            .iter()
+            .map(|c| JsContentFieldChange {
+                path: c.path.clone(),
+                old_value: c.old_value.clone(),
+                new_value: c.new_value.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn link_change_to_js(l: &vcs::diff::LinkChangeSummary) -> JsLinkChangeSummary {
+    JsLinkChangeSummary {
+        link_id: l.link_id.clone(),
+        from_node: l.from_node.clone(),
+        to_node: l.to_node.clone(),
+        relation: l.relation.clone(),
+        bidirectional: l.bidirectional,
+        confidence: l.confidence.clone(),
+    }
+}
+
+fn diff_has_changes(diff: &vcs::diff::ChangeSummary) -> bool {
+    !diff.nodes_created.is_empty()
+        || !diff.nodes_updated.is_empty()
+        || !diff.nodes_deleted.is_empty()
+        || !diff.links_created.is_empty()
+        || !diff.links_removed.is_empty()
+        || !diff.links_updated.is_empty()
+}
+
+fn map_vec<T, U>(items: &[T], f: fn(&T) -> U) -> Vec<U> {
+    items.iter().map(f).collect()
+}
+
+fn attachment_change_to_js(a: &vcs::diff::AttachmentChangeSummary) -> JsAttachmentChangeSummary {
+    JsAttachmentChangeSummary {
+        node_id: a.node_id.clone(),
+        hash: a.hash.clone(),
+        mime: a.mime.clone(),
+    }
+}
+
+fn change_summary_to_js(diff: &vcs::diff::ChangeSummary) -> JsChangeSummary {
+    JsChangeSummary {
+        nodes_created: map_vec(&diff.nodes_created, node_change_to_js),
+        nodes_updated: map_vec(&diff.nodes_updated, node_change_to_js),
+        nodes_deleted: map_vec(&diff.nodes_deleted, node_change_to_js),
+        links_created: map_vec(&diff.links_created, link_change_to_js),
+        links_removed: map_vec(&diff.links_removed, link_change_to_js),
+        links_updated: map_vec(&diff.links_updated, link_change_to_js),
+        attachments_added: map_vec(&diff.attachments_added, attachment_change_to_js),
+        attachments_removed: map_vec(&diff.attachments_removed, attachment_change_to_js),
+    }
+}
+
+fn js_input_to_commit_input(input: JsCommitInput) -> vcs::types::CommitInput {
+    let source = js_commit_source(JsCommitSource {
+        source: input.source,
+        conversation_id: input.conversation_id,
+        summary: input.summary,
+        job_id: input.job_id,
+        tool_name: input.tool_name,
+    });
+    vcs::types::CommitInput {
+        message: input.message,
+        source,
+    }
+}
+
+fn repo_config_to_js(config: &vcs::types::RepoConfig) -> JsRepoConfig {
+    JsRepoConfig {
+        format_version: config.format_version,
+        snapshot_interval: config.snapshot_interval,
+        default_branch: config.default_branch.clone(),
+        snapshot_compression_level: config.snapshot_compression_level,
+        snapshot_dictionary: config.snapshot_dictionary,
+        snapshot_keyframe_interval: config.snapshot_keyframe_interval,
+        webhooks: config
+            .webhooks
+            .iter()
+            .map(|hook| JsWebhook {
+                url: hook.url.clone(),
+                events: (!hook.events.is_empty()).then(|| hook.events.iter().map(|e| e.as_str().to_string()).collect()),
+                max_sensitivity: Some(hook.max_sensitivity.as_str().to_string()),
+            })
+            .collect(),
+        object_storage: config.object_storage.as_ref().map(|s3| JsObjectStorage {
+            endpoint: s3.endpoint.clone(),
+            bucket: s3.bucket.clone(),
+            prefix: (!s3.prefix.is_empty()).then(|| s3.prefix.clone()),
+            region: Some(s3.region.clone()),
+        }),
+    }
+}
+
+fn js_webhook_to_model(hook: JsWebhook) -> napi::Result<webhooks::Webhook> {
+    let events = hook
+        .events
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| {
+            webhooks::WebhookEvent::parse(&e)
+                .ok_or_else(|| WillowError::InvalidRepoConfig(format!("unknown webhook event '{e}'")).into())
+        })
+        .collect::<napi::Result<Vec<_>>>()?;
+    Ok(webhooks::Webhook {
+        url: hook.url,
+        events,
+        max_sensitivity: parse_sensitivity(hook.max_sensitivity)?,
+    })
+}
+
+fn rule_to_js(rule: &rules::Rule) -> JsRule {
+    let action = |name: &str| JsRuleAction {
+        action: name.to_string(),
+        tag: None,
+        to: None,
+        relation: None,
+        parent: None,
+        days: None,
+        label: None,
+    };
+    JsRule {
+        id: rule.id.clone(),
+        node_type: rule.matcher.node_type.as_ref().map(|t| t.as_str().to_string()),
+        content_contains: rule.matcher.content_contains.clone(),
+        metadata: Some(rule.matcher.metadata.clone().into_iter().collect()),
+        actions: rule
+            .actions
+            .iter()
+            .map(|a| match a {
+                rules::RuleAction::AddTag { tag } => JsRuleAction {
+                    tag: Some(tag.clone()),
+                    ..action("add_tag")
+                },
+                rules::RuleAction::CreateLink { to, relation } => JsRuleAction {
+                    to: Some(to.0.to_string()),
+                    relation: Some(relation.clone()),
+                    ..action("create_link")
+                },
+                rules::RuleAction::MoveUnder { parent } => JsRuleAction {
+                    parent: Some(parent.0.to_string()),
+                    ..action("move_under")
+                },
+                rules::RuleAction::SetValidity { days, label } => JsRuleAction {
+                    days: Some(*days),
+                    label: label.clone(),
+                    ..action("set_validity")
+                },
+            })
+            .collect(),
+    }
+}
+
+fn js_rule_to_model(rule: JsRule) -> napi::Result<rules::Rule> {
+    let invalid = |reason: String| napi::Error::from(WillowError::InvalidRule(reason));
+    let actions = rule
+        .actions
+        .into_iter()
+        .map(|a| {
+            let missing = |field: &str| invalid(format!("'{}' needs `{field}` for {}", rule.id, a.action));
+            Ok(match a.action.as_str() {
+                "add_tag" => rules::RuleAction::AddTag {
+                    tag: a.tag.clone().ok_or_else(|| missing("tag"))?,
+                },
+                "create_link" => rules::RuleAction::CreateLink {
+                    to: model::NodeId(a.to.as_deref().ok_or_else(|| missing("to"))?.into()),
+                    relation: a.relation.clone().ok_or_else(|| missing("relation"))?,
+                },
+                "move_under" => rules::RuleAction::MoveUnder {
+                    parent: model::NodeId(a.parent.as_deref().ok_or_else(|| missing("parent"))?.into()),
+                },
+                "set_validity" => rules::RuleAction::SetValidity {
+                    days: a.days.ok_or_else(|| missing("days"))?,
+                    label: a.label.clone(),
+                },
+                other => return Err(invalid(format!("unknown action '{other}'"))),
+            })
+        })
+        .collect::<napi::Result<Vec<_>>>()?;
+    let node_type = rule
+        .node_type
+        .map(|t| model::NodeType::from_str(&t).ok_or(WillowError::InvalidNodeType(t)))
+        .transpose()?;
+    Ok(rules::Rule {
+        id: rule.id,
+        matcher: rules::RuleMatch {
+            node_type,
+            content_contains: rule.content_contains,
+            metadata: rule.metadata.unwrap_or_default().into_iter().collect(),
+        },
+        actions,
+    })
+}
+
+fn job_run_to_js(job: &str, run: maintenance::JobRun) -> JsJobRun {
+    JsJobRun {
+        job: job.to_string(),
+        at: run.at.to_rfc3339(),
+        summary: run.summary,
+        failed: run.failed,
+        commit_hash: run.commit.map(|h| h.0),
+    }
+}
+
+fn js_commit_source(input: JsCommitSource) -> vcs::types::CommitSource {
+    match input.source.as_str() {
+        "conversation" => vcs::types::CommitSource::Conversation {
+            conversation_id: input.conversation_id,
+            summary: input.summary,
+        },
+        "maintenance" => vcs::types::CommitSource::Maintenance {
+            job_id: input.job_id,
+        },
+        "migration" => vcs::types::CommitSource::Migration,
+        _ => vcs::types::CommitSource::Manual {
+            tool_name: input.tool_name,
+        },
+    }
+}
+
+// ---- JsGraphStore ----
+
+#[napi]
+pub struct JsGraphStore {
+    inner: store::StoreHandle,
+}
+
+#[napi]
+impl JsGraphStore {
+    fn store(&self) -> store::SessionGuard<'_> {
+        self.inner.lock()
+    }
+
+    fn require_open(&self) -> napi::Result<()> {
+        self.store().require_open().map_err(napi::Error::from)
+    }
+
+    /// The store, locked once this instance is known to be able to write.
+    fn writable(&self) -> napi::Result<store::SessionGuard<'_>> {
+        self.inner.write().map_err(napi::Error::from)
+    }
+
+    /// Open the graph at `filePath`. Instances opened on the same file in
+    /// this process, including from worker threads, share one store, so
+    /// changes made through one are seen by all, and must pass the same
+    /// `options`. Each instance keeps its own actor, undo history and
+    /// transaction. Callbacks that run during a call, such as progress
+    /// listeners, must not call back into the store.
+    #[napi(factory)]
+    pub fn open(file_path: String, options: Option<JsOpenOptions>) -> napi::Result<Self> {
+        crate::init_tracing();
+        let options = options.unwrap_or(JsOpenOptions {
+            compress: None,
+            pretty: None,
+            sorted_keys: None,
+            inline_history: None,
+            lock: None,
+            journal: None,
+            defer_history: None,
+            sharded: None,
+            write_behind_ms: None,
+            write_behind_max_ms: None,
+            audit: None,
+        });
+        let options = store::OpenOptions {
+            storage: crate::storage::StorageOptions {
+                compress: options.compress.unwrap_or(false),
+                pretty: options.pretty.unwrap_or(true),
+                sorted_keys: options.sorted_keys.unwrap_or(true),
+                inline_history: options.inline_history.unwrap_or(true),
+            },
+            journal: options.journal.map(|n| n.max(1) as usize),
+            defer_history: options.defer_history.unwrap_or(false),
+            sharded: options.sharded.unwrap_or(false),
+            read_only: false,
+            audit: options.audit.unwrap_or(false),
+            write_behind: options.write_behind_ms.map(|ms| store::WriteBehind {
+                debounce: std::time::Duration::from_millis(u64::from(ms)),
+                max_delay: std::time::Duration::from_millis(u64::from(
+                    options.write_behind_max_ms.unwrap_or(ms.saturating_mul(10)),
+                )),
+            }),
+            lock: options
+                .lock
+                .map(|l| crate::storage::LockMode::from_str(&l).ok_or(WillowError::InvalidLockMode(l)))
+                .transpose()?,
+        };
+        let inner =
+            store::GraphStore::open_shared(Path::new(&file_path), &options).map_err(napi::Error::from)?;
+        info!("GraphStore opened");
+        Ok(JsGraphStore { inner })
+    }
+
+    /// Open an existing graph without ever writing to it, e.g. from a
+    /// renderer process while the main process owns the store. Shares the
+    /// store of a writer in this process, so it sees the writer's changes.
+    /// Mutating methods fail with a "Store is open read-only" error.
+    #[napi(factory)]
+    pub fn open_read_only(file_path: String) -> napi::Result<Self> {
+        crate::init_tracing();
+        let inner = store::GraphStore::open_shared_read_only(Path::new(&file_path)).map_err(napi::Error::from)?;
+        info!("GraphStore opened read-only");
+        Ok(JsGraphStore { inner })
+    }
+
+    /// Set when `open` found the graph file corrupt and fell back to the
+    /// last backup or commit: what happened and where the corrupt file went.
+    #[napi]
+    pub fn recovery_warning(&self) -> Option<String> {
+        self.store().recovery_warning().map(str::to_string)
+    }
+
+    /// Timings of the main operations since opening, as totals and a
+    /// histogram, and an estimate of the memory the graph holds.
+    #[napi]
+    pub fn perf_report(&self) -> JsPerfReport {
+        debug!("perf_report");
+        let report = self.store().perf_report();
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        JsPerfReport {
+            operations: report
+                .operations
+                .iter()
+                .map(|(operation, t)| JsOperationTimings {
+                    operation: operation.to_string(),
+                    count: t.count as u32,
+                    total_ms: ms(t.total),
+                    mean_ms: ms(t.mean()),
+                    max_ms: ms(t.max),
+                    histogram: t.histogram.iter().map(|&n| n as u32).collect(),
+                })
+                .collect(),
+            bucket_bounds_ms: crate::perf::TIMING_BUCKETS.iter().map(|&d| ms(d)).collect(),
+            memory: JsMemoryFootprint {
+                nodes: report.memory.nodes as u32,
+                links: report.memory.links as u32,
+                content_bytes: report.memory.content_bytes as i64,
+                metadata_bytes: report.memory.metadata_bytes as i64,
+                previous_values_bytes: report.memory.previous_values_bytes as i64,
+                estimated_total_bytes: report.memory.estimated_total_bytes as i64,
+            },
+            pending_changes: report.pending_changes as u32,
+        }
+    }
+
+    /// The top `maxResults` (default 10) ranked matches. `searchNodesPage`
+    /// pages through them and counts them all.
+    #[napi]
+    pub fn search_nodes(
+        &self,
+        query: String,
+        max_results: Option<u32>,
+        root_node_id: Option<String>,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<Vec<JsSearchResult>> {
+        let page = JsPage {
+            offset: None,
+            limit: max_results,
+        };
+        Ok(self.search_nodes_page(query, Some(page), root_node_id, options)?.results)
+    }
+
+    /// Ranked matches; `page.limit` defaults to 10. `total` counts every match.
+    #[napi]
+    pub fn search_nodes_page(
+        &self,
+        query: String,
+        page: Option<JsPage>,
+        root_node_id: Option<String>,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<JsSearchPage> {
+        self.require_open()?;
+        debug!(query = %query, "search_nodes");
+        let options = js_search_options_to_model(options)?;
+        let page = js_page_to_model(page, Some(DEFAULT_SEARCH_LIMIT));
+        Ok(search_page_to_js(&self.store().search_nodes(&query, &page, root_node_id.as_deref(), &options)))
+    }
+
+    /// Embed nodes that are new or changed since the last refresh. `embed` is
+    /// called with batches of texts and must return one vector per text.
+    /// Returns the number of nodes embedded.
+    #[napi]
+    pub fn refresh_embeddings(&mut self, embed: Function<Vec<String>, Vec<Vec<f64>>>) -> napi::Result<u32> {
+        let mut store = self.writable()?;
+        debug!("refresh_embeddings");
+        let provider = JsEmbeddingProvider { embed: &embed };
+        Ok(store.refresh_embeddings(&provider).map_err(napi::Error::from)? as u32)
+    }
+
+    /// The `k` (default 10) nodes most similar to `queryEmbedding` by cosine
+    /// similarity. Nodes changed since the last refresh are left out.
+    #[napi]
+    pub fn semantic_search(
+        &self,
+        query_embedding: Vec<f64>,
+        k: Option<u32>,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<Vec<JsSearchResult>> {
+        self.require_open()?;
+        debug!(k = ?k, "semantic_search");
+        let options = js_search_options_to_model(options)?;
+        let query: Vec<f32> = query_embedding.into_iter().map(|x| x as f32).collect();
+        let k = k.map_or(DEFAULT_SEARCH_LIMIT, |k| k as usize);
+        let results = self.store().semantic_search(&query, k, &options).map_err(napi::Error::from)?;
+        Ok(map_vec(&results, search_result_to_js))
+    }
+
+    /// Keyword and semantic search fused by reciprocal rank; `page.limit`
+    /// defaults to 10.
+    #[napi]
+    pub fn hybrid_search(
+        &self,
+        query: String,
+        query_embedding: Vec<f64>,
+        page: Option<JsPage>,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<JsSearchPage> {
+        self.require_open()?;
+        debug!(query = %query, "hybrid_search");
+        let options = js_search_options_to_model(options)?;
+        let query_embedding: Vec<f32> = query_embedding.into_iter().map(|x| x as f32).collect();
+        let page = js_page_to_model(page, Some(DEFAULT_SEARCH_LIMIT));
+        let results = self
+            .store()
+            .hybrid_search(&query, &query_embedding, &page, &options)
+            .map_err(napi::Error::from)?;
+        Ok(search_page_to_js(&results))
+    }
+
+    /// Call `listener` with an event for every node created, updated or
+    /// deleted from now on, and a `reset` event when the graph is replaced
+    /// (branch switch, restore, rollback), after which an index should be
+    /// rebuilt. Lets external indexes stay consistent without wrapping every
+    /// mutation.
+    #[napi]
+    pub fn add_search_index(&mut self, listener: IndexListener) -> napi::Result<()> {
+        self.require_open()?;
+        debug!("add_search_index");
+        self.store().add_search_index(Box::new(JsSearchIndex { listener }));
+        Ok(())
+    }
+
+    /// Call `listener` with an event for every node and link change, commit
+    /// and branch switch from now on, so views can update in place instead
+    /// of re-fetching the tree. Events are delivered on the JS thread after
+    /// the mutating call returns. Returns an id for `unsubscribe`.
+    #[napi]
+    pub fn subscribe(&mut self, listener: StoreListener) -> napi::Result<u32> {
+        self.require_open()?;
+        debug!("subscribe");
+        Ok(self.store().subscribe(move |event, graph| {
+            listener.call(store_event_to_js(event, graph), ThreadsafeFunctionCallMode::NonBlocking);
+        }))
+    }
+
+    #[napi]
+    pub fn unsubscribe(&mut self, id: u32) -> bool {
+        debug!(id, "unsubscribe");
+        self.store().unsubscribe(id)
+    }
+
+    /// Close this instance, e.g. on app shutdown: its listeners stop and
+    /// its methods throw "Store is closed" afterwards. Closing the last
+    /// instance on a file also writes out anything buffered, stops file
+    /// watchers and releases the file lock. Fails while this instance has
+    /// a transaction open; closing again does nothing.
+    #[napi]
+    pub fn close(&mut self) -> napi::Result<()> {
+        info!("close");
+        self.inner.close().map_err(napi::Error::from)
+    }
+
+    #[napi]
+    pub fn is_closed(&self) -> bool {
+        self.store().is_closed()
+    }
+
+    /// Fold the journal into the graph file now. Call before exiting when
+    /// opened with `journal`, as the store may not be dropped.
+    #[napi]
+    pub fn compact(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!("compact");
+        store.compact().map_err(napi::Error::from)
+    }
+
+    /// Write saves held back by `writeBehindMs` now, e.g. before handing
+    /// the file to another process.
+    #[napi]
+    pub fn flush(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        debug!("flush");
+        store.flush().map_err(napi::Error::from)
+    }
+
+    /// Whether another process wrote the graph file since this store last
+    /// loaded or saved it. Saving is refused until `reloadIfChanged`.
+    #[napi]
+    pub fn changed_on_disk(&self) -> napi::Result<bool> {
+        self.require_open()?;
+        self.store().changed_on_disk().map_err(napi::Error::from)
+    }
+
+    /// Reload an externally changed graph file. Returns whether it reloaded.
+    /// Refused if that would drop unsaved or pending changes or undo
+    /// history, unless `discard` is true.
+    #[napi]
+    pub fn reload_if_changed(&mut self, discard: Option<bool>) -> napi::Result<bool> {
+        self.require_open()?;
+        info!(discard = ?discard, "reload_if_changed");
+        self.store().reload_if_changed(discard.unwrap_or(false)).map_err(napi::Error::from)
+    }
+
+    /// Call `listener` with the file path whenever another process writes the
+    /// graph file. Where file events are unavailable the file is polled every
+    /// `intervalMs` (default 1000).
+    #[napi]
+    pub fn watch_file(
+        &mut self,
+        listener: ExternalChangeListener,
+        interval_ms: Option<u32>,
+    ) -> napi::Result<JsFileWatcher> {
+        self.require_open()?;
+        debug!("watch_file");
+        let path = self.store().path.display().to_string();
+        let interval = std::time::Duration::from_millis(u64::from(interval_ms.unwrap_or(1000)));
+        Ok(JsFileWatcher {
+            inner: self
+                .store()
+                .watch(interval, move || {
+                    listener.call(path.clone(), ThreadsafeFunctionCallMode::NonBlocking);
+                })
+                .map_err(napi::Error::from)?,
+        })
+    }
+
+    /// Up to `limit` (default 10) node contents and tags starting with or
+    /// containing `prefix`, for type-ahead.
+    #[napi]
+    pub fn suggest(&self, prefix: String, limit: Option<u32>) -> Vec<JsSuggestion> {
+        let limit = limit.map_or(DEFAULT_SEARCH_LIMIT, |l| l as usize);
+        self.store()
+            .suggest(&prefix, limit)
+            .into_iter()
+            .map(|s| JsSuggestion {
+                text: s.text,
+                kind: s.kind.as_str().to_string(),
+                node_id: s.node_id.map(|id| id.0.to_string()),
+            })
+            .collect()
+    }
+
+    /// Links whose relation or endpoint content matches `query`, best first.
+    #[napi]
+    pub fn search_links(
+        &self,
+        query: String,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<Vec<JsLinkSearchResult>> {
+        self.require_open()?;
+        debug!(query = %query, "search_links");
+        let options = js_search_options_to_model(options)?;
+        Ok(map_vec(&self.store().search_links(&query, &options), link_search_result_to_js))
+    }
+
+    /// `searchNodes` against the graph as it was at `date`.
+    #[napi]
+    pub fn search_nodes_as_of(
+        &self,
+        query: String,
+        date: String,
+        max_results: Option<u32>,
+        root_node_id: Option<String>,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<Vec<JsSearchResult>> {
+        let page = JsPage {
+            offset: None,
+            limit: max_results,
+        };
+        Ok(self.search_nodes_as_of_page(query, date, Some(page), root_node_id, options)?.results)
+    }
+
+    /// `searchNodesPage` against the graph as it was at `date`.
+    #[napi]
+    pub fn search_nodes_as_of_page(
+        &self,
+        query: String,
+        date: String,
+        page: Option<JsPage>,
+        root_node_id: Option<String>,
+        options: Option<JsSearchOptions>,
+    ) -> napi::Result<JsSearchPage> {
+        self.require_open()?;
+        debug!(query = %query, date = %date, "search_nodes_as_of");
+        let date = parse_date(&date)?;
+        let options = js_search_options_to_model(options)?;
+        let page = js_page_to_model(page, Some(DEFAULT_SEARCH_LIMIT));
+        Ok(search_page_to_js(&self.store().search_nodes_as_of(
+            &query,
+            date,
+            &page,
+            root_node_id.as_deref(),
+            &options,
+        )))
+    }
+
+    /// A single node by id, without its surroundings.
+    #[napi]
+    pub fn get_node(&self, node_id: String) -> napi::Result<JsNode> {
+        self.require_open()?;
+        debug!(node_id = %node_id, "get_node");
+        let store = self.store();
+        let node = store.get_node(&node_id).map_err(napi::Error::from)?;
+        Ok(node_to_js(node))
+    }
+
+    #[napi]
+    pub fn get_link(&self, link_id: String) -> napi::Result<JsLink> {
+        self.require_open()?;
+        debug!(link_id = %link_id, "get_link");
+        let store = self.store();
+        let link = store.get_link(&link_id).map_err(napi::Error::from)?;
+        Ok(link_to_js(link))
+    }
+
+    #[napi]
+    pub fn get_context(
+        &self,
+        node_id: String,
+        depth: Option<u32>,
+        options: Option<JsContextOptions>,
+    ) -> napi::Result<JsContextResult> {
+        self.require_open()?;
+        debug!(node_id = %node_id, "get_context");
+        let options = match options {
+            Some(o) => js_context_options_to_model(o)?,
+            None => store::ContextOptions::default(),
+        };
+        let ctx = self
+            .store()
+            .get_context(&node_id, depth, &options)
+            .map_err(napi::Error::from)?;
+        Ok(context_to_js(&ctx))
+    }
+
+    #[napi]
+    pub fn get_context_as_of(
+        &self,
+        node_id: String,
+        date: String,
+        depth: Option<u32>,
+    ) -> napi::Result<JsContextResult> {
+        self.require_open()?;
+        debug!(node_id = %node_id, date = %date, "get_context_as_of");
+        let ctx = self
+            .store()
+            .get_context_as_of(&node_id, depth, parse_date(&date)?)
+            .map_err(napi::Error::from)?;
+        Ok(context_to_js(&ctx))
+    }
+
+    #[napi]
+    pub fn view_as_of(&self, date: String) -> napi::Result<String> {
+        self.require_open()?;
+        debug!(date = %date, "view_as_of");
+        let graph = self.store().view_as_of(parse_date(&date)?);
+        serde_json::to_string(&graph).map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn get_neighborhood(
+        &self,
+        node_id: String,
+        link_depth: Option<u32>,
+    ) -> napi::Result<JsNeighborhoodResult> {
+        self.require_open()?;
+        debug!(node_id = %node_id, "get_neighborhood");
+        let hood = self
+            .store()
+            .get_neighborhood(&node_id, link_depth)
+            .map_err(napi::Error::from)?;
+        Ok(JsNeighborhoodResult {
+            node: node_to_js(&hood.node),
+            neighbors: hood
+                .neighbors
+                .iter()
+                .map(|n| JsNeighbor {
+                    node: node_to_js(&n.node),
+                    hops: n.hops,
+                })
+                .collect(),
+            links: map_vec(&hood.links, link_to_js),
+        })
+    }
+
+    #[napi]
+    pub fn build_context(&self, node_ids: Vec<String>, max_chars: u32) -> napi::Result<JsAssembledContext> {
+        self.require_open()?;
+        debug!(nodes = node_ids.len(), max_chars, "build_context");
+        let ctx = self
+            .store()
+            .build_context(&node_ids, max_chars as usize)
+            .map_err(napi::Error::from)?;
+        Ok(JsAssembledContext {
+            entries: ctx
+                .entries
+                .iter()
+                .map(|e| JsContextEntry {
+                    node: node_to_js(&e.node),
+                    role: e.role.as_str().to_string(),
+                    depth: e.depth,
+                })
+                .collect(),
+            links: map_vec(&ctx.links, link_to_js),
+            used_chars: ctx.used_chars as u32,
+            omitted: ctx.omitted as u32,
+        })
+    }
+
+    /// Create many nodes in one call, saved once and undone together; none
+    /// are created if any input is invalid. Returns the new ids in order.
+    #[napi]
+    pub fn create_nodes(&mut self, inputs: Vec<JsCreateNodeInput>) -> napi::Result<Vec<String>> {
+        let mut store = self.writable()?;
+        info!(count = inputs.len(), "create_nodes");
+        let nodes = inputs
+            .into_iter()
+            .map(|input| {
+                let node = store::NewNode {
+                    node_type: input.node_type,
+                    content: input.content,
+                    format: input.content_format,
+                    metadata: input.metadata,
+                    temporal: input.temporal.as_ref().map(js_temporal_to_model),
+                    children: Vec::new(),
+                };
+                (input.parent_id, node)
+            })
+            .collect();
+        let created = store.create_nodes(nodes).map_err(napi::Error::from)?;
+        Ok(created.into_iter().map(|n| n.id.0.to_string()).collect())
+    }
+
+    /// Create trees of nodes under `parentId` in one call, like
+    /// `createNodes`. Returns the new ids depth-first, each node before its
+    /// children.
+    #[napi]
+    pub fn create_tree(&mut self, parent_id: String, trees: Vec<JsNodeTree>) -> napi::Result<Vec<String>> {
+        let mut store = self.writable()?;
+        info!(parent = %parent_id, "create_tree");
+        let nodes = trees.into_iter().map(|tree| (parent_id.clone(), js_tree_to_model(tree))).collect();
+        let created = store.create_nodes(nodes).map_err(napi::Error::from)?;
+        Ok(created.into_iter().map(|n| n.id.0.to_string()).collect())
+    }
+
+    #[napi]
+    pub fn create_node(&mut self, input: JsCreateNodeInput) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_type = %input.node_type, parent = %input.parent_id, "create_node");
+        let temporal = input.temporal.as_ref().map(js_temporal_to_model);
+        let node = match input.content_format.as_deref() {
+            Some(format) => store.create_structured_node(
+                &input.parent_id,
+                &input.node_type,
+                format,
+                &input.content,
+                input.metadata,
+                temporal,
+            ),
+            None => store.create_node(
+                &input.parent_id,
+                &input.node_type,
+                &input.content,
+                input.metadata,
+                temporal,
+            ),
+        }
+        .map_err(napi::Error::from)?;
+
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn update_node(&mut self, input: JsUpdateNodeInput) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %input.node_id, "update_node");
+        let temporal = input.temporal.as_ref().map(js_temporal_to_model);
+        let node = store
+            .update_node(
+                &input.node_id,
+                input.content.as_deref(),
+                input.metadata,
+                temporal,
+                input.reason.as_deref(),
+            )
+            .map_err(napi::Error::from)?;
+
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn delete_node(&mut self, node_id: String) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, "delete_node");
+        store.delete_node(&node_id).map_err(napi::Error::from)
+    }
+
+    #[napi]
+    pub fn list_nodes(
+        &self,
+        filter: Option<JsNodeFilter>,
+        sort: Option<JsNodeSort>,
+        page: Option<JsPage>,
+    ) -> napi::Result<JsNodePage> {
+        self.require_open()?;
+        debug!("list_nodes");
+        let filter = filter.map(js_node_filter_to_model).transpose()?.unwrap_or_default();
+        let sort = sort.map(js_node_sort_to_model).transpose()?.unwrap_or_default();
+        let page = js_page_to_model(page, None);
+        let result = self.store().list_nodes(&filter, &sort, &page);
+        Ok(JsNodePage {
+            nodes: map_vec(&result.nodes, node_to_js),
+            total: result.total as u32,
+        })
+    }
+
+    #[napi]
+    pub fn list_links(&self, filter: Option<JsLinkFilter>, page: Option<JsPage>) -> napi::Result<JsLinkPage> {
+        self.require_open()?;
+        debug!("list_links");
+        let filter = filter.map(js_link_filter_to_model).transpose()?.unwrap_or_default();
+        let result = self.store().list_links(&filter, &js_page_to_model(page, None));
+        Ok(JsLinkPage {
+            links: map_vec(&result.links, link_to_js),
+            total: result.total as u32,
+        })
+    }
+
+    #[napi]
+    pub fn count_nodes(&self, filter: Option<JsNodeFilter>) -> napi::Result<u32> {
+        self.require_open()?;
+        let filter = filter.map(js_node_filter_to_model).transpose()?.unwrap_or_default();
+        Ok(self.store().count_nodes(&filter) as u32)
+    }
+
+    #[napi]
+    pub fn node_exists(&self, node_id: String) -> bool {
+        self.store().node_exists(&node_id)
+    }
+
+    /// A node's superseded values, oldest first, including any still on
+    /// disk when opened with `deferHistory`.
+    #[napi]
+    pub fn node_history(&self, node_id: String) -> napi::Result<Vec<JsSupersededValue>> {
+        self.require_open()?;
+        debug!(node = %node_id, "node_history");
+        let values = self.store().node_history(&node_id).map_err(napi::Error::from)?;
+        Ok(map_vec(&values, superseded_to_js))
+    }
+
+    /// Read all history deferred by `deferHistory` into memory.
+    #[napi]
+    pub fn load_history(&mut self) -> napi::Result<()> {
+        self.require_open()?;
+        info!("load_history");
+        self.store().load_history().map_err(napi::Error::from)
+    }
+
+    /// Pairs of sibling nodes of the same type with similar content, most
+    /// similar first. `threshold` is a trigram Jaccard score in 0..1.
+    #[napi]
+    pub fn find_duplicates(&self, threshold: f64) -> Vec<JsDuplicatePair> {
+        debug!(threshold, "find_duplicates");
+        self.store()
+            .find_duplicates(threshold)
+            .into_iter()
+            .map(|p| JsDuplicatePair {
+                a: p.a.0.to_string(),
+                b: p.b.0.to_string(),
+                similarity: p.similarity,
+            })
+            .collect()
+    }
+
+    #[napi]
+    pub fn find_by_content_exact(&self, content: String) -> Vec<String> {
+        self.store()
+            .find_by_content_exact(&content)
+            .into_iter()
+            .map(|id| id.0.to_string())
+            .collect()
+    }
+
+    /// Delete every node matching the filter as one undoable change.
+    /// Returns the number of nodes removed, including descendants.
+    #[napi]
+    pub fn delete_where(&mut self, filter: JsNodeFilter) -> napi::Result<u32> {
+        let mut store = self.writable()?;
+        info!("delete_where");
+        let filter = js_node_filter_to_model(filter)?;
+        let removed = store.delete_where(&filter).map_err(napi::Error::from)?;
+        Ok(removed as u32)
+    }
+
+    #[napi]
+    pub fn add_link(&mut self, input: JsAddLinkInput) -> napi::Result<JsLink> {
+        let mut store = self.writable()?;
+        info!(from = %input.from_node, to = %input.to_node, relation = %input.relation, "add_link");
+        let link = store
+            .add_link(
+                &input.from_node,
+                &input.to_node,
+                &input.relation,
+                input.bidirectional.unwrap_or(false),
+                input.confidence.as_deref(),
+            )
+            .map_err(napi::Error::from)?;
+
+        Ok(link_to_js(&link))
+    }
+
+    #[napi]
+    pub fn update_link(&mut self, input: JsUpdateLinkInput) -> napi::Result<JsLink> {
+        let mut store = self.writable()?;
+        info!(link_id = %input.link_id, "update_link");
+        let link = store
+            .update_link(
+                &input.link_id,
+                input.relation.as_deref(),
+                input.bidirectional,
+                input.confidence.as_deref(),
+            )
+            .map_err(napi::Error::from)?;
+
+        Ok(link_to_js(&link))
+    }
+
+    #[napi]
+    pub fn delete_link(&mut self, link_id: String) -> napi::Result<JsLink> {
+        let mut store = self.writable()?;
+        info!(link_id = %link_id, "delete_link");
+        let link = store
+            .delete_link(&link_id)
+            .map_err(napi::Error::from)?;
+        Ok(link_to_js(&link))
+    }
+
+    #[napi]
+    pub fn clone_subtree(
+        &mut self,
+        node_id: String,
+        new_parent_id: String,
+        include_links: Option<bool>,
+    ) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, new_parent = %new_parent_id, "clone_subtree");
+        let node = store
+            .clone_subtree(&node_id, &new_parent_id, include_links.unwrap_or(false))
+            .map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    /// Set (or, with no value, remove) a metadata key on every matching node
+    /// as one undoable change. Returns the ids of nodes that changed.
+    #[napi]
+    pub fn update_metadata_bulk(
+        &mut self,
+        filter: JsNodeFilter,
+        key: String,
+        value: Option<String>,
+    ) -> napi::Result<Vec<String>> {
+        let mut store = self.writable()?;
+        info!(key = %key, "update_metadata_bulk");
+        let filter = js_node_filter_to_model(filter)?;
+        let updated = store
+            .update_metadata_bulk(&filter, &key, value.as_deref())
+            .map_err(napi::Error::from)?;
+        Ok(updated.into_iter().map(|id| id.0.to_string()).collect())
+    }
+
+    #[napi]
+    pub fn archive_node(&mut self, node_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, "archive_node");
+        let node = store.archive_node(&node_id).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn unarchive_node(&mut self, node_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, "unarchive_node");
+        let node = store.unarchive_node(&node_id).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    /// `level` is `normal`, `sensitive` or `secret`.
+    #[napi]
+    pub fn set_sensitivity(&mut self, node_id: String, level: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, level = %level, "set_sensitivity");
+        let node = store.set_sensitivity(&node_id, &level).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    /// Replace a node's display hints; pass nothing to clear them.
+    #[napi]
+    pub fn set_display(&mut self, node_id: String, display: Option<JsDisplayHints>) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, "set_display");
+        let display = display.map(|d| model::DisplayHints {
+            icon: d.icon,
+            color: d.color,
+            collapsed: d.collapsed.unwrap_or(false),
+        });
+        let node = store.set_display(&node_id, display).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn pin_node(&mut self, node_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, "pin_node");
+        let node = store.pin_node(&node_id).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn unpin_node(&mut self, node_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, "unpin_node");
+        let node = store.unpin_node(&node_id).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn list_pinned(&self) -> Vec<JsNode> {
+        debug!("list_pinned");
+        map_vec(&self.store().list_pinned(), node_to_js)
+    }
+
+    #[napi]
+    pub fn supersede_node(&mut self, old_id: String, new_content: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %old_id, "supersede_node");
+        let node = store
+            .supersede_node(&old_id, &new_content)
+            .map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn reorder_children(
+        &mut self,
+        parent_id: String,
+        ordered_ids: Vec<String>,
+    ) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(parent = %parent_id, "reorder_children");
+        let node = store
+            .reorder_children(&parent_id, &ordered_ids)
+            .map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn add_parent(&mut self, node_id: String, parent_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, parent = %parent_id, "add_parent");
+        let node = store
+            .add_parent(&node_id, &parent_id)
+            .map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn remove_parent(&mut self, node_id: String, parent_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, parent = %parent_id, "remove_parent");
+        let node = store
+            .remove_parent(&node_id, &parent_id)
+            .map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    #[napi]
+    pub fn extract_subgraph(&self, node_id: String, max_sensitivity: Option<String>) -> napi::Result<String> {
+        self.require_open()?;
+        debug!(node_id = %node_id, "extract_subgraph");
+        let graph = self
+            .store()
+            .extract_subgraph(&node_id, parse_sensitivity(max_sensitivity)?)
+            .map_err(napi::Error::from)?;
+        serde_json::to_string(&graph).map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn export_subgraph(
+        &self,
+        node_id: String,
+        path: String,
+        max_sensitivity: Option<String>,
+    ) -> napi::Result<()> {
+        self.require_open()?;
+        info!(node_id = %node_id, path = %path, "export_subgraph");
+        self.store()
+            .export_subgraph(&node_id, Path::new(&path), parse_sensitivity(max_sensitivity)?)
+            .map_err(napi::Error::from)
+    }
+
+    /// The whole graph as `json` (default), `msgpack` or `compressed`
+    /// (zstd JSON), taken from memory so it never races a save.
+    #[napi]
+    pub fn export_graph(&mut self, format: Option<String>) -> napi::Result<Buffer> {
+        self.require_open()?;
+        let format = parse_graph_format(format)?.unwrap_or_default();
+        let data = self.store().export_graph(format).map_err(napi::Error::from)?;
+        Ok(data.into())
+    }
+
+    /// Replace the graph with an `exportGraph` buffer. The format is
+    /// detected unless `options.format` is given.
+    #[napi]
+    pub fn import_graph(&mut self, data: Buffer, options: Option<JsImportGraphOptions>) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        let format = parse_graph_format(options.and_then(|o| o.format))?;
+        store.import_graph(&data, format).map_err(napi::Error::from)
+    }
+
+    /// UTF-8 CSV of every node up to `maxSensitivity` (default `normal`):
+    /// id, type, content, parent, path, dates and metadata as JSON.
+    #[napi]
+    pub fn export_csv_nodes(&self, max_sensitivity: Option<String>) -> napi::Result<Buffer> {
+        self.require_open()?;
+        info!("export_csv_nodes");
+        Ok(self.store().export_csv_nodes(parse_sensitivity(max_sensitivity)?).into_bytes().into())
+    }
+
+    /// UTF-8 CSV of the links between nodes up to `maxSensitivity`.
+    #[napi]
+    pub fn export_csv_links(&self, max_sensitivity: Option<String>) -> napi::Result<Buffer> {
+        self.require_open()?;
+        info!("export_csv_links");
+        Ok(self.store().export_csv_links(parse_sensitivity(max_sensitivity)?).into_bytes().into())
+    }
+
+    // ---- Import ----
+
+    /// Import a Markdown outline under `parentId`: headings become
+    /// categories, nested list items entities and details. One undoable
+    /// operation, committed when VCS is initialized.
+    #[napi]
+    pub fn import_markdown(
+        &mut self,
+        parent_id: String,
+        text: String,
+        on_progress: Option<ProgressListener>,
+        cancel: Option<&JsCancelToken>,
+    ) -> napi::Result<JsImportReport> {
+        let mut store = self.writable()?;
+        info!(parent = %parent_id, "import_markdown");
+        let mut progress = js_progress(on_progress.as_ref(), cancel);
+        let report = store.import_markdown(&parent_id, &text, &mut progress).map_err(napi::Error::from)?;
+        Ok(import_report_to_js(report))
+    }
+
+    /// Import an Obsidian vault directory under `parentId`: folders become
+    /// categories, notes entities, frontmatter metadata and `[[wikilinks]]`
+    /// `links_to` links.
+    #[napi]
+    pub fn import_obsidian(
+        &mut self,
+        parent_id: String,
+        vault_dir: String,
+        on_progress: Option<ProgressListener>,
+        cancel: Option<&JsCancelToken>,
+    ) -> napi::Result<JsImportReport> {
+        let mut store = self.writable()?;
+        info!(parent = %parent_id, vault = %vault_dir, "import_obsidian");
+        let mut progress = js_progress(on_progress.as_ref(), cancel);
+        let report = store
+            .import_obsidian(&parent_id, Path::new(&vault_dir), &mut progress)
+            .map_err(napi::Error::from)?;
+        Ok(import_report_to_js(report))
+    }
+
+    /// Import the outlines of an OPML document under `parentId`. Attributes
+    /// other than `text` become snake_case metadata, e.g. `xml_url`.
+    #[napi]
+    pub fn import_opml(
+        &mut self,
+        parent_id: String,
+        text: String,
+        on_progress: Option<ProgressListener>,
+        cancel: Option<&JsCancelToken>,
+    ) -> napi::Result<JsImportReport> {
+        let mut store = self.writable()?;
+        info!(parent = %parent_id, "import_opml");
+        let mut progress = js_progress(on_progress.as_ref(), cancel);
+        let report = store.import_opml(&parent_id, &text, &mut progress).map_err(napi::Error::from)?;
+        Ok(import_report_to_js(report))
+    }
+
+    /// Import a Roam Research or Logseq JSON export under `parentId`: pages
+    /// and blocks become nodes, `((uid))` block references `references`
+    /// links and `[[Page]]` references `links_to` links.
+    #[napi]
+    pub fn import_roam_json(
+        &mut self,
+        parent_id: String,
+        text: String,
+        on_progress: Option<ProgressListener>,
+        cancel: Option<&JsCancelToken>,
+    ) -> napi::Result<JsImportReport> {
+        let mut store = self.writable()?;
+        info!(parent = %parent_id, "import_roam_json");
+        let mut progress = js_progress(on_progress.as_ref(), cancel);
+        let report = store.import_roam_json(&parent_id, &text, &mut progress).map_err(napi::Error::from)?;
+        Ok(import_report_to_js(report))
+    }
+
+    // ---- Attribution ----
+
+    /// Attribute subsequent nodes and changes to `actor` (a tool name,
+    /// conversation id or user); pass nothing to clear it.
+    #[napi]
+    pub fn set_actor(&mut self, actor: Option<String>) {
+        info!(actor = ?actor, "set_actor");
+        self.store().set_actor(actor);
+    }
+
+    // ---- Audit log ----
+
+    /// Entries of the audit log matching `query`, oldest first.
+    #[napi]
+    pub fn audit_log(&self, query: Option<JsAuditQuery>) -> napi::Result<Vec<JsAuditEntry>> {
+        self.require_open()?;
+        debug!("audit_log");
+        let query = match query {
+            Some(q) => crate::audit::AuditQuery {
+                since: q.since.as_deref().map(parse_date).transpose()?,
+                until: q.until.as_deref().map(parse_date).transpose()?,
+                actor: q.actor,
+                operation: q.operation,
+                node_id: q.node_id.map(|id| model::NodeId(id.into())),
+                limit: q.limit.map(|n| n as usize),
+            },
+            None => crate::audit::AuditQuery::default(),
+        };
+        let entries = self.store().audit_log(&query).map_err(napi::Error::from)?;
+        Ok(entries
+            .into_iter()
+            .map(|e| JsAuditEntry {
+                timestamp: e.timestamp.to_rfc3339(),
+                actor: e.actor,
+                operation: e.operation,
+                node_ids: e.node_ids.into_iter().map(|id| id.0.to_string()).collect(),
+                link_ids: e.link_ids.into_iter().map(|id| id.0.to_string()).collect(),
+                target: e.target,
+            })
+            .collect())
+    }
+
+    /// Drop audit entries made before `before` (RFC 3339). Returns how many.
+    #[napi]
+    pub fn prune_audit_log(&mut self, before: String) -> napi::Result<u32> {
+        let mut store = self.writable()?;
+        info!(before = %before, "prune_audit_log");
+        let pruned = store.prune_audit_log(parse_date(&before)?).map_err(napi::Error::from)?;
+        Ok(pruned as u32)
+    }
+
+    // ---- Profiles ----
+
+    #[napi]
+    pub fn list_profiles(&self) -> Vec<JsProfile> {
+        let store = self.store();
+        let current = store.current_profile();
+        store
+            .list_profiles()
+            .into_iter()
+            .map(|(name, root_id)| JsProfile {
+                current: name == current,
+                name,
+                root_id: root_id.0.to_string(),
+            })
+            .collect()
+    }
+
+    /// Add a profile with its own empty root tree and return its root node.
+    #[napi]
+    pub fn create_profile(&mut self, name: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(profile = %name, "create_profile");
+        let node = store.create_profile(&name).map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
+
+    /// Scope unrooted searches and context of `"root"` to this profile.
+    #[napi]
+    pub fn switch_profile(&mut self, name: String) -> napi::Result<()> {
+        self.require_open()?;
+        self.store().switch_profile(&name).map_err(napi::Error::from)
+    }
+
+    // ---- Metadata schema ----
+
+    #[napi]
+    pub fn get_metadata_schema(&self) -> Vec<JsNodeTypeSchema> {
+        schema_to_js(&self.store().schema)
+    }
+
+    #[napi]
+    pub fn set_metadata_schema(&mut self, types: Vec<JsNodeTypeSchema>) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!(types = types.len(), "set_metadata_schema");
+        let schema = js_schema_to_model(types)?;
+        store.set_metadata_schema(schema).map_err(napi::Error::from)
+    }
+
+    // ---- Text analysis ----
+
+    #[napi]
+    pub fn get_text_analysis(&self) -> JsTextAnalysis {
+        let analysis = &self.store().analysis;
+        JsTextAnalysis {
+            stemmer: analysis.stemmer.map(|s| s.as_str().to_string()),
+            synonyms: analysis.synonyms.clone(),
+        }
+    }
+
+    /// Set the stemming and synonyms used by searches that do not set their own.
+    #[napi]
+    pub fn set_text_analysis(&mut self, analysis: JsTextAnalysis) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!("set_text_analysis");
+        store
+            .set_text_analysis(analysis::TextAnalysis {
+                stemmer: parse_stemmer(analysis.stemmer)?,
+                synonyms: analysis.synonyms,
+            })
+            .map_err(napi::Error::from)
+    }
+
+    // ---- Ranking ----
+
+    #[napi]
+    pub fn get_ranking_boosts(&self) -> JsRankingBoosts {
+        let ranking = &self.store().ranking;
+        JsRankingBoosts {
+            recency_weight: Some(ranking.recency_weight),
+            recency_half_life_days: Some(ranking.recency_half_life_days),
+            type_weights: Some(ranking.type_weights.clone().into_iter().collect()),
+        }
+    }
+
+    /// Set the recency and node type boosts used by searches that do not set
+    /// their own.
+    #[napi]
+    pub fn set_ranking_boosts(&mut self, boosts: JsRankingBoosts) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!("set_ranking_boosts");
+        let boosts = js_ranking_boosts_to_model(boosts)?;
+        store.set_ranking_boosts(boosts).map_err(napi::Error::from)
+    }
+
+    // ---- Limits ----
+
+    #[napi]
+    pub fn get_limits(&self) -> JsLimits {
+        let limits = &self.store().limits;
+        JsLimits {
+            max_content_len: limits.max_content_len as u32,
+            max_metadata_entries: limits.max_metadata_entries as u32,
+            max_metadata_value_len: limits.max_metadata_value_len as u32,
+            max_children: limits.max_children as u32,
+        }
+    }
+
+    #[napi]
+    pub fn set_limits(&mut self, limits: JsLimits) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!("set_limits");
+        store
+            .set_limits(limits::Limits {
+                max_content_len: limits.max_content_len as usize,
+                max_metadata_entries: limits.max_metadata_entries as usize,
+                max_metadata_value_len: limits.max_metadata_value_len as usize,
+                max_children: limits.max_children as usize,
+            })
+            .map_err(napi::Error::from)
+    }
+
+    // ---- History retention ----
+
+    #[napi]
+    pub fn get_retention_policy(&self) -> JsRetentionPolicy {
+        let policy = &self.store().retention;
+        JsRetentionPolicy {
+            max_values: policy.max_values.map(|n| n as u32),
+            max_age_days: policy.max_age_days,
+            summarize: Some(policy.summarize),
+        }
     }
-}
 
-fn commit_entry_to_js(entry: &vcs::types::CommitEntry) -> JsCommitEntry {
-    let (source, source_detail) = commit_source_to_string(&entry.data.source);
-    JsCommitEntry {
-        hash: entry.hash.0.clone(),
-        message: entry.data.message.clone(),
-        timestamp: entry.data.timestamp.to_rfc3339(),
-        source,
-        source_detail,
-        parents: entry.data.parents.iter().map(|p| p.0.clone()).collect(),
-        storage_type: match entry.data.storage_type {
-            vcs::types::CommitStorageType::Snapshot => "snapshot".to_string(),
-            vcs::types::CommitStorageType::Delta => "delta".to_string(),
-        },
+    #[napi]
+    pub fn set_retention_policy(&mut self, policy: JsRetentionPolicy) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!("set_retention_policy");
+        store
+            .set_retention_policy(retention::RetentionPolicy {
+                max_values: policy.max_values.map(|n| n as usize),
+                max_age_days: policy.max_age_days,
+                summarize: policy.summarize.unwrap_or(false),
+            })
+            .map_err(napi::Error::from)
     }
-}
 
-fn node_change_to_js(n: &vcs::diff::NodeChangeSummary) -> JsNodeChangeSummary {
-    JsNodeChangeSummary {
-        node_id: n.node_id.clone(),
-        node_type: n.node_type.clone(),
-        content: n.content.clone(),
-        old_content: n.old_content.clone(),
-        path: n.path.clone(),
+    /// Apply the retention policy to every node; returns the values removed.
+    #[napi]
+    pub fn prune_history(&mut self) -> napi::Result<u32> {
+        let mut store = self.writable()?;
+        info!("prune_history");
+        let pruned = store.prune_history(chrono::Utc::now()).map_err(napi::Error::from)?;
+        Ok(pruned as u32)
     }
-}
 
-fn link_change_to_js(l: &vcs::diff::LinkChangeSummary) -> JsLinkChangeSummary {
-    JsLinkChangeSummary {
-        link_id: l.link_id.clone(),
-        from_node: l.from_node.clone(),
-        to_node: l.to_node.clone(),
-        relation: l.relation.clone(),
-        bidirectional: l.bidirectional,
-        confidence: l.confidence.clone(),
+    // ---- Relation registry ----
+
+    #[napi]
+    pub fn get_relation_registry(&self) -> Vec<JsRelationSpec> {
+        self.store()
+            .relations
+            .relations
+            .iter()
+            .map(|(relation, spec)| JsRelationSpec {
+                relation: relation.clone(),
+                allow_bidirectional: Some(spec.allow_bidirectional),
+                inverse: spec.inverse.clone(),
+            })
+            .collect()
     }
-}
 
-fn diff_has_changes(diff: &vcs::diff::ChangeSummary) -> bool {
-    !diff.nodes_created.is_empty()
-        || !diff.nodes_updated.is_empty()
-        || !diff.nodes_deleted.is_empty()
-        || !diff.links_created.is_empty()
-        || !diff.links_removed.is_empty()
-        || !diff.links_updated.is_empty()
-}
+    #[napi]
+    pub fn set_relation_registry(&mut self, specs: Vec<JsRelationSpec>) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!(relations = specs.len(), "set_relation_registry");
+        let registry = relations::RelationRegistry {
+            relations: specs
+                .into_iter()
+                .map(|s| {
+                    (
+                        s.relation,
+                        relations::RelationSpec {
+                            allow_bidirectional: s.allow_bidirectional.unwrap_or(true),
+                            inverse: s.inverse,
+                        },
+                    )
+                })
+                .collect(),
+        };
+        store.set_relation_registry(registry).map_err(napi::Error::from)
+    }
 
-fn map_vec<T, U>(items: &[T], f: fn(&T) -> U) -> Vec<U> {
-    items.iter().map(f).collect()
-}
+    #[napi]
+    pub fn list_relations(&self) -> Vec<JsRelationUsage> {
+        debug!("list_relations");
+        map_vec(&self.store().list_relations(), relation_usage_to_js)
+    }
 
-fn change_summary_to_js(diff: &vcs::diff::ChangeSummary) -> JsChangeSummary {
-    JsChangeSummary {
-        nodes_created: map_vec(&diff.nodes_created, node_change_to_js),
-        nodes_updated: map_vec(&diff.nodes_updated, node_change_to_js),
-        nodes_deleted: map_vec(&diff.nodes_deleted, node_change_to_js),
-        links_created: map_vec(&diff.links_created, link_change_to_js),
-        links_removed: map_vec(&diff.links_removed, link_change_to_js),
-        links_updated: map_vec(&diff.links_updated, link_change_to_js),
+    // ---- Integrity ----
+
+    #[napi]
+    pub fn validate(&self) -> Vec<JsIntegrityIssue> {
+        debug!("validate");
+        map_vec(&self.store().validate(), integrity_issue_to_js)
     }
-}
 
-fn js_input_to_commit_input(input: JsCommitInput) -> vcs::types::CommitInput {
-    let source = match input.source.as_str() {
-        "conversation" => vcs::types::CommitSource::Conversation {
-            conversation_id: input.conversation_id,
-            summary: input.summary,
-        },
-        "maintenance" => vcs::types::CommitSource::Maintenance {
-            job_id: input.job_id,
-        },
-        "migration" => vcs::types::CommitSource::Migration,
-        _ => vcs::types::CommitSource::Manual {
-            tool_name: input.tool_name,
-        },
-    };
-    vcs::types::CommitInput {
-        message: input.message,
-        source,
+    #[napi]
+    pub fn repair(&mut self) -> napi::Result<JsRepairReport> {
+        let mut store = self.writable()?;
+        info!("repair");
+        let report = store.repair().map_err(napi::Error::from)?;
+        Ok(JsRepairReport {
+            repaired: map_vec(&report.repaired, integrity_issue_to_js),
+            remaining: map_vec(&report.remaining, integrity_issue_to_js),
+        })
     }
-}
 
-// ---- JsGraphStore ----
+    #[napi]
+    pub fn list_orphans(&self) -> Vec<JsNode> {
+        debug!("list_orphans");
+        map_vec(&self.store().list_orphans(), node_to_js)
+    }
 
-#[napi]
-pub struct JsGraphStore {
-    inner: store::GraphStore,
-}
+    #[napi]
+    pub fn adopt_orphan(&mut self, node_id: String, parent_id: String) -> napi::Result<JsNode> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, parent = %parent_id, "adopt_orphan");
+        let node = store
+            .adopt_orphan(&node_id, &parent_id)
+            .map_err(napi::Error::from)?;
+        Ok(node_to_js(&node))
+    }
 
-#[napi]
-impl JsGraphStore {
-    fn repo(&self) -> napi::Result<&vcs::repository::Repository> {
-        self.inner.get_repo().map_err(napi::Error::from)
+    // ---- Attachments ----
+
+    #[napi]
+    pub fn attach_blob(&mut self, node_id: String, data: Buffer, mime: String) -> napi::Result<JsAttachment> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, mime = %mime, "attach_blob");
+        let attachment = store
+            .attach_blob(&node_id, &data, &mime)
+            .map_err(napi::Error::from)?;
+        Ok(attachment_to_js(&attachment))
     }
 
-    #[napi(factory)]
-    pub fn open(file_path: String) -> napi::Result<Self> {
-        crate::init_tracing();
-        let inner =
-            store::GraphStore::open(Path::new(&file_path)).map_err(napi::Error::from)?;
-        info!("GraphStore opened");
-        Ok(JsGraphStore { inner })
+    #[napi]
+    pub fn detach_blob(&mut self, node_id: String, hash: String) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!(node_id = %node_id, hash = %hash, "detach_blob");
+        store.detach_blob(&node_id, &hash).map_err(napi::Error::from)
     }
 
     #[napi]
-    pub fn search_nodes(
-        &self,
-        query: String,
-        max_results: Option<u32>,
-        root_node_id: Option<String>,
-    ) -> Vec<JsSearchResult> {
-        debug!(query = %query, "search_nodes");
-        map_vec(
-            &self.inner.search_nodes(&query, max_results.map(|n| n as usize), root_node_id.as_deref()),
-            search_result_to_js,
-        )
+    pub fn get_attachment(&self, hash: String) -> napi::Result<Buffer> {
+        self.require_open()?;
+        debug!(hash = %hash, "get_attachment");
+        let bytes = self.store().get_attachment(&hash).map_err(napi::Error::from)?;
+        Ok(bytes.into())
     }
 
+    /// Delete unreachable attachment blobs. `onProgress` is called for each
+    /// commit scanned for references; cancelling `cancel` stops the scan
+    /// before anything is deleted.
     #[napi]
-    pub fn get_context(
+    pub fn gc_attachments(
         &self,
-        node_id: String,
-        depth: Option<u32>,
-    ) -> napi::Result<JsContextResult> {
-        debug!(node_id = %node_id, "get_context");
-        let ctx = self.inner.get_context(&node_id, depth).map_err(napi::Error::from)?;
-        Ok(JsContextResult {
-            node: node_to_js(&ctx.node),
-            ancestors: map_vec(&ctx.ancestors, node_to_js),
-            descendants: map_vec(&ctx.descendants, node_to_js),
-            links: map_vec(&ctx.links, link_to_js),
-        })
+        on_progress: Option<ProgressListener>,
+        cancel: Option<&JsCancelToken>,
+    ) -> napi::Result<Vec<String>> {
+        let store = self.writable()?;
+        info!("gc_attachments");
+        let mut progress = js_progress(on_progress.as_ref(), cancel);
+        store.gc_attachments(&mut progress).map_err(napi::Error::from)
     }
 
+    // ---- Maintenance ----
+
     #[napi]
-    pub fn create_node(&mut self, input: JsCreateNodeInput) -> napi::Result<JsNode> {
-        info!(node_type = %input.node_type, parent = %input.parent_id, "create_node");
-        let temporal = input.temporal.as_ref().map(js_temporal_to_model);
-        let node = self
-            .inner
-            .create_node(
-                &input.parent_id,
-                &input.node_type,
-                &input.content,
-                input.metadata,
-                temporal,
-            )
+    pub fn sweep_expired(&mut self, policy: String, job_id: Option<String>) -> napi::Result<JsSweepReport> {
+        let mut store = self.writable()?;
+        info!(policy = %policy, "sweep_expired");
+        let report = store
+            .sweep_expired(&policy, chrono::Utc::now(), job_id)
             .map_err(napi::Error::from)?;
+        Ok(JsSweepReport {
+            expired: report.expired.into_iter().map(|id| id.0.to_string()).collect(),
+            commit_hash: report.commit.map(|h| h.0),
+        })
+    }
 
-        Ok(node_to_js(&node))
+    #[napi]
+    pub fn maintenance_jobs(&self) -> napi::Result<Vec<JsScheduledJob>> {
+        self.require_open()?;
+        let schedule = self.store().maintenance_schedule().map_err(napi::Error::from)?;
+        Ok(schedule
+            .jobs
+            .into_iter()
+            .map(|s| {
+                let name = s.job.name();
+                let (policy, threshold) = match &s.job {
+                    maintenance::MaintenanceJob::ExpireTemporal { policy } => (Some(policy.as_str().to_string()), None),
+                    maintenance::MaintenanceJob::DetectDuplicates { threshold } => (None, Some(*threshold)),
+                    _ => (None, None),
+                };
+                JsScheduledJob {
+                    job: name.to_string(),
+                    interval_hours: s.interval_hours,
+                    policy,
+                    threshold,
+                    last_run: s.last_run.map(|run| job_run_to_js(name, run)),
+                }
+            })
+            .collect())
     }
 
+    /// Have `runDueJobs` run `job` every `intervalHours`, replacing a job of
+    /// the same kind. `policy` (default `flag`) is for `expire_temporal`,
+    /// `threshold` (default 0.8) for `detect_duplicates`.
     #[napi]
-    pub fn update_node(&mut self, input: JsUpdateNodeInput) -> napi::Result<JsNode> {
-        info!(node_id = %input.node_id, "update_node");
-        let temporal = input.temporal.as_ref().map(js_temporal_to_model);
-        let node = self
-            .inner
-            .update_node(
-                &input.node_id,
-                input.content.as_deref(),
-                input.metadata,
-                temporal,
-                input.reason.as_deref(),
-            )
-            .map_err(napi::Error::from)?;
+    pub fn register_job(
+        &mut self,
+        job: String,
+        interval_hours: u32,
+        policy: Option<String>,
+        threshold: Option<f64>,
+    ) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!(job = %job, interval_hours, "register_job");
+        let job = match job.as_str() {
+            "expire_temporal" => {
+                let policy = policy.unwrap_or_else(|| "flag".to_string());
+                maintenance::MaintenanceJob::ExpireTemporal {
+                    policy: crate::temporal::ExpiryPolicy::from_str(&policy)
+                        .ok_or(WillowError::InvalidExpiryPolicy(policy))?,
+                }
+            }
+            "prune_history" => maintenance::MaintenanceJob::PruneHistory,
+            "detect_duplicates" => maintenance::MaintenanceJob::DetectDuplicates {
+                threshold: threshold.unwrap_or(0.8),
+            },
+            "gc_repo" => maintenance::MaintenanceJob::GcRepo,
+            "rebuild_indexes" => maintenance::MaintenanceJob::RebuildIndexes,
+            _ => return Err(napi::Error::from_reason(format!("Unknown maintenance job: {job}"))),
+        };
+        store.register_job(job, interval_hours).map_err(napi::Error::from)
+    }
 
-        Ok(node_to_js(&node))
+    #[napi]
+    pub fn unregister_job(&mut self, job: String) -> napi::Result<bool> {
+        let mut store = self.writable()?;
+        info!(job = %job, "unregister_job");
+        store.unregister_job(&job).map_err(napi::Error::from)
     }
 
+    /// Run the maintenance jobs that are due, committing the run as one
+    /// Maintenance commit. Call it periodically, e.g. hourly.
     #[napi]
-    pub fn delete_node(&mut self, node_id: String) -> napi::Result<()> {
-        info!(node_id = %node_id, "delete_node");
-        self.inner.delete_node(&node_id).map_err(napi::Error::from)
+    pub fn run_due_jobs(&mut self) -> napi::Result<JsMaintenanceReport> {
+        let mut store = self.writable()?;
+        info!("run_due_jobs");
+        let report = store.run_due_jobs(chrono::Utc::now()).map_err(napi::Error::from)?;
+        Ok(JsMaintenanceReport {
+            runs: report.runs.into_iter().map(|(job, run)| job_run_to_js(job, run)).collect(),
+            commit_hash: report.commit.map(|h| h.0),
+        })
     }
 
     #[napi]
-    pub fn add_link(&mut self, input: JsAddLinkInput) -> napi::Result<JsLink> {
-        info!(from = %input.from_node, to = %input.to_node, relation = %input.relation, "add_link");
-        let link = self
-            .inner
-            .add_link(
-                &input.from_node,
-                &input.to_node,
-                &input.relation,
-                input.bidirectional.unwrap_or(false),
-                input.confidence.as_deref(),
-            )
-            .map_err(napi::Error::from)?;
+    pub fn get_rules(&self) -> Vec<JsRule> {
+        self.store().rules().iter().map(rule_to_js).collect()
+    }
 
-        Ok(link_to_js(&link))
+    /// Replace the maintenance rules. They are stored in the graph, so they
+    /// are versioned and undoable, and apply to the nodes each later
+    /// operation touches.
+    #[napi]
+    pub fn set_rules(&mut self, rules: Vec<JsRule>) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        info!(rules = rules.len(), "set_rules");
+        let rules = rules.into_iter().map(js_rule_to_model).collect::<napi::Result<Vec<_>>>()?;
+        store.set_rules(rules).map_err(napi::Error::from)
     }
 
+    /// Apply the rules to every node, committing the result as maintenance
+    /// when VCS is enabled.
     #[napi]
-    pub fn update_link(&mut self, input: JsUpdateLinkInput) -> napi::Result<JsLink> {
-        info!(link_id = %input.link_id, "update_link");
-        let link = self
-            .inner
-            .update_link(
-                &input.link_id,
-                input.relation.as_deref(),
-                input.bidirectional,
-                input.confidence.as_deref(),
-            )
-            .map_err(napi::Error::from)?;
+    pub fn run_rules(&mut self, job_id: Option<String>) -> napi::Result<JsRulesReport> {
+        let mut store = self.writable()?;
+        info!("run_rules");
+        let report = store.run_rules(job_id).map_err(napi::Error::from)?;
+        Ok(JsRulesReport {
+            changed: report.changed.into_iter().map(|id| id.0.to_string()).collect(),
+            links_created: report.links_created as u32,
+            commit_hash: report.commit.map(|h| h.0),
+        })
+    }
 
-        Ok(link_to_js(&link))
+    // ---- Undo / redo ----
+
+    #[napi]
+    pub fn undo(&mut self) -> napi::Result<bool> {
+        let mut store = self.writable()?;
+        info!("undo");
+        store.undo().map_err(napi::Error::from)
     }
 
     #[napi]
-    pub fn delete_link(&mut self, link_id: String) -> napi::Result<JsLink> {
-        info!(link_id = %link_id, "delete_link");
-        let link = self
-            .inner
-            .delete_link(&link_id)
-            .map_err(napi::Error::from)?;
-        Ok(link_to_js(&link))
+    pub fn redo(&mut self) -> napi::Result<bool> {
+        let mut store = self.writable()?;
+        info!("redo");
+        store.redo().map_err(napi::Error::from)
+    }
+
+    #[napi]
+    pub fn can_undo(&self) -> bool {
+        self.store().can_undo()
+    }
+
+    #[napi]
+    pub fn can_redo(&self) -> bool {
+        self.store().can_redo()
+    }
+
+    // ---- Transactions ----
+
+    #[napi]
+    pub fn begin_transaction(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        debug!("begin_transaction");
+        store.begin_transaction().map_err(napi::Error::from)
+    }
+
+    #[napi]
+    pub fn commit_transaction(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        debug!("commit_transaction");
+        store.commit_transaction().map_err(napi::Error::from)
+    }
+
+    #[napi]
+    pub fn rollback_transaction(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
+        debug!("rollback_transaction");
+        store.rollback_transaction().map_err(napi::Error::from)
+    }
+
+    #[napi]
+    pub fn in_transaction(&self) -> bool {
+        self.store().in_transaction()
     }
 
     // ---- VCS methods ----
 
     #[napi]
     pub fn vcs_init(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
         info!("vcs_init");
-        self.inner.vcs_init().map_err(napi::Error::from)
+        store.vcs_init().map_err(napi::Error::from)
     }
 
     #[napi]
     pub fn has_pending_changes(&self) -> bool {
         debug!("has_pending_changes");
-        self.inner.has_pending_changes()
+        self.store().has_pending_changes()
     }
 
     #[napi]
     pub fn commit(&mut self, input: JsCommitInput) -> napi::Result<String> {
+        let mut store = self.writable()?;
         info!(message = %input.message, "commit");
-        let hash = self.inner.commit(js_input_to_commit_input(input)).map_err(napi::Error::from)?;
+        let hash = store.commit(js_input_to_commit_input(input)).map_err(napi::Error::from)?;
         Ok(hash.0)
     }
 
+    /// Commit the pending changes with a message generated from them, e.g.
+    /// "Added 3 nodes under Career; updated 'Favorite food'".
     #[napi]
-    pub fn commit_external_changes(&self, input: JsCommitInput) -> napi::Result<Option<String>> {
-        let hash = self.inner
+    pub fn commit_auto(&mut self, source: JsCommitSource) -> napi::Result<JsAutoCommit> {
+        let mut store = self.writable()?;
+        info!(source = %source.source, "commit_auto");
+        let (hash, message) = store.commit_auto(js_commit_source(source)).map_err(napi::Error::from)?;
+        Ok(JsAutoCommit { hash: hash.0, message })
+    }
+
+    #[napi]
+    pub fn commit_external_changes(&mut self, input: JsCommitInput) -> napi::Result<Option<String>> {
+        let mut store = self.writable()?;
+        let hash = store
             .commit_external_changes(js_input_to_commit_input(input))
             .map_err(napi::Error::from)?;
         Ok(hash.map(|h| h.0))
@@ -515,19 +3121,71 @@ impl JsGraphStore {
 
     #[napi]
     pub fn discard_changes(&mut self) -> napi::Result<()> {
+        let mut store = self.writable()?;
         debug!("discard_changes");
-        self.inner.discard_changes().map_err(napi::Error::from)
+        store.discard_changes().map_err(napi::Error::from)
     }
 
     #[napi]
     pub fn log(&self, limit: Option<u32>) -> napi::Result<Vec<JsCommitEntry>> {
+        self.require_open()?;
         debug!("log");
         let entries = repo_op!(self, |r: &vcs::repository::Repository| r.log(limit.map(|n| n as usize)))?;
         Ok(map_vec(&entries, commit_entry_to_js))
     }
 
+    /// An iterator over the whole log, for histories too long for `log`.
+    #[napi]
+    pub fn log_iterator(&self) -> napi::Result<JsLogIterator> {
+        self.require_open()?;
+        debug!("log_iterator");
+        let cursor = repo_op!(self, |r: &vcs::repository::Repository| r.log_cursor())?;
+        let graph_dir = self.store().path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let repo = vcs::repository::Repository::open(&graph_dir).map_err(napi::Error::from)?;
+        Ok(JsLogIterator { repo, cursor })
+    }
+
+    /// Search the last `maxCommits` (default 50) commits for content that no
+    /// longer exists at HEAD, such as deleted or since-edited nodes.
+    /// `onProgress` is called before each commit is searched; cancelling
+    /// `cancel` stops the search.
+    #[napi]
+    pub fn search_history(
+        &self,
+        query: String,
+        options: Option<JsSearchOptions>,
+        max_commits: Option<u32>,
+        on_progress: Option<ProgressListener>,
+        cancel: Option<&JsCancelToken>,
+    ) -> napi::Result<Vec<JsHistoryMatch>> {
+        self.require_open()?;
+        debug!(query = %query, "search_history");
+        let options = vcs::repository::HistorySearchOptions {
+            search: js_search_options_to_model(options)?,
+            max_commits: max_commits.map(|n| n as usize),
+        };
+        let mut progress = js_progress(on_progress.as_ref(), cancel);
+        let matches = repo_op!(self, |r: &vcs::repository::Repository| r.search_history(
+            &query,
+            &options,
+            &mut progress
+        ))?;
+        Ok(matches
+            .iter()
+            .map(|m| JsHistoryMatch {
+                node_id: m.node_id.0.to_string(),
+                content: m.content.clone(),
+                score: m.score,
+                matched_field: m.matched_field.clone(),
+                commit: commit_entry_to_js(&m.commit),
+                deleted: m.deleted,
+            })
+            .collect())
+    }
+
     #[napi]
     pub fn show_commit(&self, hash: String) -> napi::Result<JsCommitDetail> {
+        self.require_open()?;
         debug!(hash = %hash, "show_commit");
         let commit_hash = vcs::types::CommitHash(hash);
         let (data, diff) = repo_op!(self, |r: &vcs::repository::Repository| r.show_commit(&commit_hash))?;
@@ -539,6 +3197,7 @@ impl JsGraphStore {
 
     #[napi]
     pub fn diff(&self, from_hash: String, to_hash: String) -> napi::Result<JsChangeSummary> {
+        self.require_open()?;
         debug!(from = %from_hash, to = %to_hash, "diff");
         let diff = repo_op!(self, |r: &vcs::repository::Repository| r.diff(
             &vcs::types::CommitHash(from_hash),
@@ -547,8 +3206,81 @@ impl JsGraphStore {
         Ok(change_summary_to_js(&diff))
     }
 
+    /// `diff` between the heads of two branches.
+    #[napi]
+    pub fn diff_branches(&self, from_branch: String, to_branch: String) -> napi::Result<JsChangeSummary> {
+        self.require_open()?;
+        debug!(from = %from_branch, to = %to_branch, "diff_branches");
+        let diff = repo_op!(self, |r: &vcs::repository::Repository| r.diff_branches(&from_branch, &to_branch))?;
+        Ok(change_summary_to_js(&diff))
+    }
+
+    #[napi]
+    pub fn get_repo_config(&self) -> napi::Result<JsRepoConfig> {
+        self.require_open()?;
+        repo_op!(self, |r: &vcs::repository::Repository| Ok::<_, WillowError>(repo_config_to_js(r.config())))
+    }
+
+    /// Change some of the repository config, persisted under `repo/`.
+    /// Returns the config as updated.
+    #[napi]
+    pub fn set_repo_config(&mut self, update: JsRepoConfigUpdate) -> napi::Result<JsRepoConfig> {
+        let mut store = self.writable()?;
+        info!("set_repo_config");
+        let mut config = repo_op!(self, |r: &vcs::repository::Repository| Ok::<_, WillowError>(r.config().clone()))?;
+        if let Some(interval) = update.snapshot_interval {
+            config.snapshot_interval = interval;
+        }
+        if let Some(branch) = update.default_branch {
+            config.default_branch = branch;
+        }
+        if let Some(level) = update.snapshot_compression_level {
+            config.snapshot_compression_level = level;
+        }
+        if let Some(interval) = update.snapshot_keyframe_interval {
+            config.snapshot_keyframe_interval = interval;
+        }
+        if let Some(hooks) = update.webhooks {
+            config.webhooks = hooks.into_iter().map(js_webhook_to_model).collect::<napi::Result<_>>()?;
+        }
+        let mut store = store;
+        store.set_repo_config(config).map_err(napi::Error::from)?;
+        Ok(repo_config_to_js(store.get_repo().map_err(napi::Error::from)?.config()))
+    }
+
+    /// Keep commits, snapshots and deltas in `storage`, or only on disk
+    /// when null, copying every object there first. The local copies stay
+    /// as a cache. Returns the config as updated.
+    #[napi]
+    pub fn set_object_storage(&mut self, storage: Option<JsObjectStorage>) -> napi::Result<JsRepoConfig> {
+        let mut store = self.writable()?;
+        info!(bucket = ?storage.as_ref().map(|s| &s.bucket), "set_object_storage");
+        let mut config = repo_op!(self, |r: &vcs::repository::Repository| Ok::<_, WillowError>(r.config().clone()))?;
+        config.object_storage = storage.map(|s| vcs::s3::S3Config {
+            endpoint: s.endpoint,
+            bucket: s.bucket,
+            prefix: s.prefix.unwrap_or_default(),
+            region: s.region.unwrap_or_else(|| "us-east-1".to_string()),
+        });
+        let mut store = store;
+        store.set_repo_config(config).map_err(napi::Error::from)?;
+        Ok(repo_config_to_js(store.get_repo().map_err(napi::Error::from)?.config()))
+    }
+
+    /// Train a compression dictionary from the repository's snapshots
+    /// (at most `maxSizeKb`, default 110) and recompress them with it.
+    /// Returns the dictionary id, or null if there was too little to train on.
+    #[napi]
+    pub fn train_snapshot_dictionary(&mut self, max_size_kb: Option<u32>) -> napi::Result<Option<u32>> {
+        let mut store = self.writable()?;
+        info!("train_snapshot_dictionary");
+        let max_size = max_size_kb.unwrap_or(110) as usize * 1024;
+        store.train_snapshot_dictionary(max_size).map_err(napi::Error::from)
+    }
+
     #[napi]
     pub fn list_branches(&self) -> napi::Result<Vec<JsBranchInfo>> {
+        self.require_open()?;
         debug!("list_branches");
         let branches = repo_op!(self, |r: &vcs::repository::Repository| r.list_branches())?;
         Ok(branches
@@ -563,33 +3295,37 @@ impl JsGraphStore {
 
     #[napi]
     pub fn create_branch(&self, name: String) -> napi::Result<()> {
+        let mut store = self.writable()?;
         debug!(name = %name, "create_branch");
-        repo_op!(self, |r: &vcs::repository::Repository| r.create_branch(&name))
+        store.create_branch(&name).map_err(napi::Error::from)
     }
 
     #[napi]
     pub fn switch_branch(&mut self, name: String) -> napi::Result<()> {
+        let mut store = self.writable()?;
         info!(branch = %name, "switch_branch");
-        self.inner.switch_branch(&name).map_err(napi::Error::from)
+        store.switch_branch(&name).map_err(napi::Error::from)
     }
 
     #[napi]
     pub fn delete_branch(&self, name: String) -> napi::Result<()> {
+        let mut store = self.writable()?;
         debug!(name = %name, "delete_branch");
-        repo_op!(self, |r: &vcs::repository::Repository| r.delete_branch(&name))
+        store.delete_branch(&name).map_err(napi::Error::from)
     }
 
     #[napi]
     pub fn current_branch(&self) -> napi::Result<Option<String>> {
+        self.require_open()?;
         debug!("current_branch");
         repo_op!(self, |r: &vcs::repository::Repository| r.current_branch())
     }
 
     #[napi]
     pub fn merge_branch(&mut self, source: String) -> napi::Result<String> {
+        let mut store = self.writable()?;
         info!(source = %source, "merge_branch");
-        let hash = self
-            .inner
+        let hash = store
             .merge_branch(&source)
             .map_err(napi::Error::from)?;
         Ok(hash.0)
@@ -597,8 +3333,9 @@ impl JsGraphStore {
 
     #[napi]
     pub fn checkout_commit(&mut self, hash: String) -> napi::Result<()> {
+        let mut store = self.writable()?;
         info!(hash = %hash, "checkout_commit");
-        self.inner
+        store
             .checkout_commit(&vcs::types::CommitHash(hash))
             .map_err(napi::Error::from)
     }
@@ -610,23 +3347,22 @@ impl JsGraphStore {
 
     #[napi]
     pub fn head_hash(&self) -> napi::Result<Option<String>> {
+        self.require_open()?;
         debug!("head_hash");
         Ok(self.head_entry()?.map(|e| e.hash.0))
     }
 
     #[napi]
     pub fn has_local_changes(&self) -> napi::Result<bool> {
+        self.require_open()?;
         debug!("has_local_changes");
-        let head = match self.head_entry()? {
-            Some(e) => e.hash,
-            None => return Ok(false),
-        };
-        let committed = repo_op!(self, |r: &vcs::repository::Repository| r.reconstruct_at(&head))?;
-        Ok(diff_has_changes(&crate::vcs::diff::compute_graph_diff(&committed, &self.inner.graph)))
+        let diff = self.store().uncommitted_changes().map_err(napi::Error::from)?;
+        Ok(diff_has_changes(&diff))
     }
 
     #[napi]
     pub fn graph_at_commit(&self, hash: String) -> napi::Result<String> {
+        self.require_open()?;
         debug!(hash = %hash, "graph_at_commit");
         let graph = repo_op!(self, |r: &vcs::repository::Repository| r.reconstruct_at(&vcs::types::CommitHash(hash)))?;
         serde_json::to_string(&graph).map_err(|e| napi::Error::from_reason(e.to_string()))
@@ -634,9 +3370,9 @@ impl JsGraphStore {
 
     #[napi]
     pub fn restore_to_commit(&mut self, hash: String) -> napi::Result<String> {
+        let mut store = self.writable()?;
         info!(hash = %hash, "restore_to_commit");
-        let new_hash = self
-            .inner
+        let new_hash = store
             .restore_to_commit(&vcs::types::CommitHash(hash))
             .map_err(napi::Error::from)?;
         Ok(new_hash.0)
@@ -644,13 +3380,9 @@ impl JsGraphStore {
 
     #[napi]
     pub fn diff_disk_vs_head(&self) -> napi::Result<JsChangeSummary> {
+        self.require_open()?;
         debug!("diff_disk_vs_head");
-        let head = match self.head_entry()? {
-            Some(e) => e.hash,
-            None => return Ok(JsChangeSummary::default()),
-        };
-        let committed = repo_op!(self, |r: &vcs::repository::Repository| r.reconstruct_at(&head))?;
-        let disk = crate::storage::load_graph(&self.inner.path).map_err(napi::Error::from)?;
-        Ok(change_summary_to_js(&crate::vcs::diff::compute_graph_diff(&committed, &disk)))
+        let diff = self.store().uncommitted_changes_on_disk().map_err(napi::Error::from)?;
+        Ok(change_summary_to_js(&diff))
     }
 }
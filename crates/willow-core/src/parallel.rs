@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Maps at least this large are split across threads with the `parallel`
+/// feature; below it, scheduling costs more than it saves.
+#[cfg(feature = "parallel")]
+const MIN_PARALLEL_ENTRIES: usize = 10_000;
+
+#[cfg(all(test, feature = "parallel"))]
+thread_local! {
+    static FORCE_SERIAL: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Run `f` with `filter_map_entries` taking the serial path on this thread
+/// whatever the map's size, to check the parallel path against it.
+#[cfg(all(test, feature = "parallel"))]
+pub fn serially<R>(f: impl FnOnce() -> R) -> R {
+    FORCE_SERIAL.set(true);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    FORCE_SERIAL.set(false);
+    result.unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+#[cfg(feature = "parallel")]
+fn parallel_for(len: usize) -> bool {
+    #[cfg(test)]
+    if FORCE_SERIAL.get() {
+        return false;
+    }
+    len >= MIN_PARALLEL_ENTRIES
+}
+
+/// `f` applied to every entry of `map`, keeping the `Some` results in the
+/// map's iteration order. With the `parallel` feature, large maps are
+/// handed to rayon's global pool; its indexed `collect` keeps the entries'
+/// order, so the output is the same either way. A panic in `f` reaches the
+/// caller on both paths.
+pub fn filter_map_entries<'a, K, V, U>(
+    map: &'a HashMap<K, V>,
+    f: impl Fn(&'a K, &'a V) -> Option<U> + Sync + Send,
+) -> Vec<U>
+where
+    K: Eq + Hash + Sync,
+    V: Sync,
+    U: Send,
+{
+    #[cfg(feature = "parallel")]
+    if parallel_for(map.len()) {
+        let entries: Vec<(&'a K, &'a V)> = map.iter().collect();
+        return entries.into_par_iter().filter_map(|(k, v)| f(k, v)).collect();
+    }
+    map.iter().filter_map(|(k, v)| f(k, v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_map_entries_keeps_iteration_order() {
+        let map: HashMap<u32, u32> = (0..25_000).map(|i| (i, i * 2)).collect();
+        let expected: Vec<u32> = map.iter().filter(|(k, _)| *k % 3 == 0).map(|(_, v)| *v).collect();
+        let found = filter_map_entries(&map, |k, v| (k % 3 == 0).then_some(*v));
+        assert_eq!(found, expected);
+        #[cfg(feature = "parallel")]
+        assert_eq!(serially(|| filter_map_entries(&map, |k, v| (k % 3 == 0).then_some(*v))), expected);
+    }
+
+    #[test]
+    fn test_filter_map_entries_propagates_panics() {
+        let map: HashMap<u32, u32> = (0..25_000).map(|i| (i, i)).collect();
+        let result = std::panic::catch_unwind(|| {
+            filter_map_entries(&map, |k, _| {
+                assert_ne!(*k, 12_345, "bad entry");
+                Some(*k)
+            })
+        });
+        let message = result.unwrap_err();
+        assert!(format!("{:?}", message.downcast_ref::<String>()).contains("bad entry"));
+    }
+}
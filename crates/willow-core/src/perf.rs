@@ -0,0 +1,152 @@
+use crate::model::Graph;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use web_time::Instant;
+
+/// Upper bounds of the timing histogram's buckets. A last bucket counts
+/// the runs slower than all of them.
+pub const TIMING_BUCKETS: [Duration; 5] = [
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// How long one kind of operation has taken since the store was opened.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OperationTimings {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+    /// Runs within each bound of `TIMING_BUCKETS`, then the slower ones.
+    pub histogram: [u64; TIMING_BUCKETS.len() + 1],
+}
+
+impl OperationTimings {
+    fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        let bucket = TIMING_BUCKETS.iter().position(|bound| elapsed <= *bound).unwrap_or(TIMING_BUCKETS.len());
+        self.histogram[bucket] += 1;
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => self.total / n as u32,
+        }
+    }
+}
+
+/// Timings of a store's operations by name, shared with running timers.
+#[derive(Debug, Clone, Default)]
+pub struct PerfStats(Arc<Mutex<BTreeMap<&'static str, OperationTimings>>>);
+
+impl PerfStats {
+    /// Time `operation` until the returned timer is dropped. The timer does
+    /// not borrow the stats, so the store stays free to use meanwhile.
+    pub fn time(&self, operation: &'static str) -> OpTimer {
+        OpTimer {
+            stats: self.clone(),
+            operation,
+            started: Instant::now(),
+        }
+    }
+
+    fn record(&self, operation: &'static str, elapsed: Duration) {
+        let mut timings = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        timings.entry(operation).or_default().record(elapsed);
+    }
+
+    pub fn timings(&self) -> BTreeMap<&'static str, OperationTimings> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Records an operation's time, errors included, when dropped.
+pub struct OpTimer {
+    stats: PerfStats,
+    operation: &'static str,
+    started: Instant,
+}
+
+impl Drop for OpTimer {
+    fn drop(&mut self) {
+        self.stats.record(self.operation, self.started.elapsed());
+    }
+}
+
+/// Rough memory held by a graph: the bytes of its strings, which dominate
+/// for real graphs, plus the fixed size of each node and link.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryFootprint {
+    pub nodes: usize,
+    pub links: usize,
+    pub content_bytes: usize,
+    pub metadata_bytes: usize,
+    /// Superseded contents and their reasons kept in `previous_values`.
+    pub previous_values_bytes: usize,
+    pub estimated_total_bytes: usize,
+}
+
+impl MemoryFootprint {
+    pub fn of(graph: &Graph) -> Self {
+        let mut footprint = MemoryFootprint {
+            nodes: graph.nodes.len(),
+            links: graph.links.len(),
+            ..MemoryFootprint::default()
+        };
+        let mut id_bytes = 0;
+        for (id, node) in &graph.nodes {
+            id_bytes += id.0.len() + std::mem::size_of_val(node.children.as_slice());
+            footprint.content_bytes += node.content.len();
+            footprint.metadata_bytes += node.metadata.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>();
+            footprint.previous_values_bytes += node
+                .previous_values
+                .iter()
+                .map(|v| v.old_content.len() + v.reason.as_ref().map_or(0, String::len))
+                .sum::<usize>();
+        }
+        let link_bytes: usize = graph.links.iter().map(|(id, link)| id.0.len() + link.relation.len()).sum();
+        footprint.estimated_total_bytes = footprint.content_bytes
+            + footprint.metadata_bytes
+            + footprint.previous_values_bytes
+            + id_bytes
+            + link_bytes
+            + footprint.nodes * std::mem::size_of::<crate::model::Node>()
+            + footprint.links * std::mem::size_of::<crate::model::Link>();
+        footprint
+    }
+}
+
+/// What `GraphStore::perf_report` returns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerfReport {
+    pub operations: BTreeMap<&'static str, OperationTimings>,
+    pub memory: MemoryFootprint,
+    /// Changes made since the last commit, or since opening without VCS.
+    pub pending_changes: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings_fill_histogram_buckets() {
+        let mut timings = OperationTimings::default();
+        for elapsed in [Duration::from_micros(50), Duration::from_millis(5), Duration::from_millis(5), Duration::from_secs(3)] {
+            timings.record(elapsed);
+        }
+        assert_eq!(timings.count, 4);
+        assert_eq!(timings.histogram, [1, 0, 2, 0, 0, 1]);
+        assert_eq!(timings.max, Duration::from_secs(3));
+
+        let stats = PerfStats::default();
+        drop(stats.time("search_nodes"));
+        assert_eq!(stats.timings()["search_nodes"].count, 1);
+    }
+}
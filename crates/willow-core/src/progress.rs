@@ -0,0 +1,54 @@
+use crate::error::WillowError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How far a long operation has got.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressUpdate<'a> {
+    /// What the operation is doing, e.g. `search_history` or `scan_history`.
+    pub phase: &'a str,
+    pub done: usize,
+    /// Steps in this phase, when known up front.
+    pub total: Option<usize>,
+}
+
+/// Shared flag asking an operation to stop at its next step.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub type ProgressCallback<'a> = Box<dyn FnMut(&ProgressUpdate) + 'a>;
+
+/// Progress reporting and cancellation for a long operation; the default
+/// does neither. Operations call `step` between units of work, so a
+/// cancelled operation stops with `Cancelled` before its next unit.
+#[derive(Default)]
+pub struct Progress<'a> {
+    callback: Option<ProgressCallback<'a>>,
+    cancel: Option<CancelToken>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(callback: Option<ProgressCallback<'a>>, cancel: Option<CancelToken>) -> Self {
+        Progress { callback, cancel }
+    }
+
+    pub fn step(&mut self, phase: &str, done: usize, total: Option<usize>) -> Result<(), WillowError> {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(WillowError::Cancelled);
+        }
+        if let Some(callback) = self.callback.as_mut() {
+            callback(&ProgressUpdate { phase, done, total });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,10 @@
+//! What the `willow-py` bindings need from the engine. They build as a
+//! separate crate, which cannot reach the private modules; this is not an
+//! API for anything else.
+
+pub use crate::error::WillowError;
+pub use crate::model::{Graph, Link, Node, Sensitivity};
+pub use crate::query::{LinkFilter, NodeFilter, NodeSort, Page};
+pub use crate::search::{SearchOptions, SearchPage, SearchResult};
+pub use crate::storage::StorageOptions;
+pub use crate::store::{GraphStore, OpenOptions, StoreHandle};
@@ -0,0 +1,197 @@
+use crate::model::{ConfidenceLevel, Link, Node, NodeId, NodeType};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Metadata key holding a node's comma-separated tags.
+pub const TAGS_KEY: &str = "tags";
+
+/// Predicates for selecting nodes. Unset fields match everything; every set
+/// field must match.
+#[derive(Debug, Clone, Default)]
+pub struct NodeFilter {
+    pub node_types: Option<Vec<NodeType>>,
+    /// Each tag must be present in the node's `tags` metadata.
+    pub tags: Vec<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+    /// Exact metadata values.
+    pub metadata: HashMap<String, String>,
+}
+
+impl NodeFilter {
+    /// True when no predicate is set, so every node matches.
+    pub fn is_empty(&self) -> bool {
+        self.node_types.is_none()
+            && self.tags.is_empty()
+            && self.created_after.is_none()
+            && self.created_before.is_none()
+            && self.updated_after.is_none()
+            && self.updated_before.is_none()
+            && self.metadata.is_empty()
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        if self.node_types.as_ref().is_some_and(|types| !types.contains(&node.node_type)) {
+            return false;
+        }
+        if self.created_after.is_some_and(|d| node.created_at < d)
+            || self.created_before.is_some_and(|d| node.created_at >= d)
+            || self.updated_after.is_some_and(|d| node.updated_at < d)
+            || self.updated_before.is_some_and(|d| node.updated_at >= d)
+        {
+            return false;
+        }
+        if !self.metadata.iter().all(|(k, v)| node.metadata.get(k) == Some(v)) {
+            return false;
+        }
+        self.tags.iter().all(|tag| node_tags(node).any(|t| t == tag))
+    }
+}
+
+/// Field that `list_nodes` orders by. Ties are broken by node id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortField {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Content,
+}
+
+impl SortField {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<SortField> {
+        match s {
+            "created_at" => Some(SortField::CreatedAt),
+            "updated_at" => Some(SortField::UpdatedAt),
+            "content" => Some(SortField::Content),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeSort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl NodeSort {
+    pub fn compare(&self, a: &Node, b: &Node) -> Ordering {
+        let ord = match self.field {
+            SortField::CreatedAt => a.created_at.cmp(&b.created_at),
+            SortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            SortField::Content => a.content.cmp(&b.content),
+        }
+        .then_with(|| a.id.0.cmp(&b.id.0));
+        if self.descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+/// A window into a sorted result. No limit returns everything after `offset`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+pub struct NodePage {
+    pub nodes: Vec<Node>,
+    /// Matching nodes before pagination.
+    pub total: usize,
+}
+
+/// Predicates for selecting links, like `NodeFilter`.
+#[derive(Debug, Clone, Default)]
+pub struct LinkFilter {
+    pub from_node: Option<NodeId>,
+    pub to_node: Option<NodeId>,
+    pub relation: Option<String>,
+    /// Links without a confidence never meet a minimum.
+    pub min_confidence: Option<ConfidenceLevel>,
+}
+
+impl LinkFilter {
+    pub fn matches(&self, link: &Link) -> bool {
+        self.from_node.as_ref().is_none_or(|id| *id == link.from_node)
+            && self.to_node.as_ref().is_none_or(|id| *id == link.to_node)
+            && self.relation.as_ref().is_none_or(|r| *r == link.relation)
+            && self.min_confidence.as_ref().is_none_or(|min| link.confidence.as_ref().is_some_and(|c| c >= min))
+    }
+}
+
+pub struct LinkPage {
+    pub links: Vec<Link>,
+    /// Matching links before pagination.
+    pub total: usize,
+}
+
+/// The node's tags, trimmed, from its comma-separated `tags` metadata.
+pub fn node_tags(node: &Node) -> impl Iterator<Item = &str> {
+    node.metadata
+        .get(TAGS_KEY)
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ContentFormat, NodeId, Sensitivity};
+    use chrono::TimeZone;
+
+    fn node(node_type: NodeType, tags: &str) -> Node {
+        let created = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        Node {
+            id: NodeId("n".into()),
+            node_type,
+            content: "n".to_string(),
+            parent_id: None,
+            children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
+            metadata: HashMap::from([(TAGS_KEY.to_string(), tags.to_string())]),
+            previous_values: Vec::new(),
+            temporal: None,
+            created_at: created,
+            updated_at: created,
+            created_by: None,
+            updated_by: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let event = node(NodeType::Event, "work, travel");
+        assert!(NodeFilter::default().matches(&event));
+
+        let filter = NodeFilter {
+            node_types: Some(vec![NodeType::Event]),
+            tags: vec!["travel".to_string()],
+            created_before: Some(Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()),
+            ..NodeFilter::default()
+        };
+        assert!(filter.matches(&event));
+        assert!(!filter.matches(&node(NodeType::Detail, "travel")));
+        assert!(!filter.matches(&node(NodeType::Event, "work")));
+
+        let later = NodeFilter {
+            created_after: Some(Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()),
+            ..NodeFilter::default()
+        };
+        assert!(!later.matches(&event));
+    }
+}
@@ -0,0 +1,340 @@
+/// Window for a bare `NEAR`, in words.
+const DEFAULT_NEAR_DISTANCE: usize = 5;
+
+/// A parsed search query. Words and phrases are lowercased unless parsed
+/// case-sensitively; adjacent terms are ANDed. `NOT` and `NEAR` bind
+/// tightest, then `AND`, then `OR`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryExpr {
+    Term(String),
+    /// A quoted phrase, matched as whole words in order.
+    Phrase(String),
+    /// `a NEAR/3 b`: every term or phrase within `distance` words of the
+    /// others. A chain `a NEAR/2 b NEAR/4 c` keeps the widest distance.
+    Near { terms: Vec<String>, distance: usize },
+    Field(FieldFilter),
+    And(Vec<QueryExpr>),
+    Or(Vec<QueryExpr>),
+    Not(Box<QueryExpr>),
+}
+
+/// A `field:value` filter. Values are lowercased and compared exactly;
+/// a quoted value may contain spaces, e.g. `metadata.place:"new york"`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldFilter {
+    /// `type:event`
+    Type(String),
+    /// `metadata.source:conversation-42`
+    Metadata { key: String, value: String },
+    /// `tag:health`, against the comma-separated `tags` metadata.
+    Tag(String),
+    /// `relation:caused_by`, on a link in either direction.
+    Relation(String),
+}
+
+impl FieldFilter {
+    fn parse(word: &str, value: &str) -> Option<FieldFilter> {
+        let (field, _) = word.split_once(':')?;
+        let value = value.to_lowercase();
+        if value.is_empty() {
+            return None;
+        }
+        match field.to_lowercase().as_str() {
+            "type" => Some(FieldFilter::Type(value)),
+            "tag" => Some(FieldFilter::Tag(value)),
+            "relation" => Some(FieldFilter::Relation(value)),
+            _ => field.strip_prefix("metadata.").filter(|k| !k.is_empty()).map(|key| FieldFilter::Metadata {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
+}
+
+impl QueryExpr {
+    /// True for a bag of words with no operators, phrases or groups, which
+    /// search scores the way it always has.
+    pub fn is_plain(&self) -> bool {
+        match self {
+            QueryExpr::Term(_) => true,
+            QueryExpr::And(items) => items.iter().all(|e| matches!(e, QueryExpr::Term(_))),
+            _ => false,
+        }
+    }
+
+    /// Every term and phrase in the expression, negated ones included.
+    pub fn text_atoms(&self) -> Vec<&str> {
+        match self {
+            QueryExpr::Term(s) | QueryExpr::Phrase(s) => vec![s.as_str()],
+            QueryExpr::Near { terms, .. } => terms.iter().map(String::as_str).collect(),
+            QueryExpr::Field(_) => Vec::new(),
+            QueryExpr::And(items) | QueryExpr::Or(items) => items.iter().flat_map(QueryExpr::text_atoms).collect(),
+            QueryExpr::Not(inner) => inner.text_atoms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Field(FieldFilter),
+    And,
+    Or,
+    Not,
+    Near(usize),
+    Open,
+    Close,
+}
+
+fn tokenize(query: &str, case_sensitive: bool) -> Vec<Token> {
+    let case = |s: String| if case_sensitive { s } else { s.to_lowercase() };
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                // An unterminated quote runs to the end of the query.
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                let phrase = case(phrase.split_whitespace().collect::<Vec<_>>().join(" "));
+                if !phrase.is_empty() {
+                    tokens.push(Token::Phrase(phrase));
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '"' | '(' | ')') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                if word.ends_with(':') && chars.peek() == Some(&'"') {
+                    chars.next();
+                    let value: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                    match FieldFilter::parse(&word, value.trim()) {
+                        Some(filter) => tokens.push(Token::Field(filter)),
+                        None => {
+                            tokens.push(Token::Word(case(word)));
+                            tokens.extend(tokenize(&format!("\"{value}\""), case_sensitive));
+                        }
+                    }
+                    continue;
+                }
+                let value = word.split_once(':').map_or("", |(_, v)| v);
+                if let Some(filter) = FieldFilter::parse(&word, value) {
+                    tokens.push(Token::Field(filter));
+                    continue;
+                }
+                let near = match word.strip_prefix("NEAR") {
+                    Some("") => Some(DEFAULT_NEAR_DISTANCE),
+                    Some(n) => n.strip_prefix('/').and_then(|n| n.parse::<usize>().ok()).map(|n| n.max(1)),
+                    None => None,
+                };
+                if let Some(distance) = near {
+                    tokens.push(Token::Near(distance));
+                    continue;
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(case(word)),
+                });
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Option<QueryExpr> {
+        let mut items = Vec::new();
+        loop {
+            items.extend(self.and());
+            match self.peek() {
+                Some(Token::Or) => self.pos += 1,
+                _ => break,
+            }
+        }
+        combine(items, QueryExpr::Or)
+    }
+
+    fn and(&mut self) -> Option<QueryExpr> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => self.pos += 1,
+                Some(&Token::Near(distance)) => {
+                    self.pos += 1;
+                    // A dangling NEAR is dropped like any other operator.
+                    let Some(right) = self.unary() else { continue };
+                    let item = match items.pop() {
+                        Some(left) => near(left, right, distance),
+                        None => right,
+                    };
+                    items.push(item);
+                }
+                _ => items.extend(self.unary()),
+            }
+        }
+        combine(items, QueryExpr::And)
+    }
+
+    fn unary(&mut self) -> Option<QueryExpr> {
+        match self.next()? {
+            Token::Not => self.unary().map(|e| QueryExpr::Not(Box::new(e))),
+            Token::Open => {
+                let inner = self.or();
+                if self.peek() == Some(&Token::Close) {
+                    self.pos += 1;
+                }
+                inner
+            }
+            Token::Word(w) => Some(QueryExpr::Term(w)),
+            Token::Phrase(p) => Some(QueryExpr::Phrase(p)),
+            Token::Field(f) => Some(QueryExpr::Field(f)),
+            Token::And | Token::Or | Token::Near(_) | Token::Close => None,
+        }
+    }
+}
+
+/// `left NEAR right`, extending a chain. Anything other than terms and
+/// phrases is simply ANDed.
+fn near(left: QueryExpr, right: QueryExpr, distance: usize) -> QueryExpr {
+    let text = |e: &QueryExpr| match e {
+        QueryExpr::Term(s) | QueryExpr::Phrase(s) => Some(s.clone()),
+        _ => None,
+    };
+    match (left, text(&right)) {
+        (QueryExpr::Near { mut terms, distance: d }, Some(r)) => {
+            terms.push(r);
+            QueryExpr::Near {
+                terms,
+                distance: d.max(distance),
+            }
+        }
+        (left, Some(r)) if text(&left).is_some() => QueryExpr::Near {
+            terms: vec![text(&left).unwrap_or_default(), r],
+            distance,
+        },
+        (left, _) => QueryExpr::And(vec![left, right]),
+    }
+}
+
+fn combine(mut items: Vec<QueryExpr>, op: fn(Vec<QueryExpr>) -> QueryExpr) -> Option<QueryExpr> {
+    match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(op(items)),
+    }
+}
+
+/// Parse a query string. Malformed input is read leniently: dangling
+/// operators are dropped and unclosed quotes or groups end with the query.
+/// `None` when nothing searchable remains. Field filter values are always
+/// lowercased.
+pub fn parse(query: &str, case_sensitive: bool) -> Option<QueryExpr> {
+    let mut parser = Parser {
+        tokens: tokenize(query, case_sensitive),
+        pos: 0,
+    };
+    let mut items = Vec::new();
+    while parser.peek().is_some() {
+        items.extend(parser.or());
+        // A stray `)` ends `or` early; skip it and keep going.
+        if parser.peek() == Some(&Token::Close) {
+            parser.pos += 1;
+        }
+    }
+    combine(items, QueryExpr::And)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(s: &str) -> QueryExpr {
+        QueryExpr::Term(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_operators_and_phrases() {
+        assert_eq!(
+            parse("piano NOT guitar", false),
+            Some(QueryExpr::And(vec![term("piano"), QueryExpr::Not(Box::new(term("guitar")))]))
+        );
+        assert_eq!(
+            parse("\"Machine  Learning\" OR ml", false),
+            Some(QueryExpr::Or(vec![QueryExpr::Phrase("machine learning".to_string()), term("ml")]))
+        );
+        assert_eq!(
+            parse("(tea OR coffee) AND NOT decaf", false),
+            Some(QueryExpr::And(vec![
+                QueryExpr::Or(vec![term("tea"), term("coffee")]),
+                QueryExpr::Not(Box::new(term("decaf"))),
+            ]))
+        );
+        assert!(parse("likes pizza and pasta", false).unwrap().is_plain());
+        assert!(parse("10:30 meeting", false).unwrap().is_plain());
+        assert_eq!(parse("OR ) \"\"", false), None);
+    }
+
+    #[test]
+    fn test_parse_near() {
+        assert_eq!(
+            parse("coffee NEAR/3 \"bad sleep\" NEAR cat", false),
+            Some(QueryExpr::Near {
+                terms: vec!["coffee".to_string(), "bad sleep".to_string(), "cat".to_string()],
+                distance: 5,
+            })
+        );
+        assert_eq!(
+            parse("tea NEAR/x", false),
+            Some(QueryExpr::And(vec![term("tea"), term("near/x")]))
+        );
+        assert_eq!(parse("NEAR/2 tea", false), Some(term("tea")));
+    }
+
+    #[test]
+    fn test_parse_field_filters() {
+        assert_eq!(
+            parse("type:Event metadata.source:conversation-42 tag:\"Mental Health\" relation:caused_by sleep", false),
+            Some(QueryExpr::And(vec![
+                QueryExpr::Field(FieldFilter::Type("event".to_string())),
+                QueryExpr::Field(FieldFilter::Metadata {
+                    key: "source".to_string(),
+                    value: "conversation-42".to_string(),
+                }),
+                QueryExpr::Field(FieldFilter::Tag("mental health".to_string())),
+                QueryExpr::Field(FieldFilter::Relation("caused_by".to_string())),
+                term("sleep"),
+            ]))
+        );
+    }
+}
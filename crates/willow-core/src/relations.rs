@@ -0,0 +1,116 @@
+use crate::error::WillowError;
+use crate::model::Graph;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationSpec {
+    #[serde(default = "default_true")]
+    pub allow_bidirectional: bool,
+    /// Name of the relation read in the opposite direction, e.g. `part_of` ⇔ `contains`.
+    #[serde(default)]
+    pub inverse: Option<String>,
+}
+
+/// Optional registry of allowed link relations. An empty registry allows any relation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RelationRegistry {
+    pub relations: BTreeMap<String, RelationSpec>,
+}
+
+/// A relation name with its registry entry (if any) and how many links use it.
+#[derive(Debug, Clone)]
+pub struct RelationUsage {
+    pub relation: String,
+    pub count: usize,
+    pub registered: bool,
+    pub allow_bidirectional: bool,
+    pub inverse: Option<String>,
+}
+
+impl RelationRegistry {
+    /// Look up a relation by name, also accepting declared inverse names.
+    fn lookup(&self, relation: &str) -> Option<&RelationSpec> {
+        self.relations.get(relation).or_else(|| {
+            self.relations
+                .values()
+                .find(|spec| spec.inverse.as_deref() == Some(relation))
+        })
+    }
+
+    pub fn validate(&self, relation: &str, bidirectional: bool) -> Result<(), WillowError> {
+        if self.relations.is_empty() {
+            return Ok(());
+        }
+        let spec = self.lookup(relation).ok_or_else(|| {
+            WillowError::InvalidRelation(format!("'{relation}' is not a registered relation"))
+        })?;
+        if bidirectional && !spec.allow_bidirectional {
+            return Err(WillowError::InvalidRelation(format!(
+                "'{relation}' cannot be bidirectional"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Every relation that is registered or in use, sorted by name, with usage counts.
+    pub fn usage(&self, graph: &Graph) -> Vec<RelationUsage> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for name in self.relations.keys() {
+            counts.insert(name, 0);
+        }
+        for link in graph.links.values() {
+            *counts.entry(link.relation.as_str()).or_insert(0) += 1;
+        }
+
+        counts
+            .into_iter()
+            .map(|(relation, count)| {
+                let spec = self.lookup(relation);
+                RelationUsage {
+                    relation: relation.to_string(),
+                    count,
+                    registered: spec.is_some(),
+                    allow_bidirectional: spec.is_none_or(|s| s.allow_bidirectional),
+                    inverse: self.relations.get(relation).and_then(|s| s.inverse.clone()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> RelationRegistry {
+        let mut relations = BTreeMap::new();
+        relations.insert(
+            "part_of".to_string(),
+            RelationSpec { allow_bidirectional: false, inverse: Some("contains".to_string()) },
+        );
+        relations.insert(
+            "related_to".to_string(),
+            RelationSpec { allow_bidirectional: true, inverse: None },
+        );
+        RelationRegistry { relations }
+    }
+
+    #[test]
+    fn test_empty_registry_allows_anything() {
+        assert!(RelationRegistry::default().validate("whatever", true).is_ok());
+    }
+
+    #[test]
+    fn test_registry_validation() {
+        let reg = registry();
+        assert!(reg.validate("related_to", true).is_ok());
+        assert!(reg.validate("contains", false).is_ok());
+        assert!(reg.validate("related-to", false).is_err());
+        assert!(reg.validate("part_of", true).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::model::SupersededValue;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+const SUMMARY_PREFIX: &str = "summary of ";
+
+/// How much of a node's `previous_values` history is kept. The default keeps
+/// everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Keep at most this many of the most recent values.
+    pub max_values: Option<usize>,
+    /// Drop values superseded more than this many days ago.
+    pub max_age_days: Option<u32>,
+    /// Fold pruned values into a single summary entry at the front of the
+    /// history instead of discarding them. The summary keeps the oldest
+    /// content and a running count.
+    pub summarize: bool,
+}
+
+impl RetentionPolicy {
+    pub fn is_unbounded(&self) -> bool {
+        self.max_values.is_none() && self.max_age_days.is_none()
+    }
+
+    /// Prune `values` in place and return how many entries were removed.
+    /// An existing summary entry is never pruned or counted.
+    pub fn apply(&self, values: &mut Vec<SupersededValue>, now: DateTime<Utc>) -> usize {
+        if self.is_unbounded() {
+            return 0;
+        }
+        let summary = values.first().and_then(summary_count).map(|count| (values.remove(0), count));
+
+        let mut keep_from = 0;
+        if let Some(days) = self.max_age_days {
+            let cutoff = now - Duration::days(days as i64);
+            keep_from = values.iter().position(|v| v.superseded_at >= cutoff).unwrap_or(values.len());
+        }
+        if let Some(max) = self.max_values {
+            keep_from = keep_from.max(values.len().saturating_sub(max));
+        }
+        let pruned: Vec<SupersededValue> = values.drain(..keep_from).collect();
+
+        if self.summarize && !pruned.is_empty() {
+            let previous = summary.as_ref().map_or(0, |(_, count)| *count);
+            let oldest = summary.as_ref().map(|(v, _)| v).or(pruned.first()).unwrap();
+            let newest = pruned.last().unwrap().superseded_at;
+            values.insert(
+                0,
+                SupersededValue {
+                    old_content: oldest.old_content.clone(),
+                    superseded_at: newest,
+                    reason: Some(format!("{SUMMARY_PREFIX}{} pruned values", previous + pruned.len())),
+                },
+            );
+        } else if let Some((value, _)) = summary {
+            values.insert(0, value);
+        }
+        pruned.len()
+    }
+}
+
+/// The number of values folded into `value`, if it is a retention summary.
+fn summary_count(value: &SupersededValue) -> Option<usize> {
+    value
+        .reason
+        .as_deref()?
+        .strip_prefix(SUMMARY_PREFIX)?
+        .split(' ')
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(now: DateTime<Utc>, days_ago: &[i64]) -> Vec<SupersededValue> {
+        days_ago
+            .iter()
+            .map(|d| SupersededValue {
+                old_content: format!("{d} days ago"),
+                superseded_at: now - Duration::days(*d),
+                reason: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keep_last_n_and_max_age() {
+        let now = Utc::now();
+        let mut history = values(now, &[40, 20, 10, 5, 1]);
+        let policy = RetentionPolicy {
+            max_values: Some(3),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(policy.apply(&mut history, now), 2);
+        assert_eq!(history[0].old_content, "10 days ago");
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(7),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(policy.apply(&mut history, now), 1);
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn test_summary_accumulates() {
+        let now = Utc::now();
+        let policy = RetentionPolicy {
+            max_values: Some(1),
+            summarize: true,
+            ..RetentionPolicy::default()
+        };
+        let mut history = values(now, &[30, 20, 10]);
+        assert_eq!(policy.apply(&mut history, now), 2);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].old_content, "30 days ago");
+        assert_eq!(history[0].reason.as_deref(), Some("summary of 2 pruned values"));
+
+        history.extend(values(now, &[2]));
+        assert_eq!(policy.apply(&mut history, now), 1);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].old_content, "30 days ago");
+        assert_eq!(history[0].reason.as_deref(), Some("summary of 3 pruned values"));
+        assert_eq!(history[1].old_content, "2 days ago");
+    }
+}
@@ -0,0 +1,290 @@
+use crate::error::WillowError;
+use crate::model::{Graph, Link, LinkId, Node, NodeId, NodeType, TemporalMetadata};
+use crate::query::{node_tags, TAGS_KEY};
+use crate::vcs::types::{Change, CommitHash};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use uuid::Uuid;
+
+/// A maintenance rule kept in the graph: nodes it matches get its actions.
+/// Rules run on the nodes each operation touches and, over the whole
+/// graph, on `GraphStore::run_rules`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub id: String,
+    #[serde(rename = "match")]
+    pub matcher: RuleMatch,
+    pub actions: Vec<RuleAction>,
+}
+
+/// What a node must be like for a rule to apply. Every condition set must
+/// hold; a match with none set applies to every node but the roots.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleMatch {
+    pub node_type: Option<NodeType>,
+    /// Case-insensitive substring of the content.
+    pub content_contains: Option<String>,
+    /// Metadata entries the node must have, with these values.
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// What a rule does to a node it matches. Actions that are already in
+/// effect do nothing, so rules can run any number of times.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RuleAction {
+    AddTag { tag: String },
+    /// Link the node to `to` with `relation`.
+    CreateLink { to: NodeId, relation: String },
+    /// Make `parent` the node's primary parent.
+    MoveUnder { parent: NodeId },
+    /// Give a node without validity dates one ending `days` after the rule
+    /// ran on it.
+    SetValidity {
+        days: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+    },
+}
+
+impl RuleMatch {
+    fn matches(&self, node: &Node) -> bool {
+        self.node_type.as_ref().is_none_or(|t| *t == node.node_type)
+            && self
+                .content_contains
+                .as_ref()
+                .is_none_or(|text| node.content.to_lowercase().contains(&text.to_lowercase()))
+            && self.metadata.iter().all(|(k, v)| node.metadata.get(k) == Some(v))
+    }
+}
+
+/// Check `rules` can be stored in `graph`: ids are present and unique, and
+/// the nodes actions refer to exist.
+pub fn validate(rules: &[Rule], graph: &Graph) -> Result<(), WillowError> {
+    let mut ids = HashSet::new();
+    for rule in rules {
+        if rule.id.is_empty() || !ids.insert(&rule.id) {
+            return Err(WillowError::InvalidRule(format!("missing or duplicate id '{}'", rule.id)));
+        }
+        if rule.actions.is_empty() {
+            return Err(WillowError::InvalidRule(format!("'{}' has no actions", rule.id)));
+        }
+        for action in &rule.actions {
+            let target = match action {
+                RuleAction::CreateLink { to, .. } => to,
+                RuleAction::MoveUnder { parent } => parent,
+                RuleAction::AddTag { .. } | RuleAction::SetValidity { .. } => continue,
+            };
+            if !graph.nodes.contains_key(target) {
+                return Err(WillowError::NodeNotFound(target.0.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The changes `graph.rules` make to the nodes `node_ids`, in id order.
+/// Every rule sees a node as it was before any of them ran; when several
+/// move it or set its validity, the first one wins.
+pub fn evaluate<'a>(
+    graph: &Graph,
+    node_ids: impl IntoIterator<Item = &'a NodeId>,
+    now: DateTime<Utc>,
+) -> Vec<Change> {
+    if graph.rules.is_empty() {
+        return Vec::new();
+    }
+    let node_ids: BTreeSet<&NodeId> = node_ids.into_iter().collect();
+    let mut linked: HashSet<(&NodeId, &NodeId, &str)> = graph
+        .links
+        .values()
+        .map(|l| (&l.from_node, &l.to_node, l.relation.as_str()))
+        .collect();
+    let mut changes = Vec::new();
+    for id in node_ids {
+        let Some(node) = graph.nodes.get(id).filter(|n| !graph.is_root(&n.id)) else { continue };
+        let id = &node.id;
+        let mut tags: Vec<&str> = node_tags(node).collect();
+        let mut parent = None;
+        let mut temporal = None;
+        for rule in graph.rules.iter().filter(|r| r.matcher.matches(node)) {
+            for action in &rule.actions {
+                match action {
+                    RuleAction::AddTag { tag } => {
+                        if !tags.contains(&tag.as_str()) {
+                            tags.push(tag.as_str());
+                        }
+                    }
+                    RuleAction::CreateLink { to, relation } => {
+                        if to == id || !graph.nodes.contains_key(to) || !linked.insert((id, to, relation.as_str())) {
+                            continue;
+                        }
+                        let link = Link {
+                            id: LinkId(Uuid::new_v4().to_string().into()),
+                            from_node: id.clone(),
+                            to_node: to.clone(),
+                            relation: relation.clone(),
+                            bidirectional: false,
+                            confidence: None,
+                            created_at: now,
+                        };
+                        changes.push(Change::AddLink {
+                            link_id: link.id.clone(),
+                            link,
+                            actor: None,
+                        });
+                    }
+                    RuleAction::MoveUnder { parent: to } => {
+                        if parent.is_none() && node.parent_id.as_ref() != Some(to) && can_move_under(graph, id, to) {
+                            parent = Some(to);
+                        }
+                    }
+                    RuleAction::SetValidity { days, label } => {
+                        if temporal.is_none() && node.temporal.is_none() {
+                            temporal = Some(TemporalMetadata {
+                                valid_from: None,
+                                valid_until: Some(now + Duration::days(i64::from(*days))),
+                                label: label.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if tags.len() > node_tags(node).count() {
+            let mut metadata = node.metadata.clone();
+            metadata.insert(TAGS_KEY.to_string(), tags.join(", "));
+            changes.push(Change::UpdateNode {
+                node_id: id.clone(),
+                old_content: None,
+                new_content: None,
+                old_metadata: Some(node.metadata.clone()),
+                new_metadata: Some(metadata),
+                actor: None,
+            });
+        }
+        if let Some(to) = parent {
+            changes.push(Change::ReparentNode {
+                node_id: id.clone(),
+                old_parent: node.parent_id.clone(),
+                new_parent: Some(to.clone()),
+                old_index: None,
+                new_index: None,
+                actor: None,
+            });
+        }
+        if temporal.is_some() {
+            changes.push(Change::SetTemporal {
+                node_id: id.clone(),
+                old_temporal: None,
+                new_temporal: temporal,
+                actor: None,
+            });
+        }
+    }
+    changes
+}
+
+/// Whether `parent` exists and is not `id` or one of its descendants.
+fn can_move_under(graph: &Graph, id: &NodeId, parent: &NodeId) -> bool {
+    let mut stack = vec![parent];
+    let mut seen = HashSet::new();
+    while let Some(current) = stack.pop() {
+        if current == id {
+            return false;
+        }
+        let Some(node) = graph.nodes.get(current) else { return false };
+        if seen.insert(current) {
+            stack.extend(node.parents());
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RulesReport {
+    /// Nodes the rules changed or linked from, in id order.
+    pub changed: Vec<NodeId>,
+    pub links_created: usize,
+    pub commit: Option<CommitHash>,
+}
+
+impl RulesReport {
+    /// The report on `changes` from `evaluate`, before any commit.
+    pub fn new(changes: &[Change]) -> Self {
+        let mut changed = BTreeSet::new();
+        let mut links_created = 0;
+        for change in changes {
+            match change {
+                Change::AddLink { link, .. } => {
+                    changed.insert(link.from_node.clone());
+                    links_created += 1;
+                }
+                Change::UpdateNode { node_id, .. }
+                | Change::ReparentNode { node_id, .. }
+                | Change::SetTemporal { node_id, .. } => {
+                    changed.insert(node_id.clone());
+                }
+                _ => {}
+            }
+        }
+        RulesReport {
+            changed: changed.into_iter().collect(),
+            links_created,
+            commit: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::create_default_graph;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn add_node(graph: &mut Graph, id: &str, node_type: NodeType, content: &str) -> NodeId {
+        let mut node = Node::clone(&graph.nodes[&graph.root_id]);
+        node.id = NodeId(id.into());
+        node.node_type = node_type;
+        node.content = content.to_string();
+        node.parent_id = Some(graph.root_id.clone());
+        node.children = Vec::new();
+        node.metadata = HashMap::new();
+        Arc::make_mut(graph.nodes.get_mut(&graph.root_id).unwrap()).children.push(node.id.clone());
+        graph.nodes.insert(node.id.clone(), Arc::new(node));
+        NodeId(id.into())
+    }
+
+    #[test]
+    fn test_evaluate_applies_matching_rules_once() {
+        let mut graph = create_default_graph();
+        let work = add_node(&mut graph, "work", NodeType::Category, "Work");
+        let meeting = add_node(&mut graph, "meeting", NodeType::Event, "Standup meeting at 9");
+        add_node(&mut graph, "garden", NodeType::Event, "Water the tomatoes");
+        graph.rules = vec![Rule {
+            id: "meetings".to_string(),
+            matcher: RuleMatch {
+                node_type: Some(NodeType::Event),
+                content_contains: Some("MEETING".to_string()),
+                ..RuleMatch::default()
+            },
+            actions: vec![
+                RuleAction::AddTag { tag: "work".to_string() },
+                RuleAction::MoveUnder { parent: work.clone() },
+                RuleAction::CreateLink { to: work.clone(), relation: "part_of".to_string() },
+            ],
+        }];
+        validate(&graph.rules, &graph).unwrap();
+
+        let changes = evaluate(&graph, graph.nodes.keys(), Utc::now());
+        let kinds: Vec<&str> = changes.iter().map(Change::kind).collect();
+        assert_eq!(kinds, ["add_link", "update_node", "reparent_node"]);
+
+        crate::vcs::types::apply_delta(&mut graph, &crate::vcs::types::Delta::new(changes));
+        assert_eq!(graph.nodes[&meeting].metadata[TAGS_KEY], "work");
+        assert!(evaluate(&graph, graph.nodes.keys(), Utc::now()).is_empty());
+    }
+}
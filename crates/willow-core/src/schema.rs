@@ -0,0 +1,168 @@
+use crate::error::WillowError;
+use crate::model::NodeType;
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    String,
+    Number,
+    Boolean,
+    Date,
+}
+
+impl ValueType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Number => "number",
+            ValueType::Boolean => "boolean",
+            ValueType::Date => "date",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<ValueType> {
+        match s {
+            "string" => Some(ValueType::String),
+            "number" => Some(ValueType::Number),
+            "boolean" => Some(ValueType::Boolean),
+            "date" => Some(ValueType::Date),
+            _ => None,
+        }
+    }
+
+    /// Metadata values are stored as strings; check the string parses as this type.
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ValueType::String => true,
+            ValueType::Number => value.parse::<f64>().is_ok(),
+            ValueType::Boolean => value == "true" || value == "false",
+            ValueType::Date => {
+                DateTime::parse_from_rfc3339(value).is_ok()
+                    || NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTypeSchema {
+    pub fields: BTreeMap<String, FieldSpec>,
+    /// Whether keys not declared in `fields` are permitted.
+    #[serde(default = "default_true")]
+    pub allow_unknown: bool,
+}
+
+/// Metadata schemas keyed by node type name. Types without an entry are unconstrained.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataSchema {
+    pub types: BTreeMap<String, NodeTypeSchema>,
+}
+
+impl MetadataSchema {
+    /// Validate a node's metadata against the schema for its type, reporting
+    /// every violation in one error.
+    pub fn validate(
+        &self,
+        node_type: &NodeType,
+        metadata: &HashMap<String, String>,
+    ) -> Result<(), WillowError> {
+        let Some(schema) = self.types.get(node_type.as_str()) else {
+            return Ok(());
+        };
+
+        let mut problems = Vec::new();
+        for (key, spec) in &schema.fields {
+            match metadata.get(key) {
+                None if spec.required => problems.push(format!("missing required key '{key}'")),
+                Some(value) if !spec.value_type.accepts(value) => problems.push(format!(
+                    "key '{key}' expects {} but got '{value}'",
+                    spec.value_type.as_str()
+                )),
+                _ => {}
+            }
+        }
+        if !schema.allow_unknown {
+            let mut unknown: Vec<&String> = metadata
+                .keys()
+                .filter(|k| !schema.fields.contains_key(*k))
+                .collect();
+            unknown.sort();
+            problems.extend(unknown.into_iter().map(|k| format!("unknown key '{k}'")));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(WillowError::SchemaViolation(format!(
+                "{}: {}",
+                node_type.as_str(),
+                problems.join(", ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_schema() -> MetadataSchema {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "date".to_string(),
+            FieldSpec { value_type: ValueType::Date, required: true },
+        );
+        fields.insert(
+            "attendees".to_string(),
+            FieldSpec { value_type: ValueType::Number, required: false },
+        );
+        let mut types = BTreeMap::new();
+        types.insert(
+            "event".to_string(),
+            NodeTypeSchema { fields, allow_unknown: false },
+        );
+        MetadataSchema { types }
+    }
+
+    fn meta(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_valid_metadata_passes() {
+        let schema = event_schema();
+        assert!(schema.validate(&NodeType::Event, &meta(&[("date", "2024-09-01")])).is_ok());
+        assert!(schema
+            .validate(&NodeType::Event, &meta(&[("date", "2024-09-01T10:00:00Z"), ("attendees", "3")]))
+            .is_ok());
+        // Unconstrained type
+        assert!(schema.validate(&NodeType::Detail, &meta(&[("x", "y")])).is_ok());
+    }
+
+    #[test]
+    fn test_violations_reported() {
+        let schema = event_schema();
+        let err = schema
+            .validate(&NodeType::Event, &meta(&[("attendees", "many"), ("extra", "1")]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("missing required key 'date'"));
+        assert!(err.contains("key 'attendees' expects number"));
+        assert!(err.contains("unknown key 'extra'"));
+    }
+}
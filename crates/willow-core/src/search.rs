@@ -1,6 +1,13 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
-use crate::model::{Graph, Node, NodeId};
+use crate::analysis::{fold_diacritics, Stemmer, TextAnalysis};
+use crate::error::WillowError;
+use crate::model::{Graph, Link, Node, NodeId, NodeType, Sensitivity};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::query::node_tags;
+use crate::query_syntax::{self, FieldFilter, QueryExpr};
+use crate::temporal;
 use tracing::debug;
 
 #[derive(Debug, Clone)]
@@ -11,6 +18,342 @@ pub struct SearchResult {
     pub score: f64,
     pub matched_field: String,
     pub depth: usize,
+    /// Contents from the root down to the node, e.g. `["User", "Career",
+    /// "Google", "Started 2023"]`.
+    pub path: Vec<String>,
+}
+
+/// One page of ranked matches.
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    /// Matches before pagination.
+    pub total: usize,
+}
+
+/// A link matched by `search_links`, with clones of both endpoints.
+#[derive(Debug, Clone)]
+pub struct LinkSearchResult {
+    pub link: Link,
+    pub from_node: Node,
+    pub to_node: Node,
+    pub score: f64,
+    /// `relation`, `from_node` or `to_node`.
+    pub matched_field: String,
+}
+
+/// Relations of the links touching each node, for `relation:` filters.
+type NodeRelations<'a> = HashMap<&'a NodeId, HashSet<String>>;
+
+/// Added to a pinned node's score; text scores never exceed 1.0, so pinned
+/// matches always rank first.
+const PINNED_BOOST: f64 = 1.0;
+
+/// A term that only matches a word within its edit-distance budget counts as
+/// this fraction of an exact match.
+const FUZZY_MATCH_WEIGHT: f64 = 0.7;
+
+/// BM25 term-frequency saturation and document-length normalisation.
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// Raw score multiplier for a field containing a multi-word query verbatim.
+const PHRASE_BOOST: f64 = 1.5;
+
+/// Document frequencies of the query's terms and phrases over the content of
+/// the nodes in scope, so common terms weigh less than rare ones.
+struct CorpusStats {
+    docs: usize,
+    avg_len: f64,
+    doc_freq: HashMap<String, usize>,
+    stemmer: Option<Stemmer>,
+    case_sensitive: bool,
+    fold_diacritics: bool,
+    /// Each atom with its synonyms, normalized; an atom matches where any of
+    /// them does.
+    variants: HashMap<String, Vec<String>>,
+}
+
+impl CorpusStats {
+    fn new(nodes: &[(&Node, usize)], atoms: &[&str], options: &SearchOptions) -> Self {
+        let mut stats = CorpusStats {
+            docs: nodes.len(),
+            avg_len: 0.0,
+            doc_freq: HashMap::new(),
+            stemmer: options.analysis.stemmer,
+            case_sensitive: options.case_sensitive,
+            fold_diacritics: options.fold_diacritics,
+            variants: HashMap::new(),
+        };
+        for atom in atoms {
+            let mut variants = vec![stats.normalize(atom)];
+            variants.extend(options.analysis.synonyms_of(&atom.to_lowercase()).iter().map(|s| stats.normalize(s)));
+            stats.variants.insert(atom.to_string(), variants);
+        }
+        let mut total_len = 0;
+        for (node, _) in nodes {
+            let text = stats.normalize(&node.content_format.searchable_text(&node.content));
+            let words = words(&text);
+            total_len += words.len();
+            for atom in atoms {
+                if stats.term_frequency(&text, &words, atom) > 0.0 {
+                    *stats.doc_freq.entry(atom.to_string()).or_default() += 1;
+                }
+            }
+        }
+        stats.avg_len = total_len as f64 / nodes.len().max(1) as f64;
+        stats
+    }
+
+    /// Lowercase unless case-sensitive, then fold diacritics if asked to.
+    /// Applied alike to the query and to every field scored.
+    fn normalize(&self, text: &str) -> String {
+        let text = if self.case_sensitive { text.to_string() } else { text.to_lowercase() };
+        if self.fold_diacritics {
+            fold_diacritics(&text).into_owned()
+        } else {
+            text
+        }
+    }
+
+    /// The best frequency among `atom` and its synonyms, in normalized text.
+    fn term_frequency(&self, text: &str, words: &[&str], atom: &str) -> f64 {
+        match self.variants.get(atom) {
+            Some(variants) => variants
+                .iter()
+                .map(|v| term_frequency(text, words, v, self.stemmer, !self.case_sensitive))
+                .fold(0.0, f64::max),
+            None => term_frequency(text, words, &self.normalize(atom), self.stemmer, !self.case_sensitive),
+        }
+    }
+
+    /// Whether a single-word atom matches `word` the way `term_frequency`
+    /// counts it.
+    fn word_matches(&self, word: &str, atom: &str) -> bool {
+        word.contains(atom)
+            || self.stemmer.is_some_and(|s| s.stem(word) == s.stem(atom))
+            || (!self.case_sensitive && {
+                let budget = edit_budget(atom);
+                budget > 0 && within_edit_distance(atom, word, budget)
+            })
+    }
+
+    /// BM25 of `text` for `terms` if they all fall within `distance` words of
+    /// each other, scaled by how close they are: adjacent terms keep the full
+    /// score, terms `distance` apart about half of it. 0.0 otherwise.
+    fn near_score(&self, text: &str, terms: &[String], distance: usize) -> f64 {
+        let normalized = self.normalize(text);
+        let words = words(&normalized);
+        let mut occurrences = Vec::new();
+        for (i, term) in terms.iter().enumerate() {
+            let variants = self.variants.get(term).cloned().unwrap_or_else(|| vec![self.normalize(term)]);
+            for variant in &variants {
+                if variant.contains(char::is_whitespace) {
+                    let len = self::words(variant).len();
+                    occurrences.extend(phrase_starts(&words, variant).map(|start| (start, start + len - 1, i)));
+                } else {
+                    occurrences.extend(
+                        (0..words.len()).filter(|&p| self.word_matches(words[p], variant)).map(|p| (p, p, i)),
+                    );
+                }
+            }
+        }
+        match min_span(occurrences, terms.len()) {
+            Some(span) if span <= distance => {
+                let atoms: Vec<&str> = terms.iter().map(String::as_str).collect();
+                let closeness = 1.0 - span.saturating_sub(1) as f64 / (2 * distance) as f64;
+                self.score(text, &atoms, None) * closeness
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn idf(&self, atom: &str) -> f64 {
+        let df = self.doc_freq.get(atom).copied().unwrap_or(0) as f64;
+        ((self.docs as f64 - df + 0.5) / (df + 0.5) + 1.0).ln()
+    }
+
+    /// BM25 of `text` for `atoms`, squashed into `0.0..1.0`. `phrase` is the
+    /// whole query, boosted when it appears verbatim.
+    fn score(&self, text: &str, atoms: &[&str], phrase: Option<&str>) -> f64 {
+        let text = self.normalize(text);
+        let words = words(&text);
+        let norm = BM25_K1 * (1.0 - BM25_B + BM25_B * words.len() as f64 / self.avg_len.max(1.0));
+        let mut raw = 0.0;
+        for atom in atoms {
+            let tf = self.term_frequency(&text, &words, atom);
+            if tf > 0.0 {
+                raw += self.idf(atom) * tf * (BM25_K1 + 1.0) / (tf + norm);
+            }
+        }
+        if phrase.is_some_and(|p| text.contains(&self.normalize(p))) {
+            raw *= PHRASE_BOOST;
+        }
+        raw / (raw + 1.0)
+    }
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect()
+}
+
+/// Word positions where `phrase` starts as whole words, punctuation ignored,
+/// so "new york" matches "New York, NY" but not "new yorkshire".
+fn phrase_starts<'a>(words: &'a [&str], phrase: &str) -> impl Iterator<Item = usize> + 'a {
+    let phrase: Vec<String> = self::words(phrase).into_iter().map(str::to_string).collect();
+    words
+        .windows(phrase.len().max(1))
+        .enumerate()
+        .filter(move |(_, w)| !phrase.is_empty() && w.iter().zip(&phrase).all(|(a, b)| a == b))
+        .map(|(i, _)| i)
+}
+
+/// Smallest distance in words from the first to the last of a set of
+/// occurrences covering every term. Occurrences are `(start, end, term)`.
+fn min_span(mut occurrences: Vec<(usize, usize, usize)>, terms: usize) -> Option<usize> {
+    occurrences.sort();
+    let mut best: Option<usize> = None;
+    for (i, &(start, _, _)) in occurrences.iter().enumerate() {
+        let mut seen = vec![false; terms];
+        let mut covered = 0;
+        let mut end = start;
+        for &(_, e, t) in &occurrences[i..] {
+            if !seen[t] {
+                seen[t] = true;
+                covered += 1;
+            }
+            end = end.max(e);
+            if covered == terms {
+                best = Some(best.map_or(end - start, |b| b.min(end - start)));
+                break;
+            }
+        }
+    }
+    best
+}
+
+/// Occurrences of `atom` in `text`, substrings included ("run" in "running").
+/// A multi-word atom only matches as whole words in order.
+/// A single word with no occurrence falls back to words sharing its stem
+/// ("ran" never matches, "runs" does), then to typo-tolerant matching if
+/// `fuzzy`.
+fn term_frequency(text: &str, words: &[&str], atom: &str, stemmer: Option<Stemmer>, fuzzy: bool) -> f64 {
+    if atom.contains(char::is_whitespace) {
+        return phrase_starts(words, atom).count() as f64;
+    }
+    let exact = text.matches(atom).count();
+    if exact > 0 || !atom.chars().all(char::is_alphanumeric) {
+        return exact as f64;
+    }
+    if let Some(stemmer) = stemmer {
+        let stem = stemmer.stem(atom);
+        let stemmed = words.iter().filter(|w| stemmer.stem(w) == stem).count();
+        if stemmed > 0 {
+            return stemmed as f64;
+        }
+    }
+    let budget = edit_budget(atom);
+    if !fuzzy || budget == 0 {
+        return 0.0;
+    }
+    let fuzzy = words.iter().filter(|w| within_edit_distance(atom, w, budget)).count();
+    fuzzy as f64 * FUZZY_MATCH_WEIGHT
+}
+
+/// Score multipliers that favour recently updated nodes and chosen node
+/// types. Persisted next to the graph; the default leaves scores alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingBoosts {
+    /// A node updated just now scores up to `1 + recency_weight` times as
+    /// much; the bonus halves every `recency_half_life_days`.
+    pub recency_weight: f64,
+    pub recency_half_life_days: f64,
+    /// Multipliers by node type name, e.g. `{"entity": 1.5, "detail": 0.8}`.
+    /// Unlisted types keep 1.0.
+    pub type_weights: BTreeMap<String, f64>,
+}
+
+impl Default for RankingBoosts {
+    fn default() -> Self {
+        RankingBoosts {
+            recency_weight: 0.0,
+            recency_half_life_days: 30.0,
+            type_weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl RankingBoosts {
+    pub fn validate(&self) -> Result<(), WillowError> {
+        let valid = |w: f64| w.is_finite() && w >= 0.0;
+        if !valid(self.recency_weight) {
+            return Err(WillowError::InvalidBoost(format!("recency weight {}", self.recency_weight)));
+        }
+        if !valid(self.recency_half_life_days) || self.recency_half_life_days == 0.0 {
+            return Err(WillowError::InvalidBoost(format!("half-life {}", self.recency_half_life_days)));
+        }
+        for (node_type, weight) in &self.type_weights {
+            if NodeType::from_str(node_type).is_none() {
+                return Err(WillowError::InvalidNodeType(node_type.clone()));
+            }
+            if !valid(*weight) {
+                return Err(WillowError::InvalidBoost(format!("{node_type} weight {weight}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// The node's multiplier divided by the largest any node could get, so
+    /// boosted text scores stay within 0..=1 and pinned nodes still lead.
+    fn factor(&self, node: &Node, now: DateTime<Utc>) -> f64 {
+        let age_days = (now - node.updated_at).num_seconds().max(0) as f64 / 86_400.0;
+        let recency = 1.0 + self.recency_weight * 0.5f64.powf(age_days / self.recency_half_life_days);
+        let type_weight = self.type_weights.get(node.node_type.as_str()).copied().unwrap_or(1.0);
+        let max_type_weight = self.type_weights.values().copied().fold(1.0, f64::max);
+        recency * type_weight / ((1.0 + self.recency_weight) * max_type_weight)
+    }
+}
+
+/// Options for `search_nodes` beyond the query itself.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Nodes above this level are skipped along with their subtrees.
+    pub max_sensitivity: Sensitivity,
+    /// Also search archived subtrees, which are skipped by default.
+    pub include_archived: bool,
+    /// Date ranges: `_after` bounds are inclusive, `_before` bounds exclusive.
+    /// Nodes outside a range are not returned but their children are searched.
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+    /// Only nodes valid at some point in `valid_from..=valid_until`; nodes
+    /// without temporal metadata are always valid.
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    /// Stemming and synonyms applied to query terms.
+    pub analysis: TextAnalysis,
+    /// Match terms and phrases with their case, and without typo tolerance,
+    /// which would let "Nero" match "nero"; by default case is ignored.
+    pub case_sensitive: bool,
+    /// Fold accented letters on both sides, so "café" matches "cafe".
+    pub fold_diacritics: bool,
+    /// Recency and node type boosts; `GraphStore` fills in its own when unset.
+    pub boosts: Option<RankingBoosts>,
+}
+
+impl SearchOptions {
+    pub fn dates_match(&self, node: &Node) -> bool {
+        let in_range = |date: DateTime<Utc>, after: Option<DateTime<Utc>>, before: Option<DateTime<Utc>>| {
+            after.is_none_or(|a| date >= a) && before.is_none_or(|b| date < b)
+        };
+        in_range(node.created_at, self.created_after, self.created_before)
+            && in_range(node.updated_at, self.updated_after, self.updated_before)
+            && node
+                .temporal
+                .as_ref()
+                .is_none_or(|t| t.overlaps(self.valid_from, self.valid_until))
+    }
 }
 
 fn cmp_score(a: &f64, b: &f64) -> std::cmp::Ordering {
@@ -18,124 +361,482 @@ fn cmp_score(a: &f64, b: &f64) -> std::cmp::Ordering {
 }
 
 /// Search the graph by traversing from a starting node via BFS.
-/// Only nodes reachable through the tree hierarchy are visited.
+/// Only nodes reachable through the tree hierarchy are visited. Matches are
+/// ranked with BM25 over the nodes in scope, so rare terms outweigh common ones.
 /// When `root_node_id` is provided, the search starts from that node instead of the graph root.
-pub fn search_nodes(graph: &Graph, query: &str, max_results: usize, root_node_id: Option<&NodeId>) -> Vec<SearchResult> {
-    let query_lower = query.to_lowercase();
-    let terms: Vec<&str> = query_lower.split_whitespace().collect();
-
-    if terms.is_empty() {
+/// Queries may use `AND`, `OR`, `NOT`, parentheses and quoted phrases; see
+/// `query_syntax`, and `field:value` filters such as `type:event`. A pure
+/// negation matches nothing, as there is nothing to rank. Every match is
+/// returned, best first.
+pub fn search_nodes(
+    graph: &Graph,
+    query: &str,
+    root_node_id: Option<&NodeId>,
+    options: &SearchOptions,
+) -> Vec<SearchResult> {
+    let Some(expr) = query_syntax::parse(query, options.case_sensitive) else {
         return Vec::new();
+    };
+    let boolean = (!expr.is_plain()).then_some(&expr);
+    let mut relations = NodeRelations::new();
+    if boolean.is_some() {
+        for link in graph.links.values() {
+            for end in [&link.from_node, &link.to_node] {
+                relations.entry(end).or_default().insert(link.relation.to_lowercase());
+            }
+        }
     }
-
     let start_id = root_node_id.unwrap_or(&graph.root_id);
+    let candidates: Vec<(&Node, usize)> = visible_nodes(graph, start_id, options)
+        .into_iter()
+        .filter(|(node, _)| options.dates_match(node))
+        .collect();
 
-    let mut results: Vec<SearchResult> = Vec::new();
+    let atoms = expr.text_atoms();
+    let stats = CorpusStats::new(&candidates, &atoms, options);
+    let phrase = atoms.join(" ");
+    let phrase = (boolean.is_none() && atoms.len() > 1).then_some(phrase.as_str());
+    let now = Utc::now();
+    let mut results: Vec<SearchResult> = candidates
+        .into_iter()
+        .filter_map(|(node, depth)| {
+            let (score, field) = match boolean {
+                Some(expr) => score_expr(node, expr, &stats, &relations),
+                None => best_field(node, |text| stats.score(text, &atoms, phrase)),
+            }?;
+            let boost = options.boosts.as_ref().map_or(1.0, |b| b.factor(node, now));
+            to_result(graph, node, (score * boost, field), depth)
+        })
+        .collect();
+
+    // Stable, so equal scores keep BFS order: shallower first, then sibling order.
+    results.sort_by(|a, b| cmp_score(&b.score, &a.score));
+    debug!(query = %query, results = results.len(), "search complete");
+    results
+}
+
+/// Nodes reachable from `start_id` through the tree hierarchy, in BFS order
+/// with their depth. Subtrees above `max_sensitivity` or archived (unless
+/// included) are skipped.
+pub fn visible_nodes<'a>(graph: &'a Graph, start_id: &'a NodeId, options: &SearchOptions) -> Vec<(&'a Node, usize)> {
+    let mut nodes = Vec::new();
     let mut queue: VecDeque<(&NodeId, usize)> = VecDeque::new();
+    // Nodes with several parents are reachable along more than one path.
+    let mut visited: HashSet<&NodeId> = HashSet::from([start_id]);
     queue.push_back((start_id, 0));
 
     while let Some((node_id, depth)) = queue.pop_front() {
         let node = match graph.nodes.get(node_id) {
-            Some(n) => n,
+            Some(n) => n.as_ref(),
             None => continue,
         };
-
-        if let Some(result) = score_node(node, &query_lower, &terms, depth) {
-            results.push(result);
+        if node.sensitivity > options.max_sensitivity
+            || (!options.include_archived && temporal::is_archived(graph, node))
+        {
+            continue;
         }
-
+        nodes.push((node, depth));
         for child_id in &node.children {
-            queue.push_back((child_id, depth + 1));
+            if visited.insert(child_id) {
+                queue.push_back((child_id, depth + 1));
+            }
         }
     }
-
-    results.sort_by(|a, b| cmp_score(&b.score, &a.score));
-    results.truncate(max_results);
-    debug!(query = %query, results = results.len(), "search complete");
-    results
+    nodes
 }
 
-fn score_node(node: &Node, query_lower: &str, terms: &[&str], depth: usize) -> Option<SearchResult> {
-    let candidates = [
-        (1.0, "content", node.content.as_str()),
-        (0.3, "node_type", node.node_type.as_str()),
-    ];
+/// Search links by relation name and the content of their endpoints. Only
+/// links with both ends visible from `root_node_id` (default the graph root)
+/// are considered. The query is read as plain words and phrases: operators
+/// and field filters are ignored. `created_after`/`created_before` apply to
+/// the link; the other date ranges are unused. Best first, ties in link id
+/// order.
+pub fn search_links(
+    graph: &Graph,
+    query: &str,
+    root_node_id: Option<&NodeId>,
+    options: &SearchOptions,
+) -> Vec<LinkSearchResult> {
+    let Some(expr) = query_syntax::parse(query, options.case_sensitive) else {
+        return Vec::new();
+    };
+    let atoms = expr.text_atoms();
+    if atoms.is_empty() {
+        return Vec::new();
+    }
+    let start_id = root_node_id.unwrap_or(&graph.root_id);
+    let visible = visible_nodes(graph, start_id, options);
+    let by_id: HashMap<&NodeId, &Node> = visible.iter().map(|(n, _)| (&n.id, *n)).collect();
+
+    let mut links: Vec<&Link> = graph
+        .links
+        .values()
+        .filter(|l| {
+            options.created_after.is_none_or(|a| l.created_at >= a)
+                && options.created_before.is_none_or(|b| l.created_at < b)
+        })
+        .collect();
+    links.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let stats = CorpusStats::new(&visible, &atoms, options);
+    let phrase = atoms.join(" ");
+    let phrase = (atoms.len() > 1).then_some(phrase.as_str());
+    let score = |text: &str| stats.score(text, &atoms, phrase);
+    let endpoint = |node: &Node| score(&node.content_format.searchable_text(&node.content));
 
-    let (best_score, best_field) = candidates
+    let mut results: Vec<LinkSearchResult> = links
         .into_iter()
-        .map(|(weight, field, text)| (score_text(text, query_lower, terms) * weight, field.to_string()))
-        .chain(node.metadata.iter().map(|(k, v)| {
-            (score_text(v, query_lower, terms) * 0.5, format!("metadata.{k}"))
-        }))
-        .max_by(|a, b| cmp_score(&a.0, &b.0))?;
+        .filter_map(|link| {
+            let (from, to) = (*by_id.get(&link.from_node)?, *by_id.get(&link.to_node)?);
+            let (score, field) = [
+                (score(&link.relation.replace('_', " ")), "relation"),
+                (endpoint(from) * 0.5, "from_node"),
+                (endpoint(to) * 0.5, "to_node"),
+            ]
+            .into_iter()
+            .rev()
+            .max_by(|a, b| cmp_score(&a.0, &b.0))?;
+            (score > 0.0).then(|| LinkSearchResult {
+                link: link.clone(),
+                from_node: from.clone(),
+                to_node: to.clone(),
+                score,
+                matched_field: field.to_string(),
+            })
+        })
+        .collect();
+    results.sort_by(|a, b| cmp_score(&b.score, &a.score));
+    debug!(query = %query, results = results.len(), "link search complete");
+    results
+}
 
+fn to_result(
+    graph: &Graph,
+    node: &Node,
+    (best_score, best_field): (f64, String),
+    depth: usize,
+) -> Option<SearchResult> {
     if best_score > 0.0 {
         Some(SearchResult {
             node_id: node.id.clone(),
             node_type: node.node_type.as_str().to_string(),
             content: node.content.clone(),
-            score: best_score,
+            score: if node.pinned { best_score + PINNED_BOOST } else { best_score },
             matched_field: best_field,
             depth,
+            path: graph.content_path(&node.id),
         })
     } else {
         None
     }
 }
 
-fn score_text(text: &str, query_lower: &str, terms: &[&str]) -> f64 {
-    let text_lower = text.to_lowercase();
+/// The highest weighted score of `score` over the node's fields, and that
+/// field's name. Metadata is scored in key order; on equal scores the first
+/// field wins.
+fn best_field(node: &Node, score: impl Fn(&str) -> f64) -> Option<(f64, String)> {
+    let content = node.content_format.searchable_text(&node.content);
+    let candidates = [
+        (1.0, "content", content.as_ref()),
+        (0.3, "node_type", node.node_type.as_str()),
+    ];
+    let mut metadata: Vec<(&String, &String)> = node.metadata.iter().collect();
+    metadata.sort();
+    candidates
+        .into_iter()
+        .map(|(weight, field, text)| (score(text) * weight, field.to_string()))
+        .chain(metadata.into_iter().map(|(k, v)| (score(v) * 0.5, format!("metadata.{k}"))))
+        .rev()
+        .max_by(|a, b| cmp_score(&a.0, &b.0))
+}
+
+/// Whether the node passes a field filter, and the field it matched on.
+fn match_field(node: &Node, filter: &FieldFilter, relations: &NodeRelations) -> Option<String> {
+    let matched = match filter {
+        FieldFilter::Type(t) => node.node_type.as_str() == t,
+        FieldFilter::Metadata { key, value } => node.metadata.get(key).is_some_and(|v| v.to_lowercase() == *value),
+        FieldFilter::Tag(tag) => node_tags(node).any(|t| t.to_lowercase() == *tag),
+        FieldFilter::Relation(r) => relations.get(&node.id).is_some_and(|rs| rs.contains(r)),
+    };
+    matched.then(|| match filter {
+        FieldFilter::Type(_) => "node_type".to_string(),
+        FieldFilter::Metadata { key, .. } => format!("metadata.{key}"),
+        FieldFilter::Tag(_) => "tags".to_string(),
+        FieldFilter::Relation(_) => "links".to_string(),
+    })
+}
 
-    if text_lower.contains(query_lower) {
-        return 1.0;
+/// `None` when the node does not satisfy `expr`. `AND` averages the scores
+/// of its text parts, `OR` takes the best branch, and `NOT` only filters.
+/// Field filters count as full matches when an `AND` has no text parts, so
+/// `type:event` alone lists every event.
+fn score_expr(
+    node: &Node,
+    expr: &QueryExpr,
+    stats: &CorpusStats,
+    relations: &NodeRelations,
+) -> Option<(f64, String)> {
+    match expr {
+        QueryExpr::Field(filter) => match_field(node, filter, relations).map(|field| (1.0, field)),
+        QueryExpr::Term(atom) | QueryExpr::Phrase(atom) => {
+            best_field(node, |text| stats.score(text, &[atom.as_str()], None)).filter(|(s, _)| *s > 0.0)
+        }
+        QueryExpr::Near { terms, distance } => {
+            best_field(node, |text| stats.near_score(text, terms, *distance)).filter(|(s, _)| *s > 0.0)
+        }
+        QueryExpr::Not(inner) => match score_expr(node, inner, stats, relations) {
+            Some(_) => None,
+            None => Some((0.0, String::new())),
+        },
+        QueryExpr::And(items) => {
+            let mut text = Vec::new();
+            let mut fields = Vec::new();
+            for item in items {
+                let scored = score_expr(node, item, stats, relations)?;
+                match item {
+                    QueryExpr::Not(_) => {}
+                    QueryExpr::Field(_) => fields.push(scored),
+                    _ => text.push(scored),
+                }
+            }
+            let parts = if text.is_empty() { fields } else { text };
+            let mean = parts.iter().map(|(s, _)| s).sum::<f64>() / parts.len().max(1) as f64;
+            let best = parts.into_iter().rev().max_by(|a, b| cmp_score(&a.0, &b.0));
+            Some(best.map_or((0.0, String::new()), |(_, field)| (mean, field)))
+        }
+        QueryExpr::Or(items) => items
+            .iter()
+            .filter_map(|item| score_expr(node, item, stats, relations))
+            .rev()
+            .max_by(|a, b| cmp_score(&a.0, &b.0)),
     }
+}
 
-    let matched = terms.iter().filter(|t| text_lower.contains(**t)).count();
+/// Typos allowed in a term: none for short terms, where one edit already
+/// matches unrelated words, then one, then two from nine characters.
+fn edit_budget(term: &str) -> usize {
+    match term.chars().count() {
+        0..=3 => 0,
+        4..=8 => 1,
+        _ => 2,
+    }
+}
 
-    match matched {
-        n if n == terms.len() => 0.6,
-        0 => 0.0,
-        n => 0.3 * (n as f64 / terms.len() as f64),
+/// Levenshtein distance between `a` and `b` is at most `max`.
+fn within_edit_distance(a: &str, b: &str, max: usize) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return false;
     }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        if row.iter().min().is_some_and(|&m| m > max) {
+            return false;
+        }
+        prev = row;
+    }
+    prev[b.len()] <= max
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
     use crate::storage::create_default_graph;
-    use crate::model::{Node, NodeId, NodeType};
+    use crate::model::{ContentFormat, Link, LinkId, Node, NodeId, NodeType, Sensitivity};
     use chrono::Utc;
     use std::collections::HashMap;
 
     /// Insert a node into the graph and wire it into the root's children list.
     fn insert_child_of_root(graph: &mut Graph, id: &str, content: &str, node_type: NodeType) -> NodeId {
         let now = Utc::now();
-        let node_id = NodeId(id.to_string());
+        let node_id = NodeId(id.into());
         let node = Node {
             id: node_id.clone(),
             node_type,
             content: content.to_string(),
             parent_id: Some(graph.root_id.clone()),
             children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         };
-        graph.nodes.insert(node.id.clone(), node);
-        graph.nodes.get_mut(&graph.root_id).unwrap().children.push(node_id.clone());
+        graph.nodes.insert(node.id.clone(), node.into());
+        graph.nodes.get_mut(&graph.root_id).map(Arc::make_mut).unwrap().children.push(node_id.clone());
         node_id
     }
 
     #[test]
     fn test_exact_match_scores_highest() {
         let mut graph = create_default_graph();
-        insert_child_of_root(&mut graph, "n1", "favorite color is blue", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n1", "blue is my favorite color", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "favorite color is blue", NodeType::Detail);
+
+        let results = search_nodes(&graph, "favorite color is blue", None, &SearchOptions::default());
+        assert_eq!(results.len(), 2);
+        assert_eq!(&*results[0].node_id.0, "n2");
+        assert!(results[0].score > results[1].score);
+        assert!(results[0].score < 1.0);
+    }
+
+    #[test]
+    fn test_search_links_by_relation_and_endpoint() {
+        let mut graph = create_default_graph();
+        let coffee = insert_child_of_root(&mut graph, "coffee", "Drinks coffee late", NodeType::Detail);
+        let sleep = insert_child_of_root(&mut graph, "sleep", "Sleeps badly", NodeType::Detail);
+        let tea = insert_child_of_root(&mut graph, "tea", "Enjoys green tea", NodeType::Detail);
+        for (id, from, to, relation) in [("l1", &coffee, &sleep, "caused_by"), ("l2", &tea, &coffee, "related_to")] {
+            graph.links.insert(
+                LinkId(id.into()),
+                Link {
+                    id: LinkId(id.into()),
+                    from_node: from.clone(),
+                    to_node: to.clone(),
+                    relation: relation.to_string(),
+                    bidirectional: false,
+                    confidence: None,
+                    created_at: Utc::now(),
+                },
+            );
+        }
+
+        let results = search_links(&graph, "caused", None, &SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].link.id.0, "l1");
+        assert_eq!(results[0].matched_field, "relation");
+        assert_eq!(results[0].to_node.content, "Sleeps badly");
+
+        let results = search_links(&graph, "coffee", None, &SearchOptions::default());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|r| &*r.link.id.0 == "l2" && r.matched_field == "to_node"));
+
+        graph.nodes.get_mut(&sleep).map(Arc::make_mut).unwrap().sensitivity = Sensitivity::Secret;
+        let results = search_links(&graph, "coffee", None, &SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].link.id.0, "l2");
+    }
+
+    #[test]
+    fn test_stemming_and_synonyms() {
+        let mut graph = create_default_graph();
+        insert_child_of_root(&mut graph, "n1", "Runs every morning", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "Work at the bakery", NodeType::Detail);
+
+        let plain = SearchOptions::default();
+        assert!(search_nodes(&graph, "running", None, &plain).is_empty());
+        assert!(search_nodes(&graph, "job", None, &plain).is_empty());
+
+        let analysed = SearchOptions {
+            analysis: TextAnalysis {
+                stemmer: Some(Stemmer::English),
+                synonyms: vec![vec!["job".to_string(), "work".to_string()]],
+            },
+            ..SearchOptions::default()
+        };
+        let results = search_nodes(&graph, "running", None, &analysed);
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].node_id.0, "n1");
+        let results = search_nodes(&graph, "job", None, &analysed);
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].node_id.0, "n2");
+    }
+
+    #[test]
+    fn test_case_sensitivity_and_diacritic_folding() {
+        let mut graph = create_default_graph();
+        insert_child_of_root(&mut graph, "n1", "Coffee at Café Nero", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "Nero the cat", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n3", "nero is a flavour", NodeType::Detail);
+
+        let default = SearchOptions::default();
+        assert_eq!(search_nodes(&graph, "nero", None, &default).len(), 3);
+        let typo = search_nodes(&graph, "cafe", None, &default);
+
+        let strict = SearchOptions {
+            case_sensitive: true,
+            ..SearchOptions::default()
+        };
+        let ids: Vec<_> = search_nodes(&graph, "Nero", None, &strict).into_iter().map(|r| r.node_id.0).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&"n3".into()));
+
+        let folded = SearchOptions {
+            fold_diacritics: true,
+            ..SearchOptions::default()
+        };
+        let results = search_nodes(&graph, "cafe", None, &folded);
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].node_id.0, "n1");
+        assert!(results[0].score > typo[0].score);
+        assert_eq!(search_nodes(&graph, "café", None, &folded).len(), 1);
+    }
+
+    #[test]
+    fn test_phrase_and_proximity_queries() {
+        let mut graph = create_default_graph();
+        insert_child_of_root(&mut graph, "n1", "Moved to New York, NY in 2019", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "Visited New Yorkshire", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n3", "Coffee at night causes bad sleep", NodeType::Detail);
+        insert_child_of_root(
+            &mut graph,
+            "n4",
+            "Coffee with friends on Sunday. Later that week the neighbours kept me up, so bad sleep",
+            NodeType::Detail,
+        );
 
-        let results = search_nodes(&graph, "favorite color is blue", 10, None);
+        let results = search_nodes(&graph, "\"new york\"", None, &SearchOptions::default());
         assert_eq!(results.len(), 1);
-        assert!((results[0].score - 1.0).abs() < f64::EPSILON);
+        assert_eq!(&*results[0].node_id.0, "n1");
+
+        let results = search_nodes(&graph, "coffee NEAR/5 sleep", None, &SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].node_id.0, "n3");
+        let results = search_nodes(&graph, "coffee NEAR/20 sleep", None, &SearchOptions::default());
+        assert_eq!(results.len(), 2);
+        assert_eq!(&*results[0].node_id.0, "n3");
+    }
+
+    #[test]
+    fn test_rare_terms_outweigh_common_ones() {
+        let mut graph = create_default_graph();
+        for (id, content) in [("n1", "Likes tea"), ("n2", "Likes jazz"), ("n3", "Likes hiking"), ("n4", "Likes rain")] {
+            insert_child_of_root(&mut graph, id, content, NodeType::Detail);
+        }
+        insert_child_of_root(&mut graph, "n5", "Allergic to penicillin", NodeType::Detail);
+
+        let results = search_nodes(&graph, "likes penicillin", None, &SearchOptions::default());
+        assert_eq!(results.len(), 5);
+        assert_eq!(&*results[0].node_id.0, "n5");
+    }
+
+    #[test]
+    fn test_markdown_syntax_ignored() {
+        let mut graph = create_default_graph();
+        let id = insert_child_of_root(&mut graph, "n1", "My **favourite** food is _pizza_", NodeType::Detail);
+        graph.nodes.get_mut(&id).map(Arc::make_mut).unwrap().content_format = ContentFormat::Markdown;
+        insert_child_of_root(&mut graph, "n2", "My favourite food is pizza", NodeType::Detail);
+
+        let results = search_nodes(&graph, "favourite food is pizza", None, &SearchOptions::default());
+        assert_eq!(results.len(), 2);
+        assert!((results[0].score - results[1].score).abs() < f64::EPSILON);
+        assert_eq!(results[0].content, "My **favourite** food is _pizza_");
     }
 
     #[test]
@@ -143,18 +844,136 @@ mod tests {
         let mut graph = create_default_graph();
         insert_child_of_root(&mut graph, "n1", "likes pizza and pasta", NodeType::Detail);
 
-        let results = search_nodes(&graph, "pizza sushi", 10, None);
+        let results = search_nodes(&graph, "pizza sushi", None, &SearchOptions::default());
         assert_eq!(results.len(), 1);
         assert!(results[0].score > 0.0);
         assert!(results[0].score < 0.6);
     }
 
+    #[test]
+    fn test_ties_are_deterministic() {
+        let mut graph = create_default_graph();
+        for id in ["n3", "n1", "n2"] {
+            let nid = insert_child_of_root(&mut graph, id, "note", NodeType::Detail);
+            let node = graph.nodes.get_mut(&nid).map(Arc::make_mut).unwrap();
+            for key in ["source", "origin", "place", "city"] {
+                node.metadata.insert(key.to_string(), "Leeds".to_string());
+            }
+        }
+
+        let results = search_nodes(&graph, "leeds", None, &SearchOptions::default());
+        let ids: Vec<&str> = results.iter().map(|r| &*r.node_id.0).collect();
+        assert_eq!(ids, ["n3", "n1", "n2"]);
+        assert!(results.iter().all(|r| r.matched_field == "metadata.city"));
+    }
+
+    #[test]
+    fn test_fuzzy_match_with_penalty() {
+        let mut graph = create_default_graph();
+        insert_child_of_root(&mut graph, "n1", "Studied at Imperial College", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "Favourite food is pizza", NodeType::Detail);
+
+        let exact = search_nodes(&graph, "Imperial College", None, &SearchOptions::default());
+        let results = search_nodes(&graph, "Imperal College", None, &SearchOptions::default());
+        assert_eq!(&*results[0].node_id.0, "n1");
+        assert!(results[0].score < exact[0].score);
+        let results = search_nodes(&graph, "pizzza", None, &SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(&*results[0].node_id.0, "n2");
+        assert!(search_nodes(&graph, "pizzeria", None, &SearchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_boolean_queries() {
+        let mut graph = create_default_graph();
+        insert_child_of_root(&mut graph, "n1", "Plays piano", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "Plays piano and guitar", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n3", "Works in machine learning", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n4", "Took an ML course", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n5", "Learning to use a sewing machine", NodeType::Detail);
+
+        let ids = |query: &str| -> Vec<String> {
+            let mut ids: Vec<String> = search_nodes(&graph, query, None, &SearchOptions::default())
+                .into_iter()
+                .map(|r| r.node_id.0.to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids("piano NOT guitar"), ["n1"]);
+        assert_eq!(ids("\"machine learning\" OR ml"), ["n3", "n4"]);
+        assert!(ids("NOT guitar").is_empty());
+    }
+
+    #[test]
+    fn test_field_filters() {
+        let mut graph = create_default_graph();
+        let run = insert_child_of_root(&mut graph, "n1", "Went for a run", NodeType::Event);
+        let sleep = insert_child_of_root(&mut graph, "n2", "Slept badly", NodeType::Event);
+        insert_child_of_root(&mut graph, "n3", "Prefers running shoes", NodeType::Detail);
+        let node = graph.nodes.get_mut(&run).map(Arc::make_mut).unwrap();
+        node.metadata.insert("source".to_string(), "conversation-42".to_string());
+        node.metadata.insert("tags".to_string(), "health, sport".to_string());
+        graph.links.insert(
+            LinkId("l1".into()),
+            Link {
+                id: LinkId("l1".into()),
+                from_node: sleep.clone(),
+                to_node: run.clone(),
+                relation: "caused_by".to_string(),
+                bidirectional: false,
+                confidence: None,
+                created_at: Utc::now(),
+            },
+        );
+
+        let ids = |query: &str| -> Vec<String> {
+            search_nodes(&graph, query, None, &SearchOptions::default())
+                .into_iter()
+                .map(|r| r.node_id.0.to_string())
+                .collect()
+        };
+        assert_eq!(ids("type:event"), ["n1", "n2"]);
+        assert_eq!(ids("run type:detail"), ["n3"]);
+        assert_eq!(ids("metadata.source:conversation-42 tag:health"), ["n1"]);
+        assert_eq!(ids("relation:caused_by NOT run"), ["n2"]);
+    }
+
+    #[test]
+    fn test_date_range_filters() {
+        let mut graph = create_default_graph();
+        let now = Utc::now();
+        let old = insert_child_of_root(&mut graph, "n1", "Old note", NodeType::Detail);
+        insert_child_of_root(&mut graph, "n2", "Recent note", NodeType::Detail);
+        let job = insert_child_of_root(&mut graph, "n3", "Job note", NodeType::Detail);
+        graph.nodes.get_mut(&old).map(Arc::make_mut).unwrap().updated_at = now - chrono::Duration::days(30);
+        graph.nodes.get_mut(&job).map(Arc::make_mut).unwrap().temporal = Some(crate::model::TemporalMetadata {
+            valid_from: Some(now - chrono::Duration::days(400)),
+            valid_until: Some(now - chrono::Duration::days(300)),
+            label: None,
+        });
+
+        let ids = |options: &SearchOptions| -> Vec<String> {
+            search_nodes(&graph, "note", None, options).into_iter().map(|r| r.node_id.0.to_string()).collect()
+        };
+        let last_week = SearchOptions {
+            updated_after: Some(now - chrono::Duration::days(7)),
+            ..SearchOptions::default()
+        };
+        assert_eq!(ids(&last_week), ["n2", "n3"]);
+        let this_year = SearchOptions {
+            valid_from: Some(now - chrono::Duration::days(200)),
+            ..SearchOptions::default()
+        };
+        assert_eq!(ids(&this_year), ["n1", "n2"]);
+    }
+
     #[test]
     fn test_no_match() {
         let mut graph = create_default_graph();
         insert_child_of_root(&mut graph, "n1", "likes pizza", NodeType::Detail);
 
-        let results = search_nodes(&graph, "quantum mechanics", 10, None);
+        let results = search_nodes(&graph, "quantum mechanics", None, &SearchOptions::default());
         assert!(results.is_empty());
     }
 
@@ -162,16 +981,16 @@ mod tests {
     fn test_metadata_match() {
         let mut graph = create_default_graph();
         let node_id = insert_child_of_root(&mut graph, "n1", "some content", NodeType::Detail);
-        graph.nodes.get_mut(&node_id).unwrap()
+        graph.nodes.get_mut(&node_id).map(Arc::make_mut).unwrap()
             .metadata.insert("source".to_string(), "conversation about hobbies".to_string());
 
-        let results = search_nodes(&graph, "hobbies", 10, None);
+        let results = search_nodes(&graph, "hobbies", None, &SearchOptions::default());
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].matched_field, "metadata.source");
     }
 
     #[test]
-    fn test_results_limited_and_sorted() {
+    fn test_results_sorted() {
         let mut graph = create_default_graph();
         for i in 0..20 {
             insert_child_of_root(
@@ -182,8 +1001,8 @@ mod tests {
             );
         }
 
-        let results = search_nodes(&graph, "item", 5, None);
-        assert_eq!(results.len(), 5);
+        let results = search_nodes(&graph, "item", None, &SearchOptions::default());
+        assert_eq!(results.len(), 20);
         for i in 1..results.len() {
             assert!(results[i - 1].score >= results[i].score);
         }
@@ -196,20 +1015,29 @@ mod tests {
         // BFS from root should never reach this node.
         let now = Utc::now();
         let orphan = Node {
-            id: NodeId("orphan".to_string()),
+            id: NodeId("orphan".into()),
             node_type: NodeType::Detail,
             content: "secret orphan data".to_string(),
             parent_id: None,
             children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         };
-        graph.nodes.insert(orphan.id.clone(), orphan);
+        graph.nodes.insert(orphan.id.clone(), orphan.into());
 
-        let results = search_nodes(&graph, "orphan", 10, None);
+        let results = search_nodes(&graph, "orphan", None, &SearchOptions::default());
         assert!(results.is_empty(), "orphan node should not be reachable via BFS from root");
     }
 
@@ -220,25 +1048,35 @@ mod tests {
 
         // Add a grandchild under the category
         let now = Utc::now();
-        let detail_id = NodeId("detail".to_string());
+        let detail_id = NodeId("detail".into());
         let detail = Node {
             id: detail_id.clone(),
             node_type: NodeType::Detail,
             content: "favorite food is pizza".to_string(),
             parent_id: Some(cat_id.clone()),
             children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         };
-        graph.nodes.insert(detail.id.clone(), detail);
-        graph.nodes.get_mut(&cat_id).unwrap().children.push(detail_id);
+        graph.nodes.insert(detail.id.clone(), detail.into());
+        graph.nodes.get_mut(&cat_id).map(Arc::make_mut).unwrap().children.push(detail_id);
 
-        let results = search_nodes(&graph, "pizza", 10, None);
+        let results = search_nodes(&graph, "pizza", None, &SearchOptions::default());
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].depth, 2); // root(0) -> cat(1) -> detail(2)
+        assert_eq!(results[0].path, vec!["User", "food preferences", "favorite food is pizza"]);
     }
 
     #[test]
@@ -251,50 +1089,68 @@ mod tests {
         let family_id = insert_child_of_root(&mut graph, "family", "Family", NodeType::Category);
 
         // Add children under each
-        let cs_id = NodeId("cs".to_string());
+        let cs_id = NodeId("cs".into());
         let cs = Node {
             id: cs_id.clone(),
             node_type: NodeType::Detail,
             content: "Computer Science degree".to_string(),
             parent_id: Some(edu_id.clone()),
             children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         };
-        graph.nodes.insert(cs.id.clone(), cs);
-        graph.nodes.get_mut(&edu_id).unwrap().children.push(cs_id);
+        graph.nodes.insert(cs.id.clone(), cs.into());
+        graph.nodes.get_mut(&edu_id).map(Arc::make_mut).unwrap().children.push(cs_id);
 
-        let sibling_id = NodeId("sibling".to_string());
+        let sibling_id = NodeId("sibling".into());
         let sibling = Node {
             id: sibling_id.clone(),
             node_type: NodeType::Detail,
             content: "Has a sister studying Computer Science".to_string(),
             parent_id: Some(family_id.clone()),
             children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         };
-        graph.nodes.insert(sibling.id.clone(), sibling);
-        graph.nodes.get_mut(&family_id).unwrap().children.push(sibling_id);
+        graph.nodes.insert(sibling.id.clone(), sibling.into());
+        graph.nodes.get_mut(&family_id).map(Arc::make_mut).unwrap().children.push(sibling_id);
 
         // Global search should find both
-        let all_results = search_nodes(&graph, "Computer Science", 10, None);
+        let all_results = search_nodes(&graph, "Computer Science", None, &SearchOptions::default());
         assert_eq!(all_results.len(), 2);
 
         // Scoped search under Education should only find the CS degree
-        let scoped_results = search_nodes(&graph, "Computer Science", 10, Some(&edu_id));
+        let scoped_results = search_nodes(&graph, "Computer Science", Some(&edu_id), &SearchOptions::default());
         assert_eq!(scoped_results.len(), 1);
-        assert_eq!(scoped_results[0].node_id.0, "cs");
+        assert_eq!(&*scoped_results[0].node_id.0, "cs");
 
         // Scoped search under Family should only find the sibling
-        let family_results = search_nodes(&graph, "Computer Science", 10, Some(&family_id));
+        let family_results = search_nodes(&graph, "Computer Science", Some(&family_id), &SearchOptions::default());
         assert_eq!(family_results.len(), 1);
-        assert_eq!(family_results[0].node_id.0, "sibling");
+        assert_eq!(&*family_results[0].node_id.0, "sibling");
     }
 }
@@ -0,0 +1,90 @@
+use crate::model::{Graph, LinkId, Node, NodeId};
+use crate::vcs::types::Change;
+use std::collections::HashSet;
+
+/// A search index kept in step with the graph, e.g. a full-text engine, an
+/// embedding store or an external service. `GraphStore` calls it after each
+/// operation, undo and redo, with the graph as it is afterwards; `on_reset`
+/// follows wholesale replacements such as a branch switch or a transaction
+/// rollback.
+pub trait SearchIndex: Send {
+    fn on_node_created(&mut self, node: &Node);
+    fn on_node_updated(&mut self, node: &Node);
+    fn on_node_deleted(&mut self, node_id: &NodeId);
+
+    /// The graph was replaced. The default reports every node as updated;
+    /// indexes that can hold nodes no longer in the graph should rebuild.
+    fn on_reset(&mut self, graph: &Graph) {
+        for node in graph.nodes.values() {
+            self.on_node_updated(node);
+        }
+    }
+}
+
+/// Nodes whose indexed state a change may affect. Links count against both
+/// endpoints, since `relation:` filters match on them.
+pub fn touched(change: &Change) -> Vec<&NodeId> {
+    match change {
+        Change::CreateNode { node_id, .. }
+        | Change::UpdateNode { node_id, .. }
+        | Change::ReparentNode { node_id, .. }
+        | Change::AddParent { node_id, .. }
+        | Change::RemoveParent { node_id, .. }
+        | Change::SetTemporal { node_id, .. }
+        | Change::SetPinned { node_id, .. }
+        | Change::SetSensitivity { node_id, .. }
+        | Change::SetArchived { node_id, .. }
+        | Change::SetDisplay { node_id, .. }
+        | Change::AttachBlob { node_id, .. }
+        | Change::DetachBlob { node_id, .. }
+        | Change::StampNode { node_id, .. } => vec![node_id],
+        Change::ReorderChildren { parent_id, .. } => vec![parent_id],
+        Change::DeleteNode { deleted_nodes, .. } => deleted_nodes.iter().map(|n| &n.id).collect(),
+        Change::AddLink { link, .. } | Change::RemoveLink { link, .. } => vec![&link.from_node, &link.to_node],
+        Change::UpdateLink { old_link, new_link, .. } => {
+            vec![&old_link.from_node, &old_link.to_node, &new_link.from_node, &new_link.to_node]
+        }
+        Change::SetProfile { .. } | Change::SetRules { .. } => Vec::new(),
+    }
+}
+
+/// Links a change adds, removes or updates, including those removed with
+/// a deleted node.
+pub fn touched_links(change: &Change) -> Vec<&LinkId> {
+    match change {
+        Change::AddLink { link_id, .. } | Change::RemoveLink { link_id, .. } | Change::UpdateLink { link_id, .. } => {
+            vec![link_id]
+        }
+        Change::DeleteNode { deleted_links, .. } => deleted_links.iter().map(|l| &l.id).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Report one operation's changes to `indexes`, one event per node in the
+/// order first touched. Whether a node was created, updated or deleted is
+/// judged by its state in `graph` after the operation.
+pub fn notify(indexes: &mut [&mut dyn SearchIndex], graph: &Graph, changes: &[Change]) {
+    if indexes.is_empty() {
+        return;
+    }
+    let created: HashSet<&NodeId> = changes
+        .iter()
+        .filter_map(|c| match c {
+            Change::CreateNode { node_id, .. } => Some(node_id),
+            _ => None,
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    for node_id in changes.iter().flat_map(touched) {
+        if !seen.insert(node_id) {
+            continue;
+        }
+        for index in indexes.iter_mut() {
+            match graph.nodes.get(node_id) {
+                Some(node) if created.contains(node_id) => index.on_node_created(node),
+                Some(node) => index.on_node_updated(node),
+                None => index.on_node_deleted(node_id),
+            }
+        }
+    }
+}
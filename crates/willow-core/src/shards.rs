@@ -0,0 +1,273 @@
+use crate::error::WillowError;
+use crate::files;
+use crate::model::{Graph, Link, LinkId, Node, NodeId};
+use crate::rules::Rule;
+use crate::storage::{self, FileStamp};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Value of the manifest's `layout` field.
+const LAYOUT: &str = "sharded";
+/// Shard holding the profile roots themselves.
+const CORE_SHARD: &str = "core";
+/// Shard holding every link, as links may cross subtrees.
+const LINKS_SHARD: &str = "links";
+
+/// What the graph file holds in the sharded layout: the roots and the
+/// shards the nodes and links are spread over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Manifest {
+    layout: String,
+    root_id: NodeId,
+    #[serde(default)]
+    roots: BTreeMap<String, NodeId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<Rule>,
+    shards: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ShardOut<'a> {
+    nodes: BTreeMap<&'a NodeId, &'a Node>,
+    links: BTreeMap<&'a LinkId, &'a Link>,
+}
+
+#[derive(Deserialize)]
+struct ShardIn {
+    #[serde(default)]
+    nodes: HashMap<NodeId, Node>,
+    #[serde(default)]
+    links: HashMap<LinkId, Link>,
+}
+
+/// The directory next to the graph file holding its shards, e.g.
+/// `graph.shards`.
+pub fn shard_dir(graph_path: &Path) -> PathBuf {
+    graph_path.with_extension("shards")
+}
+
+pub fn is_sharded(graph_path: &Path) -> bool {
+    files::is_dir(&shard_dir(graph_path))
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Node ids are used as file names; anything but ASCII letters, digits,
+/// `-` and `_` is replaced.
+fn file_key(id: &NodeId) -> String {
+    let safe: String = id
+        .0
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    format!("subtree-{safe}")
+}
+
+/// The shard each node belongs to: that of its top-level ancestor, the
+/// node just below a root on its primary parent chain. Roots go in the
+/// core shard.
+fn shard_keys(graph: &Graph) -> HashMap<&NodeId, String> {
+    let mut keys: HashMap<&NodeId, String> = HashMap::new();
+    for node in graph.nodes.values() {
+        let mut chain = vec![&node.id];
+        let mut current = node;
+        let key = loop {
+            if let Some(key) = keys.get(&current.id) {
+                break key.clone();
+            }
+            let parent = current.parent_id.as_ref().and_then(|p| graph.nodes.get(p));
+            match parent {
+                None if current.parent_id.is_none() => break CORE_SHARD.to_string(),
+                // Parent missing: the node heads its own shard.
+                None => break file_key(&current.id),
+                Some(p) if p.parent_id.is_none() => break file_key(&current.id),
+                // Guards against parent cycles.
+                Some(_) if chain.len() > graph.nodes.len() => break file_key(&node.id),
+                Some(p) => {
+                    chain.push(&p.id);
+                    current = p;
+                }
+            }
+        };
+        for id in chain {
+            keys.insert(id, key.clone());
+        }
+    }
+    keys
+}
+
+/// Where a sharded graph's files stand on disk, to write only the shards
+/// whose contents changed and to notice shards another process wrote.
+#[derive(Debug)]
+pub struct ShardedLayout {
+    graph_path: PathBuf,
+    manifest: Option<Manifest>,
+    /// Digest and stamp of each shard file as last read or written.
+    digests: HashMap<String, String>,
+    stamps: HashMap<String, Option<FileStamp>>,
+}
+
+impl ShardedLayout {
+    /// A layout for a graph not yet sharded; the first `save` writes every
+    /// shard and the manifest over the graph file.
+    pub fn create(graph_path: &Path) -> Result<Self, WillowError> {
+        files::create_dir_all(&shard_dir(graph_path))?;
+        Ok(ShardedLayout {
+            graph_path: graph_path.to_path_buf(),
+            manifest: None,
+            digests: HashMap::new(),
+            stamps: HashMap::new(),
+        })
+    }
+
+    /// Whether the manifest has been written, i.e. the graph is sharded on
+    /// disk.
+    pub fn is_saved(&self) -> bool {
+        self.manifest.is_some()
+    }
+
+    fn shard_path(&self, key: &str) -> PathBuf {
+        shard_dir(&self.graph_path).join(format!("{key}.json"))
+    }
+
+    /// Refuse to overwrite or remove a shard another process wrote since
+    /// this layout last read or wrote it.
+    fn check_stamp(&self, key: &str, path: &Path) -> Result<(), WillowError> {
+        if storage::file_stamp(path)? != self.stamps.get(key).copied().flatten() {
+            return Err(WillowError::ExternalChange(path.display().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Write the shards whose contents changed and, if the set of shards or
+    /// the roots changed, the manifest. Returns the number of shards written.
+    pub fn save(&mut self, graph: &Graph) -> Result<usize, WillowError> {
+        let keys = shard_keys(graph);
+        let mut shards: BTreeMap<String, ShardOut> = BTreeMap::new();
+        shards.insert(CORE_SHARD.to_string(), ShardOut { nodes: BTreeMap::new(), links: BTreeMap::new() });
+        for (id, node) in &graph.nodes {
+            let key = keys.get(id).cloned().unwrap_or_else(|| CORE_SHARD.to_string());
+            shards
+                .entry(key)
+                .or_insert_with(|| ShardOut { nodes: BTreeMap::new(), links: BTreeMap::new() })
+                .nodes
+                .insert(id, node);
+        }
+        shards.insert(
+            LINKS_SHARD.to_string(),
+            ShardOut {
+                nodes: BTreeMap::new(),
+                links: graph.links.iter().collect(),
+            },
+        );
+
+        let mut written = 0;
+        for (key, shard) in &shards {
+            let data = serde_json::to_vec_pretty(shard)?;
+            let hash = digest(&data);
+            if self.digests.get(key) == Some(&hash) {
+                continue;
+            }
+            let path = self.shard_path(key);
+            self.check_stamp(key, &path)?;
+            let tmp_path = path.with_extension("tmp");
+            files::write(&tmp_path, &data)?;
+            files::rename(&tmp_path, &path)?;
+            self.digests.insert(key.clone(), hash);
+            self.stamps.insert(key.clone(), storage::file_stamp(&path)?);
+            written += 1;
+        }
+
+        let manifest = Manifest {
+            layout: LAYOUT.to_string(),
+            root_id: graph.root_id.clone(),
+            roots: graph.roots.clone(),
+            rules: graph.rules.clone(),
+            shards: shards.keys().cloned().collect(),
+        };
+        if self.manifest.as_ref() != Some(&manifest) {
+            let tmp_path = self.graph_path.with_extension("tmp");
+            files::write(&tmp_path, serde_json::to_vec_pretty(&manifest)?)?;
+            files::rename(&tmp_path, &self.graph_path)?;
+            let gone: Vec<String> = self.digests.keys().filter(|k| !shards.contains_key(*k)).cloned().collect();
+            for key in gone {
+                let path = self.shard_path(&key);
+                self.check_stamp(&key, &path)?;
+                files::remove_file(&path)?;
+                self.digests.remove(&key);
+                self.stamps.remove(&key);
+            }
+            self.manifest = Some(manifest);
+        }
+        debug!(written, shards = shards.len(), "sharded graph saved");
+        Ok(written)
+    }
+}
+
+/// Load a sharded graph from its manifest and shard files, with the layout
+/// to save it through.
+pub fn load(graph_path: &Path) -> Result<(Graph, ShardedLayout), WillowError> {
+    let corrupt = |path: &Path, reason: String| WillowError::CorruptGraph {
+        path: path.display().to_string(),
+        reason,
+    };
+    let manifest: Manifest = serde_json::from_slice(&files::read(graph_path)?)
+        .map_err(|e| corrupt(graph_path, e.to_string()))?;
+    if manifest.layout != LAYOUT {
+        return Err(corrupt(graph_path, format!("unknown layout {}", manifest.layout)));
+    }
+    let mut layout = ShardedLayout {
+        graph_path: graph_path.to_path_buf(),
+        manifest: None,
+        digests: HashMap::new(),
+        stamps: HashMap::new(),
+    };
+    let mut graph = Graph::empty(manifest.root_id.clone());
+    graph.roots = manifest.roots.clone();
+    graph.rules = manifest.rules.clone();
+    for key in &manifest.shards {
+        let path = layout.shard_path(key);
+        let stamp = storage::file_stamp(&path)?;
+        let data = files::read(&path)?;
+        let shard: ShardIn = serde_json::from_slice(&data).map_err(|e| corrupt(&path, e.to_string()))?;
+        graph.nodes.extend(shard.nodes.into_iter().map(|(id, node)| (id, Arc::new(node))));
+        graph.links.extend(shard.links);
+        layout.digests.insert(key.clone(), digest(&data));
+        layout.stamps.insert(key.clone(), stamp);
+    }
+    layout.manifest = Some(manifest);
+    info!(nodes = graph.nodes.len(), shards = layout.digests.len(), "sharded graph loaded");
+    Ok((graph, layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::create_default_graph;
+
+    #[test]
+    fn test_shard_keys_follow_top_level_ancestor() {
+        let mut graph = create_default_graph();
+        let root = Node::clone(&graph.nodes[&graph.root_id]);
+        for (id, parent) in [("work", "root"), ("project", "work"), ("task", "project")] {
+            let node = Node {
+                id: NodeId(id.into()),
+                parent_id: Some(NodeId(parent.into())),
+                ..root.clone()
+            };
+            graph.nodes.insert(node.id.clone(), node.into());
+        }
+
+        let keys = shard_keys(&graph);
+        assert_eq!(keys[&graph.root_id], CORE_SHARD);
+        for id in ["work", "project", "task"] {
+            assert_eq!(keys[&NodeId(id.into())], "subtree-work");
+        }
+    }
+}
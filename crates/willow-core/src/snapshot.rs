@@ -0,0 +1,318 @@
+use crate::error::WillowError;
+use crate::model::Graph;
+use serde_json::{Map, Number, Value};
+
+/// First bytes of a zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Encoding of a whole graph handed to or taken from a host, e.g. for
+/// backups and sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    /// The graph file's JSON.
+    #[default]
+    Json,
+    MessagePack,
+    /// zstd-compressed JSON, as written by the `compress` storage option.
+    Compressed,
+}
+
+impl GraphFormat {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<GraphFormat> {
+        match s {
+            "json" => Some(GraphFormat::Json),
+            "msgpack" => Some(GraphFormat::MessagePack),
+            "compressed" => Some(GraphFormat::Compressed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GraphFormat::Json => "json",
+            GraphFormat::MessagePack => "msgpack",
+            GraphFormat::Compressed => "compressed",
+        }
+    }
+
+    /// Guess the format of encoded data: zstd frames are compressed,
+    /// anything starting like a JSON object is JSON, the rest MessagePack.
+    pub fn detect(data: &[u8]) -> GraphFormat {
+        if data.starts_with(&ZSTD_MAGIC) {
+            GraphFormat::Compressed
+        } else if data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+            GraphFormat::Json
+        } else {
+            GraphFormat::MessagePack
+        }
+    }
+}
+
+pub fn encode(graph: &Graph, format: GraphFormat) -> Result<Vec<u8>, WillowError> {
+    Ok(match format {
+        GraphFormat::Json => serde_json::to_vec(graph)?,
+        GraphFormat::MessagePack => {
+            let mut out = Vec::new();
+            write_value(&mut out, &serde_json::to_value(graph)?);
+            out
+        }
+        GraphFormat::Compressed => zstd::encode_all(serde_json::to_vec(graph)?.as_slice(), 3)?,
+    })
+}
+
+/// Decode a graph encoded by `encode`, detecting the format when not given.
+pub fn decode(data: &[u8], format: Option<GraphFormat>) -> Result<Graph, WillowError> {
+    let format = format.unwrap_or_else(|| GraphFormat::detect(data));
+    let invalid = |reason: String| WillowError::InvalidImport {
+        format: format.as_str().to_string(),
+        reason,
+    };
+    let graph: Graph = match format {
+        GraphFormat::Json => serde_json::from_slice(data).map_err(|e| invalid(e.to_string()))?,
+        GraphFormat::MessagePack => {
+            let mut reader = Reader { data, pos: 0, depth: 0 };
+            let value = reader.value().map_err(invalid)?;
+            if reader.pos != data.len() {
+                return Err(invalid("trailing data".to_string()));
+            }
+            serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?
+        }
+        GraphFormat::Compressed => {
+            let json = zstd::decode_all(data).map_err(|e| invalid(e.to_string()))?;
+            serde_json::from_slice(&json).map_err(|e| invalid(e.to_string()))?
+        }
+    };
+    if !graph.nodes.contains_key(&graph.root_id) {
+        return Err(invalid(format!("root node {} is missing", graph.root_id.0)));
+    }
+    Ok(graph)
+}
+
+// ---- MessagePack ----
+//
+// Only the types JSON has are written or read; binary and extension types
+// are rejected.
+
+fn write_len(out: &mut Vec<u8>, len: usize, fix: (u8, usize), markers: [u8; 3]) {
+    let [m8, m16, m32] = markers;
+    if len < fix.1 {
+        out.push(fix.0 | len as u8);
+    } else if m8 != 0 && len <= u8::MAX as usize {
+        out.extend([m8, len as u8]);
+    } else if len <= u16::MAX as usize {
+        out.push(m16);
+        out.extend((len as u16).to_be_bytes());
+    } else {
+        out.push(m32);
+        out.extend((len as u32).to_be_bytes());
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                match u {
+                    0..=0x7f => out.push(u as u8),
+                    0x80..=0xff => out.extend([0xcc, u as u8]),
+                    0x100..=0xffff => {
+                        out.push(0xcd);
+                        out.extend((u as u16).to_be_bytes());
+                    }
+                    0x1_0000..=0xffff_ffff => {
+                        out.push(0xce);
+                        out.extend((u as u32).to_be_bytes());
+                    }
+                    _ => {
+                        out.push(0xcf);
+                        out.extend(u.to_be_bytes());
+                    }
+                }
+            } else if let Some(i) = n.as_i64() {
+                if i >= -32 {
+                    out.push(i as u8);
+                } else {
+                    out.push(0xd3);
+                    out.extend(i.to_be_bytes());
+                }
+            } else {
+                out.push(0xcb);
+                out.extend(n.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        Value::String(s) => {
+            write_len(out, s.len(), (0xa0, 32), [0xd9, 0xda, 0xdb]);
+            out.extend(s.as_bytes());
+        }
+        Value::Array(items) => {
+            write_len(out, items.len(), (0x90, 16), [0, 0xdc, 0xdd]);
+            for item in items {
+                write_value(out, item);
+            }
+        }
+        Value::Object(map) => {
+            write_len(out, map.len(), (0x80, 16), [0, 0xde, 0xdf]);
+            for (key, item) in map {
+                write_len(out, key.len(), (0xa0, 32), [0xd9, 0xda, 0xdb]);
+                out.extend(key.as_bytes());
+                write_value(out, item);
+            }
+        }
+    }
+}
+
+/// How deeply arrays and maps may nest, as serde_json allows for JSON.
+const MAX_DEPTH: usize = 128;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Check a declared array or map length against the bytes left, each
+    /// item taking at least `item_size`, before anything is allocated.
+    fn check_len(&self, len: usize, item_size: usize) -> Result<(), String> {
+        match len.checked_mul(item_size).is_some_and(|size| size <= self.remaining()) {
+            true => Ok(()),
+            false => Err(format!("length {len} runs past the end of data")),
+        }
+    }
+
+    fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nested more than {MAX_DEPTH} levels deep"));
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len());
+        let end = end.ok_or_else(|| "unexpected end of data".to_string())?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64, String> {
+        Ok(self.take(n)?.iter().fold(0, |acc, b| acc << 8 | u64::from(*b)))
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, String> {
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn array(&mut self, len: usize) -> Result<Value, String> {
+        self.check_len(len, 1)?;
+        self.nested(|r| (0..len).map(|_| r.value()).collect::<Result<_, _>>().map(Value::Array))
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value, String> {
+        self.check_len(len, 2)?;
+        self.nested(|r| {
+            let mut map = Map::new();
+            for _ in 0..len {
+                let Value::String(key) = r.value()? else {
+                    return Err("map key is not a string".to_string());
+                };
+                map.insert(key, r.value()?);
+            }
+            Ok(Value::Object(map))
+        })
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let marker = self.take(1)?[0];
+        Ok(match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map(usize::from(marker & 0x0f))?,
+            0x90..=0x9f => self.array(usize::from(marker & 0x0f))?,
+            0xa0..=0xbf => Value::String(self.string(usize::from(marker & 0x1f))?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xca => {
+                let f = f32::from_bits(self.uint(4)? as u32);
+                Number::from_f64(f64::from(f)).map_or(Value::Null, Value::Number)
+            }
+            0xcb => Number::from_f64(f64::from_bits(self.uint(8)?)).map_or(Value::Null, Value::Number),
+            0xcc => Value::from(self.uint(1)?),
+            0xcd => Value::from(self.uint(2)?),
+            0xce => Value::from(self.uint(4)?),
+            0xcf => Value::from(self.uint(8)?),
+            0xd0 => Value::from(self.uint(1)? as u8 as i8),
+            0xd1 => Value::from(self.uint(2)? as u16 as i16),
+            0xd2 => Value::from(self.uint(4)? as u32 as i32),
+            0xd3 => Value::from(self.uint(8)? as i64),
+            0xd9..=0xdb => {
+                let len = self.uint(1 << (marker - 0xd9))? as usize;
+                Value::String(self.string(len)?)
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(2 << (marker - 0xdc))? as usize;
+                self.array(len)?
+            }
+            0xde | 0xdf => {
+                let len = self.uint(2 << (marker - 0xde))? as usize;
+                self.map(len)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            _ => return Err(format!("unsupported MessagePack type 0x{marker:02x}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::storage::create_default_graph;
+
+    #[test]
+    fn test_formats_round_trip_and_are_detected() {
+        let mut graph = create_default_graph();
+        let root = graph.root_id.clone();
+        let node = graph.nodes.get_mut(&root).map(Arc::make_mut).unwrap();
+        node.content = "A longer root content, past the fixstr limit: ünïcödé".to_string();
+        node.metadata.insert("count".to_string(), "-40".to_string());
+
+        for format in [GraphFormat::Json, GraphFormat::MessagePack, GraphFormat::Compressed] {
+            let data = encode(&graph, format).unwrap();
+            assert_eq!(GraphFormat::detect(&data), format);
+            let decoded = decode(&data, None).unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&graph).unwrap(),
+                "{format:?}"
+            );
+        }
+        assert!(decode(b"{}", None).is_err());
+    }
+
+    #[test]
+    fn test_message_pack_rejects_deep_nesting_and_oversized_lengths() {
+        // Arrays of one array, nested far past the limit.
+        let mut deep = vec![0x91; 100_000];
+        deep.push(0xc0);
+        let err = decode(&deep, Some(GraphFormat::MessagePack)).unwrap_err();
+        assert!(err.to_string().contains("nested more than"), "{err}");
+
+        // An array32 and a map32 claiming four billion items in a few bytes.
+        for data in [&[0xdd, 0xff, 0xff, 0xff, 0xff, 0xc0][..], &[0xdf, 0xff, 0xff, 0xff, 0xff, 0xc0, 0xc0]] {
+            let err = decode(data, Some(GraphFormat::MessagePack)).unwrap_err();
+            assert!(err.to_string().contains("runs past the end"), "{err}");
+        }
+        assert!(decode(&[0x92, 0xc0], Some(GraphFormat::MessagePack)).is_err());
+    }
+}
@@ -1,30 +1,567 @@
 use crate::error::WillowError;
-use crate::model::{Graph, Node, NodeId, NodeType};
+use crate::files;
+use crate::shards;
+use crate::model::{ContentFormat, Graph, Node, NodeId, NodeType, Sensitivity, SupersededValue};
 use chrono::Utc;
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
-use tracing::{info, debug};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(not(target_arch = "wasm32"))]
+use notify::{RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, TryLockError};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::{info, debug, warn};
 
+/// Leading bytes of a zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// SHA-256 of the graph file as last written, e.g. `graph.sha256`.
+const CHECKSUM_SIDECAR: &str = "sha256";
+/// The graph file as it was before the last write, e.g. `graph.bak`, and
+/// its checksum.
+const BACKUP_SIDECAR: &str = "bak";
+const BACKUP_CHECKSUM_SIDECAR: &str = "bak.sha256";
+/// Where a corrupt graph file is set aside by `quarantine`.
+const CORRUPT_SIDECAR: &str = "corrupt";
+/// Nodes' `previous_values` when not inlined, e.g. `graph.history.json`.
+const HISTORY_SIDECAR: &str = "history.json";
+
+/// How a graph file is written. Every style is read back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageOptions {
+    /// zstd-compress the file, whose JSON is then always compact.
+    pub compress: bool,
+    /// Indent the JSON for reading by hand. Compact JSON is smaller and
+    /// much quicker to write for large graphs.
+    pub pretty: bool,
+    /// Write nodes, links and metadata in key order, so an unchanged graph
+    /// saves to the same bytes and diffs stay small. Unordered saves skip
+    /// the sorting.
+    pub sorted_keys: bool,
+    /// Keep nodes' `previous_values` in the graph file rather than in a
+    /// `graph.history.json` sidecar.
+    pub inline_history: bool,
+}
+
+impl Default for StorageOptions {
+    fn default() -> Self {
+        StorageOptions {
+            compress: false,
+            pretty: true,
+            sorted_keys: true,
+            inline_history: true,
+        }
+    }
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn corrupt(path: &Path, reason: impl Into<String>) -> WillowError {
+    WillowError::CorruptGraph {
+        path: path.display().to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Hashes everything read through it, so a file's checksum is checked
+/// while it streams rather than after reading it whole.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Deserialize `file`, plain JSON or zstd-compressed, one record at a time
+/// straight off the disk, and check it against the checksum sidecar
+/// `suffix`. Files written before checksums were kept have none and pass.
+fn read_graph_file<T: DeserializeOwned>(graph_path: &Path, suffix: &str, file: &Path) -> Result<T, WillowError> {
+    let mut reader = BufReader::new(HashingReader {
+        inner: files::open(file)?,
+        hasher: Sha256::new(),
+    });
+    let compressed = reader.fill_buf()?.starts_with(&ZSTD_MAGIC);
+    let parsed = if compressed {
+        zstd::stream::read::Decoder::with_buffer(&mut reader)
+            .map_err(serde_json::Error::io)
+            .and_then(|decoder| serde_json::from_reader(BufReader::new(decoder)))
+    } else {
+        serde_json::from_reader(&mut reader)
+    };
+    // Whatever the parser left unread still counts towards the checksum.
+    io::copy(&mut reader, &mut io::sink())?;
+    let actual = format!("{:x}", reader.into_inner().hasher.finalize());
+    match files::read_to_string(&sidecar_path(graph_path, suffix)) {
+        Ok(expected) if expected.trim() != actual => return Err(corrupt(file, "checksum mismatch")),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    parsed.map_err(|e| corrupt(file, e.to_string()))
+}
+
+/// Load a graph file, plain JSON or zstd-compressed; the format is detected
+/// from its first bytes. A file that fails its checksum, is cut short or
+/// does not parse is reported as `CorruptGraph`. A sharded graph is read
+/// from its shards.
 pub fn load_graph(path: &Path) -> Result<Graph, WillowError> {
     debug!(path = %path.display(), "loading graph");
-    let data = fs::read_to_string(path)?;
-    let graph: Graph = serde_json::from_str(&data)?;
+    if shards::is_sharded(path) {
+        let (mut graph, _) = shards::load(path)?;
+        graph.intern_ids();
+        return Ok(graph);
+    }
+    let mut graph: Graph = read_graph_file(path, CHECKSUM_SIDECAR, path)?;
+    if !SKIP_HISTORY.with(Cell::get) {
+        merge_history_sidecar(path, &mut graph)?;
+    }
+    graph.intern_ids();
     info!(nodes = graph.nodes.len(), links = graph.links.len(), "graph loaded");
     Ok(graph)
 }
 
-pub fn save_graph(path: &Path, graph: &Graph) -> Result<(), WillowError> {
-    debug!(path = %path.display(), "saving graph");
-    let json = serde_json::to_string_pretty(graph)?;
+/// Give the nodes of `graph` their history from the sidecar, if it is not
+/// inlined.
+fn merge_history_sidecar(path: &Path, graph: &mut Graph) -> Result<(), WillowError> {
+    for (id, values) in read_history_sidecar(path)? {
+        if let Some(node) = graph.nodes.get_mut(&id).map(Arc::make_mut) {
+            node.previous_values = values;
+        }
+    }
+    Ok(())
+}
+
+/// The history written beside the graph file when it is not inlined.
+fn read_history_sidecar(path: &Path) -> Result<HashMap<NodeId, Vec<SupersededValue>>, WillowError> {
+    match files::open(&sidecar_path(path, HISTORY_SIDECAR)) {
+        Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+thread_local! {
+    /// Set while `load_graph_deferring_history` runs.
+    static SKIP_HISTORY: Cell<bool> = const { Cell::new(false) };
+    /// The `sorted_keys` and `inline_history` of the `save_graph` running.
+    static SORT_KEYS: Cell<bool> = const { Cell::new(true) };
+    static INLINE_HISTORY: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Whether maps are being serialized in key order; see
+/// `StorageOptions::sorted_keys`.
+pub fn sorting_keys() -> bool {
+    SORT_KEYS.with(Cell::get)
+}
+
+/// Serializer for `Node::previous_values`: empty while `save_graph` writes
+/// history to its sidecar.
+pub fn serialize_previous_values<S: Serializer>(values: &[SupersededValue], serializer: S) -> Result<S::Ok, S::Error> {
+    if INLINE_HISTORY.with(Cell::get) {
+        values.serialize(serializer)
+    } else {
+        serializer.collect_seq(std::iter::empty::<SupersededValue>())
+    }
+}
+
+/// Deserializer for `Node::previous_values`: skipped without being
+/// allocated while `load_graph_deferring_history` runs.
+pub fn previous_values<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SupersededValue>, D::Error> {
+    if SKIP_HISTORY.with(Cell::get) {
+        IgnoredAny::deserialize(deserializer)?;
+        Ok(Vec::new())
+    } else {
+        Vec::deserialize(deserializer)
+    }
+}
+
+/// Load a graph file leaving every node's `previous_values` empty, for
+/// large graphs whose history is rarely read; `load_history` reads it back.
+pub fn load_graph_deferring_history(path: &Path) -> Result<Graph, WillowError> {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SKIP_HISTORY.with(|skip| skip.set(false));
+        }
+    }
+    SKIP_HISTORY.with(|skip| skip.set(true));
+    let _reset = Reset;
+    load_graph(path)
+}
+
+#[derive(Deserialize)]
+struct HistoryRecord {
+    #[serde(default)]
+    previous_values: Vec<SupersededValue>,
+}
+
+#[derive(Deserialize)]
+struct HistoryFile {
+    nodes: HashMap<NodeId, HistoryRecord>,
+}
+
+/// The non-empty `previous_values` of each node in the graph file, read
+/// without keeping the rest of the graph.
+pub fn load_history(path: &Path) -> Result<HashMap<NodeId, Vec<SupersededValue>>, WillowError> {
+    let file: HistoryFile = if shards::is_sharded(path) {
+        let nodes = shards::load(path)?.0.nodes.into_iter();
+        HistoryFile {
+            nodes: nodes
+                .map(|(id, node)| (id, HistoryRecord { previous_values: Arc::unwrap_or_clone(node).previous_values }))
+                .collect(),
+        }
+    } else {
+        read_graph_file(path, CHECKSUM_SIDECAR, path)?
+    };
+    Ok(file
+        .nodes
+        .into_iter()
+        .filter(|(_, record)| !record.previous_values.is_empty())
+        .map(|(id, record)| (id, record.previous_values))
+        .chain(read_history_sidecar(path)?)
+        .collect())
+}
+
+/// Load the backup `save_graph` keeps of the previous graph file, if any,
+/// with the history from the sidecar as `load_graph` would.
+pub fn load_backup(path: &Path) -> Result<Option<Graph>, WillowError> {
+    let backup = sidecar_path(path, BACKUP_SIDECAR);
+    if !files::exists(&backup) {
+        return Ok(None);
+    }
+    let mut graph: Graph = read_graph_file(path, BACKUP_CHECKSUM_SIDECAR, &backup)?;
+    merge_history_sidecar(path, &mut graph)?;
+    graph.intern_ids();
+    Ok(Some(graph))
+}
+
+/// Move a corrupt graph file aside so a recovered graph can take its place,
+/// returning where it went.
+pub fn quarantine(path: &Path) -> Result<PathBuf, WillowError> {
+    let target = sidecar_path(path, CORRUPT_SIDECAR);
+    files::rename(path, &target)?;
+    warn!(path = %path.display(), to = %target.display(), "corrupt graph file set aside");
+    Ok(target)
+}
+
+fn remove_if_present(path: &Path) -> Result<(), WillowError> {
+    match files::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Keep the current graph file and checksum as the backup, by hard link
+/// where the filesystem allows. Both are only ever replaced by rename, so
+/// the links stay intact.
+fn keep_backup(path: &Path) -> Result<(), WillowError> {
+    for (from, to) in [
+        (path.to_path_buf(), sidecar_path(path, BACKUP_SIDECAR)),
+        (sidecar_path(path, CHECKSUM_SIDECAR), sidecar_path(path, BACKUP_CHECKSUM_SIDECAR)),
+    ] {
+        remove_if_present(&to)?;
+        if files::exists(&from) {
+            files::link(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> Result<Vec<u8>, WillowError> {
+    Ok(if pretty {
+        serde_json::to_vec_pretty(value)?
+    } else {
+        serde_json::to_vec(value)?
+    })
+}
+
+/// Write the history sidecar, or remove it when history is inlined.
+fn save_history_sidecar(path: &Path, graph: &Graph, options: &StorageOptions) -> Result<(), WillowError> {
+    let sidecar = sidecar_path(path, HISTORY_SIDECAR);
+    if options.inline_history {
+        return remove_if_present(&sidecar);
+    }
+    let history: BTreeMap<&NodeId, &Vec<SupersededValue>> = graph
+        .nodes
+        .iter()
+        .filter(|(_, node)| !node.previous_values.is_empty())
+        .map(|(id, node)| (id, &node.previous_values))
+        .collect();
+    let tmp_path = sidecar_path(path, "history.tmp");
+    files::write(&tmp_path, to_json(&history, options.pretty && !options.compress)?)?;
+    files::rename(&tmp_path, &sidecar)?;
+    Ok(())
+}
+
+/// Write a graph file atomically in the style of `options`, keeping the
+/// previous file as a backup. The new checksum lands before the new file,
+/// so a crash in between shows as a mismatch and the backup, still
+/// matching its own checksum, is used.
+pub fn save_graph(path: &Path, graph: &Graph, options: &StorageOptions) -> Result<(), WillowError> {
+    debug!(path = %path.display(), ?options, "saving graph");
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SORT_KEYS.with(|sort| sort.set(true));
+            INLINE_HISTORY.with(|inline| inline.set(true));
+        }
+    }
+    SORT_KEYS.with(|sort| sort.set(options.sorted_keys));
+    INLINE_HISTORY.with(|inline| inline.set(options.inline_history));
+    let _reset = Reset;
+
+    let data = if options.compress {
+        zstd::encode_all(serde_json::to_vec(graph)?.as_slice(), 3).map_err(WillowError::Io)?
+    } else {
+        to_json(graph, options.pretty)?
+    };
+    save_history_sidecar(path, graph, options)?;
     let tmp_path = path.with_extension("tmp");
-    fs::write(&tmp_path, &json)?;
-    fs::rename(&tmp_path, path)?;
+    files::write(&tmp_path, &data)?;
+    if files::exists(path) {
+        keep_backup(path)?;
+    }
+    let checksum_path = sidecar_path(path, CHECKSUM_SIDECAR);
+    let checksum_tmp = sidecar_path(path, "sha256.tmp");
+    files::write(&checksum_tmp, digest(&data))?;
+    files::rename(&checksum_tmp, &checksum_path)?;
+    files::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// How a graph file is locked against other processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Sole access, for a process that writes the graph.
+    Exclusive,
+    /// Access alongside other shared holders, for processes that only read.
+    /// Not enforced: a shared holder that writes can still clobber another.
+    Shared,
+}
+
+impl LockMode {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<LockMode> {
+        match s {
+            "exclusive" => Some(LockMode::Exclusive),
+            "shared" => Some(LockMode::Shared),
+            _ => None,
+        }
+    }
+}
+
+/// An advisory lock on a graph file, released when dropped. It is taken on
+/// a `.lock` sidecar, since saving replaces the graph file itself. Graphs
+/// in mounted files belong to this process alone and are not locked.
+#[derive(Debug)]
+pub struct GraphLock {
+    _file: Option<File>,
+}
+
+/// Lock the graph at `graph_path` without waiting, failing with
+/// `GraphLocked` if another process holds a conflicting lock.
+pub fn lock_graph(graph_path: &Path, mode: LockMode) -> Result<GraphLock, WillowError> {
+    if files::is_mounted(graph_path) {
+        return Ok(GraphLock { _file: None });
+    }
+    let path = sidecar_path(graph_path, "lock");
+    let file = File::options().create(true).truncate(false).write(true).open(&path)?;
+    let locked = match mode {
+        LockMode::Exclusive => file.try_lock(),
+        LockMode::Shared => file.try_lock_shared(),
+    };
+    match locked {
+        Ok(()) => {
+            debug!(path = %path.display(), ?mode, "graph locked");
+            Ok(GraphLock { _file: Some(file) })
+        }
+        Err(TryLockError::WouldBlock) => Err(WillowError::GraphLocked(graph_path.display().to_string())),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Modification time and size of a graph file, compared to notice writes by
+/// other processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+/// The stamp of the file at `path`, or `None` if there is no file.
+pub fn file_stamp(path: &Path) -> Result<Option<FileStamp>, WillowError> {
+    match files::stat(path) {
+        Ok(stat) => Ok(Some(FileStamp {
+            modified: stat.modified,
+            len: stat.len,
+        })),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The stamp a store last loaded or saved, shared with its watchers.
+pub type KnownStamp = Arc<Mutex<Option<FileStamp>>>;
+
+/// Watches a graph file and calls `on_change` once for each new version
+/// that differs from the known stamp, i.e. each write by another process.
+/// The file's directory is watched rather than the file, as saves rename a
+/// new file over it. Stops when dropped.
+pub struct FileWatcher {
+    stop: Arc<AtomicBool>,
+    _watcher: Box<dyn Send>,
+}
+
+impl FileWatcher {
+    /// Watch with the platform's file events, or where those are
+    /// unavailable, e.g. on some network mounts, by polling every `interval`.
+    /// Mounted files have no one else to change them and are not watched.
+    pub fn spawn(
+        path: PathBuf,
+        known: KnownStamp,
+        interval: Duration,
+        on_change: impl Fn() + Send + 'static,
+    ) -> Result<Self, WillowError> {
+        if files::is_mounted(&path) {
+            return Err(WillowError::FileWatch(format!("{} is not on disk", path.display())));
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let notified = Mutex::new((None, on_change));
+        let handler = move |event: Result<Vec<PathBuf>, String>| {
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            match event {
+                Ok(paths) if paths.iter().any(|p| p.file_name() == path.file_name()) => {}
+                Ok(_) => return,
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "file watch error");
+                    return;
+                }
+            }
+            // Holding the known stamp waits out a save by this process, so
+            // its own write is never reported.
+            let known = *known.lock().unwrap_or_else(|e| e.into_inner());
+            let Ok(current) = file_stamp(&path) else { return };
+            let mut notified = notified.lock().unwrap_or_else(|e| e.into_inner());
+            if current != known && current != notified.0 {
+                debug!(path = %path.display(), "graph file changed externally");
+                notified.0 = current;
+                (notified.1)();
+            }
+        };
+        Ok(FileWatcher {
+            stop,
+            _watcher: watch_dir(&dir, interval, handler)?,
+        })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// The flag that stops this watcher, for stopping it from elsewhere.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn watch_error(e: notify::Error) -> WillowError {
+    WillowError::FileWatch(e.to_string())
+}
+
+/// A watcher sending the paths of `dir`'s events to `handler`: the
+/// platform's own if it can watch `dir`, otherwise one polling every
+/// `interval`. Fails if `dir` does not exist.
+#[cfg(not(target_arch = "wasm32"))]
+fn watch_dir(
+    dir: &Path,
+    interval: Duration,
+    handler: impl Fn(Result<Vec<PathBuf>, String>) + Send + Sync + 'static,
+) -> Result<Box<dyn Send>, WillowError> {
+    let handler = Arc::new(move |event: notify::Result<notify::Event>| {
+        handler(event.map(|e| e.paths).map_err(|e| e.to_string()))
+    });
+    let events = handler.clone();
+    let native = notify::recommended_watcher(move |event| events(event))
+        .and_then(|mut watcher| watcher.watch(dir, RecursiveMode::NonRecursive).map(|()| watcher));
+    match native {
+        Ok(watcher) => return Ok(Box::new(watcher)),
+        // Polling would not find it either.
+        Err(e) if matches!(e.kind, notify::ErrorKind::PathNotFound) => return Err(watch_error(e)),
+        Err(e) => debug!(dir = %dir.display(), error = %e, "file events unavailable, polling"),
+    }
+    let config = notify::Config::default().with_poll_interval(interval);
+    let mut watcher = notify::PollWatcher::new(move |event| handler(event), config).map_err(watch_error)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive).map_err(watch_error)?;
+    Ok(Box::new(watcher))
+}
+
+/// Browsers have no file system to watch.
+#[cfg(target_arch = "wasm32")]
+fn watch_dir(
+    dir: &Path,
+    _interval: Duration,
+    _handler: impl Fn(Result<Vec<PathBuf>, String>) + Send + Sync + 'static,
+) -> Result<Box<dyn Send>, WillowError> {
+    Err(WillowError::FileWatch(format!("cannot watch {} in this build", dir.display())))
+}
+
+/// Path of a sidecar file stored next to the graph, e.g. `graph.schema.json`.
+fn sidecar_path(graph_path: &Path, suffix: &str) -> PathBuf {
+    let stem = graph_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("graph");
+    graph_path.with_file_name(format!("{stem}.{suffix}"))
+}
+
+/// Load a JSON sidecar (schema, relation registry, ...), defaulting when absent.
+pub fn load_sidecar<T: DeserializeOwned + Default>(
+    graph_path: &Path,
+    suffix: &str,
+) -> Result<T, WillowError> {
+    let path = sidecar_path(graph_path, suffix);
+    if !files::exists(&path) {
+        return Ok(T::default());
+    }
+    let data = files::read_to_string(&path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+pub fn save_sidecar<T: Serialize>(graph_path: &Path, suffix: &str, value: &T) -> Result<(), WillowError> {
+    let path = sidecar_path(graph_path, suffix);
+    files::write(&path, serde_json::to_string_pretty(value)?)?;
     Ok(())
 }
 
 pub fn create_default_graph() -> Graph {
-    let root_id = NodeId("root".to_string());
+    let root_id = NodeId("root".into());
     let now = Utc::now();
 
     let root = Node {
@@ -33,19 +570,30 @@ pub fn create_default_graph() -> Graph {
         content: "User".to_string(),
         parent_id: None,
         children: Vec::new(),
+        extra_parents: Vec::new(),
+        pinned: false,
+        sensitivity: Sensitivity::Normal,
+        archived: false,
+        display: None,
+        content_format: ContentFormat::Text,
+        attachments: Vec::new(),
         metadata: HashMap::new(),
         previous_values: Vec::new(),
         temporal: None,
         created_at: now,
         updated_at: now,
+        created_by: None,
+        updated_by: None,
     };
 
     let mut nodes = HashMap::new();
-    nodes.insert(root_id.clone(), root);
+    nodes.insert(root_id.clone(), Arc::new(root));
 
     Graph {
         root_id,
         nodes,
         links: HashMap::new(),
+        roots: BTreeMap::new(),
+        rules: Vec::new(),
     }
 }
@@ -1,60 +1,978 @@
+use crate::analysis::TextAnalysis;
+use crate::attachments::{self, BlobStore};
+use crate::audit::{self, AuditEntry, AuditQuery};
+use crate::csv_export;
+use crate::dedupe::{self, DuplicatePair};
 use crate::error::WillowError;
+use crate::events::{self, StoreEvent};
+use crate::files;
+use crate::import::{self, Outline};
+use crate::integrity::{self, IntegrityIssue, RepairReport};
+use crate::journal::{self, JournalEntry};
+use crate::limits::Limits;
+use crate::maintenance::{JobRun, MaintenanceJob, MaintenanceReport, MaintenanceSchedule};
+use crate::link_index::LinkIndex;
 use crate::model::*;
-use crate::search;
-use crate::storage;
+use crate::perf::{MemoryFootprint, PerfReport, PerfStats};
+use crate::progress::Progress;
+use crate::query::{LinkFilter, LinkPage, NodeFilter, NodePage, NodeSort, Page};
+use crate::relations::{RelationRegistry, RelationUsage};
+use crate::retention::RetentionPolicy;
+use crate::rules::{self, Rule, RulesReport};
+use crate::schema::MetadataSchema;
+use crate::search::{self, LinkSearchResult, RankingBoosts, SearchOptions, SearchPage};
+use crate::search_index::{self, SearchIndex};
+use crate::shards::{self, ShardedLayout};
+use crate::snapshot::{self, GraphFormat};
+use crate::suggest::{PrefixIndex, Suggestion};
+use crate::storage::{self, FileWatcher, GraphLock, KnownStamp, LockMode, StorageOptions};
+use crate::temporal::{self, ExpiryPolicy, SweepReport};
 use crate::vcs::repository::Repository;
-use crate::vcs::types::{Change, CommitInput};
-use chrono::Utc;
-use std::collections::HashMap;
+use crate::vector::{self, EmbeddingIndex, EmbeddingProvider};
+use crate::vcs::diff::{compute_graph_diff, ChangeSummary};
+use crate::webhooks::{self, WebhookEvent, WebhookPayload};
+use crate::vcs::types::{
+    apply_delta, apply_recorded, child_positions, invert_delta, Change, CommitInput, CommitSource, Delta, NodeStamp,
+};
+use chrono::{DateTime, Utc};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError, TryLockError, Weak};
+use std::time::Duration;
+use web_time::Instant;
 use uuid::Uuid;
-use tracing::{info, debug};
+use tracing::{info, debug, warn};
+
+/// Maximum number of operations kept on the undo stack.
+const UNDO_LIMIT: usize = 100;
+
+const SCHEMA_SIDECAR: &str = "schema.json";
+const LIMITS_SIDECAR: &str = "limits.json";
+const RELATIONS_SIDECAR: &str = "relations.json";
+const RETENTION_SIDECAR: &str = "retention.json";
+const EMBEDDINGS_SIDECAR: &str = "embeddings.json";
+const ANALYSIS_SIDECAR: &str = "analysis.json";
+const RANKING_SIDECAR: &str = "ranking.json";
+const SUPERSEDED_BY: &str = "superseded_by";
+/// Most linked neighbors considered by `build_context`.
+const CONTEXT_NEIGHBOR_LIMIT: usize = 10;
+
+/// A node for `create_nodes`, with any children to create beneath it.
+#[derive(Debug, Clone, Default)]
+pub struct NewNode {
+    pub node_type: String,
+    pub content: String,
+    /// `markdown` or a structured format; plain text when `None`.
+    pub format: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub temporal: Option<TemporalMetadata>,
+    pub children: Vec<NewNode>,
+}
+
+/// What an import created.
+#[derive(Debug, Clone)]
+pub struct ImportReport {
+    /// Top-level imported nodes, now children of the target parent.
+    pub root_ids: Vec<NodeId>,
+    pub nodes: usize,
+    pub links: usize,
+    /// The commit recording the import, when VCS is initialized.
+    pub commit: Option<crate::vcs::types::CommitHash>,
+}
 
+/// Nodes are shared with the graph they were read from rather than copied,
+/// so assembling a context allocates little beyond the result vectors.
 pub struct ContextResult {
+    pub node: Arc<Node>,
+    pub ancestors: Vec<Arc<Node>>,
+    pub descendants: Vec<Arc<Node>>,
+    pub links: Vec<Link>,
+    /// Descendants were cut off at `ContextOptions::max_descendants`.
+    pub truncated: bool,
+}
+
+/// Optional filters for `get_context`. The default returns everything.
+#[derive(Debug, Clone, Default)]
+pub struct ContextOptions {
+    /// Only return descendants of these types. Other descendants are still
+    /// traversed, so a filtered type nested under an excluded one is found.
+    pub node_types: Option<Vec<NodeType>>,
+    /// Also return archived descendants, which are skipped with their
+    /// subtrees by default. See `temporal::is_archived`.
+    pub include_archived: bool,
+    pub max_descendants: Option<usize>,
+    /// Clear `previous_values` on every returned node.
+    pub omit_previous_values: bool,
+    /// Skip descendants above this level, with their subtrees.
+    pub max_sensitivity: Sensitivity,
+}
+
+/// How `GraphStore::open` opens a graph file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenOptions {
+    /// How the graph file is written: compressed, pretty, key-ordered and
+    /// with inline history, or not.
+    pub storage: StorageOptions,
+    /// Lock the graph file against other processes for the store's
+    /// lifetime; unlocked by default.
+    pub lock: Option<LockMode>,
+    /// Append each operation to a journal next to the graph file instead of
+    /// rewriting the whole file, compacting the journal into the file after
+    /// this many entries and when the store is dropped. A journal left by
+    /// an earlier store is replayed on open either way.
+    pub journal: Option<usize>,
+    /// Leave nodes' `previous_values` on disk until something needs them,
+    /// to open very large graphs faster and in less memory. Until then,
+    /// nodes carry only values superseded since opening; `node_history`
+    /// reads the full list, and saves merge the rest back in from the file.
+    /// Deletes, content edits, commits and `prune_history` load it all, so
+    /// their undo entries restore the whole list. Ignored with a
+    /// journal, whose entries hold whole nodes, and for sharded graphs.
+    pub defer_history: bool,
+    /// Store each top-level subtree in its own file under `graph.shards/`,
+    /// with the graph file as their index, so a save rewrites only the
+    /// subtrees that changed and processes editing different subtrees do
+    /// not clobber each other. A graph already sharded opens sharded either
+    /// way. Sharded graphs ignore `storage`, are not backed up or
+    /// recovered, and `changed_on_disk` sees only changes to the index.
+    pub sharded: bool,
+    /// Never write: the graph must exist and is not recovered, converted
+    /// or compacted, a journal is replayed in memory only, and every write
+    /// fails with `ReadOnly`.
+    pub read_only: bool,
+    /// Hold saves back so a burst of operations is written to disk once,
+    /// rather than the whole file after each. Ignored with a journal.
+    pub write_behind: Option<WriteBehind>,
+    /// Append every mutating call, with its actor and the ids it touched,
+    /// to an audit log next to the graph file; see `GraphStore::audit_log`.
+    pub audit: bool,
+}
+
+/// When saves held back by `OpenOptions::write_behind` reach disk: once
+/// operations pause for `debounce`, or `max_delay` after the first unsaved
+/// one however busy the store stays. Stores from `open_shared` check in the
+/// background; others write on the next operation past `max_delay` or on
+/// `flush_if_due`. `flush`, `close` and dropping the store write at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBehind {
+    pub debounce: Duration,
+    pub max_delay: Duration,
+}
+
+/// A node reached while following links, with its distance in hops.
+pub struct Neighbor {
+    pub node: Node,
+    pub hops: u32,
+}
+
+pub struct NeighborhoodResult {
+    pub node: Node,
+    pub neighbors: Vec<Neighbor>,
+    /// Links whose endpoints are both within the neighborhood.
+    pub links: Vec<Link>,
+}
+
+/// Why a node is part of an assembled context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextRole {
+    Requested,
+    Ancestor,
+    Neighbor,
+}
+
+impl ContextRole {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContextRole::Requested => "requested",
+            ContextRole::Ancestor => "ancestor",
+            ContextRole::Neighbor => "neighbor",
+        }
+    }
+}
+
+pub struct ContextEntry {
     pub node: Node,
-    pub ancestors: Vec<Node>,
-    pub descendants: Vec<Node>,
+    pub role: ContextRole,
+    /// Hops from the nearest requested node.
+    pub depth: u32,
+}
+
+pub struct AssembledContext {
+    pub entries: Vec<ContextEntry>,
+    /// Links whose endpoints are both included.
     pub links: Vec<Link>,
+    /// Characters of content included.
+    pub used_chars: usize,
+    /// Candidates dropped to stay within the budget.
+    pub omitted: usize,
+}
+
+/// State captured when a transaction begins, restored on rollback.
+struct TransactionState {
+    /// The session that began it, the only one that may write until it ends.
+    owner: u64,
+    backup: Graph,
+    history_deferred: bool,
+    pending_len: usize,
+    undo_stack: VecDeque<Vec<Change>>,
+    redo_stack: Vec<Vec<Change>>,
+}
+
+/// Replace a corrupt graph file with the backup kept by the last save or,
+/// failing that, the latest commit, setting the corrupt file aside. Returns
+/// the recovered graph and a warning saying what was lost.
+fn recover_graph(
+    path: &Path,
+    reason: &str,
+    repo: Option<&Repository>,
+    options: &StorageOptions,
+) -> Result<(Graph, String), WillowError> {
+    let backup = storage::load_backup(path).unwrap_or_else(|e| {
+        warn!(error = %e, "graph backup unusable");
+        None
+    });
+    let (graph, source) = match backup {
+        Some(graph) => (graph, "the backup of the previous save".to_string()),
+        None => {
+            let head = match repo {
+                Some(repo) => repo.log(Some(1))?.into_iter().next(),
+                None => None,
+            };
+            let Some(head) = head else {
+                return Err(WillowError::CorruptGraph {
+                    path: path.display().to_string(),
+                    reason: format!("{reason}; no backup or commit to recover from"),
+                });
+            };
+            (repo.unwrap().reconstruct_at(&head.hash)?, format!("commit {}", head.hash.0))
+        }
+    };
+    let moved = storage::quarantine(path)?;
+    storage::save_graph(path, &graph, options)?;
+    let warning = format!(
+        "Graph file was corrupt ({reason}); recovered from {source}. The corrupt file was kept at {}",
+        moved.display()
+    );
+    warn!(path = %path.display(), "{warning}");
+    Ok((graph, warning))
+}
+
+/// The key `open_shared` files a store under: the canonical path, or for
+/// a graph not created yet its canonical directory joined with its name.
+fn canonical_graph_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| {
+        let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        match (dir.canonicalize(), path.file_name()) {
+            (Ok(dir), Some(name)) => dir.join(name),
+            _ => path.to_path_buf(),
+        }
+    })
+}
+
+/// A store shared by every `open_shared` caller in the process.
+type SharedStore = Arc<Mutex<GraphStore>>;
+
+/// Stores opened with `open_shared`, by canonical graph path.
+static SHARED_STORES: LazyLock<Mutex<HashMap<PathBuf, Weak<Mutex<GraphStore>>>>> = LazyLock::new(Default::default);
+
+/// Ids for the sessions of `StoreHandle`s; a store's own session is 0.
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+/// Lock a shared store, carrying on past a panic in another holder.
+fn lock_shared(store: &SharedStore) -> MutexGuard<'_, GraphStore> {
+    store.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What belongs to one caller of a store rather than to its graph: who
+/// changes are attributed to, the undo history, whether it may write and
+/// whether it has been closed. A `StoreHandle` keeps its own and swaps it
+/// into the shared store while it holds the lock.
+#[derive(Default)]
+struct Session {
+    id: u64,
+    /// Attributed to every node and change made until it is changed.
+    actor: Option<String>,
+    undo_stack: VecDeque<Vec<Change>>,
+    redo_stack: Vec<Vec<Change>>,
+    read_only: bool,
+    closed: bool,
+    /// Ids from `subscribe`, dropped when the session ends.
+    subscriptions: Vec<u32>,
+}
+
+/// One caller's handle on a store from `open_shared`. The graph, history
+/// and settings are shared by every handle on the file; the actor, undo
+/// history and any transaction are the handle's own. While one handle
+/// has a transaction open, writes through the others fail with
+/// `TransactionActive`. Dropping a handle rolls back its transaction and
+/// closes it.
+pub struct StoreHandle {
+    store: SharedStore,
+    session: Arc<Mutex<Session>>,
+}
+
+/// A locked shared store with a handle's session in place; see
+/// `StoreHandle::lock`.
+pub struct SessionGuard<'a> {
+    store: MutexGuard<'a, GraphStore>,
+    session: MutexGuard<'a, Session>,
+}
+
+impl std::ops::Deref for SessionGuard<'_> {
+    type Target = GraphStore;
+
+    fn deref(&self) -> &GraphStore {
+        &self.store
+    }
+}
+
+impl std::ops::DerefMut for SessionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut GraphStore {
+        &mut self.store
+    }
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        std::mem::swap(&mut self.store.session, &mut self.session);
+    }
+}
+
+impl StoreHandle {
+    /// A handle with a new session on `store`, which `open` holds locked.
+    fn new(store: &SharedStore, open: &mut GraphStore, read_only: bool) -> Self {
+        let session = Arc::new(Mutex::new(Session {
+            id: NEXT_SESSION.fetch_add(1, Ordering::Relaxed),
+            read_only,
+            ..Session::default()
+        }));
+        open.handles += 1;
+        open.sessions.retain(|s| s.strong_count() > 0);
+        open.sessions.push(Arc::downgrade(&session));
+        StoreHandle { store: store.clone(), session }
+    }
+
+    /// Lock the store for this handle, carrying on past a panic in another
+    /// holder. The store is locked before the session, so the holder of
+    /// the store can always look into other handles' sessions.
+    pub fn lock(&self) -> SessionGuard<'_> {
+        let mut store = lock_shared(&self.store);
+        let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::swap(&mut store.session, &mut session);
+        SessionGuard { store, session }
+    }
+
+    /// Like `lock`, failing unless this handle may write right now. The
+    /// check is made under the lock the write then uses, so no other
+    /// handle can begin a transaction or close the store in between.
+    pub fn write(&self) -> Result<SessionGuard<'_>, WillowError> {
+        let store = self.lock();
+        store.require_writable()?;
+        Ok(store)
+    }
+
+    /// Close this handle: its subscriptions end and its later calls fail
+    /// with `StoreClosed`. The store itself is closed, as by
+    /// `GraphStore::close`, with its last open handle. Fails while this
+    /// handle has a transaction open; closing twice does nothing.
+    pub fn close(&self) -> Result<(), WillowError> {
+        self.lock().end_session()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().is_closed()
+    }
+}
+
+impl Drop for StoreHandle {
+    fn drop(&mut self) {
+        let mut store = self.lock();
+        if store.is_closed() {
+            return;
+        }
+        if store.in_transaction() {
+            if let Err(e) = store.rollback_transaction() {
+                warn!(error = %e, "transaction of a dropped handle not rolled back");
+            }
+        }
+        if let Err(e) = store.end_session() {
+            warn!(error = %e, "store handle not closed cleanly");
+        }
+    }
+}
+
+/// Write `store`'s held-back saves as their `WriteBehind` windows pass,
+/// until it is closed or dropped.
+fn spawn_flusher(store: Weak<Mutex<GraphStore>>, write_behind: WriteBehind) {
+    let tick = (write_behind.debounce.min(write_behind.max_delay) / 2).max(Duration::from_millis(5));
+    std::thread::spawn(move || loop {
+        std::thread::sleep(tick);
+        let Some(store) = store.upgrade() else { break };
+        let mut store = lock_shared(&store);
+        if store.is_closed() {
+            break;
+        }
+        if let Err(e) = store.flush_if_due() {
+            warn!(error = %e, "held-back save not written");
+        }
+    });
 }
 
+/// Called with each event and the graph as it is after it.
+type Subscriber = Box<dyn FnMut(&StoreEvent, &Graph) + Send>;
+
 pub struct GraphStore {
     pub graph: Graph,
     pub path: PathBuf,
+    /// How the graph file is written.
+    storage: StorageOptions,
+    /// Held until the store is dropped.
+    _lock: Option<GraphLock>,
+    /// Stop flags of the watchers started by `watch`, set by `close`.
+    watchers: Vec<Arc<AtomicBool>>,
+    closed: bool,
+    /// The graph file as this store last loaded or saved it.
+    disk_stamp: KnownStamp,
+    /// Entries a journal may reach before compaction; `None` saves the
+    /// whole graph after every operation.
+    journal_limit: Option<usize>,
+    /// Entries appended since the graph file was last written.
+    journal_len: Cell<usize>,
+    write_behind: Option<WriteBehind>,
+    /// When the first and the latest save held back by `write_behind`
+    /// were asked for; `None` once the graph file is current.
+    unsaved: Option<(Instant, Instant)>,
+    read_only: bool,
+    /// See `OpenOptions::audit`.
+    audit: bool,
+    /// Set when `open` found the graph file corrupt and recovered it.
+    recovery: Option<String>,
+    /// Whether nodes' older `previous_values` are still only in the graph
+    /// file; see `OpenOptions::defer_history`.
+    history_deferred: bool,
+    /// Set for the sharded layout; see `OpenOptions::sharded`.
+    shards: Option<ShardedLayout>,
     pub repo: Option<Repository>,
+    pub schema: MetadataSchema,
+    pub relations: RelationRegistry,
+    pub limits: Limits,
+    pub retention: RetentionPolicy,
+    /// Stemming and synonyms for searches that do not set their own.
+    pub analysis: TextAnalysis,
+    /// Recency and node type boosts for searches that do not set their own.
+    pub ranking: RankingBoosts,
+    embeddings: EmbeddingIndex,
+    indexes: Vec<Box<dyn SearchIndex>>,
+    /// Callbacks registered with `subscribe`, by subscription id.
+    subscribers: Vec<(u32, Subscriber)>,
+    next_subscription: u32,
+    /// Content words and tags for `suggest`.
+    prefixes: PrefixIndex,
+    /// Each node's links, for lookups that would otherwise scan them all.
+    links_by_node: LinkIndex,
+    /// The actor and undo history of whoever holds the store.
+    session: Session,
+    /// Open handles from `open_shared`; the last to close closes the store.
+    handles: usize,
+    /// The sessions of those handles, for what their undo history holds.
+    sessions: Vec<Weak<Mutex<Session>>>,
+    /// What the store was opened with, which later `open_shared` calls
+    /// must match.
+    options: OpenOptions,
+    /// Profile that unscoped search and context of the root operate on.
+    profile: String,
+    blobs: BlobStore,
     pending_changes: Vec<Change>,
+    /// `pending_changes` lead from HEAD to the graph. Not known after the
+    /// graph is loaded from disk, where it may hold uncommitted work from
+    /// an earlier session or another process.
+    pending_from_head: bool,
+    transaction: Option<TransactionState>,
+    /// Timings of the main operations, for `perf_report`.
+    perf: PerfStats,
 }
 
 impl GraphStore {
-    pub fn open(path: &Path) -> Result<Self, WillowError> {
-        let graph = if path.exists() {
-            storage::load_graph(path)?
+    pub fn open(path: &Path, options: &OpenOptions) -> Result<Self, WillowError> {
+        if options.read_only && !files::exists(path) {
+            let missing = std::io::Error::new(std::io::ErrorKind::NotFound, path.display().to_string());
+            return Err(WillowError::Io(missing));
+        }
+        let perf = PerfStats::default();
+        let _timer = perf.time("open");
+        if let Some(parent) = path.parent() {
+            files::create_dir_all(parent)?;
+        }
+        let lock = options.lock.map(|mode| storage::lock_graph(path, mode)).transpose()?;
+        let repo = path.parent().and_then(|p| Repository::open(p).ok());
+        let mut recovery = None;
+        let sharded = (options.sharded && !options.read_only) || shards::is_sharded(path);
+        let mut history_deferred = options.defer_history
+            && options.journal.is_none()
+            && !sharded
+            && !files::exists(&journal::journal_path(path));
+        let mut layout = None;
+        let mut graph = if shards::is_sharded(path) {
+            let (graph, loaded) = shards::load(path)?;
+            layout = Some(loaded);
+            graph
+        } else if files::exists(path) {
+            let loaded = if history_deferred {
+                storage::load_graph_deferring_history(path)
+            } else {
+                storage::load_graph(path)
+            };
+            match loaded {
+                Ok(graph) => graph,
+                Err(WillowError::CorruptGraph { reason, .. }) if !options.read_only => {
+                    let (graph, warning) = recover_graph(path, &reason, repo.as_ref(), &options.storage)?;
+                    recovery = Some(warning);
+                    history_deferred = false;
+                    graph
+                }
+                Err(e) => return Err(e),
+            }
         } else {
             let graph = storage::create_default_graph();
-            if let Some(parent) = path.parent() {
-                std::fs::create_dir_all(parent)?;
-            }
-            storage::save_graph(path, &graph)?;
+            storage::save_graph(path, &graph, &options.storage)?;
             graph
         };
-
-        let repo = path.parent().and_then(|p| Repository::open(p).ok());
+        let mut journal_len = journal::replay(path, &mut graph)?;
+        let compact =
+            !options.read_only && journal_len > 0 && options.journal.is_none_or(|limit| journal_len >= limit);
+        if sharded && layout.is_none() {
+            info!(path = %path.display(), "converting graph to sharded layout");
+            layout = Some(ShardedLayout::create(path)?);
+        }
+        if let Some(layout) = layout.as_mut().filter(|l| compact || !l.is_saved()) {
+            layout.save(&graph)?;
+        } else if compact {
+            storage::save_graph(path, &graph, &options.storage)?;
+        }
+        if compact {
+            journal::clear(path)?;
+            journal_len = 0;
+        }
+        let disk_stamp = Arc::new(Mutex::new(storage::file_stamp(path)?));
+
+        let schema = storage::load_sidecar(path, SCHEMA_SIDECAR)?;
+        let relations = storage::load_sidecar(path, RELATIONS_SIDECAR)?;
+        let limits = storage::load_sidecar(path, LIMITS_SIDECAR)?;
+        let retention = storage::load_sidecar(path, RETENTION_SIDECAR)?;
+        let analysis = storage::load_sidecar(path, ANALYSIS_SIDECAR)?;
+        let ranking = storage::load_sidecar(path, RANKING_SIDECAR)?;
+        let embeddings = storage::load_sidecar(path, EMBEDDINGS_SIDECAR)?;
+        let blobs = BlobStore::new(path.parent().unwrap_or(Path::new(".")));
+        let prefixes = PrefixIndex::build(&graph);
+        let links_by_node = LinkIndex::build(&graph);
 
         info!(path = %path.display(), nodes = graph.nodes.len(), vcs = repo.is_some(), "store opened");
         Ok(GraphStore {
             graph,
             path: path.to_path_buf(),
+            storage: options.storage,
+            _lock: lock,
+            watchers: Vec::new(),
+            closed: false,
+            disk_stamp,
+            journal_limit: options.journal,
+            write_behind: options.write_behind,
+            unsaved: None,
+            journal_len: Cell::new(journal_len),
+            read_only: options.read_only,
+            audit: options.audit && !options.read_only,
+            recovery,
+            history_deferred,
+            shards: layout,
             repo,
+            schema,
+            relations,
+            limits,
+            retention,
+            analysis,
+            ranking,
+            embeddings,
+            indexes: Vec::new(),
+            subscribers: Vec::new(),
+            next_subscription: 0,
+            prefixes,
+            links_by_node,
+            session: Session::default(),
+            handles: 0,
+            sessions: Vec::new(),
+            options: options.clone(),
+            profile: DEFAULT_PROFILE.to_string(),
+            blobs,
             pending_changes: Vec::new(),
+            pending_from_head: false,
+            transaction: None,
+            perf,
         })
     }
 
-    fn save(&self) -> Result<(), WillowError> {
-        storage::save_graph(&self.path, &self.graph)
+    /// How long the main operations have taken since opening, and roughly
+    /// how much memory the graph holds, for diagnosing slow graphs.
+    pub fn perf_report(&self) -> PerfReport {
+        PerfReport {
+            operations: self.perf.timings(),
+            memory: MemoryFootprint::of(&self.graph),
+            pending_changes: self.pending_changes.len(),
+        }
+    }
+
+    /// What `open` recovered from, if it found the graph file corrupt.
+    pub fn recovery_warning(&self) -> Option<&str> {
+        self.recovery.as_deref()
+    }
+
+    /// Persist the graph after an operation. In journal mode the operation
+    /// is appended by `record_changes` instead, and with write-behind it is
+    /// only noted until its `WriteBehind::max_delay` has passed.
+    fn save(&mut self) -> Result<(), WillowError> {
+        if self.journal_limit.is_some() {
+            return Ok(());
+        }
+        if let Some(write_behind) = self.write_behind {
+            self.require_writable()?;
+            let now = Instant::now();
+            let first = self.unsaved.map_or(now, |(first, _)| first);
+            self.unsaved = Some((first, now));
+            if now.duration_since(first) < write_behind.max_delay {
+                return Ok(());
+            }
+        }
+        self.write_graph()
+    }
+
+    /// Write saves held back by write-behind once their window has passed.
+    /// Returns whether it wrote.
+    pub fn flush_if_due(&mut self) -> Result<bool, WillowError> {
+        let (Some(write_behind), Some((first, last))) = (self.write_behind, self.unsaved) else {
+            return Ok(false);
+        };
+        if last.elapsed() < write_behind.debounce && first.elapsed() < write_behind.max_delay {
+            return Ok(false);
+        }
+        self.write_graph()?;
+        Ok(true)
+    }
+
+    /// Write saves held back by write-behind now.
+    pub fn flush(&mut self) -> Result<(), WillowError> {
+        if self.unsaved.is_some() {
+            self.write_graph()?;
+        }
+        Ok(())
+    }
+
+    /// Write the whole graph file and empty the journal. Deferred while a
+    /// transaction is active. Refused if another process wrote the file
+    /// since this store loaded or saved it, in which case `record_changes`
+    /// rolls back the operation that asked for the write. With deferred
+    /// history, the values still on disk are merged in for the write and
+    /// dropped again after it.
+    fn write_graph(&mut self) -> Result<(), WillowError> {
+        if self.transaction.is_some() {
+            return Ok(());
+        }
+        self.require_writable()?;
+        let _timer = self.perf.time("save");
+        #[cfg(feature = "metrics")]
+        let _metric = crate::metrics::time(crate::metrics::Metric::SaveDuration);
+        let disk_stamp = self.disk_stamp.clone();
+        let mut known = disk_stamp.lock().unwrap_or_else(|e| e.into_inner());
+        if storage::file_stamp(&self.path)? != *known {
+            return Err(WillowError::ExternalChange(self.path.display().to_string()));
+        }
+        if let Some(layout) = self.shards.as_mut() {
+            let written = layout.save(&self.graph)?;
+            debug!(written, "shards written");
+        } else if self.history_deferred {
+            let history = storage::load_history(&self.path)?;
+            self.merge_history(history);
+            let saved = storage::save_graph(&self.path, &self.graph, &self.storage);
+            match saved {
+                Ok(()) => self.graph.nodes.values_mut().map(Arc::make_mut).for_each(|n| n.previous_values.clear()),
+                // The graph now holds the whole history; keep it.
+                Err(_) => self.history_deferred = false,
+            }
+            saved?;
+        } else {
+            storage::save_graph(&self.path, &self.graph, &self.storage)?;
+        }
+        *known = storage::file_stamp(&self.path)?;
+        self.unsaved = None;
+        if self.journal_len.replace(0) > 0 {
+            journal::clear(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Append one operation's changes to the journal, compacting once it
+    /// reaches its limit. Nothing to do outside journal mode, and deferred
+    /// to the commit inside a transaction.
+    fn append_journal(&mut self, changes: &[Change]) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let Some(limit) = self.journal_limit else {
+            return Ok(());
+        };
+        if self.transaction.is_some() {
+            return Ok(());
+        }
+        let _timer = self.perf.time("journal_append");
+        journal::append(&self.path, &JournalEntry::new(&self.graph, changes))?;
+        self.journal_len.set(self.journal_len.get() + 1);
+        if self.journal_len.get() >= limit {
+            debug!(entries = self.journal_len.get(), "compacting journal");
+            self.write_graph()?;
+        }
+        Ok(())
+    }
+
+    /// Fold the journal into the graph file now rather than at the next
+    /// compaction.
+    pub fn compact(&mut self) -> Result<(), WillowError> {
+        self.write_graph()
+    }
+
+    /// Put the `previous_values` still in the graph file in front of those
+    /// recorded since, applying the retention policy to the result.
+    fn merge_history(&mut self, history: HashMap<NodeId, Vec<SupersededValue>>) {
+        let now = Utc::now();
+        for (id, mut values) in history {
+            if let Some(node) = self.graph.nodes.get_mut(&id).map(Arc::make_mut) {
+                values.append(&mut node.previous_values);
+                self.retention.apply(&mut values, now);
+                node.previous_values = values;
+            }
+        }
+    }
+
+    /// Read every node's deferred `previous_values` into memory, ending
+    /// deferral. Nothing to do unless opened with `defer_history`.
+    pub fn load_history(&mut self) -> Result<(), WillowError> {
+        if !self.history_deferred {
+            return Ok(());
+        }
+        let history = storage::load_history(&self.path)?;
+        self.merge_history(history);
+        self.history_deferred = false;
+        info!("deferred history loaded");
+        Ok(())
+    }
+
+    /// A node's superseded values, oldest first, including any still
+    /// deferred on disk.
+    pub fn node_history(&self, node_id: &str) -> Result<Vec<SupersededValue>, WillowError> {
+        let node = self.get_node(node_id)?;
+        if !self.history_deferred {
+            return Ok(node.previous_values.clone());
+        }
+        let mut values = storage::load_history(&self.path)?.remove(&node.id).unwrap_or_default();
+        values.extend(node.previous_values.iter().cloned());
+        Ok(values)
+    }
+
+    /// Whether another process wrote the graph file since this store last
+    /// loaded or saved it.
+    pub fn changed_on_disk(&self) -> Result<bool, WillowError> {
+        let known = *self.disk_stamp.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(storage::file_stamp(&self.path)? != known)
+    }
+
+    /// Reload the graph if another process wrote the file. Returns whether
+    /// it reloaded. Refused during a transaction, and, unless `discard` is
+    /// set, while there is anything the reload would drop: changes held back
+    /// by write-behind, pending VCS changes, or undo and redo history, which
+    /// would not apply to the new graph. An operation refused with
+    /// `ExternalChange` left nothing behind to drop.
+    pub fn reload_if_changed(&mut self, discard: bool) -> Result<bool, WillowError> {
+        let _timer = self.perf.time("reload");
+        if self.transaction.is_some() {
+            return Err(WillowError::TransactionActive);
+        }
+        if !self.changed_on_disk()? {
+            return Ok(false);
+        }
+        let has_history = !self.session.undo_stack.is_empty() || !self.session.redo_stack.is_empty();
+        if !discard && (self.unsaved.is_some() || self.has_pending_changes() || has_history) {
+            return Err(WillowError::UnsavedChanges);
+        }
+        let stamp = storage::file_stamp(&self.path)?;
+        let mut graph = if self.shards.is_some() {
+            let (graph, layout) = shards::load(&self.path)?;
+            self.shards = Some(layout);
+            graph
+        } else if self.history_deferred {
+            storage::load_graph_deferring_history(&self.path)?
+        } else {
+            storage::load_graph(&self.path)?
+        };
+        self.journal_len.set(journal::replay(&self.path, &mut graph)?);
+        self.graph = graph;
+        *self.disk_stamp.lock().unwrap_or_else(|e| e.into_inner()) = stamp;
+        self.reset_indexes();
+        self.unsaved = None;
+        self.pending_changes.clear();
+        self.pending_from_head = false;
+        self.session.undo_stack.clear();
+        self.session.redo_stack.clear();
+        info!(path = %self.path.display(), nodes = self.graph.nodes.len(), "graph reloaded");
+        Ok(true)
+    }
+
+    /// Call `on_change` when another process writes the graph file, until
+    /// the returned watcher is stopped or dropped. `interval` is how often
+    /// to poll where the platform's file events are unavailable.
+    pub fn watch(&mut self, interval: Duration, on_change: impl Fn() + Send + 'static) -> Result<FileWatcher, WillowError> {
+        let watcher = FileWatcher::spawn(self.path.clone(), self.disk_stamp.clone(), interval, on_change)?;
+        self.watchers.retain(|stop| !stop.load(Ordering::Relaxed));
+        self.watchers.push(watcher.stop_flag());
+        Ok(watcher)
+    }
+
+    /// Write out anything not yet in the graph file, stop watchers and
+    /// listeners and release the file lock. Later calls fail with
+    /// `StoreClosed`; closing twice does nothing.
+    pub fn close(&mut self) -> Result<(), WillowError> {
+        if self.closed {
+            return Ok(());
+        }
+        self.require_no_transaction()?;
+        if (self.journal_len.get() > 0 || self.unsaved.is_some()) && !self.read_only {
+            self.write_graph()?;
+        }
+        for stop in self.watchers.drain(..) {
+            stop.store(true, Ordering::Relaxed);
+        }
+        self.indexes.clear();
+        self.subscribers.clear();
+        self._lock = None;
+        self.closed = true;
+        info!(path = %self.path.display(), "store closed");
+        Ok(())
+    }
+
+    /// A handle on the store already open on `path` through `open_shared`
+    /// in this process, or on a new one opened with `options`, so that
+    /// every caller, on any thread, works on the same graph instead of
+    /// copies that silently diverge. Fails with `OpenOptionsMismatch` if
+    /// the open store was opened with other options; closed stores are
+    /// replaced.
+    pub fn open_shared(path: &Path, options: &OpenOptions) -> Result<StoreHandle, WillowError> {
+        Self::attach_shared(path, options, false)
+    }
+
+    /// A read-only handle on the store open on `path` through
+    /// `open_shared`, whatever its options, so a reader sees the writer's
+    /// changes as they are made. With no such store, one is opened
+    /// read-only, and writers cannot share it until it is closed.
+    pub fn open_shared_read_only(path: &Path) -> Result<StoreHandle, WillowError> {
+        let options = OpenOptions { read_only: true, ..OpenOptions::default() };
+        Self::attach_shared(path, &options, true)
+    }
+
+    fn attach_shared(path: &Path, options: &OpenOptions, reader: bool) -> Result<StoreHandle, WillowError> {
+        let key = canonical_graph_path(path);
+        let mut stores = SHARED_STORES.lock().unwrap_or_else(PoisonError::into_inner);
+        stores.retain(|_, store| store.strong_count() > 0);
+        if let Some(store) = stores.get(&key).and_then(Weak::upgrade) {
+            let mut open = lock_shared(&store);
+            if !open.is_closed() {
+                if !reader && open.options != *options {
+                    return Err(WillowError::OpenOptionsMismatch(path.display().to_string()));
+                }
+                let handle = StoreHandle::new(&store, &mut open, reader);
+                return Ok(handle);
+            }
+        }
+        let store = Arc::new(Mutex::new(GraphStore::open(path, options)?));
+        stores.insert(key, Arc::downgrade(&store));
+        if let Some(write_behind) = options.write_behind {
+            spawn_flusher(Arc::downgrade(&store), write_behind);
+        }
+        let handle = StoreHandle::new(&store, &mut lock_shared(&store), reader);
+        Ok(handle)
+    }
+
+    /// End the session of the handle holding the store, closing the store
+    /// if it was the last.
+    fn end_session(&mut self) -> Result<(), WillowError> {
+        if self.session.closed {
+            return Ok(());
+        }
+        if self.in_transaction() {
+            return Err(WillowError::TransactionActive);
+        }
+        if self.handles <= 1 {
+            self.close()?;
+        }
+        self.handles = self.handles.saturating_sub(1);
+        for id in std::mem::take(&mut self.session.subscriptions) {
+            self.unsubscribe(id);
+        }
+        self.session.closed = true;
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed || self.session.closed
+    }
+
+    fn record_change(&mut self, change: Change) -> Result<(), WillowError> {
+        self.record_changes(vec![change])
     }
 
-    fn record_change(&mut self, change: Change) {
+    /// Save the changes one operation made to the graph and record them;
+    /// they are undone together. The rules are applied first, so what they
+    /// change is part of the same save and undo entry. The operation is
+    /// audited before the save, so it is logged even if the save fails, in
+    /// which case every change is rolled back before the error is returned.
+    fn record_changes(&mut self, mut changes: Vec<Change>) -> Result<(), WillowError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut derived = rules::evaluate(&self.graph, changes.iter().flat_map(search_index::touched), Utc::now());
+        apply_recorded(&mut self.graph, &mut derived);
+        changes.extend(derived);
+        for change in &mut changes {
+            change.set_actor(self.session.actor.clone());
+        }
+        self.audit(AuditEntry::of_changes(self.session.actor.clone(), &changes));
+        if let Err(e) = self.save() {
+            // The graph keeps nothing that is neither saved nor recorded.
+            apply_delta(&mut self.graph, &invert_delta(&Delta::new(changes)));
+            return Err(e);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::increment(crate::metrics::Metric::Mutations);
+        self.session.redo_stack.clear();
+        self.notify_indexes(&changes);
+        self.push_undo(changes.clone());
         if self.repo.is_some() {
-            self.pending_changes.push(change);
+            self.pending_changes.extend(changes.iter().cloned());
+        }
+        self.journal_changes(&changes)
+    }
+
+    /// Journal changes already applied and recorded in memory. A failed
+    /// append falls back to writing the whole graph, erroring only if that
+    /// fails too, so the store's undo history and pending changes still
+    /// match its graph either way.
+    fn journal_changes(&mut self, changes: &[Change]) -> Result<(), WillowError> {
+        if let Err(e) = self.append_journal(changes) {
+            warn!(error = %e, "could not append to the journal, writing the graph instead");
+            self.write_graph()?;
+        }
+        Ok(())
+    }
+
+    fn push_undo(&mut self, changes: Vec<Change>) {
+        self.session.undo_stack.push_back(changes);
+        if self.session.undo_stack.len() > UNDO_LIMIT {
+            self.session.undo_stack.pop_front();
         }
     }
 
@@ -62,36 +980,78 @@ impl GraphStore {
         self.repo.as_ref().ok_or(WillowError::VcsNotInitialized)
     }
 
+    /// Like `require_repo`, for operations that write history or replace the
+    /// graph, which must not interleave with an open transaction.
+    fn require_repo_idle(&self) -> Result<&Repository, WillowError> {
+        self.require_no_transaction()?;
+        self.require_repo()
+    }
+
+    pub fn require_open(&self) -> Result<(), WillowError> {
+        match self.is_closed() {
+            true => Err(WillowError::StoreClosed),
+            false => Ok(()),
+        }
+    }
+
+    /// Fails for a store opened `read_only` or closed.
+    pub fn require_writable(&self) -> Result<(), WillowError> {
+        self.require_open()?;
+        if self.read_only || self.session.read_only {
+            return Err(WillowError::ReadOnly);
+        }
+        match &self.transaction {
+            Some(transaction) if transaction.owner != self.session.id => Err(WillowError::TransactionActive),
+            _ => Ok(()),
+        }
+    }
+
+    fn require_no_transaction(&self) -> Result<(), WillowError> {
+        match self.transaction {
+            Some(_) => Err(WillowError::TransactionActive),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace the whole graph, which must carry its full history. Callers
+    /// leave it at HEAD: it was read from a commit or is committed next.
     fn apply_graph(&mut self, graph: Graph) -> Result<(), WillowError> {
         self.graph = graph;
-        self.save()?;
+        self.history_deferred = false;
+        self.reset_indexes();
+        self.write_graph()?;
         self.pending_changes.clear();
+        self.pending_from_head = true;
+        self.session.undo_stack.clear();
+        self.session.redo_stack.clear();
         Ok(())
     }
 
-    fn get_node(&self, node_id: &str) -> Result<&Node, WillowError> {
-        let nid = NodeId(node_id.to_string());
+    pub fn get_node(&self, node_id: &str) -> Result<&Node, WillowError> {
+        let nid = NodeId(node_id.into());
         self.graph
             .nodes
             .get(&nid)
+            .map(Arc::as_ref)
             .ok_or_else(|| WillowError::NodeNotFound(node_id.to_string()))
     }
 
-    fn links_touching(&self, node_ids: &std::collections::HashSet<&NodeId>) -> Vec<Link> {
+    pub fn get_link(&self, link_id: &str) -> Result<&Link, WillowError> {
+        let lid = LinkId(link_id.into());
         self.graph
             .links
-            .values()
-            .filter(|link| {
-                node_ids.contains(&link.from_node) || node_ids.contains(&link.to_node)
-            })
-            .cloned()
-            .collect()
+            .get(&lid)
+            .ok_or_else(|| WillowError::LinkNotFound(link_id.to_string()))
     }
 
-    fn save_and_record(&mut self, change: Change) -> Result<(), WillowError> {
-        self.save()?;
-        self.record_change(change);
-        Ok(())
+    fn links_touching(&self, node_ids: &std::collections::HashSet<&NodeId>) -> Vec<Link> {
+        let mut seen = HashSet::new();
+        node_ids
+            .iter()
+            .flat_map(|id| self.links_by_node.links_of(&self.graph, id))
+            .filter(|link| seen.insert(&link.id))
+            .cloned()
+            .collect()
     }
 
     fn parse_confidence(confidence: Option<&str>) -> Result<Option<ConfidenceLevel>, WillowError> {
@@ -103,641 +1063,5043 @@ impl GraphStore {
             .transpose()
     }
 
+    /// Collect all descendants in pre-order, each once even when reachable
+    /// through several parents.
     fn collect_descendant_ids(&self, node_id: &NodeId, result: &mut Vec<NodeId>) {
+        let mut seen: HashSet<NodeId> = result.iter().cloned().collect();
+        seen.insert(node_id.clone());
+        self.collect_descendant_ids_inner(node_id, result, &mut seen);
+    }
+
+    fn collect_descendant_ids_inner(
+        &self,
+        node_id: &NodeId,
+        result: &mut Vec<NodeId>,
+        seen: &mut HashSet<NodeId>,
+    ) {
         if let Some(node) = self.graph.nodes.get(node_id) {
             for child_id in &node.children {
-                result.push(child_id.clone());
-                self.collect_descendant_ids(child_id, result);
+                if seen.insert(child_id.clone()) {
+                    result.push(child_id.clone());
+                    self.collect_descendant_ids_inner(child_id, result, seen);
+                }
             }
         }
     }
 
-    // ---- VCS methods ----
+    /// Mark `ids` as updated at `now` by the current actor, recording the
+    /// stamps so undo restores the ones they replace.
+    fn stamp_nodes(&mut self, ids: &[NodeId], now: DateTime<Utc>, changes: &mut Vec<Change>) {
+        for id in ids {
+            let node = self.graph.nodes.get_mut(id).map(Arc::make_mut).unwrap();
+            let old_stamp = NodeStamp::of(node);
+            node.updated_at = now;
+            node.updated_by = self.session.actor.clone();
+            changes.push(Change::StampNode {
+                node_id: id.clone(),
+                old_stamp,
+                new_stamp: NodeStamp::of(node),
+                actor: None,
+            });
+        }
+    }
 
-    pub fn vcs_init(&mut self) -> Result<(), WillowError> {
-        let graph_dir = self
-            .path
-            .parent()
-            .ok_or_else(|| WillowError::Io(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "No parent directory for graph file",
-            )))?;
+    // ---- Attribution ----
 
-        let repo = Repository::init(graph_dir, &self.graph)?;
-        self.repo = Some(repo);
-        Ok(())
+    /// Attribute subsequent operations to `actor` (a tool name, conversation
+    /// id or user), or to nobody.
+    pub fn set_actor(&mut self, actor: Option<String>) {
+        self.session.actor = actor;
     }
 
-    pub fn has_pending_changes(&self) -> bool {
-        !self.pending_changes.is_empty()
+    // ---- Audit log ----
+
+    /// Append `entry` to the audit log, if the store keeps one. Operations
+    /// are audited before they are saved, whether or not the save succeeds;
+    /// a failed append does not stop them and is only logged.
+    fn audit(&self, entry: AuditEntry) {
+        if !self.audit {
+            return;
+        }
+        if let Err(e) = audit::append(&self.path, &entry) {
+            warn!(error = %e, operation = %entry.operation, "could not append to the audit log");
+        }
     }
 
-    pub fn commit(&mut self, input: CommitInput) -> Result<crate::vcs::types::CommitHash, WillowError> {
-        let repo = self.require_repo()?;
-        let hash = repo.create_commit(&input, &self.pending_changes, &self.graph)?;
-        self.pending_changes.clear();
-        Ok(hash)
+    /// Audit a call that changed something other than the graph's nodes
+    /// and links, or replaced the graph wholesale.
+    fn audit_call(&self, operation: &str, target: Option<&str>) {
+        let entry = AuditEntry::new(self.session.actor.clone(), operation);
+        self.audit(match target {
+            Some(target) => entry.with_target(target),
+            None => entry,
+        });
     }
 
-    /// Commit if the graph on disk differs from the last committed state.
-    /// Used after external processes modify the graph file.
-    pub fn commit_external_changes(&self, input: CommitInput) -> Result<Option<crate::vcs::types::CommitHash>, WillowError> {
-        self.require_repo()?.commit_if_changed(&input, &self.graph)
+    /// Entries of the audit log matching `query`, oldest first. The log is
+    /// read from disk, so it includes calls made by earlier stores.
+    pub fn audit_log(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, WillowError> {
+        audit::read(&self.path, query)
     }
 
-    pub fn discard_changes(&mut self) -> Result<(), WillowError> {
-        let repo = self.require_repo()?;
-        if let Some(head) = repo.log(Some(1))?.first() {
-            let graph = repo.reconstruct_at(&head.hash)?;
-            self.apply_graph(graph)?;
-        } else {
-            self.pending_changes.clear();
-        }
-        Ok(())
+    /// Drop audit entries made before `before`. Returns the number dropped;
+    /// the pruning itself is audited.
+    pub fn prune_audit_log(&mut self, before: DateTime<Utc>) -> Result<usize, WillowError> {
+        self.require_writable()?;
+        let pruned = audit::prune(&self.path, before)?;
+        info!(pruned, "prune_audit_log");
+        self.audit_call("prune_audit_log", None);
+        Ok(pruned)
     }
 
-    pub fn get_repo(&self) -> Result<&Repository, WillowError> {
-        self.require_repo()
-    }
-
-    /// Switch branch — replaces the in-memory graph and saves to disk.
-    pub fn switch_branch(&mut self, name: &str) -> Result<(), WillowError> {
-        let graph = self.require_repo()?.switch_branch(name, self.has_pending_changes())?;
-        self.apply_graph(graph)
-    }
+    // ---- Profiles ----
 
-    /// Checkout a specific commit (detached HEAD).
-    pub fn checkout_commit(&mut self, hash: &crate::vcs::types::CommitHash) -> Result<(), WillowError> {
-        let graph = self.require_repo()?.checkout_commit(hash, self.has_pending_changes())?;
-        self.apply_graph(graph)
+    /// Every profile and its root node, the default profile first.
+    pub fn list_profiles(&self) -> Vec<(String, NodeId)> {
+        std::iter::once((DEFAULT_PROFILE.to_string(), self.graph.root_id.clone()))
+            .chain(self.graph.roots.iter().map(|(name, id)| (name.clone(), id.clone())))
+            .collect()
     }
 
-    /// Restore to a past commit (creates a new commit).
-    pub fn restore_to_commit(&mut self, hash: &crate::vcs::types::CommitHash) -> Result<crate::vcs::types::CommitHash, WillowError> {
-        let (new_hash, graph) = self.require_repo()?.restore_to_commit(hash, &self.graph)?;
-        self.apply_graph(graph)?;
-        Ok(new_hash)
+    pub fn current_profile(&self) -> &str {
+        &self.profile
     }
 
-    /// Merge a source branch into current. Returns Ok(hash) on success.
-    pub fn merge_branch(&mut self, source: &str) -> Result<crate::vcs::types::CommitHash, WillowError> {
-        match self.require_repo()?.merge_branch(source, &self.graph)? {
-            crate::vcs::repository::MergeBranchResult::Success(hash, graph) => {
-                self.apply_graph(graph)?;
-                Ok(hash)
-            }
-            crate::vcs::repository::MergeBranchResult::Conflicts { conflicts, .. } => {
-                Err(WillowError::MergeConflict(conflicts.len()))
-            }
-        }
+    /// Root node of the current profile.
+    pub fn profile_root(&self) -> &NodeId {
+        self.graph.profile_root(&self.profile).unwrap_or(&self.graph.root_id)
     }
 
-    // ---- Mutation methods ----
-
-    pub fn create_node(
-        &mut self,
-        parent_id: &str,
-        node_type: &str,
-        content: &str,
-        metadata: Option<HashMap<String, String>>,
-        temporal: Option<TemporalMetadata>,
-    ) -> Result<Node, WillowError> {
-        debug!(parent = %parent_id, node_type = %node_type, "create_node");
-        let parent_nid = NodeId(parent_id.to_string());
-
-        if !self.graph.nodes.contains_key(&parent_nid) {
-            return Err(WillowError::ParentNotFound(parent_id.to_string()));
+    /// Add a profile with its own, initially empty, root tree.
+    pub fn create_profile(&mut self, name: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(profile = %name, "create_profile");
+        if name.is_empty() || self.graph.profile_root(name).is_some() {
+            return Err(WillowError::ProfileExists(name.to_string()));
         }
-
-        let nt = NodeType::from_str(node_type)
-            .ok_or_else(|| WillowError::InvalidNodeType(node_type.to_string()))?;
-
         let now = Utc::now();
-        let node_id = NodeId(Uuid::new_v4().to_string());
-
         let node = Node {
-            id: node_id.clone(),
-            node_type: nt,
-            content: content.to_string(),
-            parent_id: Some(parent_nid.clone()),
+            id: NodeId(Uuid::new_v4().to_string().into()),
+            node_type: NodeType::Root,
+            content: name.to_string(),
+            parent_id: None,
             children: Vec::new(),
-            metadata: metadata.unwrap_or_default(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
+            metadata: HashMap::new(),
             previous_values: Vec::new(),
-            temporal,
+            temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: self.session.actor.clone(),
+            updated_by: self.session.actor.clone(),
         };
-
-        // Add to parent's children
-        self.graph
-            .nodes
-            .get_mut(&parent_nid)
-            .unwrap()
-            .children
-            .push(node_id.clone());
-
-        self.graph.nodes.insert(node_id.clone(), node.clone());
-
-        self.save_and_record(Change::CreateNode {
-            node_id,
-            node: node.clone(),
-        })?;
-
+        let mut changes = vec![
+            Change::CreateNode {
+                node_id: node.id.clone(),
+                node: node.clone().into(),
+                positions: Vec::new(),
+                actor: None,
+            },
+            Change::SetProfile {
+                name: name.to_string(),
+                old_root: None,
+                new_root: Some(node.id.clone()),
+                actor: None,
+            },
+        ];
+        apply_recorded(&mut self.graph, &mut changes);
+        self.record_changes(changes)?;
         Ok(node)
     }
 
-    pub fn get_context(
-        &self,
-        node_id: &str,
-        depth: Option<u32>,
-    ) -> Result<ContextResult, WillowError> {
-        let node = self.get_node(node_id)?.clone();
-        let nid = &node.id;
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), WillowError> {
+        if self.graph.profile_root(name).is_none() {
+            return Err(WillowError::ProfileNotFound(name.to_string()));
+        }
+        info!(profile = %name, "switch_profile");
+        self.profile = name.to_string();
+        Ok(())
+    }
 
-        let ancestors = self.collect_ancestors(nid);
+    /// Map the graph root id, which hosts use as the entry point, to the
+    /// current profile's root.
+    fn resolve_profile_node<'a>(&'a self, node_id: &'a str) -> &'a str {
+        if node_id == &*self.graph.root_id.0 {
+            &self.profile_root().0
+        } else {
+            node_id
+        }
+    }
 
-        let max_depth = depth.unwrap_or(2);
-        let mut descendants = Vec::new();
-        self.collect_descendants(nid, max_depth, 0, &mut descendants);
+    // ---- Transactions ----
 
-        let involved_ids: std::collections::HashSet<&NodeId> = std::iter::once(nid)
-            .chain(ancestors.iter().map(|n| &n.id))
-            .chain(descendants.iter().map(|n| &n.id))
-            .collect();
+    /// Start buffering mutations: nothing is written to disk until
+    /// `commit_transaction`, and `rollback_transaction` restores the graph
+    /// and pending changes to their state at this point.
+    pub fn begin_transaction(&mut self) -> Result<(), WillowError> {
+        if self.transaction.is_some() {
+            return Err(WillowError::TransactionAlreadyActive);
+        }
+        debug!("begin_transaction");
+        self.transaction = Some(TransactionState {
+            owner: self.session.id,
+            backup: self.graph.clone(),
+            history_deferred: self.history_deferred,
+            pending_len: self.pending_changes.len(),
+            undo_stack: self.session.undo_stack.clone(),
+            redo_stack: self.session.redo_stack.clone(),
+        });
+        Ok(())
+    }
 
-        let links = self.links_touching(&involved_ids);
+    pub fn commit_transaction(&mut self) -> Result<(), WillowError> {
+        if !self.in_transaction() {
+            return Err(WillowError::NoActiveTransaction);
+        }
+        self.transaction = None;
+        debug!("commit_transaction");
+        self.write_graph()
+    }
 
-        Ok(ContextResult {
-            node,
-            ancestors,
-            descendants,
-            links,
-        })
+    pub fn rollback_transaction(&mut self) -> Result<(), WillowError> {
+        let Some(state) = self.transaction.take_if(|t| t.owner == self.session.id) else {
+            return Err(WillowError::NoActiveTransaction);
+        };
+        debug!("rollback_transaction");
+        self.graph = state.backup;
+        self.history_deferred = state.history_deferred;
+        self.reset_indexes();
+        self.pending_changes.truncate(state.pending_len);
+        self.session.undo_stack = state.undo_stack;
+        self.session.redo_stack = state.redo_stack;
+        self.audit_call("rollback_transaction", None);
+        Ok(())
     }
 
-    fn collect_ancestors(&self, node_id: &NodeId) -> Vec<Node> {
-        let mut ancestors = Vec::new();
-        let mut current_id = self.graph.nodes.get(node_id).and_then(|n| n.parent_id.clone());
-        while let Some(pid) = current_id {
-            let Some(parent) = self.graph.nodes.get(&pid) else { break };
-            ancestors.push(parent.clone());
-            current_id = parent.parent_id.clone();
-        }
-        ancestors
+    /// Whether the caller has a transaction open; another handle's does
+    /// not count.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.as_ref().is_some_and(|t| t.owner == self.session.id)
     }
 
-    fn collect_descendants(
-        &self,
-        node_id: &NodeId,
-        max_depth: u32,
-        current_depth: u32,
-        result: &mut Vec<Node>,
-    ) {
-        if current_depth >= max_depth {
-            return;
+    /// Run `f` as a single all-or-nothing unit: the graph is saved once if it
+    /// returns Ok, and every mutation it made is rolled back if it returns Err.
+    /// Nested calls join the enclosing transaction.
+    pub fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut GraphStore) -> Result<T, WillowError>,
+    ) -> Result<T, WillowError> {
+        if self.in_transaction() {
+            return f(self);
         }
-        let Some(node) = self.graph.nodes.get(node_id) else { return };
-        for child_id in &node.children {
-            if let Some(child) = self.graph.nodes.get(child_id) {
-                result.push(child.clone());
-                self.collect_descendants(child_id, max_depth, current_depth + 1, result);
+        self.begin_transaction()?;
+        match f(self) {
+            Ok(value) => {
+                self.commit_transaction()?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.rollback_transaction()?;
+                Err(e)
             }
         }
     }
 
-    pub fn update_node(
-        &mut self,
-        node_id: &str,
-        content: Option<&str>,
-        metadata: Option<HashMap<String, String>>,
-        temporal: Option<TemporalMetadata>,
-        reason: Option<&str>,
-    ) -> Result<Node, WillowError> {
-        debug!(node_id = %node_id, "update_node");
-        let nid = NodeId(node_id.to_string());
+    // ---- Metadata schema ----
 
-        let (old_content, old_metadata) = {
-            let node = self.get_node(node_id)?;
-            (node.content.clone(), node.metadata.clone())
-        };
+    /// Replace the metadata schema and persist it next to the graph.
+    /// Existing nodes are not re-validated.
+    pub fn set_metadata_schema(&mut self, schema: MetadataSchema) -> Result<(), WillowError> {
+        self.require_writable()?;
+        storage::save_sidecar(&self.path, SCHEMA_SIDECAR, &schema)?;
+        self.schema = schema;
+        self.audit_call("set_metadata_schema", None);
+        Ok(())
+    }
 
-        let node = self.graph.nodes.get_mut(&nid).unwrap();
+    // ---- Search indexes ----
 
-        let content_changed = content.is_some_and(|c| c != node.content);
-        if let Some(new_content) = content {
-            if content_changed {
-                node.previous_values.push(SupersededValue {
-                    old_content: node.content.clone(),
-                    superseded_at: Utc::now(),
-                    reason: reason.map(|s| s.to_string()),
-                });
-                node.content = new_content.to_string();
+    /// Register an index to be told about every node created, updated or
+    /// deleted from now on. It is not sent the nodes that already exist.
+    pub fn add_search_index(&mut self, index: Box<dyn SearchIndex>) {
+        self.indexes.push(index);
+    }
+
+    /// Tell the registered indexes and the built-in prefix index about one
+    /// operation's changes, and subscribers what they amount to.
+    fn notify_indexes(&mut self, changes: &[Change]) {
+        self.links_by_node.apply(changes);
+        let graph = &self.graph;
+        let mut indexes: Vec<&mut dyn SearchIndex> = self.indexes.iter_mut().map(|i| i.as_mut() as _).collect();
+        indexes.push(&mut self.prefixes);
+        search_index::notify(&mut indexes, graph, changes);
+        if !self.subscribers.is_empty() {
+            for event in events::from_changes(&self.graph, changes) {
+                self.emit(event);
             }
         }
+    }
 
-        let metadata_changed = metadata.as_ref().is_some_and(|m| *m != node.metadata);
-        if let Some(new_metadata) = metadata {
-            node.metadata = new_metadata;
+    fn reset_indexes(&mut self) {
+        self.links_by_node = LinkIndex::build(&self.graph);
+        let graph = &self.graph;
+        let mut indexes: Vec<&mut dyn SearchIndex> = self.indexes.iter_mut().map(|i| i.as_mut() as _).collect();
+        indexes.push(&mut self.prefixes);
+        for index in indexes {
+            index.on_reset(graph);
         }
+        self.emit(StoreEvent::Reset);
+    }
 
-        if let Some(new_temporal) = temporal {
-            node.temporal = Some(new_temporal);
-        }
+    /// Call `callback` with every event from now on, and the graph as it is
+    /// after it. Returns an id for `unsubscribe`.
+    pub fn subscribe(&mut self, callback: impl FnMut(&StoreEvent, &Graph) + Send + 'static) -> u32 {
+        self.next_subscription += 1;
+        self.subscribers.push((self.next_subscription, Box::new(callback)));
+        self.session.subscriptions.push(self.next_subscription);
+        self.next_subscription
+    }
 
-        node.updated_at = Utc::now();
-        let updated = node.clone();
-        self.save()?;
+    /// Stop a subscription; false if there was none with this id.
+    pub fn unsubscribe(&mut self, id: u32) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|(sub, _)| *sub != id);
+        self.subscribers.len() < before
+    }
 
-        if content_changed || metadata_changed {
-            self.record_change(Change::UpdateNode {
-                node_id: nid,
-                old_content: if content_changed { Some(old_content) } else { None },
-                new_content: if content_changed { Some(updated.content.clone()) } else { None },
-                old_metadata: if metadata_changed { Some(old_metadata) } else { None },
-                new_metadata: if metadata_changed { Some(updated.metadata.clone()) } else { None },
-            });
+    fn emit(&mut self, event: StoreEvent) {
+        for (_, callback) in &mut self.subscribers {
+            callback(&event, &self.graph);
         }
+    }
 
-        Ok(updated)
+    /// Up to `limit` node contents and tags starting with or containing
+    /// `prefix`, for type-ahead. Sensitive and archived nodes are left out.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.prefixes.suggest(&self.graph, prefix, limit)
     }
 
-    pub fn delete_node(&mut self, node_id: &str) -> Result<(), WillowError> {
-        let nid = NodeId(node_id.to_string());
+    // ---- Text analysis ----
 
-        if nid == self.graph.root_id {
-            return Err(WillowError::CannotDeleteRoot);
-        }
+    /// Replace the default stemming and synonyms and persist them next to
+    /// the graph.
+    pub fn set_text_analysis(&mut self, analysis: TextAnalysis) -> Result<(), WillowError> {
+        self.require_writable()?;
+        storage::save_sidecar(&self.path, ANALYSIS_SIDECAR, &analysis)?;
+        self.analysis = analysis;
+        self.audit_call("set_text_analysis", None);
+        Ok(())
+    }
 
-        self.get_node(node_id)?;
+    // ---- Ranking ----
 
-        let mut to_delete = Vec::new();
-        self.collect_descendant_ids(&nid, &mut to_delete);
-        to_delete.push(nid.clone());
-        debug!(node_id = %node_id, cascade = to_delete.len(), "delete_node");
+    /// Replace the default recency and node type boosts and persist them
+    /// next to the graph.
+    pub fn set_ranking_boosts(&mut self, ranking: RankingBoosts) -> Result<(), WillowError> {
+        self.require_writable()?;
+        ranking.validate()?;
+        storage::save_sidecar(&self.path, RANKING_SIDECAR, &ranking)?;
+        self.ranking = ranking;
+        self.audit_call("set_ranking_boosts", None);
+        Ok(())
+    }
 
-        let delete_set: std::collections::HashSet<&NodeId> = to_delete.iter().collect();
-        let deleted_nodes: Vec<Node> = to_delete
-            .iter()
-            .filter_map(|id| self.graph.nodes.get(id).cloned())
-            .collect();
-        let deleted_links = self.links_touching(&delete_set);
+    // ---- Import ----
 
-        if let Some(parent_id) = self.graph.nodes.get(&nid).and_then(|n| n.parent_id.clone()) {
-            if let Some(parent) = self.graph.nodes.get_mut(&parent_id) {
-                parent.children.retain(|c| c != &nid);
+    /// Create `outline` under `parent_id` as one operation, undone together,
+    /// and commit it (with any other pending changes) when VCS is initialized
+    /// and no transaction is open. Every node and link is validated first, so
+    /// a failed import changes nothing.
+    pub fn import_outline(
+        &mut self,
+        parent_id: &str,
+        outline: Outline,
+        source: &str,
+        progress: &mut Progress,
+    ) -> Result<ImportReport, WillowError> {
+        self.require_writable()?;
+        let parent_nid = NodeId(parent_id.into());
+        let Some(parent) = self.graph.nodes.get(&parent_nid) else {
+            return Err(WillowError::ParentNotFound(parent_id.to_string()));
+        };
+        let mut child_counts = vec![0; outline.nodes.len()];
+        let mut top_level = 0;
+        for node in &outline.nodes {
+            self.schema.validate(&node.node_type, &node.metadata)?;
+            self.limits.check_content(&node.content)?;
+            self.limits.check_metadata(&node.metadata)?;
+            match node.parent {
+                Some(p) => child_counts[p] += 1,
+                None => top_level += 1,
             }
         }
+        self.limits.check_children(parent, top_level)?;
+        if child_counts.iter().any(|&c| c > self.limits.max_children) {
+            return Err(WillowError::TooManyChildren {
+                parent: "imported node".to_string(),
+                max: self.limits.max_children,
+            });
+        }
+        for link in &outline.links {
+            self.relations.validate(&link.relation, false)?;
+        }
 
-        for id in &to_delete {
-            self.graph.nodes.remove(id);
+        let now = Utc::now();
+        let ids: Vec<NodeId> = outline.nodes.iter().map(|_| NodeId(Uuid::new_v4().to_string().into())).collect();
+        let mut changes = Vec::new();
+        for (i, draft) in outline.nodes.into_iter().enumerate() {
+            progress.step("import", i, Some(ids.len()))?;
+            let node = Node {
+                id: ids[i].clone(),
+                node_type: draft.node_type,
+                content: draft.content,
+                parent_id: Some(draft.parent.map_or(parent_nid.clone(), |p| ids[p].clone())),
+                children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
+                metadata: draft.metadata,
+                previous_values: Vec::new(),
+                temporal: None,
+                created_at: now,
+                updated_at: now,
+                created_by: self.session.actor.clone(),
+                updated_by: self.session.actor.clone(),
+            };
+            changes.push(Change::CreateNode {
+                node_id: node.id.clone(),
+                node: node.into(),
+                positions: Vec::new(),
+                actor: None,
+            });
+        }
+        for draft in &outline.links {
+            let link = Link {
+                id: LinkId(Uuid::new_v4().to_string().into()),
+                from_node: ids[draft.from].clone(),
+                to_node: ids[draft.to].clone(),
+                relation: draft.relation.clone(),
+                bidirectional: false,
+                confidence: None,
+                created_at: now,
+            };
+            changes.push(Change::AddLink {
+                link_id: link.id.clone(),
+                link,
+                actor: None,
+            });
         }
 
-        self.graph
-            .links
-            .retain(|_, link| !delete_set.contains(&link.from_node) && !delete_set.contains(&link.to_node));
+        let root_ids: Vec<NodeId> = changes
+            .iter()
+            .filter_map(|c| match c {
+                Change::CreateNode { node, .. } if node.parent_id.as_ref() == Some(&parent_nid) => Some(node.id.clone()),
+                _ => None,
+            })
+            .collect();
+        let (nodes, links) = (ids.len(), outline.links.len());
+        info!(source = %source, parent = %parent_id, nodes, links, "import");
+        if changes.is_empty() {
+            return Ok(ImportReport { root_ids, nodes, links, commit: None });
+        }
+        apply_recorded(&mut self.graph, &mut changes);
+        self.record_changes(changes)?;
+
+        let commit = if self.repo.is_some() && self.transaction.is_none() {
+            Some(self.commit(CommitInput {
+                message: format!("Import {nodes} nodes and {links} links from {source}"),
+                source: CommitSource::Manual {
+                    tool_name: Some(format!("import_{source}")),
+                },
+            })?)
+        } else {
+            None
+        };
+        Ok(ImportReport { root_ids, nodes, links, commit })
+    }
 
-        self.save_and_record(Change::DeleteNode {
-            node_id: nid,
-            deleted_nodes,
-            deleted_links,
-        })
+    /// Import a Markdown outline under `parent_id`; see `import::from_markdown`.
+    pub fn import_markdown(&mut self, parent_id: &str, text: &str, progress: &mut Progress) -> Result<ImportReport, WillowError> {
+        self.import_outline(parent_id, import::from_markdown(text), "markdown", progress)
     }
 
-    pub fn add_link(
+    /// Import an Obsidian vault under `parent_id`; see `import::from_obsidian`.
+    pub fn import_obsidian(
         &mut self,
-        from_node: &str,
-        to_node: &str,
-        relation: &str,
-        bidirectional: bool,
-        confidence: Option<&str>,
-    ) -> Result<Link, WillowError> {
-        debug!(from = %from_node, to = %to_node, relation = %relation, "add_link");
-        let from_nid = NodeId(from_node.to_string());
-        let to_nid = NodeId(to_node.to_string());
-
-        self.get_node(from_node)?;
-        self.get_node(to_node)?;
-
-        let confidence_level = Self::parse_confidence(confidence)?;
+        parent_id: &str,
+        vault_dir: &Path,
+        progress: &mut Progress,
+    ) -> Result<ImportReport, WillowError> {
+        self.import_outline(parent_id, import::from_obsidian(vault_dir)?, "obsidian", progress)
+    }
 
-        let is_dup = self.graph.links.values().any(|link| {
-            let forward = link.from_node == from_nid && link.to_node == to_nid && link.relation == relation;
-            let reverse = bidirectional
-                && link.from_node == to_nid
-                && link.to_node == from_nid
-                && link.relation == relation;
-            forward || reverse
-        });
-        if is_dup {
-            return Err(WillowError::DuplicateLink {
-                from: from_node.to_string(),
-                to: to_node.to_string(),
-                relation: relation.to_string(),
-            });
-        }
+    /// Import a Roam Research or Logseq JSON export under `parent_id`; see
+    /// `import::from_roam_json`.
+    pub fn import_roam_json(&mut self, parent_id: &str, text: &str, progress: &mut Progress) -> Result<ImportReport, WillowError> {
+        self.import_outline(parent_id, import::from_roam_json(text)?, "roam", progress)
+    }
 
-        let link = Link {
-            id: LinkId(Uuid::new_v4().to_string()),
-            from_node: from_nid,
-            to_node: to_nid,
-            relation: relation.to_string(),
-            bidirectional,
-            confidence: confidence_level,
-            created_at: Utc::now(),
-        };
+    /// Import an OPML outline under `parent_id`; see `import::from_opml`.
+    pub fn import_opml(&mut self, parent_id: &str, text: &str, progress: &mut Progress) -> Result<ImportReport, WillowError> {
+        self.import_outline(parent_id, import::from_opml(text)?, "opml", progress)
+    }
 
-        self.graph.links.insert(link.id.clone(), link.clone());
+    // ---- Embeddings ----
+
+    /// Embed nodes that are new or changed since the last refresh, drop
+    /// embeddings of deleted nodes, and persist them next to the graph.
+    /// Returns the number of nodes embedded.
+    pub fn refresh_embeddings(&mut self, provider: &dyn EmbeddingProvider) -> Result<usize, WillowError> {
+        self.require_writable()?;
+        let embedded = self.embeddings.refresh(&self.graph, provider)?;
+        storage::save_sidecar(&self.path, EMBEDDINGS_SIDECAR, &self.embeddings)?;
+        self.audit_call("refresh_embeddings", None);
+        Ok(embedded)
+    }
 
-        self.save_and_record(Change::AddLink {
-            link_id: link.id.clone(),
-            link: link.clone(),
-        })?;
+    /// The `k` nodes in the current profile most similar to `query_embedding`.
+    pub fn semantic_search(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<search::SearchResult>, WillowError> {
+        self.embeddings
+            .search(&self.graph, query_embedding, k, Some(self.profile_root()), options)
+    }
 
-        Ok(link)
+    /// Keyword and semantic search over the current profile, fused by rank.
+    /// Nodes without a fresh embedding can still match on keywords.
+    pub fn hybrid_search(
+        &self,
+        query: &str,
+        query_embedding: &[f32],
+        page: &Page,
+        options: &SearchOptions,
+    ) -> Result<SearchPage, WillowError> {
+        let root = self.profile_root();
+        let keyword = search::search_nodes(&self.graph, query, Some(root), &self.configured(options));
+        let semantic = self
+            .embeddings
+            .search(&self.graph, query_embedding, usize::MAX, Some(root), options)?;
+        Ok(Self::paginate(vector::fuse(keyword, semantic), page))
     }
 
-    pub fn update_link(
-        &mut self,
-        link_id: &str,
-        relation: Option<&str>,
-        bidirectional: Option<bool>,
-        confidence: Option<&str>,
-    ) -> Result<Link, WillowError> {
-        debug!(link_id = %link_id, "update_link");
-        let lid = LinkId(link_id.to_string());
+    // ---- Limits ----
 
-        let old_link = self
-            .graph
-            .links
-            .get(&lid)
-            .ok_or_else(|| WillowError::LinkNotFound(link_id.to_string()))?
-            .clone();
+    /// Replace the size limits and persist them next to the graph.
+    /// Existing nodes over a new limit are not touched.
+    pub fn set_limits(&mut self, limits: Limits) -> Result<(), WillowError> {
+        self.require_writable()?;
+        storage::save_sidecar(&self.path, LIMITS_SIDECAR, &limits)?;
+        self.limits = limits;
+        self.audit_call("set_limits", None);
+        Ok(())
+    }
 
-        let confidence_level = Self::parse_confidence(confidence)?;
+    // ---- History retention ----
 
-        let link = self.graph.links.get_mut(&lid).unwrap();
+    /// Replace the retention policy and persist it next to the graph. Existing
+    /// histories are pruned on their next update or by `prune_history`.
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) -> Result<(), WillowError> {
+        self.require_writable()?;
+        storage::save_sidecar(&self.path, RETENTION_SIDECAR, &policy)?;
+        self.retention = policy;
+        self.audit_call("set_retention_policy", None);
+        Ok(())
+    }
 
-        if let Some(r) = relation {
-            link.relation = r.to_string();
+    /// Apply the retention policy to every node's history. Returns the number
+    /// of values removed.
+    pub fn prune_history(&mut self, now: DateTime<Utc>) -> Result<usize, WillowError> {
+        self.require_writable()?;
+        self.load_history()?;
+        let mut pruned = 0;
+        for node in self.graph.nodes.values_mut().map(Arc::make_mut) {
+            pruned += self.retention.apply(&mut node.previous_values, now);
         }
-        if let Some(b) = bidirectional {
-            link.bidirectional = b;
+        info!(pruned, "prune_history");
+        if pruned > 0 {
+            self.write_graph()?;
+            self.audit_call("prune_history", None);
         }
-        if let Some(c) = confidence_level {
-            link.confidence = Some(c);
+        Ok(pruned)
+    }
+
+    // ---- Relation registry ----
+
+    /// Replace the relation registry and persist it next to the graph.
+    /// Existing links are not re-validated.
+    pub fn set_relation_registry(&mut self, registry: RelationRegistry) -> Result<(), WillowError> {
+        self.require_writable()?;
+        storage::save_sidecar(&self.path, RELATIONS_SIDECAR, &registry)?;
+        self.relations = registry;
+        self.audit_call("set_relation_registry", None);
+        Ok(())
+    }
+
+    pub fn list_relations(&self) -> Vec<RelationUsage> {
+        self.relations.usage(&self.graph)
+    }
+
+    // ---- Integrity ----
+
+    pub fn validate(&self) -> Vec<IntegrityIssue> {
+        integrity::validate(&self.graph)
+    }
+
+    /// Repair reparable integrity issues. Repairs are not expressible as
+    /// changes, so with VCS enabled the result is committed as a snapshot
+    /// (together with any pending changes) and undo history is cleared.
+    pub fn repair(&mut self) -> Result<RepairReport, WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        self.load_history()?;
+        let mut graph = self.graph.clone();
+        let report = integrity::repair(&mut graph);
+        info!(repaired = report.repaired.len(), remaining = report.remaining.len(), "repair");
+        if report.repaired.is_empty() {
+            return Ok(report);
+        }
+        self.apply_graph(graph)?;
+        self.audit_call("repair", None);
+        if let Some(repo) = &self.repo {
+            let hash = repo.commit_if_changed(
+                &CommitInput {
+                    message: format!("Repair {} integrity issue(s)", report.repaired.len()),
+                    source: CommitSource::Maintenance { job_id: None },
+                },
+                &self.graph,
+            )?;
+            if let Some(hash) = hash {
+                self.notify_commit(&hash);
+                self.emit(StoreEvent::CommitCreated(hash));
+            }
         }
+        Ok(report)
+    }
 
-        let new_link = link.clone();
+    /// Nodes at the top of subtrees that lost their connection to the root.
+    /// These are invisible to search and tree queries.
+    pub fn list_orphans(&self) -> Vec<Node> {
+        integrity::orphans(&self.graph)
+            .iter()
+            .map(|id| Node::clone(&self.graph.nodes[id]))
+            .collect()
+    }
 
-        self.save_and_record(Change::UpdateLink {
-            link_id: lid,
-            old_link,
-            new_link: new_link.clone(),
+    /// Reattach an orphan (and its subtree) under a reachable parent.
+    pub fn adopt_orphan(&mut self, node_id: &str, parent_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %node_id, parent = %parent_id, "adopt_orphan");
+        let nid = NodeId(node_id.into());
+        let pid = NodeId(parent_id.into());
+        let node = self.get_node(node_id)?;
+        if !self.graph.nodes.contains_key(&pid) {
+            return Err(WillowError::ParentNotFound(parent_id.to_string()));
+        }
+        let reachable = integrity::reachable_from_root(&self.graph);
+        if reachable.contains(&nid) {
+            return Err(WillowError::NotAnOrphan(node_id.to_string()));
+        }
+        if !reachable.contains(&pid) {
+            return Err(WillowError::OrphanParent(parent_id.to_string()));
+        }
+        self.limits.check_children(&self.graph.nodes[&pid], 1)?;
+
+        let mut change = Change::ReparentNode {
+            node_id: nid.clone(),
+            old_parent: node.parent_id.clone(),
+            new_parent: Some(pid),
+            old_index: None,
+            new_index: None,
+            actor: None,
+        };
+        apply_recorded(&mut self.graph, std::slice::from_mut(&mut change));
+        self.record_change(change)?;
+        Ok(Node::clone(&self.graph.nodes[&nid]))
+    }
+
+    // ---- Attachments ----
+
+    /// Store `bytes` in the blob store and reference it from a node.
+    /// Attaching the same content twice returns the existing reference.
+    pub fn attach_blob(&mut self, node_id: &str, bytes: &[u8], mime: &str) -> Result<AttachmentRef, WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %node_id, size = bytes.len(), mime = %mime, "attach_blob");
+        let nid = NodeId(node_id.into());
+        self.get_node(node_id)?;
+        let hash = self.blobs.put(bytes)?;
+        if let Some(existing) = self.graph.nodes[&nid].attachments.iter().find(|a| a.hash == hash) {
+            return Ok(existing.clone());
+        }
+
+        let attachment = AttachmentRef {
+            hash,
+            mime: mime.to_string(),
+            size: bytes.len() as u64,
+        };
+        self.graph.nodes.get_mut(&nid).map(Arc::make_mut).unwrap().attachments.push(attachment.clone());
+        self.record_change(Change::AttachBlob {
+            node_id: nid,
+            attachment: attachment.clone(),
+            actor: None,
         })?;
+        Ok(attachment)
+    }
 
-        Ok(new_link)
+    /// Remove a node's reference to a blob. The blob itself stays until `gc_attachments`.
+    pub fn detach_blob(&mut self, node_id: &str, hash: &str) -> Result<(), WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %node_id, hash = %hash, "detach_blob");
+        let nid = NodeId(node_id.into());
+        let attachment = self
+            .get_node(node_id)?
+            .attachments
+            .iter()
+            .find(|a| a.hash == hash)
+            .cloned()
+            .ok_or_else(|| WillowError::AttachmentNotFound(hash.to_string()))?;
+        self.graph.nodes.get_mut(&nid).map(Arc::make_mut).unwrap().attachments.retain(|a| a.hash != hash);
+        self.record_change(Change::DetachBlob {
+            node_id: nid,
+            attachment,
+            actor: None,
+        })
     }
 
-    pub fn delete_link(&mut self, link_id: &str) -> Result<Link, WillowError> {
-        debug!(link_id = %link_id, "delete_link");
-        let lid = LinkId(link_id.to_string());
+    pub fn get_attachment(&self, hash: &str) -> Result<Vec<u8>, WillowError> {
+        self.blobs.get(hash)
+    }
 
-        let link = self
-            .graph
-            .links
-            .remove(&lid)
-            .ok_or_else(|| WillowError::LinkNotFound(link_id.to_string()))?;
+    /// Delete blobs that nothing can reach any more: not the graph, not
+    /// pending changes, not the undo/redo history of any handle on the
+    /// store, and not any commit in VCS history.
+    pub fn gc_attachments(&self, progress: &mut Progress) -> Result<Vec<String>, WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        let mut referenced = HashSet::new();
+        attachments::collect_graph_refs(&self.graph, &mut referenced);
+        attachments::collect_change_refs(&self.pending_changes, &mut referenced);
+        let mut collect_history = |session: &Session| {
+            for group in session.undo_stack.iter().chain(&session.redo_stack) {
+                attachments::collect_change_refs(group, &mut referenced);
+            }
+        };
+        collect_history(&self.session);
+        for session in self.sessions.iter().filter_map(Weak::upgrade) {
+            // The caller's own session is locked by its guard, and in place.
+            match session.try_lock() {
+                Ok(other) => collect_history(&other),
+                Err(TryLockError::Poisoned(other)) => collect_history(&other.into_inner()),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+        if let Some(repo) = &self.repo {
+            referenced.extend(repo.referenced_attachments(progress)?);
+        }
+        let removed = self.blobs.gc(&referenced)?;
+        info!(removed = removed.len(), "gc_attachments");
+        Ok(removed)
+    }
 
-        self.save_and_record(Change::RemoveLink {
-            link_id: lid,
-            link: link.clone(),
+    // ---- Expiry sweep ----
+
+    /// Apply `policy` to every node whose validity ended before `now`, as one
+    /// undo step. With VCS enabled the sweep is committed on its own as a
+    /// maintenance commit, so there must be no other pending changes.
+    /// Metadata schemas are not enforced on the `expired` flag.
+    pub fn sweep_expired(
+        &mut self,
+        policy: &str,
+        now: DateTime<Utc>,
+        job_id: Option<String>,
+    ) -> Result<SweepReport, WillowError> {
+        self.require_writable()?;
+        let policy = ExpiryPolicy::from_str(policy)
+            .ok_or_else(|| WillowError::InvalidExpiryPolicy(policy.to_string()))?;
+        self.require_no_transaction()?;
+        if self.repo.is_some() && self.has_pending_changes() {
+            return Err(WillowError::HasPendingChanges);
+        }
+        let expired = self.expire_nodes(policy, now)?;
+        if expired.is_empty() {
+            return Ok(SweepReport::default());
+        }
+        let commit = match self.repo {
+            Some(_) => Some(self.commit(CommitInput {
+                message: format!("Sweep {} expired node(s)", expired.len()),
+                source: CommitSource::Maintenance { job_id },
+            })?),
+            None => None,
+        };
+        Ok(SweepReport { expired, commit })
+    }
+
+    /// The uncommitted part of `sweep_expired`.
+    fn expire_nodes(&mut self, policy: ExpiryPolicy, now: DateTime<Utc>) -> Result<Vec<NodeId>, WillowError> {
+        let expired = temporal::expired_nodes(&self.graph, now);
+        info!(policy = ?policy, expired = expired.len(), "sweep_expired");
+        if expired.is_empty() {
+            return Ok(expired);
+        }
+        if policy == ExpiryPolicy::Supersede {
+            self.load_history()?;
+        }
+
+        let mut changes = Vec::new();
+        let archive_id = match policy {
+            ExpiryPolicy::Archive => Some(self.archive_category(now, &mut changes)),
+            _ => None,
+        };
+        let expired_set: HashSet<&NodeId> = expired.iter().collect();
+
+        for id in &expired {
+            let node = &self.graph.nodes[id];
+            let mut metadata = node.metadata.clone();
+            metadata.insert(temporal::EXPIRED_KEY.to_string(), "true".to_string());
+            // Structured payloads can't hold a placeholder, so they are only flagged.
+            let new_content = (policy == ExpiryPolicy::Supersede && !node.content_format.is_structured()).then(|| {
+                let until = node.temporal.as_ref().and_then(|t| t.valid_until).unwrap_or(now);
+                format!("(expired {})", until.format("%Y-%m-%d"))
+            });
+            changes.push(Change::UpdateNode {
+                node_id: id.clone(),
+                old_content: new_content.as_ref().map(|_| node.content.clone()),
+                new_content,
+                old_metadata: Some(node.metadata.clone()),
+                new_metadata: Some(metadata),
+                actor: None,
+            });
+
+            // Expired nodes under an expired ancestor move with the ancestor.
+            if let Some(archive_id) = &archive_id {
+                let under_expired = Self::collect_ancestors(&self.graph, id)
+                    .iter()
+                    .any(|a| expired_set.contains(&a.id) || &a.id == archive_id);
+                if !under_expired {
+                    changes.push(Change::ReparentNode {
+                        node_id: id.clone(),
+                        old_parent: node.parent_id.clone(),
+                        new_parent: Some(archive_id.clone()),
+                        old_index: None,
+                        new_index: None,
+                        actor: None,
+                    });
+                }
+            }
+        }
+
+        let stamp = match policy {
+            ExpiryPolicy::Supersede => NodeStamp::with_history,
+            _ => NodeStamp::of,
+        };
+        let stamps: Vec<NodeStamp> = expired.iter().map(|id| stamp(&self.graph.nodes[id])).collect();
+        if policy == ExpiryPolicy::Supersede {
+            for id in &expired {
+                let node = self.graph.nodes.get_mut(id).map(Arc::make_mut).unwrap();
+                if node.content_format.is_structured() {
+                    continue;
+                }
+                node.previous_values.push(SupersededValue {
+                    old_content: node.content.clone(),
+                    superseded_at: now,
+                    reason: Some("expired".to_string()),
+                });
+            }
+        }
+        apply_recorded(&mut self.graph, &mut changes);
+        for (id, old_stamp) in expired.iter().zip(stamps) {
+            let node = self.graph.nodes.get_mut(id).map(Arc::make_mut).unwrap();
+            node.updated_at = now;
+            node.updated_by = self.session.actor.clone();
+            changes.push(Change::StampNode {
+                node_id: id.clone(),
+                old_stamp,
+                new_stamp: stamp(node),
+                actor: None,
+            });
+        }
+        self.record_changes(changes)?;
+        Ok(expired)
+    }
+
+    // ---- Rules ----
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.graph.rules
+    }
+
+    /// Replace the graph's maintenance rules. They are versioned and undone
+    /// like any other change, and from the next operation on apply to the
+    /// nodes each operation touches; `run_rules` applies them to the rest.
+    pub fn set_rules(&mut self, rules: Vec<Rule>) -> Result<(), WillowError> {
+        self.require_writable()?;
+        rules::validate(&rules, &self.graph)?;
+        info!(rules = rules.len(), "set_rules");
+        let change = Change::SetRules {
+            old_rules: self.graph.rules.clone(),
+            new_rules: rules,
+            actor: None,
+        };
+        apply_delta(&mut self.graph, &Delta::new(vec![change.clone()]));
+        self.record_change(change)
+    }
+
+    /// Apply the rules to every node. Like `sweep_expired`, this is a
+    /// maintenance pass: with VCS enabled its changes are committed on
+    /// their own, so there must be no pending changes.
+    pub fn run_rules(&mut self, job_id: Option<String>) -> Result<RulesReport, WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        if self.repo.is_some() && self.has_pending_changes() {
+            return Err(WillowError::HasPendingChanges);
+        }
+        let mut changes = rules::evaluate(&self.graph, self.graph.nodes.keys(), Utc::now());
+        let mut report = RulesReport::new(&changes);
+        info!(changed = report.changed.len(), links = report.links_created, "run_rules");
+        if changes.is_empty() {
+            return Ok(report);
+        }
+        apply_recorded(&mut self.graph, &mut changes);
+        self.record_changes(changes)?;
+
+        if self.repo.is_some() {
+            report.commit = Some(self.commit(CommitInput {
+                message: format!("Apply rules to {} node(s)", report.changed.len()),
+                source: CommitSource::Maintenance { job_id },
+            })?);
+        }
+        Ok(report)
+    }
+
+    // ---- Maintenance jobs ----
+
+    pub fn maintenance_schedule(&self) -> Result<MaintenanceSchedule, WillowError> {
+        self.require_repo()?.maintenance_schedule()
+    }
+
+    /// Have `run_due_jobs` run `job` every `interval_hours`, in place of any
+    /// job of the same kind. The schedule is kept in the repository.
+    pub fn register_job(&mut self, job: MaintenanceJob, interval_hours: u32) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let repo = self.require_repo()?;
+        let mut schedule = repo.maintenance_schedule()?;
+        let name = job.name();
+        schedule.register(job, interval_hours);
+        repo.set_maintenance_schedule(&schedule)?;
+        self.audit_call("register_job", Some(name));
+        Ok(())
+    }
+
+    /// Stop running the job named `name`. Returns whether it was registered.
+    pub fn unregister_job(&mut self, name: &str) -> Result<bool, WillowError> {
+        self.require_writable()?;
+        let repo = self.require_repo()?;
+        let mut schedule = repo.maintenance_schedule()?;
+        if !schedule.unregister(name) {
+            return Ok(false);
+        }
+        repo.set_maintenance_schedule(&schedule)?;
+        self.audit_call("unregister_job", Some(name));
+        Ok(true)
+    }
+
+    /// Run the jobs due at `now` in registration order, and commit what they
+    /// changed as one Maintenance commit, made even if they changed nothing
+    /// so that every run shows in the history. A job that fails is recorded
+    /// as such and the others still run. Hosts call this periodically, e.g.
+    /// on startup and then hourly.
+    pub fn run_due_jobs(&mut self, now: DateTime<Utc>) -> Result<MaintenanceReport, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("run_due_jobs");
+        self.require_repo_idle()?;
+        if self.has_pending_changes() {
+            return Err(WillowError::HasPendingChanges);
+        }
+        let mut schedule = self.require_repo()?.maintenance_schedule()?;
+        let due = schedule.due(now);
+        info!(due = due.len(), "run_due_jobs");
+        if due.is_empty() {
+            return Ok(MaintenanceReport::default());
+        }
+
+        let mut runs = Vec::new();
+        for &i in &due {
+            let job = schedule.jobs[i].job.clone();
+            let (summary, failed) = match self.run_job(&job, now) {
+                Ok(summary) => (summary, false),
+                Err(e) => {
+                    warn!(job = job.name(), error = %e, "maintenance job failed");
+                    (e.to_string(), true)
+                }
+            };
+            runs.push((i, JobRun { at: now, summary, failed, commit: None }));
+        }
+        let summaries: Vec<String> = runs
+            .iter()
+            .map(|(i, run)| format!("{}: {}", schedule.jobs[*i].job.name(), run.summary))
+            .collect();
+        let names: Vec<&str> = runs.iter().map(|(i, _)| schedule.jobs[*i].job.name()).collect();
+        let hash = self.commit(CommitInput {
+            message: format!("Maintenance: {}", summaries.join("; ")),
+            source: CommitSource::Maintenance { job_id: Some(names.join(",")) },
         })?;
 
-        Ok(link)
+        let mut report = MaintenanceReport {
+            runs: Vec::new(),
+            commit: Some(hash.clone()),
+        };
+        for (i, mut run) in runs {
+            run.commit = Some(hash.clone());
+            report.runs.push((schedule.jobs[i].job.name(), run.clone()));
+            schedule.jobs[i].last_run = Some(run);
+        }
+        self.require_repo()?.set_maintenance_schedule(&schedule)?;
+        Ok(report)
+    }
+
+    /// Run one job, leaving its changes pending. Returns what it did.
+    fn run_job(&mut self, job: &MaintenanceJob, now: DateTime<Utc>) -> Result<String, WillowError> {
+        Ok(match job {
+            MaintenanceJob::ExpireTemporal { policy } => {
+                format!("{} node(s) expired", self.expire_nodes(*policy, now)?.len())
+            }
+            MaintenanceJob::PruneHistory => format!("{} previous value(s) pruned", self.prune_history(now)?),
+            MaintenanceJob::DetectDuplicates { threshold } => {
+                format!("{} possible duplicate pair(s)", self.find_duplicates(*threshold).len())
+            }
+            MaintenanceJob::GcRepo => format!("{} unreachable commit(s) deleted", self.require_repo()?.gc()?),
+            MaintenanceJob::RebuildIndexes => {
+                self.reset_indexes();
+                format!("indexes rebuilt for {} node(s)", self.graph.nodes.len())
+            }
+        })
+    }
+
+    /// Find the root's "Archive" category, queueing its creation if missing.
+    fn archive_category(&self, now: DateTime<Utc>, changes: &mut Vec<Change>) -> NodeId {
+        let root = &self.graph.nodes[&self.graph.root_id];
+        let existing = root.children.iter().find(|id| {
+            self.graph
+                .nodes
+                .get(*id)
+                .is_some_and(|n| n.node_type == NodeType::Category && n.content == temporal::ARCHIVE_CONTENT)
+        });
+        if let Some(id) = existing {
+            return id.clone();
+        }
+        let node_id = NodeId(Uuid::new_v4().to_string().into());
+        changes.push(Change::CreateNode {
+            node_id: node_id.clone(),
+            node: Arc::new(Node {
+                id: node_id.clone(),
+                node_type: NodeType::Category,
+                content: temporal::ARCHIVE_CONTENT.to_string(),
+                parent_id: Some(root.id.clone()),
+                children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
+                metadata: HashMap::new(),
+                previous_values: Vec::new(),
+                temporal: None,
+                created_at: now,
+                updated_at: now,
+                created_by: self.session.actor.clone(),
+                updated_by: self.session.actor.clone(),
+            }),
+            positions: Vec::new(),
+            actor: None,
+        });
+        node_id
+    }
+
+    // ---- Undo / redo ----
+    //
+    // Operation-level history kept in memory only, independent of VCS.
+    // Undoing is itself a change, so it is recorded as pending for the next commit.
+
+    fn apply_history_step(&mut self, operation: &str, changes: Vec<Change>) -> Result<(), WillowError> {
+        let delta = Delta::new(changes);
+        apply_delta(&mut self.graph, &delta);
+        self.notify_indexes(&delta.changes);
+        if self.repo.is_some() {
+            self.pending_changes.extend(delta.changes.iter().cloned());
+        }
+        self.audit(AuditEntry {
+            operation: operation.to_string(),
+            ..AuditEntry::of_changes(self.session.actor.clone(), &delta.changes)
+        });
+        self.save()?;
+        self.journal_changes(&delta.changes)
+    }
+
+    /// Revert the most recent operation. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<bool, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("undo");
+        let Some(changes) = self.session.undo_stack.pop_back() else {
+            return Ok(false);
+        };
+        debug!("undo");
+        let inverse = invert_delta(&Delta::new(changes.clone()));
+        let applied = self.apply_history_step("undo", inverse.changes);
+        self.session.redo_stack.push(changes);
+        applied.map(|()| true)
+    }
+
+    /// Re-apply the most recently undone operation. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> Result<bool, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("redo");
+        let Some(changes) = self.session.redo_stack.pop() else {
+            return Ok(false);
+        };
+        debug!("redo");
+        let applied = self.apply_history_step("redo", changes.clone());
+        self.push_undo(changes);
+        applied.map(|()| true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.session.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.session.redo_stack.is_empty()
+    }
+
+    // ---- Webhooks ----
+
+    /// Whether a webhook wants `event`, so a summary is only worked out
+    /// when someone will receive it.
+    fn wants_webhook(&self, event: WebhookEvent) -> bool {
+        self.repo.as_ref().is_some_and(|r| r.config().webhooks.iter().any(|h| h.wants(event)))
+    }
+
+    /// Send `event` to the webhooks that want it. `branch` defaults to the
+    /// current branch.
+    fn notify_webhooks(
+        &self,
+        event: WebhookEvent,
+        branch: Option<&str>,
+        source: Option<&str>,
+        commit: Option<crate::vcs::types::CommitHash>,
+        summary: ChangeSummary,
+    ) {
+        let Some(repo) = &self.repo else { return };
+        let branch = match branch {
+            Some(branch) => Some(branch.to_string()),
+            None => repo.current_branch().unwrap_or_default(),
+        };
+        let payload = WebhookPayload {
+            event,
+            timestamp: Utc::now(),
+            branch,
+            source: source.map(str::to_string),
+            commit,
+            summary,
+        };
+        webhooks::deliver(&repo.config().webhooks, &payload);
+    }
+
+    /// Send the commit `hash`, with what it changed against its first
+    /// parent, to the webhooks that want commits.
+    fn notify_commit(&self, hash: &crate::vcs::types::CommitHash) {
+        if !self.wants_webhook(WebhookEvent::Commit) {
+            return;
+        }
+        match self.require_repo().and_then(|r| r.show_commit(hash)) {
+            Ok((_, summary)) => self.notify_webhooks(WebhookEvent::Commit, None, None, Some(hash.clone()), summary),
+            Err(e) => warn!(error = %e, commit = %hash, "could not summarize commit for webhooks"),
+        }
+    }
+
+    // ---- VCS methods ----
+
+    pub fn vcs_init(&mut self) -> Result<(), WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        self.load_history()?;
+        let graph_dir = self
+            .path
+            .parent()
+            .ok_or_else(|| WillowError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No parent directory for graph file",
+            )))?;
+
+        let repo = Repository::init(graph_dir, &self.graph)?;
+        self.repo = Some(repo);
+        self.pending_from_head = true;
+        self.audit_call("vcs_init", None);
+        Ok(())
+    }
+
+    pub fn has_pending_changes(&self) -> bool {
+        !self.pending_changes.is_empty()
+    }
+
+    #[cfg(test)]
+    pub fn pending_changes(&self) -> &[Change] {
+        &self.pending_changes
+    }
+
+    pub fn commit(&mut self, input: CommitInput) -> Result<crate::vcs::types::CommitHash, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("commit");
+        self.require_repo_idle()?;
+        self.load_history()?;
+        let repo = self.require_repo_idle()?;
+        let hash = repo.create_commit(&input, &self.pending_changes, &self.graph)?;
+        self.pending_changes.clear();
+        self.pending_from_head = true;
+        self.audit_call("commit", Some(&hash.0));
+        self.emit(StoreEvent::CommitCreated(hash.clone()));
+        self.notify_commit(&hash);
+        Ok(hash)
+    }
+
+    /// Commit the pending changes with a message describing them, e.g.
+    /// "Added 3 nodes under Career; updated 'Favorite food'". Returns the
+    /// commit and its message.
+    pub fn commit_auto(&mut self, source: CommitSource) -> Result<(crate::vcs::types::CommitHash, String), WillowError> {
+        self.require_writable()?;
+        self.require_repo_idle()?;
+        if self.pending_changes.is_empty() {
+            return Err(WillowError::NothingToCommit);
+        }
+        let message = crate::vcs::diff::describe(&self.uncommitted_changes()?);
+        let hash = self.commit(CommitInput {
+            message: message.clone(),
+            source,
+        })?;
+        Ok((hash, message))
+    }
+
+    /// Commit if the graph on disk differs from the last committed state.
+    /// Used after external processes modify the graph file.
+    pub fn commit_external_changes(&mut self, input: CommitInput) -> Result<Option<crate::vcs::types::CommitHash>, WillowError> {
+        self.require_writable()?;
+        self.require_repo_idle()?;
+        self.load_history()?;
+        let hash = self.require_repo_idle()?.commit_if_changed(&input, &self.graph)?;
+        self.pending_changes.clear();
+        self.pending_from_head = true;
+        if let Some(hash) = &hash {
+            self.audit_call("commit", Some(&hash.0));
+            self.emit(StoreEvent::CommitCreated(hash.clone()));
+            self.notify_commit(hash);
+        }
+        Ok(hash)
+    }
+
+    fn head_graph(&self) -> Result<Graph, WillowError> {
+        let repo = self.require_repo()?;
+        match repo.log(Some(1))?.first() {
+            Some(head) => repo.reconstruct_at(&head.hash),
+            None => Ok(Graph::empty(self.graph.root_id.clone())),
+        }
+    }
+
+    /// What the graph holds that HEAD does not. Read off the pending
+    /// changes when they are known to lead from HEAD, otherwise found by
+    /// diffing the whole graph against HEAD, as after opening a store on
+    /// uncommitted work or reloading another process's writes.
+    pub fn uncommitted_changes(&mut self) -> Result<crate::vcs::diff::ChangeSummary, WillowError> {
+        if self.pending_from_head {
+            return Ok(crate::vcs::diff::summarize_changes(&self.pending_changes, &self.graph));
+        }
+        let summary = crate::vcs::diff::compute_graph_diff(&self.head_graph()?, &self.graph);
+        if summary.is_empty() && self.pending_changes.is_empty() {
+            self.pending_from_head = true;
+        }
+        Ok(summary)
+    }
+
+    /// `uncommitted_changes` for the graph file rather than the graph in
+    /// memory. The two differ while a transaction, the journal or
+    /// write-behind holds back writes, and once another process has written
+    /// the file.
+    pub fn uncommitted_changes_on_disk(&mut self) -> Result<crate::vcs::diff::ChangeSummary, WillowError> {
+        let current = self.transaction.is_none() && self.journal_len.get() == 0 && self.unsaved.is_none();
+        if current && !self.changed_on_disk()? {
+            return self.uncommitted_changes();
+        }
+        let disk = storage::load_graph(&self.path)?;
+        Ok(crate::vcs::diff::compute_graph_diff(&self.head_graph()?, &disk))
+    }
+
+    pub fn discard_changes(&mut self) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let repo = self.require_repo_idle()?;
+        if let Some(head) = repo.log(Some(1))?.first() {
+            let graph = repo.reconstruct_at(&head.hash)?;
+            self.apply_graph(graph)?;
+        } else {
+            self.pending_changes.clear();
+        }
+        self.audit_call("discard_changes", None);
+        Ok(())
+    }
+
+    pub fn get_repo(&self) -> Result<&Repository, WillowError> {
+        self.require_repo()
+    }
+
+    pub fn set_repo_config(&mut self, config: crate::vcs::types::RepoConfig) -> Result<(), WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        self.repo.as_mut().ok_or(WillowError::VcsNotInitialized)?.set_config(config)?;
+        self.audit_call("set_repo_config", None);
+        Ok(())
+    }
+
+    /// See `Repository::train_snapshot_dictionary`.
+    pub fn train_snapshot_dictionary(&mut self, max_size: usize) -> Result<Option<u32>, WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        let dictionary = self
+            .repo
+            .as_mut()
+            .ok_or(WillowError::VcsNotInitialized)?
+            .train_snapshot_dictionary(max_size)?;
+        self.audit_call("train_snapshot_dictionary", None);
+        Ok(dictionary)
+    }
+
+    /// See `Repository::create_branch`.
+    pub fn create_branch(&mut self, name: &str) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let repo = self.require_repo()?;
+        repo.create_branch(name)?;
+        let head = repo.head_hash()?;
+        self.audit_call("create_branch", Some(name));
+        self.notify_webhooks(WebhookEvent::CreateBranch, Some(name), None, Some(head), ChangeSummary::default());
+        Ok(())
+    }
+
+    /// See `Repository::delete_branch`.
+    pub fn delete_branch(&mut self, name: &str) -> Result<(), WillowError> {
+        self.require_writable()?;
+        self.require_repo()?.delete_branch(name)?;
+        self.audit_call("delete_branch", Some(name));
+        self.notify_webhooks(WebhookEvent::DeleteBranch, Some(name), None, None, ChangeSummary::default());
+        Ok(())
+    }
+
+    /// Switch branch — replaces the in-memory graph and saves to disk.
+    pub fn switch_branch(&mut self, name: &str) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("switch_branch");
+        let graph = self.require_repo_idle()?.switch_branch(name, self.has_pending_changes())?;
+        let summary = self
+            .wants_webhook(WebhookEvent::SwitchBranch)
+            .then(|| compute_graph_diff(&self.graph, &graph));
+        self.apply_graph(graph)?;
+        self.audit_call("switch_branch", Some(name));
+        self.emit(StoreEvent::BranchSwitched(name.to_string()));
+        if let Some(summary) = summary {
+            let head = self.require_repo()?.head_hash()?;
+            self.notify_webhooks(WebhookEvent::SwitchBranch, Some(name), None, Some(head), summary);
+        }
+        Ok(())
+    }
+
+    /// Checkout a specific commit (detached HEAD).
+    pub fn checkout_commit(&mut self, hash: &crate::vcs::types::CommitHash) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let graph = self.require_repo_idle()?.checkout_commit(hash, self.has_pending_changes())?;
+        self.apply_graph(graph)?;
+        self.audit_call("checkout_commit", Some(&hash.0));
+        Ok(())
+    }
+
+    /// Restore to a past commit (creates a new commit).
+    pub fn restore_to_commit(&mut self, hash: &crate::vcs::types::CommitHash) -> Result<crate::vcs::types::CommitHash, WillowError> {
+        self.require_writable()?;
+        let (new_hash, graph) = self.require_repo_idle()?.restore_to_commit(hash, &self.graph)?;
+        self.apply_graph(graph)?;
+        self.audit_call("restore_to_commit", Some(&hash.0));
+        self.emit(StoreEvent::CommitCreated(new_hash.clone()));
+        self.notify_commit(&new_hash);
+        Ok(new_hash)
+    }
+
+    /// Merge a source branch into current. Returns Ok(hash) on success.
+    pub fn merge_branch(&mut self, source: &str) -> Result<crate::vcs::types::CommitHash, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("merge_branch");
+        match self.require_repo_idle()?.merge_branch(source, &self.graph)? {
+            crate::vcs::repository::MergeBranchResult::Success(hash, graph) => {
+                let summary = self
+                    .wants_webhook(WebhookEvent::Merge)
+                    .then(|| compute_graph_diff(&self.graph, &graph));
+                self.apply_graph(graph)?;
+                self.audit_call("merge_branch", Some(source));
+                self.emit(StoreEvent::CommitCreated(hash.clone()));
+                if let Some(summary) = summary {
+                    self.notify_webhooks(WebhookEvent::Merge, None, Some(source), Some(hash.clone()), summary);
+                }
+                Ok(hash)
+            }
+            crate::vcs::repository::MergeBranchResult::Conflicts { conflicts, .. } => {
+                Err(WillowError::MergeConflict(conflicts.len()))
+            }
+        }
+    }
+
+    // ---- Mutation methods ----
+
+    pub fn create_node(
+        &mut self,
+        parent_id: &str,
+        node_type: &str,
+        content: &str,
+        metadata: Option<HashMap<String, String>>,
+        temporal: Option<TemporalMetadata>,
+    ) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        self.create_node_with_format(parent_id, node_type, content, ContentFormat::Text, metadata, temporal)
+    }
+
+    /// Create a node whose content is `markdown` or a structured payload
+    /// (`list`, `table`, `record`) encoded as JSON. Payloads are validated
+    /// against the format.
+    pub fn create_structured_node(
+        &mut self,
+        parent_id: &str,
+        node_type: &str,
+        format: &str,
+        content: &str,
+        metadata: Option<HashMap<String, String>>,
+        temporal: Option<TemporalMetadata>,
+    ) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        let format = ContentFormat::from_str(format)
+            .ok_or_else(|| WillowError::InvalidContentFormat(format.to_string()))?;
+        self.create_node_with_format(parent_id, node_type, content, format, metadata, temporal)
+    }
+
+    fn create_node_with_format(
+        &mut self,
+        parent_id: &str,
+        node_type: &str,
+        content: &str,
+        format: ContentFormat,
+        metadata: Option<HashMap<String, String>>,
+        temporal: Option<TemporalMetadata>,
+    ) -> Result<Node, WillowError> {
+        let _timer = self.perf.time("create_node");
+        debug!(parent = %parent_id, node_type = %node_type, format = %format.as_str(), "create_node");
+        let node = self.insert_node(parent_id, node_type, content, format, metadata, temporal)?;
+        self.record_change(Change::CreateNode {
+            node_id: node.id.clone(),
+            node: node.clone().into(),
+            positions: child_positions(&self.graph, &node.id),
+            actor: None,
+        })?;
+        Ok(node)
+    }
+
+    /// Create many nodes, each under its parent and followed by its own
+    /// children, as one operation: one save, one undo entry, and nothing
+    /// created if any node is invalid. Returns the nodes in that order.
+    pub fn create_nodes(&mut self, nodes: Vec<(String, NewNode)>) -> Result<Vec<Node>, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("create_nodes");
+        let mut created = Vec::new();
+        let mut changes = Vec::new();
+        let mut stack: Vec<(String, NewNode)> = nodes.into_iter().rev().collect();
+        while let Some((parent_id, new)) = stack.pop() {
+            let format = match new.format.as_deref() {
+                None => Ok(ContentFormat::Text),
+                Some(f) => ContentFormat::from_str(f).ok_or_else(|| WillowError::InvalidContentFormat(f.to_string())),
+            };
+            let inserted = format.and_then(|format| {
+                self.insert_node(&parent_id, &new.node_type, &new.content, format, new.metadata, new.temporal)
+            });
+            let node = match inserted {
+                Ok(node) => node,
+                Err(e) => {
+                    apply_delta(&mut self.graph, &invert_delta(&Delta::new(changes)));
+                    return Err(e);
+                }
+            };
+            stack.extend(new.children.into_iter().rev().map(|child| (node.id.0.to_string(), child)));
+            changes.push(Change::CreateNode {
+                node_id: node.id.clone(),
+                node: node.clone().into(),
+                positions: child_positions(&self.graph, &node.id),
+                actor: None,
+            });
+            created.push(node);
+        }
+        info!(nodes = created.len(), "create_nodes");
+        if !changes.is_empty() {
+            self.record_changes(changes)?;
+        }
+        Ok(created)
+    }
+
+    /// Validate a new node and add it to the graph, without saving or
+    /// recording it.
+    fn insert_node(
+        &mut self,
+        parent_id: &str,
+        node_type: &str,
+        content: &str,
+        format: ContentFormat,
+        metadata: Option<HashMap<String, String>>,
+        temporal: Option<TemporalMetadata>,
+    ) -> Result<Node, WillowError> {
+        let parent_nid = NodeId(parent_id.into());
+
+        if !self.graph.nodes.contains_key(&parent_nid) {
+            return Err(WillowError::ParentNotFound(parent_id.to_string()));
+        }
+
+        let nt = NodeType::from_str(node_type)
+            .ok_or_else(|| WillowError::InvalidNodeType(node_type.to_string()))?;
+        let metadata = metadata.unwrap_or_default();
+        self.schema.validate(&nt, &metadata)?;
+        format.validate(content)?;
+        self.limits.check_content(content)?;
+        self.limits.check_metadata(&metadata)?;
+        self.limits.check_children(&self.graph.nodes[&parent_nid], 1)?;
+
+        let now = Utc::now();
+        let node_id = NodeId(Uuid::new_v4().to_string().into());
+
+        let node = Node {
+            id: node_id.clone(),
+            node_type: nt,
+            content: content.to_string(),
+            parent_id: Some(parent_nid.clone()),
+            children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: format,
+            attachments: Vec::new(),
+            metadata,
+            previous_values: Vec::new(),
+            temporal,
+            created_at: now,
+            updated_at: now,
+            created_by: self.session.actor.clone(),
+            updated_by: self.session.actor.clone(),
+        };
+
+        // Add to parent's children
+        self.graph
+            .nodes
+            .get_mut(&parent_nid)
+            .map(Arc::make_mut)
+            .unwrap()
+            .children
+            .push(node_id.clone());
+
+        self.graph.nodes.insert(node_id, node.clone().into());
+        Ok(node)
+    }
+
+    pub fn get_context(
+        &self,
+        node_id: &str,
+        depth: Option<u32>,
+        options: &ContextOptions,
+    ) -> Result<ContextResult, WillowError> {
+        let _timer = self.perf.time("get_context");
+        Self::context_in(&self.graph, &self.links_by_node, self.resolve_profile_node(node_id), depth, options)
+    }
+
+    /// `get_context` against the graph as it was valid at `date`.
+    pub fn get_context_as_of(
+        &self,
+        node_id: &str,
+        depth: Option<u32>,
+        date: DateTime<Utc>,
+    ) -> Result<ContextResult, WillowError> {
+        let view = temporal::view_as_of(&self.graph, date);
+        Self::context_in(
+            &view,
+            &LinkIndex::build(&view),
+            self.resolve_profile_node(node_id),
+            depth,
+            &ContextOptions::default(),
+        )
+    }
+
+    fn context_in(
+        graph: &Graph,
+        links_by_node: &LinkIndex,
+        node_id: &str,
+        depth: Option<u32>,
+        options: &ContextOptions,
+    ) -> Result<ContextResult, WillowError> {
+        let nid = NodeId(node_id.into());
+        let mut node = graph
+            .nodes
+            .get(&nid)
+            .cloned()
+            .ok_or_else(|| WillowError::NodeNotFound(node_id.to_string()))?;
+
+        let mut ancestors = Self::collect_ancestors(graph, &nid);
+
+        let max_depth = depth.unwrap_or(2);
+        let mut descendants = Vec::new();
+        Self::collect_descendants(graph, &nid, max_depth, options, &mut descendants);
+        let truncated = options.max_descendants.is_some_and(|max| descendants.len() > max);
+        if let Some(max) = options.max_descendants {
+            descendants.truncate(max);
+        }
+        if options.omit_previous_values {
+            for n in std::iter::once(&mut node).chain(&mut ancestors).chain(&mut descendants) {
+                if !n.previous_values.is_empty() {
+                    Arc::make_mut(n).previous_values.clear();
+                }
+            }
+        }
+
+        let involved_ids: HashSet<&NodeId> = std::iter::once(&nid)
+            .chain(ancestors.iter().map(|n| &n.id))
+            .chain(descendants.iter().map(|n| &n.id))
+            .collect();
+
+        let mut seen_links = HashSet::new();
+        let links = involved_ids
+            .iter()
+            .flat_map(|id| links_by_node.links_of(graph, id))
+            .filter(|link| seen_links.insert(&link.id))
+            .cloned()
+            .collect();
+
+        Ok(ContextResult {
+            node,
+            ancestors,
+            descendants,
+            links,
+            truncated,
+        })
+    }
+
+    /// The graph restricted to nodes valid at `date`.
+    pub fn view_as_of(&self, date: DateTime<Utc>) -> Graph {
+        temporal::view_as_of(&self.graph, date)
+    }
+
+    /// Follow links in either direction up to `link_depth` hops from a node.
+    pub fn get_neighborhood(
+        &self,
+        node_id: &str,
+        link_depth: Option<u32>,
+    ) -> Result<NeighborhoodResult, WillowError> {
+        let node = self.get_node(node_id)?.clone();
+        let max_hops = link_depth.unwrap_or(1);
+
+        let mut seen: HashSet<&NodeId> = HashSet::from([&node.id]);
+        let mut queue: VecDeque<(&NodeId, u32)> = VecDeque::from([(&node.id, 0)]);
+        let mut neighbors = Vec::new();
+        while let Some((id, hops)) = queue.pop_front() {
+            if hops >= max_hops {
+                continue;
+            }
+            for link in self.links_by_node.links_of(&self.graph, id) {
+                let next = if link.from_node == *id { &link.to_node } else { &link.from_node };
+                if !seen.insert(next) {
+                    continue;
+                }
+                if let Some(n) = self.graph.nodes.get(next) {
+                    neighbors.push(Neighbor { node: Node::clone(n), hops: hops + 1 });
+                    queue.push_back((next, hops + 1));
+                }
+            }
+        }
+
+        let mut seen_links = HashSet::new();
+        let links = seen
+            .iter()
+            .flat_map(|id| self.links_by_node.links_of(&self.graph, id))
+            .filter(|l| seen.contains(&l.from_node) && seen.contains(&l.to_node) && seen_links.insert(&l.id))
+            .cloned()
+            .collect();
+
+        Ok(NeighborhoodResult {
+            node,
+            neighbors,
+            links,
+        })
+    }
+
+    /// Gather the requested nodes, their ancestors and their most linked
+    /// neighbors, then keep as many as fit in `max_chars` of content.
+    /// Sensitive ancestors and neighbors are left out; requested nodes are not.
+    /// Candidates are admitted pinned first, then most recently updated, then
+    /// closest; entries keep requested / ancestor / neighbor order.
+    pub fn build_context(&self, node_ids: &[String], max_chars: usize) -> Result<AssembledContext, WillowError> {
+        let _timer = self.perf.time("build_context");
+        let mut candidates: Vec<ContextEntry> = Vec::new();
+        let mut seen: HashSet<NodeId> = HashSet::new();
+        for id in node_ids {
+            let node = self.get_node(id)?;
+            if seen.insert(node.id.clone()) {
+                candidates.push(ContextEntry {
+                    node: node.clone(),
+                    role: ContextRole::Requested,
+                    depth: 0,
+                });
+            }
+        }
+        let requested: Vec<NodeId> = candidates.iter().map(|e| e.node.id.clone()).collect();
+
+        let mut queue: VecDeque<(&NodeId, u32)> = requested.iter().map(|id| (id, 0)).collect();
+        while let Some((id, depth)) = queue.pop_front() {
+            for pid in self.graph.nodes[id].parents() {
+                let Some(parent) = self.graph.nodes.get(pid) else { continue };
+                if seen.insert(pid.clone()) {
+                    if parent.sensitivity.is_normal() {
+                        candidates.push(ContextEntry {
+                            node: Node::clone(parent),
+                            role: ContextRole::Ancestor,
+                            depth: depth + 1,
+                        });
+                    }
+                    queue.push_back((pid, depth + 1));
+                }
+            }
+        }
+
+        let requested_set: HashSet<&NodeId> = requested.iter().collect();
+        let mut link_counts: HashMap<&NodeId, usize> = HashMap::new();
+        let requested_links = requested_set.iter().flat_map(|id| {
+            self.links_by_node.links_of(&self.graph, id).map(move |link| (*id, link))
+        });
+        for (id, link) in requested_links {
+            let other = if link.from_node == *id { &link.to_node } else { &link.from_node };
+            let visible = self.graph.nodes.get(other).is_some_and(|n| n.sensitivity.is_normal());
+            if !seen.contains(other) && visible {
+                *link_counts.entry(other).or_default() += 1;
+            }
+        }
+        let mut neighbors: Vec<(&NodeId, usize)> = link_counts.into_iter().collect();
+        neighbors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0 .0.cmp(&b.0 .0)));
+        for (id, _) in neighbors.into_iter().take(CONTEXT_NEIGHBOR_LIMIT) {
+            candidates.push(ContextEntry {
+                node: Node::clone(&self.graph.nodes[id]),
+                role: ContextRole::Neighbor,
+                depth: 1,
+            });
+        }
+
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&candidates[a], &candidates[b]);
+            b.node
+                .pinned
+                .cmp(&a.node.pinned)
+                .then_with(|| b.node.updated_at.cmp(&a.node.updated_at))
+                .then_with(|| a.depth.cmp(&b.depth))
+        });
+        let mut included = vec![false; candidates.len()];
+        let mut used_chars = 0;
+        for i in order {
+            let cost = candidates[i].node.content.chars().count();
+            if used_chars + cost <= max_chars {
+                used_chars += cost;
+                included[i] = true;
+            }
+        }
+
+        let omitted = included.iter().filter(|i| !**i).count();
+        let entries: Vec<ContextEntry> = candidates
+            .into_iter()
+            .zip(included)
+            .filter_map(|(entry, keep)| keep.then_some(entry))
+            .collect();
+        let ids: HashSet<&NodeId> = entries.iter().map(|e| &e.node.id).collect();
+        let links = self
+            .graph
+            .links
+            .values()
+            .filter(|l| ids.contains(&l.from_node) && ids.contains(&l.to_node))
+            .cloned()
+            .collect();
+
+        Ok(AssembledContext {
+            entries,
+            links,
+            used_chars,
+            omitted,
+        })
+    }
+
+    /// Copy a subtree and the links between its nodes into a standalone graph
+    /// rooted at `root_node_id`. References to nodes outside the subtree are
+    /// dropped, as are descendants above `max_sensitivity` and everything
+    /// beneath them.
+    pub fn extract_subgraph(&self, root_node_id: &str, max_sensitivity: Sensitivity) -> Result<Graph, WillowError> {
+        let root = self.get_node(root_node_id)?;
+        let mut ids = vec![root.id.clone()];
+        self.collect_descendant_ids(&root.id, &mut ids);
+        let mut hidden = Vec::new();
+        for id in &ids[1..] {
+            if self.graph.nodes[id].sensitivity > max_sensitivity {
+                hidden.push(id.clone());
+                self.collect_descendant_ids(id, &mut hidden);
+            }
+        }
+        let hidden: HashSet<NodeId> = hidden.into_iter().collect();
+        ids.retain(|id| !hidden.contains(id));
+        let members: HashSet<&NodeId> = ids.iter().collect();
+
+        let mut nodes = HashMap::new();
+        for id in &ids {
+            let mut node = Node::clone(&self.graph.nodes[id]);
+            if id == &root.id {
+                node.parent_id = None;
+                node.extra_parents.clear();
+            } else {
+                let mut parents: Vec<NodeId> = node.parents().filter(|p| members.contains(p)).cloned().collect();
+                node.parent_id = Some(parents.remove(0));
+                node.extra_parents = parents;
+            }
+            node.children.retain(|c| members.contains(c));
+            nodes.insert(id.clone(), Arc::new(node));
+        }
+
+        let links = self
+            .graph
+            .links
+            .iter()
+            .filter(|(_, l)| members.contains(&l.from_node) && members.contains(&l.to_node))
+            .map(|(id, l)| (id.clone(), l.clone()))
+            .collect();
+
+        Ok(Graph {
+            root_id: root.id.clone(),
+            nodes,
+            links,
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
+        })
+    }
+
+    /// Write `extract_subgraph` to a graph file that another store can open.
+    pub fn export_subgraph(
+        &self,
+        root_node_id: &str,
+        path: &Path,
+        max_sensitivity: Sensitivity,
+    ) -> Result<(), WillowError> {
+        let graph = self.extract_subgraph(root_node_id, max_sensitivity)?;
+        info!(root = %root_node_id, nodes = graph.nodes.len(), path = %path.display(), "export_subgraph");
+        storage::save_graph(path, &graph, &self.storage)
+    }
+
+    /// Nodes as a CSV table for spreadsheets and data frames; see
+    /// `csv_export::nodes_csv`.
+    pub fn export_csv_nodes(&self, max_sensitivity: Sensitivity) -> String {
+        csv_export::nodes_csv(&self.graph, max_sensitivity)
+    }
+
+    /// Links as a CSV table; see `csv_export::links_csv`.
+    pub fn export_csv_links(&self, max_sensitivity: Sensitivity) -> String {
+        csv_export::links_csv(&self.graph, max_sensitivity)
+    }
+
+    /// The whole current graph, history included, encoded for a backup.
+    pub fn export_graph(&mut self, format: GraphFormat) -> Result<Vec<u8>, WillowError> {
+        self.load_history()?;
+        let data = snapshot::encode(&self.graph, format)?;
+        info!(format = format.as_str(), bytes = data.len(), "export_graph");
+        Ok(data)
+    }
+
+    /// Replace the graph with one from `export_graph`, detecting the format
+    /// when not given. Like `repair`, the result is committed as a snapshot
+    /// when VCS is enabled and undo history is cleared.
+    pub fn import_graph(&mut self, data: &[u8], format: Option<GraphFormat>) -> Result<(), WillowError> {
+        self.require_writable()?;
+        self.require_no_transaction()?;
+        let graph = snapshot::decode(data, format)?;
+        info!(nodes = graph.nodes.len(), links = graph.links.len(), "import_graph");
+        self.apply_graph(graph)?;
+        self.audit_call("import_graph", None);
+        if let Some(repo) = &self.repo {
+            let hash = repo.commit_if_changed(
+                &CommitInput {
+                    message: "Import graph".to_string(),
+                    source: CommitSource::Maintenance { job_id: None },
+                },
+                &self.graph,
+            )?;
+            if let Some(hash) = hash {
+                self.notify_commit(&hash);
+                self.emit(StoreEvent::CommitCreated(hash));
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_ancestors(graph: &Graph, node_id: &NodeId) -> Vec<Arc<Node>> {
+        let mut ancestors = Vec::new();
+        let mut seen: HashSet<&NodeId> = HashSet::from([node_id]);
+        let mut queue: VecDeque<&NodeId> = VecDeque::from([node_id]);
+        while let Some(id) = queue.pop_front() {
+            let Some(node) = graph.nodes.get(id) else { continue };
+            for pid in node.parents() {
+                if !seen.insert(pid) {
+                    continue;
+                }
+                if let Some(parent) = graph.nodes.get(pid) {
+                    ancestors.push(Arc::clone(parent));
+                    queue.push_back(pid);
+                }
+            }
+        }
+        ancestors
+    }
+
+    /// Collects at most one more than `options.max_descendants`, so the
+    /// caller can tell whether anything was cut off.
+    fn collect_descendants(
+        graph: &Graph,
+        node_id: &NodeId,
+        max_depth: u32,
+        options: &ContextOptions,
+        result: &mut Vec<Arc<Node>>,
+    ) {
+        let mut seen = HashSet::from([node_id.clone()]);
+        Self::collect_descendants_inner(graph, node_id, max_depth, 0, options, result, &mut seen);
+    }
+
+    fn collect_descendants_inner(
+        graph: &Graph,
+        node_id: &NodeId,
+        max_depth: u32,
+        current_depth: u32,
+        options: &ContextOptions,
+        result: &mut Vec<Arc<Node>>,
+        seen: &mut HashSet<NodeId>,
+    ) {
+        if current_depth >= max_depth {
+            return;
+        }
+        let Some(node) = graph.nodes.get(node_id) else { return };
+        for child_id in &node.children {
+            if options.max_descendants.is_some_and(|max| result.len() > max) {
+                return;
+            }
+            if !seen.insert(child_id.clone()) {
+                continue;
+            }
+            let Some(child) = graph.nodes.get(child_id) else { continue };
+            if !options.include_archived && temporal::is_archived(graph, child) {
+                continue;
+            }
+            if child.sensitivity > options.max_sensitivity {
+                continue;
+            }
+            if options.node_types.as_ref().is_none_or(|types| types.contains(&child.node_type)) {
+                result.push(Arc::clone(child));
+            }
+            Self::collect_descendants_inner(graph, child_id, max_depth, current_depth + 1, options, result, seen);
+        }
+    }
+
+    pub fn update_node(
+        &mut self,
+        node_id: &str,
+        content: Option<&str>,
+        metadata: Option<HashMap<String, String>>,
+        temporal: Option<TemporalMetadata>,
+        reason: Option<&str>,
+    ) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("update_node");
+        debug!(node_id = %node_id, "update_node");
+        let nid = NodeId(node_id.into());
+
+        let (old_content, old_metadata) = {
+            let node = self.get_node(node_id)?;
+            if let Some(new_metadata) = &metadata {
+                self.schema.validate(&node.node_type, new_metadata)?;
+                self.limits.check_metadata(new_metadata)?;
+            }
+            if let Some(new_content) = content {
+                node.content_format.validate(new_content)?;
+                self.limits.check_content(new_content)?;
+            }
+            (node.content.clone(), node.metadata.clone())
+        };
+
+        let node = &self.graph.nodes[&nid];
+        let content_changed = content.is_some_and(|c| c != node.content);
+        let metadata_changed = metadata.as_ref().is_some_and(|m| *m != node.metadata);
+        let temporal_changed = temporal.as_ref().is_some_and(|t| Some(t) != node.temporal.as_ref());
+        if !content_changed && !metadata_changed && !temporal_changed {
+            return Ok(Node::clone(node));
+        }
+        if content_changed {
+            // The stamp recorded for undo must carry the whole history.
+            self.load_history()?;
+        }
+
+        let node = self.graph.nodes.get_mut(&nid).map(Arc::make_mut).unwrap();
+        let old_stamp = match content_changed {
+            true => NodeStamp::with_history(node),
+            false => NodeStamp::of(node),
+        };
+        if let (Some(new_content), true) = (content, content_changed) {
+            node.previous_values.push(SupersededValue {
+                old_content: node.content.clone(),
+                superseded_at: Utc::now(),
+                reason: reason.map(|s| s.to_string()),
+            });
+            self.retention.apply(&mut node.previous_values, Utc::now());
+            node.content = new_content.to_string();
+        }
+        if let (Some(new_metadata), true) = (metadata, metadata_changed) {
+            node.metadata = new_metadata;
+        }
+        let old_temporal = node.temporal.clone();
+        if let (Some(new_temporal), true) = (temporal, temporal_changed) {
+            node.temporal = Some(new_temporal);
+        }
+
+        node.updated_at = Utc::now();
+        node.updated_by = self.session.actor.clone();
+        let updated = node.clone();
+
+        let mut changes = Vec::new();
+        if content_changed || metadata_changed {
+            changes.push(Change::UpdateNode {
+                node_id: nid.clone(),
+                old_content: if content_changed { Some(old_content) } else { None },
+                new_content: if content_changed { Some(updated.content.clone()) } else { None },
+                old_metadata: if metadata_changed { Some(old_metadata) } else { None },
+                new_metadata: if metadata_changed { Some(updated.metadata.clone()) } else { None },
+                actor: None,
+            });
+        }
+        if temporal_changed {
+            changes.push(Change::SetTemporal {
+                node_id: nid.clone(),
+                old_temporal,
+                new_temporal: updated.temporal.clone(),
+                actor: None,
+            });
+        }
+        changes.push(Change::StampNode {
+            node_id: nid,
+            new_stamp: match content_changed {
+                true => NodeStamp::with_history(&updated),
+                false => NodeStamp::of(&updated),
+            },
+            old_stamp,
+            actor: None,
+        });
+        self.record_changes(changes)?;
+
+        Ok(updated)
+    }
+
+    /// Set `key` to `value` (or remove it when `value` is None) on every node
+    /// matching `filter`, with one save and one undo entry. Every updated node
+    /// is validated before anything changes. Returns the ids of nodes whose
+    /// metadata actually changed.
+    pub fn update_metadata_bulk(
+        &mut self,
+        filter: &NodeFilter,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<NodeId>, WillowError> {
+        self.require_writable()?;
+        if filter.is_empty() {
+            return Err(WillowError::EmptyFilter);
+        }
+        let mut changes = Vec::new();
+        for node in self.graph.nodes.values().filter(|n| filter.matches(n)) {
+            let mut metadata = node.metadata.clone();
+            let changed = match value {
+                Some(v) => metadata.insert(key.to_string(), v.to_string()).as_deref() != Some(v),
+                None => metadata.remove(key).is_some(),
+            };
+            if !changed {
+                continue;
+            }
+            self.schema.validate(&node.node_type, &metadata)?;
+            self.limits.check_metadata(&metadata)?;
+            changes.push(Change::UpdateNode {
+                node_id: node.id.clone(),
+                old_content: None,
+                new_content: None,
+                old_metadata: Some(node.metadata.clone()),
+                new_metadata: Some(metadata),
+                actor: None,
+            });
+        }
+        info!(key = %key, updated = changes.len(), "update_metadata_bulk");
+        if changes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        apply_recorded(&mut self.graph, &mut changes);
+        let now = Utc::now();
+        let updated: Vec<NodeId> = changes
+            .iter()
+            .filter_map(|c| match c {
+                Change::UpdateNode { node_id, .. } => Some(node_id.clone()),
+                _ => None,
+            })
+            .collect();
+        self.stamp_nodes(&updated, now, &mut changes);
+        self.record_changes(changes)?;
+        Ok(updated)
+    }
+
+    /// Hide a node and its subtree from search and context without deleting it.
+    pub fn archive_node(&mut self, node_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        self.set_archived(node_id, true)
+    }
+
+    pub fn unarchive_node(&mut self, node_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        self.set_archived(node_id, false)
+    }
+
+    fn set_archived(&mut self, node_id: &str, archived: bool) -> Result<Node, WillowError> {
+        let node = self.get_node(node_id)?;
+        if self.graph.is_root(&node.id) {
+            return Err(WillowError::CannotArchiveRoot);
+        }
+        if node.archived != archived {
+            let change = Change::SetArchived {
+                node_id: node.id.clone(),
+                archived,
+                actor: None,
+            };
+            apply_delta(&mut self.graph, &Delta::new(vec![change.clone()]));
+            self.record_change(change)?;
+        }
+        Ok(self.get_node(node_id)?.clone())
+    }
+
+    /// Set a node's sensitivity: `normal`, `sensitive` or `secret`.
+    pub fn set_sensitivity(&mut self, node_id: &str, level: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        let level = Sensitivity::from_str(level).ok_or_else(|| WillowError::InvalidSensitivity(level.to_string()))?;
+        let node = self.get_node(node_id)?;
+        if node.sensitivity != level {
+            let change = Change::SetSensitivity {
+                node_id: node.id.clone(),
+                old_sensitivity: node.sensitivity,
+                new_sensitivity: level,
+                actor: None,
+            };
+            apply_delta(&mut self.graph, &Delta::new(vec![change.clone()]));
+            self.record_change(change)?;
+        }
+        Ok(self.get_node(node_id)?.clone())
+    }
+
+    /// Replace a node's display hints; `None` clears them.
+    pub fn set_display(&mut self, node_id: &str, display: Option<DisplayHints>) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        let node = self.get_node(node_id)?;
+        if node.display != display {
+            let change = Change::SetDisplay {
+                node_id: node.id.clone(),
+                old_display: node.display.clone(),
+                new_display: display,
+                actor: None,
+            };
+            apply_delta(&mut self.graph, &Delta::new(vec![change.clone()]));
+            self.record_change(change)?;
+        }
+        Ok(self.get_node(node_id)?.clone())
+    }
+
+    pub fn pin_node(&mut self, node_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        self.set_pinned(node_id, true)
+    }
+
+    pub fn unpin_node(&mut self, node_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        self.set_pinned(node_id, false)
+    }
+
+    fn set_pinned(&mut self, node_id: &str, pinned: bool) -> Result<Node, WillowError> {
+        debug!(node_id = %node_id, pinned, "set_pinned");
+        let nid = NodeId(node_id.into());
+        if self.get_node(node_id)?.pinned != pinned {
+            self.graph.nodes.get_mut(&nid).map(Arc::make_mut).unwrap().pinned = pinned;
+            self.record_change(Change::SetPinned {
+                node_id: nid.clone(),
+                pinned,
+                actor: None,
+            })?;
+        }
+        Ok(Node::clone(&self.graph.nodes[&nid]))
+    }
+
+    /// Pinned nodes, ordered by content.
+    pub fn list_pinned(&self) -> Vec<Node> {
+        let mut pinned: Vec<Node> = self.graph.nodes.values().filter(|n| n.pinned).map(|n| Node::clone(n)).collect();
+        pinned.sort_by(|a, b| a.content.cmp(&b.content));
+        pinned
+    }
+
+    /// Replace a fact with a new sibling node instead of editing it in place.
+    /// The old node's validity ends now, the new one's starts now, and they are
+    /// joined by a `superseded_by` link, which is allowed regardless of the
+    /// relation registry.
+    pub fn supersede_node(&mut self, old_id: &str, new_content: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %old_id, "supersede_node");
+        let old = self.get_node(old_id)?.clone();
+        if self.graph.is_root(&old.id) {
+            return Err(WillowError::CannotSupersedeRoot);
+        }
+        self.limits.check_content(new_content)?;
+        if let Some(parent) = old.parent_id.as_ref().and_then(|p| self.graph.nodes.get(p)) {
+            self.limits.check_children(parent, 1)?;
+        }
+
+        let now = Utc::now();
+        let label = old.temporal.as_ref().and_then(|t| t.label.clone());
+        let ended = TemporalMetadata {
+            valid_from: old.temporal.as_ref().and_then(|t| t.valid_from),
+            valid_until: Some(now),
+            label: label.clone(),
+        };
+        let node_id = NodeId(Uuid::new_v4().to_string().into());
+        let node = Node {
+            id: node_id.clone(),
+            node_type: old.node_type.clone(),
+            content: new_content.to_string(),
+            parent_id: old.parent_id.clone(),
+            children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: old.pinned,
+            sensitivity: old.sensitivity,
+            archived: false,
+            display: None,
+            content_format: old.content_format,
+            attachments: Vec::new(),
+            metadata: old.metadata.clone(),
+            previous_values: Vec::new(),
+            temporal: Some(TemporalMetadata {
+                valid_from: Some(now),
+                valid_until: None,
+                label,
+            }),
+            created_at: now,
+            updated_at: now,
+            created_by: self.session.actor.clone(),
+            updated_by: self.session.actor.clone(),
+        };
+        let link = Link {
+            id: LinkId(Uuid::new_v4().to_string().into()),
+            from_node: old.id.clone(),
+            to_node: node_id.clone(),
+            relation: SUPERSEDED_BY.to_string(),
+            bidirectional: false,
+            confidence: None,
+            created_at: now,
+        };
+
+        let mut changes = vec![
+            Change::SetTemporal {
+                node_id: old.id.clone(),
+                old_temporal: old.temporal.clone(),
+                new_temporal: Some(ended),
+                actor: None,
+            },
+            Change::CreateNode {
+                node_id: node_id.clone(),
+                node: node.clone().into(),
+                positions: Vec::new(),
+                actor: None,
+            },
+            Change::AddLink {
+                link_id: link.id.clone(),
+                link,
+                actor: None,
+            },
+        ];
+        // The pin moves to the current fact.
+        if old.pinned {
+            changes.push(Change::SetPinned {
+                node_id: old.id.clone(),
+                pinned: false,
+                actor: None,
+            });
+        }
+        apply_recorded(&mut self.graph, &mut changes);
+        self.stamp_nodes(std::slice::from_ref(&old.id), now, &mut changes);
+        self.record_changes(changes)?;
+        Ok(node)
+    }
+
+    /// Delete a node and every descendant whose parents are all being deleted.
+    /// Descendants that also belong to a surviving parent are kept and only
+    /// detached from the deleted parents.
+    pub fn delete_node(&mut self, node_id: &str) -> Result<(), WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("delete_node");
+        let nid = NodeId(node_id.into());
+
+        if self.graph.is_root(&nid) {
+            return Err(WillowError::CannotDeleteRoot);
+        }
+        // Deleted nodes keep their history in the undo stack.
+        self.load_history()?;
+
+        self.get_node(node_id)?;
+
+        let changes = self.remove_subtree(&nid);
+        self.record_changes(changes)?;
+        Ok(())
+    }
+
+    /// Matching nodes in `sort` order, windowed by `page`.
+    pub fn list_nodes(&self, filter: &NodeFilter, sort: &NodeSort, page: &Page) -> NodePage {
+        let mut matched: Vec<&Node> = self.graph.nodes.values().map(Arc::as_ref).filter(|n| filter.matches(n)).collect();
+        matched.sort_by(|a, b| sort.compare(a, b));
+        let total = matched.len();
+        let nodes = matched
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        debug!(total, offset = page.offset, "list_nodes");
+        NodePage { nodes, total }
+    }
+
+    /// Matching links, oldest first, windowed by `page`.
+    pub fn list_links(&self, filter: &LinkFilter, page: &Page) -> LinkPage {
+        let mut matched: Vec<&Link> = self.graph.links.values().filter(|l| filter.matches(l)).collect();
+        matched.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.0.cmp(&b.id.0)));
+        let total = matched.len();
+        let links = matched
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        debug!(total, offset = page.offset, "list_links");
+        LinkPage { links, total }
+    }
+
+    pub fn count_nodes(&self, filter: &NodeFilter) -> usize {
+        self.graph.nodes.values().filter(|n| filter.matches(n)).count()
+    }
+
+    pub fn node_exists(&self, node_id: &str) -> bool {
+        self.graph.nodes.contains_key(&NodeId(node_id.into()))
+    }
+
+    /// Near-duplicate siblings of the same type, for review before merging.
+    pub fn find_duplicates(&self, threshold: f64) -> Vec<DuplicatePair> {
+        dedupe::find_duplicates(&self.graph, threshold)
+    }
+
+    /// Ids of nodes whose content is exactly `content`, for duplicate checks.
+    pub fn find_by_content_exact(&self, content: &str) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self
+            .graph
+            .nodes
+            .values()
+            .filter(|n| n.content == content)
+            .map(|n| n.id.clone())
+            .collect();
+        ids.sort_by(|a, b| a.0.cmp(&b.0));
+        ids
+    }
+
+    /// Delete every non-root node matching `filter`, cascading as `delete_node`
+    /// does, with one save and one undo entry. Returns the number of nodes
+    /// removed, including cascaded descendants.
+    pub fn delete_where(&mut self, filter: &NodeFilter) -> Result<usize, WillowError> {
+        self.require_writable()?;
+        if filter.is_empty() {
+            return Err(WillowError::EmptyFilter);
+        }
+        self.load_history()?;
+        let matched: Vec<NodeId> = self
+            .graph
+            .nodes
+            .values()
+            .filter(|n| !self.graph.is_root(&n.id) && filter.matches(n))
+            .map(|n| n.id.clone())
+            .collect();
+        let before = self.graph.nodes.len();
+        let mut changes = Vec::new();
+        for id in matched {
+            // Already removed as part of an earlier match's subtree.
+            if self.graph.nodes.contains_key(&id) {
+                changes.extend(self.remove_subtree(&id));
+            }
+        }
+        let removed = before - self.graph.nodes.len();
+        info!(removed, "delete_where");
+        if removed > 0 {
+            self.record_changes(changes)?;
+        }
+        Ok(removed)
+    }
+
+    /// Remove a node and its exclusively-owned descendants from the graph,
+    /// returning the changes that describe it. Does not save or record.
+    fn remove_subtree(&mut self, nid: &NodeId) -> Vec<Change> {
+        let mut descendants = Vec::new();
+        self.collect_descendant_ids(nid, &mut descendants);
+
+        // A descendant is deleted only if every one of its parents is deleted;
+        // iterate to a fixpoint since survivors keep their own subtrees alive.
+        let mut delete_set: HashSet<NodeId> = descendants.iter().cloned().collect();
+        delete_set.insert(nid.clone());
+        loop {
+            let survivors: Vec<NodeId> = descendants
+                .iter()
+                .filter(|id| delete_set.contains(*id))
+                .filter(|id| {
+                    self.graph.nodes[*id]
+                        .parents()
+                        .any(|p| !delete_set.contains(p))
+                })
+                .cloned()
+                .collect();
+            if survivors.is_empty() {
+                break;
+            }
+            for id in survivors {
+                delete_set.remove(&id);
+            }
+        }
+
+        // Detach survivors from deleted parents first, so replay sees the same structure.
+        let mut changes = Vec::new();
+        for id in descendants.iter().filter(|id| !delete_set.contains(*id)) {
+            let node = &self.graph.nodes[id];
+            for pid in node.parents().filter(|p| delete_set.contains(*p)) {
+                changes.push(Change::RemoveParent {
+                    node_id: id.clone(),
+                    parent_id: pid.clone(),
+                    primary: node.parent_id.as_ref() == Some(pid),
+                    child_index: None,
+                    extra_index: None,
+                    actor: None,
+                });
+            }
+        }
+        apply_recorded(&mut self.graph, &mut changes);
+
+        let mut to_delete: Vec<NodeId> = descendants
+            .into_iter()
+            .filter(|id| delete_set.contains(id))
+            .collect();
+        to_delete.push(nid.clone());
+        debug!(node_id = %nid.0, cascade = to_delete.len(), "delete_node");
+
+        let delete_refs: HashSet<&NodeId> = to_delete.iter().collect();
+        let deleted_nodes: Vec<Node> = to_delete
+            .iter()
+            .filter_map(|id| self.graph.nodes.get(id).map(|n| Node::clone(n)))
+            .collect();
+        let deleted_links = self.links_touching(&delete_refs);
+        let positions = child_positions(&self.graph, nid);
+
+        let parents: Vec<NodeId> = self.graph.nodes[nid].parents().cloned().collect();
+        for parent_id in parents {
+            if let Some(parent) = self.graph.nodes.get_mut(&parent_id).map(Arc::make_mut) {
+                parent.children.retain(|c| c != nid);
+            }
+        }
+
+        for id in &to_delete {
+            self.graph.nodes.remove(id);
+        }
+
+        self.graph
+            .links
+            .retain(|_, link| !delete_set.contains(&link.from_node) && !delete_set.contains(&link.to_node));
+
+        changes.push(Change::DeleteNode {
+            node_id: nid.clone(),
+            deleted_nodes,
+            deleted_links,
+            positions,
+            actor: None,
+        });
+        changes
+    }
+
+    pub fn add_link(
+        &mut self,
+        from_node: &str,
+        to_node: &str,
+        relation: &str,
+        bidirectional: bool,
+        confidence: Option<&str>,
+    ) -> Result<Link, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("add_link");
+        debug!(from = %from_node, to = %to_node, relation = %relation, "add_link");
+        let from_nid = NodeId(from_node.into());
+        let to_nid = NodeId(to_node.into());
+
+        self.get_node(from_node)?;
+        self.get_node(to_node)?;
+
+        let confidence_level = Self::parse_confidence(confidence)?;
+        self.relations.validate(relation, bidirectional)?;
+
+        let is_dup = self.links_by_node.links_of(&self.graph, &from_nid).any(|link| {
+            let forward = link.from_node == from_nid && link.to_node == to_nid && link.relation == relation;
+            let reverse = bidirectional
+                && link.from_node == to_nid
+                && link.to_node == from_nid
+                && link.relation == relation;
+            forward || reverse
+        });
+        if is_dup {
+            return Err(WillowError::DuplicateLink {
+                from: from_node.to_string(),
+                to: to_node.to_string(),
+                relation: relation.to_string(),
+            });
+        }
+
+        let link = Link {
+            id: LinkId(Uuid::new_v4().to_string().into()),
+            from_node: from_nid,
+            to_node: to_nid,
+            relation: relation.to_string(),
+            bidirectional,
+            confidence: confidence_level,
+            created_at: Utc::now(),
+        };
+
+        self.graph.links.insert(link.id.clone(), link.clone());
+
+        self.record_change(Change::AddLink {
+            link_id: link.id.clone(),
+            link: link.clone(),
+            actor: None,
+        })?;
+
+        Ok(link)
+    }
+
+    pub fn update_link(
+        &mut self,
+        link_id: &str,
+        relation: Option<&str>,
+        bidirectional: Option<bool>,
+        confidence: Option<&str>,
+    ) -> Result<Link, WillowError> {
+        self.require_writable()?;
+        debug!(link_id = %link_id, "update_link");
+        let lid = LinkId(link_id.into());
+
+        let old_link = self
+            .graph
+            .links
+            .get(&lid)
+            .ok_or_else(|| WillowError::LinkNotFound(link_id.to_string()))?
+            .clone();
+
+        let confidence_level = Self::parse_confidence(confidence)?;
+        self.relations.validate(
+            relation.unwrap_or(&old_link.relation),
+            bidirectional.unwrap_or(old_link.bidirectional),
+        )?;
+
+        let link = self.graph.links.get_mut(&lid).unwrap();
+
+        if let Some(r) = relation {
+            link.relation = r.to_string();
+        }
+        if let Some(b) = bidirectional {
+            link.bidirectional = b;
+        }
+        if let Some(c) = confidence_level {
+            link.confidence = Some(c);
+        }
+
+        let new_link = link.clone();
+
+        self.record_change(Change::UpdateLink {
+            link_id: lid,
+            old_link,
+            new_link: new_link.clone(),
+            actor: None,
+        })?;
+
+        Ok(new_link)
+    }
+
+    pub fn delete_link(&mut self, link_id: &str) -> Result<Link, WillowError> {
+        self.require_writable()?;
+        let _timer = self.perf.time("delete_link");
+        debug!(link_id = %link_id, "delete_link");
+        let lid = LinkId(link_id.into());
+
+        let link = self
+            .graph
+            .links
+            .remove(&lid)
+            .ok_or_else(|| WillowError::LinkNotFound(link_id.to_string()))?;
+
+        self.record_change(Change::RemoveLink {
+            link_id: lid,
+            link: link.clone(),
+            actor: None,
+        })?;
+
+        Ok(link)
+    }
+
+    /// Deep-copy a node and its descendants under `new_parent_id` with fresh ids.
+    /// When `include_links` is set, links between nodes inside the subtree are
+    /// duplicated onto the copies as well.
+    pub fn clone_subtree(
+        &mut self,
+        node_id: &str,
+        new_parent_id: &str,
+        include_links: bool,
+    ) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %node_id, new_parent = %new_parent_id, "clone_subtree");
+        let nid = NodeId(node_id.into());
+        if self.graph.is_root(&nid) {
+            return Err(WillowError::CannotCloneRoot);
+        }
+        self.get_node(node_id)?;
+        let new_parent_nid = NodeId(new_parent_id.into());
+        let Some(new_parent) = self.graph.nodes.get(&new_parent_nid) else {
+            return Err(WillowError::ParentNotFound(new_parent_id.to_string()));
+        };
+        self.limits.check_children(new_parent, 1)?;
+
+        let mut source_ids = vec![nid.clone()];
+        self.collect_descendant_ids(&nid, &mut source_ids);
+        let id_map: HashMap<NodeId, NodeId> = source_ids
+            .iter()
+            .map(|id| (id.clone(), NodeId(Uuid::new_v4().to_string().into())))
+            .collect();
+
+        let now = Utc::now();
+        let mut changes = Vec::new();
+        for old_id in &source_ids {
+            let source = &self.graph.nodes[old_id];
+            // Only parents inside the copied subtree carry over; the copy's
+            // root hangs off the new parent alone.
+            let mut parents: Vec<NodeId> = if old_id == &nid {
+                vec![new_parent_nid.clone()]
+            } else {
+                source.parents().filter_map(|p| id_map.get(p).cloned()).collect()
+            };
+            let parent_id = parents.remove(0);
+            let node = Node {
+                id: id_map[old_id].clone(),
+                node_type: source.node_type.clone(),
+                content: source.content.clone(),
+                parent_id: Some(parent_id),
+                children: source.children.iter().map(|c| id_map[c].clone()).collect(),
+                extra_parents: parents,
+                pinned: false,
+                sensitivity: source.sensitivity,
+                archived: false,
+                display: None,
+                content_format: source.content_format,
+                attachments: source.attachments.clone(),
+                metadata: source.metadata.clone(),
+                previous_values: Vec::new(),
+                temporal: source.temporal.clone(),
+                created_at: now,
+                updated_at: now,
+                created_by: self.session.actor.clone(),
+                updated_by: self.session.actor.clone(),
+            };
+            changes.push(Change::CreateNode {
+                node_id: node.id.clone(),
+                node: node.into(),
+                positions: Vec::new(),
+                actor: None,
+            });
+        }
+
+        if include_links {
+            let internal: Vec<Link> = self
+                .graph
+                .links
+                .values()
+                .filter(|l| id_map.contains_key(&l.from_node) && id_map.contains_key(&l.to_node))
+                .cloned()
+                .collect();
+            for link in internal {
+                let link = Link {
+                    id: LinkId(Uuid::new_v4().to_string().into()),
+                    from_node: id_map[&link.from_node].clone(),
+                    to_node: id_map[&link.to_node].clone(),
+                    created_at: now,
+                    ..link
+                };
+                changes.push(Change::AddLink {
+                    link_id: link.id.clone(),
+                    link,
+                    actor: None,
+                });
+            }
+        }
+
+        apply_recorded(&mut self.graph, &mut changes);
+        self.record_changes(changes)?;
+
+        Ok(Node::clone(&self.graph.nodes[&id_map[&nid]]))
+    }
+
+    /// Make `node_id` additionally appear under `parent_id`, keeping its primary parent.
+    pub fn add_parent(&mut self, node_id: &str, parent_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %node_id, parent = %parent_id, "add_parent");
+        let nid = NodeId(node_id.into());
+        let pid = NodeId(parent_id.into());
+        if self.graph.is_root(&nid) {
+            return Err(WillowError::WouldCreateCycle(node_id.to_string()));
+        }
+        self.get_node(node_id)?;
+        if !self.graph.nodes.contains_key(&pid) {
+            return Err(WillowError::ParentNotFound(parent_id.to_string()));
+        }
+        let mut descendants = Vec::new();
+        self.collect_descendant_ids(&nid, &mut descendants);
+        if pid == nid || descendants.contains(&pid) {
+            return Err(WillowError::WouldCreateCycle(node_id.to_string()));
+        }
+        if self.graph.nodes[&nid].parents().any(|p| p == &pid) {
+            return Ok(Node::clone(&self.graph.nodes[&nid]));
+        }
+        self.limits.check_children(&self.graph.nodes[&pid], 1)?;
+
+        let mut change = Change::AddParent {
+            node_id: nid.clone(),
+            parent_id: pid,
+            primary: false,
+            child_index: None,
+            extra_index: None,
+            actor: None,
+        };
+        apply_recorded(&mut self.graph, std::slice::from_mut(&mut change));
+        self.record_change(change)?;
+        Ok(Node::clone(&self.graph.nodes[&nid]))
+    }
+
+    /// Remove one of a node's parents. The node must keep at least one parent;
+    /// removing the primary promotes the first extra parent.
+    pub fn remove_parent(&mut self, node_id: &str, parent_id: &str) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(node_id = %node_id, parent = %parent_id, "remove_parent");
+        let nid = NodeId(node_id.into());
+        let pid = NodeId(parent_id.into());
+        let node = self.get_node(node_id)?;
+        if !node.parents().any(|p| p == &pid) {
+            return Err(WillowError::ParentNotFound(parent_id.to_string()));
+        }
+        if node.extra_parents.is_empty() {
+            return Err(WillowError::LastParent(node_id.to_string()));
+        }
+
+        let mut change = Change::RemoveParent {
+            node_id: nid.clone(),
+            parent_id: pid.clone(),
+            primary: node.parent_id.as_ref() == Some(&pid),
+            child_index: None,
+            extra_index: None,
+            actor: None,
+        };
+        apply_recorded(&mut self.graph, std::slice::from_mut(&mut change));
+        self.record_change(change)?;
+        Ok(Node::clone(&self.graph.nodes[&nid]))
+    }
+
+    /// Reorder a node's children. `ordered_ids` must contain exactly the
+    /// current children, each once.
+    pub fn reorder_children(
+        &mut self,
+        parent_id: &str,
+        ordered_ids: &[String],
+    ) -> Result<Node, WillowError> {
+        self.require_writable()?;
+        debug!(parent = %parent_id, "reorder_children");
+        let old_order = self.get_node(parent_id)?.children.clone();
+        let new_order: Vec<NodeId> = ordered_ids.iter().map(|id| NodeId(id.as_str().into())).collect();
+
+        let mut current_sorted: Vec<&NodeId> = old_order.iter().collect();
+        let mut requested_sorted: Vec<&NodeId> = new_order.iter().collect();
+        current_sorted.sort_by(|a, b| a.0.cmp(&b.0));
+        requested_sorted.sort_by(|a, b| a.0.cmp(&b.0));
+        if current_sorted != requested_sorted {
+            return Err(WillowError::InvalidChildOrder(parent_id.to_string()));
+        }
+
+        let parent_nid = NodeId(parent_id.into());
+        let parent = self.graph.nodes.get_mut(&parent_nid).map(Arc::make_mut).unwrap();
+        parent.children = new_order.clone();
+        let updated = parent.clone();
+
+        if old_order != new_order {
+            self.record_change(Change::ReorderChildren {
+                parent_id: parent_nid,
+                old_order,
+                new_order,
+                actor: None,
+            })?;
+        }
+
+        Ok(updated)
+    }
+
+    pub fn search_nodes(
+        &self,
+        query: &str,
+        page: &Page,
+        root_node_id: Option<&str>,
+        options: &SearchOptions,
+    ) -> SearchPage {
+        let _timer = self.perf.time("search_nodes");
+        #[cfg(feature = "metrics")]
+        crate::metrics::increment(crate::metrics::Metric::Searches);
+        let root_nid = root_node_id.map_or_else(|| self.profile_root().clone(), |id| NodeId(id.into()));
+        Self::paginate(search::search_nodes(&self.graph, query, Some(&root_nid), &self.configured(options)), page)
+    }
+
+    /// `search_nodes` over the graph as it was valid at `date`.
+    pub fn search_nodes_as_of(
+        &self,
+        query: &str,
+        date: DateTime<Utc>,
+        page: &Page,
+        root_node_id: Option<&str>,
+        options: &SearchOptions,
+    ) -> SearchPage {
+        let root_nid = root_node_id.map_or_else(|| self.profile_root().clone(), |id| NodeId(id.into()));
+        let view = temporal::view_as_of(&self.graph, date);
+        Self::paginate(search::search_nodes(&view, query, Some(&root_nid), &self.configured(options)), page)
+    }
+
+    /// Links in the current profile matching `query` by relation or endpoint
+    /// content, best first.
+    pub fn search_links(&self, query: &str, options: &SearchOptions) -> Vec<LinkSearchResult> {
+        search::search_links(&self.graph, query, Some(self.profile_root()), &self.configured(options))
+    }
+
+    /// `options` with the store's text analysis and ranking boosts filling
+    /// in whatever the caller left unset.
+    fn configured(&self, options: &SearchOptions) -> SearchOptions {
+        let mut options = options.clone();
+        if options.analysis.stemmer.is_none() {
+            options.analysis.stemmer = self.analysis.stemmer;
+        }
+        if options.analysis.synonyms.is_empty() {
+            options.analysis.synonyms = self.analysis.synonyms.clone();
+        }
+        if options.boosts.is_none() {
+            options.boosts = Some(self.ranking.clone());
+        }
+        options
+    }
+
+    fn paginate(results: Vec<search::SearchResult>, page: &Page) -> SearchPage {
+        let total = results.len();
+        let results = results
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit.unwrap_or(usize::MAX))
+            .collect();
+        SearchPage { results, total }
+    }
+}
+
+impl Drop for GraphStore {
+    /// Compact a journal on close, so the next open need not replay it,
+    /// and write saves held back by write-behind.
+    fn drop(&mut self) {
+        if (self.journal_len.get() > 0 || self.unsaved.is_some()) && !self.read_only && !self.closed {
+            if let Err(e) = self.write_graph() {
+                warn!(error = %e, "graph file not brought up to date on close");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::SortField;
+    use tempfile::NamedTempFile;
+
+    fn temp_store() -> GraphStore {
+        let tmp = NamedTempFile::new().unwrap();
+        let path = tmp.path().to_path_buf();
+        // Drop the temp file so GraphStore creates it fresh
+        drop(tmp);
+        let _ = std::fs::remove_file(&path);
+        GraphStore::open(&path, &OpenOptions::default()).unwrap()
+    }
+
+    /// A store with VCS initialized in its own directory; keep the guard alive.
+    fn temp_vcs_store() -> (tempfile::TempDir, GraphStore) {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut store = GraphStore::open(&tmp.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        store.vcs_init().unwrap();
+        (tmp, store)
+    }
+
+    #[test]
+    fn test_compressed_graph_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions {
+            storage: StorageOptions { compress: true, ..StorageOptions::default() },
+            ..OpenOptions::default()
+        }).unwrap();
+        store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        assert!(std::fs::read(&path).unwrap().starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+
+        let mut plain = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        assert_eq!(plain.graph.nodes.len(), 2);
+        plain.create_node("root", "detail", "Likes coffee", None, None).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with('{'));
+    }
+
+    #[test]
+    fn test_storage_options_compact_with_history_sidecar() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions {
+            storage: StorageOptions {
+                pretty: false,
+                sorted_keys: false,
+                inline_history: false,
+                ..StorageOptions::default()
+            },
+            ..OpenOptions::default()
+        };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        let node = store.create_node("root", "detail", "v1", None, None).unwrap();
+        store.update_node(&node.id.0, Some("v2"), None, None, None).unwrap();
+        drop(store);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains('\n') && !text.contains("v1"));
+        assert!(std::fs::read_to_string(tmp.path().join("graph.history.json")).unwrap().contains("v1"));
+
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        assert_eq!(store.graph.nodes[&node.id].previous_values[0].old_content, "v1");
+        store.update_node(&node.id.0, Some("v3"), None, None, None).unwrap();
+        assert!(!tmp.path().join("graph.history.json").exists());
+        assert!(std::fs::read_to_string(&path).unwrap().contains("\n  \"links\""));
+    }
+
+    #[test]
+    fn test_graph_lock_modes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let locked = |mode| OpenOptions { lock: Some(mode), ..OpenOptions::default() };
+
+        let writer = GraphStore::open(&path, &locked(LockMode::Exclusive)).unwrap();
+        assert!(matches!(
+            GraphStore::open(&path, &locked(LockMode::Shared)),
+            Err(WillowError::GraphLocked(_))
+        ));
+        drop(writer);
+
+        let reader = GraphStore::open(&path, &locked(LockMode::Shared)).unwrap();
+        GraphStore::open(&path, &locked(LockMode::Shared)).unwrap();
+        assert!(matches!(
+            GraphStore::open(&path, &locked(LockMode::Exclusive)),
+            Err(WillowError::GraphLocked(_))
+        ));
+        drop(reader);
+    }
+
+    #[test]
+    fn test_subscribers_receive_change_events() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let id = store.subscribe(move |event, _| sink.lock().unwrap().push(event.kind()));
+
+        let a = store.create_node("root", "detail", "Alice", None, None).unwrap();
+        let b = store.create_node("root", "detail", "Bob", None, None).unwrap();
+        store.add_link(&a.id.0, &b.id.0, "knows", false, None).unwrap();
+        store.delete_node(&b.id.0).unwrap();
+        store
+            .commit(CommitInput {
+                message: "People".to_string(),
+                source: CommitSource::Manual { tool_name: None },
+            })
+            .unwrap();
+        store.begin_transaction().unwrap();
+        store.rollback_transaction().unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "node_created",
+                "node_created",
+                "node_updated",
+                "node_updated",
+                "link_added",
+                "node_deleted",
+                "link_removed",
+                "commit_created",
+                "reset"
+            ]
+        );
+
+        assert!(store.unsubscribe(id));
+        store.create_node("root", "detail", "Carol", None, None).unwrap();
+        assert_eq!(events.lock().unwrap().len(), 9);
+    }
+
+    #[test]
+    fn test_create_nodes_saves_once_and_undoes_together() {
+        let mut store = temp_store();
+        let tree = NewNode {
+            node_type: "category".to_string(),
+            content: "Work".to_string(),
+            children: vec![NewNode {
+                node_type: "detail".to_string(),
+                content: "Project".to_string(),
+                format: Some("markdown".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let note = NewNode {
+            node_type: "detail".to_string(),
+            content: "Note".to_string(),
+            ..Default::default()
+        };
+        let created = store
+            .create_nodes(vec![("root".to_string(), tree), ("root".to_string(), note.clone())])
+            .unwrap();
+        let contents: Vec<&str> = created.iter().map(|n| n.content.as_str()).collect();
+        assert_eq!(contents, ["Work", "Project", "Note"]);
+        assert_eq!(created[1].parent_id, Some(created[0].id.clone()));
+        assert_eq!(store.graph.nodes.len(), 4);
+
+        // A bad entry leaves nothing behind.
+        let err = store.create_nodes(vec![("root".to_string(), note.clone()), ("missing".to_string(), note)]);
+        assert!(err.is_err());
+        assert_eq!(store.graph.nodes.len(), 4);
+
+        assert!(store.undo().unwrap());
+        assert_eq!(store.graph.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_read_only_store_rejects_writes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let read_only = OpenOptions { read_only: true, ..OpenOptions::default() };
+        assert!(GraphStore::open(&path, &read_only).is_err());
+
+        let mut writer = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        writer.create_node("root", "detail", "Alice", None, None).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let mut reader = GraphStore::open(&path, &read_only).unwrap();
+        assert_eq!(reader.graph.nodes.len(), 2);
+        assert!(matches!(reader.require_writable(), Err(WillowError::ReadOnly)));
+        assert!(matches!(reader.compact(), Err(WillowError::ReadOnly)));
+        // Refused before anything changes, not when the save fails.
+        let graph = serde_json::to_value(&reader.graph).unwrap();
+        let alice = reader.graph.nodes.values().find(|n| n.content == "Alice").unwrap().id.0.to_string();
+        assert!(matches!(reader.create_node("root", "detail", "Bob", None, None), Err(WillowError::ReadOnly)));
+        assert!(matches!(reader.update_node(&alice, Some("Eve"), None, None, None), Err(WillowError::ReadOnly)));
+        assert!(matches!(reader.pin_node(&alice), Err(WillowError::ReadOnly)));
+        assert!(matches!(reader.add_link(&alice, "root", "related_to", false, None), Err(WillowError::ReadOnly)));
+        assert!(matches!(reader.delete_node(&alice), Err(WillowError::ReadOnly)));
+        assert!(matches!(reader.set_limits(Limits::default()), Err(WillowError::ReadOnly)));
+        assert_eq!(serde_json::to_value(&reader.graph).unwrap(), graph);
+        assert!(!reader.can_undo());
+        drop(reader);
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn test_close_compacts_journal_and_releases_lock() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions {
+            journal: Some(100),
+            lock: Some(LockMode::Exclusive),
+            ..OpenOptions::default()
+        };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        store.create_node("root", "detail", "Alice", None, None).unwrap();
+        let watcher = store.watch(Duration::from_secs(60), || {}).unwrap();
+        store.close().unwrap();
+
+        assert!(!journal::journal_path(&path).exists());
+        assert!(watcher.stop_flag().load(Ordering::Relaxed));
+        assert!(matches!(
+            store.create_node("root", "detail", "Bob", None, None),
+            Err(WillowError::StoreClosed)
+        ));
+        store.close().unwrap();
+        let reopened = GraphStore::open(&path, &options).unwrap();
+        assert_eq!(reopened.graph.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_link_index_follows_deletes_and_undo() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "detail", "Alice", None, None).unwrap();
+        let b = store.create_node("root", "detail", "Bob", None, None).unwrap();
+        let link = store.add_link(&a.id.0, &b.id.0, "knows", true, None).unwrap();
+        assert!(matches!(
+            store.add_link(&b.id.0, &a.id.0, "knows", true, None),
+            Err(WillowError::DuplicateLink { .. })
+        ));
+
+        store.delete_node(&b.id.0).unwrap();
+        let ctx = store.get_context(&a.id.0, Some(0), &ContextOptions::default()).unwrap();
+        assert!(ctx.links.is_empty());
+        store.add_link(&a.id.0, &a.id.0, "self", false, None).unwrap();
+
+        store.undo().unwrap();
+        store.undo().unwrap();
+        let ctx = store.get_context(&a.id.0, Some(0), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.links.iter().map(|l| &l.id).collect::<Vec<_>>(), [&link.id]);
+        let hood = store.get_neighborhood(&a.id.0, Some(1)).unwrap();
+        assert_eq!(hood.neighbors[0].node.id, b.id);
+        assert!(store.add_link(&a.id.0, &b.id.0, "knows", false, None).is_err());
+    }
+
+    #[test]
+    fn test_open_shared_returns_one_store_per_path() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions {
+            lock: Some(LockMode::Exclusive),
+            ..OpenOptions::default()
+        };
+        let first = GraphStore::open_shared(&path, &options).unwrap();
+        let second = GraphStore::open_shared(&tmp.path().join(".").join("graph.json"), &options).unwrap();
+        assert!(Arc::ptr_eq(&first.store, &second.store));
+
+        let writer = std::thread::spawn(move || {
+            second.write().unwrap().create_node("root", "detail", "Alice", None, None).unwrap();
+            second
+        })
+        .join()
+        .unwrap();
+        assert_eq!(first.lock().graph.nodes.len(), 2);
+
+        // The store stays open until its last handle closes.
+        first.close().unwrap();
+        assert!(matches!(first.write().map(|_| ()), Err(WillowError::StoreClosed)));
+        assert!(!writer.is_closed());
+        writer.close().unwrap();
+        let reopened = GraphStore::open_shared(&path, &options).unwrap();
+        assert!(!Arc::ptr_eq(&writer.store, &reopened.store));
+        assert_eq!(reopened.lock().graph.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_shared_handles_keep_their_own_sessions() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let a = GraphStore::open_shared(&path, &OpenOptions::default()).unwrap();
+        let b = GraphStore::open_shared(&path, &OpenOptions::default()).unwrap();
+        a.lock().set_actor(Some("a".to_string()));
+        b.lock().set_actor(Some("b".to_string()));
+        let from_a = a.write().unwrap().create_node("root", "detail", "From a", None, None).unwrap();
+        let from_b = b.write().unwrap().create_node("root", "detail", "From b", None, None).unwrap();
+        assert_eq!(from_a.created_by.as_deref(), Some("a"));
+        assert_eq!(from_b.created_by.as_deref(), Some("b"));
+
+        assert!(b.write().unwrap().undo().unwrap());
+        assert!(!b.lock().can_undo());
+        let store = a.lock();
+        assert!(store.graph.nodes.contains_key(&from_a.id) && !store.graph.nodes.contains_key(&from_b.id));
+        assert!(store.can_undo());
+        drop(store);
+
+        a.write().unwrap().begin_transaction().unwrap();
+        assert!(!b.lock().in_transaction());
+        assert!(matches!(b.write().map(|_| ()), Err(WillowError::TransactionActive)));
+        assert!(matches!(b.lock().commit_transaction(), Err(WillowError::NoActiveTransaction)));
+        assert!(matches!(b.lock().begin_transaction(), Err(WillowError::TransactionAlreadyActive)));
+        a.write().unwrap().create_node("root", "detail", "Uncommitted", None, None).unwrap();
+        assert!(matches!(a.close(), Err(WillowError::TransactionActive)));
+
+        // Dropping a handle rolls its transaction back.
+        drop(a);
+        assert_eq!(b.lock().graph.nodes.len(), 2);
+        b.write().unwrap().create_node("root", "detail", "After", None, None).unwrap();
+    }
+
+    #[test]
+    fn test_attachments_gc_keeps_blobs_in_other_handles_history() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let a = GraphStore::open_shared(&path, &OpenOptions::default()).unwrap();
+        let b = GraphStore::open_shared(&path, &OpenOptions::default()).unwrap();
+        let node = a.write().unwrap().create_node("root", "entity", "Passport", None, None).unwrap();
+        let scan = a.write().unwrap().attach_blob(&node.id.0, b"scan", "image/png").unwrap();
+        a.write().unwrap().detach_blob(&node.id.0, &scan.hash).unwrap();
+
+        assert!(b.lock().gc_attachments(&mut Progress::default()).unwrap().is_empty());
+        drop(a);
+        assert_eq!(b.lock().gc_attachments(&mut Progress::default()).unwrap(), [scan.hash]);
+    }
+
+    #[test]
+    fn test_open_shared_rejects_other_options_and_shares_with_readers() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let writer = GraphStore::open_shared(&path, &OpenOptions::default()).unwrap();
+        let journaled = OpenOptions { journal: Some(10), ..OpenOptions::default() };
+        assert!(matches!(
+            GraphStore::open_shared(&path, &journaled).map(|_| ()),
+            Err(WillowError::OpenOptionsMismatch(_))
+        ));
+
+        let reader = GraphStore::open_shared_read_only(&path).unwrap();
+        assert!(matches!(reader.write().map(|_| ()), Err(WillowError::ReadOnly)));
+        let node = writer.write().unwrap().create_node("root", "detail", "Seen", None, None).unwrap();
+        assert!(reader.lock().graph.nodes.contains_key(&node.id));
+        drop(reader);
+        assert!(writer.write().is_ok());
+    }
+
+    #[test]
+    fn test_commit_auto_describes_pending_changes() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let career = store.create_node("root", "category", "Career", None, None).unwrap();
+        let food = store.create_node("root", "detail", "Favorite food: pizza", None, None).unwrap();
+        store
+            .commit(CommitInput {
+                message: "Setup".to_string(),
+                source: CommitSource::Manual { tool_name: None },
+            })
+            .unwrap();
+
+        for job in ["Engineer", "Manager", "Director"] {
+            store.create_node(&career.id.0, "detail", job, None, None).unwrap();
+        }
+        store.update_node(&food.id.0, Some("Favorite food: sushi"), None, None, None).unwrap();
+        let (_, message) = store.commit_auto(CommitSource::Manual { tool_name: None }).unwrap();
+        assert_eq!(message, "Added 'Director', 'Engineer' and 'Manager' under Career; updated 'Favorite food: sushi'");
+        assert!(matches!(
+            store.commit_auto(CommitSource::Manual { tool_name: None }),
+            Err(WillowError::NothingToCommit)
+        ));
+    }
+
+    #[test]
+    fn test_uncommitted_changes_match_full_diff() {
+        let (tmp, mut store) = temp_vcs_store();
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        let old_job = store.create_node(&work.id.0, "detail", "Engineer", None, None).unwrap();
+        store.commit_auto(CommitSource::Manual { tool_name: None }).unwrap();
+
+        store.update_node(&work.id.0, Some("Career"), None, None, None).unwrap();
+        store.delete_node(&old_job.id.0).unwrap();
+        let scratch = store.create_node("root", "detail", "Scratch", None, None).unwrap();
+        store.delete_node(&scratch.id.0).unwrap();
+        store.create_node(&work.id.0, "detail", "Manager", None, None).unwrap();
+
+        let ids = |nodes: &[crate::vcs::diff::NodeChangeSummary]| -> Vec<(String, Vec<String>)> {
+            nodes.iter().map(|n| (n.node_id.clone(), n.path.clone())).collect()
+        };
+        let fast = store.uncommitted_changes().unwrap();
+        let full = crate::vcs::diff::compute_graph_diff(&store.head_graph().unwrap(), &store.graph);
+        assert_eq!(ids(&fast.nodes_created), ids(&full.nodes_created));
+        assert_eq!(ids(&fast.nodes_updated), ids(&full.nodes_updated));
+        assert_eq!(ids(&fast.nodes_deleted), ids(&full.nodes_deleted));
+        assert_eq!(fast.nodes_deleted[0].path, vec!["User", "Work", "Engineer"]);
+
+        // A new store cannot vouch for work left uncommitted by the last one.
+        drop(store);
+        let mut store = GraphStore::open(&tmp.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        assert_eq!(store.uncommitted_changes().unwrap().nodes_updated.len(), 1);
+        assert_eq!(store.uncommitted_changes_on_disk().unwrap().nodes_created.len(), 1);
+    }
+
+    #[test]
+    fn test_sharded_layout_rewrites_only_changed_subtrees() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let sharded = OpenOptions { sharded: true, ..OpenOptions::default() };
+        let mut store = GraphStore::open(&path, &sharded).unwrap();
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        let health = store.create_node("root", "category", "Health", None, None).unwrap();
+        let task = store.create_node(&work.id.0, "detail", "Ship it", None, None).unwrap();
+        store.create_node(&health.id.0, "detail", "Runs", None, None).unwrap();
+        drop(store);
+
+        let shard = |id: &NodeId| shards::shard_dir(&path).join(format!("subtree-{}.json", id.0));
+        let health_before = std::fs::metadata(shard(&health.id)).unwrap().modified().unwrap();
+
+        // Two processes editing different subtrees both succeed.
+        let mut a = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let mut b = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        a.update_node(&task.id.0, Some("Shipped"), None, None, None).unwrap();
+        assert_eq!(std::fs::metadata(shard(&health.id)).unwrap().modified().unwrap(), health_before);
+        b.create_node(&health.id.0, "detail", "Swims", None, None).unwrap();
+        drop((a, b));
+
+        let store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let contents: HashSet<&str> = store.graph.nodes.values().map(|n| n.content.as_str()).collect();
+        assert!(["Shipped", "Runs", "Swims"].iter().all(|c| contents.contains(c)));
+    }
+
+    #[test]
+    fn test_deferred_history_loaded_on_demand() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let node = store.create_node("root", "detail", "v1", None, None).unwrap();
+        store.update_node(&node.id.0, Some("v2"), None, None, None).unwrap();
+        drop(store);
+
+        let deferred = OpenOptions { defer_history: true, ..OpenOptions::default() };
+        let mut store = GraphStore::open(&path, &deferred).unwrap();
+        assert!(store.graph.nodes[&node.id].previous_values.is_empty());
+        let old: Vec<String> = store.node_history(&node.id.0).unwrap().into_iter().map(|v| v.old_content).collect();
+        assert_eq!(old, ["v1"]);
+        store.update_node(&node.id.0, None, Some(HashMap::from([("k".into(), "v".into())])), None, None).unwrap();
+        assert!(store.graph.nodes[&node.id].previous_values.is_empty());
+        store.update_node(&node.id.0, Some("v3"), None, None, None).unwrap();
+        assert_eq!(store.graph.nodes[&node.id].previous_values.len(), 2);
+        store.undo().unwrap();
+        let old: Vec<String> = store.node_history(&node.id.0).unwrap().into_iter().map(|v| v.old_content).collect();
+        assert_eq!(old, ["v1"]);
+        store.redo().unwrap();
+        drop(store);
+
+        let store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let old: Vec<&str> =
+            store.graph.nodes[&node.id].previous_values.iter().map(|v| v.old_content.as_str()).collect();
+        assert_eq!(old, ["v1", "v2"]);
+    }
+
+    #[test]
+    fn test_corrupt_graph_recovered_from_backup() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        store.create_node("root", "detail", "First", None, None).unwrap();
+        store.create_node("root", "detail", "Second", None, None).unwrap();
+        drop(store);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..data.len() / 2]).unwrap();
+        let store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        assert!(store.recovery_warning().unwrap().contains("backup"));
+        let contents: HashSet<&str> = store.graph.nodes.values().map(|n| n.content.as_str()).collect();
+        assert!(contents.contains("First") && !contents.contains("Second"));
+        assert!(tmp.path().join("graph.corrupt").exists());
+        drop(store);
+        assert!(GraphStore::open(&path, &OpenOptions::default()).unwrap().recovery_warning().is_none());
+
+        std::fs::write(&path, b"{\"nodes\": {").unwrap();
+        std::fs::remove_file(tmp.path().join("graph.bak")).unwrap();
+        assert!(matches!(
+            GraphStore::open(&path, &OpenOptions::default()),
+            Err(WillowError::CorruptGraph { .. })
+        ));
+    }
+
+    #[test]
+    fn test_recovery_keeps_history_sidecar() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions {
+            storage: StorageOptions { inline_history: false, ..StorageOptions::default() },
+            ..OpenOptions::default()
+        };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        let node = store.create_node("root", "detail", "Draft", None, None).unwrap();
+        store.update_node(&node.id.0, Some("Final"), None, None, None).unwrap();
+        store.create_node("root", "detail", "Later", None, None).unwrap();
+        drop(store);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &data[..data.len() / 2]).unwrap();
+        let store = GraphStore::open(&path, &options).unwrap();
+        assert!(store.recovery_warning().is_some());
+        let history: Vec<&str> = store.graph.nodes[&node.id].previous_values.iter().map(|v| v.old_content.as_str()).collect();
+        assert_eq!(history, ["Draft"]);
+        drop(store);
+        let store = GraphStore::open(&path, &options).unwrap();
+        assert_eq!(store.node_history(&node.id.0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_store_runs_on_mounted_memory_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path().join("browser");
+        let memory = Arc::new(files::MemoryFiles::new());
+        files::mount(&dir, memory.clone());
+        let path = dir.join("graph.json");
+        let options = OpenOptions {
+            lock: Some(LockMode::Exclusive),
+            journal: Some(100),
+            audit: true,
+            ..OpenOptions::default()
+        };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        store.vcs_init().unwrap();
+        let node = store.create_node("root", "detail", "Draft", None, None).unwrap();
+        let blob = store.attach_blob(&node.id.0, b"notes", "text/plain").unwrap();
+        store.commit(CommitInput {
+            message: "Draft".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+        store.update_node(&node.id.0, Some("Final"), None, None, None).unwrap();
+        assert!(matches!(store.watch(Duration::from_millis(10), || {}), Err(WillowError::FileWatch(_))));
+        drop(store);
+        assert!(!dir.exists());
+
+        let store = GraphStore::open(&path, &options).unwrap();
+        assert_eq!(store.get_node(&node.id.0).unwrap().content, "Final");
+        assert_eq!(store.get_attachment(&blob.hash).unwrap(), b"notes");
+        assert_eq!(store.get_repo().unwrap().log(None).unwrap().len(), 2);
+        assert_eq!(store.audit_log(&AuditQuery::default()).unwrap().len(), 5);
+        let written: Vec<PathBuf> = memory
+            .take_changes()
+            .into_iter()
+            .filter_map(|(path, data)| data.map(|_| path))
+            .collect();
+        assert!(written.contains(&path) && written.contains(&dir.join("repo/HEAD")));
+        drop(store);
+        files::unmount(&dir);
+    }
+
+    #[test]
+    fn test_external_change_detected_and_reloaded() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let changes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = changes.clone();
+        let _watcher = store
+            .watch(Duration::from_millis(5), move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+            .unwrap();
+        store.create_node("root", "detail", "Mine", None, None).unwrap();
+        assert!(!store.changed_on_disk().unwrap());
+
+        let mut other = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        other.create_node("root", "detail", "Theirs", None, None).unwrap();
+        assert!(store.changed_on_disk().unwrap());
+        assert!(matches!(
+            store.create_node("root", "detail", "Clobber", None, None),
+            Err(WillowError::ExternalChange(_))
+        ));
+        for _ in 0..200 {
+            if changes.load(std::sync::atomic::Ordering::SeqCst) > 0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        // One external save is several events: the temporary file, the
+        // checksum and the rename.
+        assert!(changes.load(std::sync::atomic::Ordering::SeqCst) >= 1);
+
+        // "Clobber" was rolled back; the undo history of "Mine" is kept.
+        assert_eq!(store.graph.nodes.len(), 2);
+        assert_eq!(store.session.undo_stack.len(), 1);
+        assert!(matches!(store.reload_if_changed(false), Err(WillowError::UnsavedChanges)));
+        assert!(store.reload_if_changed(true).unwrap());
+        assert!(!store.reload_if_changed(false).unwrap());
+        assert_eq!(store.graph.nodes.len(), 3);
+        assert!(!store.can_undo());
+        store.create_node("root", "detail", "Mine again", None, None).unwrap();
+    }
+
+    #[test]
+    fn test_refused_write_leaves_nothing_to_reload_over() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        store.vcs_init().unwrap();
+        let rule = Rule {
+            id: "tag".to_string(),
+            matcher: rules::RuleMatch {
+                content_contains: Some("Clobber".to_string()),
+                ..rules::RuleMatch::default()
+            },
+            actions: vec![rules::RuleAction::AddTag { tag: "seen".to_string() }],
+        };
+        store.set_rules(vec![rule]).unwrap();
+        let mut other = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        other.create_node("root", "detail", "Theirs", None, None).unwrap();
+        assert!(store.reload_if_changed(true).unwrap());
+        other.create_node("root", "detail", "Theirs again", None, None).unwrap();
+
+        // Neither the node nor the rule's tag outlives the refused save.
+        let before = serde_json::to_value(&store.graph).unwrap();
+        assert!(matches!(
+            store.create_node("root", "detail", "Clobber", None, None),
+            Err(WillowError::ExternalChange(_))
+        ));
+        assert_eq!(serde_json::to_value(&store.graph).unwrap(), before);
+        assert!(!store.can_undo() && !store.has_pending_changes());
+
+        // With nothing of its own to lose, the store reloads as asked.
+        assert!(store.reload_if_changed(false).unwrap());
+        assert_eq!(store.graph.nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_watch_fails_without_the_directory() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path().join("graphs");
+        std::fs::create_dir(&dir).unwrap();
+        let mut store = GraphStore::open(&dir.join("graph.json"), &OpenOptions::default()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(store.watch(Duration::from_millis(5), || {}), Err(WillowError::FileWatch(_))));
+    }
+
+    #[test]
+    fn test_reload_refuses_to_drop_work() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let mut other = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+
+        // Nothing to lose: reloads without being told to discard.
+        other.create_node("root", "detail", "Theirs", None, None).unwrap();
+        assert!(store.reload_if_changed(false).unwrap());
+        assert_eq!(store.graph.nodes.len(), 2);
+
+        // Never inside a transaction, even when discarding.
+        other.create_node("root", "detail", "Theirs again", None, None).unwrap();
+        store.begin_transaction().unwrap();
+        assert!(matches!(store.reload_if_changed(true), Err(WillowError::TransactionActive)));
+        store.rollback_transaction().unwrap();
+        assert!(store.reload_if_changed(false).unwrap());
+
+        // Pending VCS changes and undo history are kept unless discarded.
+        store.vcs_init().unwrap();
+        store.create_node("root", "detail", "Mine", None, None).unwrap();
+        assert!(store.has_pending_changes() && store.can_undo());
+        assert!(other.reload_if_changed(true).unwrap());
+        other.create_node("root", "detail", "Theirs last", None, None).unwrap();
+        assert!(matches!(store.reload_if_changed(false), Err(WillowError::UnsavedChanges)));
+        assert!(store.has_pending_changes() && store.can_undo());
+        assert!(store.reload_if_changed(true).unwrap());
+        assert!(!store.has_pending_changes() && !store.can_undo());
+        assert_eq!(store.graph.nodes.len(), 5);
+    }
+
+    #[test]
+    fn test_journal_mode_appends_and_compacts() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let journaled = OpenOptions { journal: Some(3), ..OpenOptions::default() };
+        let mut store = GraphStore::open(&path, &journaled).unwrap();
+        let written = std::fs::read(&path).unwrap();
+
+        let tea = store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        let coffee = store.create_node("root", "detail", "Likes coffee", None, None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), written);
+        assert_eq!(std::fs::read_to_string(journal::journal_path(&path)).unwrap().lines().count(), 2);
+
+        // Reopening replays the journal without compacting, as it is under the limit.
+        let reopened = GraphStore::open(&path, &journaled).unwrap();
+        assert_eq!(reopened.graph.nodes[&reopened.graph.root_id].children, vec![tea.id.clone(), coffee.id.clone()]);
+        // Dropping it would compact, which `store` would then see as an external write.
+        std::mem::forget(reopened);
+
+        store.delete_node(&tea.id.0).unwrap();
+        assert!(!journal::journal_path(&path).exists());
+        let compacted = storage::load_graph(&path).unwrap();
+        assert_eq!(compacted.nodes.len(), 2);
+
+        store.update_node(&coffee.id.0, Some("Likes espresso"), None, None, None).unwrap();
+        drop(store);
+        assert!(!journal::journal_path(&path).exists());
+        assert_eq!(storage::load_graph(&path).unwrap().nodes[&coffee.id].content, "Likes espresso");
+    }
+
+    #[test]
+    fn test_write_behind_coalesces_saves() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let hour = Duration::from_secs(3600);
+        let held = OpenOptions {
+            write_behind: Some(WriteBehind { debounce: hour, max_delay: hour }),
+            ..OpenOptions::default()
+        };
+        let mut store = GraphStore::open(&path, &held).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        for name in ["Alice", "Bob", "Carol"] {
+            store.create_node("root", "entity", name, None, None).unwrap();
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), written);
+        assert!(!store.flush_if_due().unwrap());
+        store.flush().unwrap();
+        assert_eq!(storage::load_graph(&path).unwrap().nodes.len(), 4);
+
+        let dave = store.create_node("root", "entity", "Dave", None, None).unwrap();
+        drop(store);
+        assert!(storage::load_graph(&path).unwrap().nodes.contains_key(&dave.id));
+    }
+
+    #[test]
+    fn test_shared_write_behind_flushes_in_background() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let held = OpenOptions {
+            write_behind: Some(WriteBehind { debounce: Duration::from_millis(20), max_delay: Duration::from_secs(1) }),
+            ..OpenOptions::default()
+        };
+        let store = GraphStore::open_shared(&path, &held).unwrap();
+        let alice = store.lock().create_node("root", "entity", "Alice", None, None).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // A read can land between the checksum and the file being replaced.
+        while !storage::load_graph(&path).is_ok_and(|graph| graph.nodes.contains_key(&alice.id)) {
+            assert!(Instant::now() < deadline, "write-behind never flushed");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_import_markdown_is_one_operation_and_commit() {
+        let (_dir, mut store) = temp_vcs_store();
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        let report = store.import_markdown(&work.id.0, "## Projects\n- Falcon\n  - Due in May\n", &mut Progress::default()).unwrap();
+        assert_eq!((report.nodes, report.root_ids.len()), (3, 1));
+        assert!(report.commit.is_some());
+        assert!(!store.has_pending_changes());
+
+        let projects = &store.graph.nodes[&report.root_ids[0]];
+        assert_eq!(projects.parent_id.as_ref(), Some(&work.id));
+        let falcon = &store.graph.nodes[&projects.children[0]];
+        assert_eq!((falcon.node_type.clone(), falcon.children.len()), (NodeType::Entity, 1));
+
+        store.undo().unwrap();
+        assert!(store.graph.nodes[&work.id].children.is_empty());
+        assert_eq!(store.graph.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_open_creates_default_graph() {
+        let store = temp_store();
+        assert!(store.graph.nodes.contains_key(&NodeId("root".into())));
+        assert_eq!(store.graph.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_create_and_get_node() {
+        let mut store = temp_store();
+        let node = store
+            .create_node("root", "category", "Hobbies", None, None)
+            .unwrap();
+        assert_eq!(node.content, "Hobbies");
+        assert_eq!(node.node_type, NodeType::Category);
+
+        let ctx = store.get_context(&node.id.0, Some(1), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.node.content, "Hobbies");
+        assert_eq!(ctx.ancestors.len(), 1); // root
+        assert_eq!(&*ctx.ancestors[0].id.0, "root");
+    }
+
+    #[test]
+    fn test_create_node_invalid_parent() {
+        let mut store = temp_store();
+        let result = store.create_node("nonexistent", "category", "Test", None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_create_node_invalid_type() {
+        let mut store = temp_store();
+        let result = store.create_node("root", "invalid_type", "Test", None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_update_node_tracks_history() {
+        let mut store = temp_store();
+        let node = store
+            .create_node("root", "detail", "Favorite color: blue", None, None)
+            .unwrap();
+
+        let updated = store
+            .update_node(
+                &node.id.0,
+                Some("Favorite color: green"),
+                None,
+                None,
+                Some("Changed preference"),
+            )
+            .unwrap();
+
+        assert_eq!(updated.content, "Favorite color: green");
+        assert_eq!(updated.previous_values.len(), 1);
+        assert_eq!(updated.previous_values[0].old_content, "Favorite color: blue");
+        assert_eq!(
+            updated.previous_values[0].reason.as_deref(),
+            Some("Changed preference")
+        );
+    }
+
+    #[test]
+    fn test_update_node_same_content_no_history() {
+        let mut store = temp_store();
+        let node = store
+            .create_node("root", "detail", "Same content", None, None)
+            .unwrap();
+
+        let updated = store
+            .update_node(&node.id.0, Some("Same content"), None, None, None)
+            .unwrap();
+
+        assert!(updated.previous_values.is_empty());
+    }
+
+    #[test]
+    fn test_delete_node_cascades() {
+        let mut store = temp_store();
+        let cat = store
+            .create_node("root", "category", "Hobbies", None, None)
+            .unwrap();
+        let detail = store
+            .create_node(&cat.id.0, "detail", "Reading", None, None)
+            .unwrap();
+        let _link = store.add_link(&cat.id.0, &detail.id.0, "includes", false, None).unwrap();
+
+        assert_eq!(store.graph.nodes.len(), 3);
+        assert_eq!(store.graph.links.len(), 1);
+
+        store.delete_node(&cat.id.0).unwrap();
+
+        assert_eq!(store.graph.nodes.len(), 1); // only root
+        assert_eq!(store.graph.links.len(), 0);
+        // Root's children should be empty
+        assert!(store.graph.nodes[&NodeId("root".into())]
+            .children
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cannot_delete_root() {
+        let mut store = temp_store();
+        let result = store.delete_node("root");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_add_link() {
+        let mut store = temp_store();
+        let a = store
+            .create_node("root", "category", "A", None, None)
+            .unwrap();
+        let b = store
+            .create_node("root", "category", "B", None, None)
+            .unwrap();
+        let link = store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
+
+        assert_eq!(link.from_node, a.id);
+        assert_eq!(link.to_node, b.id);
+        assert_eq!(link.relation, "related_to");
+        assert!(!link.bidirectional);
+        assert!(link.confidence.is_none());
+    }
+
+    #[test]
+    fn test_duplicate_link_rejected() {
+        let mut store = temp_store();
+        let a = store
+            .create_node("root", "category", "A", None, None)
+            .unwrap();
+        let b = store
+            .create_node("root", "category", "B", None, None)
+            .unwrap();
+        store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
+        let result = store.add_link(&a.id.0, &b.id.0, "related_to", false, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_add_link_with_confidence() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        let b = store.create_node("root", "category", "B", None, None).unwrap();
+
+        let link = store.add_link(&a.id.0, &b.id.0, "caused_by", true, Some("high")).unwrap();
+        assert!(link.bidirectional);
+        assert_eq!(link.confidence, Some(ConfidenceLevel::High));
+    }
+
+    #[test]
+    fn test_bidirectional_duplicate_detection() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        let b = store.create_node("root", "category", "B", None, None).unwrap();
+
+        // Create A->B bidirectional
+        store.add_link(&a.id.0, &b.id.0, "related_to", true, None).unwrap();
+
+        // B->A with same relation should be rejected when creating as bidirectional
+        let result = store.add_link(&b.id.0, &a.id.0, "related_to", true, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_update_link() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        let b = store.create_node("root", "category", "B", None, None).unwrap();
+
+        let link = store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
+
+        let updated = store.update_link(&link.id.0, Some("caused_by"), Some(true), Some("high")).unwrap();
+        assert_eq!(updated.relation, "caused_by");
+        assert!(updated.bidirectional);
+        assert_eq!(updated.confidence, Some(ConfidenceLevel::High));
+
+        // Partial update
+        let updated2 = store.update_link(&link.id.0, None, None, Some("low")).unwrap();
+        assert_eq!(updated2.relation, "caused_by"); // unchanged
+        assert!(updated2.bidirectional); // unchanged
+        assert_eq!(updated2.confidence, Some(ConfidenceLevel::Low));
+    }
+
+    #[test]
+    fn test_reorder_children() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        let b = store.create_node("root", "category", "B", None, None).unwrap();
+        let c = store.create_node("root", "category", "C", None, None).unwrap();
+
+        let order = vec![c.id.0.to_string(), a.id.0.to_string(), b.id.0.to_string()];
+        let root = store.reorder_children("root", &order).unwrap();
+        assert_eq!(root.children, vec![c.id.clone(), a.id.clone(), b.id.clone()]);
+
+        // Missing or extra ids are rejected
+        assert!(store.reorder_children("root", &order[..2]).is_err());
+        let dup = vec![a.id.0.to_string(), a.id.0.to_string(), b.id.0.to_string()];
+        assert!(store.reorder_children("root", &dup).is_err());
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let mut store = temp_store();
+        let result: Result<(), WillowError> = store.transaction(|tx| {
+            tx.create_node("root", "category", "Kept?", None, None)?;
+            tx.create_node("missing", "detail", "Fails", None, None)?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(store.graph.nodes.len(), 1);
+        assert!(!store.in_transaction());
+
+        // Disk was never touched
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.graph.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_saves_once_on_success() {
+        let mut store = temp_store();
+        store.begin_transaction().unwrap();
+        let cat = store.create_node("root", "category", "Hobbies", None, None).unwrap();
+        store.create_node(&cat.id.0, "detail", "Reading", None, None).unwrap();
+        assert_eq!(GraphStore::open(&store.path, &OpenOptions::default()).unwrap().graph.nodes.len(), 1);
+
+        store.commit_transaction().unwrap();
+        assert_eq!(GraphStore::open(&store.path, &OpenOptions::default()).unwrap().graph.nodes.len(), 3);
+        assert!(store.commit_transaction().is_err());
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut store = temp_store();
+        assert!(!store.undo().unwrap());
+
+        let node = store.create_node("root", "detail", "Original", None, None).unwrap();
+        store.update_node(&node.id.0, Some("Edited"), None, None, None).unwrap();
+
+        assert!(store.undo().unwrap());
+        assert_eq!(store.graph.nodes[&node.id].content, "Original");
+        assert!(store.undo().unwrap());
+        assert!(!store.graph.nodes.contains_key(&node.id));
+        assert!(store.graph.nodes[&NodeId("root".into())].children.is_empty());
+
+        assert!(store.redo().unwrap());
+        assert!(store.redo().unwrap());
+        assert_eq!(store.graph.nodes[&node.id].content, "Edited");
+        assert!(!store.can_redo());
+
+        // A new edit clears the redo stack
+        store.undo().unwrap();
+        store.create_node("root", "detail", "Other", None, None).unwrap();
+        assert!(!store.can_redo());
+    }
+
+    /// Run `step`, then check undo restores the graph exactly and redo
+    /// brings the step back, leaving it undone.
+    fn assert_undo_exact(store: &mut GraphStore, step: impl FnOnce(&mut GraphStore)) {
+        let before = serde_json::to_value(&store.graph).unwrap();
+        step(store);
+        let after = serde_json::to_value(&store.graph).unwrap();
+        assert!(store.undo().unwrap());
+        assert_eq!(serde_json::to_value(&store.graph).unwrap(), before);
+        assert!(store.redo().unwrap());
+        assert_eq!(serde_json::to_value(&store.graph).unwrap(), after);
+        assert!(store.undo().unwrap());
+    }
+
+    #[test]
+    fn test_undo_restores_exact_graph() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap().id.0.to_string();
+        let b = store.create_node("root", "category", "B", None, None).unwrap().id.0.to_string();
+        let c = store.create_node("root", "detail", "C", None, None).unwrap().id.0.to_string();
+        let d = store.create_node(&b, "detail", "D", None, None).unwrap().id.0.to_string();
+        store.add_link(&d, &c, "related_to", false, None).unwrap();
+        store.add_parent(&c, &a).unwrap();
+        store.update_node(&a, Some("A1"), None, None, None).unwrap();
+
+        assert_undo_exact(&mut store, |s| s.delete_node(&b).unwrap());
+        assert_undo_exact(&mut store, |s| {
+            s.update_node(&a, Some("A2"), None, None, Some("typo")).unwrap();
+        });
+        assert_undo_exact(&mut store, |s| {
+            s.update_node(&c, None, Some(HashMap::from([("k".into(), "v".into())])), None, None).unwrap();
+        });
+        assert_undo_exact(&mut store, |s| {
+            s.remove_parent(&c, "root").unwrap();
+        });
+        assert_undo_exact(&mut store, |s| {
+            s.remove_parent(&c, &a).unwrap();
+        });
+        assert_undo_exact(&mut store, |s| {
+            s.reorder_children("root", &[c.clone(), b.clone(), a.clone()]).unwrap();
+        });
+        assert_undo_exact(&mut store, |s| {
+            s.pin_node(&a).unwrap();
+        });
+        assert_undo_exact(&mut store, |s| {
+            s.supersede_node(&c, "C3").unwrap();
+        });
+        assert_undo_exact(&mut store, |s| s.delete_node(&a).unwrap());
+    }
+
+    #[test]
+    fn test_clone_subtree() {
+        let mut store = temp_store();
+        let template = store.create_node("root", "category", "Template: weekly review", None, None).unwrap();
+        let a = store.create_node(&template.id.0, "detail", "Wins", None, None).unwrap();
+        let b = store.create_node(&template.id.0, "detail", "Blockers", None, None).unwrap();
+        store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
+        let target = store.create_node("root", "category", "Reviews", None, None).unwrap();
+
+        let copy = store.clone_subtree(&template.id.0, &target.id.0, true).unwrap();
+        assert_ne!(copy.id, template.id);
+        assert_eq!(copy.parent_id.as_ref(), Some(&target.id));
+        assert_eq!(copy.children.len(), 2);
+        assert!(copy.children.iter().all(|c| c != &a.id && c != &b.id));
+        assert_eq!(store.graph.nodes.len(), 8);
+        assert_eq!(store.graph.links.len(), 2);
+
+        store.clone_subtree(&template.id.0, &target.id.0, false).unwrap();
+        assert_eq!(store.graph.nodes.len(), 11);
+        assert_eq!(store.graph.links.len(), 2);
+
+        // One undo removes the whole copy
+        store.undo().unwrap();
+        assert_eq!(store.graph.nodes.len(), 8);
+        assert!(store.clone_subtree("root", &target.id.0, false).is_err());
+    }
+
+    #[test]
+    fn test_copies_keep_sensitivity() {
+        let mut store = temp_store();
+        let health = store.create_node("root", "category", "Health", None, None).unwrap();
+        let pin = store.create_node(&health.id.0, "detail", "Bank pin 4321", None, None).unwrap();
+        store.set_sensitivity(&pin.id.0, "secret").unwrap();
+        let target = store.create_node("root", "category", "Copies", None, None).unwrap();
+
+        let copy = store.clone_subtree(&pin.id.0, &target.id.0, false).unwrap();
+        assert_eq!(copy.sensitivity, Sensitivity::Secret);
+        let tree = store.clone_subtree(&health.id.0, &target.id.0, true).unwrap();
+        assert!(tree.children.iter().all(|c| store.graph.nodes[c].sensitivity == Sensitivity::Secret));
+        let newer = store.supersede_node(&pin.id.0, "Bank pin 8765").unwrap();
+        assert_eq!(newer.sensitivity, Sensitivity::Secret);
+
+        assert!(store.search_nodes("pin", &Page::default(), None, &SearchOptions::default()).results.is_empty());
+    }
+
+    #[test]
+    fn test_metadata_schema_enforced() {
+        use crate::schema::{FieldSpec, NodeTypeSchema, ValueType};
+
+        let mut store = temp_store();
+        let mut schema = MetadataSchema::default();
+        schema.types.insert(
+            "event".to_string(),
+            NodeTypeSchema {
+                fields: [("date".to_string(), FieldSpec { value_type: ValueType::Date, required: true })]
+                    .into_iter()
+                    .collect(),
+                allow_unknown: true,
+            },
+        );
+        store.set_metadata_schema(schema).unwrap();
+
+        let err = store.create_node("root", "event", "Started job", None, None).unwrap_err();
+        assert!(matches!(err, WillowError::SchemaViolation(_)));
+
+        let dated = HashMap::from([("date".to_string(), "2023-04-01".to_string())]);
+        let event = store.create_node("root", "event", "Started job", Some(dated), None).unwrap();
+        assert!(store
+            .update_node(&event.id.0, None, Some(HashMap::new()), None, None)
+            .is_err());
+
+        // Schema survives reopen
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert!(reopened.schema.types.contains_key("event"));
+    }
+
+    #[test]
+    fn test_relation_registry_enforced() {
+        use crate::relations::RelationSpec;
+
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        let b = store.create_node("root", "category", "B", None, None).unwrap();
+        store.add_link(&a.id.0, &b.id.0, "legacy", false, None).unwrap();
+
+        let mut registry = RelationRegistry::default();
+        registry.relations.insert(
+            "related_to".to_string(),
+            RelationSpec { allow_bidirectional: true, inverse: None },
+        );
+        store.set_relation_registry(registry).unwrap();
+
+        assert!(store.add_link(&a.id.0, &b.id.0, "related-to", false, None).is_err());
+        let link = store.add_link(&a.id.0, &b.id.0, "related_to", true, None).unwrap();
+        assert!(store.update_link(&link.id.0, Some("relates_to"), None, None).is_err());
+
+        let usage = store.list_relations();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].relation, "legacy");
+        assert!(!usage[0].registered);
+        assert_eq!(usage[1].count, 1);
+    }
+
+    #[test]
+    fn test_multi_parent_membership() {
+        let mut store = temp_store();
+        let skills = store.create_node("root", "category", "Skills", None, None).unwrap();
+        let hobbies = store.create_node("root", "category", "Hobbies", None, None).unwrap();
+        let rust = store.create_node(&skills.id.0, "entity", "Rust", None, None).unwrap();
+
+        let node = store.add_parent(&rust.id.0, &hobbies.id.0).unwrap();
+        assert_eq!(node.extra_parents, vec![hobbies.id.clone()]);
+        assert!(store.graph.nodes[&hobbies.id].children.contains(&rust.id));
+
+        let ctx = store.get_context(&rust.id.0, Some(0), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.ancestors.len(), 3); // skills, hobbies, root
+        let ctx = store.get_context("root", Some(3), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.descendants.len(), 3); // rust listed once
+
+        // Cycles are rejected
+        assert!(store.add_parent(&skills.id.0, &rust.id.0).is_err());
+
+        // Deleting one parent keeps the shared child under the other
+        store.delete_node(&skills.id.0).unwrap();
+        let rust_node = &store.graph.nodes[&rust.id];
+        assert_eq!(rust_node.parent_id.as_ref(), Some(&hobbies.id));
+        assert!(rust_node.extra_parents.is_empty());
+        assert!(store.remove_parent(&rust.id.0, &hobbies.id.0).is_err());
+
+        // Undo restores the original membership
+        store.undo().unwrap();
+        let rust_node = &store.graph.nodes[&rust.id];
+        assert_eq!(rust_node.parent_id.as_ref(), Some(&skills.id));
+        assert_eq!(rust_node.extra_parents, vec![hobbies.id.clone()]);
+    }
+
+    #[test]
+    fn test_repair_commits_snapshot() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        store.graph.nodes.get_mut(&store.graph.root_id.clone()).map(Arc::make_mut).unwrap().children.clear();
+        let kinds: Vec<&str> = store.validate().iter().map(|i| i.kind()).collect();
+        assert_eq!(kinds, vec!["child_not_listed", "unreachable"]);
+
+        let report = store.repair().unwrap();
+        assert_eq!(report.repaired.len(), 2);
+        assert!(report.remaining.is_empty());
+        assert!(store.validate().is_empty());
+        assert!(!store.has_pending_changes());
+        assert!(!store.can_undo());
+
+        let repo = store.get_repo().unwrap();
+        let head = repo.log(Some(1)).unwrap().remove(0);
+        assert!(head.data.message.starts_with("Repair"));
+        let committed = repo.reconstruct_at(&head.hash).unwrap();
+        assert!(committed.nodes[&committed.root_id].children.contains(&a.id));
+    }
+
+    #[test]
+    fn test_adopt_orphan() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "category", "A", None, None).unwrap();
+        let b = store.create_node(&a.id.0, "entity", "Findable", None, None).unwrap();
+        store.graph.nodes.get_mut(&a.id).map(Arc::make_mut).unwrap().parent_id = Some(NodeId("gone".into()));
+        store.graph.nodes.get_mut(&store.graph.root_id.clone()).map(Arc::make_mut).unwrap().children.clear();
+
+        let orphans: Vec<NodeId> = store.list_orphans().into_iter().map(|n| n.id).collect();
+        assert_eq!(orphans, vec![a.id.clone()]);
+        assert!(store.search_nodes("Findable", &Page::default(), None, &SearchOptions::default()).results.is_empty());
+        assert!(store.adopt_orphan(&a.id.0, &b.id.0).is_err());
+        assert!(store.adopt_orphan("root", &a.id.0).is_err());
+
+        store.adopt_orphan(&a.id.0, "root").unwrap();
+        assert!(store.list_orphans().is_empty());
+        assert_eq!(store.search_nodes("Findable", &Page::default(), None, &SearchOptions::default()).results.len(), 1);
+    }
+
+    #[test]
+    fn test_get_neighborhood() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "entity", "A", None, None).unwrap();
+        let b = store.create_node("root", "entity", "B", None, None).unwrap();
+        let c = store.create_node("root", "entity", "C", None, None).unwrap();
+        let d = store.create_node("root", "entity", "D", None, None).unwrap();
+        store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
+        store.add_link(&c.id.0, &b.id.0, "related_to", false, None).unwrap();
+        store.add_link(&c.id.0, &d.id.0, "related_to", false, None).unwrap();
+
+        let one = store.get_neighborhood(&a.id.0, Some(1)).unwrap();
+        assert_eq!(one.neighbors.len(), 1);
+        assert_eq!(one.links.len(), 1);
+
+        let two = store.get_neighborhood(&a.id.0, Some(2)).unwrap();
+        let hops: Vec<(String, u32)> = two.neighbors.iter().map(|n| (n.node.content.clone(), n.hops)).collect();
+        assert_eq!(hops, vec![("B".to_string(), 1), ("C".to_string(), 2)]);
+        assert_eq!(two.links.len(), 2);
+    }
+
+    #[test]
+    fn test_extract_subgraph() {
+        let mut store = temp_store();
+        let recipes = store.create_node("root", "category", "Recipes", None, None).unwrap();
+        let soup = store.create_node(&recipes.id.0, "entity", "Soup", None, None).unwrap();
+        let bread = store.create_node(&recipes.id.0, "entity", "Bread", None, None).unwrap();
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        store.add_link(&soup.id.0, &bread.id.0, "related_to", false, None).unwrap();
+        store.add_link(&soup.id.0, &work.id.0, "related_to", false, None).unwrap();
+
+        let sub = store.extract_subgraph(&recipes.id.0, Sensitivity::Normal).unwrap();
+        assert_eq!(sub.root_id, recipes.id);
+        assert_eq!(sub.nodes.len(), 3);
+        assert_eq!(sub.links.len(), 1);
+        assert!(sub.nodes[&recipes.id].parent_id.is_none());
+        assert!(crate::integrity::validate(&sub).is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recipes.json");
+        store.export_subgraph(&recipes.id.0, &path, Sensitivity::Normal).unwrap();
+        let other = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        assert_eq!(other.graph.nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_as_of_queries() {
+        let mut store = temp_store();
+        let date = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let span = |from: &str, until: &str| TemporalMetadata {
+            valid_from: Some(date(from)),
+            valid_until: Some(date(until)),
+            label: None,
+        };
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        store
+            .create_node(&work.id.0, "entity", "Acme Corp", None, Some(span("2020-01-01T00:00:00Z", "2022-12-31T00:00:00Z")))
+            .unwrap();
+        store
+            .create_node(&work.id.0, "entity", "Globex Corp", None, Some(span("2023-01-01T00:00:00Z", "2025-01-01T00:00:00Z")))
+            .unwrap();
+
+        let in_2022 = date("2022-06-01T00:00:00Z");
+        let hits = store.search_nodes_as_of("Corp", in_2022, &Page::default(), None, &SearchOptions::default()).results;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].content, "Acme Corp");
+
+        let ctx = store.get_context_as_of(&work.id.0, None, in_2022).unwrap();
+        assert_eq!(ctx.descendants.len(), 1);
+        assert_eq!(store.view_as_of(in_2022).nodes.len(), 3);
+        assert_eq!(store.search_nodes("Corp", &Page::default(), None, &SearchOptions::default()).results.len(), 2);
+    }
+
+    #[test]
+    fn test_sweep_expired_archives_in_one_commit() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let now = Utc::now();
+        let ended = TemporalMetadata {
+            valid_from: None,
+            valid_until: Some(now - chrono::Duration::days(30)),
+            label: None,
+        };
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        let old_job = store.create_node(&work.id.0, "entity", "Acme", None, Some(ended.clone())).unwrap();
+        let old_role = store.create_node(&old_job.id.0, "detail", "Engineer", None, Some(ended)).unwrap();
+        store.create_node(&work.id.0, "entity", "Globex", None, None).unwrap();
+        store.commit(CommitInput {
+            message: "setup".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+
+        let report = store.sweep_expired("archive", now, Some("nightly".to_string())).unwrap();
+        assert_eq!(report.expired.len(), 2);
+        assert!(report.commit.is_some());
+        assert!(!store.has_pending_changes());
+
+        let archive_id = store.graph.nodes[&old_job.id].parent_id.clone().unwrap();
+        assert_eq!(store.graph.nodes[&archive_id].content, "Archive");
+        assert_eq!(store.graph.nodes[&old_role.id].parent_id.as_ref(), Some(&old_job.id));
+        assert_eq!(store.graph.nodes[&old_role.id].metadata["expired"], "true");
+
+        let head = store.get_repo().unwrap().log(Some(1)).unwrap().remove(0);
+        assert!(matches!(head.data.source, CommitSource::Maintenance { .. }));
+
+        // Already-swept nodes are skipped
+        let again = store.sweep_expired("archive", now, None).unwrap();
+        assert!(again.expired.is_empty());
+    }
+
+    #[test]
+    fn test_rules_apply_on_mutation_and_in_maintenance_pass() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        let early = store.create_node("root", "event", "Planning meeting", None, None).unwrap();
+        let rule = Rule {
+            id: "meetings".to_string(),
+            matcher: rules::RuleMatch {
+                content_contains: Some("meeting".to_string()),
+                ..rules::RuleMatch::default()
+            },
+            actions: vec![
+                rules::RuleAction::AddTag { tag: "work".to_string() },
+                rules::RuleAction::MoveUnder { parent: work.id.clone() },
+            ],
+        };
+        store.set_rules(vec![rule]).unwrap();
+
+        let before = store.perf_report().operations["save"].count;
+        let later = store.create_node("root", "event", "Standup meeting", None, None).unwrap();
+        // The rules' changes are written with the operation, not after it.
+        assert_eq!(store.perf_report().operations["save"].count, before + 1);
+        let node = &store.graph.nodes[&later.id];
+        assert_eq!((node.metadata["tags"].as_str(), node.parent_id.as_ref()), ("work", Some(&work.id)));
+        assert!(store.graph.nodes[&early.id].metadata.is_empty());
+        // The rules' changes are undone with the operation that set them off.
+        store.undo().unwrap();
+        assert!(!store.graph.nodes.contains_key(&later.id));
+        assert!(store.graph.nodes[&work.id].children.is_empty());
+
+        assert!(matches!(store.run_rules(None), Err(WillowError::HasPendingChanges)));
+        store.commit(CommitInput {
+            message: "setup".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+        let report = store.run_rules(Some("nightly".to_string())).unwrap();
+        assert_eq!(report.changed, vec![early.id.clone()]);
+        assert_eq!(store.graph.nodes[&early.id].parent_id.as_ref(), Some(&work.id));
+        let head = store.get_repo().unwrap().log(Some(1)).unwrap().remove(0);
+        assert!(matches!(head.data.source, CommitSource::Maintenance { .. }));
+        assert!(store.run_rules(None).unwrap().changed.is_empty());
+
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.rules(), store.rules());
+    }
+
+    #[test]
+    fn test_webhooks_receive_filtered_events() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let (_tmp, mut store) = temp_vcs_store();
+        let config = crate::vcs::types::RepoConfig {
+            webhooks: vec![crate::webhooks::Webhook {
+                url: format!("http://{}/hook", listener.local_addr().unwrap()),
+                events: vec![WebhookEvent::Commit, WebhookEvent::Merge],
+                max_sensitivity: Sensitivity::Normal,
+            }],
+            ..store.get_repo().unwrap().config().clone()
+        };
+        store.set_repo_config(config).unwrap();
+        let next_delivery = || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader.get_mut().write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        // Branch events are filtered out, so the first delivery is the commit.
+        // Secret nodes, and everything under them, stay out of the payload.
+        store.create_branch("side").unwrap();
+        store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        let secret = store.create_node("root", "detail", "Bank pin", None, None).unwrap();
+        store.set_sensitivity(&secret.id.0, "secret").unwrap();
+        store.create_node(&secret.id.0, "detail", "1234", None, None).unwrap();
+        let hash = store.commit(CommitInput {
+            message: "tea".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+        let payload = next_delivery();
+        assert_eq!(payload["event"], "commit");
+        assert_eq!(payload["branch"], "main");
+        assert_eq!(payload["commit"], hash.0);
+        assert_eq!(payload["summary"]["nodes_created"].as_array().unwrap().len(), 1);
+        assert_eq!(payload["summary"]["nodes_created"][0]["content"], "Likes tea");
+        assert!(!payload.to_string().contains("Bank pin") && !payload.to_string().contains("1234"));
+
+        store.switch_branch("side").unwrap();
+        store.create_node("root", "detail", "Likes coffee", None, None).unwrap();
+        store.commit(CommitInput {
+            message: "coffee".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+        assert_eq!(next_delivery()["branch"], "side");
+        store.switch_branch("main").unwrap();
+        store.merge_branch("side").unwrap();
+        let payload = next_delivery();
+        assert_eq!((&payload["event"], &payload["branch"], &payload["source"]), (&"merge".into(), &"main".into(), &"side".into()));
+        assert_eq!(payload["summary"]["nodes_created"][0]["content"], "Likes coffee");
+    }
+
+    #[test]
+    fn test_run_due_jobs_commits_each_run() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let now = Utc::now();
+        let ended = TemporalMetadata {
+            valid_from: None,
+            valid_until: Some(now - chrono::Duration::days(1)),
+            label: None,
+        };
+        let old = store.create_node("root", "detail", "Old job", None, Some(ended)).unwrap();
+        store.commit(CommitInput {
+            message: "setup".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+        store.register_job(MaintenanceJob::ExpireTemporal { policy: temporal::ExpiryPolicy::Flag }, 24).unwrap();
+        store.register_job(MaintenanceJob::GcRepo, 1).unwrap();
+
+        let report = store.run_due_jobs(now).unwrap();
+        let summaries: Vec<_> = report.runs.iter().map(|(job, run)| (*job, run.summary.as_str())).collect();
+        assert_eq!(summaries, [("expire_temporal", "1 node(s) expired"), ("gc_repo", "0 unreachable commit(s) deleted")]);
+        assert_eq!(store.graph.nodes[&old.id].metadata["expired"], "true");
+        let head = store.get_repo().unwrap().log(Some(1)).unwrap().remove(0);
+        assert_eq!(Some(&head.hash), report.commit.as_ref());
+        assert!(matches!(head.data.source, CommitSource::Maintenance { job_id: Some(ref id) } if id == "expire_temporal,gc_repo"));
+
+        // Only the hourly job is due again in two hours; it changes nothing
+        // but still gets its commit, and the schedule remembers both runs.
+        let later = store.run_due_jobs(now + chrono::Duration::hours(2)).unwrap();
+        assert_eq!(later.runs.len(), 1);
+        assert!(later.commit.is_some());
+        let schedule = store.maintenance_schedule().unwrap();
+        assert_eq!(schedule.jobs[0].last_run.as_ref().unwrap().commit, report.commit);
+        assert_eq!(schedule.jobs[1].last_run.as_ref().unwrap().commit, later.commit);
+        assert!(store.run_due_jobs(now + chrono::Duration::hours(2)).unwrap().runs.is_empty());
+    }
+
+    #[test]
+    fn test_sweep_expired_supersede() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let now = Utc::now();
+        let ended = TemporalMetadata {
+            valid_from: None,
+            valid_until: Some(now - chrono::Duration::days(1)),
+            label: None,
+        };
+        let node = store.create_node("root", "entity", "Lives in Leeds", None, Some(ended)).unwrap();
+        store.commit(CommitInput {
+            message: "setup".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+
+        store.sweep_expired("supersede", now, None).unwrap();
+        let swept = &store.graph.nodes[&node.id];
+        assert!(swept.content.starts_with("(expired"));
+        assert_eq!(swept.previous_values[0].old_content, "Lives in Leeds");
+    }
+
+    #[test]
+    fn test_supersede_node() {
+        let mut store = temp_store();
+        let home = store.create_node("root", "category", "Home", None, None).unwrap();
+        let old = store.create_node(&home.id.0, "attribute", "Lives in Leeds", None, None).unwrap();
+
+        let new = store.supersede_node(&old.id.0, "Lives in York").unwrap();
+        assert_eq!(new.parent_id.as_ref(), Some(&home.id));
+        assert!(new.temporal.as_ref().unwrap().valid_until.is_none());
+        let old_node = &store.graph.nodes[&old.id];
+        assert!(old_node.temporal.as_ref().unwrap().valid_until.is_some());
+        assert_eq!(old_node.content, "Lives in Leeds");
+
+        let link = store.graph.links.values().find(|l| l.relation == "superseded_by").unwrap();
+        assert_eq!((&link.from_node, &link.to_node), (&old.id, &new.id));
+        assert_eq!(store.search_nodes("Lives in", &Page::default(), None, &SearchOptions::default()).results.len(), 2);
+
+        // Temporal edits are recorded, so undo restores the open-ended old node
+        store.undo().unwrap();
+        assert!(store.graph.nodes[&old.id].temporal.is_none());
+        assert!(!store.graph.nodes.contains_key(&new.id));
+    }
+
+    #[test]
+    fn test_pinned_nodes_rank_first() {
+        let mut store = temp_store();
+        store.create_node("root", "entity", "Coffee", None, None).unwrap();
+        let pinned = store.create_node("root", "entity", "Allergic to coffee beans", None, None).unwrap();
+
+        assert_eq!(store.search_nodes("coffee", &Page::default(), None, &SearchOptions::default()).results[0].content, "Coffee");
+        store.pin_node(&pinned.id.0).unwrap();
+        assert_eq!(store.search_nodes("coffee", &Page::default(), None, &SearchOptions::default()).results[0].node_id, pinned.id);
+        assert_eq!(store.list_pinned().len(), 1);
+
+        store.undo().unwrap();
+        assert!(store.list_pinned().is_empty());
+        store.pin_node(&pinned.id.0).unwrap();
+        store.unpin_node(&pinned.id.0).unwrap();
+        assert!(store.list_pinned().is_empty());
+    }
+
+    #[test]
+    fn test_attachments_gc_respects_history() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let node = store.create_node("root", "entity", "Passport", None, None).unwrap();
+        let kept = store.attach_blob(&node.id.0, b"scan", "image/png").unwrap();
+        let dropped = store.attach_blob(&node.id.0, b"draft", "application/pdf").unwrap();
+        assert_eq!(store.get_attachment(&kept.hash).unwrap(), b"scan");
+
+        store.detach_blob(&node.id.0, &dropped.hash).unwrap();
+        // Still reachable through undo history
+        assert!(store.gc_attachments(&mut Progress::default()).unwrap().is_empty());
+
+        store.commit(CommitInput {
+            message: "passport".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+        let repo = store.get_repo().unwrap();
+        let head = repo.log(Some(1)).unwrap().remove(0);
+        let diff = repo.diff(&head.data.parents[0], &head.hash).unwrap();
+        assert_eq!(diff.attachments_added.len(), 1);
+
+        // Committed history still references the detached blob
+        store.session.undo_stack.clear();
+        assert!(store.gc_attachments(&mut Progress::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_structured_content() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let table = r#"{"columns": ["item", "price"], "rows": [["tea", "3"], ["cake", "4"]]}"#;
+        let node = store.create_structured_node("root", "detail", "table", table, None, None).unwrap();
+        assert_eq!(node.content_format, ContentFormat::Table);
+        assert!(store.create_structured_node("root", "detail", "table", "[1]", None, None).is_err());
+        assert!(store.create_structured_node("root", "detail", "grid", table, None, None).is_err());
+        store.commit(CommitInput {
+            message: "prices".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+
+        assert!(store.update_node(&node.id.0, Some("not json"), None, None, None).is_err());
+        let updated = table.replace(r#""4""#, r#""5""#);
+        store.update_node(&node.id.0, Some(&updated), None, None, None).unwrap();
+        store.commit(CommitInput {
+            message: "cake price".to_string(),
+            source: CommitSource::Manual { tool_name: None },
+        }).unwrap();
+
+        let repo = store.get_repo().unwrap();
+        let head = repo.log(Some(1)).unwrap().remove(0);
+        let diff = repo.diff(&head.data.parents[0], &head.hash).unwrap();
+        let cells = &diff.nodes_updated[0].content_changes;
+        assert_eq!(cells.len(), 1);
+        assert_eq!(cells[0].path, "rows[1].price");
+    }
+
+    #[test]
+    fn test_build_context_budget() {
+        let mut store = temp_store();
+        let cat = store.create_node("root", "category", "Work", None, None).unwrap();
+        let fact = store.create_node(&cat.id.0, "detail", "Ships on Fridays", None, None).unwrap();
+        let linked = store.create_node("root", "detail", "Release checklist", None, None).unwrap();
+        let pinned = store.create_node("root", "detail", "Prefers short answers", None, None).unwrap();
+        store.add_link(&fact.id.0, &linked.id.0, "related_to", false, None).unwrap();
+        store.pin_node(&pinned.id.0).unwrap();
+
+        let ids = vec![fact.id.0.to_string(), pinned.id.0.to_string()];
+        let all = store.build_context(&ids, 1_000).unwrap();
+        let roles: Vec<_> = all.entries.iter().map(|e| (e.node.content.as_str(), e.role)).collect();
+        assert!(roles.contains(&("Work", ContextRole::Ancestor)));
+        assert!(roles.contains(&("Release checklist", ContextRole::Neighbor)));
+        assert_eq!(all.links.len(), 1);
+        assert_eq!(all.omitted, 0);
+
+        // The pinned node goes in first; the newer neighbor no longer fits
+        // but the requested fact does.
+        let tight = store.build_context(&ids, 21 + 16).unwrap();
+        let kept: Vec<_> = tight.entries.iter().map(|e| &e.node.id).collect();
+        assert_eq!(kept, vec![&fact.id, &pinned.id]);
+        assert_eq!(tight.used_chars, 37);
+        assert_eq!(tight.omitted, 3);
+    }
+
+    #[test]
+    fn test_limits_enforced() {
+        let mut store = temp_store();
+        store
+            .set_limits(Limits {
+                max_content_len: 8,
+                max_children: 1,
+                ..Limits::default()
+            })
+            .unwrap();
+
+        assert!(matches!(
+            store.create_node("root", "detail", "far too long", None, None),
+            Err(WillowError::ContentTooLarge { len: 12, max: 8 })
+        ));
+        let a = store.create_node("root", "detail", "short", None, None).unwrap();
+        assert!(matches!(
+            store.create_node("root", "detail", "second", None, None),
+            Err(WillowError::TooManyChildren { .. })
+        ));
+        assert!(store.update_node(&a.id.0, Some("also too long"), None, None, None).is_err());
+
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.limits.max_children, 1);
+    }
+
+    #[test]
+    fn test_search_nodes() {
+        let mut store = temp_store();
+        store
+            .create_node("root", "detail", "Favorite food is pizza", None, None)
+            .unwrap();
+        store
+            .create_node("root", "detail", "Works at Google", None, None)
+            .unwrap();
+
+        let results = store.search_nodes("pizza", &Page::default(), None, &SearchOptions::default()).results;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].content.contains("pizza"));
     }
 
-    pub fn search_nodes(
-        &self,
-        query: &str,
-        max_results: Option<usize>,
-        root_node_id: Option<&str>,
-    ) -> Vec<search::SearchResult> {
-        let root_nid = root_node_id.map(|id| NodeId(id.to_string()));
-        search::search_nodes(&self.graph, query, max_results.unwrap_or(10), root_nid.as_ref())
+    #[test]
+    fn test_delete_where() {
+        let (_tmp, mut store) = temp_vcs_store();
+        let cat = store.create_node("root", "category", "Log", None, None).unwrap();
+        let keep = store.create_node(&cat.id.0, "detail", "Keep me", None, None).unwrap();
+        let mut stale = Vec::new();
+        for i in 0..5 {
+            let event = store.create_node(&cat.id.0, "event", &format!("Event {i}"), None, None).unwrap();
+            store.create_node(&event.id.0, "detail", "note", None, None).unwrap();
+            stale.push(event);
+        }
+        store.add_link(&keep.id.0, &stale[0].id.0, "related_to", false, None).unwrap();
+        let undo_depth = store.session.undo_stack.len();
+
+        let filter = NodeFilter {
+            node_types: Some(vec![NodeType::Event]),
+            ..NodeFilter::default()
+        };
+        assert_eq!(store.delete_where(&filter).unwrap(), 10);
+        assert_eq!(store.graph.nodes[&cat.id].children, vec![keep.id.clone()]);
+        assert!(store.graph.links.is_empty());
+        assert_eq!(store.session.undo_stack.len(), undo_depth + 1);
+        assert!(matches!(store.delete_where(&NodeFilter::default()), Err(WillowError::EmptyFilter)));
+
+        assert!(store.undo().unwrap());
+        assert_eq!(store.graph.nodes.len(), 13);
+        assert_eq!(store.graph.links.len(), 1);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
+    #[test]
+    fn test_list_nodes_sorted_and_paged() {
+        let mut store = temp_store();
+        for content in ["delta", "alpha", "charlie", "bravo"] {
+            store.create_node("root", "detail", content, None, None).unwrap();
+        }
+        store.create_node("root", "event", "echo", None, None).unwrap();
 
-    fn temp_store() -> GraphStore {
-        let tmp = NamedTempFile::new().unwrap();
-        let path = tmp.path().to_path_buf();
-        // Drop the temp file so GraphStore creates it fresh
-        drop(tmp);
-        let _ = std::fs::remove_file(&path);
-        GraphStore::open(&path).unwrap()
+        let filter = NodeFilter {
+            node_types: Some(vec![NodeType::Detail]),
+            ..NodeFilter::default()
+        };
+        let sort = NodeSort {
+            field: SortField::Content,
+            descending: false,
+        };
+        let page = store.list_nodes(&filter, &sort, &Page { offset: 1, limit: Some(2) });
+        assert_eq!(page.total, 4);
+        let contents: Vec<_> = page.nodes.iter().map(|n| n.content.as_str()).collect();
+        assert_eq!(contents, vec!["bravo", "charlie"]);
+
+        let newest = store.list_nodes(
+            &NodeFilter::default(),
+            &NodeSort {
+                field: SortField::CreatedAt,
+                descending: true,
+            },
+            &Page { offset: 0, limit: Some(1) },
+        );
+        assert_eq!(newest.total, 6);
+        assert_eq!(newest.nodes[0].content, "echo");
     }
 
     #[test]
-    fn test_open_creates_default_graph() {
-        let store = temp_store();
-        assert!(store.graph.nodes.contains_key(&NodeId("root".to_string())));
-        assert_eq!(store.graph.nodes.len(), 1);
+    fn test_list_links_filtered_and_paged() {
+        let mut store = temp_store();
+        let a = store.create_node("root", "detail", "Alice", None, None).unwrap();
+        let b = store.create_node("root", "detail", "Bob", None, None).unwrap();
+        let c = store.create_node("root", "detail", "Carol", None, None).unwrap();
+        store.add_link(&a.id.0, &b.id.0, "knows", false, Some("high")).unwrap();
+        store.add_link(&a.id.0, &c.id.0, "knows", false, Some("low")).unwrap();
+        store.add_link(&a.id.0, &c.id.0, "works_with", false, None).unwrap();
+        store.add_link(&b.id.0, &c.id.0, "knows", false, Some("medium")).unwrap();
+
+        let from_alice = LinkFilter {
+            from_node: Some(a.id.clone()),
+            ..LinkFilter::default()
+        };
+        let page = store.list_links(&from_alice, &Page { offset: 1, limit: Some(1) });
+        assert_eq!(page.total, 3);
+        assert_eq!(page.links[0].to_node, c.id);
+
+        let confident = LinkFilter {
+            relation: Some("knows".to_string()),
+            min_confidence: Some(ConfidenceLevel::Medium),
+            ..LinkFilter::default()
+        };
+        let page = store.list_links(&confident, &Page::default());
+        let pairs: Vec<_> = page.links.iter().map(|l| (&l.from_node, &l.to_node)).collect();
+        assert_eq!(pairs, [(&a.id, &b.id), (&b.id, &c.id)]);
     }
 
     #[test]
-    fn test_create_and_get_node() {
+    fn test_count_and_existence() {
         let mut store = temp_store();
-        let node = store
-            .create_node("root", "category", "Hobbies", None, None)
-            .unwrap();
-        assert_eq!(node.content, "Hobbies");
-        assert_eq!(node.node_type, NodeType::Category);
+        let a = store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        store.create_node("root", "event", "Likes tea", None, None).unwrap();
 
-        let ctx = store.get_context(&node.id.0, Some(1)).unwrap();
-        assert_eq!(ctx.node.content, "Hobbies");
-        assert_eq!(ctx.ancestors.len(), 1); // root
-        assert_eq!(ctx.ancestors[0].id.0, "root");
+        assert_eq!(store.count_nodes(&NodeFilter::default()), 3);
+        let events = NodeFilter {
+            node_types: Some(vec![NodeType::Event]),
+            ..NodeFilter::default()
+        };
+        assert_eq!(store.count_nodes(&events), 1);
+        assert!(store.node_exists(&a.id.0));
+        assert!(!store.node_exists("missing"));
+        assert_eq!(store.find_by_content_exact("Likes tea").len(), 2);
+        assert!(store.find_by_content_exact("likes tea").is_empty());
     }
 
     #[test]
-    fn test_create_node_invalid_parent() {
+    fn test_retention_on_update_and_sweep() {
         let mut store = temp_store();
-        let result = store.create_node("nonexistent", "category", "Test", None, None);
-        assert!(result.is_err());
+        let node = store.create_node("root", "detail", "v0", None, None).unwrap();
+        for i in 1..=4 {
+            store.update_node(&node.id.0, Some(&format!("v{i}")), None, None, None).unwrap();
+        }
+        assert_eq!(store.graph.nodes[&node.id].previous_values.len(), 4);
+
+        store
+            .set_retention_policy(RetentionPolicy {
+                max_values: Some(2),
+                ..RetentionPolicy::default()
+            })
+            .unwrap();
+        assert_eq!(store.prune_history(Utc::now()).unwrap(), 2);
+        store.update_node(&node.id.0, Some("v5"), None, None, None).unwrap();
+        let history: Vec<_> = store.graph.nodes[&node.id]
+            .previous_values
+            .iter()
+            .map(|v| v.old_content.as_str())
+            .collect();
+        assert_eq!(history, vec!["v3", "v4"]);
+
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.retention.max_values, Some(2));
     }
 
     #[test]
-    fn test_create_node_invalid_type() {
-        let mut store = temp_store();
-        let result = store.create_node("root", "invalid_type", "Test", None, None);
-        assert!(result.is_err());
+    fn test_actor_attribution() {
+        let (_tmp, mut store) = temp_vcs_store();
+        store.set_actor(Some("extractor".to_string()));
+        let node = store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        assert_eq!(node.created_by.as_deref(), Some("extractor"));
+
+        store.set_actor(Some("user".to_string()));
+        let node = store.update_node(&node.id.0, Some("Likes green tea"), None, None, None).unwrap();
+        assert_eq!(node.created_by.as_deref(), Some("extractor"));
+        assert_eq!(node.updated_by.as_deref(), Some("user"));
+
+        let actors: Vec<_> = store.pending_changes().iter().map(|c| c.actor()).collect();
+        assert_eq!(actors, vec![Some("extractor"), Some("user"), Some("user")]);
     }
 
     #[test]
-    fn test_update_node_tracks_history() {
-        let mut store = temp_store();
-        let node = store
-            .create_node("root", "detail", "Favorite color: blue", None, None)
-            .unwrap();
+    fn test_profiles_scope_search_and_context() {
+        let (_dir, mut store) = temp_vcs_store();
+        store.create_node("root", "detail", "Likes pizza", None, None).unwrap();
+        let work = store.create_profile("work").unwrap();
+        store.create_node(&work.id.0, "detail", "Pizza Fridays at the office", None, None).unwrap();
+        assert!(matches!(store.create_profile("work"), Err(WillowError::ProfileExists(_))));
+        assert!(integrity::validate(&store.graph).is_empty());
+
+        let opts = SearchOptions::default();
+        assert_eq!(store.search_nodes("pizza", &Page::default(), None, &opts).results[0].content, "Likes pizza");
+        store.switch_profile("work").unwrap();
+        let results = store.search_nodes("pizza", &Page::default(), None, &opts).results;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "Pizza Fridays at the office");
+        let ctx = store.get_context("root", Some(1), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.node.id, work.id);
+        assert!(matches!(store.delete_node(&work.id.0), Err(WillowError::CannotDeleteRoot)));
+        assert!(matches!(store.switch_profile("play"), Err(WillowError::ProfileNotFound(_))));
 
-        let updated = store
-            .update_node(
-                &node.id.0,
-                Some("Favorite color: green"),
-                None,
-                None,
-                Some("Changed preference"),
-            )
+        store
+            .commit(CommitInput {
+                message: "Add work profile".to_string(),
+                source: CommitSource::Manual { tool_name: None },
+            })
             .unwrap();
-
-        assert_eq!(updated.content, "Favorite color: green");
-        assert_eq!(updated.previous_values.len(), 1);
-        assert_eq!(updated.previous_values[0].old_content, "Favorite color: blue");
-        assert_eq!(
-            updated.previous_values[0].reason.as_deref(),
-            Some("Changed preference")
-        );
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.list_profiles()[1], ("work".to_string(), work.id.clone()));
     }
 
     #[test]
-    fn test_update_node_same_content_no_history() {
+    fn test_display_hints_roundtrip_and_undo() {
         let mut store = temp_store();
-        let node = store
-            .create_node("root", "detail", "Same content", None, None)
-            .unwrap();
+        let node = store.create_node("root", "category", "Travel", None, None).unwrap();
+        let hints = DisplayHints {
+            icon: Some("plane".to_string()),
+            color: Some("#3366ff".to_string()),
+            collapsed: true,
+        };
+        store.set_display(&node.id.0, Some(hints.clone())).unwrap();
 
-        let updated = store
-            .update_node(&node.id.0, Some("Same content"), None, None, None)
-            .unwrap();
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        assert_eq!(reopened.get_node(&node.id.0).unwrap().display, Some(hints));
+        store.undo().unwrap();
+        assert_eq!(store.get_node(&node.id.0).unwrap().display, None);
+    }
 
-        assert!(updated.previous_values.is_empty());
+    #[derive(Clone, Default)]
+    struct RecordingIndex(Arc<Mutex<Vec<String>>>);
+
+    impl SearchIndex for RecordingIndex {
+        fn on_node_created(&mut self, node: &Node) {
+            self.0.lock().unwrap().push(format!("created {}", node.content));
+        }
+        fn on_node_updated(&mut self, node: &Node) {
+            self.0.lock().unwrap().push(format!("updated {}", node.content));
+        }
+        fn on_node_deleted(&mut self, node_id: &NodeId) {
+            self.0.lock().unwrap().push(format!("deleted {}", node_id.0));
+        }
+        fn on_reset(&mut self, graph: &Graph) {
+            self.0.lock().unwrap().push(format!("reset {}", graph.nodes.len()));
+        }
     }
 
     #[test]
-    fn test_delete_node_cascades() {
+    fn test_search_index_hooks() {
         let mut store = temp_store();
-        let cat = store
-            .create_node("root", "category", "Hobbies", None, None)
-            .unwrap();
-        let detail = store
-            .create_node(&cat.id.0, "detail", "Reading", None, None)
-            .unwrap();
-        let _link = store.add_link(&cat.id.0, &detail.id.0, "includes", false, None).unwrap();
+        let index = RecordingIndex::default();
+        store.add_search_index(Box::new(index.clone()));
 
-        assert_eq!(store.graph.nodes.len(), 3);
-        assert_eq!(store.graph.links.len(), 1);
-
-        store.delete_node(&cat.id.0).unwrap();
+        let node = store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        store.update_node(&node.id.0, Some("Likes green tea"), None, None, None).unwrap();
+        store.delete_node(&node.id.0).unwrap();
+        store.undo().unwrap();
+        store.begin_transaction().unwrap();
+        store.create_node("root", "detail", "Scratch", None, None).unwrap();
+        store.rollback_transaction().unwrap();
 
-        assert_eq!(store.graph.nodes.len(), 1); // only root
-        assert_eq!(store.graph.links.len(), 0);
-        // Root's children should be empty
-        assert!(store.graph.nodes[&NodeId("root".to_string())]
-            .children
-            .is_empty());
+        assert_eq!(
+            *index.0.lock().unwrap(),
+            vec![
+                "created Likes tea".to_string(),
+                "updated Likes green tea".to_string(),
+                format!("deleted {}", node.id.0),
+                "created Likes green tea".to_string(),
+                "created Scratch".to_string(),
+                "reset 2".to_string(),
+            ]
+        );
     }
 
     #[test]
-    fn test_cannot_delete_root() {
+    fn test_suggest_tracks_mutations() {
         let mut store = temp_store();
-        let result = store.delete_node("root");
-        assert!(result.is_err());
+        let tags: HashMap<String, String> = HashMap::from([("tags".to_string(), "guitar, music".to_string())]);
+        let a = store.create_node("root", "detail", "Guitar lessons", Some(tags), None).unwrap();
+        store.create_node("root", "detail", "Bought a new guitar", None, None).unwrap();
+        store.create_node("root", "detail", "Likes bluegrass", None, None).unwrap();
+
+        let texts = |store: &GraphStore, prefix: &str| -> Vec<String> {
+            store.suggest(prefix, 10).into_iter().map(|s| s.text).collect()
+        };
+        assert_eq!(texts(&store, "gui"), vec!["Guitar lessons", "guitar", "Bought a new guitar"]);
+        assert_eq!(texts(&store, "grass"), vec!["Likes bluegrass"]);
+        assert_eq!(store.suggest("gui", 1).len(), 1);
+
+        store.update_node(&a.id.0, Some("Piano lessons"), None, None, None).unwrap();
+        assert_eq!(texts(&store, "pia"), vec!["Piano lessons"]);
+        store.delete_node(&a.id.0).unwrap();
+        assert_eq!(texts(&store, "gui"), vec!["Bought a new guitar"]);
+        store.undo().unwrap();
+        assert_eq!(texts(&store, "pia"), vec!["Piano lessons"]);
     }
 
     #[test]
-    fn test_add_link() {
+    fn test_text_analysis_persists_and_applies() {
         let mut store = temp_store();
-        let a = store
-            .create_node("root", "category", "A", None, None)
-            .unwrap();
-        let b = store
-            .create_node("root", "category", "B", None, None)
+        store.create_node("root", "detail", "Work at the bakery", None, None).unwrap();
+        store
+            .set_text_analysis(TextAnalysis {
+                stemmer: None,
+                synonyms: vec![vec!["job".to_string(), "work".to_string()]],
+            })
             .unwrap();
-        let link = store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
 
-        assert_eq!(link.from_node, a.id);
-        assert_eq!(link.to_node, b.id);
-        assert_eq!(link.relation, "related_to");
-        assert!(!link.bidirectional);
-        assert!(link.confidence.is_none());
+        let reopened = GraphStore::open(&store.path, &OpenOptions::default()).unwrap();
+        let page = reopened.search_nodes("job", &Page::default(), None, &SearchOptions::default());
+        assert_eq!(page.total, 1);
     }
 
     #[test]
-    fn test_duplicate_link_rejected() {
+    fn test_ranking_boosts_favour_types_and_recent_updates() {
         let mut store = temp_store();
-        let a = store
-            .create_node("root", "category", "A", None, None)
+        let old = store.create_node("root", "detail", "Lives in Leeds", None, None).unwrap();
+        let entity = store.create_node("root", "entity", "Lives in Leeds", None, None).unwrap();
+        let opts = SearchOptions::default();
+        let top = |store: &GraphStore| store.search_nodes("leeds", &Page::default(), None, &opts).results[0].node_id.clone();
+
+        store
+            .set_ranking_boosts(RankingBoosts {
+                type_weights: BTreeMap::from([("detail".to_string(), 2.0)]),
+                ..RankingBoosts::default()
+            })
             .unwrap();
-        let b = store
-            .create_node("root", "category", "B", None, None)
+        assert_eq!(top(&store), old.id);
+
+        store.graph.nodes.get_mut(&old.id).map(Arc::make_mut).unwrap().updated_at = Utc::now() - chrono::Duration::days(365);
+        store
+            .set_ranking_boosts(RankingBoosts {
+                recency_weight: 1.0,
+                ..RankingBoosts::default()
+            })
             .unwrap();
-        store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
-        let result = store.add_link(&a.id.0, &b.id.0, "related_to", false, None);
-        assert!(result.is_err());
+        assert_eq!(top(&store), entity.id);
+        assert_eq!(GraphStore::open(&store.path, &OpenOptions::default()).unwrap().ranking.recency_weight, 1.0);
+
+        let bad = RankingBoosts {
+            type_weights: BTreeMap::from([("gadget".to_string(), 1.0)]),
+            ..RankingBoosts::default()
+        };
+        assert!(matches!(store.set_ranking_boosts(bad), Err(WillowError::InvalidNodeType(_))));
     }
 
     #[test]
-    fn test_add_link_with_confidence() {
+    fn test_search_pagination() {
         let mut store = temp_store();
-        let a = store.create_node("root", "category", "A", None, None).unwrap();
-        let b = store.create_node("root", "category", "B", None, None).unwrap();
-
-        let link = store.add_link(&a.id.0, &b.id.0, "caused_by", true, Some("high")).unwrap();
-        assert!(link.bidirectional);
-        assert_eq!(link.confidence, Some(ConfidenceLevel::High));
+        for i in 0..25 {
+            store.create_node("root", "detail", &format!("Note {i}"), None, None).unwrap();
+        }
+        let opts = SearchOptions::default();
+        let first = store.search_nodes("note", &Page { offset: 0, limit: Some(10) }, None, &opts);
+        let last = store.search_nodes("note", &Page { offset: 20, limit: Some(10) }, None, &opts);
+        assert_eq!((first.results.len(), first.total), (10, 25));
+        assert_eq!((last.results.len(), last.total), (5, 25));
+        assert_ne!(first.results[0].node_id, last.results[0].node_id);
     }
 
     #[test]
-    fn test_bidirectional_duplicate_detection() {
+    fn test_archived_subtree_skipped() {
         let mut store = temp_store();
-        let a = store.create_node("root", "category", "A", None, None).unwrap();
-        let b = store.create_node("root", "category", "B", None, None).unwrap();
-
-        // Create A->B bidirectional
-        store.add_link(&a.id.0, &b.id.0, "related_to", true, None).unwrap();
+        let old = store.create_node("root", "category", "Old projects", None, None).unwrap();
+        store.create_node(&old.id.0, "detail", "Project Falcon", None, None).unwrap();
+        store.create_node("root", "detail", "Project Heron", None, None).unwrap();
+        store.archive_node(&old.id.0).unwrap();
+
+        assert_eq!(store.search_nodes("project", &Page::default(), None, &SearchOptions::default()).results.len(), 1);
+        let with_archived = SearchOptions {
+            include_archived: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(store.search_nodes("project", &Page::default(), None, &with_archived).results.len(), 3);
+        let ctx = store.get_context("root", Some(2), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.descendants.len(), 1);
+        assert_eq!(store.graph.nodes.len(), 4);
 
-        // B->A with same relation should be rejected when creating as bidirectional
-        let result = store.add_link(&b.id.0, &a.id.0, "related_to", true, None);
-        assert!(result.is_err());
+        store.unarchive_node(&old.id.0).unwrap();
+        assert_eq!(store.search_nodes("project", &Page::default(), None, &SearchOptions::default()).results.len(), 3);
+        assert!(matches!(store.archive_node("root"), Err(WillowError::CannotArchiveRoot)));
     }
 
     #[test]
-    fn test_update_link() {
+    fn test_sensitive_nodes_hidden_by_default() {
         let mut store = temp_store();
-        let a = store.create_node("root", "category", "A", None, None).unwrap();
-        let b = store.create_node("root", "category", "B", None, None).unwrap();
+        let health = store.create_node("root", "category", "Health notes", None, None).unwrap();
+        store.create_node(&health.id.0, "detail", "Takes notes on allergies", None, None).unwrap();
+        store.create_node("root", "detail", "Takes notes in meetings", None, None).unwrap();
+        store.set_sensitivity(&health.id.0, "sensitive").unwrap();
+
+        let hits = store.search_nodes("notes", &Page::default(), None, &SearchOptions::default()).results;
+        assert_eq!(hits.len(), 1);
+        let all = SearchOptions {
+            max_sensitivity: Sensitivity::Secret,
+            ..SearchOptions::default()
+        };
+        assert_eq!(store.search_nodes("notes", &Page::default(), None, &all).results.len(), 3);
 
-        let link = store.add_link(&a.id.0, &b.id.0, "related_to", false, None).unwrap();
+        let ctx = store.get_context("root", Some(2), &ContextOptions::default()).unwrap();
+        assert_eq!(ctx.descendants.len(), 1);
+        let sub = store.extract_subgraph("root", Sensitivity::Normal).unwrap();
+        assert_eq!(sub.nodes.len(), 2);
 
-        let updated = store.update_link(&link.id.0, Some("caused_by"), Some(true), Some("high")).unwrap();
-        assert_eq!(updated.relation, "caused_by");
-        assert!(updated.bidirectional);
-        assert_eq!(updated.confidence, Some(ConfidenceLevel::High));
+        assert!(matches!(
+            store.set_sensitivity(&health.id.0, "private"),
+            Err(WillowError::InvalidSensitivity(_))
+        ));
+    }
 
-        // Partial update
-        let updated2 = store.update_link(&link.id.0, None, None, Some("low")).unwrap();
-        assert_eq!(updated2.relation, "caused_by"); // unchanged
-        assert!(updated2.bidirectional); // unchanged
-        assert_eq!(updated2.confidence, Some(ConfidenceLevel::Low));
+    #[test]
+    fn test_find_duplicates() {
+        let mut store = temp_store();
+        let cat = store.create_node("root", "category", "Hobbies", None, None).unwrap();
+        let a = store.create_node(&cat.id.0, "detail", "Loves hiking", None, None).unwrap();
+        let b = store.create_node(&cat.id.0, "detail", "Enjoys hiking", None, None).unwrap();
+        store.create_node(&cat.id.0, "detail", "Plays chess", None, None).unwrap();
+        // Same text under another parent is not a candidate.
+        store.create_node("root", "detail", "Loves hiking", None, None).unwrap();
+
+        let pairs = store.find_duplicates(0.3);
+        assert_eq!(pairs.len(), 1);
+        let ids = HashSet::from([pairs[0].a.clone(), pairs[0].b.clone()]);
+        assert_eq!(ids, HashSet::from([a.id, b.id]));
     }
 
     #[test]
-    fn test_search_nodes() {
+    fn test_update_metadata_bulk() {
         let mut store = temp_store();
-        store
-            .create_node("root", "detail", "Favorite food is pizza", None, None)
-            .unwrap();
-        store
-            .create_node("root", "detail", "Works at Google", None, None)
-            .unwrap();
+        let conv = HashMap::from([("conversation".to_string(), "c1".to_string())]);
+        let a = store.create_node("root", "detail", "A", Some(conv.clone()), None).unwrap();
+        let b = store.create_node("root", "detail", "B", Some(conv), None).unwrap();
+        let other = store.create_node("root", "detail", "C", None, None).unwrap();
+        let undo_depth = store.session.undo_stack.len();
+
+        let filter = NodeFilter {
+            metadata: HashMap::from([("conversation".to_string(), "c1".to_string())]),
+            ..NodeFilter::default()
+        };
+        let updated = store.update_metadata_bulk(&filter, "tags", Some("imported")).unwrap();
+        assert_eq!(updated.len(), 2);
+        assert_eq!(store.graph.nodes[&a.id].metadata["tags"], "imported");
+        assert_eq!(store.graph.nodes[&b.id].metadata["tags"], "imported");
+        assert!(!store.graph.nodes[&other.id].metadata.contains_key("tags"));
+        assert_eq!(store.session.undo_stack.len(), undo_depth + 1);
+
+        // Re-applying the same value is a no-op.
+        assert!(store.update_metadata_bulk(&filter, "tags", Some("imported")).unwrap().is_empty());
+
+        store.update_metadata_bulk(&filter, "conversation", None).unwrap();
+        assert!(!store.graph.nodes[&a.id].metadata.contains_key("conversation"));
+        assert!(store.undo().unwrap());
+        assert_eq!(store.graph.nodes[&a.id].metadata["conversation"], "c1");
+    }
 
-        let results = store.search_nodes("pizza", None, None);
-        assert_eq!(results.len(), 1);
-        assert!(results[0].content.contains("pizza"));
+    #[test]
+    fn test_get_context_filters() {
+        let mut store = temp_store();
+        let person = store.create_node("root", "entity", "Alice", None, None).unwrap();
+        store.create_node(&person.id.0, "attribute", "Likes tea", None, None).unwrap();
+        let event = store.create_node(&person.id.0, "event", "Moved house", None, None).unwrap();
+        let mut expired = HashMap::new();
+        expired.insert("expired".to_string(), "true".to_string());
+        store.create_node(&person.id.0, "attribute", "Old job", Some(expired), None).unwrap();
+        store.update_node(&event.id.0, Some("Moved to Leeds"), None, None, None).unwrap();
+
+        let options = ContextOptions {
+            node_types: Some(vec![NodeType::Attribute, NodeType::Event]),
+            omit_previous_values: true,
+            ..ContextOptions::default()
+        };
+        let ctx = store.get_context(&person.id.0, Some(1), &options).unwrap();
+        let contents: Vec<_> = ctx.descendants.iter().map(|n| n.content.as_str()).collect();
+        assert_eq!(contents, vec!["Likes tea", "Moved to Leeds"]);
+        assert!(ctx.descendants.iter().all(|n| n.previous_values.is_empty()));
+        assert!(!ctx.truncated);
+        // Stripping history copies only the node that had any.
+        assert!(Arc::ptr_eq(&ctx.node, &store.graph.nodes[&person.id]));
+        assert!(!Arc::ptr_eq(&ctx.descendants[1], &store.graph.nodes[&event.id]));
+        assert!(!store.graph.nodes[&event.id].previous_values.is_empty());
+
+        let capped = ContextOptions {
+            max_descendants: Some(1),
+            ..ContextOptions::default()
+        };
+        let ctx = store.get_context(&person.id.0, Some(1), &capped).unwrap();
+        assert_eq!(ctx.descendants.len(), 1);
+        assert!(ctx.truncated);
     }
 
     #[test]
@@ -754,15 +6116,37 @@ mod tests {
             .unwrap();
 
         // Depth 1 from category should only get immediate children
-        let ctx = store.get_context(&cat.id.0, Some(1)).unwrap();
+        let ctx = store.get_context(&cat.id.0, Some(1), &ContextOptions::default()).unwrap();
         assert_eq!(ctx.descendants.len(), 1);
         assert_eq!(ctx.descendants[0].content, "Reading");
 
         // Depth 2 should get both levels
-        let ctx = store.get_context(&cat.id.0, Some(2)).unwrap();
+        let ctx = store.get_context(&cat.id.0, Some(2), &ContextOptions::default()).unwrap();
         assert_eq!(ctx.descendants.len(), 2);
     }
 
+    #[test]
+    fn test_get_context_shares_nodes_until_they_change() {
+        let mut store = temp_store();
+        let person = store.create_node("root", "entity", "Alice", None, None).unwrap();
+        let job = store.create_node(&person.id.0, "attribute", "Works at Acme", None, None).unwrap();
+
+        let ctx = store.get_context(&person.id.0, Some(1), &ContextOptions::default()).unwrap();
+        assert!(Arc::ptr_eq(&ctx.descendants[0], &store.graph.nodes[&job.id]));
+        assert!(Arc::ptr_eq(&ctx.ancestors[0], &store.graph.nodes[&store.graph.root_id]));
+
+        // A held context is a snapshot: later edits copy the node instead.
+        store.update_node(&job.id.0, Some("Works at Globex"), None, None, None).unwrap();
+        assert_eq!(ctx.descendants[0].content, "Works at Acme");
+        assert_eq!(store.graph.nodes[&job.id].content, "Works at Globex");
+        assert!(!Arc::ptr_eq(&ctx.descendants[0], &store.graph.nodes[&job.id]));
+
+        assert!(matches!(
+            store.get_context("missing", None, &ContextOptions::default()),
+            Err(WillowError::NodeNotFound(_))
+        ));
+    }
+
     #[test]
     fn test_create_new_node_types() {
         let mut store = temp_store();
@@ -797,10 +6181,135 @@ mod tests {
         assert_eq!(detail.node_type, NodeType::Detail);
 
         // Verify hierarchy: root -> cat -> entity -> attr -> detail
-        let ctx = store.get_context(&detail.id.0, Some(0)).unwrap();
+        let ctx = store.get_context(&detail.id.0, Some(0), &ContextOptions::default()).unwrap();
         assert_eq!(ctx.ancestors.len(), 4); // attr, entity, cat, root
     }
 
+    #[test]
+    fn test_perf_report_times_operations_and_sizes_graph() {
+        let mut store = temp_store();
+        let node = store.create_node("root", "detail", "Twelve bytes", None, None).unwrap();
+        store.update_node(&node.id.0, Some("Now it has twenty"), None, None, None).unwrap();
+        store.search_nodes("twenty", &Page::default(), None, &SearchOptions::default());
+
+        let report = store.perf_report();
+        assert_eq!(report.operations["create_node"].count, 1);
+        assert_eq!(report.operations["update_node"].count, 1);
+        assert_eq!(report.operations["search_nodes"].count, 1);
+        assert_eq!(report.operations["save"].count, 2);
+        assert_eq!(report.operations["open"].histogram.iter().sum::<u64>(), 1);
+        assert_eq!(report.memory.nodes, 2);
+        assert_eq!(report.memory.previous_values_bytes, "Twelve bytes".len());
+        assert!(report.memory.estimated_total_bytes > report.memory.content_bytes);
+    }
+
+    #[test]
+    fn test_audit_log_records_calls_without_vcs_and_rolled_back() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions { audit: true, ..OpenOptions::default() };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        store.set_actor(Some("assistant".to_string()));
+        let node = store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        store.begin_transaction().unwrap();
+        store.update_node(&node.id.0, Some("Likes coffee"), None, None, None).unwrap();
+        store.rollback_transaction().unwrap();
+        store.vcs_init().unwrap();
+        store.set_limits(Limits::default()).unwrap();
+        drop(store);
+
+        let store = GraphStore::open(&path, &options).unwrap();
+        let entries = store.audit_log(&AuditQuery::default()).unwrap();
+        let operations: Vec<&str> = entries.iter().map(|e| e.operation.as_str()).collect();
+        assert_eq!(operations, ["create_node", "update_node", "rollback_transaction", "vcs_init", "set_limits"]);
+        assert!(entries.iter().all(|e| e.actor.as_deref() == Some("assistant")));
+        let touched = AuditQuery { node_id: Some(node.id.clone()), ..AuditQuery::default() };
+        assert_eq!(store.audit_log(&touched).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_audit_log_records_writes_whose_save_fails() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions { audit: true, ..OpenOptions::default() };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        let mut other = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        other.create_node("root", "detail", "Theirs", None, None).unwrap();
+        assert!(matches!(
+            store.create_node("root", "detail", "Mine", None, None),
+            Err(WillowError::ExternalChange(_))
+        ));
+        assert!(matches!(store.undo(), Ok(false)));
+
+        let entries = store.audit_log(&AuditQuery::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, "create_node");
+        let created = entries[0].node_ids.iter().find(|id| !store.graph.is_root(id)).unwrap();
+        assert!(!store.graph.nodes.contains_key(created));
+    }
+
+    #[test]
+    fn test_failed_audit_and_journal_appends_keep_history() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let options = OpenOptions { audit: true, journal: Some(100), ..OpenOptions::default() };
+        let mut store = GraphStore::open(&path, &options).unwrap();
+        store.vcs_init().unwrap();
+        // Directories in their place make both appends fail.
+        std::fs::remove_file(audit::audit_path(&path)).unwrap();
+        std::fs::create_dir(audit::audit_path(&path)).unwrap();
+        std::fs::create_dir(journal::journal_path(&path)).unwrap();
+
+        let node = store.create_node("root", "detail", "Likes tea", None, None).unwrap();
+        assert_eq!(store.pending_changes().len(), 1);
+        assert!(store.undo().unwrap());
+        assert!(!store.graph.nodes.contains_key(&node.id));
+        assert!(store.redo().unwrap());
+        assert_eq!(store.pending_changes().len(), 3);
+        drop(store);
+
+        std::fs::remove_dir(journal::journal_path(&path)).unwrap();
+        let store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        assert_eq!(store.graph.nodes[&node.id].content, "Likes tea");
+    }
+
+    #[test]
+    fn test_prune_audit_log() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions { audit: true, ..OpenOptions::default() }).unwrap();
+        store.create_node("root", "detail", "First", None, None).unwrap();
+        store.create_node("root", "detail", "Second", None, None).unwrap();
+        let latest = AuditQuery { limit: Some(1), ..AuditQuery::default() };
+        assert_eq!(store.audit_log(&latest).unwrap()[0].node_ids.len(), 1);
+
+        assert_eq!(store.prune_audit_log(Utc::now() + chrono::Duration::seconds(1)).unwrap(), 2);
+        let entries = store.audit_log(&AuditQuery::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].operation, "prune_audit_log");
+    }
+
+    #[test]
+    fn test_loaded_graph_shares_each_id() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("graph.json");
+        let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let parent = store.create_node("root", "category", "Hobbies", None, None).unwrap();
+        let child = store.create_node(&parent.id.0, "detail", "Chess", None, None).unwrap();
+        let link = store.add_link(&child.id.0, &parent.id.0, "related_to", false, None).unwrap();
+        drop(store);
+
+        let store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
+        let graph = &store.graph;
+        let (key, node) = graph.nodes.get_key_value(&child.id).unwrap();
+        let parent = &graph.nodes[&parent.id];
+        assert!(Arc::ptr_eq(&node.id.0, &parent.children[0].0));
+        assert!(Arc::ptr_eq(&node.id.0, &key.0));
+        assert!(Arc::ptr_eq(&node.parent_id.as_ref().unwrap().0, &parent.id.0));
+        assert!(Arc::ptr_eq(&graph.links[&link.id].from_node.0, &node.id.0));
+        assert!(Arc::ptr_eq(&graph.root_id.0, &parent.parent_id.as_ref().unwrap().0));
+    }
+
     #[test]
     fn test_persistence() {
         let tmp = NamedTempFile::new().unwrap();
@@ -810,7 +6319,7 @@ mod tests {
 
         // Create and populate
         {
-            let mut store = GraphStore::open(&path).unwrap();
+            let mut store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
             store
                 .create_node("root", "category", "Persistent", None, None)
                 .unwrap();
@@ -818,10 +6327,16 @@ mod tests {
 
         // Reopen and verify
         {
-            let store = GraphStore::open(&path).unwrap();
+            let store = GraphStore::open(&path, &OpenOptions::default()).unwrap();
             assert_eq!(store.graph.nodes.len(), 2);
             let found = store.graph.nodes.values().any(|n| n.content == "Persistent");
             assert!(found);
+
+            // Ids are interned: the root's child list shares the child's key.
+            let root = &store.graph.nodes[&store.graph.root_id];
+            let (key, child) = store.graph.nodes.get_key_value(&root.children[0]).unwrap();
+            assert!(Arc::ptr_eq(&key.0, &root.children[0].0));
+            assert!(Arc::ptr_eq(&key.0, &child.id.0));
         }
 
         let _ = std::fs::remove_file(&path);
@@ -831,7 +6346,7 @@ mod tests {
     fn test_vcs_init_and_commit() {
         let tmp = tempfile::TempDir::new().unwrap();
         let graph_path = tmp.path().join("graph.json");
-        let mut store = GraphStore::open(&graph_path).unwrap();
+        let mut store = GraphStore::open(&graph_path, &OpenOptions::default()).unwrap();
 
         // No VCS initially
         assert!(store.repo.is_none());
@@ -868,7 +6383,7 @@ mod tests {
     fn test_vcs_discard_changes() {
         let tmp = tempfile::TempDir::new().unwrap();
         let graph_path = tmp.path().join("graph.json");
-        let mut store = GraphStore::open(&graph_path).unwrap();
+        let mut store = GraphStore::open(&graph_path, &OpenOptions::default()).unwrap();
         store.vcs_init().unwrap();
 
         let initial_count = store.graph.nodes.len();
@@ -884,4 +6399,103 @@ mod tests {
         assert_eq!(store.graph.nodes.len(), initial_count);
         assert!(!store.has_pending_changes());
     }
+
+    #[test]
+    fn test_search_pages_count_every_match() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut store = GraphStore::open(&tmp.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        for i in 0..5 {
+            store.create_node("root", "detail", &format!("Guitar lesson {i}"), None, None).unwrap();
+        }
+        store.create_node("root", "detail", "Piano lesson", None, None).unwrap();
+        let options = SearchOptions::default();
+        let search = |offset, limit| store.search_nodes("guitar", &Page { offset, limit }, None, &options);
+        let ids = |page: &SearchPage| page.results.iter().map(|r| r.node_id.clone()).collect::<Vec<_>>();
+
+        let all = search(0, None);
+        assert_eq!((all.total, all.results.len()), (5, 5));
+        let paged: Vec<NodeId> = [0, 2, 4].iter().flat_map(|&offset| ids(&search(offset, Some(2)))).collect();
+        assert_eq!(paged, ids(&all));
+        for (offset, limit, len) in [(0, Some(2), 2), (4, Some(2), 1), (5, Some(2), 0), (9, None, 0), (0, Some(0), 0)] {
+            let page = search(offset, limit);
+            assert_eq!((page.total, page.results.len()), (5, len), "offset {offset}, limit {limit:?}");
+        }
+
+        let as_of = store.search_nodes_as_of("guitar", Utc::now(), &Page { offset: 3, limit: Some(10) }, None, &options);
+        assert_eq!((as_of.total, ids(&as_of)), (5, ids(&all)[3..].to_vec()));
+    }
+
+    #[test]
+    fn test_get_node_and_link_by_id() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut store = GraphStore::open(&tmp.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        let a = store.create_node("root", "entity", "Alice", None, None).unwrap();
+        let b = store.create_node("root", "entity", "Bob", None, None).unwrap();
+        let link = store.add_link(&a.id.0, &b.id.0, "knows", false, None).unwrap();
+
+        assert_eq!(store.get_node(&a.id.0).unwrap().content, "Alice");
+        assert_eq!(store.get_link(&link.id.0).unwrap().to_node, b.id);
+        assert!(matches!(store.get_node("missing"), Err(WillowError::NodeNotFound(id)) if id == "missing"));
+        assert!(matches!(store.get_link(&a.id.0), Err(WillowError::LinkNotFound(_))));
+
+        store.delete_link(&link.id.0).unwrap();
+        assert!(matches!(store.get_link(&link.id.0), Err(WillowError::LinkNotFound(_))));
+        store.delete_node(&b.id.0).unwrap();
+        assert!(matches!(store.get_node(&b.id.0), Err(WillowError::NodeNotFound(_))));
+    }
+
+    #[test]
+    fn test_cancelled_import_and_gc_change_nothing() {
+        use crate::progress::{CancelToken, ProgressUpdate};
+        let (_dir, mut store) = temp_vcs_store();
+        let work = store.create_node("root", "category", "Work", None, None).unwrap();
+        let pending = store.pending_changes().len();
+
+        let cancel = CancelToken::default();
+        let mut steps = Vec::new();
+        let mut progress = Progress::new(
+            Some(Box::new(|update: &ProgressUpdate| {
+                steps.push((update.phase.to_string(), update.done, update.total));
+                if update.done == 1 {
+                    cancel.cancel();
+                }
+            })),
+            Some(cancel.clone()),
+        );
+        let text = "- One\n- Two\n- Three\n";
+        assert!(matches!(store.import_markdown(&work.id.0, text, &mut progress), Err(WillowError::Cancelled)));
+        drop(progress);
+        assert_eq!(steps, [("import".to_string(), 0, Some(3)), ("import".to_string(), 1, Some(3))]);
+        assert_eq!(store.graph.nodes.len(), 2);
+        assert_eq!(store.pending_changes().len(), pending);
+        assert_eq!(storage::load_graph(&store.path).unwrap().nodes.len(), 2);
+
+        store.commit(CommitInput { message: "Work".to_string(), source: CommitSource::Manual { tool_name: None } }).unwrap();
+        let mut progress = Progress::new(None, Some(cancel));
+        assert!(matches!(store.gc_attachments(&mut progress), Err(WillowError::Cancelled)));
+    }
+
+    #[test]
+    fn test_search_paths_follow_primary_parents() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut store = GraphStore::open(&tmp.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        let career = store.create_node("root", "category", "Career", None, None).unwrap();
+        let google = store.create_node(&career.id.0, "entity", "Google", None, None).unwrap();
+        let started = store.create_node(&google.id.0, "detail", "Started 2023", None, None).unwrap();
+        let places = store.create_node("root", "category", "Places", None, None).unwrap();
+        store.add_parent(&started.id.0, &places.id.0).unwrap();
+
+        let options = SearchOptions::default();
+        let path = |root: Option<&str>| {
+            let page = store.search_nodes("started", &Page::default(), root, &options);
+            page.results.into_iter().map(|r| r.path).collect::<Vec<_>>()
+        };
+        let expected = vec![vec!["User", "Career", "Google", "Started 2023"]];
+        assert_eq!(path(None), expected);
+        // Scoped and reached through the extra parent, the path is still the
+        // primary one from the root.
+        assert_eq!(path(Some(&places.id.0)), expected);
+        assert_eq!(path(Some(&google.id.0)), expected);
+        assert!(store.search_nodes("started", &Page::default(), Some("missing"), &options).results.is_empty());
+    }
 }
@@ -0,0 +1,175 @@
+use crate::model::{Graph, Node, NodeId, NodeType};
+use crate::query::node_tags;
+use crate::search_index::SearchIndex;
+use crate::temporal;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    Content,
+    Tag,
+}
+
+impl SuggestionKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SuggestionKind::Content => "content",
+            SuggestionKind::Tag => "tag",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub text: String,
+    pub kind: SuggestionKind,
+    /// The node whose content this is; `None` for tags.
+    pub node_id: Option<NodeId>,
+}
+
+/// Lowercased content words and tags of every node, sorted so a prefix
+/// lookup is a range scan. Kept current through `SearchIndex`.
+#[derive(Debug, Default)]
+pub struct PrefixIndex {
+    words: BTreeMap<String, HashSet<NodeId>>,
+    tags: BTreeMap<String, HashSet<NodeId>>,
+    /// The words and tags indexed for each node, so it can be unindexed.
+    keys: HashMap<NodeId, (Vec<String>, Vec<String>)>,
+}
+
+fn prefixed<'a>(
+    map: &'a BTreeMap<String, HashSet<NodeId>>,
+    prefix: &'a str,
+) -> impl Iterator<Item = (&'a String, &'a HashSet<NodeId>)> {
+    map.range(prefix.to_string()..).take_while(move |(key, _)| key.starts_with(prefix))
+}
+
+fn unindex(map: &mut BTreeMap<String, HashSet<NodeId>>, keys: &[String], node_id: &NodeId) {
+    for key in keys {
+        if let Some(ids) = map.get_mut(key) {
+            ids.remove(node_id);
+            if ids.is_empty() {
+                map.remove(key);
+            }
+        }
+    }
+}
+
+/// Nodes a type-ahead box may show: normal sensitivity and not archived.
+fn suggestible(graph: &Graph, node: &Node) -> bool {
+    node.node_type != NodeType::Root && node.sensitivity.is_normal() && !temporal::is_archived(graph, node)
+}
+
+impl PrefixIndex {
+    pub fn build(graph: &Graph) -> Self {
+        let mut index = PrefixIndex::default();
+        for node in graph.nodes.values() {
+            index.insert(node);
+        }
+        index
+    }
+
+    fn insert(&mut self, node: &Node) {
+        self.remove(&node.id);
+        if node.node_type == NodeType::Root {
+            return;
+        }
+        let text = node.content_format.searchable_text(&node.content).to_lowercase();
+        let mut words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_string)
+            .collect();
+        words.sort();
+        words.dedup();
+        let mut tags: Vec<String> = node_tags(node).map(str::to_lowercase).collect();
+        tags.sort();
+        tags.dedup();
+        for word in &words {
+            self.words.entry(word.clone()).or_default().insert(node.id.clone());
+        }
+        for tag in &tags {
+            self.tags.entry(tag.clone()).or_default().insert(node.id.clone());
+        }
+        self.keys.insert(node.id.clone(), (words, tags));
+    }
+
+    fn remove(&mut self, node_id: &NodeId) {
+        if let Some((words, tags)) = self.keys.remove(node_id) {
+            unindex(&mut self.words, &words, node_id);
+            unindex(&mut self.tags, &tags, node_id);
+        }
+    }
+
+    /// Up to `limit` suggestions for a typed prefix, case-insensitively:
+    /// contents starting with it, then tags starting with it, then contents
+    /// with a word starting with it, then contents merely containing it.
+    /// Shorter contents come first within each group.
+    pub fn suggest(&self, graph: &Graph, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = prefix.trim().to_lowercase();
+        if prefix.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+        let visible = |id: &NodeId| graph.nodes.get(id).map(Arc::as_ref).filter(|n| suggestible(graph, n));
+
+        let mut word_hits: Vec<&Node> = prefixed(&self.words, &prefix)
+            .flat_map(|(_, ids)| ids)
+            .filter_map(visible)
+            .collect();
+        word_hits.sort_by(|a, b| (a.content.len(), &a.content, &a.id).cmp(&(b.content.len(), &b.content, &b.id)));
+        word_hits.dedup_by(|a, b| a.id == b.id);
+        let (starts, words): (Vec<&Node>, Vec<&Node>) =
+            word_hits.into_iter().partition(|n| n.content.to_lowercase().starts_with(&prefix));
+
+        let content = |n: &Node| Suggestion {
+            text: n.content.clone(),
+            kind: SuggestionKind::Content,
+            node_id: Some(n.id.clone()),
+        };
+        let mut out: Vec<Suggestion> = starts.iter().map(|n| content(n)).collect();
+        out.extend(
+            prefixed(&self.tags, &prefix)
+                .filter(|(_, ids)| ids.iter().any(|id| visible(id).is_some()))
+                .map(|(tag, _)| Suggestion {
+                    text: tag.clone(),
+                    kind: SuggestionKind::Tag,
+                    node_id: None,
+                }),
+        );
+        out.extend(words.iter().map(|n| content(n)));
+
+        if out.len() < limit {
+            let seen: HashSet<&NodeId> = starts.iter().chain(&words).map(|n| &n.id).collect();
+            let mut contains: Vec<&Node> = graph
+                .nodes
+                .values()
+                .map(Arc::as_ref)
+                .filter(|n| !seen.contains(&n.id) && suggestible(graph, n))
+                .filter(|n| n.content.to_lowercase().contains(&prefix))
+                .collect();
+            contains.sort_by(|a, b| (a.content.len(), &a.content, &a.id).cmp(&(b.content.len(), &b.content, &b.id)));
+            out.extend(contains.into_iter().map(content));
+        }
+        out.truncate(limit);
+        out
+    }
+}
+
+impl SearchIndex for PrefixIndex {
+    fn on_node_created(&mut self, node: &Node) {
+        self.insert(node);
+    }
+
+    fn on_node_updated(&mut self, node: &Node) {
+        self.insert(node);
+    }
+
+    fn on_node_deleted(&mut self, node_id: &NodeId) {
+        self.remove(node_id);
+    }
+
+    fn on_reset(&mut self, graph: &Graph) {
+        *self = PrefixIndex::build(graph);
+    }
+}
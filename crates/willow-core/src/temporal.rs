@@ -0,0 +1,128 @@
+use crate::model::{Graph, Node, NodeId, NodeType};
+use crate::vcs::types::CommitHash;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+/// The graph as it stood at `date`: nodes not valid at that date are removed
+/// together with any descendants left without a remaining parent. Links are
+/// kept only between remaining nodes.
+pub fn view_as_of(graph: &Graph, date: DateTime<Utc>) -> Graph {
+    let mut kept: HashSet<&NodeId> = HashSet::new();
+    let mut queue: VecDeque<&NodeId> = VecDeque::new();
+    if graph.nodes.contains_key(&graph.root_id) {
+        kept.insert(&graph.root_id);
+        queue.push_back(&graph.root_id);
+    }
+    while let Some(id) = queue.pop_front() {
+        for cid in &graph.nodes[id].children {
+            let Some(child) = graph.nodes.get(cid) else { continue };
+            if child.is_valid_at(date) && kept.insert(cid) {
+                queue.push_back(cid);
+            }
+        }
+    }
+
+    let nodes: HashMap<NodeId, _> = kept
+        .iter()
+        .map(|id| {
+            let mut node = Node::clone(&graph.nodes[*id]);
+            node.children.retain(|c| kept.contains(c));
+            let mut parents: Vec<NodeId> = node.parents().filter(|p| kept.contains(p)).cloned().collect();
+            if !parents.is_empty() {
+                node.parent_id = Some(parents.remove(0));
+            }
+            node.extra_parents = parents;
+            ((*id).clone(), Arc::new(node))
+        })
+        .collect();
+
+    let links = graph
+        .links
+        .iter()
+        .filter(|(_, l)| kept.contains(&l.from_node) && kept.contains(&l.to_node))
+        .map(|(id, l)| (id.clone(), l.clone()))
+        .collect();
+
+    Graph {
+        root_id: graph.root_id.clone(),
+        nodes,
+        links,
+        roots: graph.roots.clone(),
+        rules: graph.rules.clone(),
+    }
+}
+
+/// Metadata key set on nodes handled by an expiry sweep, so later sweeps skip them.
+pub const EXPIRED_KEY: &str = "expired";
+
+/// Content of the root-level category the `Archive` expiry policy moves nodes into.
+pub const ARCHIVE_CONTENT: &str = "Archive";
+
+/// Archived by flag, expired by a sweep, or the root's Archive category itself.
+pub fn is_archived(graph: &Graph, node: &Node) -> bool {
+    node.archived
+        || node.metadata.get(EXPIRED_KEY).is_some_and(|v| v == "true")
+        || (node.node_type == NodeType::Category
+            && node.content == ARCHIVE_CONTENT
+            && node.parent_id.as_ref() == Some(&graph.root_id))
+}
+
+/// What an expiry sweep does with nodes whose `valid_until` has passed.
+/// Every policy also sets the `expired` metadata flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryPolicy {
+    /// Only set the `expired` flag.
+    Flag,
+    /// Move the content into `previous_values`, leaving a placeholder.
+    Supersede,
+    /// Move the node under the root's "Archive" category.
+    Archive,
+}
+
+impl ExpiryPolicy {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<ExpiryPolicy> {
+        match s {
+            "flag" => Some(ExpiryPolicy::Flag),
+            "supersede" => Some(ExpiryPolicy::Supersede),
+            "archive" => Some(ExpiryPolicy::Archive),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpiryPolicy::Flag => "flag",
+            ExpiryPolicy::Supersede => "supersede",
+            ExpiryPolicy::Archive => "archive",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SweepReport {
+    pub expired: Vec<NodeId>,
+    pub commit: Option<CommitHash>,
+}
+
+/// Non-root nodes whose validity ended before `now` and that no sweep has handled yet.
+pub fn expired_nodes(graph: &Graph, now: DateTime<Utc>) -> Vec<NodeId> {
+    let mut ids: Vec<NodeId> = graph
+        .nodes
+        .values()
+        .filter(|n| n.id != graph.root_id)
+        .filter(|n| n.metadata.get(EXPIRED_KEY).is_none_or(|v| v != "true"))
+        .filter(|n| {
+            n.temporal
+                .as_ref()
+                .and_then(|t| t.valid_until)
+                .is_some_and(|until| until < now)
+        })
+        .map(|n| n.id.clone())
+        .collect();
+    ids.sort_by(|a, b| a.0.cmp(&b.0));
+    ids
+}
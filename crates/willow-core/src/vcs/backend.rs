@@ -0,0 +1,79 @@
+use crate::error::WillowError;
+use crate::files;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Where `ObjectStore` keeps commits, snapshots, snapshot patches, deltas
+/// and dictionaries: opaque bytes named by a kind (e.g. "commits") and a
+/// name within it (a commit hash or dictionary id). Objects are only ever
+/// written whole, so backends need no partial reads or appends.
+pub trait ObjectBackend: Send + Sync {
+    fn put(&self, kind: &str, name: &str, data: &[u8]) -> Result<(), WillowError>;
+    /// `None` when there is no such object.
+    fn get(&self, kind: &str, name: &str) -> Result<Option<Vec<u8>>, WillowError>;
+    fn exists(&self, kind: &str, name: &str) -> Result<bool, WillowError>;
+    /// Deleting an object that is not there is not an error.
+    fn delete(&self, kind: &str, name: &str) -> Result<(), WillowError>;
+    /// The names of every object of `kind`, in no particular order.
+    fn list(&self, kind: &str) -> Result<Vec<String>, WillowError>;
+}
+
+/// Objects as files under `repo/objects/<kind>/<name>`.
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: &Path) -> Self {
+        LocalBackend { root: root.to_path_buf() }
+    }
+
+    fn path(&self, kind: &str, name: &str) -> PathBuf {
+        self.root.join(kind).join(name)
+    }
+}
+
+impl ObjectBackend for LocalBackend {
+    /// Written to a temporary file first, so an object is never left half
+    /// replaced.
+    fn put(&self, kind: &str, name: &str, data: &[u8]) -> Result<(), WillowError> {
+        let path = self.path(kind, name);
+        files::create_dir_all(&self.root.join(kind))?;
+        let tmp_path = path.with_extension("tmp");
+        files::write(&tmp_path, data)?;
+        files::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, kind: &str, name: &str) -> Result<Option<Vec<u8>>, WillowError> {
+        match files::read(&self.path(kind, name)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn exists(&self, kind: &str, name: &str) -> Result<bool, WillowError> {
+        Ok(files::exists(&self.path(kind, name)))
+    }
+
+    fn delete(&self, kind: &str, name: &str) -> Result<(), WillowError> {
+        match files::remove_file(&self.path(kind, name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Skips leftover temporary files.
+    fn list(&self, kind: &str) -> Result<Vec<String>, WillowError> {
+        let dir = self.root.join(kind);
+        if !files::exists(&dir) {
+            return Ok(Vec::new());
+        }
+        Ok(files::list(&dir)?
+            .into_iter()
+            .filter_map(|path| path.file_name()?.to_str().map(str::to_string))
+            .filter(|name| !name.contains('.'))
+            .collect())
+    }
+}
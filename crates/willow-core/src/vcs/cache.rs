@@ -0,0 +1,113 @@
+use crate::model::Graph;
+use crate::vcs::types::CommitHash;
+use std::collections::{HashMap, VecDeque};
+
+/// Default number of reconstructed graphs kept per repository.
+pub const DEFAULT_CACHE_CAPACITY: usize = 16;
+
+/// Bounded LRU cache of reconstructed graphs keyed by commit hash.
+///
+/// Commits are content-addressed and immutable, so entries never go stale;
+/// the cache only needs clearing when objects are removed from the store.
+pub struct GraphCache {
+    capacity: usize,
+    entries: HashMap<CommitHash, Graph>,
+    order: VecDeque<CommitHash>,
+}
+
+impl GraphCache {
+    pub fn new(capacity: usize) -> Self {
+        GraphCache {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, hash: &CommitHash) {
+        if let Some(pos) = self.order.iter().position(|h| h == hash) {
+            let h = self.order.remove(pos).unwrap();
+            self.order.push_back(h);
+        }
+    }
+
+    pub fn get(&mut self, hash: &CommitHash) -> Option<Graph> {
+        let graph = self.entries.get(hash)?.clone();
+        self.touch(hash);
+        Some(graph)
+    }
+
+    pub fn contains(&self, hash: &CommitHash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn insert(&mut self, hash: CommitHash, graph: Graph) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(hash.clone(), graph).is_some() {
+            self.touch(&hash);
+            return;
+        }
+        self.order.push_back(hash);
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, hash: &CommitHash) {
+        if self.entries.remove(hash).is_some() {
+            self.order.retain(|h| h != hash);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::NodeId;
+
+    fn ch(s: &str) -> CommitHash {
+        CommitHash(s.to_string())
+    }
+
+    fn graph() -> Graph {
+        Graph::empty(NodeId("root".into()))
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = GraphCache::new(2);
+        cache.insert(ch("a"), graph());
+        cache.insert(ch("b"), graph());
+        assert!(cache.get(&ch("a")).is_some()); // a is now most recent
+        cache.insert(ch("c"), graph());
+
+        assert!(cache.contains(&ch("a")));
+        assert!(!cache.contains(&ch("b")));
+        assert!(cache.contains(&ch("c")));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_cache() {
+        let mut cache = GraphCache::new(0);
+        cache.insert(ch("a"), graph());
+        assert!(cache.is_empty());
+    }
+}
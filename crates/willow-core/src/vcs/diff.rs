@@ -1,28 +1,57 @@
-use crate::model::{Graph, NodeId};
+use crate::content::{diff_content, ContentFieldChange};
+use crate::journal;
+use crate::model::{Graph, NodeId, Sensitivity};
+use crate::parallel;
+use crate::search_index;
+use crate::vcs::types::{apply_delta, invert_delta, Change, CommitStats, Delta};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
 use tracing::debug;
 
-#[derive(Debug, Clone)]
+/// Node contents longer than this are cut short in commit messages.
+const MESSAGE_CONTENT_CHARS: usize = 40;
+/// Nodes named individually per clause of a commit message.
+const MESSAGE_NAMED_NODES: usize = 3;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct NodeChangeSummary {
     pub node_id: String,
     pub node_type: String,
     pub content: String,
     pub old_content: Option<String>,
     pub path: Vec<String>,
+    /// Cell-level changes for structured content; empty for plain text.
+    pub content_changes: Vec<ContentFieldChange>,
+    /// The highest sensitivity along `path`, before or after the change.
+    #[serde(skip_serializing_if = "Sensitivity::is_normal")]
+    pub sensitivity: Sensitivity,
 }
 
 impl NodeChangeSummary {
-    fn new(node: &crate::model::Node, old_content: Option<String>, path: Vec<String>) -> Self {
+    fn new(
+        node: &crate::model::Node,
+        old_content: Option<String>,
+        path: Vec<String>,
+        sensitivity: Sensitivity,
+    ) -> Self {
+        let content_changes = old_content
+            .as_deref()
+            .map(|old| diff_content(node.content_format, old, &node.content))
+            .unwrap_or_default();
         Self {
-            node_id: node.id.0.clone(),
+            node_id: node.id.0.to_string(),
             node_type: node.node_type.as_str().to_string(),
             content: node.content.clone(),
             old_content,
             path,
+            content_changes,
+            sensitivity,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LinkChangeSummary {
     pub link_id: String,
     pub from_node: String,
@@ -30,22 +59,35 @@ pub struct LinkChangeSummary {
     pub relation: String,
     pub bidirectional: bool,
     pub confidence: Option<String>,
+    /// The higher `Graph::path_sensitivity` of the two ends.
+    #[serde(skip_serializing_if = "Sensitivity::is_normal")]
+    pub sensitivity: Sensitivity,
 }
 
 impl LinkChangeSummary {
-    fn from_link(id: &crate::model::LinkId, link: &crate::model::Link) -> Self {
+    fn from_link(id: &crate::model::LinkId, link: &crate::model::Link, graph: &Graph) -> Self {
         Self {
-            link_id: id.0.clone(),
-            from_node: link.from_node.0.clone(),
-            to_node: link.to_node.0.clone(),
+            link_id: id.0.to_string(),
+            from_node: link.from_node.0.to_string(),
+            to_node: link.to_node.0.to_string(),
             relation: link.relation.clone(),
             bidirectional: link.bidirectional,
             confidence: link.confidence.as_ref().map(|c| c.as_str().to_string()),
+            sensitivity: graph.path_sensitivity(&link.from_node).max(graph.path_sensitivity(&link.to_node)),
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentChangeSummary {
+    pub node_id: String,
+    pub hash: String,
+    pub mime: String,
+    #[serde(skip_serializing_if = "Sensitivity::is_normal")]
+    pub sensitivity: Sensitivity,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ChangeSummary {
     pub nodes_created: Vec<NodeChangeSummary>,
     pub nodes_updated: Vec<NodeChangeSummary>,
@@ -53,6 +95,8 @@ pub struct ChangeSummary {
     pub links_created: Vec<LinkChangeSummary>,
     pub links_removed: Vec<LinkChangeSummary>,
     pub links_updated: Vec<LinkChangeSummary>,
+    pub attachments_added: Vec<AttachmentChangeSummary>,
+    pub attachments_removed: Vec<AttachmentChangeSummary>,
 }
 
 impl ChangeSummary {
@@ -63,74 +107,258 @@ impl ChangeSummary {
             && self.links_created.is_empty()
             && self.links_removed.is_empty()
             && self.links_updated.is_empty()
+            && self.attachments_added.is_empty()
+            && self.attachments_removed.is_empty()
+    }
+
+    /// This summary without anything above `max`: nodes, links with an end
+    /// above it, and attachments on such nodes.
+    pub fn without_above(&self, max: Sensitivity) -> ChangeSummary {
+        fn keep<T: Clone>(items: &[T], max: Sensitivity, sensitivity: impl Fn(&T) -> Sensitivity) -> Vec<T> {
+            items.iter().filter(|item| sensitivity(item) <= max).cloned().collect()
+        }
+        ChangeSummary {
+            nodes_created: keep(&self.nodes_created, max, |n| n.sensitivity),
+            nodes_updated: keep(&self.nodes_updated, max, |n| n.sensitivity),
+            nodes_deleted: keep(&self.nodes_deleted, max, |n| n.sensitivity),
+            links_created: keep(&self.links_created, max, |l| l.sensitivity),
+            links_removed: keep(&self.links_removed, max, |l| l.sensitivity),
+            links_updated: keep(&self.links_updated, max, |l| l.sensitivity),
+            attachments_added: keep(&self.attachments_added, max, |a| a.sensitivity),
+            attachments_removed: keep(&self.attachments_removed, max, |a| a.sensitivity),
+        }
+    }
+
+    /// Order every list by id, independent of map iteration order.
+    fn sort(&mut self) {
+        for nodes in [&mut self.nodes_created, &mut self.nodes_updated, &mut self.nodes_deleted] {
+            nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        }
+        for links in [&mut self.links_created, &mut self.links_removed, &mut self.links_updated] {
+            links.sort_by(|a, b| a.link_id.cmp(&b.link_id));
+        }
+        for attachments in [&mut self.attachments_added, &mut self.attachments_removed] {
+            attachments.sort_by(|a, b| (&a.node_id, &a.hash).cmp(&(&b.node_id, &b.hash)));
+        }
+    }
+
+    pub fn stats(&self) -> CommitStats {
+        CommitStats {
+            nodes_created: self.nodes_created.len() as u32,
+            nodes_updated: self.nodes_updated.len() as u32,
+            nodes_deleted: self.nodes_deleted.len() as u32,
+            links_created: self.links_created.len() as u32,
+            links_removed: self.links_removed.len() as u32,
+            links_updated: self.links_updated.len() as u32,
+        }
+    }
+}
+
+/// The first line of `content`, cut short if long.
+fn short(content: &str) -> String {
+    let line = content.lines().next().unwrap_or("");
+    match line.char_indices().nth(MESSAGE_CONTENT_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None if line.len() < content.trim_end().len() => format!("{line}…"),
+        None => line.to_string(),
+    }
+}
+
+fn counted(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {noun}"),
+        _ => format!("{n} {noun}s"),
+    }
+}
+
+/// `'a', 'b' and 'c'`, or `3 nodes` once there are too many to name.
+fn named(nodes: &[&NodeChangeSummary]) -> String {
+    if nodes.len() > MESSAGE_NAMED_NODES {
+        return counted(nodes.len(), "node");
+    }
+    let mut names: Vec<String> = nodes.iter().map(|n| format!("'{}'", short(&n.content))).collect();
+    names.sort();
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {last}", rest.join(", ")),
+        _ => names.concat(),
     }
 }
 
-/// Build the path from root to a node (list of content strings).
-fn build_node_path(graph: &Graph, node_id: &NodeId) -> Vec<String> {
-    let mut path = Vec::new();
-    let mut current = Some(node_id.clone());
-    while let Some(id) = current {
-        if let Some(node) = graph.nodes.get(&id) {
-            path.push(node.content.clone());
-            current = node.parent_id.clone();
-        } else {
-            break;
+/// Nodes grouped by the content of their parent, from their paths.
+fn by_parent(nodes: &[NodeChangeSummary]) -> BTreeMap<Option<&str>, Vec<&NodeChangeSummary>> {
+    let mut groups: BTreeMap<Option<&str>, Vec<&NodeChangeSummary>> = BTreeMap::new();
+    for node in nodes {
+        let parent = node.path.len().checked_sub(2).map(|i| node.path[i].as_str());
+        groups.entry(parent).or_default().push(node);
+    }
+    groups
+}
+
+/// A one-line, human-readable description of a diff for a commit message,
+/// e.g. "Added 3 nodes under Career; updated 'Favorite food'".
+pub fn describe(summary: &ChangeSummary) -> String {
+    let mut clauses = Vec::new();
+    for (verb, nodes) in [("added", &summary.nodes_created), ("removed", &summary.nodes_deleted)] {
+        for (parent, group) in by_parent(nodes) {
+            clauses.push(match parent {
+                Some(parent) => format!("{verb} {} under {}", named(&group), short(parent)),
+                None => format!("{verb} {}", named(&group)),
+            });
+        }
+    }
+    if !summary.nodes_updated.is_empty() {
+        let updated: Vec<&NodeChangeSummary> = summary.nodes_updated.iter().collect();
+        clauses.push(format!("updated {}", named(&updated)));
+    }
+    for (verb, count) in [
+        ("added", summary.links_created.len()),
+        ("removed", summary.links_removed.len()),
+        ("updated", summary.links_updated.len()),
+    ] {
+        if count > 0 {
+            clauses.push(format!("{verb} {}", counted(count, "link")));
         }
     }
-    path.reverse();
-    path
+    for (verb, count) in [
+        ("attached", summary.attachments_added.len()),
+        ("detached", summary.attachments_removed.len()),
+    ] {
+        if count > 0 {
+            clauses.push(format!("{verb} {}", counted(count, "file")));
+        }
+    }
+
+    let message = clauses.join("; ");
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "No changes".to_string(),
+    }
 }
 
 /// Collect items from `source` whose keys are absent in `other`.
 fn diff_keys_only_in<K, V, T>(
     source: &std::collections::HashMap<K, V>,
     other: &std::collections::HashMap<K, V>,
-    map_fn: impl Fn(&K, &V) -> T,
+    map_fn: impl Fn(&K, &V) -> T + Sync,
 ) -> Vec<T>
 where
-    K: Eq + std::hash::Hash,
+    K: Eq + std::hash::Hash + Sync,
+    V: Sync,
+    T: Send,
 {
-    source.iter()
-        .filter(|(k, _)| !other.contains_key(k))
-        .map(|(k, v)| map_fn(k, v))
-        .collect()
+    parallel::filter_map_entries(source, |k, v| (!other.contains_key(k)).then(|| map_fn(k, v)))
+}
+
+/// Attachments on nodes in `source` that the same node in `other` lacks.
+fn diff_attachments(source: &Graph, other: &Graph) -> Vec<AttachmentChangeSummary> {
+    parallel::filter_map_entries(&source.nodes, |nid, node| {
+        let other_node = other.nodes.get(nid);
+        let sensitivity = source.path_sensitivity(nid).max(other.path_sensitivity(nid));
+        let missing: Vec<_> = node.attachments.iter()
+            .filter(|a| other_node.is_none_or(|o| !o.attachments.contains(a)))
+            .map(|a| AttachmentChangeSummary {
+                node_id: nid.0.to_string(),
+                hash: a.hash.clone(),
+                mime: a.mime.clone(),
+                sensitivity,
+            })
+            .collect();
+        (!missing.is_empty()).then_some(missing)
+    })
+    .into_iter()
+    .flatten()
+    .collect()
 }
 
 /// Compute a diff between two graph states.
 pub fn compute_graph_diff(old: &Graph, new: &Graph) -> ChangeSummary {
     let nodes_created = diff_keys_only_in(&new.nodes, &old.nodes, |nid, node| {
-        NodeChangeSummary::new(node, None, build_node_path(new, nid))
+        NodeChangeSummary::new(node, None, new.content_path(nid), new.path_sensitivity(nid))
     });
     let nodes_deleted = diff_keys_only_in(&old.nodes, &new.nodes, |nid, node| {
-        NodeChangeSummary::new(node, None, build_node_path(old, nid))
+        NodeChangeSummary::new(node, None, old.content_path(nid), old.path_sensitivity(nid))
+    });
+    let nodes_updated = parallel::filter_map_entries(&new.nodes, |nid, new_node| {
+        let old_node = old.nodes.get(nid).filter(|old_node| !Arc::ptr_eq(old_node, new_node))?;
+        (old_node.content != new_node.content
+            || old_node.metadata != new_node.metadata
+            || old_node.pinned != new_node.pinned
+            || old_node.sensitivity != new_node.sensitivity
+            || old_node.archived != new_node.archived
+            || old_node.display != new_node.display)
+            .then(|| {
+                let sensitivity = old.path_sensitivity(nid).max(new.path_sensitivity(nid));
+                NodeChangeSummary::new(new_node, Some(old_node.content.clone()), new.content_path(nid), sensitivity)
+            })
     });
-    let nodes_updated: Vec<_> = new.nodes.iter()
-        .filter_map(|(nid, new_node)| {
-            let old_node = old.nodes.get(nid)?;
-            (old_node.content != new_node.content || old_node.metadata != new_node.metadata)
-                .then(|| NodeChangeSummary::new(new_node, Some(old_node.content.clone()), build_node_path(new, nid)))
-        })
-        .collect();
 
     let links_created = diff_keys_only_in(&new.links, &old.links, |lid, link| {
-        LinkChangeSummary::from_link(lid, link)
+        LinkChangeSummary::from_link(lid, link, new)
     });
     let links_removed = diff_keys_only_in(&old.links, &new.links, |lid, link| {
-        LinkChangeSummary::from_link(lid, link)
+        LinkChangeSummary::from_link(lid, link, old)
+    });
+    let links_updated = parallel::filter_map_entries(&new.links, |lid, new_link| {
+        let old_link = old.links.get(lid)?;
+        (old_link.relation != new_link.relation
+            || old_link.bidirectional != new_link.bidirectional
+            || old_link.confidence != new_link.confidence)
+            .then(|| LinkChangeSummary::from_link(lid, new_link, new))
     });
-    let links_updated: Vec<_> = new.links.iter()
-        .filter_map(|(lid, new_link)| {
-            let old_link = old.links.get(lid)?;
-            (old_link.relation != new_link.relation
-                || old_link.bidirectional != new_link.bidirectional
-                || old_link.confidence != new_link.confidence)
-                .then(|| LinkChangeSummary::from_link(lid, new_link))
-        })
-        .collect();
+
+    let attachments_added = diff_attachments(new, old);
+    let attachments_removed = diff_attachments(old, new);
 
     debug!(created = nodes_created.len(), updated = nodes_updated.len(), deleted = nodes_deleted.len(), "graph diff computed");
-    ChangeSummary { nodes_created, nodes_updated, nodes_deleted, links_created, links_removed, links_updated }
+    let mut summary = ChangeSummary {
+        nodes_created,
+        nodes_updated,
+        nodes_deleted,
+        links_created,
+        links_removed,
+        links_updated,
+        attachments_added,
+        attachments_removed,
+    };
+    summary.sort();
+    summary
+}
+
+/// `compute_graph_diff` from the graph before `changes` to `current`, the
+/// graph after them, in time proportional to the changes: only the nodes
+/// and links they touch are compared, with the ancestors their paths (and
+/// the sensitivity of their links' ends) need.
+pub fn summarize_changes(changes: &[Change], current: &Graph) -> ChangeSummary {
+    let mut seeds: HashSet<&NodeId> = HashSet::new();
+    for change in changes {
+        seeds.extend(search_index::touched(change));
+        seeds.extend(journal::former_parents(change));
+    }
+    for id in changes.iter().flat_map(search_index::touched_links) {
+        if let Some(link) = current.links.get(id) {
+            seeds.extend([&link.from_node, &link.to_node]);
+        }
+    }
+    let mut after = Graph::empty(current.root_id.clone());
+    for id in seeds {
+        let mut next = Some(id);
+        while let Some(node) = next.and_then(|id| current.nodes.get(id)) {
+            if after.nodes.insert(node.id.clone(), Arc::clone(node)).is_some() {
+                break;
+            }
+            next = node.parent_id.as_ref();
+        }
+    }
+    for id in changes.iter().flat_map(search_index::touched_links) {
+        if let Some(link) = current.links.get(id) {
+            after.links.insert(id.clone(), link.clone());
+        }
+    }
+
+    let mut before = after.clone();
+    apply_delta(&mut before, &invert_delta(&Delta::new(changes.to_vec())));
+    compute_graph_diff(&before, &after)
 }
 
 #[cfg(test)]
@@ -138,11 +366,11 @@ mod tests {
     use super::*;
     use crate::model::*;
     use chrono::Utc;
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     fn empty_graph() -> Graph {
-        let root_id = NodeId("root".to_string());
-        let mut nodes = HashMap::new();
+        let root_id = NodeId("root".into());
+        let mut nodes: HashMap<NodeId, Arc<Node>> = HashMap::new();
         nodes.insert(
             root_id.clone(),
             Node {
@@ -151,17 +379,28 @@ mod tests {
                 content: "User".to_string(),
                 parent_id: None,
                 children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
                 metadata: HashMap::new(),
                 previous_values: Vec::new(),
                 temporal: None,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
-            },
+                created_by: None,
+                updated_by: None,
+            }.into(),
         );
         Graph {
             root_id,
             nodes,
             links: HashMap::new(),
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
         }
     }
 
@@ -180,24 +419,34 @@ mod tests {
     fn test_diff_node_created() {
         let old = empty_graph();
         let mut new = old.clone();
-        let nid = NodeId("n1".to_string());
+        let nid = NodeId("n1".into());
         new.nodes.insert(
             nid.clone(),
             Node {
                 id: nid.clone(),
                 node_type: NodeType::Detail,
                 content: "Likes pizza".to_string(),
-                parent_id: Some(NodeId("root".to_string())),
+                parent_id: Some(NodeId("root".into())),
                 children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
                 metadata: HashMap::new(),
                 previous_values: Vec::new(),
                 temporal: None,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
-            },
+                created_by: None,
+                updated_by: None,
+            }.into(),
         );
         new.nodes
-            .get_mut(&NodeId("root".to_string()))
+            .get_mut(&NodeId("root".into()))
+            .map(Arc::make_mut)
             .unwrap()
             .children
             .push(nid);
@@ -209,28 +458,61 @@ mod tests {
         assert!(diff.nodes_updated.is_empty());
     }
 
+    #[test]
+    fn test_diff_and_serialization_are_ordered() {
+        let old = empty_graph();
+        let template = Node::clone(&old.nodes[&old.root_id]);
+        let ids = ["n3", "n1", "n4", "n2"];
+        let mut new = old.clone();
+        let mut reversed = old.clone();
+        for id in ids {
+            let mut node = template.clone();
+            node.id = NodeId(id.into());
+            node.metadata = HashMap::from([("b".to_string(), "1".to_string()), ("a".to_string(), "2".to_string())]);
+            new.nodes.insert(node.id.clone(), node.into());
+        }
+        for id in ids.iter().rev() {
+            let id = NodeId((*id).into());
+            reversed.nodes.insert(id.clone(), new.nodes[&id].clone());
+        }
+
+        let diff = compute_graph_diff(&old, &new);
+        let created: Vec<&str> = diff.nodes_created.iter().map(|n| n.node_id.as_str()).collect();
+        assert_eq!(created, ["n1", "n2", "n3", "n4"]);
+        assert_eq!(serde_json::to_string(&new).unwrap(), serde_json::to_string(&reversed).unwrap());
+    }
+
     #[test]
     fn test_diff_node_updated() {
         let mut old = empty_graph();
-        let nid = NodeId("n1".to_string());
+        let nid = NodeId("n1".into());
         old.nodes.insert(
             nid.clone(),
             Node {
                 id: nid.clone(),
                 node_type: NodeType::Detail,
                 content: "Old content".to_string(),
-                parent_id: Some(NodeId("root".to_string())),
+                parent_id: Some(NodeId("root".into())),
                 children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
                 metadata: HashMap::new(),
                 previous_values: Vec::new(),
                 temporal: None,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
-            },
+                created_by: None,
+                updated_by: None,
+            }.into(),
         );
 
         let mut new = old.clone();
-        new.nodes.get_mut(&nid).unwrap().content = "New content".to_string();
+        new.nodes.get_mut(&nid).map(Arc::make_mut).unwrap().content = "New content".to_string();
 
         let diff = compute_graph_diff(&old, &new);
         assert_eq!(diff.nodes_updated.len(), 1);
@@ -241,21 +523,30 @@ mod tests {
     #[test]
     fn test_diff_node_deleted() {
         let mut old = empty_graph();
-        let nid = NodeId("n1".to_string());
+        let nid = NodeId("n1".into());
         old.nodes.insert(
             nid.clone(),
             Node {
                 id: nid.clone(),
                 node_type: NodeType::Detail,
                 content: "Gone".to_string(),
-                parent_id: Some(NodeId("root".to_string())),
+                parent_id: Some(NodeId("root".into())),
                 children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
                 metadata: HashMap::new(),
                 previous_values: Vec::new(),
                 temporal: None,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
-            },
+                created_by: None,
+                updated_by: None,
+            }.into(),
         );
         let new = empty_graph();
 
@@ -268,13 +559,13 @@ mod tests {
     fn test_diff_links() {
         let old = empty_graph();
         let mut new = old.clone();
-        let lid = LinkId("l1".to_string());
+        let lid = LinkId("l1".into());
         new.links.insert(
             lid.clone(),
             Link {
                 id: lid,
-                from_node: NodeId("root".to_string()),
-                to_node: NodeId("root".to_string()),
+                from_node: NodeId("root".into()),
+                to_node: NodeId("root".into()),
                 relation: "self".to_string(),
                 bidirectional: false,
                 confidence: None,
@@ -294,13 +585,13 @@ mod tests {
     #[test]
     fn test_diff_link_updated() {
         let mut old = empty_graph();
-        let lid = LinkId("l1".to_string());
+        let lid = LinkId("l1".into());
         old.links.insert(
             lid.clone(),
             Link {
                 id: lid.clone(),
-                from_node: NodeId("root".to_string()),
-                to_node: NodeId("root".to_string()),
+                from_node: NodeId("root".into()),
+                to_node: NodeId("root".into()),
                 relation: "related_to".to_string(),
                 bidirectional: false,
                 confidence: None,
@@ -322,4 +613,56 @@ mod tests {
         assert!(diff.links_created.is_empty());
         assert!(diff.links_removed.is_empty());
     }
+
+    /// The root with `count` detail children, each linked to the next.
+    #[cfg(feature = "parallel")]
+    fn wide_graph(count: usize) -> Graph {
+        let mut graph = empty_graph();
+        let template = Node::clone(&graph.nodes[&graph.root_id]);
+        for i in 0..count {
+            let mut node = template.clone();
+            node.id = NodeId(format!("n{i}").into());
+            node.node_type = NodeType::Detail;
+            node.content = format!("Fact {i}");
+            node.parent_id = Some(graph.root_id.clone());
+            graph.nodes.insert(node.id.clone(), node.into());
+            let link = Link {
+                id: LinkId(format!("l{i}").into()),
+                from_node: NodeId(format!("n{i}").into()),
+                to_node: NodeId(format!("n{}", (i + 1) % count).into()),
+                relation: "related_to".to_string(),
+                bidirectional: false,
+                confidence: None,
+                created_at: Utc::now(),
+            };
+            graph.links.insert(link.id.clone(), link);
+        }
+        graph
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_diff_matches_serial() {
+        let old = wide_graph(12_000);
+        let mut new = old.clone();
+        for i in (0..12_000).step_by(7) {
+            Arc::make_mut(new.nodes.get_mut(&NodeId(format!("n{i}").into())).unwrap()).content = format!("Changed {i}");
+        }
+        for i in (0..12_000).step_by(11) {
+            new.nodes.remove(&NodeId(format!("n{i}").into()));
+            new.links.remove(&LinkId(format!("l{i}").into()));
+        }
+        for i in (0..12_000).step_by(13) {
+            if let Some(link) = new.links.get_mut(&LinkId(format!("l{i}").into())) {
+                link.relation = "knows".to_string();
+            }
+        }
+
+        let parallel = compute_graph_diff(&old, &new);
+        let serial = crate::parallel::serially(|| compute_graph_diff(&old, &new));
+        assert!(!parallel.nodes_updated.is_empty() && !parallel.nodes_deleted.is_empty());
+        assert_eq!(serde_json::to_string(&parallel).unwrap(), serde_json::to_string(&serial).unwrap());
+        let reversed = compute_graph_diff(&new, &old);
+        assert_eq!(reversed.nodes_created.len(), parallel.nodes_deleted.len());
+    }
 }
@@ -1,6 +1,8 @@
-use crate::model::{Graph, Link, Node, NodeId};
+use crate::model::{DisplayHints, Graph, Link, Node, NodeId, Sensitivity};
+use crate::parallel;
 use crate::vcs::types::CommitHash;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum MergeSide {
@@ -22,7 +24,7 @@ pub enum ConflictType {
     },
     DeleteModifyConflict {
         deleted_by: MergeSide,
-        modified_node: Node,
+        modified_node: Box<Node>,
     },
     DeleteLinkConflict {
         deleted_node: NodeId,
@@ -54,7 +56,7 @@ fn node_modified(node: &Node, base: &Node) -> bool {
 }
 
 fn modify_parent(graph: &mut Graph, parent_id: &NodeId, child_id: &NodeId, add: bool) {
-    if let Some(parent) = graph.nodes.get_mut(parent_id) {
+    if let Some(parent) = graph.nodes.get_mut(parent_id).map(Arc::make_mut) {
         if add {
             if !parent.children.contains(child_id) {
                 parent.children.push(child_id.clone());
@@ -66,7 +68,12 @@ fn modify_parent(graph: &mut Graph, parent_id: &NodeId, child_id: &NodeId, add:
 }
 
 fn remove_node(graph: &mut Graph, node_id: &NodeId) {
-    if let Some(pid) = graph.nodes.get(node_id).and_then(|n| n.parent_id.clone()) {
+    let parents: Vec<NodeId> = graph
+        .nodes
+        .get(node_id)
+        .map(|n| n.parents().cloned().collect())
+        .unwrap_or_default();
+    for pid in parents {
         modify_parent(graph, &pid, node_id, false);
     }
     graph.nodes.remove(node_id);
@@ -75,7 +82,7 @@ fn remove_node(graph: &mut Graph, node_id: &NodeId) {
 fn parent_id_or_empty(node: &Node) -> NodeId {
     node.parent_id
         .clone()
-        .unwrap_or(NodeId("".to_string()))
+        .unwrap_or(NodeId("".into()))
 }
 
 fn bfs_expand(
@@ -175,7 +182,7 @@ fn merge_deleted_nodes(
                 node_id: nid.clone(),
                 conflict_type: ConflictType::DeleteModifyConflict {
                     deleted_by: deleted_by.clone(),
-                    modified_node: survivor_node.clone(),
+                    modified_node: Box::new(Node::clone(survivor_node)),
                 },
             });
         } else if matches!(deleted_by, MergeSide::Theirs) {
@@ -188,7 +195,7 @@ fn reparent_node(merged: &mut Graph, nid: &NodeId, new_parent_id: &Option<NodeId
     if let Some(old_pid) = merged.nodes.get(nid).and_then(|n| n.parent_id.clone()) {
         modify_parent(merged, &old_pid, nid, false);
     }
-    if let Some(node) = merged.nodes.get_mut(nid) {
+    if let Some(node) = merged.nodes.get_mut(nid).map(Arc::make_mut) {
         node.parent_id = new_parent_id.clone();
     }
     if let Some(ref new_pid) = new_parent_id {
@@ -202,6 +209,70 @@ enum ThreeWayChange<T> {
     NoAction,
 }
 
+/// Merge extra parents as sets: keep everything either side added and drop
+/// anything either side removed.
+fn merge_extra_parents(merged: &mut Graph, nid: &NodeId, base: &Node, ours: &Node, theirs: &Node) {
+    if ours.extra_parents == theirs.extra_parents || theirs.extra_parents == base.extra_parents {
+        return;
+    }
+    let removed = |side: &Node, p: &NodeId| base.extra_parents.contains(p) && !side.extra_parents.contains(p);
+    let mut extra: Vec<NodeId> = base
+        .extra_parents
+        .iter()
+        .chain(&ours.extra_parents)
+        .chain(&theirs.extra_parents)
+        .filter(|p| !removed(ours, p) && !removed(theirs, p))
+        .cloned()
+        .collect();
+    let mut seen = HashSet::new();
+    extra.retain(|p| seen.insert(p.clone()) && merged.nodes.contains_key(p));
+
+    let Some(node) = merged.nodes.get_mut(nid).map(Arc::make_mut) else { return };
+    let primary = node.parent_id.clone();
+    extra.retain(|p| primary.as_ref() != Some(p));
+    let old = std::mem::replace(&mut node.extra_parents, extra.clone());
+    for pid in old.iter().filter(|p| !extra.contains(p) && primary.as_ref() != Some(*p)) {
+        modify_parent(merged, pid, nid, false);
+    }
+    for pid in &extra {
+        modify_parent(merged, pid, nid, true);
+    }
+}
+
+/// Step 3 of `three_way_merge` for one node present in all three graphs,
+/// worked out from the three versions alone so nodes can be compared in
+/// parallel before the outcomes are applied to the merged graph in turn.
+struct NodeMerge<'a> {
+    nid: &'a NodeId,
+    base: &'a Node,
+    ours: &'a Node,
+    theirs: &'a Node,
+    content: ThreeWayChange<(String, HashMap<String, String>)>,
+    parent: ThreeWayChange<Option<NodeId>>,
+    pinned: ThreeWayChange<bool>,
+    archived: ThreeWayChange<bool>,
+    display: ThreeWayChange<Option<DisplayHints>>,
+    sensitivity: ThreeWayChange<Sensitivity>,
+}
+
+impl<'a> NodeMerge<'a> {
+    fn new(nid: &'a NodeId, base: &'a Node, ours: &'a Node, theirs: &'a Node) -> Self {
+        let content_key = |n: &Node| (n.content.clone(), n.metadata.clone());
+        NodeMerge {
+            nid,
+            base,
+            ours,
+            theirs,
+            content: three_way_diff(&content_key(base), &content_key(ours), &content_key(theirs)),
+            parent: three_way_diff(&base.parent_id, &ours.parent_id, &theirs.parent_id),
+            pinned: three_way_diff(&base.pinned, &ours.pinned, &theirs.pinned),
+            archived: three_way_diff(&base.archived, &ours.archived, &theirs.archived),
+            display: three_way_diff(&base.display, &ours.display, &theirs.display),
+            sensitivity: three_way_diff(&base.sensitivity, &ours.sensitivity, &theirs.sensitivity),
+        }
+    }
+}
+
 fn three_way_diff<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> ThreeWayChange<T> {
     let ours_changed = ours != base;
     let theirs_changed = theirs != base;
@@ -222,27 +293,55 @@ pub fn three_way_merge(base: &Graph, ours: &Graph, theirs: &Graph) -> MergeResul
     // 1. Nodes added only by theirs
     for (nid, node) in &theirs.nodes {
         if !base.nodes.contains_key(nid) && !ours.nodes.contains_key(nid) {
-            if let Some(ref parent_id) = node.parent_id {
+            for parent_id in node.parents() {
                 modify_parent(&mut merged, parent_id, nid, true);
             }
             merged.nodes.insert(nid.clone(), node.clone());
         }
     }
 
+    // Profiles added only by theirs
+    for (name, root) in &theirs.roots {
+        if !base.roots.contains_key(name) && !merged.roots.contains_key(name) {
+            merged.roots.insert(name.clone(), root.clone());
+        }
+    }
+
+    // Rules changed only by theirs
+    if ours.rules == base.rules {
+        merged.rules = theirs.rules.clone();
+    }
+
     // 2. Nodes deleted by one side, possibly modified by the other
     merge_deleted_nodes(base, theirs, ours, MergeSide::Theirs, &mut merged, &mut conflicts);
     merge_deleted_nodes(base, ours, theirs, MergeSide::Ours, &mut merged, &mut conflicts);
 
-    // 3. Nodes present in all three — check content and structural changes
-    for (nid, base_node) in &base.nodes {
-        let (Some(ours_node), Some(theirs_node)) =
-            (ours.nodes.get(nid), theirs.nodes.get(nid))
-        else {
-            continue;
-        };
-
-        let content_key = |n: &Node| (n.content.clone(), n.metadata.clone());
-        match three_way_diff(&content_key(base_node), &content_key(ours_node), &content_key(theirs_node)) {
+    // 3. Nodes present in all three — check content and structural changes,
+    // applied in node id order so conflicts come out the same every time
+    let mut node_merges = parallel::filter_map_entries(&base.nodes, |nid, base_node| {
+        let ours_node = ours.nodes.get(nid)?;
+        let theirs_node = theirs.nodes.get(nid)?;
+        // Still shared with the base: theirs left the node alone.
+        if Arc::ptr_eq(base_node, theirs_node) {
+            return None;
+        }
+        Some(NodeMerge::new(nid, base_node, ours_node, theirs_node))
+    });
+    node_merges.sort_by(|a, b| a.nid.cmp(b.nid));
+    for node_merge in node_merges {
+        let NodeMerge {
+            nid,
+            base: base_node,
+            ours: ours_node,
+            theirs: theirs_node,
+            content,
+            parent,
+            pinned,
+            archived,
+            display,
+            sensitivity,
+        } = node_merge;
+        match content {
             ThreeWayChange::BothDiverged(_, _) => {
                 conflicts.push(MergeConflict {
                     node_id: nid.clone(),
@@ -254,7 +353,7 @@ pub fn three_way_merge(base: &Graph, ours: &Graph, theirs: &Graph) -> MergeResul
                 });
             }
             ThreeWayChange::OnlyTheirs((content, metadata)) => {
-                if let Some(node) = merged.nodes.get_mut(nid) {
+                if let Some(node) = merged.nodes.get_mut(nid).map(Arc::make_mut) {
                     node.content = content;
                     node.metadata = metadata;
                 }
@@ -262,7 +361,7 @@ pub fn three_way_merge(base: &Graph, ours: &Graph, theirs: &Graph) -> MergeResul
             ThreeWayChange::NoAction => {}
         }
 
-        match three_way_diff(&base_node.parent_id, &ours_node.parent_id, &theirs_node.parent_id) {
+        match parent {
             ThreeWayChange::BothDiverged(_, _) => {
                 conflicts.push(MergeConflict {
                     node_id: nid.clone(),
@@ -278,6 +377,34 @@ pub fn three_way_merge(base: &Graph, ours: &Graph, theirs: &Graph) -> MergeResul
             }
             ThreeWayChange::NoAction => {}
         }
+
+        if let ThreeWayChange::OnlyTheirs(pinned) = pinned {
+            if let Some(node) = merged.nodes.get_mut(nid).map(Arc::make_mut) {
+                node.pinned = pinned;
+            }
+        }
+        if let ThreeWayChange::OnlyTheirs(archived) = archived {
+            if let Some(node) = merged.nodes.get_mut(nid).map(Arc::make_mut) {
+                node.archived = archived;
+            }
+        }
+        if let ThreeWayChange::OnlyTheirs(display) = display {
+            if let Some(node) = merged.nodes.get_mut(nid).map(Arc::make_mut) {
+                node.display = display;
+            }
+        }
+
+        // Diverging sensitivity changes resolve to the stricter level.
+        let sensitivity = match sensitivity {
+            ThreeWayChange::OnlyTheirs(level) => Some(level),
+            ThreeWayChange::BothDiverged(ours, theirs) => Some(ours.max(theirs)),
+            ThreeWayChange::NoAction => None,
+        };
+        if let (Some(level), Some(node)) = (sensitivity, merged.nodes.get_mut(nid).map(Arc::make_mut)) {
+            node.sensitivity = level;
+        }
+
+        merge_extra_parents(&mut merged, nid, base_node, ours_node, theirs_node);
     }
 
     // 4. Merge links from theirs
@@ -307,7 +434,7 @@ pub fn three_way_merge(base: &Graph, ours: &Graph, theirs: &Graph) -> MergeResul
 pub fn apply_resolutions(graph: &mut Graph, resolutions: &[ConflictResolution]) {
     for res in resolutions {
         if let Some(content) = &res.resolved_content {
-            if let Some(node) = graph.nodes.get_mut(&res.node_id) {
+            if let Some(node) = graph.nodes.get_mut(&res.node_id).map(Arc::make_mut) {
                 node.content = content.clone();
             }
         } else {
@@ -325,21 +452,30 @@ mod tests {
     use super::*;
     use crate::model::*;
     use chrono::Utc;
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     fn make_node(id: &str, content: &str, parent: Option<&str>, children: &[&str]) -> Node {
         let now = Utc::now();
         Node {
-            id: NodeId(id.to_string()),
+            id: NodeId(id.into()),
             node_type: if parent.is_none() { NodeType::Root } else { NodeType::Detail },
             content: content.to_string(),
-            parent_id: parent.map(|p| NodeId(p.to_string())),
-            children: children.iter().map(|c| NodeId(c.to_string())).collect(),
+            parent_id: parent.map(|p| NodeId(p.into())),
+            children: children.iter().map(|c| NodeId((*c).into())).collect(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         }
     }
 
@@ -348,17 +484,19 @@ mod tests {
         if let Some(ref pid) = node.parent_id {
             modify_parent(graph, pid, &nid, true);
         }
-        graph.nodes.insert(nid, node);
+        graph.nodes.insert(nid, node.into());
     }
 
     fn base_graph() -> Graph {
-        let mut nodes = HashMap::new();
-        nodes.insert(NodeId("root".to_string()), make_node("root", "User", None, &["n1"]));
-        nodes.insert(NodeId("n1".to_string()), make_node("n1", "Base content", Some("root"), &[]));
+        let mut nodes: HashMap<NodeId, Arc<Node>> = HashMap::new();
+        nodes.insert(NodeId("root".into()), make_node("root", "User", None, &["n1"]).into());
+        nodes.insert(NodeId("n1".into()), make_node("n1", "Base content", Some("root"), &[]).into());
         Graph {
-            root_id: NodeId("root".to_string()),
+            root_id: NodeId("root".into()),
             nodes,
             links: HashMap::new(),
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
         }
     }
 
@@ -367,7 +505,7 @@ mod tests {
     }
 
     fn nid(s: &str) -> NodeId {
-        NodeId(s.to_string())
+        NodeId(s.into())
     }
 
     #[test]
@@ -416,8 +554,8 @@ mod tests {
         let mut ours = base.clone();
         let mut theirs = base.clone();
 
-        ours.nodes.get_mut(&nid("n1")).unwrap().content = "Ours version".to_string();
-        theirs.nodes.get_mut(&nid("n1")).unwrap().content = "Theirs version".to_string();
+        ours.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().content = "Ours version".to_string();
+        theirs.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().content = "Theirs version".to_string();
 
         match three_way_merge(&base, &ours, &theirs) {
             MergeResult::Conflicts(conflicts) => {
@@ -441,7 +579,7 @@ mod tests {
         let ours = base.clone();
         let mut theirs = base.clone();
 
-        theirs.nodes.get_mut(&nid("n1")).unwrap().content = "Updated by theirs".to_string();
+        theirs.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().content = "Updated by theirs".to_string();
 
         match three_way_merge(&base, &ours, &theirs) {
             MergeResult::Success(merged) => {
@@ -457,8 +595,8 @@ mod tests {
         let mut ours = base.clone();
         let mut theirs = base.clone();
 
-        ours.nodes.get_mut(&nid("n1")).unwrap().content = "Same change".to_string();
-        theirs.nodes.get_mut(&nid("n1")).unwrap().content = "Same change".to_string();
+        ours.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().content = "Same change".to_string();
+        theirs.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().content = "Same change".to_string();
 
         match three_way_merge(&base, &ours, &theirs) {
             MergeResult::Success(merged) => {
@@ -474,9 +612,9 @@ mod tests {
         let mut ours = base.clone();
         let mut theirs = base.clone();
 
-        ours.nodes.get_mut(&nid("n1")).unwrap().content = "Modified".to_string();
+        ours.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().content = "Modified".to_string();
         theirs.nodes.remove(&nid("n1"));
-        theirs.nodes.get_mut(&nid("root")).unwrap().children.retain(|c| c != &nid("n1"));
+        theirs.nodes.get_mut(&nid("root")).map(Arc::make_mut).unwrap().children.retain(|c| c != &nid("n1"));
 
         match three_way_merge(&base, &ours, &theirs) {
             MergeResult::Conflicts(conflicts) => {
@@ -487,6 +625,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_merge_extra_parents_union() {
+        let mut base = base_graph();
+        add_node(&mut base, make_node("n2", "Two", Some("root"), &[]));
+        add_node(&mut base, make_node("n3", "Three", Some("root"), &[]));
+        let mut ours = base.clone();
+        ours.nodes.get_mut(&nid("n3")).map(Arc::make_mut).unwrap().extra_parents.push(nid("n1"));
+        ours.nodes.get_mut(&nid("n1")).map(Arc::make_mut).unwrap().children.push(nid("n3"));
+        let mut theirs = base.clone();
+        theirs.nodes.get_mut(&nid("n3")).map(Arc::make_mut).unwrap().extra_parents.push(nid("n2"));
+        theirs.nodes.get_mut(&nid("n2")).map(Arc::make_mut).unwrap().children.push(nid("n3"));
+
+        let MergeResult::Success(merged) = three_way_merge(&base, &ours, &theirs) else {
+            panic!("expected clean merge");
+        };
+        assert_eq!(merged.nodes[&nid("n3")].extra_parents, vec![nid("n1"), nid("n2")]);
+        assert!(merged.nodes[&nid("n2")].children.contains(&nid("n3")));
+    }
+
     #[test]
     fn test_is_ancestor() {
         let (a, b, c) = (ch("a"), ch("b"), ch("c"));
@@ -501,4 +658,40 @@ mod tests {
         assert!(is_ancestor(&a, &a, &parents));
         assert!(!is_ancestor(&c, &a, &parents));
     }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_merge_matches_serial() {
+        let mut base = base_graph();
+        for i in 0..12_000 {
+            add_node(&mut base, make_node(&format!("w{i}"), &format!("Fact {i}"), Some("root"), &[]));
+        }
+        let edit = |graph: &Graph, step: usize, skip: Option<usize>, label: &str| {
+            let mut graph = graph.clone();
+            for i in (0..12_000).step_by(step).filter(|i| skip.is_none_or(|s| i % s != 0)) {
+                Arc::make_mut(graph.nodes.get_mut(&nid(&format!("w{i}"))).unwrap()).content = format!("{label} {i}");
+            }
+            graph
+        };
+        let theirs = edit(&base, 3, None, "Theirs");
+
+        // Overlapping edits conflict, and the conflicts come out in the same order.
+        let ours = edit(&base, 5, None, "Ours");
+        let parallel = three_way_merge(&base, &ours, &theirs);
+        let serial = crate::parallel::serially(|| three_way_merge(&base, &ours, &theirs));
+        let MergeResult::Conflicts(conflicts) = &parallel else { panic!("expected conflicts") };
+        assert_eq!(conflicts.len(), 12_000 / 15);
+        assert_eq!(format!("{parallel:?}"), format!("{serial:?}"));
+
+        // Disjoint edits merge to the same graph.
+        let ours = edit(&base, 5, Some(3), "Ours");
+        let parallel = three_way_merge(&base, &ours, &theirs);
+        let serial = crate::parallel::serially(|| three_way_merge(&base, &ours, &theirs));
+        let (MergeResult::Success(parallel), MergeResult::Success(serial)) = (parallel, serial) else {
+            panic!("expected clean merges");
+        };
+        assert_eq!(parallel.nodes[&nid("w3")].content, "Theirs 3");
+        assert_eq!(parallel.nodes[&nid("w5")].content, "Ours 5");
+        assert_eq!(serde_json::to_string(&parallel).unwrap(), serde_json::to_string(&serial).unwrap());
+    }
 }
@@ -1,5 +1,8 @@
+pub mod backend;
+pub mod cache;
 pub mod diff;
 pub mod merge;
 pub mod object_store;
 pub mod repository;
+pub mod s3;
 pub mod types;
@@ -1,47 +1,99 @@
 use crate::error::WillowError;
+use crate::files;
+use crate::maintenance::MaintenanceSchedule;
 use crate::model::Graph;
-use crate::vcs::types::{CommitData, CommitHash, Delta, HeadState, RepoConfig};
+use crate::vcs::backend::{LocalBackend, ObjectBackend};
+use crate::vcs::types::{CommitData, CommitEntry, CommitHash, Delta, HeadState, RepoConfig, SnapshotPatch, TipSnapshot};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use tracing::debug;
+use std::sync::{Mutex, MutexGuard};
+use tracing::{debug, info, warn};
 
-/// Manages on-disk storage of VCS objects (commits, snapshots, deltas, refs).
+/// The kinds of object kept in the `ObjectBackend`. Tip snapshots are left
+/// out: they are rewritten on every commit and only ever read locally.
+const OBJECT_KINDS: [&str; 5] = ["commits", "snapshots", "snapshot_patches", "deltas", "dictionaries"];
+
+/// Manages storage of VCS objects (commits, snapshots, deltas) and refs.
+/// Refs, config and the commit index live on disk under the repo; the
+/// objects go to an `ObjectBackend`, the same directory by default.
 pub struct ObjectStore {
     repo_path: PathBuf,
+    objects: Box<dyn ObjectBackend>,
+    tips: LocalBackend,
+    commit_index: Mutex<CommitIndex>,
+    snapshot_compression: SnapshotCompression,
+}
+
+/// How new snapshots are compressed: the zstd level, and the trained
+/// dictionary with its id, if any. Snapshots name the dictionary they need
+/// in their zstd frame, so earlier ones stay readable when it changes.
+struct SnapshotCompression {
+    level: i32,
+    dictionary: Option<(u32, Vec<u8>)>,
+}
+
+/// The commits read so far from the `commit_index` file, which holds one
+/// `CommitEntry` per line in the order they were written. Commits never
+/// change, so entries are only ever appended; each process reads what was
+/// added since it last looked, and only when asked for a commit it has not
+/// seen.
+#[derive(Default)]
+struct CommitIndex {
+    commits: HashMap<CommitHash, CommitData>,
+    read_to: u64,
 }
 
 impl ObjectStore {
     pub fn new(repo_path: &Path) -> Self {
+        let objects_dir = repo_path.join("objects");
         ObjectStore {
             repo_path: repo_path.to_path_buf(),
+            objects: Box::new(LocalBackend::new(&objects_dir)),
+            tips: LocalBackend::new(&objects_dir),
+            commit_index: Mutex::new(CommitIndex::default()),
+            snapshot_compression: SnapshotCompression {
+                level: RepoConfig::default().snapshot_compression_level,
+                dictionary: None,
+            },
         }
     }
 
     /// Initialize the repo directory structure.
     pub fn init(&self) -> Result<(), WillowError> {
-        for dir in [self.commits_dir(), self.snapshots_dir(), self.deltas_dir(), self.refs_heads_dir()] {
-            std::fs::create_dir_all(dir)?;
-        }
+        files::create_dir_all(&self.refs_heads_dir())?;
+        files::write(&self.commit_index_path(), "")?;
         Ok(())
     }
 
-    // ---- Path helpers ----
-
-    fn object_dir(&self, kind: &str) -> PathBuf {
-        self.repo_path.join("objects").join(kind)
+    /// Keep objects in `objects` from now on. Those already stored are not
+    /// moved; see `copy_objects`.
+    pub fn set_backend(&mut self, objects: Box<dyn ObjectBackend>) {
+        self.objects = objects;
     }
 
-    fn commits_dir(&self) -> PathBuf {
-        self.object_dir("commits")
+    /// Copy every object into `to`. Returns the number copied.
+    pub fn copy_objects(&self, to: &dyn ObjectBackend) -> Result<usize, WillowError> {
+        let mut copied = 0;
+        for kind in OBJECT_KINDS {
+            for name in self.objects.list(kind)? {
+                if let Some(data) = self.objects.get(kind, &name)? {
+                    to.put(kind, &name, &data)?;
+                    copied += 1;
+                }
+            }
+        }
+        info!(copied, "objects copied");
+        Ok(copied)
     }
 
-    fn snapshots_dir(&self) -> PathBuf {
-        self.object_dir("snapshots")
-    }
+    // ---- Path helpers ----
 
-    fn deltas_dir(&self) -> PathBuf {
-        self.object_dir("deltas")
+    fn commit_index_path(&self) -> PathBuf {
+        self.repo_path.join("commit_index")
     }
 
     fn refs_heads_dir(&self) -> PathBuf {
@@ -56,29 +108,37 @@ impl ObjectStore {
         self.repo_path.join("config.json")
     }
 
+    fn maintenance_path(&self) -> PathBuf {
+        self.repo_path.join("maintenance.json")
+    }
+
     // ---- Generic JSON helpers ----
 
     fn write_json<T: Serialize>(&self, path: &Path, data: &T) -> Result<(), WillowError> {
         let json = serde_json::to_string_pretty(data)?;
-        std::fs::write(path, json)?;
+        files::write(path, json)?;
         Ok(())
     }
 
     fn read_json<T: serde::de::DeserializeOwned>(&self, path: &Path) -> Result<T, WillowError> {
-        let data = std::fs::read_to_string(path)?;
+        let data = files::read_to_string(path)?;
         let value: T = serde_json::from_str(&data)?;
         Ok(value)
     }
 
-    fn read_json_or_not_found<T: serde::de::DeserializeOwned>(
-        &self,
-        path: &Path,
-        hash: &CommitHash,
-    ) -> Result<T, WillowError> {
-        if !path.exists() {
-            return Err(WillowError::VcsCommitNotFound(hash.0.clone()));
-        }
-        self.read_json(path)
+    /// The object `kind`/`hash`, which must exist.
+    fn get_object(&self, kind: &str, hash: &CommitHash) -> Result<Vec<u8>, WillowError> {
+        self.objects
+            .get(kind, &hash.0)?
+            .ok_or_else(|| WillowError::VcsCommitNotFound(hash.0.clone()))
+    }
+
+    fn put_json<T: Serialize>(&self, kind: &str, hash: &CommitHash, data: &T) -> Result<(), WillowError> {
+        self.objects.put(kind, &hash.0, &serde_json::to_vec_pretty(data)?)
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, kind: &str, hash: &CommitHash) -> Result<T, WillowError> {
+        Ok(serde_json::from_slice(&self.get_object(kind, hash)?)?)
     }
 
     // ---- Config ----
@@ -91,6 +151,20 @@ impl ObjectStore {
         self.read_json(&self.config_path())
     }
 
+    // ---- Maintenance schedule ----
+
+    pub fn write_maintenance(&self, schedule: &MaintenanceSchedule) -> Result<(), WillowError> {
+        self.write_json(&self.maintenance_path(), schedule)
+    }
+
+    /// The schedule, or an empty one before any job is registered.
+    pub fn read_maintenance(&self) -> Result<MaintenanceSchedule, WillowError> {
+        match files::exists(&self.maintenance_path()) {
+            true => self.read_json(&self.maintenance_path()),
+            false => Ok(MaintenanceSchedule::default()),
+        }
+    }
+
     // ---- HEAD ----
 
     pub fn write_head(&self, state: &HeadState) -> Result<(), WillowError> {
@@ -98,12 +172,12 @@ impl ObjectStore {
             HeadState::Branch(name) => format!("ref: refs/heads/{}", name),
             HeadState::Detached(hash) => hash.0.clone(),
         };
-        std::fs::write(self.head_path(), content)?;
+        files::write(&self.head_path(), content)?;
         Ok(())
     }
 
     pub fn read_head(&self) -> Result<HeadState, WillowError> {
-        let content = std::fs::read_to_string(self.head_path())?;
+        let content = files::read_to_string(&self.head_path())?;
         let content = content.trim();
         if let Some(ref_path) = content.strip_prefix("ref: refs/heads/") {
             Ok(HeadState::Branch(ref_path.to_string()))
@@ -116,41 +190,35 @@ impl ObjectStore {
 
     pub fn write_branch_ref(&self, branch: &str, hash: &CommitHash) -> Result<(), WillowError> {
         let path = self.refs_heads_dir().join(branch);
-        std::fs::write(path, &hash.0)?;
+        files::write(&path, &hash.0)?;
         Ok(())
     }
 
     pub fn read_branch_ref(&self, branch: &str) -> Result<Option<CommitHash>, WillowError> {
         let path = self.refs_heads_dir().join(branch);
-        if !path.exists() {
+        if !files::exists(&path) {
             return Ok(None);
         }
-        let content = std::fs::read_to_string(path)?;
+        let content = files::read_to_string(&path)?;
         Ok(Some(CommitHash(content.trim().to_string())))
     }
 
     pub fn delete_branch_ref(&self, branch: &str) -> Result<(), WillowError> {
         let path = self.refs_heads_dir().join(branch);
-        if path.exists() {
-            std::fs::remove_file(path)?;
+        if files::exists(&path) {
+            files::remove_file(&path)?;
         }
         Ok(())
     }
 
     pub fn list_branches(&self) -> Result<Vec<String>, WillowError> {
         let dir = self.refs_heads_dir();
-        if !dir.exists() {
+        if !files::exists(&dir) {
             return Ok(Vec::new());
         }
-        let mut branches: Vec<String> = std::fs::read_dir(dir)?
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
-                if entry.file_type().ok()?.is_file() {
-                    entry.file_name().to_str().map(str::to_string)
-                } else {
-                    None
-                }
-            })
+        let mut branches: Vec<String> = files::list(&dir)?
+            .into_iter()
+            .filter_map(|path| path.file_name()?.to_str().map(str::to_string))
             .collect();
         branches.sort();
         Ok(branches)
@@ -169,47 +237,260 @@ impl ObjectStore {
 
     pub fn write_commit(&self, hash: &CommitHash, data: &CommitData) -> Result<(), WillowError> {
         debug!(hash = %hash.0, "writing commit");
-        self.write_json(&self.commits_dir().join(&hash.0), data)
+        self.put_json("commits", hash, data)?;
+        if !files::exists(&self.commit_index_path()) {
+            return self.build_commit_index();
+        }
+        let entry = CommitEntry { hash: hash.clone(), data: data.clone() };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        files::append(&self.commit_index_path(), line.as_bytes())?;
+        Ok(())
     }
 
+    /// Served from the commit index where it has the commit, so walking
+    /// history reads one file rather than one per commit.
     pub fn read_commit(&self, hash: &CommitHash) -> Result<CommitData, WillowError> {
+        let mut index = self.commit_index();
+        if !index.commits.contains_key(hash) {
+            self.catch_up_commit_index(&mut index)?;
+        }
+        if let Some(data) = index.commits.get(hash) {
+            return Ok(data.clone());
+        }
+        drop(index);
         debug!(hash = %hash.0, "reading commit");
-        self.read_json_or_not_found(&self.commits_dir().join(&hash.0), hash)
+        self.get_json("commits", hash)
+    }
+
+    fn commit_index(&self) -> MutexGuard<'_, CommitIndex> {
+        self.commit_index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Read the lines appended to the commit index since the last call. A
+    /// repository from before the index gets one built on first use.
+    fn catch_up_commit_index(&self, index: &mut CommitIndex) -> Result<(), WillowError> {
+        let path = self.commit_index_path();
+        if !files::exists(&path) {
+            self.build_commit_index()?;
+        }
+        let mut file = files::open(&path)?;
+        if file.seek(SeekFrom::End(0))? < index.read_to {
+            *index = CommitIndex::default();
+        }
+        file.seek(SeekFrom::Start(index.read_to))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        // A line still being written is picked up next time.
+        let complete = appended.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        for line in appended[..complete].split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+            match serde_json::from_slice::<CommitEntry>(line) {
+                Ok(entry) => {
+                    index.commits.insert(entry.hash, entry.data);
+                }
+                Err(e) => warn!(error = %e, "skipping unreadable commit index line"),
+            }
+        }
+        index.read_to += complete as u64;
+        debug!(commits = index.commits.len(), "commit index read");
+        Ok(())
+    }
+
+    /// Every commit written, reachable or not.
+    pub fn list_commits(&self) -> Result<Vec<CommitHash>, WillowError> {
+        self.list_objects("commits")
+    }
+
+    /// Delete a commit and everything stored for it, leaving the commit
+    /// index to `rebuild_commit_index`.
+    pub fn delete_commit_objects(&self, hash: &CommitHash) -> Result<(), WillowError> {
+        for kind in ["commits", "deltas", "snapshots", "snapshot_patches"] {
+            self.objects.delete(kind, &hash.0)?;
+        }
+        self.tips.delete("tips", &hash.0)
+    }
+
+    /// Rewrite the commit index from the commit files, after some were
+    /// deleted.
+    pub fn rebuild_commit_index(&self) -> Result<(), WillowError> {
+        let mut index = self.commit_index();
+        self.build_commit_index()?;
+        *index = CommitIndex::default();
+        Ok(())
+    }
+
+    fn build_commit_index(&self) -> Result<(), WillowError> {
+        let mut lines = String::new();
+        for hash in self.list_objects("commits")? {
+            let data = self.get_json("commits", &hash)?;
+            lines.push_str(&serde_json::to_string(&CommitEntry { hash, data })?);
+            lines.push('\n');
+        }
+        files::write(&self.commit_index_path(), lines)?;
+        debug!(path = %self.commit_index_path().display(), "commit index built");
+        Ok(())
     }
 
     // ---- Snapshots (zstd compressed) ----
 
+    fn compress<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, WillowError> {
+        let json = serde_json::to_vec(value)?;
+        let SnapshotCompression { level, dictionary } = &self.snapshot_compression;
+        let compressed = match dictionary {
+            Some((_, dictionary)) => zstd::bulk::Compressor::with_dictionary(*level, dictionary)?.compress(&json)?,
+            None => zstd::encode_all(json.as_slice(), *level).map_err(WillowError::Io)?,
+        };
+        Ok(compressed)
+    }
+
+    fn decompress<T: DeserializeOwned>(&self, compressed: &[u8]) -> Result<T, WillowError> {
+        let json = match zstd::zstd_safe::get_dict_id_from_frame(compressed) {
+            Some(id) => {
+                let dictionary = self.read_dictionary(id.get())?;
+                let mut json = Vec::new();
+                zstd::stream::Decoder::with_dictionary(compressed, &dictionary)?.read_to_end(&mut json)?;
+                json
+            }
+            None => zstd::decode_all(compressed).map_err(WillowError::Io)?,
+        };
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// Compress new snapshots at `level` and with the stored dictionary
+    /// `dictionary`, if given.
+    pub fn set_snapshot_compression(&mut self, level: i32, dictionary: Option<u32>) -> Result<(), WillowError> {
+        let dictionary = match dictionary {
+            Some(id) => Some((id, self.read_dictionary(id)?)),
+            None => None,
+        };
+        self.snapshot_compression = SnapshotCompression { level, dictionary };
+        Ok(())
+    }
+
+    /// Store a trained zstd dictionary under the id it carries, and return it.
+    pub fn write_dictionary(&self, dictionary: &[u8]) -> Result<u32, WillowError> {
+        let id = zstd::zstd_safe::get_dict_id_from_dict(dictionary)
+            .ok_or_else(|| WillowError::InvalidRepoConfig("not a zstd dictionary".to_string()))?
+            .get();
+        self.objects.put("dictionaries", &id.to_string(), dictionary)?;
+        Ok(id)
+    }
+
+    fn read_dictionary(&self, id: u32) -> Result<Vec<u8>, WillowError> {
+        self.objects
+            .get("dictionaries", &id.to_string())?
+            .ok_or_else(|| WillowError::InvalidRepoConfig(format!("snapshot dictionary {id} not found")))
+    }
+
     pub fn write_snapshot(&self, hash: &CommitHash, graph: &Graph) -> Result<(), WillowError> {
         debug!(hash = %hash.0, "writing snapshot");
-        let path = self.snapshots_dir().join(&hash.0);
-        let json = serde_json::to_vec(graph)?;
-        let compressed = zstd::encode_all(json.as_slice(), 3).map_err(WillowError::Io)?;
-        std::fs::write(path, compressed)?;
-        Ok(())
+        self.objects.put("snapshots", &hash.0, &self.compress(graph)?)
+    }
+
+    pub fn write_snapshot_patch(&self, hash: &CommitHash, patch: &SnapshotPatch) -> Result<(), WillowError> {
+        debug!(hash = %hash.0, base = %patch.base.0, chain = patch.chain, "writing snapshot patch");
+        self.objects.put("snapshot_patches", &hash.0, &self.compress(patch)?)
     }
 
+    /// The full graph at a snapshot commit, applying the patches between it
+    /// and the nearest full snapshot if it was stored as one.
     pub fn read_snapshot(&self, hash: &CommitHash) -> Result<Graph, WillowError> {
         debug!(hash = %hash.0, "reading snapshot");
-        let path = self.snapshots_dir().join(&hash.0);
-        if !path.exists() {
-            return Err(WillowError::VcsCommitNotFound(hash.0.clone()));
+        let mut patches = Vec::new();
+        let mut current = hash.clone();
+        while let Some(patch) = self.read_snapshot_patch(&current)? {
+            current = patch.base.clone();
+            patches.push(patch);
+        }
+        let mut graph: Graph = self.decompress(&self.get_object("snapshots", &current)?)?;
+        for patch in patches.into_iter().rev() {
+            patch.apply(&mut graph);
         }
-        let compressed = std::fs::read(path)?;
-        let json = zstd::decode_all(compressed.as_slice()).map_err(WillowError::Io)?;
-        let graph: Graph = serde_json::from_slice(&json)?;
+        graph.intern_ids();
         Ok(graph)
     }
 
+    pub fn read_snapshot_patch(&self, hash: &CommitHash) -> Result<Option<SnapshotPatch>, WillowError> {
+        match self.objects.get("snapshot_patches", &hash.0)? {
+            Some(compressed) => self.decompress(&compressed).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Patches to apply to reach the snapshot at `hash`: 0 for a full one.
+    pub fn snapshot_chain(&self, hash: &CommitHash) -> Result<u32, WillowError> {
+        Ok(self.read_snapshot_patch(hash)?.map_or(0, |patch| patch.chain))
+    }
+
+    /// Snapshot commits, whether stored full or as patches.
+    pub fn list_snapshots(&self) -> Result<Vec<CommitHash>, WillowError> {
+        let mut hashes = self.list_objects("snapshots")?;
+        hashes.extend(self.list_objects("snapshot_patches")?);
+        Ok(hashes)
+    }
+
+    /// Rewrite the snapshot at `hash` in the form it is stored in, with the
+    /// current compression settings.
+    pub fn recompress_snapshot(&self, hash: &CommitHash) -> Result<(), WillowError> {
+        match self.read_snapshot_patch(hash)? {
+            Some(patch) => self.write_snapshot_patch(hash, &patch),
+            None => {
+                let graph: Graph = self.decompress(&self.get_object("snapshots", hash)?)?;
+                self.write_snapshot(hash, &graph)
+            }
+        }
+    }
+
+    /// Commit hashes naming the objects of `kind`.
+    fn list_objects(&self, kind: &str) -> Result<Vec<CommitHash>, WillowError> {
+        Ok(self.objects.list(kind)?.into_iter().map(CommitHash).collect())
+    }
+
+    // ---- Tip snapshots ----
+    //
+    // Graphs kept for delta commits that are currently branch heads, so
+    // recent history can be reconstructed by walking deltas backwards.
+
+    pub fn write_tip_snapshot(&self, hash: &CommitHash, tip: &TipSnapshot) -> Result<(), WillowError> {
+        debug!(hash = %hash.0, "writing tip snapshot");
+        self.tips.put("tips", &hash.0, &self.compress(tip)?)
+    }
+
+    pub fn read_tip_snapshot(&self, hash: &CommitHash) -> Result<TipSnapshot, WillowError> {
+        debug!(hash = %hash.0, "reading tip snapshot");
+        let compressed = self
+            .tips
+            .get("tips", &hash.0)?
+            .ok_or_else(|| WillowError::VcsCommitNotFound(hash.0.clone()))?;
+        let mut tip: TipSnapshot = self.decompress(&compressed)?;
+        if let TipSnapshot::Full(graph) = &mut tip {
+            graph.intern_ids();
+        }
+        Ok(tip)
+    }
+
+    pub fn delete_tip_snapshot(&self, hash: &CommitHash) -> Result<(), WillowError> {
+        self.tips.delete("tips", &hash.0)
+    }
+
+    pub fn list_tip_snapshots(&self) -> Result<Vec<CommitHash>, WillowError> {
+        Ok(self.tips.list("tips")?.into_iter().map(CommitHash).collect())
+    }
+
     // ---- Deltas ----
 
     pub fn write_delta(&self, hash: &CommitHash, delta: &Delta) -> Result<(), WillowError> {
         debug!(hash = %hash.0, "writing delta");
-        self.write_json(&self.deltas_dir().join(&hash.0), delta)
+        self.put_json("deltas", hash, delta)
     }
 
     pub fn read_delta(&self, hash: &CommitHash) -> Result<Delta, WillowError> {
         debug!(hash = %hash.0, "reading delta");
-        self.read_json_or_not_found(&self.deltas_dir().join(&hash.0), hash)
+        self.get_json("deltas", hash)
+    }
+
+    pub fn has_delta(&self, hash: &CommitHash) -> Result<bool, WillowError> {
+        self.objects.exists("deltas", &hash.0)
     }
 
     /// Resolve HEAD to a concrete commit hash.
@@ -225,10 +506,11 @@ impl ObjectStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
     use crate::model::*;
     use crate::vcs::types::*;
     use chrono::Utc;
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
     use tempfile::TempDir;
 
     fn test_repo() -> (TempDir, ObjectStore) {
@@ -240,8 +522,8 @@ mod tests {
     }
 
     fn test_graph() -> Graph {
-        let mut nodes = HashMap::new();
-        let root_id = NodeId("root".to_string());
+        let mut nodes: HashMap<NodeId, Arc<Node>> = HashMap::new();
+        let root_id = NodeId("root".into());
         nodes.insert(
             root_id.clone(),
             Node {
@@ -250,17 +532,28 @@ mod tests {
                 content: "User".to_string(),
                 parent_id: None,
                 children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
                 metadata: HashMap::new(),
                 previous_values: Vec::new(),
                 temporal: None,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
-            },
+                created_by: None,
+                updated_by: None,
+            }.into(),
         );
         Graph {
             root_id,
             nodes,
             links: HashMap::new(),
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
         }
     }
 
@@ -328,6 +621,7 @@ mod tests {
             source: CommitSource::Migration,
             storage_type: CommitStorageType::Snapshot,
             depth_since_snapshot: 0,
+            stats: None,
         };
         let hash = ObjectStore::hash_commit(&data);
         store.write_commit(&hash, &data).unwrap();
@@ -354,6 +648,7 @@ mod tests {
             },
             storage_type: CommitStorageType::Delta,
             depth_since_snapshot: 1,
+            stats: None,
         };
         let hash = ObjectStore::hash_commit(&data);
         store.write_commit(&hash, &data).unwrap();
@@ -382,6 +677,7 @@ mod tests {
             },
             storage_type: CommitStorageType::Delta,
             depth_since_snapshot: 2,
+            stats: None,
         };
         let hash = ObjectStore::hash_commit(&data);
         store.write_commit(&hash, &data).unwrap();
@@ -410,6 +706,7 @@ mod tests {
             },
             storage_type: CommitStorageType::Snapshot,
             depth_since_snapshot: 0,
+            stats: None,
         };
         let hash = ObjectStore::hash_commit(&data);
         store.write_commit(&hash, &data).unwrap();
@@ -434,30 +731,50 @@ mod tests {
         let hash = CommitHash("snapshot1".to_string());
         store.write_snapshot(&hash, &graph).unwrap();
         let loaded = store.read_snapshot(&hash).unwrap();
-        assert_eq!(loaded.root_id.0, "root");
+        assert_eq!(&*loaded.root_id.0, "root");
         assert_eq!(loaded.nodes.len(), 1);
     }
 
+    #[test]
+    fn test_tip_snapshot_round_trip() {
+        let (_dir, store) = test_repo();
+        let hash = CommitHash("tip1".to_string());
+        store.write_tip_snapshot(&hash, &TipSnapshot::Full(test_graph())).unwrap();
+        assert_eq!(store.list_tip_snapshots().unwrap(), vec![hash.clone()]);
+        assert!(matches!(store.read_tip_snapshot(&hash).unwrap(), TipSnapshot::Full(graph) if graph.nodes.len() == 1));
+        store.delete_tip_snapshot(&hash).unwrap();
+        assert!(store.list_tip_snapshots().unwrap().is_empty());
+    }
+
     #[test]
     fn test_delta_round_trip() {
         let (_dir, store) = test_repo();
-        let delta = Delta {
-            changes: vec![Change::CreateNode {
-                node_id: NodeId("new-node".to_string()),
+        let delta = Delta::new(vec![Change::CreateNode {
+                node_id: NodeId("new-node".into()),
                 node: Node {
-                    id: NodeId("new-node".to_string()),
+                    id: NodeId("new-node".into()),
                     node_type: NodeType::Detail,
                     content: "Test detail".to_string(),
-                    parent_id: Some(NodeId("root".to_string())),
+                    parent_id: Some(NodeId("root".into())),
                     children: Vec::new(),
+                    extra_parents: Vec::new(),
+                    pinned: false,
+                    sensitivity: Sensitivity::Normal,
+                    archived: false,
+                    display: None,
+                    content_format: ContentFormat::Text,
+                    attachments: Vec::new(),
                     metadata: HashMap::new(),
                     previous_values: Vec::new(),
                     temporal: None,
                     created_at: Utc::now(),
                     updated_at: Utc::now(),
-                },
-            }],
-        };
+                    created_by: None,
+                    updated_by: None,
+                }.into(),
+                positions: Vec::new(),
+                actor: None,
+            }]);
         let hash = CommitHash("delta1".to_string());
         store.write_delta(&hash, &delta).unwrap();
         let loaded = store.read_delta(&hash).unwrap();
@@ -475,6 +792,7 @@ mod tests {
             source: CommitSource::Migration,
             storage_type: CommitStorageType::Snapshot,
             depth_since_snapshot: 0,
+            stats: None,
         };
         let hash1 = ObjectStore::hash_commit(&data);
         let hash2 = ObjectStore::hash_commit(&data);
@@ -501,4 +819,47 @@ mod tests {
         let resolved = store.resolve_head().unwrap();
         assert_eq!(resolved.unwrap().0, "commit1");
     }
+
+    fn commit(message: &str) -> (CommitHash, CommitData) {
+        let data = CommitData {
+            parents: vec![],
+            message: message.to_string(),
+            timestamp: Utc::now(),
+            source: CommitSource::Migration,
+            storage_type: CommitStorageType::Snapshot,
+            depth_since_snapshot: 0,
+            stats: None,
+        };
+        (ObjectStore::hash_commit(&data), data)
+    }
+
+    #[test]
+    fn test_commit_index_serves_reads() {
+        let (dir, store) = test_repo();
+        let (hash, data) = commit("Indexed");
+        store.write_commit(&hash, &data).unwrap();
+        std::fs::remove_file(dir.path().join("repo/objects/commits").join(&hash.0)).unwrap();
+
+        // A fresh store, as in another process, finds it through the index.
+        let other = ObjectStore::new(&dir.path().join("repo"));
+        assert_eq!(other.read_commit(&hash).unwrap().message, "Indexed");
+        let (later, later_data) = commit("Later");
+        store.write_commit(&later, &later_data).unwrap();
+        assert_eq!(other.read_commit(&later).unwrap().message, "Later");
+    }
+
+    #[test]
+    fn test_commit_index_built_for_older_repos() {
+        let (dir, store) = test_repo();
+        let (hash, data) = commit("Before the index");
+        store.write_commit(&hash, &data).unwrap();
+        let index_path = dir.path().join("repo/commit_index");
+        std::fs::remove_file(&index_path).unwrap();
+
+        let store = ObjectStore::new(&dir.path().join("repo"));
+        assert_eq!(store.read_commit(&hash).unwrap().message, "Before the index");
+        let (next, next_data) = commit("After");
+        store.write_commit(&next, &next_data).unwrap();
+        assert_eq!(std::fs::read_to_string(&index_path).unwrap().lines().count(), 2);
+    }
 }
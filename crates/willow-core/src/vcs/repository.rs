@@ -1,22 +1,55 @@
+use crate::attachments;
 use crate::error::WillowError;
-use crate::model::Graph;
+use crate::files;
+use crate::maintenance::MaintenanceSchedule;
+use crate::model::{Graph, NodeId};
+use crate::progress::Progress;
+use crate::search::{self, SearchOptions};
+use crate::vcs::cache::{GraphCache, DEFAULT_CACHE_CAPACITY};
 use crate::vcs::diff::{compute_graph_diff, ChangeSummary};
 use crate::vcs::merge::{
     apply_resolutions, find_merge_base, is_ancestor, three_way_merge, ConflictResolution,
     MergeConflict, MergeResult,
 };
+use crate::vcs::backend::{LocalBackend, ObjectBackend};
 use crate::vcs::object_store::ObjectStore;
+use crate::vcs::s3::{S3Backend, S3Config, S3Credentials};
 use crate::vcs::types::*;
+use crate::webhooks;
 use chrono::Utc;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
 use tracing::{info, debug};
 
 /// High-level VCS repository managing commits, branches, and history.
 pub struct Repository {
     store: ObjectStore,
     config: RepoConfig,
-    #[allow(dead_code)]
     repo_path: PathBuf,
+    cache: Mutex<GraphCache>,
+}
+
+/// Where objects are kept for `storage`: in the bucket, cached under
+/// `objects/`, or only there.
+fn object_backend(repo_path: &Path, storage: Option<&S3Config>) -> Result<Box<dyn ObjectBackend>, WillowError> {
+    let objects_dir = repo_path.join("objects");
+    Ok(match storage {
+        Some(config) => Box::new(S3Backend::new(config.clone(), S3Credentials::from_env()?, &objects_dir)?),
+        None => Box::new(LocalBackend::new(&objects_dir)),
+    })
+}
+
+/// Where a batched walk of the log has got to; see `Repository::log_batch`.
+#[derive(Debug, Clone)]
+pub struct LogCursor {
+    next: Option<CommitHash>,
+}
+
+impl LogCursor {
+    pub fn is_done(&self) -> bool {
+        self.next.is_none()
+    }
 }
 
 /// A branch info entry.
@@ -27,11 +60,33 @@ pub struct BranchInfo {
     pub is_current: bool,
 }
 
+/// Content found by `search_history` that HEAD no longer has.
+#[derive(Debug, Clone)]
+pub struct HistoryMatch {
+    pub node_id: NodeId,
+    /// The content as it was in `commit`.
+    pub content: String,
+    pub score: f64,
+    pub matched_field: String,
+    /// The most recent commit that still had this content.
+    pub commit: CommitEntry,
+    /// The node is gone at HEAD, rather than edited.
+    pub deleted: bool,
+}
+
+/// Options for `search_history`.
+#[derive(Debug, Clone, Default)]
+pub struct HistorySearchOptions {
+    pub search: SearchOptions,
+    /// Commits to search back from HEAD along first parents; defaults to 50.
+    pub max_commits: Option<usize>,
+}
+
 impl Repository {
     /// Initialize a new repository next to the graph file.
     pub fn init(graph_dir: &Path, graph: &Graph) -> Result<Self, WillowError> {
         let repo_path = graph_dir.join("repo");
-        if repo_path.exists() {
+        if files::exists(&repo_path) {
             return Err(WillowError::VcsAlreadyInitialized);
         }
 
@@ -49,6 +104,7 @@ impl Repository {
             source: CommitSource::Migration,
             storage_type: CommitStorageType::Snapshot,
             depth_since_snapshot: 0,
+            stats: Some(CommitStats::for_full_graph(graph)),
         };
         let hash = ObjectStore::hash_commit(&commit_data);
         store.write_commit(&hash, &commit_data)?;
@@ -63,34 +119,140 @@ impl Repository {
             store,
             config,
             repo_path,
+            cache: Mutex::new(GraphCache::new(DEFAULT_CACHE_CAPACITY)),
         })
     }
 
     /// Open an existing repository.
     pub fn open(graph_dir: &Path) -> Result<Self, WillowError> {
         let repo_path = graph_dir.join("repo");
-        if !repo_path.exists() {
+        if !files::exists(&repo_path) {
             return Err(WillowError::VcsNotInitialized);
         }
 
-        let store = ObjectStore::new(&repo_path);
+        let mut store = ObjectStore::new(&repo_path);
         let config = store.read_config()?;
+        if config.object_storage.is_some() {
+            store.set_backend(object_backend(&repo_path, config.object_storage.as_ref())?);
+        }
+        store.set_snapshot_compression(config.snapshot_compression_level, config.snapshot_dictionary)?;
 
         Ok(Repository {
             store,
             config,
             repo_path,
+            cache: Mutex::new(GraphCache::new(DEFAULT_CACHE_CAPACITY)),
         })
     }
 
+    pub fn config(&self) -> &RepoConfig {
+        &self.config
+    }
+
+    pub fn maintenance_schedule(&self) -> Result<MaintenanceSchedule, WillowError> {
+        self.store.read_maintenance()
+    }
+
+    pub fn set_maintenance_schedule(&self, schedule: &MaintenanceSchedule) -> Result<(), WillowError> {
+        self.store.write_maintenance(schedule)
+    }
+
+    /// Replace and persist the configuration. The default branch must
+    /// exist and the format version cannot change. A change of object
+    /// storage copies every object to the new place before switching.
+    pub fn set_config(&mut self, config: RepoConfig) -> Result<(), WillowError> {
+        if config.format_version != self.config.format_version {
+            return Err(WillowError::InvalidRepoConfig("format_version cannot be changed".to_string()));
+        }
+        if config.snapshot_interval == 0 {
+            return Err(WillowError::InvalidRepoConfig("snapshot_interval must be at least 1".to_string()));
+        }
+        if config.snapshot_keyframe_interval == 0 {
+            return Err(WillowError::InvalidRepoConfig("snapshot_keyframe_interval must be at least 1".to_string()));
+        }
+        let levels = zstd::compression_level_range();
+        if !levels.contains(&config.snapshot_compression_level) {
+            return Err(WillowError::InvalidRepoConfig(format!(
+                "snapshot_compression_level must be between {} and {}",
+                levels.start(),
+                levels.end()
+            )));
+        }
+        webhooks::validate(&config.webhooks)?;
+        if self.store.read_branch_ref(&config.default_branch)?.is_none() {
+            return Err(WillowError::BranchNotFound(config.default_branch));
+        }
+        if config.object_storage != self.config.object_storage {
+            let backend = object_backend(&self.repo_path, config.object_storage.as_ref())?;
+            self.store.copy_objects(backend.as_ref())?;
+            self.store.set_backend(backend);
+        }
+        self.store.set_snapshot_compression(config.snapshot_compression_level, config.snapshot_dictionary)?;
+        self.store.write_config(&config)?;
+        info!(snapshot_interval = config.snapshot_interval, default_branch = %config.default_branch, "repo config updated");
+        self.config = config;
+        Ok(())
+    }
+
+    /// Train a zstd dictionary of at most `max_size` bytes from the nodes and
+    /// links of the stored snapshots, compress new snapshots with it, and
+    /// recompress the existing ones. Returns the dictionary id, or `None`
+    /// when there was too little to train on.
+    pub fn train_snapshot_dictionary(&mut self, max_size: usize) -> Result<Option<u32>, WillowError> {
+        let snapshots = self.store.list_snapshots()?;
+        let sample_budget = max_size.saturating_mul(100);
+        let mut samples = Vec::new();
+        let mut sampled = 0;
+        'snapshots: for hash in &snapshots {
+            let graph = self.store.read_snapshot(hash)?;
+            let nodes = graph.nodes.values().map(serde_json::to_vec);
+            let links = graph.links.values().map(serde_json::to_vec);
+            for sample in nodes.chain(links) {
+                let sample = sample?;
+                sampled += sample.len();
+                samples.push(sample);
+                if sampled >= sample_budget {
+                    break 'snapshots;
+                }
+            }
+        }
+        let dictionary = match zstd::dict::from_samples(&samples, max_size) {
+            Ok(dictionary) => dictionary,
+            Err(e) => {
+                debug!(samples = samples.len(), error = %e, "too little snapshot data to train a dictionary");
+                return Ok(None);
+            }
+        };
+        let id = self.store.write_dictionary(&dictionary)?;
+        self.set_config(RepoConfig {
+            snapshot_dictionary: Some(id),
+            ..self.config.clone()
+        })?;
+        for hash in &snapshots {
+            self.store.recompress_snapshot(hash)?;
+        }
+        info!(id, size = dictionary.len(), samples = samples.len(), snapshots = snapshots.len(), "snapshot dictionary trained");
+        Ok(Some(id))
+    }
+
     /// Check if a repo exists at the given directory.
     pub fn exists(graph_dir: &Path) -> bool {
-        graph_dir.join("repo").exists()
+        files::exists(&graph_dir.join("repo"))
     }
 
     // ---- Internal helpers ----
 
-    fn head_hash(&self) -> Result<CommitHash, WillowError> {
+    fn cache(&self) -> MutexGuard<'_, GraphCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Drop all cached reconstructions. Must be called whenever objects are
+    /// removed from the store (gc, history truncation).
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    pub fn head_hash(&self) -> Result<CommitHash, WillowError> {
         self.store
             .resolve_head()?
             .ok_or(WillowError::VcsNotInitialized)
@@ -98,9 +260,10 @@ impl Repository {
 
     fn advance_head(&self, hash: &CommitHash) -> Result<(), WillowError> {
         match self.store.read_head()? {
-            HeadState::Branch(name) => self.store.write_branch_ref(&name, hash),
-            HeadState::Detached(_) => self.store.write_head(&HeadState::Detached(hash.clone())),
+            HeadState::Branch(name) => self.store.write_branch_ref(&name, hash)?,
+            HeadState::Detached(_) => self.store.write_head(&HeadState::Detached(hash.clone()))?,
         }
+        self.prune_tips()
     }
 
     fn write_snapshot_commit(
@@ -108,6 +271,7 @@ impl Repository {
         parents: Vec<CommitHash>,
         message: String,
         source: CommitSource,
+        stats: CommitStats,
         graph: &Graph,
     ) -> Result<CommitHash, WillowError> {
         let commit_data = CommitData {
@@ -117,13 +281,49 @@ impl Repository {
             source,
             storage_type: CommitStorageType::Snapshot,
             depth_since_snapshot: 0,
+            stats: Some(stats),
         };
         let hash = ObjectStore::hash_commit(&commit_data);
         self.store.write_commit(&hash, &commit_data)?;
-        self.store.write_snapshot(&hash, graph)?;
+        self.store_snapshot(&hash, commit_data.parents.first(), graph)?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::increment(crate::metrics::Metric::Commits);
         Ok(hash)
     }
 
+    /// Write the snapshot for commit `hash`: as a patch against the nearest
+    /// snapshot behind `parent` while keyframes are spaced out and the
+    /// chain of patches allows, otherwise as the full graph.
+    fn store_snapshot(&self, hash: &CommitHash, parent: Option<&CommitHash>, graph: &Graph) -> Result<(), WillowError> {
+        let keyframe_interval = self.config.snapshot_keyframe_interval;
+        if let (true, Some(parent)) = (keyframe_interval > 1, parent) {
+            let base = self.nearest_snapshot(parent)?;
+            let chain = self.store.snapshot_chain(&base)? + 1;
+            if chain < keyframe_interval {
+                let base_graph = match self.cache().get(&base) {
+                    Some(graph) => graph,
+                    None => self.store.read_snapshot(&base)?,
+                };
+                return self.store.write_snapshot_patch(hash, &SnapshotPatch::between(base, chain, &base_graph, graph));
+            }
+        }
+        self.store.write_snapshot(hash, graph)
+    }
+
+    /// The first snapshot commit on the first-parent chain from `hash`.
+    fn nearest_snapshot(&self, hash: &CommitHash) -> Result<CommitHash, WillowError> {
+        let mut current = hash.clone();
+        loop {
+            let data = self.store.read_commit(&current)?;
+            if data.storage_type == CommitStorageType::Snapshot {
+                return Ok(current);
+            }
+            current = data.parents.first().cloned().ok_or_else(|| {
+                WillowError::VcsCommitNotFound("No snapshot found in commit chain".to_string())
+            })?;
+        }
+    }
+
     fn read_parents(&self, h: &CommitHash) -> Vec<CommitHash> {
         self.store
             .read_commit(h)
@@ -155,6 +355,7 @@ impl Repository {
         message: String,
         graph: &Graph,
     ) -> Result<CommitHash, WillowError> {
+        let stats = compute_graph_diff(&self.reconstruct_at(&target_hash)?, graph).stats();
         let hash = self.write_snapshot_commit(
             vec![target_hash, source_hash],
             message,
@@ -162,22 +363,28 @@ impl Repository {
                 source_branch: source_branch.to_string(),
                 target_branch: current_branch_name.to_string(),
             },
+            stats,
             graph,
         )?;
         self.store.write_branch_ref(current_branch_name, &hash)?;
+        self.prune_tips()?;
         Ok(hash)
     }
 
     // ---- Commit operations ----
 
     /// Create a commit from pending changes. Returns the new commit hash.
+    /// Only maintenance commits may have none, to record a run that left
+    /// the graph's nodes and links alone. The changes are undone to rebuild
+    /// older commits, so they must carry positions and stamps as
+    /// `GraphStore` records them; see `apply_recorded`.
     pub fn create_commit(
         &self,
         input: &CommitInput,
         pending_changes: &[Change],
         current_graph: &Graph,
     ) -> Result<CommitHash, WillowError> {
-        if pending_changes.is_empty() {
+        if pending_changes.is_empty() && !matches!(input.source, CommitSource::Maintenance { .. }) {
             return Err(WillowError::NothingToCommit);
         }
 
@@ -199,42 +406,64 @@ impl Repository {
             source: input.source.clone(),
             storage_type,
             depth_since_snapshot: if is_snapshot { 0 } else { depth },
+            stats: Some(CommitStats::from_changes(pending_changes)),
         };
 
         let hash = ObjectStore::hash_commit(&commit_data);
         info!(message = %input.message, storage_type = ?storage_type, "commit created");
         self.store.write_commit(&hash, &commit_data)?;
 
+        // Deltas are kept for snapshot commits too, so backward
+        // reconstruction can walk through them.
+        self.store.write_delta(&hash, &Delta::new(pending_changes.to_vec()))?;
         if is_snapshot {
-            self.store.write_snapshot(&hash, current_graph)?;
+            self.store_snapshot(&hash, commit_data.parents.first(), current_graph)?;
         } else {
-            self.store.write_delta(
-                &hash,
-                &Delta {
-                    changes: pending_changes.to_vec(),
-                },
-            )?;
+            self.write_tip(&hash, &commit_data.parents[0], current_graph)?;
         }
 
         self.advance_head(&hash)?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::increment(crate::metrics::Metric::Commits);
         Ok(hash)
     }
 
-    /// Reconstruct graph at a specific commit by finding nearest snapshot and replaying deltas.
+    /// Reconstruct graph at a specific commit, either by replaying deltas forward
+    /// from the nearest snapshot or by undoing deltas backwards from a branch tip,
+    /// whichever touches fewer deltas. The result is cached.
     pub fn reconstruct_at(&self, target_hash: &CommitHash) -> Result<Graph, WillowError> {
+        if let Some(graph) = self.cache().get(target_hash) {
+            debug!(target = %target_hash.0, "reconstruction cache hit");
+            return Ok(graph);
+        }
+        #[cfg(feature = "metrics")]
+        let _metric = crate::metrics::time(crate::metrics::Metric::ReconstructDuration);
+
+        let forward_cost = self.store.read_commit(target_hash)?.depth_since_snapshot as usize;
+        let backward = match self.plan_backward(target_hash, forward_cost)? {
+            Some((base, undo)) => self.reconstruct_backward(target_hash, base, &undo)?,
+            None => None,
+        };
+        let graph = match backward {
+            Some(graph) => graph,
+            None => self.reconstruct_forward(target_hash)?,
+        };
+        self.cache().insert(target_hash.clone(), graph.clone());
+        Ok(graph)
+    }
+
+    /// Replay deltas forward from the nearest snapshot (or cached ancestor).
+    fn reconstruct_forward(&self, target_hash: &CommitHash) -> Result<Graph, WillowError> {
         let mut chain: Vec<CommitHash> = Vec::new();
         let mut current = target_hash.clone();
 
-        loop {
+        let mut graph = loop {
+            if let Some(graph) = self.cache().get(&current) {
+                break graph;
+            }
             let data = self.store.read_commit(&current)?;
             if data.storage_type == CommitStorageType::Snapshot {
-                let mut graph = self.store.read_snapshot(&current)?;
-                debug!(target = %target_hash.0, chain_len = chain.len(), "reconstructing graph");
-                for hash in chain.iter().rev() {
-                    let delta = self.store.read_delta(hash)?;
-                    apply_delta(&mut graph, &delta);
-                }
-                return Ok(graph);
+                break self.store.read_snapshot(&current)?;
             }
             chain.push(current.clone());
             if data.parents.is_empty() {
@@ -243,32 +472,258 @@ impl Repository {
                 ));
             }
             current = data.parents[0].clone();
+        };
+
+        debug!(target = %target_hash.0, chain_len = chain.len(), "reconstructing graph forward");
+        for hash in chain.iter().rev() {
+            let delta = self.store.read_delta(hash)?;
+            apply_delta(&mut graph, &delta);
         }
+        Ok(graph)
+    }
+
+    /// Find a tip whose first-parent chain reaches `target` by undoing fewer
+    /// than `max_cost` deltas. Returns the base to start from and the commits
+    /// whose deltas must be undone, newest first.
+    fn plan_backward(
+        &self,
+        target: &CommitHash,
+        max_cost: usize,
+    ) -> Result<Option<(ReconstructBase, Vec<CommitHash>)>, WillowError> {
+        let mut best: Option<(ReconstructBase, Vec<CommitHash>)> = None;
+        let mut best_cost = max_cost;
+
+        for tip in self.store.list_tip_snapshots()? {
+            let mut base = ReconstructBase::Tip(tip.clone());
+            let mut undo: Vec<CommitHash> = Vec::new();
+            let mut current = tip;
+
+            while undo.len() < best_cost {
+                if &current == target {
+                    best_cost = undo.len();
+                    best = Some((base, undo));
+                    break;
+                }
+                let Ok(data) = self.store.read_commit(&current) else { break };
+                let Some(parent) = data.parents.first().cloned() else { break };
+                if !matches!(self.store.has_delta(&current), Ok(true)) {
+                    break;
+                }
+                if data.storage_type == CommitStorageType::Snapshot {
+                    base = ReconstructBase::Snapshot(current.clone());
+                    undo.clear();
+                }
+                undo.push(current);
+                current = parent;
+            }
+        }
+        Ok(best)
+    }
+
+    /// Undo `undo`'s deltas from `base`. `None` if one of them predates
+    /// exact deltas, as undoing it could leave later state behind.
+    fn reconstruct_backward(
+        &self,
+        target_hash: &CommitHash,
+        base: ReconstructBase,
+        undo: &[CommitHash],
+    ) -> Result<Option<Graph>, WillowError> {
+        let mut deltas = Vec::with_capacity(undo.len());
+        for hash in undo {
+            let delta = self.store.read_delta(hash)?;
+            if !delta.exact {
+                debug!(target = %target_hash.0, commit = %hash.0, "delta not exactly invertible");
+                return Ok(None);
+            }
+            deltas.push(delta);
+        }
+        let mut graph = match &base {
+            ReconstructBase::Tip(hash) => self.read_tip(hash)?,
+            ReconstructBase::Snapshot(hash) => self.store.read_snapshot(hash)?,
+        };
+        debug!(target = %target_hash.0, chain_len = undo.len(), "reconstructing graph backward");
+        for delta in &deltas {
+            apply_delta(&mut graph, &invert_delta(delta));
+        }
+        Ok(Some(graph))
+    }
+
+    /// Keep the graph at `hash`, a new branch head, for backward
+    /// reconstruction: as a patch against the snapshot its deltas build on.
+    fn write_tip(&self, hash: &CommitHash, parent: &CommitHash, graph: &Graph) -> Result<(), WillowError> {
+        let base = self.nearest_snapshot(parent)?;
+        let base_graph = match self.cache().get(&base) {
+            Some(graph) => graph,
+            None => self.store.read_snapshot(&base)?,
+        };
+        let chain = self.store.snapshot_chain(&base)? + 1;
+        self.store.write_tip_snapshot(hash, &TipSnapshot::Patch(SnapshotPatch::between(base, chain, &base_graph, graph)))
+    }
+
+    fn read_tip(&self, hash: &CommitHash) -> Result<Graph, WillowError> {
+        match self.store.read_tip_snapshot(hash)? {
+            TipSnapshot::Full(graph) => Ok(graph),
+            TipSnapshot::Patch(patch) => {
+                let mut graph = match self.cache().get(&patch.base) {
+                    Some(graph) => graph,
+                    None => self.store.read_snapshot(&patch.base)?,
+                };
+                patch.apply(&mut graph);
+                graph.intern_ids();
+                Ok(graph)
+            }
+        }
+    }
+
+    /// Remove tip snapshots for commits that are no longer referenced by a
+    /// branch or a detached HEAD.
+    fn prune_tips(&self) -> Result<(), WillowError> {
+        let live = self.live_refs()?;
+        for tip in self.store.list_tip_snapshots()? {
+            if !live.contains(&tip) {
+                self.store.delete_tip_snapshot(&tip)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The commits branches and a detached HEAD point at.
+    fn live_refs(&self) -> Result<Vec<CommitHash>, WillowError> {
+        let mut live: Vec<CommitHash> = Vec::new();
+        for name in self.store.list_branches()? {
+            if let Some(hash) = self.store.read_branch_ref(&name)? {
+                live.push(hash);
+            }
+        }
+        if let HeadState::Detached(hash) = self.store.read_head()? {
+            live.push(hash);
+        }
+        Ok(live)
+    }
+
+    /// Delete the commits no branch or detached HEAD reaches, such as those
+    /// left by deleted branches, with their deltas and snapshots. Snapshots
+    /// that reachable patches build on are kept. Returns the number of
+    /// commits deleted.
+    pub fn gc(&self) -> Result<usize, WillowError> {
+        let mut reachable: HashSet<CommitHash> = HashSet::new();
+        let mut stack = self.live_refs()?;
+        while let Some(hash) = stack.pop() {
+            if reachable.contains(&hash) {
+                continue;
+            }
+            stack.extend(self.store.read_commit(&hash)?.parents);
+            reachable.insert(hash);
+        }
+        for snapshot in self.store.list_snapshots()? {
+            let mut current = snapshot;
+            while reachable.contains(&current) {
+                let Some(patch) = self.store.read_snapshot_patch(&current)? else { break };
+                current = patch.base;
+                reachable.insert(current.clone());
+            }
+        }
+
+        let dead: Vec<CommitHash> = self
+            .store
+            .list_commits()?
+            .into_iter()
+            .filter(|hash| !reachable.contains(hash))
+            .collect();
+        for hash in &dead {
+            self.store.delete_commit_objects(hash)?;
+        }
+        if !dead.is_empty() {
+            self.store.rebuild_commit_index()?;
+            self.clear_cache();
+        }
+        self.prune_tips()?;
+        info!(deleted = dead.len(), "repository gc");
+        Ok(dead.len())
     }
 
     /// Get commit log (most recent first).
     pub fn log(&self, limit: Option<usize>) -> Result<Vec<CommitEntry>, WillowError> {
-        let Some(head) = self.store.resolve_head()? else {
-            return Ok(Vec::new());
-        };
+        let mut cursor = self.log_cursor()?;
+        self.log_batch(&mut cursor, limit.unwrap_or(50))
+    }
 
-        let max = limit.unwrap_or(50);
-        let mut entries = Vec::new();
-        let mut current = Some(head);
+    /// A cursor at HEAD for reading the log a batch at a time.
+    pub fn log_cursor(&self) -> Result<LogCursor, WillowError> {
+        Ok(LogCursor {
+            next: self.store.resolve_head()?,
+        })
+    }
 
-        while let Some(hash) = current {
-            if entries.len() >= max {
+    /// Up to `limit` more log entries from `cursor`, following first
+    /// parents, and advance it past them. Empty once the root commit has
+    /// been read.
+    pub fn log_batch(&self, cursor: &mut LogCursor, limit: usize) -> Result<Vec<CommitEntry>, WillowError> {
+        let mut entries = Vec::new();
+        while entries.len() < limit {
+            let Some(hash) = cursor.next.take() else {
                 break;
-            }
+            };
             let data = self.store.read_commit(&hash)?;
-            let parent = data.parents.first().cloned();
+            cursor.next = data.parents.first().cloned();
             entries.push(CommitEntry { hash, data });
-            current = parent;
         }
-
         Ok(entries)
     }
 
+    /// Search past commits for content that no longer exists at HEAD: nodes
+    /// since deleted, or whose matching content has since been edited. Each
+    /// node and content pair is reported once, from the newest commit that
+    /// had it. Best first.
+    pub fn search_history(
+        &self,
+        query: &str,
+        options: &HistorySearchOptions,
+        progress: &mut Progress,
+    ) -> Result<Vec<HistoryMatch>, WillowError> {
+        let entries = self.log(Some(options.max_commits.unwrap_or(50)))?;
+        let Some(head) = entries.first() else {
+            return Ok(Vec::new());
+        };
+        let head_graph = self.reconstruct_at(&head.hash)?;
+
+        let mut matches: Vec<HistoryMatch> = Vec::new();
+        let mut seen: HashSet<(NodeId, String)> = HashSet::new();
+        for (i, entry) in entries.iter().enumerate().skip(1) {
+            progress.step("search_history", i - 1, Some(entries.len() - 1))?;
+            let graph = self.reconstruct_at(&entry.hash)?;
+            let roots: Vec<&NodeId> = std::iter::once(&graph.root_id).chain(graph.roots.values()).collect();
+            let mut results: HashMap<NodeId, search::SearchResult> = HashMap::new();
+            for root in roots {
+                for result in search::search_nodes(&graph, query, Some(root), &options.search) {
+                    results.entry(result.node_id.clone()).or_insert(result);
+                }
+            }
+            let mut results: Vec<search::SearchResult> = results.into_values().collect();
+            results.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+            for result in results {
+                let current = head_graph.nodes.get(&result.node_id);
+                if current.is_some_and(|n| n.content == result.content)
+                    || !seen.insert((result.node_id.clone(), result.content.clone()))
+                {
+                    continue;
+                }
+                matches.push(HistoryMatch {
+                    deleted: current.is_none(),
+                    node_id: result.node_id,
+                    content: result.content,
+                    score: result.score,
+                    matched_field: result.matched_field,
+                    commit: entry.clone(),
+                });
+            }
+        }
+        // Stable, so equal scores keep newest commits first.
+        matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        debug!(query = %query, commits = entries.len(), matches = matches.len(), "history search complete");
+        Ok(matches)
+    }
+
     /// Show diff for a specific commit (compare with parent).
     pub fn show_commit(
         &self,
@@ -297,6 +752,16 @@ impl Repository {
         Ok(compute_graph_diff(&from_graph, &to_graph))
     }
 
+    /// Diff the heads of two branches.
+    pub fn diff_branches(&self, from_branch: &str, to_branch: &str) -> Result<ChangeSummary, WillowError> {
+        let head = |name: &str| {
+            self.store
+                .read_branch_ref(name)?
+                .ok_or_else(|| WillowError::BranchNotFound(name.to_string()))
+        };
+        self.diff(&head(from_branch)?, &head(to_branch)?)
+    }
+
     /// Create a snapshot commit if the current graph differs from HEAD.
     /// Used when changes were made externally (e.g. by a subprocess) and
     /// in-memory pending_changes are not available.
@@ -317,12 +782,40 @@ impl Repository {
             vec![head_hash],
             input.message.clone(),
             input.source.clone(),
+            diff.stats(),
             current_graph,
         )?;
         self.advance_head(&hash)?;
         Ok(Some(hash))
     }
 
+    /// Attachment hashes referenced by any commit reachable from a branch or HEAD.
+    pub fn referenced_attachments(&self, progress: &mut Progress) -> Result<HashSet<String>, WillowError> {
+        let mut stack: Vec<CommitHash> = Vec::new();
+        for branch in self.store.list_branches()? {
+            stack.extend(self.store.read_branch_ref(&branch)?);
+        }
+        stack.extend(self.store.resolve_head()?);
+
+        let mut seen = HashSet::new();
+        let mut refs = HashSet::new();
+        while let Some(hash) = stack.pop() {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            progress.step("scan_history", seen.len() - 1, None)?;
+            let data = self.store.read_commit(&hash)?;
+            // Every attachment enters history through a change or a snapshot.
+            if self.store.has_delta(&hash)? {
+                attachments::collect_change_refs(&self.store.read_delta(&hash)?.changes, &mut refs);
+            } else {
+                attachments::collect_graph_refs(&self.store.read_snapshot(&hash)?, &mut refs);
+            }
+            stack.extend(data.parents);
+        }
+        Ok(refs)
+    }
+
     // ---- Branch operations ----
 
     /// Get current branch name (None if detached).
@@ -403,7 +896,7 @@ impl Repository {
         }
 
         self.store.delete_branch_ref(name)?;
-        Ok(())
+        self.prune_tips()
     }
 
     /// Checkout a specific commit (detached HEAD). Returns reconstructed graph.
@@ -431,6 +924,7 @@ impl Repository {
     ) -> Result<(CommitHash, Graph), WillowError> {
         let target_graph = self.reconstruct_at(hash)?;
         let head_hash = self.head_hash()?;
+        let stats = compute_graph_diff(&self.reconstruct_at(&head_hash)?, &target_graph).stats();
 
         let new_hash = self.write_snapshot_commit(
             vec![head_hash],
@@ -438,6 +932,7 @@ impl Repository {
             CommitSource::Manual {
                 tool_name: Some("restore".to_string()),
             },
+            stats,
             &target_graph,
         )?;
         self.advance_head(&new_hash)?;
@@ -462,6 +957,7 @@ impl Repository {
         if is_ancestor(&target_hash, &source_hash, &read_parents) {
             self.store
                 .write_branch_ref(&current_branch_name, &source_hash)?;
+            self.prune_tips()?;
             let graph = self.reconstruct_at(&source_hash)?;
             return Ok(MergeBranchResult::Success(source_hash, graph));
         }
@@ -526,6 +1022,12 @@ impl Repository {
     }
 }
 
+/// Starting point for backward reconstruction.
+enum ReconstructBase {
+    Tip(CommitHash),
+    Snapshot(CommitHash),
+}
+
 #[derive(Debug)]
 pub enum MergeBranchResult {
     Success(CommitHash, Graph),
@@ -538,13 +1040,16 @@ pub enum MergeBranchResult {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
     use crate::model::*;
-    use std::collections::HashMap;
+    use crate::progress::{CancelToken, ProgressUpdate};
+    use crate::store::{GraphStore, OpenOptions};
+    use std::collections::{BTreeMap, HashMap};
     use tempfile::TempDir;
 
     fn test_graph() -> Graph {
-        let root_id = NodeId("root".to_string());
-        let mut nodes = HashMap::new();
+        let root_id = NodeId("root".into());
+        let mut nodes: HashMap<NodeId, Arc<Node>> = HashMap::new();
         let now = Utc::now();
         nodes.insert(
             root_id.clone(),
@@ -554,43 +1059,64 @@ mod tests {
                 content: "User".to_string(),
                 parent_id: None,
                 children: Vec::new(),
+                extra_parents: Vec::new(),
+                pinned: false,
+                sensitivity: Sensitivity::Normal,
+                archived: false,
+                display: None,
+                content_format: ContentFormat::Text,
+                attachments: Vec::new(),
                 metadata: HashMap::new(),
                 previous_values: Vec::new(),
                 temporal: None,
                 created_at: now,
                 updated_at: now,
-            },
+                created_by: None,
+                updated_by: None,
+            }.into(),
         );
         Graph {
             root_id,
             nodes,
             links: HashMap::new(),
+            roots: BTreeMap::new(),
+            rules: Vec::new(),
         }
     }
 
     fn test_node(id: &str, content: &str) -> Node {
         let now = Utc::now();
         Node {
-            id: NodeId(id.to_string()),
+            id: NodeId(id.into()),
             node_type: NodeType::Detail,
             content: content.to_string(),
-            parent_id: Some(NodeId("root".to_string())),
+            parent_id: Some(NodeId("root".into())),
             children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
             metadata: HashMap::new(),
             previous_values: Vec::new(),
             temporal: None,
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
         }
     }
 
     fn add_node_to_graph(graph: &mut Graph, id: &str, content: &str) -> Node {
-        let nid = NodeId(id.to_string());
+        let nid = NodeId(id.into());
         let node = test_node(id, content);
-        graph.nodes.insert(nid.clone(), node.clone());
+        graph.nodes.insert(nid.clone(), node.clone().into());
         graph
             .nodes
-            .get_mut(&NodeId("root".to_string()))
+            .get_mut(&NodeId("root".into()))
+            .map(Arc::make_mut)
             .unwrap()
             .children
             .push(nid);
@@ -612,12 +1138,14 @@ mod tests {
         message: &str,
     ) -> CommitHash {
         let node = add_node_to_graph(graph, id, content);
-        let nid = NodeId(id.to_string());
+        let nid = NodeId(id.into());
         repo.create_commit(
             &commit_input(message),
             &[Change::CreateNode {
                 node_id: nid,
-                node,
+                node: node.into(),
+                positions: Vec::new(),
+                actor: None,
             }],
             graph,
         )
@@ -663,7 +1191,7 @@ mod tests {
     #[test]
     fn test_reconstruct() {
         let (_dir, repo, mut graph) = init_repo();
-        let nid = NodeId("n1".to_string());
+        let nid = NodeId("n1".into());
         let hash = commit_node(&repo, &mut graph, "n1", "Reconstructed", "Test");
 
         let reconstructed = repo.reconstruct_at(&hash).unwrap();
@@ -671,6 +1199,226 @@ mod tests {
         assert_eq!(reconstructed.nodes.get(&nid).unwrap().content, "Reconstructed");
     }
 
+    #[test]
+    fn test_commit_stats_cached_in_log() {
+        let (_dir, repo, mut graph) = init_repo();
+        commit_node(&repo, &mut graph, "n1", "Stats node", "Add node");
+
+        let log = repo.log(None).unwrap();
+        let stats = log[0].data.stats.unwrap();
+        assert_eq!(stats.nodes_created, 1);
+        assert_eq!(stats.nodes_updated, 0);
+        assert_eq!(log[1].data.stats.unwrap().nodes_created, 1); // root
+    }
+
+    #[test]
+    fn test_commit_stats_net_changes() {
+        let node = test_node("n1", "Short-lived");
+        let changes = vec![
+            Change::CreateNode { node_id: node.id.clone(), node: node.clone().into(), positions: Vec::new(), actor: None },
+            Change::UpdateNode {
+                node_id: node.id.clone(),
+                old_content: Some("Short-lived".to_string()),
+                new_content: Some("Edited".to_string()),
+                old_metadata: None,
+                new_metadata: None,
+                actor: None,
+            },
+            Change::UpdateNode {
+                node_id: NodeId("existing".into()),
+                old_content: Some("a".to_string()),
+                new_content: Some("b".to_string()),
+                old_metadata: None,
+                new_metadata: None,
+                actor: None,
+            },
+        ];
+        let stats = CommitStats::from_changes(&changes);
+        assert_eq!(stats.nodes_created, 1);
+        assert_eq!(stats.nodes_updated, 1);
+
+        let mut with_delete = changes.clone();
+        with_delete.push(Change::DeleteNode {
+            node_id: node.id.clone(),
+            deleted_nodes: vec![node],
+            deleted_links: vec![],
+            positions: Vec::new(),
+            actor: None,
+        });
+        let stats = CommitStats::from_changes(&with_delete);
+        assert_eq!(stats.nodes_created, 0);
+        assert_eq!(stats.nodes_deleted, 0);
+        assert_eq!(stats.nodes_updated, 1);
+    }
+
+    #[test]
+    fn test_reconstruct_uses_cached_ancestor() {
+        let (_dir, repo, mut graph) = init_repo();
+        let first = commit_node(&repo, &mut graph, "n1", "First", "First");
+        repo.reconstruct_at(&first).unwrap();
+        let second = commit_node(&repo, &mut graph, "n2", "Second", "Second");
+
+        let reconstructed = repo.reconstruct_at(&second).unwrap();
+        assert_eq!(reconstructed.nodes.len(), 3);
+        assert!(repo.cache().contains(&second));
+
+        repo.clear_cache();
+        assert!(repo.cache().is_empty());
+        assert_eq!(repo.reconstruct_at(&second).unwrap().nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_search_history_finds_deleted_and_edited_content() {
+        let (_dir, repo, mut graph) = init_repo();
+        commit_node(&repo, &mut graph, "n1", "Dinner at Chez Panisse", "Add dinner");
+        commit_node(&repo, &mut graph, "n2", "Lunch at Zuni Cafe", "Add lunch");
+
+        let n1 = NodeId("n1".into());
+        let old_node = Arc::unwrap_or_clone(graph.nodes.remove(&n1).unwrap());
+        graph.nodes.get_mut(&NodeId("root".into())).map(Arc::make_mut).unwrap().children.retain(|c| c != &n1);
+        let n2 = NodeId("n2".into());
+        let old_content = graph.nodes[&n2].content.clone();
+        graph.nodes.get_mut(&n2).map(Arc::make_mut).unwrap().content = "Lunch somewhere".to_string();
+        repo.create_commit(
+            &commit_input("Forget restaurants"),
+            &[
+                Change::DeleteNode {
+                    node_id: n1.clone(),
+                    deleted_nodes: vec![old_node],
+                    deleted_links: vec![],
+                    positions: Vec::new(),
+                    actor: None,
+                },
+                Change::UpdateNode {
+                    node_id: n2.clone(),
+                    old_content: Some(old_content),
+                    new_content: Some("Lunch somewhere".to_string()),
+                    old_metadata: None,
+                    new_metadata: None,
+                    actor: None,
+                },
+            ],
+            &graph,
+        )
+        .unwrap();
+
+        let options = HistorySearchOptions::default();
+        let found = repo.search_history("panisse", &options, &mut Progress::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].deleted);
+        assert_eq!(found[0].commit.data.message, "Add lunch");
+
+        let found = repo.search_history("zuni", &options, &mut Progress::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].deleted);
+        assert_eq!(found[0].content, "Lunch at Zuni Cafe");
+
+        assert!(repo.search_history("somewhere", &options, &mut Progress::default()).unwrap().is_empty());
+
+        let cancel = CancelToken::default();
+        let mut steps = Vec::new();
+        let mut progress = Progress::new(
+            Some(Box::new(|update: &ProgressUpdate| {
+                steps.push((update.done, update.total));
+                cancel.cancel();
+            })),
+            Some(cancel.clone()),
+        );
+        assert!(matches!(
+            repo.search_history("zuni", &options, &mut progress),
+            Err(WillowError::Cancelled)
+        ));
+        drop(progress);
+        assert_eq!(steps, [(0, Some(3))]);
+    }
+
+    #[test]
+    fn test_backward_reconstruction_matches_forward() {
+        let dir = TempDir::new().unwrap();
+        let mut store = GraphStore::open(&dir.path().join("graph.json"), &OpenOptions::default()).unwrap();
+        store.vcs_init().unwrap();
+        let mut hashes = Vec::new();
+        let mut commit = |store: &mut GraphStore, message: &str| {
+            hashes.push(store.commit(commit_input(message)).unwrap());
+        };
+
+        let a = store.create_node("root", "category", "A", None, None).unwrap().id.0;
+        let b = store.create_node("root", "category", "B", None, None).unwrap().id.0;
+        let c = store.create_node("root", "detail", "C", None, None).unwrap().id.0;
+        let d = store.create_node(&b, "detail", "D", None, None).unwrap().id.0;
+        commit(&mut store, "Create");
+        store.update_node(&a, Some("A2"), None, None, None).unwrap();
+        let meta = HashMap::from([("k".to_string(), "v".to_string())]);
+        store.update_node(&a, Some("A3"), Some(meta), None, None).unwrap();
+        store.update_node(&c, Some("C2"), None, None, None).unwrap();
+        commit(&mut store, "Update");
+        store.add_parent(&c, &a).unwrap();
+        store.add_link(&d, &c, "related_to", false, None).unwrap();
+        commit(&mut store, "Share and link");
+        store.delete_node(&b).unwrap();
+        store.update_node(&a, Some("A4"), None, None, None).unwrap();
+        commit(&mut store, "Delete middle child");
+        store.remove_parent(&c, "root").unwrap();
+        store.delete_node(&a).unwrap();
+        commit(&mut store, "Delete last parent");
+
+        let repo = store.get_repo().unwrap();
+        for hash in &hashes[..hashes.len() - 1] {
+            repo.clear_cache();
+            let forward = repo.reconstruct_forward(hash).unwrap();
+            let (base, undo) = repo.plan_backward(hash, usize::MAX).unwrap().unwrap();
+            assert!(matches!(base, ReconstructBase::Tip(_)));
+            let backward = repo.reconstruct_backward(hash, base, &undo).unwrap().unwrap();
+            assert_eq!(
+                serde_json::to_value(&backward).unwrap(),
+                serde_json::to_value(&forward).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_backward_reconstruction_skips_inexact_deltas() {
+        let (_dir, repo, mut graph) = init_repo();
+        let first = commit_node(&repo, &mut graph, "n1", "First", "First");
+        let second = commit_node(&repo, &mut graph, "n2", "Second", "Second");
+        let mut legacy = repo.store.read_delta(&second).unwrap();
+        legacy.exact = false;
+        repo.store.write_delta(&second, &legacy).unwrap();
+
+        let (base, undo) = repo.plan_backward(&first, usize::MAX).unwrap().unwrap();
+        assert!(repo.reconstruct_backward(&first, base, &undo).unwrap().is_none());
+        repo.clear_cache();
+        assert_eq!(repo.reconstruct_at(&first).unwrap().nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_tip_snapshots_follow_branch_heads() {
+        let (_dir, repo, mut graph) = init_repo();
+        let first = commit_node(&repo, &mut graph, "n1", "First", "First");
+        assert_eq!(repo.store.list_tip_snapshots().unwrap(), vec![first]);
+
+        let second = commit_node(&repo, &mut graph, "n2", "Second", "Second");
+        assert_eq!(repo.store.list_tip_snapshots().unwrap(), vec![second]);
+    }
+
+    #[test]
+    fn test_gc_deletes_commits_of_deleted_branches() {
+        let (_dir, repo, mut graph) = init_repo();
+        let kept = commit_node(&repo, &mut graph, "n1", "First", "First");
+        repo.create_branch("experiment").unwrap();
+        repo.switch_branch("experiment", false).unwrap();
+        let mut side = graph.clone();
+        let dropped = commit_node(&repo, &mut side, "n2", "Side", "Side");
+        repo.switch_branch("main", false).unwrap();
+        assert_eq!(repo.gc().unwrap(), 0);
+
+        repo.delete_branch("experiment").unwrap();
+        assert_eq!(repo.gc().unwrap(), 1);
+        assert!(matches!(repo.reconstruct_at(&dropped), Err(WillowError::VcsCommitNotFound(_))));
+        assert!(repo.reconstruct_at(&kept).unwrap().nodes.contains_key(&NodeId("n1".into())));
+        assert_eq!(repo.log(None).unwrap().len(), 2);
+    }
+
     #[test]
     fn test_show_commit() {
         let (_dir, repo, mut graph) = init_repo();
@@ -696,6 +1444,136 @@ mod tests {
         assert!(repo.delete_branch("main").is_err());
     }
 
+    #[test]
+    fn test_diff_branches() {
+        let (_dir, repo, mut graph) = init_repo();
+        repo.create_branch("experiment").unwrap();
+        commit_node(&repo, &mut graph, "on-main", "Main branch node", "Main commit");
+
+        let diff = repo.diff_branches("experiment", "main").unwrap();
+        assert_eq!(diff.nodes_created.len(), 1);
+        assert!(repo.diff_branches("main", "missing").is_err());
+    }
+
+    #[test]
+    fn test_set_config_persists() {
+        let (dir, mut repo, _graph) = init_repo();
+        repo.create_branch("stable").unwrap();
+        let config = RepoConfig {
+            snapshot_interval: 10,
+            default_branch: "stable".to_string(),
+            ..repo.config().clone()
+        };
+        repo.set_config(config).unwrap();
+        assert!(repo.set_config(RepoConfig { snapshot_interval: 0, ..repo.config().clone() }).is_err());
+
+        assert!(repo.set_config(RepoConfig { snapshot_compression_level: 100, ..repo.config().clone() }).is_err());
+
+        let reopened = Repository::open(dir.path()).unwrap();
+        assert_eq!(reopened.config().snapshot_interval, 10);
+        assert_eq!(reopened.config().default_branch, "stable");
+        assert!(matches!(
+            reopened.delete_branch("stable"),
+            Err(WillowError::CannotDeleteDefaultBranch(_))
+        ));
+    }
+
+    #[test]
+    fn test_train_snapshot_dictionary() {
+        let (dir, mut repo, mut graph) = init_repo();
+        repo.set_config(RepoConfig { snapshot_interval: 2, ..repo.config().clone() }).unwrap();
+        for i in 0..300 {
+            add_node_to_graph(&mut graph, &format!("n{i}"), &format!("Note {i} about the weekly planning meeting"));
+        }
+        commit_node(&repo, &mut graph, "a", "First", "First");
+        let snapshot = commit_node(&repo, &mut graph, "b", "Second", "Second");
+        let before = serde_json::to_string(&repo.store.read_snapshot(&snapshot).unwrap()).unwrap();
+
+        let id = repo.train_snapshot_dictionary(16 * 1024).unwrap().expect("dictionary trained");
+        assert_eq!(repo.config().snapshot_dictionary, Some(id));
+        let after = serde_json::to_string(&repo.store.read_snapshot(&snapshot).unwrap()).unwrap();
+        assert_eq!(after, before);
+
+        let reopened = Repository::open(dir.path()).unwrap();
+        commit_node(&reopened, &mut graph, "c", "Third", "Third");
+        let head = commit_node(&reopened, &mut graph, "d", "Fourth", "Fourth");
+        reopened.clear_cache();
+        assert_eq!(reopened.reconstruct_at(&head).unwrap().nodes.len(), graph.nodes.len());
+    }
+
+    #[test]
+    fn test_snapshot_patches_between_keyframes() {
+        let (dir, mut repo, mut graph) = init_repo();
+        repo.set_config(RepoConfig {
+            snapshot_interval: 1,
+            snapshot_keyframe_interval: 3,
+            ..repo.config().clone()
+        })
+        .unwrap();
+        let first = commit_node(&repo, &mut graph, "a", "First", "First");
+        let second = commit_node(&repo, &mut graph, "b", "Second", "Second");
+        graph.nodes.remove(&NodeId("a".into()));
+        let third = repo
+            .create_commit(
+                &commit_input("Third"),
+                &[Change::DeleteNode {
+                    node_id: NodeId("a".into()),
+                    deleted_nodes: vec![],
+                    deleted_links: vec![],
+                    positions: Vec::new(),
+                    actor: None,
+                }],
+                &graph,
+            )
+            .unwrap();
+        assert_eq!(repo.store.snapshot_chain(&first).unwrap(), 1);
+        assert_eq!(repo.store.snapshot_chain(&second).unwrap(), 2);
+        assert_eq!(repo.store.snapshot_chain(&third).unwrap(), 0);
+        assert!(!dir.path().join("repo/objects/snapshots").join(&second.0).exists());
+
+        let reopened = Repository::open(dir.path()).unwrap();
+        let at_second = reopened.reconstruct_at(&second).unwrap();
+        assert!(at_second.nodes.contains_key(&NodeId("a".into())));
+        assert!(at_second.nodes.contains_key(&NodeId("b".into())));
+        assert!(!reopened.reconstruct_at(&third).unwrap().nodes.contains_key(&NodeId("a".into())));
+    }
+
+    #[test]
+    fn test_reconstructed_graphs_share_unchanged_nodes() {
+        let (_dir, repo, mut graph) = init_repo();
+        commit_node(&repo, &mut graph, "n1", "First", "First");
+        let second = commit_node(&repo, &mut graph, "n2", "Second", "Second");
+
+        let after = repo.reconstruct_at(&second).unwrap();
+        let cached = repo.reconstruct_at(&second).unwrap();
+        let n1 = NodeId("n1".into());
+        assert!(Arc::ptr_eq(&after.nodes[&n1], &cached.nodes[&n1]));
+
+        let root = NodeId("root".into());
+        let mut edited = after.clone();
+        edited.nodes.get_mut(&root).map(Arc::make_mut).unwrap().content = "Edited".to_string();
+        assert_eq!(after.nodes[&root].content, "User");
+        assert!(Arc::ptr_eq(&edited.nodes[&n1], &after.nodes[&n1]));
+    }
+
+    #[test]
+    fn test_log_batches_walk_whole_history() {
+        let (_dir, repo, mut graph) = init_repo();
+        for i in 0..4 {
+            commit_node(&repo, &mut graph, &format!("n{i}"), "Node", &format!("Commit {i}"));
+        }
+
+        let mut cursor = repo.log_cursor().unwrap();
+        let mut messages = Vec::new();
+        while !cursor.is_done() {
+            let batch = repo.log_batch(&mut cursor, 2).unwrap();
+            assert!(batch.len() <= 2);
+            messages.extend(batch.into_iter().map(|e| e.data.message));
+        }
+        assert_eq!(messages, ["Commit 3", "Commit 2", "Commit 1", "Commit 0", "Initial snapshot"]);
+        assert!(repo.log_batch(&mut cursor, 2).unwrap().is_empty());
+    }
+
     #[test]
     fn test_switch_branch() {
         let (_dir, repo, mut graph) = init_repo();
@@ -704,10 +1582,10 @@ mod tests {
         commit_node(&repo, &mut graph, "on-main", "Main branch node", "Main commit");
 
         let exp_graph = repo.switch_branch("experiment", false).unwrap();
-        assert!(!exp_graph.nodes.contains_key(&NodeId("on-main".to_string())));
+        assert!(!exp_graph.nodes.contains_key(&NodeId("on-main".into())));
 
         let main_graph = repo.switch_branch("main", false).unwrap();
-        assert!(main_graph.nodes.contains_key(&NodeId("on-main".to_string())));
+        assert!(main_graph.nodes.contains_key(&NodeId("on-main".into())));
     }
 
     #[test]
@@ -737,7 +1615,7 @@ mod tests {
 
         match result {
             MergeBranchResult::Success(_, merged) => {
-                assert!(merged.nodes.contains_key(&NodeId("feat-node".to_string())));
+                assert!(merged.nodes.contains_key(&NodeId("feat-node".into())));
             }
             _ => panic!("Expected fast-forward success"),
         }
@@ -754,7 +1632,7 @@ mod tests {
 
         let (_restore_hash, restored_graph) =
             repo.restore_to_commit(&initial_hash, &graph).unwrap();
-        assert!(!restored_graph.nodes.contains_key(&NodeId("n1".to_string())));
+        assert!(!restored_graph.nodes.contains_key(&NodeId("n1".into())));
 
         let log = repo.log(None).unwrap();
         assert_eq!(log.len(), 3);
@@ -1,7 +1,13 @@
-use crate::model::{Graph, Link, LinkId, Node, NodeId};
+use crate::model::{
+    AttachmentRef, DisplayHints, Graph, Link, LinkId, Node, NodeId, Sensitivity, SupersededValue, TemporalMetadata,
+};
+use crate::rules::Rule;
+use crate::vcs::s3::S3Config;
+use crate::webhooks::Webhook;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CommitHash(pub String);
@@ -37,6 +43,127 @@ pub enum CommitStorageType {
     Delta,
 }
 
+/// Change counts cached on each commit so `log` can show change sizes
+/// without reconstructing graphs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitStats {
+    pub nodes_created: u32,
+    pub nodes_updated: u32,
+    pub nodes_deleted: u32,
+    pub links_created: u32,
+    pub links_removed: u32,
+    pub links_updated: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum NetChange {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// Fold one change into the net per-key state, so that e.g. a node created
+/// and deleted within the same commit is not counted at all.
+fn fold_net_change<K: Eq + std::hash::Hash>(
+    state: &mut HashMap<K, NetChange>,
+    key: K,
+    change: NetChange,
+) {
+    let next = match (state.get(&key).copied(), change) {
+        (Some(NetChange::Created), NetChange::Deleted) => {
+            state.remove(&key);
+            return;
+        }
+        (Some(NetChange::Created), _) => NetChange::Created,
+        (Some(NetChange::Deleted), NetChange::Created) => NetChange::Updated,
+        (_, c) => c,
+    };
+    state.insert(key, next);
+}
+
+fn count_net(state: &HashMap<impl Eq + std::hash::Hash, NetChange>, kind: NetChange) -> u32 {
+    state.values().filter(|c| **c == kind).count() as u32
+}
+
+impl CommitStats {
+    /// Compute net change counts from a list of recorded changes.
+    /// Structural changes (reparenting, reordering, parent membership) and
+    /// node stamps are not counted.
+    pub fn from_changes(changes: &[Change]) -> Self {
+        let mut nodes: HashMap<&NodeId, NetChange> = HashMap::new();
+        let mut links: HashMap<&LinkId, NetChange> = HashMap::new();
+
+        for change in changes {
+            match change {
+                Change::CreateNode { node_id, .. } => {
+                    fold_net_change(&mut nodes, node_id, NetChange::Created)
+                }
+                Change::UpdateNode { node_id, .. }
+                | Change::SetTemporal { node_id, .. }
+                | Change::SetPinned { node_id, .. }
+                | Change::SetSensitivity { node_id, .. }
+                | Change::SetArchived { node_id, .. }
+                | Change::SetDisplay { node_id, .. }
+                | Change::AttachBlob { node_id, .. }
+                | Change::DetachBlob { node_id, .. } => {
+                    fold_net_change(&mut nodes, node_id, NetChange::Updated)
+                }
+                Change::DeleteNode {
+                    node_id,
+                    deleted_nodes,
+                    deleted_links,
+                    ..
+                } => {
+                    let mut ids: Vec<&NodeId> = deleted_nodes.iter().map(|n| &n.id).collect();
+                    if !ids.contains(&node_id) {
+                        ids.push(node_id);
+                    }
+                    for id in ids {
+                        fold_net_change(&mut nodes, id, NetChange::Deleted);
+                    }
+                    for link in deleted_links {
+                        fold_net_change(&mut links, &link.id, NetChange::Deleted);
+                    }
+                }
+                Change::AddLink { link_id, .. } => {
+                    fold_net_change(&mut links, link_id, NetChange::Created)
+                }
+                Change::RemoveLink { link_id, .. } => {
+                    fold_net_change(&mut links, link_id, NetChange::Deleted)
+                }
+                Change::UpdateLink { link_id, .. } => {
+                    fold_net_change(&mut links, link_id, NetChange::Updated)
+                }
+                Change::ReparentNode { .. }
+                | Change::ReorderChildren { .. }
+                | Change::AddParent { .. }
+                | Change::RemoveParent { .. }
+                | Change::SetProfile { .. }
+                | Change::SetRules { .. }
+                | Change::StampNode { .. } => {}
+            }
+        }
+
+        CommitStats {
+            nodes_created: count_net(&nodes, NetChange::Created),
+            nodes_updated: count_net(&nodes, NetChange::Updated),
+            nodes_deleted: count_net(&nodes, NetChange::Deleted),
+            links_created: count_net(&links, NetChange::Created),
+            links_removed: count_net(&links, NetChange::Deleted),
+            links_updated: count_net(&links, NetChange::Updated),
+        }
+    }
+
+    /// Stats for a commit that introduces an entire graph (e.g. the initial snapshot).
+    pub fn for_full_graph(graph: &Graph) -> Self {
+        CommitStats {
+            nodes_created: graph.nodes.len() as u32,
+            links_created: graph.links.len() as u32,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommitData {
     pub parents: Vec<CommitHash>,
@@ -45,13 +172,90 @@ pub struct CommitData {
     pub source: CommitSource,
     pub storage_type: CommitStorageType,
     pub depth_since_snapshot: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<CommitStats>,
+}
+
+/// Where a node sits among one parent's children.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChildPosition {
+    pub parent_id: NodeId,
+    pub index: usize,
+}
+
+/// The positions a node holds in its parents' child lists, primary first.
+pub fn child_positions(graph: &Graph, node_id: &NodeId) -> Vec<ChildPosition> {
+    let Some(node) = graph.nodes.get(node_id) else {
+        return Vec::new();
+    };
+    node.parents()
+        .filter_map(|parent_id| {
+            let index = graph.nodes.get(parent_id)?.children.iter().position(|c| c == node_id)?;
+            Some(ChildPosition {
+                parent_id: parent_id.clone(),
+                index,
+            })
+        })
+        .collect()
+}
+
+/// When and by whom a node was last changed, and for edits of its content
+/// the values that content replaced: what an edit updates besides the
+/// edited fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeStamp {
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
+    /// Left as it is when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_values: Option<Vec<SupersededValue>>,
 }
 
+impl NodeStamp {
+    pub fn of(node: &Node) -> Self {
+        NodeStamp {
+            updated_at: node.updated_at,
+            updated_by: node.updated_by.clone(),
+            previous_values: None,
+        }
+    }
+
+    /// The stamp with the node's history, for edits that supersede its
+    /// content. The history must not be deferred.
+    pub fn with_history(node: &Node) -> Self {
+        NodeStamp {
+            previous_values: Some(node.previous_values.clone()),
+            ..NodeStamp::of(node)
+        }
+    }
+
+    fn apply(&self, node: &mut Node) {
+        node.updated_at = self.updated_at;
+        node.updated_by = self.updated_by.clone();
+        if let Some(values) = &self.previous_values {
+            node.previous_values = values.clone();
+        }
+    }
+}
+
+/// One recorded mutation. Every variant carries the optional `actor` (tool
+/// name, conversation id or user) that made it.
+///
+/// Changes carry enough of the state they replace for `invert_delta` to
+/// restore it exactly, including where nodes sat in child lists; see
+/// `apply_recorded` for the positions.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Change {
     CreateNode {
         node_id: NodeId,
-        node: Node,
+        node: Arc<Node>,
+        /// Where the node goes in its parents' children. Without any, it
+        /// is appended to its primary parent's.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        positions: Vec<ChildPosition>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
     UpdateNode {
         node_id: NodeId,
@@ -59,35 +263,326 @@ pub enum Change {
         new_content: Option<String>,
         old_metadata: Option<HashMap<String, String>>,
         new_metadata: Option<HashMap<String, String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
     DeleteNode {
         node_id: NodeId,
         deleted_nodes: Vec<Node>,
         deleted_links: Vec<Link>,
+        /// Where the deleted node sat in its parents' children.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        positions: Vec<ChildPosition>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
     AddLink {
         link_id: LinkId,
         link: Link,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
     RemoveLink {
         link_id: LinkId,
         link: Link,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
     UpdateLink {
         link_id: LinkId,
         old_link: Link,
         new_link: Link,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
     ReparentNode {
         node_id: NodeId,
         old_parent: Option<NodeId>,
         new_parent: Option<NodeId>,
+        /// The node's index in the old parent's children.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        old_index: Option<usize>,
+        /// Its index in the new parent's children; appended when unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_index: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    ReorderChildren {
+        parent_id: NodeId,
+        old_order: Vec<NodeId>,
+        new_order: Vec<NodeId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    /// Add a parent. As primary, the previous primary becomes the first extra parent.
+    AddParent {
+        node_id: NodeId,
+        parent_id: NodeId,
+        primary: bool,
+        /// The node's index in the parent's children; appended when unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        child_index: Option<usize>,
+        /// The parent's index in the node's extra parents, when not primary;
+        /// appended when unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extra_index: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    /// Remove a parent. Removing the primary promotes the first extra parent.
+    RemoveParent {
+        node_id: NodeId,
+        parent_id: NodeId,
+        primary: bool,
+        /// The node's index in the parent's children.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        child_index: Option<usize>,
+        /// The parent's index in the node's extra parents, when not primary.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extra_index: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    SetTemporal {
+        node_id: NodeId,
+        old_temporal: Option<TemporalMetadata>,
+        new_temporal: Option<TemporalMetadata>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    SetPinned {
+        node_id: NodeId,
+        pinned: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    /// Register, re-point or remove (`new_root: None`) a named profile.
+    SetProfile {
+        name: String,
+        old_root: Option<NodeId>,
+        new_root: Option<NodeId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    SetDisplay {
+        node_id: NodeId,
+        old_display: Option<DisplayHints>,
+        new_display: Option<DisplayHints>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    SetArchived {
+        node_id: NodeId,
+        archived: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    SetSensitivity {
+        node_id: NodeId,
+        old_sensitivity: Sensitivity,
+        new_sensitivity: Sensitivity,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    AttachBlob {
+        node_id: NodeId,
+        attachment: AttachmentRef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    DetachBlob {
+        node_id: NodeId,
+        attachment: AttachmentRef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    /// Replace the graph's maintenance rules.
+    SetRules {
+        old_rules: Vec<Rule>,
+        new_rules: Vec<Rule>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    /// The bookkeeping an edit left on a node, recorded after the edit.
+    StampNode {
+        node_id: NodeId,
+        old_stamp: NodeStamp,
+        new_stamp: NodeStamp,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
     },
 }
 
+impl Change {
+    /// Who made the change, if known.
+    pub fn actor(&self) -> Option<&str> {
+        match self {
+            Change::CreateNode { actor, .. }
+            | Change::UpdateNode { actor, .. }
+            | Change::DeleteNode { actor, .. }
+            | Change::AddLink { actor, .. }
+            | Change::RemoveLink { actor, .. }
+            | Change::UpdateLink { actor, .. }
+            | Change::ReparentNode { actor, .. }
+            | Change::ReorderChildren { actor, .. }
+            | Change::AddParent { actor, .. }
+            | Change::RemoveParent { actor, .. }
+            | Change::SetTemporal { actor, .. }
+            | Change::SetPinned { actor, .. }
+            | Change::SetSensitivity { actor, .. }
+            | Change::SetArchived { actor, .. }
+            | Change::SetDisplay { actor, .. }
+            | Change::SetProfile { actor, .. }
+            | Change::AttachBlob { actor, .. }
+            | Change::DetachBlob { actor, .. }
+            | Change::SetRules { actor, .. }
+            | Change::StampNode { actor, .. } => actor.as_deref(),
+        }
+    }
+
+    pub fn set_actor(&mut self, value: Option<String>) {
+        match self {
+            Change::CreateNode { actor, .. }
+            | Change::UpdateNode { actor, .. }
+            | Change::DeleteNode { actor, .. }
+            | Change::AddLink { actor, .. }
+            | Change::RemoveLink { actor, .. }
+            | Change::UpdateLink { actor, .. }
+            | Change::ReparentNode { actor, .. }
+            | Change::ReorderChildren { actor, .. }
+            | Change::AddParent { actor, .. }
+            | Change::RemoveParent { actor, .. }
+            | Change::SetTemporal { actor, .. }
+            | Change::SetPinned { actor, .. }
+            | Change::SetSensitivity { actor, .. }
+            | Change::SetArchived { actor, .. }
+            | Change::SetDisplay { actor, .. }
+            | Change::SetProfile { actor, .. }
+            | Change::AttachBlob { actor, .. }
+            | Change::DetachBlob { actor, .. }
+            | Change::SetRules { actor, .. }
+            | Change::StampNode { actor, .. } => *actor = value,
+        }
+    }
+
+    /// The variant's name in snake case, e.g. "create_node".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::CreateNode { .. } => "create_node",
+            Change::UpdateNode { .. } => "update_node",
+            Change::DeleteNode { .. } => "delete_node",
+            Change::AddLink { .. } => "add_link",
+            Change::RemoveLink { .. } => "remove_link",
+            Change::UpdateLink { .. } => "update_link",
+            Change::ReparentNode { .. } => "reparent_node",
+            Change::ReorderChildren { .. } => "reorder_children",
+            Change::AddParent { .. } => "add_parent",
+            Change::RemoveParent { .. } => "remove_parent",
+            Change::SetTemporal { .. } => "set_temporal",
+            Change::SetPinned { .. } => "set_pinned",
+            Change::SetProfile { .. } => "set_profile",
+            Change::SetDisplay { .. } => "set_display",
+            Change::SetArchived { .. } => "set_archived",
+            Change::SetSensitivity { .. } => "set_sensitivity",
+            Change::AttachBlob { .. } => "attach_blob",
+            Change::DetachBlob { .. } => "detach_blob",
+            Change::SetRules { .. } => "set_rules",
+            Change::StampNode { .. } => "stamp_node",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Delta {
     pub changes: Vec<Change>,
+    /// Recorded with child positions and node stamps, so inverting it
+    /// restores the parent commit exactly. Deltas written before those
+    /// were recorded are only replayed forward.
+    #[serde(default)]
+    pub exact: bool,
+}
+
+impl Delta {
+    pub fn new(changes: Vec<Change>) -> Self {
+        Delta { changes, exact: true }
+    }
+}
+
+/// A snapshot stored as the nodes and links that differ from an earlier
+/// snapshot, its base, rather than as the full graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotPatch {
+    pub base: CommitHash,
+    /// Patches from the nearest full snapshot up to this one, inclusive.
+    pub chain: u32,
+    pub root_id: NodeId,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roots: BTreeMap<String, NodeId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+    /// Nodes added or changed since the base.
+    pub nodes: BTreeMap<NodeId, Arc<Node>>,
+    pub removed_nodes: Vec<NodeId>,
+    /// Links added or changed since the base.
+    pub links: BTreeMap<LinkId, Link>,
+    pub removed_links: Vec<LinkId>,
+}
+
+impl SnapshotPatch {
+    /// The patch taking `base_graph`, the snapshot at `base`, to `graph`.
+    pub fn between(base: CommitHash, chain: u32, base_graph: &Graph, graph: &Graph) -> Self {
+        fn differs<T: Serialize>(a: &T, b: &T) -> bool {
+            serde_json::to_vec(a).ok() != serde_json::to_vec(b).ok()
+        }
+        let nodes = crate::parallel::filter_map_entries(&graph.nodes, |nid, node| {
+            let changed = match base_graph.nodes.get(nid) {
+                Some(old) => !Arc::ptr_eq(old, node) && differs(old, node),
+                None => true,
+            };
+            changed.then(|| (nid.clone(), Arc::clone(node)))
+        });
+        let links = crate::parallel::filter_map_entries(&graph.links, |lid, link| {
+            let changed = base_graph.links.get(lid).is_none_or(|old| differs(old, link));
+            changed.then(|| (lid.clone(), link.clone()))
+        });
+        SnapshotPatch {
+            base,
+            chain,
+            root_id: graph.root_id.clone(),
+            roots: graph.roots.clone(),
+            rules: graph.rules.clone(),
+            nodes: nodes.into_iter().collect(),
+            removed_nodes: base_graph.nodes.keys().filter(|nid| !graph.nodes.contains_key(*nid)).cloned().collect(),
+            links: links.into_iter().collect(),
+            removed_links: base_graph.links.keys().filter(|lid| !graph.links.contains_key(*lid)).cloned().collect(),
+        }
+    }
+
+    /// Turn `graph`, the snapshot at the base, into the patched snapshot.
+    pub fn apply(self, graph: &mut Graph) {
+        graph.root_id = self.root_id;
+        graph.roots = self.roots;
+        graph.rules = self.rules;
+        for nid in &self.removed_nodes {
+            graph.nodes.remove(nid);
+        }
+        graph.nodes.extend(self.nodes);
+        for lid in &self.removed_links {
+            graph.links.remove(lid);
+        }
+        graph.links.extend(self.links);
+    }
+}
+
+/// The graph kept for a branch head, for reconstructing recent commits
+/// backwards: a patch against the snapshot its deltas build on or, in
+/// repositories from before those, the full graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TipSnapshot {
+    Patch(SnapshotPatch),
+    Full(Graph),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -101,6 +596,33 @@ pub struct RepoConfig {
     pub format_version: u32,
     pub snapshot_interval: u32,
     pub default_branch: String,
+    /// zstd level new snapshots are written at.
+    #[serde(default = "default_snapshot_compression_level")]
+    pub snapshot_compression_level: i32,
+    /// Id of the trained dictionary new snapshots are compressed with; see
+    /// `Repository::train_snapshot_dictionary`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_dictionary: Option<u32>,
+    /// Every this many snapshots one holds the full graph; those between
+    /// store a `SnapshotPatch` against the one before. 1 makes every
+    /// snapshot full.
+    #[serde(default = "default_snapshot_keyframe_interval")]
+    pub snapshot_keyframe_interval: u32,
+    /// Notified after commits, merges and branch operations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<Webhook>,
+    /// The bucket commits, snapshots and deltas are kept in, with
+    /// `objects/` as a local cache; unset keeps them only in `objects/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_storage: Option<S3Config>,
+}
+
+fn default_snapshot_compression_level() -> i32 {
+    3
+}
+
+fn default_snapshot_keyframe_interval() -> u32 {
+    1
 }
 
 impl Default for RepoConfig {
@@ -109,6 +631,11 @@ impl Default for RepoConfig {
             format_version: 1,
             snapshot_interval: 50,
             default_branch: "main".to_string(),
+            snapshot_compression_level: default_snapshot_compression_level(),
+            snapshot_dictionary: None,
+            snapshot_keyframe_interval: default_snapshot_keyframe_interval(),
+            webhooks: Vec::new(),
+            object_storage: None,
         }
     }
 }
@@ -121,95 +648,546 @@ pub struct CommitInput {
 }
 
 /// A commit entry returned by log queries.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommitEntry {
     pub hash: CommitHash,
     pub data: CommitData,
 }
 
 fn remove_child(graph: &mut Graph, parent_id: &NodeId, child_id: &NodeId) {
-    if let Some(parent) = graph.nodes.get_mut(parent_id) {
+    if let Some(parent) = graph.nodes.get_mut(parent_id).map(Arc::make_mut) {
         parent.children.retain(|c| c != child_id);
     }
 }
 
-fn add_child(graph: &mut Graph, parent_id: &NodeId, child_id: &NodeId) {
-    if let Some(parent) = graph.nodes.get_mut(parent_id) {
+/// Put `child_id` among a parent's children at `index`, or last, unless it
+/// is there already.
+fn add_child(graph: &mut Graph, parent_id: &NodeId, child_id: &NodeId, index: Option<usize>) {
+    if let Some(parent) = graph.nodes.get_mut(parent_id).map(Arc::make_mut) {
         if !parent.children.contains(child_id) {
-            parent.children.push(child_id.clone());
+            let index = index.map_or(parent.children.len(), |i| i.min(parent.children.len()));
+            parent.children.insert(index, child_id.clone());
         }
     }
 }
 
+/// Where `add_child` puts `child_id`: its current index, or the end.
+fn child_slot(graph: &Graph, parent_id: &NodeId, child_id: &NodeId) -> Option<usize> {
+    let parent = graph.nodes.get(parent_id)?;
+    Some(parent.children.iter().position(|c| c == child_id).unwrap_or(parent.children.len()))
+}
+
+fn child_index(graph: &Graph, parent_id: &NodeId, child_id: &NodeId) -> Option<usize> {
+    graph.nodes.get(parent_id)?.children.iter().position(|c| c == child_id)
+}
+
+/// Add `parent_id` to a node's parents. As primary, the previous primary
+/// becomes the first extra parent; otherwise it goes in the extra parents
+/// at `extra_index`, or last.
+pub fn add_parent(node: &mut Node, parent_id: &NodeId, primary: bool, extra_index: Option<usize>) {
+    if node.parents().any(|p| p == parent_id) {
+        return;
+    }
+    if primary {
+        if let Some(old) = node.parent_id.replace(parent_id.clone()) {
+            node.extra_parents.insert(0, old);
+        }
+    } else {
+        let index = extra_index.map_or(node.extra_parents.len(), |i| i.min(node.extra_parents.len()));
+        node.extra_parents.insert(index, parent_id.clone());
+    }
+}
+
+/// Remove `parent_id` from a node's parents. Removing the primary promotes
+/// the first extra parent, so this exactly undoes `add_parent`.
+pub fn remove_parent(node: &mut Node, parent_id: &NodeId) {
+    if node.parent_id.as_ref() == Some(parent_id) {
+        node.parent_id = if node.extra_parents.is_empty() {
+            None
+        } else {
+            Some(node.extra_parents.remove(0))
+        };
+    } else {
+        node.extra_parents.retain(|p| p != parent_id);
+    }
+}
+
+/// Invert a single change so that applying it undoes the original. The
+/// inverse keeps the original change's actor.
+fn invert_change(change: &Change) -> Vec<Change> {
+    let actor = change.actor().map(str::to_string);
+    match change {
+        Change::CreateNode {
+            node_id, node, positions, ..
+        } => vec![Change::DeleteNode {
+            node_id: node_id.clone(),
+            deleted_nodes: vec![Node::clone(node)],
+            deleted_links: Vec::new(),
+            positions: positions.clone(),
+            actor,
+        }],
+        Change::UpdateNode {
+            node_id,
+            old_content,
+            new_content,
+            old_metadata,
+            new_metadata,
+            ..
+        } => vec![Change::UpdateNode {
+            node_id: node_id.clone(),
+            old_content: new_content.clone(),
+            new_content: old_content.clone(),
+            old_metadata: new_metadata.clone(),
+            new_metadata: old_metadata.clone(),
+            actor,
+        }],
+        Change::DeleteNode {
+            node_id,
+            deleted_nodes,
+            deleted_links,
+            positions,
+            ..
+        } => {
+            // Deleted nodes are recorded descendants-first; recreate the
+            // subtree root first so every child finds its parent. The root
+            // goes back where it was; descendants are already listed in
+            // their parents' children.
+            let nodes = deleted_nodes.iter().rev().map(|n| Change::CreateNode {
+                node_id: n.id.clone(),
+                node: n.clone().into(),
+                positions: if &n.id == node_id { positions.clone() } else { Vec::new() },
+                actor: actor.clone(),
+            });
+            let links = deleted_links.iter().map(|l| Change::AddLink {
+                link_id: l.id.clone(),
+                link: l.clone(),
+                actor: actor.clone(),
+            });
+            nodes.chain(links).collect()
+        }
+        Change::AddLink { link_id, link, .. } => vec![Change::RemoveLink {
+            link_id: link_id.clone(),
+            link: link.clone(),
+            actor,
+        }],
+        Change::RemoveLink { link_id, link, .. } => vec![Change::AddLink {
+            link_id: link_id.clone(),
+            link: link.clone(),
+            actor,
+        }],
+        Change::UpdateLink {
+            link_id,
+            old_link,
+            new_link,
+            ..
+        } => vec![Change::UpdateLink {
+            link_id: link_id.clone(),
+            old_link: new_link.clone(),
+            new_link: old_link.clone(),
+            actor,
+        }],
+        Change::ReparentNode {
+            node_id,
+            old_parent,
+            new_parent,
+            old_index,
+            new_index,
+            ..
+        } => vec![Change::ReparentNode {
+            node_id: node_id.clone(),
+            old_parent: new_parent.clone(),
+            new_parent: old_parent.clone(),
+            old_index: *new_index,
+            new_index: *old_index,
+            actor,
+        }],
+        Change::ReorderChildren {
+            parent_id,
+            old_order,
+            new_order,
+            ..
+        } => vec![Change::ReorderChildren {
+            parent_id: parent_id.clone(),
+            old_order: new_order.clone(),
+            new_order: old_order.clone(),
+            actor,
+        }],
+        Change::AddParent {
+            node_id,
+            parent_id,
+            primary,
+            child_index,
+            extra_index,
+            ..
+        } => vec![Change::RemoveParent {
+            node_id: node_id.clone(),
+            parent_id: parent_id.clone(),
+            primary: *primary,
+            child_index: *child_index,
+            extra_index: *extra_index,
+            actor,
+        }],
+        Change::RemoveParent {
+            node_id,
+            parent_id,
+            primary,
+            child_index,
+            extra_index,
+            ..
+        } => vec![Change::AddParent {
+            node_id: node_id.clone(),
+            parent_id: parent_id.clone(),
+            primary: *primary,
+            child_index: *child_index,
+            extra_index: *extra_index,
+            actor,
+        }],
+        Change::SetTemporal {
+            node_id,
+            old_temporal,
+            new_temporal,
+            ..
+        } => vec![Change::SetTemporal {
+            node_id: node_id.clone(),
+            old_temporal: new_temporal.clone(),
+            new_temporal: old_temporal.clone(),
+            actor,
+        }],
+        Change::SetPinned { node_id, pinned, .. } => vec![Change::SetPinned {
+            node_id: node_id.clone(),
+            pinned: !pinned,
+            actor,
+        }],
+        Change::SetDisplay {
+            node_id,
+            old_display,
+            new_display,
+            ..
+        } => vec![Change::SetDisplay {
+            node_id: node_id.clone(),
+            old_display: new_display.clone(),
+            new_display: old_display.clone(),
+            actor,
+        }],
+        Change::SetArchived { node_id, archived, .. } => vec![Change::SetArchived {
+            node_id: node_id.clone(),
+            archived: !archived,
+            actor,
+        }],
+        Change::SetSensitivity {
+            node_id,
+            old_sensitivity,
+            new_sensitivity,
+            ..
+        } => vec![Change::SetSensitivity {
+            node_id: node_id.clone(),
+            old_sensitivity: *new_sensitivity,
+            new_sensitivity: *old_sensitivity,
+            actor,
+        }],
+        Change::AttachBlob { node_id, attachment, .. } => vec![Change::DetachBlob {
+            node_id: node_id.clone(),
+            attachment: attachment.clone(),
+            actor,
+        }],
+        Change::DetachBlob { node_id, attachment, .. } => vec![Change::AttachBlob {
+            node_id: node_id.clone(),
+            attachment: attachment.clone(),
+            actor,
+        }],
+        Change::SetProfile {
+            name,
+            old_root,
+            new_root,
+            ..
+        } => vec![Change::SetProfile {
+            name: name.clone(),
+            old_root: new_root.clone(),
+            new_root: old_root.clone(),
+            actor,
+        }],
+        Change::SetRules { old_rules, new_rules, .. } => vec![Change::SetRules {
+            old_rules: new_rules.clone(),
+            new_rules: old_rules.clone(),
+            actor,
+        }],
+        Change::StampNode {
+            node_id,
+            old_stamp,
+            new_stamp,
+            ..
+        } => vec![Change::StampNode {
+            node_id: node_id.clone(),
+            old_stamp: new_stamp.clone(),
+            new_stamp: old_stamp.clone(),
+            actor,
+        }],
+    }
+}
+
+/// Build the reverse of a delta: applying it to the graph at a commit
+/// yields the graph at that commit's parent. Only exact for an `exact` delta.
+pub fn invert_delta(delta: &Delta) -> Delta {
+    Delta {
+        changes: delta.changes.iter().rev().flat_map(invert_change).collect(),
+        exact: delta.exact,
+    }
+}
+
+/// Fill in the child and parent positions `change` leaves behind, read off
+/// `graph` as it is just before the change is applied.
+fn record_positions(graph: &Graph, change: &mut Change) {
+    match change {
+        Change::CreateNode {
+            node_id, node, positions, ..
+        } if positions.is_empty() => {
+            *positions = node
+                .parent_id
+                .iter()
+                .filter_map(|parent_id| {
+                    Some(ChildPosition {
+                        parent_id: parent_id.clone(),
+                        index: child_slot(graph, parent_id, node_id)?,
+                    })
+                })
+                .collect();
+        }
+        Change::DeleteNode { node_id, positions, .. } => {
+            *positions = child_positions(graph, node_id);
+        }
+        Change::ReparentNode {
+            node_id,
+            old_parent,
+            new_parent,
+            old_index,
+            new_index,
+            ..
+        } => {
+            *old_index = old_parent.as_ref().and_then(|p| child_index(graph, p, node_id));
+            if new_index.is_none() {
+                *new_index = new_parent.as_ref().and_then(|p| child_slot(graph, p, node_id));
+            }
+        }
+        Change::AddParent {
+            node_id,
+            parent_id,
+            primary,
+            child_index: index,
+            extra_index,
+            ..
+        } => {
+            if index.is_none() {
+                *index = child_slot(graph, parent_id, node_id);
+            }
+            if !*primary && extra_index.is_none() {
+                *extra_index = graph.nodes.get(node_id).map(|n| n.extra_parents.len());
+            }
+        }
+        Change::RemoveParent {
+            node_id,
+            parent_id,
+            primary,
+            child_index: index,
+            extra_index,
+            ..
+        } => {
+            *index = child_index(graph, parent_id, node_id);
+            if let Some(node) = graph.nodes.get(node_id) {
+                // An earlier removal may have promoted this parent.
+                *primary = node.parent_id.as_ref() == Some(parent_id);
+                *extra_index = node.extra_parents.iter().position(|p| p == parent_id);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Apply changes a store is about to record, filling in the positions each
+/// leaves behind as it goes so the recorded delta inverts exactly.
+pub fn apply_recorded(graph: &mut Graph, changes: &mut [Change]) {
+    for change in changes.iter_mut() {
+        record_positions(graph, change);
+        apply_change(graph, change);
+    }
+}
+
 /// Apply a delta's changes to a Graph in-place (forward replay).
 pub fn apply_delta(graph: &mut Graph, delta: &Delta) {
     for change in &delta.changes {
-        match change {
-            Change::CreateNode { node_id, node } => {
+        apply_change(graph, change);
+    }
+}
+
+fn apply_change(graph: &mut Graph, change: &Change) {
+    match change {
+        Change::CreateNode {
+            node_id, node, positions, ..
+        } => {
+            if positions.is_empty() {
                 if let Some(ref parent_id) = node.parent_id {
-                    add_child(graph, parent_id, node_id);
-                }
-                graph.nodes.insert(node_id.clone(), node.clone());
-            }
-            Change::UpdateNode {
-                node_id,
-                new_content,
-                new_metadata,
-                ..
-            } => {
-                if let Some(node) = graph.nodes.get_mut(node_id) {
-                    if let Some(content) = new_content {
-                        node.content = content.clone();
-                    }
-                    if let Some(metadata) = new_metadata {
-                        node.metadata = metadata.clone();
-                    }
+                    add_child(graph, parent_id, node_id, None);
                 }
             }
-            Change::DeleteNode {
-                node_id,
-                deleted_nodes,
-                deleted_links,
-                ..
-            } => {
-                let parent_id = graph.nodes.get(node_id).and_then(|n| n.parent_id.clone());
-                if let Some(parent_id) = parent_id {
-                    remove_child(graph, &parent_id, node_id);
-                }
-                graph.nodes.remove(node_id);
-                for dn in deleted_nodes {
-                    graph.nodes.remove(&dn.id);
+            for position in positions {
+                add_child(graph, &position.parent_id, node_id, Some(position.index));
+            }
+            graph.nodes.insert(node_id.clone(), Arc::clone(node));
+        }
+        Change::UpdateNode {
+            node_id,
+            new_content,
+            new_metadata,
+            ..
+        } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                if let Some(content) = new_content {
+                    node.content = content.clone();
                 }
-                for dl in deleted_links {
-                    graph.links.remove(&dl.id);
+                if let Some(metadata) = new_metadata {
+                    node.metadata = metadata.clone();
                 }
             }
-            Change::AddLink { link_id, link } => {
-                graph.links.insert(link_id.clone(), link.clone());
+        }
+        Change::DeleteNode {
+            node_id,
+            deleted_nodes,
+            deleted_links,
+            ..
+        } => {
+            let parents: Vec<NodeId> = graph
+                .nodes
+                .get(node_id)
+                .map(|n| n.parents().cloned().collect())
+                .unwrap_or_default();
+            for parent_id in parents {
+                remove_child(graph, &parent_id, node_id);
             }
-            Change::RemoveLink { link_id, .. } => {
-                graph.links.remove(link_id);
+            graph.nodes.remove(node_id);
+            for dn in deleted_nodes {
+                graph.nodes.remove(&dn.id);
             }
-            Change::UpdateLink { link_id, new_link, .. } => {
-                if let Some(link) = graph.links.get_mut(link_id) {
-                    *link = new_link.clone();
-                }
+            for dl in deleted_links {
+                graph.links.remove(&dl.id);
             }
-            Change::ReparentNode {
-                node_id,
-                old_parent,
-                new_parent,
-            } => {
-                if let Some(old_pid) = old_parent {
-                    remove_child(graph, old_pid, node_id);
-                }
-                if let Some(new_pid) = new_parent {
-                    add_child(graph, new_pid, node_id);
-                }
-                if let Some(node) = graph.nodes.get_mut(node_id) {
-                    node.parent_id = new_parent.clone();
+        }
+        Change::AddLink { link_id, link, .. } => {
+            graph.links.insert(link_id.clone(), link.clone());
+        }
+        Change::RemoveLink { link_id, .. } => {
+            graph.links.remove(link_id);
+        }
+        Change::UpdateLink { link_id, new_link, .. } => {
+            if let Some(link) = graph.links.get_mut(link_id) {
+                *link = new_link.clone();
+            }
+        }
+        Change::ReparentNode {
+            node_id,
+            old_parent,
+            new_parent,
+            new_index,
+            ..
+        } => {
+            if let Some(old_pid) = old_parent {
+                remove_child(graph, old_pid, node_id);
+            }
+            if let Some(new_pid) = new_parent {
+                add_child(graph, new_pid, node_id, *new_index);
+            }
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.parent_id = new_parent.clone();
+            }
+        }
+        Change::ReorderChildren {
+            parent_id,
+            new_order,
+            ..
+        } => {
+            if let Some(parent) = graph.nodes.get_mut(parent_id).map(Arc::make_mut) {
+                parent.children = new_order.clone();
+            }
+        }
+        Change::AddParent {
+            node_id,
+            parent_id,
+            primary,
+            child_index,
+            extra_index,
+            ..
+        } => {
+            add_child(graph, parent_id, node_id, *child_index);
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                add_parent(node, parent_id, *primary, *extra_index);
+            }
+        }
+        Change::RemoveParent {
+            node_id, parent_id, ..
+        } => {
+            remove_child(graph, parent_id, node_id);
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                remove_parent(node, parent_id);
+            }
+        }
+        Change::SetTemporal {
+            node_id,
+            new_temporal,
+            ..
+        } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.temporal = new_temporal.clone();
+            }
+        }
+        Change::SetPinned { node_id, pinned, .. } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.pinned = *pinned;
+            }
+        }
+        Change::SetDisplay { node_id, new_display, .. } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.display = new_display.clone();
+            }
+        }
+        Change::SetArchived { node_id, archived, .. } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.archived = *archived;
+            }
+        }
+        Change::SetSensitivity {
+            node_id,
+            new_sensitivity,
+            ..
+        } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.sensitivity = *new_sensitivity;
+            }
+        }
+        Change::AttachBlob { node_id, attachment, .. } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                if !node.attachments.iter().any(|a| a.hash == attachment.hash) {
+                    node.attachments.push(attachment.clone());
                 }
             }
         }
+        Change::DetachBlob { node_id, attachment, .. } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                node.attachments.retain(|a| a.hash != attachment.hash);
+            }
+        }
+        Change::SetProfile { name, new_root, .. } => match new_root {
+            Some(root) => {
+                graph.roots.insert(name.clone(), root.clone());
+            }
+            None => {
+                graph.roots.remove(name);
+            }
+        },
+        Change::SetRules { new_rules, .. } => {
+            graph.rules = new_rules.clone();
+        }
+        Change::StampNode { node_id, new_stamp, .. } => {
+            if let Some(node) = graph.nodes.get_mut(node_id).map(Arc::make_mut) {
+                new_stamp.apply(node);
+            }
+        }
     }
 }
@@ -0,0 +1,289 @@
+use crate::error::WillowError;
+use crate::model::{Graph, Node, NodeId, NodeType};
+use crate::search::{self, SearchOptions, SearchResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Texts sent to the provider per call.
+const EMBED_BATCH_SIZE: usize = 64;
+
+/// Reciprocal rank fusion constant: a result at rank `r` in a list scores
+/// `1 / (RRF_K + r)`, which damps the gap between the top few ranks.
+const RRF_K: f64 = 60.0;
+
+/// Turns texts into embedding vectors, one per text and all of the same
+/// length. Supplied by the host, e.g. a JS callback wrapping a model or an
+/// embeddings API.
+pub trait EmbeddingProvider {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, WillowError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Embedding {
+    /// Hash of the text that was embedded; a node whose text no longer
+    /// hashes to this needs re-embedding.
+    content_hash: String,
+    vector: Vec<f32>,
+}
+
+/// Per-node embeddings, persisted next to the graph.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingIndex {
+    /// Vector length, fixed by the first embedding stored.
+    dims: Option<usize>,
+    entries: BTreeMap<NodeId, Embedding>,
+}
+
+/// What gets embedded for a node: its searchable content.
+fn embedding_text(node: &Node) -> String {
+    node.content_format.searchable_text(&node.content).into_owned()
+}
+
+fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| f64::from(*x) * f64::from(*y)).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        0.0
+    } else {
+        dot / denom
+    }
+}
+
+impl EmbeddingIndex {
+    /// Nodes with no embedding or whose content changed since it was made.
+    /// Root nodes and empty content are never embedded.
+    fn stale<'a>(&self, graph: &'a Graph) -> Vec<(&'a Node, String)> {
+        let mut nodes: Vec<(&Node, String)> = graph
+            .nodes
+            .values()
+            .map(Arc::as_ref)
+            .filter(|n| n.node_type != NodeType::Root)
+            .map(|n| (n, embedding_text(n)))
+            .filter(|(n, text)| {
+                !text.trim().is_empty()
+                    && self.entries.get(&n.id).is_none_or(|e| e.content_hash != content_hash(text))
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.0.id.cmp(&b.0.id));
+        nodes
+    }
+
+    /// Embed new and changed nodes and drop embeddings of deleted ones.
+    /// Returns the number of nodes embedded.
+    pub fn refresh(&mut self, graph: &Graph, provider: &dyn EmbeddingProvider) -> Result<usize, WillowError> {
+        self.entries.retain(|id, _| graph.nodes.contains_key(id));
+        let stale = self.stale(graph);
+        for batch in stale.chunks(EMBED_BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+            let vectors = provider.embed(&texts)?;
+            if vectors.len() != texts.len() {
+                return Err(WillowError::EmbeddingProvider(format!(
+                    "returned {} embeddings for {} texts",
+                    vectors.len(),
+                    texts.len()
+                )));
+            }
+            for ((node, text), vector) in batch.iter().zip(vectors) {
+                self.check_dims(vector.len())?;
+                self.dims = Some(vector.len());
+                self.entries.insert(
+                    node.id.clone(),
+                    Embedding {
+                        content_hash: content_hash(text),
+                        vector,
+                    },
+                );
+            }
+        }
+        debug!(embedded = stale.len(), total = self.entries.len(), "embeddings refreshed");
+        Ok(stale.len())
+    }
+
+    fn check_dims(&self, actual: usize) -> Result<(), WillowError> {
+        match self.dims {
+            Some(expected) if expected != actual => Err(WillowError::EmbeddingDimensions { expected, actual }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The `k` nodes most similar to `query` by cosine similarity, among
+    /// those `search::search_nodes` would consider with the same root and
+    /// options. Nodes whose embedding is stale are left out until refreshed.
+    pub fn search(
+        &self,
+        graph: &Graph,
+        query: &[f32],
+        k: usize,
+        root_node_id: Option<&NodeId>,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>, WillowError> {
+        self.check_dims(query.len())?;
+        let start_id = root_node_id.unwrap_or(&graph.root_id);
+        let mut results: Vec<SearchResult> = search::visible_nodes(graph, start_id, options)
+            .into_iter()
+            .filter(|(node, _)| options.dates_match(node))
+            .filter_map(|(node, depth)| {
+                let entry = self.entries.get(&node.id)?;
+                if entry.content_hash != content_hash(&embedding_text(node)) {
+                    return None;
+                }
+                Some(SearchResult {
+                    node_id: node.id.clone(),
+                    node_type: node.node_type.as_str().to_string(),
+                    content: node.content.clone(),
+                    score: cosine(query, &entry.vector),
+                    matched_field: "embedding".to_string(),
+                    depth,
+                    path: graph.content_path(&node.id),
+                })
+            })
+            .collect();
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(k);
+        Ok(results)
+    }
+}
+
+/// Merge keyword and semantic rankings with reciprocal rank fusion, so a
+/// node ranked well by either list surfaces and one ranked well by both
+/// rises to the top. Ranks matter, not the lists' incomparable scores. The
+/// matched field is the keyword one when there is one.
+pub fn fuse(keyword: Vec<SearchResult>, semantic: Vec<SearchResult>) -> Vec<SearchResult> {
+    let mut fused: Vec<SearchResult> = Vec::new();
+    let mut positions: BTreeMap<NodeId, usize> = BTreeMap::new();
+    for list in [keyword, semantic] {
+        for (rank, result) in list.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f64 + 1.0);
+            match positions.get(&result.node_id) {
+                Some(&i) => fused[i].score += score,
+                None => {
+                    positions.insert(result.node_id.clone(), fused.len());
+                    fused.push(SearchResult { score, ..result });
+                }
+            }
+        }
+    }
+    // Stable, so ties keep keyword order ahead of semantic-only results.
+    fused.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ContentFormat, Sensitivity};
+    use crate::storage::create_default_graph;
+    use chrono::Utc;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    /// Embeds a text as its counts of the letters a, e, i, o and u.
+    struct VowelProvider {
+        calls: Cell<usize>,
+    }
+
+    impl EmbeddingProvider for VowelProvider {
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, WillowError> {
+            self.calls.set(self.calls.get() + texts.len());
+            Ok(texts
+                .iter()
+                .map(|t| "aeiou".chars().map(|v| t.matches(v).count() as f32).collect())
+                .collect())
+        }
+    }
+
+    fn add_node(graph: &mut Graph, id: &str, content: &str) {
+        let now = Utc::now();
+        let node = Node {
+            id: NodeId(id.into()),
+            node_type: NodeType::Detail,
+            content: content.to_string(),
+            parent_id: Some(graph.root_id.clone()),
+            children: Vec::new(),
+            extra_parents: Vec::new(),
+            pinned: false,
+            sensitivity: Sensitivity::Normal,
+            archived: false,
+            display: None,
+            content_format: ContentFormat::Text,
+            attachments: Vec::new(),
+            metadata: HashMap::new(),
+            previous_values: Vec::new(),
+            temporal: None,
+            created_at: now,
+            updated_at: now,
+            created_by: None,
+            updated_by: None,
+        };
+        graph.nodes.insert(node.id.clone(), node.into());
+        graph.nodes.get_mut(&graph.root_id).map(Arc::make_mut).unwrap().children.push(NodeId(id.into()));
+    }
+
+    #[test]
+    fn test_refresh_only_embeds_changed_nodes() {
+        let mut graph = create_default_graph();
+        add_node(&mut graph, "n1", "banana");
+        add_node(&mut graph, "n2", "kiwi");
+        let provider = VowelProvider { calls: Cell::new(0) };
+        let mut index = EmbeddingIndex::default();
+
+        assert_eq!(index.refresh(&graph, &provider).unwrap(), 2);
+        assert_eq!(index.refresh(&graph, &provider).unwrap(), 0);
+
+        graph.nodes.get_mut(&NodeId("n1".into())).map(Arc::make_mut).unwrap().content = "papaya".to_string();
+        graph.nodes.remove(&NodeId("n2".into()));
+        assert_eq!(index.refresh(&graph, &provider).unwrap(), 1);
+        assert_eq!(provider.calls.get(), 3);
+        assert_eq!(index.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_search_ranks_by_cosine_similarity() {
+        let mut graph = create_default_graph();
+        add_node(&mut graph, "n1", "banana");
+        add_node(&mut graph, "n2", "kiwi");
+        add_node(&mut graph, "n3", "ambrosia");
+        let mut index = EmbeddingIndex::default();
+        index.refresh(&graph, &VowelProvider { calls: Cell::new(0) }).unwrap();
+
+        let results = index.search(&graph, &[1.0, 0.0, 0.0, 0.0, 0.0], 2, None, &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(&*results[0].node_id.0, "n1");
+        assert_eq!(&*results[1].node_id.0, "n3");
+        assert!((results[0].score - 1.0).abs() < 1e-9);
+
+        assert!(matches!(
+            index.search(&graph, &[1.0], 2, None, &SearchOptions::default()),
+            Err(WillowError::EmbeddingDimensions { expected: 5, actual: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_fuse_favours_nodes_in_both_rankings() {
+        let mut graph = create_default_graph();
+        add_node(&mut graph, "n1", "Order 7731 shipped");
+        add_node(&mut graph, "n2", "The parcel went out");
+        add_node(&mut graph, "n3", "Order 7731 shipped late, parcel delayed");
+        let mut index = EmbeddingIndex::default();
+        index.refresh(&graph, &VowelProvider { calls: Cell::new(0) }).unwrap();
+
+        let keyword = search::search_nodes(&graph, "7731", None, &SearchOptions::default());
+        let query = VowelProvider { calls: Cell::new(0) }.embed(&["parcel".to_string()]).unwrap();
+        let semantic = index.search(&graph, &query[0], usize::MAX, None, &SearchOptions::default()).unwrap();
+        let fused = fuse(keyword, semantic);
+
+        assert_eq!(fused.len(), 3);
+        assert!(fused[..2].iter().all(|r| r.matched_field == "content"));
+        assert_eq!(&*fused[2].node_id.0, "n2");
+        assert_eq!(fused[2].matched_field, "embedding");
+    }
+}
@@ -0,0 +1,613 @@
+//! Browser bindings: `WasmGraphStore`, with the DTO shapes of the Node
+//! bindings. A graph's files live in memory under `/willow/<name>`, loaded
+//! from IndexedDB when it is opened and written back after every change.
+
+use crate::error::WillowError;
+use crate::files::{self, MemoryFiles};
+use crate::model;
+use crate::query::{LinkFilter, NodeFilter, NodeSort, Page};
+use crate::search::{self, SearchOptions};
+use crate::snapshot::GraphFormat;
+use crate::store::{self, OpenOptions};
+use crate::vcs;
+use js_sys::{Array, Promise, Uint8Array};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Event, IdbDatabase, IdbFactory, IdbKeyRange, IdbOpenDbRequest, IdbRequest, IdbTransactionMode};
+
+/// The IndexedDB database holding every graph, and its one object store,
+/// keyed by `<graph name>/<path within the graph's directory>`.
+const DATABASE: &str = "willow";
+const FILES: &str = "files";
+
+/// Where graphs' files are mounted while they are open.
+const MOUNT_ROOT: &str = "/willow";
+
+/// Search results per call unless the caller asks for a different limit.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+fn js_err(e: WillowError) -> JsValue {
+    JsError::new(&e.to_string()).into()
+}
+
+fn map_vec<T, U>(items: &[T], f: impl Fn(&T) -> U) -> Vec<U> {
+    items.iter().map(f).collect()
+}
+
+/// `value` as a plain JS object; maps become objects rather than `Map`s.
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+fn from_js<T: serde::de::DeserializeOwned>(value: JsValue) -> Result<T, JsValue> {
+    Ok(serde_wasm_bindgen::from_value(value)?)
+}
+
+// ---- DTO structs ----
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TemporalMetadata {
+    valid_from: Option<String>,
+    valid_until: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DisplayHints {
+    icon: Option<String>,
+    color: Option<String>,
+    collapsed: Option<bool>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Attachment {
+    hash: String,
+    mime: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SupersededValue {
+    old_content: String,
+    superseded_at: String,
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    id: String,
+    node_type: String,
+    content: String,
+    content_format: String,
+    parent_id: Option<String>,
+    children: Vec<String>,
+    extra_parents: Vec<String>,
+    pinned: bool,
+    sensitivity: String,
+    archived: bool,
+    display: Option<DisplayHints>,
+    attachments: Vec<Attachment>,
+    metadata: HashMap<String, String>,
+    previous_values: Vec<SupersededValue>,
+    temporal: Option<TemporalMetadata>,
+    created_at: String,
+    updated_at: String,
+    created_by: Option<String>,
+    updated_by: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Link {
+    id: String,
+    from_node: String,
+    to_node: String,
+    relation: String,
+    bidirectional: bool,
+    confidence: Option<String>,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchResult {
+    node_id: String,
+    node_type: String,
+    content: String,
+    score: f64,
+    matched_field: String,
+    depth: usize,
+    path: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchPage {
+    results: Vec<SearchResult>,
+    total: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NodePage {
+    nodes: Vec<Node>,
+    total: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkPage {
+    links: Vec<Link>,
+    total: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CommitEntry {
+    hash: String,
+    message: String,
+    timestamp: String,
+    parents: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateNodeInput {
+    parent_id: String,
+    node_type: String,
+    content: String,
+    metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateNodeInput {
+    node_id: String,
+    content: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddLinkInput {
+    from_node: String,
+    to_node: String,
+    relation: String,
+    bidirectional: Option<bool>,
+    confidence: Option<String>,
+}
+
+// ---- Conversions ----
+
+fn node_to_js(node: &model::Node) -> Node {
+    Node {
+        id: node.id.0.to_string(),
+        node_type: node.node_type.as_str().to_string(),
+        content: node.content.clone(),
+        content_format: node.content_format.as_str().to_string(),
+        parent_id: node.parent_id.as_ref().map(|id| id.0.to_string()),
+        children: node.children.iter().map(|id| id.0.to_string()).collect(),
+        extra_parents: node.extra_parents.iter().map(|id| id.0.to_string()).collect(),
+        pinned: node.pinned,
+        sensitivity: node.sensitivity.as_str().to_string(),
+        archived: node.archived,
+        display: node.display.as_ref().map(|d| DisplayHints {
+            icon: d.icon.clone(),
+            color: d.color.clone(),
+            collapsed: Some(d.collapsed),
+        }),
+        attachments: map_vec(&node.attachments, |a| Attachment {
+            hash: a.hash.clone(),
+            mime: a.mime.clone(),
+            size: a.size,
+        }),
+        metadata: node.metadata.clone(),
+        previous_values: map_vec(&node.previous_values, |sv| SupersededValue {
+            old_content: sv.old_content.clone(),
+            superseded_at: sv.superseded_at.to_rfc3339(),
+            reason: sv.reason.clone(),
+        }),
+        temporal: node.temporal.as_ref().map(|t| TemporalMetadata {
+            valid_from: t.valid_from.map(|d| d.to_rfc3339()),
+            valid_until: t.valid_until.map(|d| d.to_rfc3339()),
+            label: t.label.clone(),
+        }),
+        created_at: node.created_at.to_rfc3339(),
+        updated_at: node.updated_at.to_rfc3339(),
+        created_by: node.created_by.clone(),
+        updated_by: node.updated_by.clone(),
+    }
+}
+
+fn link_to_js(link: &model::Link) -> Link {
+    Link {
+        id: link.id.0.to_string(),
+        from_node: link.from_node.0.to_string(),
+        to_node: link.to_node.0.to_string(),
+        relation: link.relation.clone(),
+        bidirectional: link.bidirectional,
+        confidence: link.confidence.as_ref().map(|c| c.as_str().to_string()),
+        created_at: link.created_at.to_rfc3339(),
+    }
+}
+
+fn search_page_to_js(page: &search::SearchPage) -> SearchPage {
+    SearchPage {
+        results: map_vec(&page.results, |r| SearchResult {
+            node_id: r.node_id.0.to_string(),
+            node_type: r.node_type.clone(),
+            content: r.content.clone(),
+            score: r.score,
+            matched_field: r.matched_field.clone(),
+            depth: r.depth,
+            path: r.path.clone(),
+        }),
+        total: page.total,
+    }
+}
+
+fn commit_entry_to_js(entry: &vcs::types::CommitEntry) -> CommitEntry {
+    CommitEntry {
+        hash: entry.hash.0.clone(),
+        message: entry.data.message.clone(),
+        timestamp: entry.data.timestamp.to_rfc3339(),
+        parents: entry.data.parents.iter().map(|p| p.0.clone()).collect(),
+    }
+}
+
+fn parse_graph_format(format: Option<String>) -> Result<Option<GraphFormat>, WillowError> {
+    format.map(|f| GraphFormat::from_str(&f).ok_or(WillowError::InvalidGraphFormat(f))).transpose()
+}
+
+// ---- IndexedDB ----
+
+/// Wait for `request` to succeed and return its result.
+async fn finished(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let done = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(done).await?;
+    request.result()
+}
+
+/// Open the database, creating its object store the first time.
+async fn open_database() -> Result<IdbDatabase, JsValue> {
+    // The `indexedDB` of whichever window or worker is running us.
+    let factory: IdbFactory = js_sys::Reflect::get(&js_sys::global(), &"indexedDB".into())?.dyn_into()?;
+    let request = factory.open_with_u32(DATABASE, 1)?;
+    let upgrade = Closure::<dyn FnMut(Event)>::new(|event: Event| {
+        let db = event
+            .target()
+            .and_then(|target| target.dyn_into::<IdbOpenDbRequest>().ok())
+            .and_then(|request| request.result().ok())
+            .and_then(|db| db.dyn_into::<IdbDatabase>().ok());
+        if let Some(db) = db {
+            if !db.object_store_names().contains(FILES) {
+                // A failure here aborts the upgrade, which fails the open.
+                let _ = db.create_object_store(FILES);
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+    finished(&request).await?.dyn_into()
+}
+
+/// The files of graph `name`, by path within its directory.
+async fn load_files(db: &IdbDatabase, name: &str) -> Result<Vec<(String, Vec<u8>)>, JsValue> {
+    let store = db.transaction_with_str(FILES)?.object_store(FILES)?;
+    let prefix = format!("{name}/");
+    // Every key from the prefix up to, not including, the next name: '0'
+    // sorts right after '/'.
+    let range = IdbKeyRange::bound(&prefix.as_str().into(), &format!("{name}0").into())?;
+    let keys = store.get_all_keys_with_key(&range)?;
+    let values = store.get_all_with_key(&range)?;
+    let keys = Array::from(&finished(&keys).await?);
+    let values = Array::from(&finished(&values).await?);
+    Ok(keys
+        .iter()
+        .zip(values.iter())
+        .filter_map(|(key, value)| {
+            let path = key.as_string()?.strip_prefix(&prefix)?.to_string();
+            Some((path, Uint8Array::new(&value).to_vec()))
+        })
+        .collect())
+}
+
+/// Write `changes` under `dir` to graph `name` in one transaction, returning
+/// a promise that settles when it does. IndexedDB commits transactions on
+/// the same store in the order they were started.
+fn write_files(
+    db: &IdbDatabase,
+    name: &str,
+    dir: &Path,
+    changes: Vec<(PathBuf, Option<Arc<Vec<u8>>>)>,
+) -> Result<Promise, JsValue> {
+    let transaction = db.transaction_with_str_and_mode(FILES, IdbTransactionMode::Readwrite)?;
+    let store = transaction.object_store(FILES)?;
+    for (path, data) in changes {
+        let Ok(relative) = path.strip_prefix(dir) else { continue };
+        let key = JsValue::from(format!("{name}/{}", relative.to_string_lossy()));
+        match data {
+            Some(data) => store.put_with_key(&Uint8Array::from(data.as_slice()), &key)?,
+            None => store.delete(&key)?,
+        };
+    }
+    Ok(Promise::new(&mut |resolve, reject| {
+        transaction.set_oncomplete(Some(&resolve));
+        transaction.set_onerror(Some(&reject));
+        transaction.set_onabort(Some(&reject));
+    }))
+}
+
+// ---- WasmGraphStore ----
+
+#[wasm_bindgen]
+pub struct WasmGraphStore {
+    store: store::GraphStore,
+    name: String,
+    dir: PathBuf,
+    memory: Arc<MemoryFiles>,
+    db: IdbDatabase,
+    /// Settles once every write to IndexedDB started so far has; rejects
+    /// if any failed.
+    saved: Promise,
+}
+
+#[wasm_bindgen]
+impl WasmGraphStore {
+    /// Open graph `name` from this origin's IndexedDB, creating it if it
+    /// is new. A graph can be open only once per page.
+    pub async fn open(name: String) -> Result<WasmGraphStore, JsValue> {
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(JsError::new(&format!("Invalid graph name: {name}")).into());
+        }
+        let dir = Path::new(MOUNT_ROOT).join(&name);
+        if files::is_mounted(&dir) {
+            return Err(JsError::new(&format!("Graph {name} is already open")).into());
+        }
+        let db = open_database().await?;
+        let memory = Arc::new(MemoryFiles::new());
+        for (path, data) in load_files(&db, &name).await? {
+            memory.insert(dir.join(path), data);
+        }
+        // Another open of the same name may have finished while we loaded.
+        if files::is_mounted(&dir) {
+            return Err(JsError::new(&format!("Graph {name} is already open")).into());
+        }
+        files::mount(&dir, memory.clone());
+        let store = match store::GraphStore::open(&dir.join("graph.json"), &OpenOptions::default()) {
+            Ok(store) => store,
+            Err(e) => {
+                files::unmount(&dir);
+                return Err(js_err(e));
+            }
+        };
+        let mut opened = WasmGraphStore {
+            store,
+            name,
+            dir,
+            memory,
+            db,
+            saved: Promise::resolve(&JsValue::UNDEFINED),
+        };
+        // A new graph's first save.
+        opened.persist()?;
+        Ok(opened)
+    }
+
+    /// Resolves once every change so far is in IndexedDB; rejects if
+    /// writing any of them failed.
+    pub fn flush(&mut self) -> Result<Promise, JsValue> {
+        self.persist()?;
+        Ok(std::mem::replace(&mut self.saved, Promise::resolve(&JsValue::UNDEFINED)))
+    }
+
+    /// Close this store; its methods throw afterwards. Resolves like
+    /// `flush`.
+    pub fn close(&mut self) -> Result<Promise, JsValue> {
+        self.store.close().map_err(js_err)?;
+        let saved = self.flush()?;
+        files::unmount(&self.dir);
+        Ok(saved)
+    }
+
+    #[wasm_bindgen(js_name = isClosed)]
+    pub fn is_closed(&self) -> bool {
+        self.store.is_closed()
+    }
+
+    #[wasm_bindgen(js_name = getNode)]
+    pub fn get_node(&self, node_id: &str) -> Result<JsValue, JsValue> {
+        self.store.require_open().map_err(js_err)?;
+        to_js(&node_to_js(self.store.get_node(node_id).map_err(js_err)?))
+    }
+
+    #[wasm_bindgen(js_name = getLink)]
+    pub fn get_link(&self, link_id: &str) -> Result<JsValue, JsValue> {
+        self.store.require_open().map_err(js_err)?;
+        to_js(&link_to_js(self.store.get_link(link_id).map_err(js_err)?))
+    }
+
+    /// Every node, in id order, a page at a time.
+    #[wasm_bindgen(js_name = listNodes)]
+    pub fn list_nodes(&self, offset: Option<usize>, limit: Option<usize>) -> Result<JsValue, JsValue> {
+        self.store.require_open().map_err(js_err)?;
+        let page = Page { offset: offset.unwrap_or(0), limit };
+        let result = self.store.list_nodes(&NodeFilter::default(), &NodeSort::default(), &page);
+        to_js(&NodePage { nodes: map_vec(&result.nodes, node_to_js), total: result.total })
+    }
+
+    #[wasm_bindgen(js_name = listLinks)]
+    pub fn list_links(&self, offset: Option<usize>, limit: Option<usize>) -> Result<JsValue, JsValue> {
+        self.store.require_open().map_err(js_err)?;
+        let page = Page { offset: offset.unwrap_or(0), limit };
+        let result = self.store.list_links(&LinkFilter::default(), &page);
+        to_js(&LinkPage { links: map_vec(&result.links, link_to_js), total: result.total })
+    }
+
+    /// Ranked matches; `limit` defaults to 10. `total` counts every match.
+    #[wasm_bindgen(js_name = searchNodes)]
+    pub fn search_nodes(
+        &self,
+        query: &str,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        root_node_id: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        self.store.require_open().map_err(js_err)?;
+        let page = Page { offset: offset.unwrap_or(0), limit: Some(limit.unwrap_or(DEFAULT_SEARCH_LIMIT)) };
+        let result = self.store.search_nodes(query, &page, root_node_id.as_deref(), &SearchOptions::default());
+        to_js(&search_page_to_js(&result))
+    }
+
+    /// `input` is `{ parentId, nodeType, content, metadata? }`.
+    #[wasm_bindgen(js_name = createNode)]
+    pub fn create_node(&mut self, input: JsValue) -> Result<JsValue, JsValue> {
+        let input: CreateNodeInput = from_js(input)?;
+        let node = self.write(|store| {
+            store.create_node(&input.parent_id, &input.node_type, &input.content, input.metadata, None)
+        })?;
+        to_js(&node_to_js(&node))
+    }
+
+    /// `input` is `{ nodeId, content?, metadata?, reason? }`.
+    #[wasm_bindgen(js_name = updateNode)]
+    pub fn update_node(&mut self, input: JsValue) -> Result<JsValue, JsValue> {
+        let input: UpdateNodeInput = from_js(input)?;
+        let node = self.write(|store| {
+            store.update_node(&input.node_id, input.content.as_deref(), input.metadata, None, input.reason.as_deref())
+        })?;
+        to_js(&node_to_js(&node))
+    }
+
+    #[wasm_bindgen(js_name = deleteNode)]
+    pub fn delete_node(&mut self, node_id: &str) -> Result<(), JsValue> {
+        self.write(|store| store.delete_node(node_id))
+    }
+
+    /// `input` is `{ fromNode, toNode, relation, bidirectional?, confidence? }`.
+    #[wasm_bindgen(js_name = addLink)]
+    pub fn add_link(&mut self, input: JsValue) -> Result<JsValue, JsValue> {
+        let input: AddLinkInput = from_js(input)?;
+        let link = self.write(|store| {
+            store.add_link(
+                &input.from_node,
+                &input.to_node,
+                &input.relation,
+                input.bidirectional.unwrap_or(false),
+                input.confidence.as_deref(),
+            )
+        })?;
+        to_js(&link_to_js(&link))
+    }
+
+    #[wasm_bindgen(js_name = deleteLink)]
+    pub fn delete_link(&mut self, link_id: &str) -> Result<JsValue, JsValue> {
+        let link = self.write(|store| store.delete_link(link_id))?;
+        to_js(&link_to_js(&link))
+    }
+
+    pub fn undo(&mut self) -> Result<bool, JsValue> {
+        self.write(|store| store.undo())
+    }
+
+    pub fn redo(&mut self) -> Result<bool, JsValue> {
+        self.write(|store| store.redo())
+    }
+
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo(&self) -> bool {
+        self.store.can_undo()
+    }
+
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo(&self) -> bool {
+        self.store.can_redo()
+    }
+
+    /// The whole graph as "json" (the default), "msgpack" or "compressed".
+    #[wasm_bindgen(js_name = exportGraph)]
+    pub fn export_graph(&mut self, format: Option<String>) -> Result<Vec<u8>, JsValue> {
+        let format = parse_graph_format(format).map_err(js_err)?.unwrap_or_default();
+        self.store.export_graph(format).map_err(js_err)
+    }
+
+    /// Replace the graph with an export; `format` is detected if omitted.
+    #[wasm_bindgen(js_name = importGraph)]
+    pub fn import_graph(&mut self, data: &[u8], format: Option<String>) -> Result<(), JsValue> {
+        let format = parse_graph_format(format).map_err(js_err)?;
+        self.write(|store| store.import_graph(data, format))
+    }
+
+    // ---- VCS methods ----
+
+    #[wasm_bindgen(js_name = vcsInit)]
+    pub fn vcs_init(&mut self) -> Result<(), JsValue> {
+        self.write(|store| store.vcs_init())
+    }
+
+    #[wasm_bindgen(js_name = hasPendingChanges)]
+    pub fn has_pending_changes(&self) -> bool {
+        self.store.has_pending_changes()
+    }
+
+    /// Commit the pending changes as a manual commit; returns its hash.
+    pub fn commit(&mut self, message: String) -> Result<String, JsValue> {
+        let input = vcs::types::CommitInput {
+            message,
+            source: vcs::types::CommitSource::Manual { tool_name: None },
+        };
+        Ok(self.write(|store| store.commit(input))?.0)
+    }
+
+    pub fn log(&self, limit: Option<usize>) -> Result<JsValue, JsValue> {
+        self.store.require_open().map_err(js_err)?;
+        let entries = self.store.get_repo().map_err(js_err)?.log(limit).map_err(js_err)?;
+        to_js(&map_vec(&entries, commit_entry_to_js))
+    }
+}
+
+impl WasmGraphStore {
+    /// Start writing the files changed since the last call to IndexedDB.
+    fn persist(&mut self) -> Result<(), JsValue> {
+        let changes = self.memory.take_changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let written = write_files(&self.db, &self.name, &self.dir, changes)?;
+        self.saved = Promise::all(&Array::of2(&self.saved, &written));
+        Ok(())
+    }
+
+    /// Run `op`, then persist whatever it wrote, even if it failed.
+    fn write<T>(&mut self, op: impl FnOnce(&mut store::GraphStore) -> Result<T, WillowError>) -> Result<T, JsValue> {
+        let result = op(&mut self.store);
+        self.persist()?;
+        result.map_err(js_err)
+    }
+}
+
+impl Drop for WasmGraphStore {
+    /// Like `close`, for a store JS freed without closing, so its last
+    /// writes still go to IndexedDB.
+    fn drop(&mut self) {
+        if self.store.is_closed() {
+            return;
+        }
+        let _ = self.store.close();
+        let _ = self.persist();
+        files::unmount(&self.dir);
+    }
+}
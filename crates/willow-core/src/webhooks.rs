@@ -0,0 +1,311 @@
+use crate::error::WillowError;
+use crate::http;
+use crate::model::Sensitivity;
+use crate::vcs::diff::ChangeSummary;
+use crate::vcs::types::CommitHash;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// How long a delivery may take to connect, send or get its response.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Threads making deliveries, shared by every store in the process.
+const DELIVERY_WORKERS: usize = 4;
+/// Deliveries waiting for a worker before new ones are dropped.
+const DELIVERY_QUEUE: usize = 256;
+/// The most of a response body read; only the status is used.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// What happened to a repository that webhooks can be told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Commit,
+    Merge,
+    CreateBranch,
+    DeleteBranch,
+    SwitchBranch,
+}
+
+impl WebhookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::Commit => "commit",
+            WebhookEvent::Merge => "merge",
+            WebhookEvent::CreateBranch => "create_branch",
+            WebhookEvent::DeleteBranch => "delete_branch",
+            WebhookEvent::SwitchBranch => "switch_branch",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "commit" => Some(WebhookEvent::Commit),
+            "merge" => Some(WebhookEvent::Merge),
+            "create_branch" => Some(WebhookEvent::CreateBranch),
+            "delete_branch" => Some(WebhookEvent::DeleteBranch),
+            "switch_branch" => Some(WebhookEvent::SwitchBranch),
+            _ => None,
+        }
+    }
+}
+
+/// A URL a `WebhookPayload` is POSTed to as JSON: `https://` (with the
+/// `tls` feature), or plain `http://` to a loopback address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// The events to send; empty sends them all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<WebhookEvent>,
+    /// The most sensitive changes the hook is sent; anything above is left
+    /// out of its payloads.
+    #[serde(default, skip_serializing_if = "Sensitivity::is_normal")]
+    pub max_sensitivity: Sensitivity,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub timestamp: DateTime<Utc>,
+    /// The branch named by a branch event, otherwise the current branch;
+    /// absent on a detached HEAD.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// The branch merged in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The commit made, or the head of the branch afterwards.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<CommitHash>,
+    /// How the graph changed: against the commit's first parent for a
+    /// commit, against the graph before for a merge or switch, and empty
+    /// for creating or deleting a branch.
+    pub summary: ChangeSummary,
+}
+
+/// Check every hook has a URL deliveries can be made to, and that none
+/// would send a payload over the network unencrypted.
+pub fn validate(hooks: &[Webhook]) -> Result<(), WillowError> {
+    for hook in hooks {
+        let invalid = |reason: &str| Err(WillowError::InvalidRepoConfig(format!("webhook URL '{}' {reason}", hook.url)));
+        match http::parse_url(&hook.url) {
+            None => return invalid("must be https://host[:port][/path]"),
+            Some(url) if url.tls && !http::TLS_SUPPORTED => {
+                return invalid("needs willow-core built with the `tls` feature")
+            }
+            Some(url) if !url.tls && !url.is_loopback() => {
+                return invalid("must use https unless it is a loopback address")
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// One POST waiting for a worker.
+struct Delivery {
+    url: String,
+    event: &'static str,
+    body: Vec<u8>,
+}
+
+/// A fixed pool of threads draining a bounded queue of deliveries, so a
+/// burst of events or a slow endpoint cannot pile up threads.
+struct Dispatcher {
+    queue: SyncSender<Delivery>,
+}
+
+impl Dispatcher {
+    fn new(workers: usize, capacity: usize) -> Self {
+        let (queue, receiver) = mpsc::sync_channel(capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let spawned = thread::Builder::new()
+                .name(format!("willow-webhook-{i}"))
+                .spawn(move || Self::work(&receiver));
+            if let Err(e) = spawned {
+                warn!(error = %e, "could not start webhook worker");
+            }
+        }
+        Dispatcher { queue }
+    }
+
+    fn work(receiver: &Mutex<Receiver<Delivery>>) {
+        loop {
+            let next = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+            let Ok(Delivery { url, event, body }) = next else { return };
+            match post(&url, event, &body) {
+                Ok(status) if (200..300).contains(&status) => debug!(%url, event, status, "webhook delivered"),
+                Ok(status) => warn!(%url, event, status, "webhook rejected"),
+                Err(e) => warn!(%url, event, error = %e, "webhook delivery failed"),
+            }
+        }
+    }
+
+    /// Queue `delivery`, or drop it if the queue is full. Returns whether
+    /// it was queued.
+    fn enqueue(&self, delivery: Delivery) -> bool {
+        match self.queue.try_send(delivery) {
+            Ok(()) => true,
+            Err(TrySendError::Full(delivery)) => {
+                warn!(url = %delivery.url, event = delivery.event, "webhook queue full; delivery dropped");
+                false
+            }
+            Err(TrySendError::Disconnected(delivery)) => {
+                warn!(url = %delivery.url, event = delivery.event, "no webhook workers; delivery dropped");
+                false
+            }
+        }
+    }
+}
+
+fn dispatcher() -> &'static Dispatcher {
+    static DISPATCHER: OnceLock<Dispatcher> = OnceLock::new();
+    DISPATCHER.get_or_init(|| Dispatcher::new(DELIVERY_WORKERS, DELIVERY_QUEUE))
+}
+
+/// The body POSTed to `hook`: `payload` as JSON, without the changes above
+/// the hook's `max_sensitivity`.
+fn encode(hook: &Webhook, payload: &WebhookPayload) -> serde_json::Result<Vec<u8>> {
+    let summary = payload.summary.without_above(hook.max_sensitivity);
+    serde_json::to_vec(&WebhookPayload { summary, ..payload.clone() })
+}
+
+/// Queue `payload` for each of `hooks` that wants its event, so the caller
+/// never waits on the network. Deliveries that fail, or find the queue
+/// full, are logged and not retried.
+pub fn deliver(hooks: &[Webhook], payload: &WebhookPayload) {
+    for hook in hooks.iter().filter(|h| h.wants(payload.event)) {
+        let body = match encode(hook, payload) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!(url = %hook.url, error = %e, "could not encode webhook payload");
+                continue;
+            }
+        };
+        dispatcher().enqueue(Delivery {
+            url: hook.url.clone(),
+            event: payload.event.as_str(),
+            body,
+        });
+    }
+}
+
+/// Send one request and return the response's status code.
+fn post(url: &str, event: &str, body: &[u8]) -> std::io::Result<u16> {
+    let url = http::parse_url(url)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "unsupported URL"))?;
+    let headers = [("Content-Type", "application/json"), ("X-Willow-Event", event)];
+    Ok(http::request("POST", &url, &headers, body, DELIVERY_TIMEOUT, MAX_RESPONSE_BYTES)?.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::diff::{AttachmentChangeSummary, LinkChangeSummary, NodeChangeSummary};
+
+    fn hook(url: &str) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            events: Vec::new(),
+            max_sensitivity: Sensitivity::Normal,
+        }
+    }
+
+    #[test]
+    fn test_validate_and_filter() {
+        assert!(validate(&[hook("http://127.0.0.1:1/")]).is_ok());
+        assert!(validate(&[hook("http://[::1]/hooks")]).is_ok());
+        assert_eq!(validate(&[hook("https://example.com/")]).is_ok(), http::TLS_SUPPORTED);
+        assert!(validate(&[hook("http://example.com/")]).is_err());
+        assert!(validate(&[hook("http://10.1.2.3:8080/")]).is_err());
+        assert!(validate(&[hook("http://:80/")]).is_err());
+        assert!(validate(&[hook("ftp://localhost/")]).is_err());
+
+        let merges = Webhook { events: vec![WebhookEvent::Merge], ..hook("http://localhost/") };
+        assert!(merges.wants(WebhookEvent::Merge) && !merges.wants(WebhookEvent::Commit));
+        assert!(hook("http://localhost/").wants(WebhookEvent::Commit));
+        let json = serde_json::to_string(&merges).unwrap();
+        assert_eq!(json, r#"{"url":"http://localhost/","events":["merge"]}"#);
+        let secret: Webhook = serde_json::from_str(r#"{"url":"http://localhost/","max_sensitivity":"secret"}"#).unwrap();
+        assert_eq!(secret.max_sensitivity, Sensitivity::Secret);
+    }
+
+    #[test]
+    fn test_encode_leaves_out_changes_above_max_sensitivity() {
+        let node = |id: &str, content: &str, sensitivity| NodeChangeSummary {
+            node_id: id.to_string(),
+            node_type: "detail".to_string(),
+            content: content.to_string(),
+            old_content: None,
+            path: vec!["root".to_string(), content.to_string()],
+            content_changes: Vec::new(),
+            sensitivity,
+        };
+        let link = |id: &str, sensitivity| LinkChangeSummary {
+            link_id: id.to_string(),
+            from_node: "a".to_string(),
+            to_node: "b".to_string(),
+            relation: "related_to".to_string(),
+            bidirectional: false,
+            confidence: None,
+            sensitivity,
+        };
+        let payload = WebhookPayload {
+            event: WebhookEvent::Commit,
+            timestamp: Utc::now(),
+            branch: Some("main".to_string()),
+            source: None,
+            commit: None,
+            summary: ChangeSummary {
+                nodes_created: vec![
+                    node("a", "likes tea", Sensitivity::Normal),
+                    node("b", "bank pin 1234", Sensitivity::Secret),
+                ],
+                nodes_deleted: vec![node("c", "diagnosis", Sensitivity::Sensitive)],
+                links_created: vec![link("l1", Sensitivity::Normal), link("l2", Sensitivity::Secret)],
+                attachments_added: vec![AttachmentChangeSummary {
+                    node_id: "b".to_string(),
+                    hash: "abc".to_string(),
+                    mime: "image/png".to_string(),
+                    sensitivity: Sensitivity::Secret,
+                }],
+                ..ChangeSummary::default()
+            },
+        };
+
+        let body = String::from_utf8(encode(&hook("http://localhost/"), &payload).unwrap()).unwrap();
+        assert!(body.contains("likes tea") && body.contains("l1"), "{body}");
+        for hidden in ["bank pin", "diagnosis", "l2", "image/png", "sensitivity"] {
+            assert!(!body.contains(hidden), "{hidden} leaked: {body}");
+        }
+
+        let sensitive = Webhook { max_sensitivity: Sensitivity::Sensitive, ..hook("http://localhost/") };
+        let body = String::from_utf8(encode(&sensitive, &payload).unwrap()).unwrap();
+        assert!(body.contains("diagnosis") && !body.contains("bank pin"), "{body}");
+    }
+
+    #[test]
+    fn test_full_queue_drops_deliveries() {
+        // Accepts connections and never answers, so the one worker stays
+        // busy with the first delivery until the listener is dropped.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let dispatcher = Dispatcher::new(1, 1);
+        let delivery = || Delivery { url: url.clone(), event: "commit", body: b"{}".to_vec() };
+        let queued = (0..5).filter(|_| dispatcher.enqueue(delivery())).count();
+        assert!((1..=2).contains(&queued), "{queued} of 5 queued with one worker and room for one");
+    }
+}
@@ -0,0 +1,856 @@
+//! Python bindings for willow-core: `GraphStore` and `Repository`, with the
+//! DTO shapes of the Node bindings (snake_case, as Python spells them), so
+//! a graph and its history can be analyzed from a notebook.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use willow_core::python as core;
+use willow_core::vcs;
+
+create_exception!(willow, WillowError, PyException, "An error from the Willow engine.");
+
+/// Search results per page unless the caller asks for a different limit.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+fn py_err(e: core::WillowError) -> PyErr {
+    WillowError::new_err(e.to_string())
+}
+
+fn map_vec<T, U>(items: &[T], f: impl Fn(&T) -> U) -> Vec<U> {
+    items.iter().map(f).collect()
+}
+
+// ---- DTO classes ----
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct TemporalMetadata {
+    pub valid_from: Option<String>,
+    pub valid_until: Option<String>,
+    pub label: Option<String>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct DisplayHints {
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub collapsed: Option<bool>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub hash: String,
+    pub mime: String,
+    pub size: u64,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct SupersededValue {
+    pub old_content: String,
+    pub superseded_at: String,
+    pub reason: Option<String>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub id: String,
+    pub node_type: String,
+    pub content: String,
+    pub content_format: String,
+    pub parent_id: Option<String>,
+    pub children: Vec<String>,
+    pub extra_parents: Vec<String>,
+    pub pinned: bool,
+    pub sensitivity: String,
+    pub archived: bool,
+    pub display: Option<DisplayHints>,
+    pub attachments: Vec<Attachment>,
+    pub metadata: HashMap<String, String>,
+    pub previous_values: Vec<SupersededValue>,
+    pub temporal: Option<TemporalMetadata>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub id: String,
+    pub from_node: String,
+    pub to_node: String,
+    pub relation: String,
+    pub bidirectional: bool,
+    pub confidence: Option<String>,
+    pub created_at: String,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub node_id: String,
+    pub node_type: String,
+    pub content: String,
+    pub score: f64,
+    pub matched_field: String,
+    pub depth: usize,
+    /// Contents from the root down to the node.
+    pub path: Vec<String>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    /// Matches before pagination.
+    pub total: usize,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct NodePage {
+    pub nodes: Vec<Node>,
+    pub total: usize,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct LinkPage {
+    pub links: Vec<Link>,
+    pub total: usize,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct CommitStats {
+    pub nodes_created: u32,
+    pub nodes_updated: u32,
+    pub nodes_deleted: u32,
+    pub links_created: u32,
+    pub links_removed: u32,
+    pub links_updated: u32,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct CommitEntry {
+    pub hash: String,
+    pub message: String,
+    pub timestamp: String,
+    pub source: String,
+    pub source_detail: Option<String>,
+    pub parents: Vec<String>,
+    pub storage_type: String,
+    pub stats: Option<CommitStats>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct ContentFieldChange {
+    pub path: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct NodeChangeSummary {
+    pub node_id: String,
+    pub node_type: String,
+    pub content: String,
+    pub old_content: Option<String>,
+    pub path: Vec<String>,
+    pub content_changes: Vec<ContentFieldChange>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct LinkChangeSummary {
+    pub link_id: String,
+    pub from_node: String,
+    pub to_node: String,
+    pub relation: String,
+    pub bidirectional: bool,
+    pub confidence: Option<String>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct AttachmentChangeSummary {
+    pub node_id: String,
+    pub hash: String,
+    pub mime: String,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct ChangeSummary {
+    pub nodes_created: Vec<NodeChangeSummary>,
+    pub nodes_updated: Vec<NodeChangeSummary>,
+    pub nodes_deleted: Vec<NodeChangeSummary>,
+    pub links_created: Vec<LinkChangeSummary>,
+    pub links_removed: Vec<LinkChangeSummary>,
+    pub links_updated: Vec<LinkChangeSummary>,
+    pub attachments_added: Vec<AttachmentChangeSummary>,
+    pub attachments_removed: Vec<AttachmentChangeSummary>,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct CommitDetail {
+    pub commit: CommitEntry,
+    pub diff: ChangeSummary,
+}
+
+#[pyclass(module = "willow", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct BranchInfo {
+    pub name: String,
+    pub head: String,
+    pub is_current: bool,
+}
+
+// ---- Conversions ----
+
+fn node_to_py(node: &core::Node) -> Node {
+    Node {
+        id: node.id.0.to_string(),
+        node_type: node.node_type.as_str().to_string(),
+        content: node.content.clone(),
+        content_format: node.content_format.as_str().to_string(),
+        parent_id: node.parent_id.as_ref().map(|id| id.0.to_string()),
+        children: node.children.iter().map(|id| id.0.to_string()).collect(),
+        extra_parents: node.extra_parents.iter().map(|id| id.0.to_string()).collect(),
+        pinned: node.pinned,
+        sensitivity: node.sensitivity.as_str().to_string(),
+        archived: node.archived,
+        display: node.display.as_ref().map(|d| DisplayHints {
+            icon: d.icon.clone(),
+            color: d.color.clone(),
+            collapsed: Some(d.collapsed),
+        }),
+        attachments: map_vec(&node.attachments, |a| Attachment {
+            hash: a.hash.clone(),
+            mime: a.mime.clone(),
+            size: a.size,
+        }),
+        metadata: node.metadata.clone(),
+        previous_values: map_vec(&node.previous_values, |sv| SupersededValue {
+            old_content: sv.old_content.clone(),
+            superseded_at: sv.superseded_at.to_rfc3339(),
+            reason: sv.reason.clone(),
+        }),
+        temporal: node.temporal.as_ref().map(|t| TemporalMetadata {
+            valid_from: t.valid_from.map(|d| d.to_rfc3339()),
+            valid_until: t.valid_until.map(|d| d.to_rfc3339()),
+            label: t.label.clone(),
+        }),
+        created_at: node.created_at.to_rfc3339(),
+        updated_at: node.updated_at.to_rfc3339(),
+        created_by: node.created_by.clone(),
+        updated_by: node.updated_by.clone(),
+    }
+}
+
+fn link_to_py(link: &core::Link) -> Link {
+    Link {
+        id: link.id.0.to_string(),
+        from_node: link.from_node.0.to_string(),
+        to_node: link.to_node.0.to_string(),
+        relation: link.relation.clone(),
+        bidirectional: link.bidirectional,
+        confidence: link.confidence.as_ref().map(|c| c.as_str().to_string()),
+        created_at: link.created_at.to_rfc3339(),
+    }
+}
+
+fn search_page_to_py(page: &core::SearchPage) -> SearchPage {
+    SearchPage {
+        results: map_vec(&page.results, |r| SearchResult {
+            node_id: r.node_id.0.to_string(),
+            node_type: r.node_type.clone(),
+            content: r.content.clone(),
+            score: r.score,
+            matched_field: r.matched_field.clone(),
+            depth: r.depth,
+            path: r.path.clone(),
+        }),
+        total: page.total,
+    }
+}
+
+fn commit_source_to_string(source: &vcs::types::CommitSource) -> (String, Option<String>) {
+    use vcs::types::CommitSource;
+    match source {
+        CommitSource::Conversation { conversation_id, summary } => {
+            ("conversation".to_string(), conversation_id.clone().or_else(|| summary.clone()))
+        }
+        CommitSource::Maintenance { job_id } => ("maintenance".to_string(), job_id.clone()),
+        CommitSource::Manual { tool_name } => ("manual".to_string(), tool_name.clone()),
+        CommitSource::Merge { source_branch, target_branch } => {
+            ("merge".to_string(), Some(format!("{source_branch} -> {target_branch}")))
+        }
+        CommitSource::Migration => ("migration".to_string(), None),
+    }
+}
+
+fn commit_entry_to_py(entry: &vcs::types::CommitEntry) -> CommitEntry {
+    let (source, source_detail) = commit_source_to_string(&entry.data.source);
+    CommitEntry {
+        hash: entry.hash.0.clone(),
+        message: entry.data.message.clone(),
+        timestamp: entry.data.timestamp.to_rfc3339(),
+        source,
+        source_detail,
+        parents: entry.data.parents.iter().map(|p| p.0.clone()).collect(),
+        storage_type: match entry.data.storage_type {
+            vcs::types::CommitStorageType::Snapshot => "snapshot".to_string(),
+            vcs::types::CommitStorageType::Delta => "delta".to_string(),
+        },
+        stats: entry.data.stats.as_ref().map(|s| CommitStats {
+            nodes_created: s.nodes_created,
+            nodes_updated: s.nodes_updated,
+            nodes_deleted: s.nodes_deleted,
+            links_created: s.links_created,
+            links_removed: s.links_removed,
+            links_updated: s.links_updated,
+        }),
+    }
+}
+
+fn change_summary_to_py(diff: &vcs::diff::ChangeSummary) -> ChangeSummary {
+    let node = |n: &vcs::diff::NodeChangeSummary| NodeChangeSummary {
+        node_id: n.node_id.clone(),
+        node_type: n.node_type.clone(),
+        content: n.content.clone(),
+        old_content: n.old_content.clone(),
+        path: n.path.clone(),
+        content_changes: map_vec(&n.content_changes, |c| ContentFieldChange {
+            path: c.path.clone(),
+            old_value: c.old_value.clone(),
+            new_value: c.new_value.clone(),
+        }),
+    };
+    let link = |l: &vcs::diff::LinkChangeSummary| LinkChangeSummary {
+        link_id: l.link_id.clone(),
+        from_node: l.from_node.clone(),
+        to_node: l.to_node.clone(),
+        relation: l.relation.clone(),
+        bidirectional: l.bidirectional,
+        confidence: l.confidence.clone(),
+    };
+    let attachment = |a: &vcs::diff::AttachmentChangeSummary| AttachmentChangeSummary {
+        node_id: a.node_id.clone(),
+        hash: a.hash.clone(),
+        mime: a.mime.clone(),
+    };
+    ChangeSummary {
+        nodes_created: map_vec(&diff.nodes_created, node),
+        nodes_updated: map_vec(&diff.nodes_updated, node),
+        nodes_deleted: map_vec(&diff.nodes_deleted, node),
+        links_created: map_vec(&diff.links_created, link),
+        links_removed: map_vec(&diff.links_removed, link),
+        links_updated: map_vec(&diff.links_updated, link),
+        attachments_added: map_vec(&diff.attachments_added, attachment),
+        attachments_removed: map_vec(&diff.attachments_removed, attachment),
+    }
+}
+
+fn branch_to_py(b: &vcs::repository::BranchInfo) -> BranchInfo {
+    BranchInfo {
+        name: b.name.clone(),
+        head: b.head.0.clone(),
+        is_current: b.is_current,
+    }
+}
+
+fn parse_sensitivity(level: Option<&str>) -> PyResult<core::Sensitivity> {
+    match level {
+        Some(l) => core::Sensitivity::from_str(l).ok_or_else(|| py_err(core::WillowError::InvalidSensitivity(l.to_string()))),
+        None => Ok(core::Sensitivity::default()),
+    }
+}
+
+fn commit_source(
+    source: &str,
+    conversation_id: Option<String>,
+    summary: Option<String>,
+    job_id: Option<String>,
+    tool_name: Option<String>,
+) -> vcs::types::CommitSource {
+    use vcs::types::CommitSource;
+    match source {
+        "conversation" => CommitSource::Conversation { conversation_id, summary },
+        "maintenance" => CommitSource::Maintenance { job_id },
+        "migration" => CommitSource::Migration,
+        _ => CommitSource::Manual { tool_name },
+    }
+}
+
+// ---- GraphStore ----
+
+/// A graph file, opened for reading and writing. Stores opened on the same
+/// file in this process share one graph, as in the Node bindings.
+#[pyclass(module = "willow", frozen)]
+pub struct GraphStore {
+    inner: core::StoreHandle,
+}
+
+#[pymethods]
+impl GraphStore {
+    /// Open the graph at `file_path`, creating it if missing.
+    #[staticmethod]
+    #[pyo3(signature = (file_path, *, compress = false, journal = None, defer_history = false, sharded = false, audit = false))]
+    pub fn open(
+        file_path: PathBuf,
+        compress: bool,
+        journal: Option<usize>,
+        defer_history: bool,
+        sharded: bool,
+        audit: bool,
+    ) -> PyResult<Self> {
+        willow_core::init_tracing();
+        let options = core::OpenOptions {
+            storage: core::StorageOptions { compress, ..core::StorageOptions::default() },
+            journal: journal.map(|n| n.max(1)),
+            defer_history,
+            sharded,
+            audit,
+            ..core::OpenOptions::default()
+        };
+        let inner = core::GraphStore::open_shared(&file_path, &options).map_err(py_err)?;
+        Ok(GraphStore { inner })
+    }
+
+    /// Open an existing graph without ever writing to it. Mutating methods
+    /// raise `WillowError`.
+    #[staticmethod]
+    pub fn open_read_only(file_path: PathBuf) -> PyResult<Self> {
+        willow_core::init_tracing();
+        let inner = core::GraphStore::open_shared_read_only(&file_path).map_err(py_err)?;
+        Ok(GraphStore { inner })
+    }
+
+    /// Close this store; its methods raise afterwards. Closing the last
+    /// store on a file writes out anything buffered and releases it.
+    pub fn close(&self) -> PyResult<()> {
+        self.inner.close().map_err(py_err)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    pub fn get_node(&self, node_id: &str) -> PyResult<Node> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        store.get_node(node_id).map(node_to_py).map_err(py_err)
+    }
+
+    pub fn get_link(&self, link_id: &str) -> PyResult<Link> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        store.get_link(link_id).map(link_to_py).map_err(py_err)
+    }
+
+    /// Every node, in id order, a page at a time.
+    #[pyo3(signature = (offset = 0, limit = None))]
+    pub fn list_nodes(&self, offset: usize, limit: Option<usize>) -> PyResult<NodePage> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        let page = core::Page { offset, limit };
+        let result = store.list_nodes(&core::NodeFilter::default(), &core::NodeSort::default(), &page);
+        Ok(NodePage { nodes: map_vec(&result.nodes, node_to_py), total: result.total })
+    }
+
+    #[pyo3(signature = (offset = 0, limit = None))]
+    pub fn list_links(&self, offset: usize, limit: Option<usize>) -> PyResult<LinkPage> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        let page = core::Page { offset, limit };
+        let result = store.list_links(&core::LinkFilter::default(), &page);
+        Ok(LinkPage { links: map_vec(&result.links, link_to_py), total: result.total })
+    }
+
+    /// Ranked matches; `limit` defaults to 10. `total` counts every match.
+    #[pyo3(signature = (query, *, offset = 0, limit = None, root_node_id = None, max_sensitivity = None, include_archived = false))]
+    pub fn search_nodes(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: Option<usize>,
+        root_node_id: Option<&str>,
+        max_sensitivity: Option<&str>,
+        include_archived: bool,
+    ) -> PyResult<SearchPage> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        let options = core::SearchOptions {
+            max_sensitivity: parse_sensitivity(max_sensitivity)?,
+            include_archived,
+            ..core::SearchOptions::default()
+        };
+        let page = core::Page { offset, limit: Some(limit.unwrap_or(DEFAULT_SEARCH_LIMIT)) };
+        Ok(search_page_to_py(&store.search_nodes(query, &page, root_node_id, &options)))
+    }
+
+    #[pyo3(signature = (parent_id, node_type, content, metadata = None))]
+    pub fn create_node(
+        &self,
+        parent_id: &str,
+        node_type: &str,
+        content: &str,
+        metadata: Option<HashMap<String, String>>,
+    ) -> PyResult<Node> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        let node = store.create_node(parent_id, node_type, content, metadata, None).map_err(py_err)?;
+        Ok(node_to_py(&node))
+    }
+
+    #[pyo3(signature = (node_id, *, content = None, metadata = None, reason = None))]
+    pub fn update_node(
+        &self,
+        node_id: &str,
+        content: Option<&str>,
+        metadata: Option<HashMap<String, String>>,
+        reason: Option<&str>,
+    ) -> PyResult<Node> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        let node = store.update_node(node_id, content, metadata, None, reason).map_err(py_err)?;
+        Ok(node_to_py(&node))
+    }
+
+    pub fn delete_node(&self, node_id: &str) -> PyResult<()> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        store.delete_node(node_id).map_err(py_err)
+    }
+
+    #[pyo3(signature = (from_node, to_node, relation, *, bidirectional = false, confidence = None))]
+    pub fn add_link(
+        &self,
+        from_node: &str,
+        to_node: &str,
+        relation: &str,
+        bidirectional: bool,
+        confidence: Option<&str>,
+    ) -> PyResult<Link> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        let link = store.add_link(from_node, to_node, relation, bidirectional, confidence).map_err(py_err)?;
+        Ok(link_to_py(&link))
+    }
+
+    // ---- VCS methods ----
+
+    pub fn vcs_init(&self) -> PyResult<()> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        store.vcs_init().map_err(py_err)
+    }
+
+    pub fn has_pending_changes(&self) -> bool {
+        self.inner.lock().has_pending_changes()
+    }
+
+    /// Commit the pending changes; `source` is "conversation",
+    /// "maintenance", "manual" (the default) or "migration".
+    #[pyo3(signature = (message, *, source = "manual", conversation_id = None, summary = None, job_id = None, tool_name = None))]
+    pub fn commit(
+        &self,
+        message: String,
+        source: &str,
+        conversation_id: Option<String>,
+        summary: Option<String>,
+        job_id: Option<String>,
+        tool_name: Option<String>,
+    ) -> PyResult<String> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        let input = vcs::types::CommitInput {
+            message,
+            source: commit_source(source, conversation_id, summary, job_id, tool_name),
+        };
+        Ok(store.commit(input).map_err(py_err)?.0)
+    }
+
+    #[pyo3(signature = (limit = None))]
+    pub fn log(&self, limit: Option<usize>) -> PyResult<Vec<CommitEntry>> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        let entries = store.get_repo().map_err(py_err)?.log(limit).map_err(py_err)?;
+        Ok(map_vec(&entries, commit_entry_to_py))
+    }
+
+    pub fn show_commit(&self, hash: String) -> PyResult<CommitDetail> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        show_commit(store.get_repo().map_err(py_err)?, hash)
+    }
+
+    pub fn diff(&self, from_hash: String, to_hash: String) -> PyResult<ChangeSummary> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        diff(store.get_repo().map_err(py_err)?, from_hash, to_hash)
+    }
+
+    pub fn list_branches(&self) -> PyResult<Vec<BranchInfo>> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        let branches = store.get_repo().map_err(py_err)?.list_branches().map_err(py_err)?;
+        Ok(map_vec(&branches, branch_to_py))
+    }
+
+    pub fn current_branch(&self) -> PyResult<Option<String>> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        store.get_repo().map_err(py_err)?.current_branch().map_err(py_err)
+    }
+
+    pub fn create_branch(&self, name: &str) -> PyResult<()> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        store.create_branch(name).map_err(py_err)
+    }
+
+    pub fn switch_branch(&self, name: &str) -> PyResult<()> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        store.switch_branch(name).map_err(py_err)
+    }
+
+    /// Merge `source` into the current branch; returns the merge commit.
+    pub fn merge_branch(&self, source: &str) -> PyResult<String> {
+        let mut store = self.inner.write().map_err(py_err)?;
+        Ok(store.merge_branch(source).map_err(py_err)?.0)
+    }
+
+    /// The whole graph as of commit `hash`, as the JSON of a graph file.
+    pub fn graph_at_commit(&self, hash: String) -> PyResult<String> {
+        let store = self.inner.lock();
+        store.require_open().map_err(py_err)?;
+        graph_at_commit(store.get_repo().map_err(py_err)?, hash)
+    }
+}
+
+fn show_commit(repo: &vcs::repository::Repository, hash: String) -> PyResult<CommitDetail> {
+    let hash = vcs::types::CommitHash(hash);
+    let (data, diff) = repo.show_commit(&hash).map_err(py_err)?;
+    Ok(CommitDetail {
+        commit: commit_entry_to_py(&vcs::types::CommitEntry { hash, data }),
+        diff: change_summary_to_py(&diff),
+    })
+}
+
+fn diff(repo: &vcs::repository::Repository, from_hash: String, to_hash: String) -> PyResult<ChangeSummary> {
+    let diff = repo.diff(&vcs::types::CommitHash(from_hash), &vcs::types::CommitHash(to_hash)).map_err(py_err)?;
+    Ok(change_summary_to_py(&diff))
+}
+
+fn graph_at_commit(repo: &vcs::repository::Repository, hash: String) -> PyResult<String> {
+    let graph = repo.reconstruct_at(&vcs::types::CommitHash(hash)).map_err(py_err)?;
+    serde_json::to_string(&graph).map_err(|e| WillowError::new_err(e.to_string()))
+}
+
+// ---- Repository ----
+
+/// The history of a graph, read straight from its `repo/` directory
+/// without loading the graph itself: for walking commits and diffs in bulk.
+#[pyclass(module = "willow", frozen)]
+pub struct Repository {
+    inner: vcs::repository::Repository,
+}
+
+#[pymethods]
+impl Repository {
+    /// Open the history of the graph file at `file_path`.
+    #[staticmethod]
+    pub fn open(file_path: PathBuf) -> PyResult<Self> {
+        willow_core::init_tracing();
+        let graph_dir = file_path.parent().unwrap_or(Path::new("."));
+        let inner = vcs::repository::Repository::open(graph_dir).map_err(py_err)?;
+        Ok(Repository { inner })
+    }
+
+    #[pyo3(signature = (limit = None))]
+    pub fn log(&self, limit: Option<usize>) -> PyResult<Vec<CommitEntry>> {
+        Ok(map_vec(&self.inner.log(limit).map_err(py_err)?, commit_entry_to_py))
+    }
+
+    pub fn head_hash(&self) -> PyResult<String> {
+        Ok(self.inner.head_hash().map_err(py_err)?.0)
+    }
+
+    pub fn show_commit(&self, hash: String) -> PyResult<CommitDetail> {
+        show_commit(&self.inner, hash)
+    }
+
+    pub fn diff(&self, from_hash: String, to_hash: String) -> PyResult<ChangeSummary> {
+        diff(&self.inner, from_hash, to_hash)
+    }
+
+    /// `diff` between the heads of two branches.
+    pub fn diff_branches(&self, from_branch: &str, to_branch: &str) -> PyResult<ChangeSummary> {
+        Ok(change_summary_to_py(&self.inner.diff_branches(from_branch, to_branch).map_err(py_err)?))
+    }
+
+    pub fn list_branches(&self) -> PyResult<Vec<BranchInfo>> {
+        Ok(map_vec(&self.inner.list_branches().map_err(py_err)?, branch_to_py))
+    }
+
+    pub fn current_branch(&self) -> PyResult<Option<String>> {
+        self.inner.current_branch().map_err(py_err)
+    }
+
+    /// The whole graph as of commit `hash`, as the JSON of a graph file.
+    pub fn graph_at_commit(&self, hash: String) -> PyResult<String> {
+        graph_at_commit(&self.inner, hash)
+    }
+}
+
+#[pymodule]
+fn willow(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("WillowError", m.py().get_type::<WillowError>())?;
+    m.add_class::<GraphStore>()?;
+    m.add_class::<Repository>()?;
+    m.add_class::<Node>()?;
+    m.add_class::<Link>()?;
+    m.add_class::<Attachment>()?;
+    m.add_class::<SupersededValue>()?;
+    m.add_class::<TemporalMetadata>()?;
+    m.add_class::<DisplayHints>()?;
+    m.add_class::<SearchResult>()?;
+    m.add_class::<SearchPage>()?;
+    m.add_class::<NodePage>()?;
+    m.add_class::<LinkPage>()?;
+    m.add_class::<CommitEntry>()?;
+    m.add_class::<CommitStats>()?;
+    m.add_class::<CommitDetail>()?;
+    m.add_class::<ChangeSummary>()?;
+    m.add_class::<NodeChangeSummary>()?;
+    m.add_class::<LinkChangeSummary>()?;
+    m.add_class::<AttachmentChangeSummary>()?;
+    m.add_class::<ContentFieldChange>()?;
+    m.add_class::<BranchInfo>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    fn temp_store() -> (tempfile::TempDir, PathBuf, GraphStore) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("graph.json");
+        let store = GraphStore::open(path.clone(), false, None, false, false, false).unwrap();
+        (dir, path, store)
+    }
+
+    fn commit(store: &GraphStore, message: &str) -> String {
+        store.commit(message.to_string(), "manual", None, None, None, None).unwrap()
+    }
+
+    #[test]
+    fn test_store_reads_writes_and_commits() {
+        let (_dir, path, store) = temp_store();
+        store.vcs_init().unwrap();
+        let tea = store.create_node("root", "detail", "Likes green tea", None).unwrap();
+        let coffee = store.create_node("root", "detail", "Likes coffee", None).unwrap();
+        store.add_link(&tea.id, &coffee.id, "related_to", false, None).unwrap();
+        assert!(store.has_pending_changes());
+        let first = commit(&store, "drinks");
+
+        let updated = store.update_node(&tea.id, Some("Likes oolong tea"), None, Some("more precise")).unwrap();
+        assert_eq!(updated.previous_values[0].old_content, "Likes green tea");
+        let second = commit(&store, "oolong");
+
+        assert_eq!(store.get_node(&tea.id).unwrap().content, "Likes oolong tea");
+        let page = store.search_nodes("tea", 0, None, None, None, false).unwrap();
+        assert_eq!((page.total, page.results[0].node_id.as_str()), (1, tea.id.as_str()));
+        assert_eq!(store.list_nodes(0, Some(1)).unwrap().nodes.len(), 1);
+        assert_eq!(store.list_links(0, None).unwrap().total, 1);
+
+        let log = store.log(None).unwrap();
+        assert_eq!(log[0].hash, second);
+        assert_eq!(log[0].parents, std::slice::from_ref(&first));
+        let detail = store.show_commit(second.clone()).unwrap();
+        assert_eq!(detail.diff.nodes_updated[0].old_content.as_deref(), Some("Likes green tea"));
+
+        // The history reads the same without the store.
+        let repo = Repository::open(path).unwrap();
+        assert_eq!(repo.head_hash().unwrap(), second);
+        assert_eq!(repo.log(None).unwrap().len(), log.len());
+        assert_eq!(repo.diff(first.clone(), second.clone()).unwrap().nodes_updated.len(), 1);
+        assert!(repo.graph_at_commit(first).unwrap().contains("Likes green tea"));
+        assert_eq!(repo.current_branch().unwrap(), store.current_branch().unwrap());
+    }
+
+    #[test]
+    fn test_branches() {
+        let (_dir, path, store) = temp_store();
+        store.vcs_init().unwrap();
+        store.create_branch("side").unwrap();
+        store.switch_branch("side").unwrap();
+        store.create_node("root", "detail", "On the side", None).unwrap();
+        commit(&store, "side");
+        store.switch_branch("main").unwrap();
+        let merge = store.merge_branch("side").unwrap();
+        let branches = store.list_branches().unwrap();
+        assert!(branches.iter().any(|b| b.name == "main" && b.is_current && b.head == merge));
+        let repo = Repository::open(path).unwrap();
+        assert!(repo.diff_branches("main", "side").unwrap().nodes_created.is_empty());
+    }
+
+    #[test]
+    fn test_errors_raise_willow_error() {
+        let (dir, path, store) = temp_store();
+        Python::attach(|py| {
+            let missing = store.get_node("missing").unwrap_err();
+            assert!(missing.is_instance_of::<WillowError>(py), "{missing}");
+            let bad = store.search_nodes("x", 0, None, None, Some("top-secret"), false).unwrap_err();
+            assert!(bad.is_instance_of::<WillowError>(py));
+            // No history yet.
+            assert!(store.log(None).unwrap_err().is_instance_of::<WillowError>(py));
+            assert!(Repository::open(dir.path().join("graph.json")).is_err());
+
+            let reader = GraphStore::open_read_only(path).unwrap();
+            assert!(reader.create_node("root", "detail", "x", None).unwrap_err().is_instance_of::<WillowError>(py));
+            store.close().unwrap();
+            assert!(store.is_closed());
+            assert!(store.get_node("root").unwrap_err().is_instance_of::<WillowError>(py));
+        });
+    }
+
+    #[test]
+    fn test_module_from_python() {
+        let (_dir, path, store) = temp_store();
+        store.create_node("root", "detail", "Likes tea", Some(HashMap::from([("k".to_string(), "v".to_string())]))).unwrap();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "willow").unwrap();
+            willow(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("willow", module).unwrap();
+            locals.set_item("path", path.to_str().unwrap()).unwrap();
+            py.run(
+                cr#"
+store = willow.GraphStore.open(path)
+page = store.search_nodes("tea", limit=5)
+assert page.total == 1, page.total
+node = store.get_node(page.results[0].node_id)
+assert (node.content, node.metadata, node.parent_id) == ("Likes tea", {"k": "v"}, "root")
+try:
+    store.get_node("missing")
+    raise AssertionError("no error")
+except willow.WillowError:
+    pass
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
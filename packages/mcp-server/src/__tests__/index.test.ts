@@ -11,7 +11,7 @@ vi.mock("os", () => ({
 
 // Create mock store instance
 const mockStore = {
-	searchNodes: vi.fn(),
+	searchNodesPage: vi.fn(),
 	getContext: vi.fn(),
 	createNode: vi.fn(),
 	updateNode: vi.fn(),
@@ -72,26 +72,29 @@ describe("mcp-server index", () => {
 		}
 	});
 
-	it("search_nodes calls store.searchNodes", async () => {
-		mockStore.searchNodes.mockReturnValue([{ id: "result1" }]);
+	it("search_nodes calls store.searchNodesPage", async () => {
+		mockStore.searchNodesPage.mockReturnValue({
+			results: [{ id: "result1" }],
+			total: 1,
+		});
 		const res = await registeredTools.search_nodes({
 			query: "test",
 			maxResults: 5,
 		});
-		expect(mockStore.searchNodes).toHaveBeenCalledWith(
+		expect(mockStore.searchNodesPage).toHaveBeenCalledWith(
 			"test",
-			5,
+			{ limit: 5 },
 			undefined,
 		);
 		expect(res.content[0].text).toContain("result1");
 	});
 
 	it("search_nodes uses default maxResults", async () => {
-		mockStore.searchNodes.mockReturnValue([]);
+		mockStore.searchNodesPage.mockReturnValue({ results: [], total: 0 });
 		await registeredTools.search_nodes({ query: "test" });
-		expect(mockStore.searchNodes).toHaveBeenCalledWith(
+		expect(mockStore.searchNodesPage).toHaveBeenCalledWith(
 			"test",
-			10,
+			{ limit: 10 },
 			undefined,
 		);
 	});
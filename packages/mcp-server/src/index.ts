@@ -103,12 +103,12 @@ registerTool(
 	"Search the knowledge graph for nodes matching a query. Use this to find existing facts before creating new ones.",
 	schemas.searchNodes.shape,
 	({ query, maxResults }) => {
-		const results = store.searchNodes(
+		const { results, total } = store.searchNodesPage(
 			query,
-			maxResults ?? 10,
+			{ limit: maxResults ?? 10 },
 			scopeNodeId ?? undefined,
 		);
-		log.debug("search_nodes result", { count: results.length });
+		log.debug("search_nodes result", { count: results.length, total });
 		return jsonResponse(results);
 	},
 );